@@ -120,6 +120,7 @@ impl VisualGraph {
                 position,
                 running: false,
                 queued: false,
+                progress: None,
                 error: None,
                 rendered_size: None,
                 id: Default::default(),
@@ -275,12 +276,36 @@ impl VisualGraph {
 
             let graph = self.inner;
             spawn(async move {
-                let fut = {
+                let (fut, mut progress, mut partial_outputs) = {
                     let current_node_write = node.write();
-                    current_node_write.instance.run(inputs)
+                    (
+                        current_node_write.instance.run(inputs),
+                        current_node_write.instance.progress(),
+                        current_node_write.instance.partial_outputs(),
+                    )
                 };
                 // Don't hold the write over an await point
-                let result = fut.await;
+                let mut fut = std::pin::pin!(fut);
+                let result = loop {
+                    tokio::select! {
+                        result = &mut fut => break result,
+                        Ok(()) = progress.changed() => {
+                            let current_progress = *progress.borrow_and_update();
+                            node.write().progress = current_progress;
+                        }
+                        Ok(()) = partial_outputs.changed() => {
+                            // Show the outputs as they stream in, but don't run the connected nodes until the final outputs are ready
+                            if let Some(outputs) = &*partial_outputs.borrow_and_update() {
+                                let current_node = node.read();
+                                for (out, current) in
+                                    outputs.iter().zip(current_node.outputs.iter())
+                                {
+                                    current.write_unchecked().value.clone_from(out);
+                                }
+                            }
+                        }
+                    }
+                };
                 let mut current_node_write = node.write();
                 match result.as_deref() {
                     Some(Ok(result)) => {
@@ -306,6 +331,7 @@ impl VisualGraph {
                 }
                 current_node_write.running = false;
                 current_node_write.queued = false;
+                current_node_write.progress = None;
             });
         }
     }
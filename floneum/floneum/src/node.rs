@@ -31,6 +31,8 @@ pub struct Node {
     // #[serde(skip)]
     pub queued: bool,
     // #[serde(skip)]
+    pub progress: Option<f32>,
+    // #[serde(skip)]
     pub error: Option<String>,
     pub id: NodeIndex<DefaultIx>,
     pub position: Point,
@@ -247,7 +249,11 @@ fn CenterNodeUI(mut node: Signal<Node>) -> Element {
                     }
                 }
                 if current_node.running {
-                    "Loading..."
+                    if let Some(fraction) = current_node.progress {
+                        progress { class: "w-20", max: "1", value: "{fraction}" }
+                    } else {
+                        "Loading..."
+                    }
                 } else {
                     button {
                         class: "p-1 border rounded-md ",
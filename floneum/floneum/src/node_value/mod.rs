@@ -56,6 +56,7 @@ impl Variants for PrimitiveValueType {
         PrimitiveValueType::Database,
         PrimitiveValueType::Page,
         PrimitiveValueType::Node,
+        PrimitiveValueType::Image,
         PrimitiveValueType::Any,
     ];
 }
@@ -73,6 +74,7 @@ impl Variants for ValueType {
         ValueType::Single(PrimitiveValueType::Database),
         ValueType::Single(PrimitiveValueType::Page),
         ValueType::Single(PrimitiveValueType::Node),
+        ValueType::Single(PrimitiveValueType::Image),
         ValueType::Single(PrimitiveValueType::Any),
         ValueType::Many(PrimitiveValueType::Text),
         ValueType::Many(PrimitiveValueType::File),
@@ -85,6 +87,7 @@ impl Variants for ValueType {
         ValueType::Many(PrimitiveValueType::Database),
         ValueType::Many(PrimitiveValueType::Page),
         ValueType::Many(PrimitiveValueType::Node),
+        ValueType::Many(PrimitiveValueType::Image),
         ValueType::Many(PrimitiveValueType::Any),
     ];
 }
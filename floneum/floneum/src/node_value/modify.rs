@@ -131,7 +131,8 @@ fn ModifySingleValue(props: ModifySingleValueProps) -> Element {
         | PrimitiveValue::EmbeddingModel(_)
         | PrimitiveValue::Database(_)
         | PrimitiveValue::Page(_)
-        | PrimitiveValue::Node(_) => show_primitive_value(&value),
+        | PrimitiveValue::Node(_)
+        | PrimitiveValue::Image(_) => show_primitive_value(&value),
         PrimitiveValue::Number(value) => {
             rsx! {
                 input {
@@ -1,4 +1,5 @@
 use crate::node_value::Named;
+use base64::Engine;
 use dioxus::prelude::*;
 use floneum_plugin::plugins::main::types::*;
 
@@ -80,6 +81,15 @@ pub fn show_primitive_value(value: &PrimitiveValue) -> Element {
         PrimitiveValue::Node(id) => {
             rsx! { "Node: {id:?}" }
         }
+        PrimitiveValue::Image(bytes) => {
+            if bytes.is_empty() {
+                return rsx! { "No image" };
+            }
+            let encoded = base64::engine::general_purpose::STANDARD.encode(bytes);
+            rsx! {
+                img { class: "max-w-full", src: "data:image/png;base64,{encoded}" }
+            }
+        }
     }
 }
 
@@ -2,7 +2,10 @@ use crate::plugins::main;
 use crate::resource::ResourceStorage;
 use crate::Both;
 use main::imports::{self};
-use main::types::{EmbeddingDbResource, EmbeddingModelResource, TextGenerationModelResource};
+use main::types::{
+    EmbeddingDbResource, EmbeddingModelResource, PrimitiveValue, TextGenerationModelResource,
+    TextStreamResource,
+};
 use std::ops::Deref;
 
 use kalosm::language::DynamicNodeId;
@@ -44,6 +47,10 @@ pub(crate) struct AnyNodeRef {
 #[derive(Clone)]
 pub struct SharedPluginState {
     pub(crate) logs: Arc<RwLock<Vec<String>>>,
+    /// The progress of the current long running task from 0 to 1, or `None` if no task with progress is running
+    pub(crate) progress: Arc<tokio::sync::watch::Sender<Option<f32>>>,
+    /// The outputs the plugin emitted while it is still running
+    pub(crate) partial_outputs: Arc<tokio::sync::watch::Sender<Option<Vec<Vec<PrimitiveValue>>>>>,
     pub(crate) resources: ResourceStorage,
 }
 
@@ -53,6 +60,17 @@ impl SharedPluginState {
         Self {
             resources,
             logs: Default::default(),
+            progress: Arc::new(tokio::sync::watch::channel(None).0),
+            partial_outputs: Arc::new(tokio::sync::watch::channel(None).0),
+        }
+    }
+
+    /// Create state for a new instance of the plugin. The instance shares logs and resources with the plugin, but has its own progress and partial outputs
+    pub(crate) fn new_instance(&self) -> Self {
+        Self {
+            progress: Arc::new(tokio::sync::watch::channel(None).0),
+            partial_outputs: Arc::new(tokio::sync::watch::channel(None).0),
+            ..self.clone()
         }
     }
 }
@@ -299,6 +317,34 @@ impl main::types::Host for State {
             .await
     }
 
+    async fn infer_stream(
+        &mut self,
+        self_: TextGenerationModelResource,
+        input: String,
+        max_tokens: Option<u32>,
+        stop_on: Option<String>,
+    ) -> wasmtime::Result<TextStreamResource> {
+        self.resources
+            .impl_infer_stream(self_, input, max_tokens, stop_on)
+            .await
+    }
+
+    async fn next_token(&mut self, self_: TextStreamResource) -> wasmtime::Result<Option<String>> {
+        self.resources.impl_next_token(self_).await
+    }
+
+    async fn drop_text_stream(&mut self, stream: TextStreamResource) -> wasmtime::Result<()> {
+        self.resources.impl_drop_text_stream(stream)
+    }
+
+    async fn emit_partial_outputs(
+        &mut self,
+        outputs: Vec<Vec<PrimitiveValue>>,
+    ) -> wasmtime::Result<()> {
+        self.partial_outputs.send_replace(Some(outputs));
+        Ok(())
+    }
+
     async fn create_embedding_model(
         &mut self,
         ty: main::types::EmbeddingModelType,
@@ -327,6 +373,22 @@ impl main::types::Host for State {
     ) -> wasmtime::Result<main::types::Embedding> {
         self.resources.impl_get_embedding(self_, document).await
     }
+
+    async fn image_model_downloaded(&mut self) -> wasmtime::Result<bool> {
+        self.shared.impl_image_model_downloaded().await
+    }
+
+    async fn generate_image(
+        &mut self,
+        prompt: String,
+        negative_prompt: String,
+        seed: Option<u64>,
+        steps: u32,
+    ) -> wasmtime::Result<Vec<u8>> {
+        self.shared
+            .impl_generate_image(prompt, negative_prompt, seed, steps)
+            .await
+    }
 }
 
 #[async_trait]
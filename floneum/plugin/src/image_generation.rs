@@ -0,0 +1,90 @@
+use crate::host::SharedPluginState;
+
+use futures_util::StreamExt;
+use image::ImageOutputFormat;
+use kalosm::language::ModelBuilder;
+use kalosm::vision::*;
+use std::io::Cursor;
+use tokio::sync::OnceCell;
+
+static IMAGE_MODEL: OnceCell<Wuerstchen> = OnceCell::const_new();
+
+/// Returns whether the image generation model has been downloaded.
+fn image_model_downloaded_sync() -> bool {
+    !Wuerstchen::builder().requires_download()
+}
+
+impl SharedPluginState {
+    async fn image_model(&self) -> wasmtime::Result<&'static Wuerstchen> {
+        let progress = self.progress.clone();
+        Ok(IMAGE_MODEL
+            .get_or_try_init(|| {
+                Wuerstchen::builder().build_with_loading_handler(
+                    move |loading: ModelLoadingProgress| {
+                        progress.send_replace(Some(loading.progress()));
+                    },
+                )
+            })
+            .await?)
+    }
+
+    pub(crate) async fn impl_image_model_downloaded(&self) -> wasmtime::Result<bool> {
+        Ok(image_model_downloaded_sync())
+    }
+
+    pub(crate) async fn impl_generate_image(
+        &self,
+        prompt: String,
+        negative_prompt: String,
+        seed: Option<u64>,
+        steps: u32,
+    ) -> wasmtime::Result<Vec<u8>> {
+        let result = self
+            .generate_png(prompt, negative_prompt, seed, steps)
+            .await;
+        // The node is done running, so it no longer has any progress to show
+        self.progress.send_replace(None);
+        result
+    }
+
+    async fn generate_png(
+        &self,
+        prompt: String,
+        negative_prompt: String,
+        seed: Option<u64>,
+        steps: u32,
+    ) -> wasmtime::Result<Vec<u8>> {
+        let model = self.image_model().await?;
+
+        let progress = self.progress.clone();
+        progress.send_replace(Some(0.));
+        let mut settings = WuerstchenInferenceSettings::new(prompt)
+            .with_negative_prompt(negative_prompt)
+            .with_progress_handler(move |step_progress| {
+                progress.send_replace(Some(step_progress));
+            });
+        if steps > 0 {
+            settings = settings.with_prior_steps(steps as usize);
+        }
+        if let Some(seed) = seed {
+            settings = settings.with_seed(seed);
+        }
+
+        let mut images = model.run(settings);
+        let image = images
+            .next()
+            .await
+            .ok_or_else(|| anyhow::anyhow!("The image model stopped before generating an image"))?;
+        if let Some(err) = image.error() {
+            return Err(anyhow::anyhow!("Failed to generate image: {err}"));
+        }
+        let Some(buffer) = image.generated_image() else {
+            return Err(anyhow::anyhow!("The image model returned no image"));
+        };
+
+        // Images are passed between plugins as png encoded bytes
+        let mut bytes = Vec::new();
+        buffer.write_to(&mut Cursor::new(&mut bytes), ImageOutputFormat::Png)?;
+        Ok(bytes)
+    }
+}
@@ -3,6 +3,7 @@ mod plugin;
 pub use plugin::*;
 mod embedding;
 mod embedding_db;
+mod image_generation;
 mod llm;
 mod node;
 mod page;
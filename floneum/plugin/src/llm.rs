@@ -1,5 +1,5 @@
 use crate::plugins::main;
-use crate::plugins::main::types::TextGenerationModelResource;
+use crate::plugins::main::types::{TextGenerationModelResource, TextStreamResource};
 use crate::resource::{Resource, ResourceStorage};
 
 use anyhow::Ok;
@@ -7,6 +7,7 @@ use kalosm::language::*;
 use once_cell::sync::Lazy;
 use std::collections::HashMap;
 use std::sync::{Arc, RwLock};
+use tokio::sync::mpsc::UnboundedReceiver;
 
 pub(crate) enum LazyTextGenerationModel {
     Uninitialized(main::types::ModelType),
@@ -18,6 +19,12 @@ pub(crate) enum ConcreteTextGenerationModel {
     Llama(Arc<Llama>),
 }
 
+/// A stream of tokens that a text generation model is generating in the background.
+pub(crate) struct TextStream {
+    // The receiver is behind an async mutex so it can be read without holding the lock on the resource storage
+    tokens: Arc<tokio::sync::Mutex<UnboundedReceiver<String>>>,
+}
+
 impl LazyTextGenerationModel {
     fn initialize(
         &self,
@@ -265,6 +272,64 @@ impl ResourceStorage {
         }
     }
 
+    pub(crate) async fn impl_infer_stream(
+        &self,
+        self_: TextGenerationModelResource,
+        input: String,
+        max_tokens: Option<u32>,
+        stop_on: Option<String>,
+    ) -> wasmtime::Result<TextStreamResource> {
+        let index = self_.into();
+        let model = self.initialize_model(index).await?;
+        let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+        match model {
+            ConcreteTextGenerationModel::Llama(model) => {
+                let mut stream = model.complete(&input).with_sampler(
+                    GenerationParameters::new()
+                        .with_max_length(max_tokens.unwrap_or(u32::MAX))
+                        .with_stop_on(stop_on),
+                );
+                tokio::spawn(async move {
+                    while let Some(token) = stream.next().await {
+                        // Stop generating if the plugin dropped the stream
+                        if sender.send(token).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+        }
+
+        let idx = self.insert(TextStream {
+            tokens: Arc::new(tokio::sync::Mutex::new(receiver)),
+        });
+        Ok(TextStreamResource {
+            id: idx.index() as u64,
+            owned: true,
+        })
+    }
+
+    pub(crate) async fn impl_next_token(
+        &self,
+        self_: TextStreamResource,
+    ) -> wasmtime::Result<Option<String>> {
+        let index = self_.into();
+        let tokens = {
+            let stream = self
+                .get(index)
+                .ok_or(anyhow::anyhow!("Text stream not found"))?;
+            stream.tokens.clone()
+        };
+        let mut tokens = tokens.lock().await;
+        Ok(tokens.recv().await)
+    }
+
+    pub(crate) fn impl_drop_text_stream(&self, stream: TextStreamResource) -> wasmtime::Result<()> {
+        let index: Resource<TextStream> = stream.into();
+        self.drop_key(index);
+        Ok(())
+    }
+
     pub(crate) fn impl_drop_text_generation_model(
         &self,
         model: TextGenerationModelResource,
@@ -125,7 +125,7 @@ impl Plugin {
             return Ok(metadata);
         }
         // then we get the structure of the plugin.
-        let (mut store, world) = self.create_world().await?;
+        let (mut store, world) = self.create_world(self.shared.clone()).await?;
         let structure = world.interface0.call_structure(&mut store).await.unwrap();
 
         let _ = self.definition.set(structure);
@@ -145,9 +145,12 @@ impl Plugin {
         Ok(self.metadata.get().unwrap())
     }
 
-    async fn create_world(&self) -> anyhow::Result<(wasmtime::Store<State>, Both)> {
+    async fn create_world(
+        &self,
+        shared: SharedPluginState,
+    ) -> anyhow::Result<(wasmtime::Store<State>, Both)> {
         // create the store of models
-        let state = State::new(self.shared.clone());
+        let state = State::new(shared);
         let mut store = Store::new(&ENGINE, state);
         let component = self.component().await?;
         let (world, _instance) = Both::instantiate_async(&mut store, component, &LINKER)
@@ -157,7 +160,8 @@ impl Plugin {
     }
 
     pub async fn instance(&self) -> anyhow::Result<PluginInstance> {
-        let (mut store, world) = self.create_world().await?;
+        let shared = self.shared.new_instance();
+        let (mut store, world) = self.create_world(shared.clone()).await?;
         let definition = self.definition().await?;
 
         let (input_sender, mut input_receiver) =
@@ -170,6 +174,8 @@ impl Plugin {
                     break;
                 };
                 let outputs = world.interface0.call_run(&mut store, &inputs).await;
+                // The final outputs replace any outputs the plugin emitted while running
+                store.data().partial_outputs.send_replace(None);
                 if output_sender.send(Arc::new(outputs)).is_err() {
                     break;
                 }
@@ -181,7 +187,7 @@ impl Plugin {
             sender: input_sender,
             receiver: output_receiver,
             metadata: definition.clone(),
-            shared_plugin_state: self.shared.clone(),
+            shared_plugin_state: shared,
         })
     }
 
@@ -252,6 +258,18 @@ impl PluginInstance {
         self.shared_plugin_state.logs.read()
     }
 
+    /// Subscribe to the progress of the current long running task like generating an image
+    pub fn progress(&self) -> tokio::sync::watch::Receiver<Option<f32>> {
+        self.shared_plugin_state.progress.subscribe()
+    }
+
+    /// Subscribe to the outputs the plugin emits while it is still running, like the tokens of a streaming text generation
+    pub fn partial_outputs(
+        &self,
+    ) -> tokio::sync::watch::Receiver<Option<Vec<Vec<PrimitiveValue>>>> {
+        self.shared_plugin_state.partial_outputs.subscribe()
+    }
+
     pub fn metadata(&self) -> &Definition {
         &self.metadata
     }
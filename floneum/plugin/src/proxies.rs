@@ -22,6 +22,7 @@ impl PartialEq for PrimitiveValue {
             (PrimitiveValue::Boolean(a), PrimitiveValue::Boolean(b)) => a == b,
             (PrimitiveValue::Page(a), PrimitiveValue::Page(b)) => a.id == b.id,
             (PrimitiveValue::Node(a), PrimitiveValue::Node(b)) => a.id == b.id,
+            (PrimitiveValue::Image(a), PrimitiveValue::Image(b)) => a == b,
             _ => false,
         }
     }
@@ -43,6 +44,7 @@ enum MyPrimitiveValue {
     Database { id: u64, owned: bool },
     Page { id: u64, owned: bool },
     Node { id: u64, owned: bool },
+    Image(Vec<u8>),
 }
 
 impl From<&PrimitiveValue> for MyPrimitiveValue {
@@ -79,6 +81,7 @@ impl From<&PrimitiveValue> for MyPrimitiveValue {
                 MyPrimitiveValue::EmbeddingModelType(value.into())
             }
             PrimitiveValue::Boolean(value) => MyPrimitiveValue::Boolean(*value),
+            PrimitiveValue::Image(value) => MyPrimitiveValue::Image(value.clone()),
         }
     }
 }
@@ -114,6 +117,7 @@ impl From<MyPrimitiveValue> for PrimitiveValue {
                 PrimitiveValue::Database(EmbeddingDbResource { id, owned })
             }
             MyPrimitiveValue::Boolean(value) => PrimitiveValue::Boolean(value),
+            MyPrimitiveValue::Image(value) => PrimitiveValue::Image(value),
         }
     }
 }
@@ -379,6 +383,7 @@ impl PrimitiveValueType {
                 "http://floneum.com".into(),
             )?),
             PrimitiveValueType::Node => return Err(anyhow::anyhow!("Cannot create a node")),
+            PrimitiveValueType::Image => PrimitiveValue::Image(Vec::new()),
             PrimitiveValueType::Any => PrimitiveValue::Number(0),
         })
     }
@@ -406,6 +411,7 @@ impl PrimitiveValueType {
                 | (PrimitiveValueType::Boolean, PrimitiveValueType::Boolean)
                 | (PrimitiveValueType::Page, PrimitiveValueType::Page)
                 | (PrimitiveValueType::Node, PrimitiveValueType::Node)
+                | (PrimitiveValueType::Image, PrimitiveValueType::Image)
                 | (PrimitiveValueType::Any, _)
                 | (_, PrimitiveValueType::Any)
         )
@@ -504,6 +510,7 @@ enum MyPrimitiveValueType {
     Boolean,
     Page,
     Node,
+    Image,
     Any,
 }
 
@@ -524,6 +531,7 @@ impl From<PrimitiveValueType> for MyPrimitiveValueType {
             PrimitiveValueType::Boolean => MyPrimitiveValueType::Boolean,
             PrimitiveValueType::Page => MyPrimitiveValueType::Page,
             PrimitiveValueType::Node => MyPrimitiveValueType::Node,
+            PrimitiveValueType::Image => MyPrimitiveValueType::Image,
             PrimitiveValueType::Any => MyPrimitiveValueType::Any,
         }
     }
@@ -546,6 +554,7 @@ impl From<MyPrimitiveValueType> for PrimitiveValueType {
             MyPrimitiveValueType::Boolean => PrimitiveValueType::Boolean,
             MyPrimitiveValueType::Page => PrimitiveValueType::Page,
             MyPrimitiveValueType::Node => PrimitiveValueType::Node,
+            MyPrimitiveValueType::Image => PrimitiveValueType::Image,
             MyPrimitiveValueType::Any => PrimitiveValueType::Any,
         }
     }
@@ -585,6 +594,7 @@ impl PrimitiveValue {
                 | (PrimitiveValue::Boolean(_), PrimitiveValueType::Boolean)
                 | (PrimitiveValue::Page(_), PrimitiveValueType::Page)
                 | (PrimitiveValue::Node(_), PrimitiveValueType::Node)
+                | (PrimitiveValue::Image(_), PrimitiveValueType::Image)
         )
     }
 
@@ -10,8 +10,11 @@ use slab::Slab;
 use std::sync::Arc;
 
 use crate::{
-    embedding::LazyTextEmbeddingModel, embedding_db::VectorDBWithDocuments, host::AnyNodeRef,
-    llm::LazyTextGenerationModel, plugins::main,
+    embedding::LazyTextEmbeddingModel,
+    embedding_db::VectorDBWithDocuments,
+    host::AnyNodeRef,
+    llm::{LazyTextGenerationModel, TextStream},
+    plugins::main,
 };
 
 type ResourceMap = Arc<RwLock<HashMap<TypeId, Slab<Box<dyn Any + Send + Sync>>>>>;
@@ -125,6 +128,16 @@ impl From<main::types::TextGenerationModelResource> for Resource<LazyTextGenerat
     }
 }
 
+impl From<main::types::TextStreamResource> for Resource<TextStream> {
+    fn from(value: main::types::TextStreamResource) -> Self {
+        Self {
+            index: value.id as usize,
+            owned: value.owned,
+            phantom: PhantomData,
+        }
+    }
+}
+
 impl From<main::types::EmbeddingDbResource> for Resource<VectorDBWithDocuments> {
     fn from(value: main::types::EmbeddingDbResource) -> Self {
         Self {
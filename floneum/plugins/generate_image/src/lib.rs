@@ -0,0 +1,22 @@
+use floneum_rust::*;
+
+#[export_plugin]
+/// Generates an image from a text prompt.
+///
+/// The prompt describes what should be in the image. The negative prompt describes what should not be in the image.
+///
+/// Generating an image with the same prompt and seed creates the same image. A seed of 0 uses a random seed.
+///
+/// More steps create more detailed images, but take longer to generate. If steps is 0, the default number of steps is used.
+fn generate_image(prompt: String, negative_prompt: String, seed: i64, steps: i64) -> Image {
+    if !Image::model_downloaded() {
+        log_to_user("downloading model... This could take several minutes");
+    }
+
+    Image::generate(
+        &prompt,
+        &negative_prompt,
+        (seed != 0).then_some(seed as u64),
+        steps.max(0) as u32,
+    )
+}
@@ -24,7 +24,12 @@ fn generate_text(model: ModelType, text: String, max_size: i64) -> String {
 
     let session = TextGenerationModel::new(model);
 
-    let mut responce = session.infer(&text, (max_size != 0).then_some(max_size as u32), None);
+    // Stream the response into the output so it shows up while it is being generated
+    let mut responce = String::new();
+    for token in session.infer_stream(&text, (max_size != 0).then_some(max_size as u32), None) {
+        responce += &token;
+        emit_outputs(responce.clone());
+    }
     responce += "\n";
 
     responce
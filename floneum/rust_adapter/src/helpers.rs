@@ -140,6 +140,37 @@ impl TextGenerationModel {
     pub fn infer_structured(&self, input: &str, regex: &str) -> String {
         infer_structured(self.model, input, regex)
     }
+
+    /// Start generating text in the background. The returned stream yields each token as soon as it is generated.
+    pub fn infer_stream(
+        &self,
+        input: &str,
+        max_tokens: Option<u32>,
+        stop_on: Option<&str>,
+    ) -> TextStream {
+        TextStream {
+            stream: infer_stream(self.model, input, max_tokens, stop_on),
+        }
+    }
+}
+
+/// A stream of tokens generated by a [`TextGenerationModel`]. Dropping the stream stops the generation.
+pub struct TextStream {
+    stream: TextStreamResource,
+}
+
+impl Iterator for TextStream {
+    type Item = String;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        next_token(self.stream)
+    }
+}
+
+impl Drop for TextStream {
+    fn drop(&mut self) {
+        drop_text_stream(self.stream);
+    }
 }
 
 impl Drop for TextGenerationModel {
@@ -300,6 +331,11 @@ pub trait IntoReturnValues<T = ()> {
     fn into_return_values(self) -> Vec<Vec<PrimitiveValue>>;
 }
 
+/// Show outputs in the workflow editor before the plugin finishes running. Nodes connected to the outputs only run once the plugin returns the final outputs.
+pub fn emit_outputs<I>(outputs: impl IntoReturnValues<I>) {
+    emit_partial_outputs(&outputs.into_return_values());
+}
+
 impl<T: IntoReturnValue<I>, I> IntoReturnValues<I> for T {
     fn into_return_values(self) -> Vec<Vec<PrimitiveValue>> {
         vec![self.into_return_value()]
@@ -458,3 +494,37 @@ impl IntoPrimitiveValue for Folder {
         PrimitiveValue::Folder(self.0.display().to_string())
     }
 }
+
+/// A png encoded image.
+pub struct Image(Vec<u8>);
+
+impl Image {
+    /// Generate an image from a prompt with the image generation model. If the seed is `None`, a random seed is used. If steps is 0, the default number of steps is used.
+    pub fn generate(prompt: &str, negative_prompt: &str, seed: Option<u64>, steps: u32) -> Self {
+        Self(generate_image(prompt, negative_prompt, seed, steps))
+    }
+
+    pub fn model_downloaded() -> bool {
+        image_model_downloaded()
+    }
+}
+
+impl std::ops::Deref for Image {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl From<Vec<u8>> for Image {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self(bytes)
+    }
+}
+
+impl IntoPrimitiveValue for Image {
+    fn into_primitive_value(self) -> PrimitiveValue {
+        PrimitiveValue::Image(self.0)
+    }
+}
@@ -241,6 +241,9 @@ impl IoDefinitionType {
             PrimitiveValueType::Node => quote! {
                 PrimitiveValue::Node(inner)
             },
+            PrimitiveValueType::Image => quote! {
+                PrimitiveValue::Image(inner)
+            },
             PrimitiveValueType::Any => quote! {
                 inner
             },
@@ -328,6 +331,9 @@ impl ToTokens for IoDefinitionType {
             PrimitiveValueType::Node => quote! {
                 PrimitiveValueType::Node
             },
+            PrimitiveValueType::Image => quote! {
+                PrimitiveValueType::Image
+            },
             PrimitiveValueType::Any => quote! {
                 PrimitiveValueType::Any
             },
@@ -441,8 +447,10 @@ fn parse_primitive_value_type(ident: &Ident) -> syn::Result<PrimitiveValueType>
         Ok(PrimitiveValueType::Page)
     } else if ident == "Node" {
         Ok(PrimitiveValueType::Node)
+    } else if ident == "Image" {
+        Ok(PrimitiveValueType::Image)
     } else {
-        let error = format!("type {} not allowed. Inputs and outputs must be one of i64, String, ModelInstance, EmbeddingDatabase, Embedding, ModelType, bool, PrimitiveValue, Page, Node", ident);
+        let error = format!("type {} not allowed. Inputs and outputs must be one of i64, String, ModelInstance, EmbeddingDatabase, Embedding, ModelType, bool, PrimitiveValue, Page, Node, Image", ident);
         Err(Error::new_spanned(ident, error))
     }
 }
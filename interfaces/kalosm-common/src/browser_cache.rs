@@ -0,0 +1,667 @@
+//! The cache used when kalosm runs in the browser. Files are stored in the [origin private file system](https://developer.mozilla.org/en-US/docs/Web/API/File_System_API/Origin_private_file_system) (OPFS) instead of the file system and loaded into memory.
+//!
+//! The browser cache has the same methods as the native cache except [`Cache::get`](https://docs.rs/kalosm-common/latest/kalosm_common/struct.Cache.html#method.get), which returns a path on disk. Models that load their files with [`Cache::load`] work in both.
+//!
+//! OPFS can only be read asynchronously, so the cache keeps an index of the stored files. The index is read from OPFS the first time the cache is used asynchronously ([`Cache::load`] or [`Cache::download_size`]), and the synchronous methods only see files in the index.
+//!
+//! Models run on the CPU with wasm SIMD in the browser. Candle does not have a WebGPU backend, so there is no accelerated device.
+
+use js_sys::Uint8Array;
+use kalosm_model_types::{FileLoadingProgress, FileSource, InMemoryFile, Mirror};
+use reqwest::StatusCode;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{
+    File, FileSystemDirectoryHandle, FileSystemFileHandle, FileSystemGetDirectoryOptions,
+    FileSystemGetFileOptions, FileSystemWritableFileStream, Navigator,
+};
+
+#[path = "cache/in_memory.rs"]
+mod in_memory;
+pub use in_memory::{CachedFile, CachedFileReader};
+#[path = "cache/browser_scheduler.rs"]
+mod scheduler;
+pub use scheduler::DownloadScheduler;
+
+#[derive(Debug, thiserror::Error)]
+pub enum CacheError {
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("HTTP error: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("Unexpected status code: {0}")]
+    UnexpectedStatusCode(StatusCode),
+    #[error("The cache is in offline mode and these files have not been downloaded: {}", display_sources(.0))]
+    Offline(Vec<FileSource>),
+    #[error("Access to {0} was denied. If the file is in a gated or private Hugging Face repo, accept the license on the model page and provide a token with `Cache::with_huggingface_token` or `FileSource::with_token`")]
+    Unauthorized(String),
+    #[error(
+        "{0} can't be loaded in the browser. Use a Hugging Face, url or in memory source instead"
+    )]
+    Unsupported(String),
+    #[error("Browser storage error: {0}")]
+    Storage(String),
+}
+
+impl From<JsValue> for CacheError {
+    fn from(value: JsValue) -> Self {
+        Self::Storage(
+            value
+                .as_string()
+                .or_else(|| {
+                    value
+                        .dyn_ref::<js_sys::Error>()
+                        .map(|err| String::from(err.message()))
+                })
+                .unwrap_or_else(|| format!("{value:?}")),
+        )
+    }
+}
+
+fn display_sources(sources: &[FileSource]) -> String {
+    sources
+        .iter()
+        .map(|source| source.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// A cache for model files in the browser. Each file is stored under [`Cache::location`] in the origin private file system.
+#[derive(Debug, Clone)]
+pub struct Cache {
+    location: PathBuf,
+    /// The huggingface token to use
+    huggingface_token: Option<String>,
+    /// Limits how many files are downloaded at once
+    scheduler: DownloadScheduler,
+    /// If the cache should never make network requests
+    offline: bool,
+    /// Files embedded in the binary, keyed by the path the file they replace would be cached at
+    preloaded: HashMap<Vec<String>, InMemoryFile>,
+    /// The files stored in the origin private file system, or `None` if the index hasn't been read yet. Clones of the cache share the index
+    index: Arc<Mutex<Option<HashMap<Vec<String>, IndexedFile>>>>,
+}
+
+/// The metadata of a file in the index of a [`Cache`]
+#[derive(Debug, Clone, Copy)]
+struct IndexedFile {
+    size: u64,
+    last_modified: Option<SystemTime>,
+}
+
+/// A file stored in a [`Cache`]
+#[derive(Debug, Clone)]
+pub struct CacheEntry {
+    path: PathBuf,
+    relative_path: PathBuf,
+    size: u64,
+    last_used: Option<SystemTime>,
+}
+
+impl CacheEntry {
+    /// Get the path of the file in the origin private file system
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Get the path of the file relative to the root of the cache. For files downloaded from Hugging Face, this is `{model_id}/{revision}/{file}`
+    pub fn relative_path(&self) -> &Path {
+        &self.relative_path
+    }
+
+    /// Get the size of the file in bytes
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Get the last time the file was written. The origin private file system doesn't track when files are read
+    pub fn last_used(&self) -> Option<SystemTime> {
+        self.last_used
+    }
+
+    /// Check if the file is an incomplete download. Files are only written to the browser cache once they are fully downloaded, so this is always false
+    pub fn is_partial(&self) -> bool {
+        false
+    }
+}
+
+impl Default for Cache {
+    fn default() -> Self {
+        Self::new(PathBuf::from("kalosm").join("cache"))
+    }
+}
+
+impl Cache {
+    /// Create a new cache that stores files in the directory at the location in the origin private file system
+    pub fn new(location: PathBuf) -> Self {
+        Self {
+            location,
+            huggingface_token: None,
+            scheduler: DownloadScheduler::global().clone(),
+            offline: false,
+            preloaded: HashMap::new(),
+            index: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Create a new cache that stores files in the directory at the location in the origin private file system
+    pub fn at(location: impl Into<PathBuf>) -> Self {
+        Self::new(location.into())
+    }
+
+    /// Get the directory the cache stores files in
+    pub fn location(&self) -> &Path {
+        &self.location
+    }
+
+    /// Set the huggingface token to use when downloading files
+    pub fn with_huggingface_token(mut self, token: Option<String>) -> Self {
+        self.huggingface_token = token;
+        self
+    }
+
+    /// The browser downloads each file with a single request, so this has no effect. It exists so the same code can configure the native and browser caches
+    pub fn with_download_connections(self, _: usize) -> Self {
+        self
+    }
+
+    /// The browser downloads each file with a single request, so this has no effect. It exists so the same code can configure the native and browser caches
+    pub fn with_download_chunk_size(self, _: u64) -> Self {
+        self
+    }
+
+    /// Set the scheduler that limits how many files are downloaded at once. (defaults to [`DownloadScheduler::global`])
+    pub fn with_scheduler(mut self, scheduler: DownloadScheduler) -> Self {
+        self.scheduler = scheduler;
+        self
+    }
+
+    /// Get the scheduler that limits how many files are downloaded at once
+    pub fn scheduler(&self) -> &DownloadScheduler {
+        &self.scheduler
+    }
+
+    /// Set if the cache should never make network requests. Files that have not been downloaded fail to load with [`CacheError::Offline`]
+    pub fn with_offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    /// Check if the cache is in offline mode
+    pub fn is_offline(&self) -> bool {
+        self.offline
+    }
+
+    /// Preload a file, like weights embedded in the binary with `include_bytes!`. Whenever a model loads the source, the cache returns the preloaded file instead of the downloaded file
+    pub fn with_preloaded(mut self, source: FileSource, file: impl Into<InMemoryFile>) -> Self {
+        if let Some(path) = self.local_path(&source) {
+            self.preloaded.insert(path, file.into());
+        }
+        self
+    }
+
+    /// Get the sources that are not available locally (neither in memory, preloaded nor downloaded). Downloaded files are only known once the index is read, see the [module docs](self)
+    pub fn missing<'a>(
+        &self,
+        sources: impl IntoIterator<Item = &'a FileSource>,
+    ) -> Vec<FileSource> {
+        sources
+            .into_iter()
+            .filter(|source| !self.exists(source))
+            .cloned()
+            .collect()
+    }
+
+    /// In offline mode, make sure all of the sources are available locally. If any are missing, this returns a [`CacheError::Offline`] error that lists every missing file.
+    ///
+    /// Outside of offline mode, or before the index is read, this always succeeds. Missing files are reported by [`Cache::load`] instead
+    pub fn ensure_available<'a>(
+        &self,
+        sources: impl IntoIterator<Item = &'a FileSource>,
+    ) -> Result<(), CacheError> {
+        if !self.offline || self.index.lock().unwrap().is_none() {
+            return Ok(());
+        }
+        let missing = self.missing(sources);
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(CacheError::Offline(missing))
+        }
+    }
+
+    /// Get the number of bytes that need to be downloaded before all of the sources are available locally. The size of each missing file is read from the server without downloading it. Files that are already available or whose size the server doesn't report are not counted
+    pub async fn download_size<'a>(
+        &self,
+        sources: impl IntoIterator<Item = &'a FileSource>,
+    ) -> u64 {
+        self.read_index().await;
+        if self.offline {
+            return 0;
+        }
+        let client = reqwest::Client::new();
+        let mut size = 0;
+        for source in self.missing(sources) {
+            for (url, token) in self.download_urls(&source) {
+                let mut request = client.head(&url);
+                if let Some(token) = token {
+                    request =
+                        request.header(reqwest::header::AUTHORIZATION, format!("Bearer {token}"));
+                }
+                let length = request
+                    .send()
+                    .await
+                    .and_then(|response| response.error_for_status())
+                    .ok()
+                    .and_then(|response| response.content_length());
+                if let Some(length) = length {
+                    size += length;
+                    break;
+                }
+            }
+        }
+        size
+    }
+
+    /// Check if the file is in memory, preloaded or has been downloaded. Downloaded files are only known once the index is read, see the [module docs](self)
+    pub fn exists(&self, source: &FileSource) -> bool {
+        if matches!(source.primary(), FileSource::InMemory(_)) {
+            return true;
+        }
+        let Some(path) = self.local_path(source) else {
+            return false;
+        };
+        self.preloaded.contains_key(&path)
+            || self
+                .index
+                .lock()
+                .unwrap()
+                .as_ref()
+                .is_some_and(|index| index.contains_key(&path))
+    }
+
+    /// List every file in the index of the cache
+    pub fn entries(&self) -> Result<Vec<CacheEntry>, CacheError> {
+        let index = self.index.lock().unwrap();
+        let Some(index) = index.as_ref() else {
+            return Ok(Vec::new());
+        };
+        let root = self.location_segments().len();
+        Ok(index
+            .iter()
+            .map(|(path, file)| CacheEntry {
+                path: path.iter().collect(),
+                relative_path: path[root.min(path.len())..].iter().collect(),
+                size: file.size,
+                last_used: file.last_modified,
+            })
+            .collect())
+    }
+
+    /// Get the total size of every file in the index of the cache in bytes
+    pub fn size(&self) -> Result<u64, CacheError> {
+        Ok(self.entries()?.iter().map(|entry| entry.size).sum())
+    }
+
+    /// Remove a downloaded file. Returns true if the file was in the index of the cache
+    ///
+    /// The file is removed from the index immediately, and from the origin private file system in the background
+    pub fn remove(&self, source: &FileSource) -> Result<bool, CacheError> {
+        let Some(path) = self.local_path(source) else {
+            return Ok(false);
+        };
+        let removed = self.forget(&path);
+        self.remove_in_background(path);
+        Ok(removed)
+    }
+
+    /// Remove the least recently written files from the cache until the total size of the cache is at most `max_total_size` bytes. Returns the entries that were removed.
+    ///
+    /// The files are removed from the index immediately, and from the origin private file system in the background
+    pub fn gc(&self, max_total_size: u64) -> Result<Vec<CacheEntry>, CacheError> {
+        let mut entries = self.entries()?;
+        let mut total_size: u64 = entries.iter().map(|entry| entry.size).sum();
+        entries.sort_by_key(|entry| entry.last_used);
+
+        let mut removed = Vec::new();
+        for entry in entries {
+            if total_size <= max_total_size {
+                break;
+            }
+            let path: Vec<String> = entry
+                .path
+                .iter()
+                .map(|part| part.to_string_lossy().to_string())
+                .collect();
+            self.forget(&path);
+            self.remove_in_background(path);
+            total_size -= entry.size;
+            removed.push(entry);
+        }
+
+        Ok(removed)
+    }
+
+    /// Load the file into memory, downloading it if necessary. If the source has mirrors, each mirror is tried in order until the download succeeds
+    ///
+    /// Progress is not reported in the browser because [`FileLoadingProgress`] uses [`std::time::Instant`] which is not available on `wasm32-unknown-unknown`
+    pub async fn load(
+        &self,
+        source: &FileSource,
+        _: impl FnMut(FileLoadingProgress),
+    ) -> Result<CachedFile, CacheError> {
+        if let FileSource::InMemory(file) = source.primary() {
+            return Ok(CachedFile::InMemory(file.clone()));
+        }
+        self.read_index().await;
+        let path = self
+            .local_path(source)
+            .ok_or_else(|| CacheError::Unsupported(source.to_string()))?;
+        if let Some(file) = self.preloaded.get(&path) {
+            return Ok(CachedFile::InMemory(file.clone()));
+        }
+
+        if let Ok(handle) = self.file_handle(&path, false).await {
+            let file: File = JsFuture::from(handle.get_file()).await?.unchecked_into();
+            let bytes = Uint8Array::new(&JsFuture::from(file.array_buffer()).await?).to_vec();
+            return Ok(CachedFile::InMemory(bytes.into()));
+        }
+        if self.offline {
+            return Err(CacheError::Offline(vec![source.clone()]));
+        }
+
+        let _permit = self.scheduler.acquire().await;
+        let mut last_error = None;
+        for (url, token) in self.download_urls(source) {
+            match download(&url, token).await {
+                Ok(bytes) => {
+                    // Failing to store the file only means it will be downloaded again next time
+                    match self.store(&path, &bytes).await {
+                        Ok(()) => self.record(
+                            path.clone(),
+                            IndexedFile {
+                                size: bytes.len() as u64,
+                                last_modified: None,
+                            },
+                        ),
+                        Err(err) => {
+                            tracing::warn!("Failed to store {source} in the browser cache: {err}")
+                        }
+                    }
+                    return Ok(CachedFile::InMemory(bytes.into()));
+                }
+                Err(err) => {
+                    tracing::warn!("Failed to fetch {source} from {url}: {err}");
+                    last_error = Some(err);
+                }
+            }
+        }
+
+        Err(last_error.expect("remote sources always have at least one location"))
+    }
+
+    /// Read the index of stored files from the origin private file system if it hasn't been read yet
+    async fn read_index(&self) {
+        if self.index.lock().unwrap().is_some() {
+            return;
+        }
+        let root = self.location_segments();
+        let mut files = HashMap::new();
+        // The cache directory doesn't exist until the first file is stored
+        if let Ok(directory) = self.directory_handle(&root, false).await {
+            if let Err(err) = list_files(directory, root, &mut files).await {
+                tracing::warn!("Failed to read the browser cache: {err}");
+            }
+        }
+        let mut index = self.index.lock().unwrap();
+        // Another clone of the cache may have read the index while this one was reading
+        let index = index.get_or_insert_with(HashMap::new);
+        for (path, file) in files {
+            index.entry(path).or_insert(file);
+        }
+    }
+
+    /// Add a stored file to the index if the index has been read
+    fn record(&self, path: Vec<String>, file: IndexedFile) {
+        if let Some(index) = self.index.lock().unwrap().as_mut() {
+            index.insert(path, file);
+        }
+    }
+
+    /// Remove a file from the index. Returns true if the file was in the index
+    fn forget(&self, path: &[String]) -> bool {
+        self.index
+            .lock()
+            .unwrap()
+            .as_mut()
+            .is_some_and(|index| index.remove(path).is_some())
+    }
+
+    /// Remove a file from the origin private file system without waiting for it to finish
+    fn remove_in_background(&self, path: Vec<String>) {
+        let cache = self.clone();
+        wasm_bindgen_futures::spawn_local(async move {
+            let Some((file_name, directories)) = path.split_last() else {
+                return;
+            };
+            if let Ok(directory) = cache.directory_handle(directories, false).await {
+                // The file may have already been removed by another tab
+                let _ = JsFuture::from(directory.remove_entry(file_name)).await;
+            }
+        });
+    }
+
+    /// Get the path segments of the cache location
+    fn location_segments(&self) -> Vec<String> {
+        self.location
+            .iter()
+            .map(|part| part.to_string_lossy().to_string())
+            .filter(|part| !matches!(part.as_str(), "" | "." | ".." | "/"))
+            .collect()
+    }
+
+    /// Get the path segments the file is stored at in the origin private file system, or `None` if the source can't be downloaded in the browser
+    fn local_path(&self, source: &FileSource) -> Option<Vec<String>> {
+        let relative = match source.primary() {
+            FileSource::HuggingFace {
+                model_id,
+                revision,
+                file,
+            } => [model_id.as_str(), revision.as_str(), file.as_str()]
+                .iter()
+                .flat_map(|part| part.split('/'))
+                .map(ToString::to_string)
+                .collect(),
+            FileSource::Url(url) => url_cache_path(url),
+            _ => return None,
+        };
+        let mut path = self.location_segments();
+        path.extend(relative);
+        path.retain(|part| !matches!(part.as_str(), "" | "." | ".."));
+        Some(path)
+    }
+
+    /// Get the URLs a source can be downloaded from in the order they should be tried along with the token to send to each URL
+    fn download_urls(&self, source: &FileSource) -> Vec<(String, Option<String>)> {
+        let primary = source.primary();
+        let mut urls = Vec::new();
+        match primary {
+            FileSource::HuggingFace {
+                model_id,
+                revision,
+                file,
+            } => {
+                let token = source
+                    .token()
+                    .map(ToString::to_string)
+                    .or_else(|| self.huggingface_token.clone());
+                let url = huggingface_url(HUGGINGFACE_ENDPOINT, model_id, revision, file);
+                urls.push((url, token));
+            }
+            FileSource::Url(url) => {
+                urls.push((url.clone(), source.token().map(ToString::to_string)))
+            }
+            _ => {}
+        }
+        // The Hugging Face token is never sent to mirrors because they may be run by a third party
+        for mirror in source.mirrors() {
+            match (mirror, primary) {
+                (
+                    Mirror::HuggingFaceEndpoint(endpoint),
+                    FileSource::HuggingFace {
+                        model_id,
+                        revision,
+                        file,
+                    },
+                ) => urls.push((huggingface_url(endpoint, model_id, revision, file), None)),
+                (Mirror::HuggingFaceEndpoint(_), _) => {}
+                (Mirror::Url(url), _) => urls.push((url.clone(), None)),
+            }
+        }
+        urls
+    }
+
+    /// Write a downloaded file to the origin private file system
+    async fn store(&self, path: &[String], bytes: &[u8]) -> Result<(), CacheError> {
+        let handle = self.file_handle(path, true).await?;
+        let writable: FileSystemWritableFileStream = JsFuture::from(handle.create_writable())
+            .await?
+            .unchecked_into();
+        // The file is only replaced when the stream is closed, so other tabs never see a partially written file
+        JsFuture::from(writable.write_with_u8_array(bytes)?).await?;
+        JsFuture::from(writable.close()).await?;
+        Ok(())
+    }
+
+    async fn file_handle(
+        &self,
+        path: &[String],
+        create: bool,
+    ) -> Result<FileSystemFileHandle, CacheError> {
+        let (file_name, directories) = path
+            .split_last()
+            .ok_or_else(|| CacheError::Storage("empty path".to_string()))?;
+        let directory = self.directory_handle(directories, create).await?;
+        let options = FileSystemGetFileOptions::new();
+        options.set_create(create);
+        Ok(
+            JsFuture::from(directory.get_file_handle_with_options(file_name, &options))
+                .await?
+                .unchecked_into(),
+        )
+    }
+
+    async fn directory_handle(
+        &self,
+        directories: &[String],
+        create: bool,
+    ) -> Result<FileSystemDirectoryHandle, CacheError> {
+        let mut directory: FileSystemDirectoryHandle =
+            JsFuture::from(navigator()?.storage().get_directory())
+                .await?
+                .unchecked_into();
+        let options = FileSystemGetDirectoryOptions::new();
+        options.set_create(create);
+        for name in directories {
+            directory = JsFuture::from(directory.get_directory_handle_with_options(name, &options))
+                .await?
+                .unchecked_into();
+        }
+        Ok(directory)
+    }
+}
+
+/// Add every file in the directory and its subdirectories to the index. `path` is the path segments of the directory
+async fn list_files(
+    directory: FileSystemDirectoryHandle,
+    path: Vec<String>,
+    files: &mut HashMap<Vec<String>, IndexedFile>,
+) -> Result<(), CacheError> {
+    let mut directories = vec![(directory, path)];
+    while let Some((directory, path)) = directories.pop() {
+        let handles = directory.values();
+        loop {
+            let next: js_sys::IteratorNext =
+                JsFuture::from(handles.next()?).await?.unchecked_into();
+            if next.done() {
+                break;
+            }
+            let handle = next.value();
+            let name = js_sys::Reflect::get(&handle, &JsValue::from_str("name"))?
+                .as_string()
+                .unwrap_or_default();
+            let mut child = path.clone();
+            child.push(name);
+            if let Some(file_handle) = handle.dyn_ref::<FileSystemFileHandle>() {
+                let file: File = JsFuture::from(file_handle.get_file())
+                    .await?
+                    .unchecked_into();
+                let last_modified =
+                    SystemTime::UNIX_EPOCH + Duration::from_millis(file.last_modified() as u64);
+                files.insert(
+                    child,
+                    IndexedFile {
+                        size: file.size() as u64,
+                        last_modified: Some(last_modified),
+                    },
+                );
+            } else if let Ok(child_directory) = handle.dyn_into::<FileSystemDirectoryHandle>() {
+                directories.push((child_directory, child));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Get the navigator of the window or worker kalosm is running in. Both expose the same storage API
+fn navigator() -> Result<Navigator, CacheError> {
+    let navigator = js_sys::Reflect::get(&js_sys::global(), &JsValue::from_str("navigator"))?;
+    if navigator.is_undefined() {
+        return Err(CacheError::Storage(
+            "the origin private file system is only available in a window or worker".to_string(),
+        ));
+    }
+    Ok(navigator.unchecked_into())
+}
+
+async fn download(url: &str, token: Option<String>) -> Result<Vec<u8>, CacheError> {
+    let mut request = reqwest::Client::new().get(url);
+    if let Some(token) = token {
+        request = request.header(reqwest::header::AUTHORIZATION, format!("Bearer {token}"));
+    }
+    let response = request.send().await?;
+    match response.status() {
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+            return Err(CacheError::Unauthorized(url.to_string()))
+        }
+        status if !status.is_success() => return Err(CacheError::UnexpectedStatusCode(status)),
+        _ => {}
+    }
+    Ok(response.bytes().await?.to_vec())
+}
+
+/// Environment variables are not available in the browser, so files are always downloaded from the main Hugging Face endpoint unless a mirror is set
+const HUGGINGFACE_ENDPOINT: &str = "https://huggingface.co";
+
+fn huggingface_url(endpoint: &str, model_id: &str, revision: &str, file: &str) -> String {
+    let endpoint = endpoint.trim_end_matches('/');
+    let revision = revision.replace('/', "%2F");
+    format!("{endpoint}/{model_id}/resolve/{revision}/{file}")
+}
+
+/// Get the path segments a url is downloaded to relative to the cache location
+fn url_cache_path(url: &str) -> Vec<String> {
+    let mut path = vec!["urls".to_string()];
+    match reqwest::Url::parse(url) {
+        Ok(url) => {
+            path.push(url.host_str().unwrap_or("unknown").to_string());
+            path.extend(url.path_segments().into_iter().flatten().map(String::from));
+        }
+        Err(_) => path.push(url.replace(['/', '\\', ':'], "_")),
+    }
+    path
+}
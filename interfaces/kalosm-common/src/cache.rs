@@ -1,15 +1,24 @@
-use hf_hub::{Repo, RepoType};
+use futures_util::StreamExt;
 use httpdate::parse_http_date;
-use kalosm_model_types::{FileLoadingProgress, FileSource};
+use kalosm_model_types::{FileLoadingProgress, FileSource, InMemoryFile, Mirror};
 use reqwest::{
-    header::{HeaderValue, CONTENT_LENGTH, LAST_MODIFIED, RANGE},
+    header::{HeaderValue, ACCEPT_RANGES, CONTENT_LENGTH, ETAG, IF_RANGE, LAST_MODIFIED, RANGE},
     IntoUrl,
 };
 use reqwest::{Response, StatusCode};
-use std::path::PathBuf;
+use std::collections::{HashMap, HashSet};
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::time::SystemTime;
 use tokio::fs::{File, OpenOptions};
-use tokio::io::AsyncWriteExt;
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+
+mod cloud;
+mod in_memory;
+pub use in_memory::{CachedFile, CachedFileReader};
+mod scheduler;
+pub use scheduler::DownloadScheduler;
 
 #[derive(Debug, thiserror::Error)]
 pub enum CacheError {
@@ -23,6 +32,25 @@ pub enum CacheError {
     Http(#[from] reqwest::Error),
     #[error("Unexpected status code: {0}")]
     UnexpectedStatusCode(StatusCode),
+    #[error("Download ended early: expected {expected} bytes but received {received}")]
+    IncompleteDownload { expected: u64, received: u64 },
+    #[error("The cache is in offline mode and these files have not been downloaded: {}", display_sources(.0))]
+    Offline(Vec<FileSource>),
+    #[error("Access to {0} was denied. If the file is in a gated or private Hugging Face repo, accept the license on the model page and provide a token with the `HF_TOKEN` environment variable, `Cache::with_huggingface_token` or `FileSource::with_token`")]
+    Unauthorized(String),
+    #[error("Downloading {file} requires the `{feature}` feature of kalosm-common")]
+    MissingFeature { file: String, feature: &'static str },
+    #[cfg(any(feature = "s3", feature = "gcs", feature = "azure"))]
+    #[error("Object store error: {0}")]
+    ObjectStore(#[from] object_store::Error),
+}
+
+fn display_sources(sources: &[FileSource]) -> String {
+    sources
+        .iter()
+        .map(|source| source.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
 }
 
 #[derive(Debug, Clone)]
@@ -30,6 +58,67 @@ pub struct Cache {
     location: PathBuf,
     /// The huggingface token to use (defaults to the token set with `huggingface-cli login`)
     huggingface_token: Option<String>,
+    /// How large files are split up between connections
+    download: DownloadConfig,
+    /// If the cache should never make network requests
+    offline: bool,
+    /// Files embedded in the binary, keyed by the path the file they replace would be cached at
+    preloaded: HashMap<PathBuf, InMemoryFile>,
+}
+
+/// A file stored in a [`Cache`]
+#[derive(Debug, Clone)]
+pub struct CacheEntry {
+    path: PathBuf,
+    relative_path: PathBuf,
+    size: u64,
+    last_used: Option<SystemTime>,
+    partial: bool,
+}
+
+impl CacheEntry {
+    /// Get the full path of the file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Get the path of the file relative to the root of the cache. For files downloaded from Hugging Face, this is `{model_id}/{revision}/{file}`
+    pub fn relative_path(&self) -> &Path {
+        &self.relative_path
+    }
+
+    /// Get the size of the file in bytes
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Get the last time the file was loaded from the cache, if the file system supports it
+    pub fn last_used(&self) -> Option<SystemTime> {
+        self.last_used
+    }
+
+    /// Check if the file is an incomplete download
+    pub fn is_partial(&self) -> bool {
+        self.partial
+    }
+}
+
+/// How large files are split into chunks and downloaded over multiple connections
+#[derive(Debug, Clone)]
+struct DownloadConfig {
+    connections: usize,
+    chunk_size: u64,
+    scheduler: DownloadScheduler,
+}
+
+impl Default for DownloadConfig {
+    fn default() -> Self {
+        Self {
+            connections: 4,
+            chunk_size: 32 * 1024 * 1024,
+            scheduler: DownloadScheduler::global().clone(),
+        }
+    }
 }
 
 impl Cache {
@@ -38,115 +127,526 @@ impl Cache {
         Self {
             location,
             huggingface_token: None,
+            download: DownloadConfig::default(),
+            offline: offline_from_env(),
+            preloaded: HashMap::new(),
         }
     }
 
-    /// Set the Hugging Face token to use for downloading (defaults to the token set with `huggingface-cli login`, and then the environment variable `HF_TOKEN`)
+    /// Create a new cache that stores files in the given directory
+    ///
+    /// # Example
+    /// ```rust, no_run
+    /// use kalosm_common::Cache;
+    ///
+    /// let cache = Cache::at("./models");
+    /// assert_eq!(cache.location(), std::path::Path::new("./models"));
+    /// ```
+    pub fn at(location: impl Into<PathBuf>) -> Self {
+        Self::new(location.into())
+    }
+
+    /// Get the directory the cache stores files in
+    pub fn location(&self) -> &Path {
+        &self.location
+    }
+
+    /// Set the Hugging Face token to use for downloading (defaults to the environment variable `HF_TOKEN`, and then the token set with `huggingface-cli login`)
+    ///
+    /// A token set on an individual source with [`FileSource::with_token`] takes precedence over this token.
     pub fn with_huggingface_token(mut self, token: Option<String>) -> Self {
         self.huggingface_token = token;
         self
     }
 
-    /// Check if the file exists locally (if it is a local file or if it has been downloaded)
+    /// Set the number of connections used to download a single large file in parallel. (defaults to 4)
+    ///
+    /// Files larger than the chunk size are split into chunks that are downloaded over this many connections at once if the server supports range requests. Setting this to 1 downloads every file over a single connection.
+    pub fn with_download_connections(mut self, connections: usize) -> Self {
+        self.download.connections = connections.max(1);
+        self
+    }
+
+    /// Set the size of the chunks large files are split into when they are downloaded in parallel. (defaults to 32 MiB)
+    pub fn with_download_chunk_size(mut self, chunk_size: u64) -> Self {
+        self.download.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    /// Set the scheduler that limits how many files are downloaded at once and how much bandwidth downloads use. (defaults to [`DownloadScheduler::global`])
+    ///
+    /// Caches that share a scheduler share its limits. Use a separate [`DownloadScheduler::new`] to give this cache its own limits.
+    pub fn with_scheduler(mut self, scheduler: DownloadScheduler) -> Self {
+        self.download.scheduler = scheduler;
+        self
+    }
+
+    /// Get the scheduler this cache downloads files with
+    pub fn scheduler(&self) -> &DownloadScheduler {
+        &self.download.scheduler
+    }
+
+    /// Set if the cache is in offline mode. (defaults to true if the environment variable `KALOSM_OFFLINE` or `HF_HUB_OFFLINE` is set to `1` or `true`)
+    ///
+    /// In offline mode the cache never makes network requests. Files that are already downloaded are used without checking for updates, and any file that is missing causes an [`CacheError::Offline`] error instead of a download.
+    pub fn with_offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    /// Check if the cache is in offline mode
+    pub fn is_offline(&self) -> bool {
+        self.offline
+    }
+
+    /// Preload a file, like weights embedded in the binary with `include_bytes!`. Whenever a model loads the source, the cache returns the preloaded file instead of the downloaded file. Preloaded files are never written to the cache
+    ///
+    /// Unlike [`FileSource::from_bytes`], preloading works with the default sources of a model, so a small model can be shipped in a single binary that never touches the network or the cache directory.
+    ///
+    /// # Example
+    /// ```rust, ignore
+    /// use kalosm_common::Cache;
+    /// use kalosm_model_types::FileSource;
+    ///
+    /// let cache = Cache::default().with_preloaded(
+    ///     FileSource::huggingface("openai/whisper-tiny", "main", "model.safetensors"),
+    ///     include_bytes!("../models/whisper-tiny/model.safetensors"),
+    /// );
+    /// ```
+    pub fn with_preloaded(mut self, source: FileSource, file: impl Into<InMemoryFile>) -> Self {
+        let path = self.local_path(&source);
+        self.preloaded.insert(path, file.into());
+        self
+    }
+
+    /// Get the preloaded file for a source if there is one
+    fn preloaded(&self, source: &FileSource) -> Option<&InMemoryFile> {
+        if self.preloaded.is_empty() {
+            return None;
+        }
+        self.preloaded.get(&self.local_path(source))
+    }
+
+    /// Get the sources that are not available locally (neither local files nor downloaded files)
+    pub fn missing<'a>(
+        &self,
+        sources: impl IntoIterator<Item = &'a FileSource>,
+    ) -> Vec<FileSource> {
+        sources
+            .into_iter()
+            .filter(|source| !self.exists(source))
+            .cloned()
+            .collect()
+    }
+
+    /// In offline mode, make sure all of the sources are available locally. If any are missing, this returns a [`CacheError::Offline`] error that lists every missing file.
+    ///
+    /// Builders that load multiple files call this before downloading anything so they can fail fast. Outside of offline mode this always succeeds.
+    pub fn ensure_available<'a>(
+        &self,
+        sources: impl IntoIterator<Item = &'a FileSource>,
+    ) -> Result<(), CacheError> {
+        if !self.offline {
+            return Ok(());
+        }
+        let missing = self.missing(sources);
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(CacheError::Offline(missing))
+        }
+    }
+
+    /// Get the number of bytes that need to be downloaded before all of the sources are available locally. The size of each missing file is read from the server without downloading it. Files that are already available or whose size the server doesn't report are not counted
+    ///
+    /// Builders that load multiple files use this to seed [`ModelLoadingProgress::aggregate_with_size`](kalosm_model_types::ModelLoadingProgress::aggregate_with_size) so the total size is known before the first file starts downloading.
+    pub async fn download_size<'a>(
+        &self,
+        sources: impl IntoIterator<Item = &'a FileSource>,
+    ) -> u64 {
+        if self.offline {
+            return 0;
+        }
+        let client = reqwest::Client::new();
+        let mut size = 0;
+        for source in self.missing(sources) {
+            for (url, token) in self.download_urls(&source) {
+                let length = client
+                    .head(&url)
+                    .with_authorization_header(token)
+                    .send()
+                    .await
+                    .and_then(|response| response.error_for_status())
+                    .ok()
+                    .and_then(|response| {
+                        response
+                            .headers()
+                            .get(CONTENT_LENGTH)
+                            .and_then(|length| length.to_str().ok())
+                            .and_then(|length| u64::from_str(length).ok())
+                    });
+                if let Some(length) = length {
+                    size += length;
+                    break;
+                }
+            }
+        }
+        size
+    }
+
+    /// Check if the file exists locally (if it is a local file, a preloaded file or if it has been downloaded)
     pub fn exists(&self, source: &FileSource) -> bool {
+        matches!(source.primary(), FileSource::InMemory(_))
+            || self.preloaded(source).is_some()
+            || self.local_path(source).exists()
+    }
+
+    /// Get the path the file is stored at locally. For remote sources this is the path the file is downloaded to
+    fn local_path(&self, source: &FileSource) -> PathBuf {
         match source {
             FileSource::HuggingFace {
                 model_id,
                 revision,
                 file,
-                ..
-            } => {
-                let path = self.location.join(model_id).join(revision);
-                let complete_download = path.join(file);
-                complete_download.exists()
+            } => self.location.join(model_id).join(revision).join(file),
+            FileSource::Local(path) => path.clone(),
+            FileSource::Url(url) => self.location.join(url_cache_path(url)),
+            FileSource::InMemory(file) => in_memory::materialized_path(file),
+            FileSource::S3 { bucket, key } => self.location.join("s3").join(bucket).join(key),
+            FileSource::Gcs { bucket, object } => {
+                self.location.join("gcs").join(bucket).join(object)
+            }
+            FileSource::AzureBlob {
+                account,
+                container,
+                blob,
+            } => self
+                .location
+                .join("azure")
+                .join(account)
+                .join(container)
+                .join(blob),
+            FileSource::Mirrored { source, .. } | FileSource::Authenticated { source, .. } => {
+                self.local_path(source)
             }
-            FileSource::Local(path) => path.exists(),
         }
     }
 
-    /// Get the file from the cache, downloading it if necessary
-    pub async fn get(
-        &self,
-        source: &FileSource,
-        progress: impl FnMut(FileLoadingProgress),
-    ) -> Result<PathBuf, CacheError> {
-        match source {
+    /// Get the URLs a source can be downloaded from in the order they should be tried along with the token to send to each URL
+    fn download_urls(&self, source: &FileSource) -> Vec<(String, Option<String>)> {
+        let primary = source.primary();
+        let mut urls = Vec::new();
+        match primary {
             FileSource::HuggingFace {
                 model_id,
                 revision,
                 file,
             } => {
-                let token = self.huggingface_token.clone().or_else(huggingface_token);
+                let token = source
+                    .token()
+                    .map(ToString::to_string)
+                    .or_else(|| self.huggingface_token.clone())
+                    .or_else(huggingface_token);
+                let url = huggingface_url(&huggingface_endpoint(), model_id, revision, file);
+                urls.push((url, token));
+            }
+            FileSource::Url(url) => {
+                urls.push((url.clone(), source.token().map(ToString::to_string)))
+            }
+            // Object store sources are downloaded with the object store client instead of a url
+            _ => {}
+        }
+        // The Hugging Face token is never sent to mirrors because they may be run by a third party
+        for mirror in source.mirrors() {
+            match (mirror, primary) {
+                (
+                    Mirror::HuggingFaceEndpoint(endpoint),
+                    FileSource::HuggingFace {
+                        model_id,
+                        revision,
+                        file,
+                    },
+                ) => urls.push((huggingface_url(endpoint, model_id, revision, file), None)),
+                (Mirror::HuggingFaceEndpoint(_), _) => {}
+                (Mirror::Url(url), _) => urls.push((url.clone(), None)),
+            }
+        }
+        urls
+    }
 
-                let path = self.location.join(model_id).join(revision);
-                let complete_download = path.join(file);
+    /// List every file in the cache, including partial downloads
+    ///
+    /// # Example
+    /// ```rust, no_run
+    /// use kalosm_common::Cache;
+    ///
+    /// let cache = Cache::default();
+    /// for entry in cache.entries().unwrap() {
+    ///     println!("{} ({} bytes)", entry.relative_path().display(), entry.size());
+    /// }
+    /// ```
+    pub fn entries(&self) -> Result<Vec<CacheEntry>, CacheError> {
+        let mut entries = Vec::new();
+        if !self.location.exists() {
+            return Ok(entries);
+        }
 
-                let repo = Repo::with_revision(
-                    model_id.to_string(),
-                    RepoType::Model,
-                    revision.to_string(),
-                );
-                let api = hf_hub::api::sync::Api::new()?.repo(repo);
-                let url = api.url(file);
-                let client = reqwest::Client::new();
-                tracing::trace!("Fetching metadata for {file} from {url}");
-                let response = client
-                    .head(&url)
-                    .with_authorization_header(token.clone())
-                    .send()
-                    .await;
-
-                if complete_download.exists() {
-                    let metadata = tokio::fs::metadata(&complete_download).await.map_err(|e| {
-                        CacheError::UnableToGetFileMetadata(complete_download.clone(), e)
-                    })?;
-                    let file_last_modified = metadata.modified()?;
-                    // If the server says the file hasn't been modified since we downloaded it, we can use the local file
-                    if let Some(last_updated) = response
-                        .as_ref()
-                        .ok()
-                        .and_then(|response| response.headers().get(LAST_MODIFIED))
-                        .and_then(|last_updated| last_updated.to_str().ok())
-                        .and_then(|s| parse_http_date(s).ok())
-                    {
-                        if last_updated <= file_last_modified {
-                            return Ok(complete_download);
-                        }
-                    } else {
-                        // Or if we are offline, we can use the local file
-                        return Ok(complete_download);
-                    }
+        let mut directories = vec![self.location.clone()];
+        while let Some(directory) = directories.pop() {
+            for item in std::fs::read_dir(&directory)? {
+                let item = item?;
+                let path = item.path();
+                let metadata = item.metadata()?;
+                if metadata.is_dir() {
+                    directories.push(path);
+                    continue;
                 }
-                let incomplete_download = path.join(format!("{}.partial", file));
-
-                tracing::trace!("Downloading into {:?}", incomplete_download);
-
-                download_into(
-                    url,
-                    &incomplete_download,
-                    response?,
-                    client,
-                    token,
-                    progress,
-                )
-                .await?;
+                let extension = path.extension().and_then(|extension| extension.to_str());
+                // Marker files are part of the partial download they sit next to
+                if matches!(extension, Some("validator" | "chunks")) {
+                    continue;
+                }
+                let relative_path = path
+                    .strip_prefix(&self.location)
+                    .unwrap_or(&path)
+                    .to_path_buf();
+                entries.push(CacheEntry {
+                    partial: extension == Some("partial"),
+                    size: metadata.len(),
+                    last_used: metadata.accessed().or_else(|_| metadata.modified()).ok(),
+                    relative_path,
+                    path,
+                });
+            }
+        }
+
+        Ok(entries)
+    }
+
+    /// Get the total size of every file in the cache in bytes
+    pub fn size(&self) -> Result<u64, CacheError> {
+        Ok(self.entries()?.iter().map(|entry| entry.size).sum())
+    }
+
+    /// Remove a file and any partial download of the file from the cache. Returns true if anything was removed.
+    ///
+    /// Local files are never removed because they are not managed by the cache.
+    pub fn remove(&self, source: &FileSource) -> Result<bool, CacheError> {
+        if let FileSource::Local(_) = source.primary() {
+            return Ok(false);
+        }
+        let complete_download = self.local_path(source);
+        let incomplete_download = marker_path(&complete_download, "partial");
+        let mut removed = remove_if_exists(&complete_download)?;
+        removed |= remove_if_exists(&incomplete_download)?;
+        remove_markers(&incomplete_download)?;
+        self.remove_empty_directories(&complete_download);
+        Ok(removed)
+    }
+
+    /// Remove the least recently used files from the cache until the total size of the cache is at most `max_total_size` bytes. Returns the entries that were removed.
+    ///
+    /// # Example
+    /// ```rust, no_run
+    /// use kalosm_common::Cache;
+    ///
+    /// // Keep at most 10GB of models on disk
+    /// let removed = Cache::default().gc(10_000_000_000).unwrap();
+    /// println!("Removed {} files", removed.len());
+    /// ```
+    pub fn gc(&self, max_total_size: u64) -> Result<Vec<CacheEntry>, CacheError> {
+        let mut entries = self.entries()?;
+        let mut total_size: u64 = entries.iter().map(|entry| entry.size).sum();
+        entries.sort_by_key(|entry| entry.last_used);
+
+        let mut removed = Vec::new();
+        for entry in entries {
+            if total_size <= max_total_size {
+                break;
+            }
+            remove_if_exists(&entry.path)?;
+            if entry.partial {
+                remove_markers(&entry.path)?;
+            }
+            self.remove_empty_directories(&entry.path);
+            total_size -= entry.size;
+            removed.push(entry);
+        }
+
+        Ok(removed)
+    }
+
+    /// Remove the directories between a removed file and the root of the cache if they are empty
+    fn remove_empty_directories(&self, file: &Path) {
+        let mut directory = file.parent();
+        while let Some(current) = directory {
+            if current == self.location || !current.starts_with(&self.location) {
+                break;
+            }
+            // This fails if the directory is not empty
+            if std::fs::remove_dir(current).is_err() {
+                break;
+            }
+            directory = current.parent();
+        }
+    }
+
+    /// Load the file, downloading it if necessary. Unlike [`Cache::get`], in memory sources are returned without writing them to disk
+    pub async fn load(
+        &self,
+        source: &FileSource,
+        progress: impl FnMut(FileLoadingProgress),
+    ) -> Result<CachedFile, CacheError> {
+        if let Some(file) = self.preloaded(source) {
+            return Ok(CachedFile::InMemory(file.clone()));
+        }
+        match source.primary() {
+            FileSource::InMemory(file) => Ok(CachedFile::InMemory(file.clone())),
+            _ => self.get(source, progress).await.map(CachedFile::Path),
+        }
+    }
 
-                // Rename the file to remove the .partial extension
-                tokio::fs::rename(&incomplete_download, &complete_download).await?;
+    /// Get the file from the cache, downloading it if necessary. If the source has mirrors, each mirror is tried in order until the download succeeds
+    ///
+    /// In memory sources are written to a temporary file outside of the cache. Use [`Cache::load`] to load them without touching the file system
+    pub async fn get(
+        &self,
+        source: &FileSource,
+        mut progress: impl FnMut(FileLoadingProgress),
+    ) -> Result<PathBuf, CacheError> {
+        match source.primary() {
+            FileSource::Local(path) => return Ok(path.clone()),
+            FileSource::InMemory(file) => return Ok(in_memory::materialize(file).await?),
+            _ => {}
+        }
+        if let Some(file) = self.preloaded(source) {
+            return Ok(in_memory::materialize(file).await?);
+        }
+        let complete_download = self.local_path(source);
 
+        if self.offline {
+            return if complete_download.exists() {
+                mark_used(&complete_download);
                 Ok(complete_download)
+            } else {
+                Err(CacheError::Offline(vec![source.clone()]))
+            };
+        }
+
+        let mut last_error = None;
+        if cloud::required_feature(source.primary()).is_some() {
+            match cloud::get_object(
+                source.primary(),
+                &complete_download,
+                &self.download.scheduler,
+                &mut progress,
+            )
+            .await
+            {
+                Ok(path) => return Ok(path),
+                Err(err) => {
+                    tracing::warn!("Failed to fetch {source}: {err}");
+                    last_error = Some(err);
+                }
+            }
+        }
+        for (url, token) in self.download_urls(source) {
+            match self
+                .get_from_url(&url, token, &complete_download, &mut progress)
+                .await
+            {
+                Ok(path) => return Ok(path),
+                Err(err) => {
+                    tracing::warn!("Failed to fetch {source} from {url}: {err}");
+                    last_error = Some(err);
+                }
+            }
+        }
+
+        Err(last_error.expect("remote sources always have at least one location"))
+    }
+
+    /// Get the file from the cache, downloading it from the url if the cached file is missing or out of date
+    async fn get_from_url(
+        &self,
+        url: &str,
+        token: Option<String>,
+        complete_download: &Path,
+        progress: impl FnMut(FileLoadingProgress),
+    ) -> Result<PathBuf, CacheError> {
+        let client = reqwest::Client::new();
+        tracing::trace!("Fetching metadata from {url}");
+        let response = client
+            .head(url)
+            .with_authorization_header(token.clone())
+            .send()
+            .await;
+        let denied = response.as_ref().is_ok_and(|response| {
+            matches!(
+                response.status(),
+                StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN
+            )
+        });
+        if denied && !complete_download.exists() {
+            return Err(CacheError::Unauthorized(url.to_string()));
+        }
+        let response = response.and_then(|response| response.error_for_status());
+
+        if complete_download.exists() {
+            let metadata = tokio::fs::metadata(&complete_download)
+                .await
+                .map_err(|e| CacheError::UnableToGetFileMetadata(complete_download.into(), e))?;
+            let file_last_modified = metadata.modified()?;
+            // If the server says the file hasn't been modified since we downloaded it, we can use the local file
+            if let Some(last_updated) = response
+                .as_ref()
+                .ok()
+                .and_then(|response| response.headers().get(LAST_MODIFIED))
+                .and_then(|last_updated| last_updated.to_str().ok())
+                .and_then(|s| parse_http_date(s).ok())
+            {
+                if last_updated <= file_last_modified {
+                    mark_used(complete_download);
+                    return Ok(complete_download.into());
+                }
+            } else {
+                // Or if we are offline, we can use the local file
+                mark_used(complete_download);
+                return Ok(complete_download.into());
             }
-            FileSource::Local(path) => Ok(path.clone()),
         }
+        let incomplete_download = marker_path(complete_download, "partial");
+
+        let response = response?;
+        let _permit = self.download.scheduler.acquire().await;
+        tracing::trace!("Downloading into {:?}", incomplete_download);
+
+        download_into(
+            url,
+            &incomplete_download,
+            response,
+            client,
+            token,
+            self.download.clone(),
+            progress,
+        )
+        .await?;
+
+        // Rename the file to remove the .partial extension
+        tokio::fs::rename(&incomplete_download, &complete_download).await?;
+
+        Ok(complete_download.into())
     }
 }
 
 impl Default for Cache {
+    /// Create a cache in the directory set by the `KALOSM_CACHE_DIR` environment variable, or DATA_DIR/kalosm/cache if it is not set
     fn default() -> Self {
-        Self {
-            location: dirs::data_dir().unwrap().join("kalosm").join("cache"),
-            huggingface_token: None,
-        }
+        let location = match std::env::var_os("KALOSM_CACHE_DIR") {
+            Some(location) if !location.is_empty() => PathBuf::from(location),
+            _ => dirs::data_dir().unwrap().join("kalosm").join("cache"),
+        };
+        Self::new(location)
     }
 }
 
@@ -156,63 +656,124 @@ async fn download_into<U: IntoUrl>(
     head: Response,
     client: reqwest::Client,
     token: Option<String>,
+    config: DownloadConfig,
     mut progress: impl FnMut(FileLoadingProgress),
 ) -> Result<(), CacheError> {
+    let start_time = std::time::Instant::now();
     let length = head
         .headers()
         .get(CONTENT_LENGTH)
-        .ok_or("response doesn't include the content length")
-        .unwrap();
-    let length = length.to_str().ok().and_then(|s| u64::from_str(s).ok());
+        .and_then(|length| length.to_str().ok())
+        .and_then(|s| u64::from_str(s).ok());
+    let validator = validator(&head);
+    let validator_file = marker_path(file, "validator");
+    let chunks_file = marker_path(file, "chunks");
+    let supports_ranges = head
+        .headers()
+        .get(ACCEPT_RANGES)
+        .is_some_and(|value| value.as_bytes() == b"bytes");
 
-    let (start, mut output_file) = if let Ok(metadata) = tokio::fs::metadata(file).await {
-        let start = metadata.len();
-        let output_file = OpenOptions::new().append(true).open(file).await.unwrap();
-        (start, output_file)
-    } else {
-        tokio::fs::create_dir_all(file.parent().unwrap()).await?;
-        (0, File::create(file).await.unwrap())
+    // Large files are split into chunks that are downloaded in parallel. A chunked partial download must be
+    // finished in chunks because the file is allocated up front
+    let chunked_partial = tokio::fs::try_exists(&chunks_file).await.unwrap_or(false);
+    if let Some(length) = length {
+        if supports_ranges
+            && (chunked_partial || (config.connections > 1 && length > config.chunk_size))
+        {
+            return download_chunks_into(
+                url.into_url()?,
+                file,
+                length,
+                validator,
+                client,
+                token,
+                config,
+                progress,
+            )
+            .await;
+        }
+    }
+    if chunked_partial {
+        let _ = tokio::fs::remove_file(&chunks_file).await;
+    }
+
+    // A partial download can only be resumed if the file on the server is the same file we started downloading
+    let mut start = match tokio::fs::metadata(file).await {
+        Ok(metadata) => {
+            let same_file = !chunked_partial
+                && partial_download_matches(&validator_file, validator.as_deref()).await;
+            let fits = !matches!(length, Some(length) if metadata.len() > length);
+            if same_file && fits {
+                metadata.len()
+            } else {
+                tracing::trace!(
+                    "The partial download {} is stale, restarting from the beginning",
+                    file.display()
+                );
+                0
+            }
+        }
+        Err(_) => {
+            tokio::fs::create_dir_all(file.parent().unwrap()).await?;
+            0
+        }
     };
+    if start == 0 {
+        if let Some(validator) = &validator {
+            tokio::fs::write(&validator_file, validator).await?;
+        }
+    }
 
     if let Some(length) = length {
         progress(FileLoadingProgress {
             progress: start,
             cached_size: start,
             size: length,
-            start_time: std::time::Instant::now(),
+            start_time,
         });
     }
 
-    if Some(start) == length {
+    if start > 0 && Some(start) == length {
         tracing::trace!("File {} already downloaded", file.display());
-        progress(FileLoadingProgress {
-            progress: start,
-            cached_size: start,
-            size: length.unwrap_or(0),
-            start_time: std::time::Instant::now(),
-        });
+        let _ = tokio::fs::remove_file(&validator_file).await;
         return Ok(());
     }
 
-    let range = length
-        .and_then(|length| HeaderValue::from_str(&format!("bytes={}-{}", start, length - 1)).ok());
-
-    tracing::trace!("Fetching range {:?}", range);
     let mut request = client.get(url).with_authorization_header(token);
-    if let Some(range) = range {
-        request = request.header(RANGE, range);
+    if start > 0 {
+        tracing::trace!("Resuming download of {} from byte {start}", file.display());
+        request = request
+            .header(RANGE, format!("bytes={start}-"))
+            .with_if_range_header(validator.as_deref());
     }
     let mut response = request.send().await?;
 
     let status = response.status();
-    if !(status == StatusCode::OK || status == StatusCode::PARTIAL_CONTENT) {
-        return Err(CacheError::UnexpectedStatusCode(status));
+    match status {
+        StatusCode::PARTIAL_CONTENT => {}
+        StatusCode::OK => {
+            if start > 0 {
+                tracing::trace!(
+                    "The server ignored the range request for {}, restarting from the beginning",
+                    file.display()
+                );
+                start = 0;
+            }
+        }
+        _ => return Err(CacheError::UnexpectedStatusCode(status)),
     }
 
+    let mut output_file = if start > 0 {
+        OpenOptions::new().append(true).open(file).await?
+    } else {
+        File::create(file).await?
+    };
+
     let mut current_progress = start;
 
     while let Some(chunk) = response.chunk().await? {
         output_file.write_all(&chunk).await?;
+        config.scheduler.throttle(chunk.len()).await;
         tracing::trace!("wrote chunk of size {}", chunk.len());
         current_progress += chunk.len() as u64;
         if let Some(length) = length {
@@ -220,18 +781,188 @@ async fn download_into<U: IntoUrl>(
                 progress: current_progress,
                 cached_size: start,
                 size: length,
-                start_time: std::time::Instant::now(),
+                start_time,
             });
         }
     }
+    output_file.flush().await?;
+
+    let _ = tokio::fs::remove_file(&validator_file).await;
+    tracing::trace!("Download of {} complete", file.display());
+
+    Ok(())
+}
+
+/// Download a file in chunks over multiple connections. The file is allocated up front and each chunk is written
+/// at its offset. Finished chunks are recorded in a marker file so an interrupted download only fetches the missing chunks
+#[allow(clippy::too_many_arguments)]
+async fn download_chunks_into(
+    url: reqwest::Url,
+    file: &Path,
+    length: u64,
+    validator: Option<String>,
+    client: reqwest::Client,
+    token: Option<String>,
+    config: DownloadConfig,
+    mut progress: impl FnMut(FileLoadingProgress),
+) -> Result<(), CacheError> {
+    let start_time = std::time::Instant::now();
+    let validator_file = marker_path(file, "validator");
+    let chunks_file = marker_path(file, "chunks");
+
+    let mut finished = HashSet::new();
+    let resumable = tokio::fs::metadata(file)
+        .await
+        .is_ok_and(|metadata| metadata.len() == length)
+        && partial_download_matches(&validator_file, validator.as_deref()).await;
+    if resumable {
+        let recorded = tokio::fs::read_to_string(&chunks_file)
+            .await
+            .unwrap_or_default();
+        finished.extend(recorded.lines().filter_map(|line| {
+            let (start, end) = line.split_once('-')?;
+            Some((start.parse::<u64>().ok()?, end.parse::<u64>().ok()?))
+        }));
+    } else {
+        tokio::fs::create_dir_all(file.parent().unwrap()).await?;
+        File::create(file).await?.set_len(length).await?;
+        match &validator {
+            Some(validator) => tokio::fs::write(&validator_file, validator).await?,
+            None => {
+                let _ = tokio::fs::remove_file(&validator_file).await;
+            }
+        }
+        tokio::fs::write(&chunks_file, "").await?;
+    }
+
+    // Inclusive byte ranges of every chunk in the file
+    let chunks = (0..length)
+        .step_by(config.chunk_size as usize)
+        .map(|start| (start, (start + config.chunk_size).min(length) - 1));
+    let mut cached_size = 0;
+    let mut pending = Vec::new();
+    for chunk in chunks {
+        if finished.contains(&chunk) {
+            cached_size += chunk.1 - chunk.0 + 1;
+        } else {
+            pending.push(chunk);
+        }
+    }
+    tracing::trace!(
+        "Downloading {} chunks of {} over {} connections",
+        pending.len(),
+        file.display(),
+        config.connections
+    );
+
+    progress(FileLoadingProgress {
+        progress: cached_size,
+        cached_size,
+        size: length,
+        start_time,
+    });
+
+    let state = std::sync::Mutex::new((cached_size, &mut progress));
+    let report = |bytes: u64| {
+        let mut state = state.lock().unwrap();
+        state.0 += bytes;
+        let current_progress = state.0;
+        (state.1)(FileLoadingProgress {
+            progress: current_progress,
+            cached_size,
+            size: length,
+            start_time,
+        });
+    };
+
+    let download_chunk = |(start, end): (u64, u64)| {
+        let request = client
+            .get(url.clone())
+            .with_authorization_header(token.clone())
+            .header(RANGE, format!("bytes={start}-{end}"))
+            .with_if_range_header(validator.as_deref());
+        let report = &report;
+        let chunks_file = &chunks_file;
+        let scheduler = &config.scheduler;
+        async move {
+            let mut response = request.send().await?;
+            let status = response.status();
+            if status != StatusCode::PARTIAL_CONTENT {
+                return Err(CacheError::UnexpectedStatusCode(status));
+            }
+
+            let mut output_file = OpenOptions::new().write(true).open(file).await?;
+            output_file.seek(SeekFrom::Start(start)).await?;
+            let expected = end - start + 1;
+            let mut received = 0;
+            while let Some(bytes) = response.chunk().await? {
+                received += bytes.len() as u64;
+                if received > expected {
+                    break;
+                }
+                output_file.write_all(&bytes).await?;
+                report(bytes.len() as u64);
+                scheduler.throttle(bytes.len()).await;
+            }
+            output_file.flush().await?;
+            if received != expected {
+                return Err(CacheError::IncompleteDownload { expected, received });
+            }
+
+            let mut chunks_file = OpenOptions::new().append(true).open(chunks_file).await?;
+            chunks_file
+                .write_all(format!("{start}-{end}\n").as_bytes())
+                .await?;
+            Ok(())
+        }
+    };
+
+    let mut downloads = futures_util::stream::iter(pending)
+        .map(download_chunk)
+        .buffer_unordered(config.connections);
+    while let Some(result) = downloads.next().await {
+        result?;
+    }
 
+    let _ = tokio::fs::remove_file(&validator_file).await;
+    let _ = tokio::fs::remove_file(&chunks_file).await;
     tracing::trace!("Download of {} complete", file.display());
 
     Ok(())
 }
 
+/// Check if the validator recorded when a partial download started matches the current validator of the file on the server
+async fn partial_download_matches(validator_file: &Path, validator: Option<&str>) -> bool {
+    let stored = tokio::fs::read_to_string(validator_file).await.ok();
+    match (validator, stored) {
+        (Some(validator), Some(stored)) => validator == stored,
+        (None, _) => true,
+        (Some(_), None) => false,
+    }
+}
+
+/// Get a string that identifies the version of the file on the server from the ETag or Last-Modified header
+fn validator(head: &Response) -> Option<String> {
+    let headers = head.headers();
+    headers
+        .get(ETAG)
+        .or_else(|| headers.get(LAST_MODIFIED))
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.to_string())
+}
+
+/// The path of a marker file next to a partial download that stores information about the download
+fn marker_path(file: &Path, marker: &str) -> PathBuf {
+    let mut path = file.as_os_str().to_owned();
+    path.push(".");
+    path.push(marker);
+    PathBuf::from(path)
+}
+
 trait RequestBuilderExt {
     fn with_authorization_header(self, token: Option<String>) -> Self;
+
+    fn with_if_range_header(self, validator: Option<&str>) -> Self;
 }
 
 impl RequestBuilderExt for reqwest::RequestBuilder {
@@ -242,6 +973,179 @@ impl RequestBuilderExt for reqwest::RequestBuilder {
             self
         }
     }
+
+    fn with_if_range_header(self, validator: Option<&str>) -> Self {
+        // If the file changed since the validator was recorded, the server will send the whole new file instead of a range.
+        // Weak validators can't be used in If-Range
+        match validator
+            .filter(|validator| !validator.starts_with("W/"))
+            .and_then(|validator| HeaderValue::from_str(validator).ok())
+        {
+            Some(validator) => self.header(IF_RANGE, validator),
+            None => self,
+        }
+    }
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn offline_mode_reports_missing_files() {
+    let location = std::env::temp_dir().join("kalosm-offline-cache");
+    let cache = Cache::new(location.clone()).with_offline(true);
+    let downloaded = FileSource::huggingface("kalosm/test", "main", "config.json");
+    let missing = FileSource::huggingface("kalosm/test", "main", "model.safetensors");
+    std::fs::create_dir_all(location.join("kalosm/test/main")).unwrap();
+    std::fs::write(location.join("kalosm/test/main/config.json"), "{}").unwrap();
+
+    let result = cache.ensure_available([&downloaded, &missing]);
+    assert!(matches!(result, Err(CacheError::Offline(files)) if files.len() == 1));
+    assert!(cache.get(&downloaded, |_| {}).await.is_ok());
+    assert!(matches!(
+        cache.get(&missing, |_| {}).await,
+        Err(CacheError::Offline(_))
+    ));
+    std::fs::remove_dir_all(location).unwrap();
+}
+
+#[cfg(test)]
+#[test]
+fn mirrors_are_tried_in_order() {
+    let cache = Cache::at("cache").with_huggingface_token(Some("token".to_string()));
+    let source = FileSource::huggingface("org/model", "refs/pr/1", "model.safetensors")
+        .with_mirror(Mirror::HuggingFaceEndpoint(
+            "https://hf-mirror.com/".to_string(),
+        ))
+        .with_mirror(Mirror::Url(
+            "https://example.com/model.safetensors".to_string(),
+        ));
+
+    let urls = cache.download_urls(&source);
+    assert_eq!(
+        urls[1..],
+        [
+            (
+                "https://hf-mirror.com/org/model/resolve/refs%2Fpr%2F1/model.safetensors"
+                    .to_string(),
+                None
+            ),
+            ("https://example.com/model.safetensors".to_string(), None),
+        ]
+    );
+    assert_eq!(urls[0].1.as_deref(), Some("token"));
+    let urls = cache.download_urls(&source.clone().with_token("source token"));
+    assert_eq!(urls[0].1.as_deref(), Some("source token"));
+    assert!(urls[1..].iter().all(|(_, token)| token.is_none()));
+    assert_eq!(
+        cache.local_path(&source),
+        Path::new("cache/org/model/refs/pr/1/model.safetensors")
+    );
+    assert_eq!(
+        cache.local_path(&FileSource::url("https://example.com/a/../model.bin")),
+        Path::new("cache/urls/example.com/model.bin")
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn gc_removes_least_recently_used_files() {
+    let location = std::env::temp_dir().join("kalosm-gc-cache");
+    let cache = Cache::at(&location);
+    let old = FileSource::huggingface("kalosm/test", "main", "old.bin");
+    let new = FileSource::huggingface("kalosm/test", "main", "new.bin");
+    std::fs::create_dir_all(location.join("kalosm/test/main")).unwrap();
+    for (file, age) in [("old.bin", 60), ("new.bin", 0)] {
+        let path = location.join("kalosm/test/main").join(file);
+        std::fs::write(&path, [0; 100]).unwrap();
+        let accessed = SystemTime::now() - std::time::Duration::from_secs(age);
+        std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_times(std::fs::FileTimes::new().set_accessed(accessed))
+            .unwrap();
+    }
+    assert_eq!(cache.size().unwrap(), 200);
+
+    let removed = cache.gc(150).unwrap();
+    assert_eq!(removed.len(), 1);
+    assert_eq!(
+        removed[0].relative_path(),
+        Path::new("kalosm/test/main/old.bin")
+    );
+    assert!(!cache.exists(&old));
+    assert!(cache.exists(&new));
+
+    assert!(cache.remove(&new).unwrap());
+    assert!(cache.entries().unwrap().is_empty());
+    assert!(!location.join("kalosm").exists());
+    std::fs::remove_dir_all(location).unwrap();
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn preloaded_files_are_used_instead_of_downloads() {
+    static MODEL: &[u8; 4] = b"GGUF";
+    let location = std::env::temp_dir().join("kalosm-preloaded-cache");
+    let source = FileSource::huggingface("kalosm/test", "main", "model.gguf");
+    let cache = Cache::new(location.clone())
+        .with_offline(true)
+        .with_preloaded(source.clone(), MODEL);
+
+    assert!(cache.ensure_available([&source]).is_ok());
+    let file = cache.load(&source, |_| {}).await.unwrap();
+    assert_eq!(file.bytes().unwrap().as_ref(), MODEL);
+    let mirrored = source.with_mirror(Mirror::Url("https://example.com/model.gguf".to_string()));
+    let path = cache.get(&mirrored, |_| {}).await.unwrap();
+    assert_eq!(std::fs::read(path).unwrap(), MODEL);
+    assert!(!location.exists());
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn in_memory_files_are_not_cached() {
+    use std::io::Read;
+
+    let location = std::env::temp_dir().join("kalosm-in-memory-test");
+    let cache = Cache::at(&location).with_offline(true);
+    let source = FileSource::from_bytes(b"in memory weights");
+    assert!(cache.missing([&source]).is_empty());
+
+    let file = cache.load(&source, |_| {}).await.unwrap();
+    assert!(file.path().is_none());
+    let mut contents = String::new();
+    file.reader()
+        .unwrap()
+        .read_to_string(&mut contents)
+        .unwrap();
+    assert_eq!(contents, "in memory weights");
+    assert!(!location.exists());
+
+    let path = cache.get(&source, |_| {}).await.unwrap();
+    assert_eq!(std::fs::read(path).unwrap(), b"in memory weights");
+    assert!(!location.exists());
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn download_size_only_counts_missing_files() {
+    let location = std::env::temp_dir().join("kalosm-download-size-cache");
+    let cache = Cache::at(&location);
+    let downloaded = FileSource::url("https://httpbin.org/range/1024");
+    let missing = FileSource::url("https://httpbin.org/range/102400");
+    let path = cache.local_path(&downloaded);
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(&path, [0; 1024]).unwrap();
+
+    assert_eq!(cache.download_size([&downloaded, &missing]).await, 102400);
+    assert_eq!(
+        cache
+            .clone()
+            .with_offline(true)
+            .download_size([&missing])
+            .await,
+        0
+    );
+    std::fs::remove_dir_all(location).unwrap();
 }
 
 #[cfg(test)]
@@ -254,14 +1158,141 @@ async fn downloads_work() {
     };
     let client = reqwest::Client::new();
     let response = client.head(url).send().await.unwrap();
-    download_into(url, &file, response, client, None, progress)
+    download_into(
+        url,
+        &file,
+        response,
+        client,
+        None,
+        DownloadConfig::default(),
+        progress,
+    )
+    .await
+    .unwrap();
+    assert!(file.exists());
+    tokio::fs::remove_file(file).await.unwrap();
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn interrupted_downloads_resume() {
+    let url = "https://httpbin.org/range/102400";
+    let file = PathBuf::from("resumed-download.bin");
+    let client = reqwest::Client::new();
+    let expected = client.get(url).send().await.unwrap().bytes().await.unwrap();
+
+    // Simulate a download that was interrupted halfway through
+    let response = client.head(url).send().await.unwrap();
+    if let Some(validator) = validator(&response) {
+        tokio::fs::write(marker_path(&file, "validator"), validator)
+            .await
+            .unwrap();
+    }
+    tokio::fs::write(&file, &expected[..51200]).await.unwrap();
+
+    let mut resumed_from = None;
+    let config = DownloadConfig {
+        connections: 1,
+        ..Default::default()
+    };
+    download_into(url, &file, response, client, None, config, |p| {
+        resumed_from.get_or_insert(p.cached_size);
+    })
+    .await
+    .unwrap();
+    assert_eq!(resumed_from, Some(51200));
+    assert_eq!(tokio::fs::read(&file).await.unwrap(), expected);
+    assert!(!marker_path(&file, "validator").exists());
+    tokio::fs::remove_file(file).await.unwrap();
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn chunked_downloads_work() {
+    let url = "https://httpbin.org/range/102400";
+    let file = PathBuf::from("chunked-download.bin");
+    let client = reqwest::Client::new();
+    let expected = client.get(url).send().await.unwrap().bytes().await.unwrap();
+
+    let response = client.head(url).send().await.unwrap();
+    let config = DownloadConfig {
+        connections: 4,
+        chunk_size: 10000,
+        ..Default::default()
+    };
+    download_into(url, &file, response, client, None, config, |_| {})
         .await
         .unwrap();
-    assert!(file.exists());
+    assert_eq!(tokio::fs::read(&file).await.unwrap(), expected);
+    assert!(!marker_path(&file, "chunks").exists());
     tokio::fs::remove_file(file).await.unwrap();
 }
 
+/// Get the Hugging Face endpoint from the `HF_ENDPOINT` environment variable, or the default endpoint if it is not set
+fn huggingface_endpoint() -> String {
+    std::env::var("HF_ENDPOINT").unwrap_or_else(|_| "https://huggingface.co".to_string())
+}
+
+/// Get the URL of a file in a Hugging Face model repo
+fn huggingface_url(endpoint: &str, model_id: &str, revision: &str, file: &str) -> String {
+    let endpoint = endpoint.trim_end_matches('/');
+    let revision = revision.replace('/', "%2F");
+    format!("{endpoint}/{model_id}/resolve/{revision}/{file}")
+}
+
+/// Get the path a file downloaded from a URL is stored at relative to the root of the cache
+fn url_cache_path(url: &str) -> PathBuf {
+    let mut path = PathBuf::from("urls");
+    match reqwest::Url::parse(url) {
+        Ok(url) => {
+            path.push(url.host_str().unwrap_or("unknown"));
+            let segments = url.path_segments().into_iter().flatten();
+            for segment in segments.filter(|segment| !matches!(*segment, "" | "." | "..")) {
+                path.push(segment);
+            }
+        }
+        Err(_) => path.push(url.replace(['/', '\\', ':'], "_")),
+    }
+    path
+}
+
+/// Record that a file in the cache was just used. Many file systems don't update the access time on reads, so it is set explicitly
+fn mark_used(file: &Path) {
+    let now = SystemTime::now();
+    if let Ok(file) = std::fs::OpenOptions::new().write(true).open(file) {
+        let _ = file.set_times(std::fs::FileTimes::new().set_accessed(now));
+    }
+}
+
+fn remove_if_exists(file: &Path) -> Result<bool, CacheError> {
+    match std::fs::remove_file(file) {
+        Ok(()) => Ok(true),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(false),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Remove the marker files next to a partial download
+fn remove_markers(partial: &Path) -> Result<(), CacheError> {
+    for marker in ["validator", "chunks"] {
+        remove_if_exists(&marker_path(partial, marker))?;
+    }
+    Ok(())
+}
+
+/// Check if offline mode is enabled with the `KALOSM_OFFLINE` or `HF_HUB_OFFLINE` environment variables
+fn offline_from_env() -> bool {
+    ["KALOSM_OFFLINE", "HF_HUB_OFFLINE"].iter().any(|var| {
+        std::env::var(var).is_ok_and(|value| {
+            let value = value.trim();
+            value == "1" || value.eq_ignore_ascii_case("true")
+        })
+    })
+}
+
 fn huggingface_token() -> Option<String> {
-    let cache = hf_hub::Cache::default();
-    cache.token().or_else(|| std::env::var("HF_TOKEN").ok())
+    std::env::var("HF_TOKEN")
+        .ok()
+        .filter(|token| !token.is_empty())
+        .or_else(|| hf_hub::Cache::default().token())
 }
@@ -0,0 +1,117 @@
+//! A scheduler that limits how many files the browser downloads at once
+
+use std::sync::{Arc, Mutex, OnceLock};
+use std::task::{Poll, Waker};
+
+/// Limits the number of files that are downloaded at the same time.
+///
+/// Every [`crate::Cache`] uses the scheduler from [`DownloadScheduler::global`] by default, so models
+/// that are loaded at the same time share the limits instead of competing for the connection.
+///
+/// The browser downloads each file with a single fetch request, so the bandwidth limit is recorded but not enforced.
+#[derive(Debug, Clone)]
+pub struct DownloadScheduler {
+    inner: Arc<Mutex<SchedulerState>>,
+}
+
+#[derive(Debug)]
+struct SchedulerState {
+    active: usize,
+    max: usize,
+    bytes_per_second: Option<u64>,
+    // Downloads waiting for a free slot
+    waiting: Vec<Waker>,
+}
+
+impl SchedulerState {
+    fn wake_waiting(&mut self) {
+        for waker in self.waiting.drain(..) {
+            waker.wake();
+        }
+    }
+}
+
+impl Default for DownloadScheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DownloadScheduler {
+    /// Create a new scheduler that downloads up to 4 files at a time. This scheduler is not shared with other caches
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(SchedulerState {
+                active: 0,
+                max: 4,
+                bytes_per_second: None,
+                waiting: Vec::new(),
+            })),
+        }
+    }
+
+    /// Get the scheduler shared by every cache on the page
+    pub fn global() -> &'static Self {
+        static GLOBAL: OnceLock<DownloadScheduler> = OnceLock::new();
+        GLOBAL.get_or_init(Self::new)
+    }
+
+    /// Set the maximum number of files that can be downloaded at the same time. (defaults to 4)
+    ///
+    /// Downloads that are already running are not interrupted if the limit is lowered.
+    pub fn set_max_concurrent_downloads(&self, max: usize) {
+        let mut state = self.inner.lock().unwrap();
+        state.max = max.max(1);
+        state.wake_waiting();
+    }
+
+    /// Get the maximum number of files that can be downloaded at the same time
+    pub fn max_concurrent_downloads(&self) -> usize {
+        self.inner.lock().unwrap().max
+    }
+
+    /// Get the number of files that are currently being downloaded
+    pub fn active_downloads(&self) -> usize {
+        self.inner.lock().unwrap().active
+    }
+
+    /// Set the maximum number of bytes per second all downloads can use together. This is not enforced in the browser
+    pub fn set_bandwidth_limit(&self, bytes_per_second: Option<u64>) {
+        self.inner.lock().unwrap().bytes_per_second = bytes_per_second.map(|limit| limit.max(1));
+    }
+
+    /// Get the maximum number of bytes per second all downloads can use together
+    pub fn bandwidth_limit(&self) -> Option<u64> {
+        self.inner.lock().unwrap().bytes_per_second
+    }
+
+    /// Wait until a download slot is free. The slot is released when the returned permit is dropped
+    pub(crate) async fn acquire(&self) -> DownloadPermit {
+        std::future::poll_fn(|cx| {
+            let mut state = self.inner.lock().unwrap();
+            if state.active < state.max {
+                state.active += 1;
+                Poll::Ready(DownloadPermit {
+                    scheduler: self.clone(),
+                })
+            } else {
+                state.waiting.push(cx.waker().clone());
+                Poll::Pending
+            }
+        })
+        .await
+    }
+}
+
+/// A download slot from a [`DownloadScheduler`]
+pub(crate) struct DownloadPermit {
+    scheduler: DownloadScheduler,
+}
+
+impl Drop for DownloadPermit {
+    fn drop(&mut self) {
+        let mut state = self.scheduler.inner.lock().unwrap();
+        state.active -= 1;
+        state.wake_waiting();
+    }
+}
@@ -0,0 +1,168 @@
+//! Downloads from cloud object stores (S3, GCS and Azure Blob Storage)
+
+use super::{CacheError, DownloadScheduler};
+use kalosm_model_types::{FileLoadingProgress, FileSource};
+use std::path::{Path, PathBuf};
+
+/// Get the cargo feature that is required to download the source if it is stored in a cloud object store
+pub(super) fn required_feature(source: &FileSource) -> Option<&'static str> {
+    match source {
+        FileSource::S3 { .. } => Some("s3"),
+        FileSource::Gcs { .. } => Some("gcs"),
+        FileSource::AzureBlob { .. } => Some("azure"),
+        _ => None,
+    }
+}
+
+#[cfg(not(any(feature = "s3", feature = "gcs", feature = "azure")))]
+pub(super) async fn get_object(
+    source: &FileSource,
+    _: &Path,
+    _: &DownloadScheduler,
+    _: impl FnMut(FileLoadingProgress),
+) -> Result<PathBuf, CacheError> {
+    Err(CacheError::MissingFeature {
+        file: source.to_string(),
+        feature: required_feature(source).unwrap_or_default(),
+    })
+}
+
+/// Get an object from the cache, downloading it from the object store if the cached file is missing or out of date
+#[cfg(any(feature = "s3", feature = "gcs", feature = "azure"))]
+pub(super) async fn get_object(
+    source: &FileSource,
+    complete_download: &Path,
+    scheduler: &DownloadScheduler,
+    mut progress: impl FnMut(FileLoadingProgress),
+) -> Result<PathBuf, CacheError> {
+    use super::{mark_used, marker_path, partial_download_matches};
+    use futures_util::StreamExt;
+    use object_store::{path::Path as ObjectPath, GetOptions, GetRange, ObjectStore};
+    use std::time::SystemTime;
+    use tokio::{fs::OpenOptions, io::AsyncWriteExt};
+
+    // The builders read credentials from the standard environment variables and fall back to the instance metadata
+    #[allow(unreachable_patterns)]
+    let (store, location): (Box<dyn ObjectStore>, ObjectPath) = match source {
+        #[cfg(feature = "s3")]
+        FileSource::S3 { bucket, key } => (
+            Box::new(
+                object_store::aws::AmazonS3Builder::from_env()
+                    .with_bucket_name(bucket)
+                    .build()?,
+            ),
+            ObjectPath::from(key.as_str()),
+        ),
+        #[cfg(feature = "gcs")]
+        FileSource::Gcs { bucket, object } => (
+            Box::new(
+                object_store::gcp::GoogleCloudStorageBuilder::from_env()
+                    .with_bucket_name(bucket)
+                    .build()?,
+            ),
+            ObjectPath::from(object.as_str()),
+        ),
+        #[cfg(feature = "azure")]
+        FileSource::AzureBlob {
+            account,
+            container,
+            blob,
+        } => (
+            Box::new(
+                object_store::azure::MicrosoftAzureBuilder::from_env()
+                    .with_account(account)
+                    .with_container_name(container)
+                    .build()?,
+            ),
+            ObjectPath::from(blob.as_str()),
+        ),
+        source => {
+            return Err(CacheError::MissingFeature {
+                file: source.to_string(),
+                feature: required_feature(source).unwrap_or_default(),
+            })
+        }
+    };
+
+    tracing::trace!("Fetching metadata for {source}");
+    let head = store.head(&location).await;
+    if complete_download.exists() {
+        let file_last_modified = tokio::fs::metadata(complete_download)
+            .await
+            .map_err(|e| CacheError::UnableToGetFileMetadata(complete_download.into(), e))?
+            .modified()?;
+        // Use the local file if it is newer than the object, or if we can't reach the object store
+        let up_to_date = match &head {
+            Ok(head) => SystemTime::from(head.last_modified) <= file_last_modified,
+            Err(_) => true,
+        };
+        if up_to_date {
+            mark_used(complete_download);
+            return Ok(complete_download.into());
+        }
+    }
+    let head = head?;
+    let length = head.size;
+    let start_time = std::time::Instant::now();
+
+    let incomplete_download = marker_path(complete_download, "partial");
+    let validator_file = marker_path(&incomplete_download, "validator");
+    let mut start = match tokio::fs::metadata(&incomplete_download).await {
+        Ok(metadata)
+            if metadata.len() <= length
+                && partial_download_matches(&validator_file, head.e_tag.as_deref()).await =>
+        {
+            metadata.len()
+        }
+        _ => 0,
+    };
+    if start == 0 {
+        tokio::fs::create_dir_all(incomplete_download.parent().unwrap()).await?;
+        tokio::fs::File::create(&incomplete_download).await?;
+        if let Some(e_tag) = &head.e_tag {
+            tokio::fs::write(&validator_file, e_tag).await?;
+        }
+    }
+    progress(FileLoadingProgress {
+        progress: start,
+        cached_size: start,
+        size: length,
+        start_time,
+    });
+
+    if start < length {
+        let _permit = scheduler.acquire().await;
+        tracing::trace!("Downloading {source} from byte {start}");
+        let options = GetOptions {
+            range: (start > 0).then_some(GetRange::Offset(start)),
+            // Fail instead of mixing two versions of the object if it changes during the download
+            if_match: head.e_tag.clone(),
+            ..Default::default()
+        };
+        let mut stream = store.get_opts(&location, options).await?.into_stream();
+        let mut output_file = OpenOptions::new()
+            .append(true)
+            .open(&incomplete_download)
+            .await?;
+        let cached_size = start;
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk?;
+            output_file.write_all(&chunk).await?;
+            scheduler.throttle(chunk.len()).await;
+            start += chunk.len() as u64;
+            progress(FileLoadingProgress {
+                progress: start,
+                cached_size,
+                size: length,
+                start_time,
+            });
+        }
+        output_file.flush().await?;
+    }
+
+    tokio::fs::rename(&incomplete_download, complete_download).await?;
+    let _ = tokio::fs::remove_file(&validator_file).await;
+    tracing::trace!("Download of {source} complete");
+
+    Ok(complete_download.into())
+}
@@ -0,0 +1,97 @@
+//! Files that are loaded from memory instead of the cache
+
+use kalosm_model_types::InMemoryFile;
+use std::borrow::Cow;
+use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+/// A file loaded with [`crate::Cache::load`]. Files from disk are not read until they are used, and in memory files are never written to disk
+#[derive(Debug, Clone)]
+pub enum CachedFile {
+    /// A file on disk
+    Path(PathBuf),
+    /// A file in memory
+    InMemory(InMemoryFile),
+}
+
+impl CachedFile {
+    /// Get the path of the file if it is stored on disk
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Self::Path(path) => Some(path),
+            Self::InMemory(_) => None,
+        }
+    }
+
+    /// Read the whole file. In memory files are borrowed instead of copied
+    pub fn bytes(&self) -> std::io::Result<Cow<'_, [u8]>> {
+        match self {
+            Self::Path(path) => std::fs::read(path).map(Cow::Owned),
+            Self::InMemory(file) => Ok(Cow::Borrowed(file.as_bytes())),
+        }
+    }
+
+    /// Open a reader for the file
+    pub fn reader(&self) -> std::io::Result<CachedFileReader> {
+        match self {
+            Self::Path(path) => std::fs::File::open(path).map(CachedFileReader::File),
+            Self::InMemory(file) => Ok(CachedFileReader::InMemory(Cursor::new(file.clone()))),
+        }
+    }
+}
+
+/// A reader for a [`CachedFile`]
+pub enum CachedFileReader {
+    /// A reader for a file on disk
+    File(std::fs::File),
+    /// A reader for a file in memory
+    InMemory(Cursor<InMemoryFile>),
+}
+
+impl Read for CachedFileReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Self::File(file) => file.read(buf),
+            Self::InMemory(cursor) => cursor.read(buf),
+        }
+    }
+}
+
+impl Seek for CachedFileReader {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        match self {
+            Self::File(file) => file.seek(pos),
+            Self::InMemory(cursor) => cursor.seek(pos),
+        }
+    }
+}
+
+/// The path an in memory file is written to for loaders that need a path. Files with the same contents share a path
+#[cfg(not(target_arch = "wasm32"))]
+pub(super) fn materialized_path(file: &InMemoryFile) -> PathBuf {
+    use std::hash::{Hash, Hasher};
+
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    file.as_bytes().hash(&mut hasher);
+    std::env::temp_dir()
+        .join("kalosm")
+        .join("in-memory")
+        .join(format!("{:016x}", hasher.finish()))
+}
+
+/// Write an in memory file to a temporary file so it can be loaded by path
+#[cfg(not(target_arch = "wasm32"))]
+pub(super) async fn materialize(file: &InMemoryFile) -> std::io::Result<PathBuf> {
+    let path = materialized_path(file);
+    let up_to_date = tokio::fs::metadata(&path)
+        .await
+        .is_ok_and(|metadata| metadata.len() == file.len() as u64);
+    if !up_to_date {
+        tokio::fs::create_dir_all(path.parent().unwrap()).await?;
+        // Write to a temporary file first so another process never sees a partially written file
+        let partial = path.with_extension(format!("{}.partial", std::process::id()));
+        tokio::fs::write(&partial, file.as_bytes()).await?;
+        tokio::fs::rename(&partial, &path).await?;
+    }
+    Ok(path)
+}
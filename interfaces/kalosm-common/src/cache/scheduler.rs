@@ -0,0 +1,178 @@
+//! A scheduler that limits how many files are downloaded at once and how much bandwidth downloads use
+
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+
+/// Limits the number of files that are downloaded at the same time and the total bandwidth of those downloads.
+///
+/// Every [`crate::Cache`] uses the process-wide scheduler from [`DownloadScheduler::global`] by default, so models
+/// that are loaded at the same time share the limits instead of competing for the connection.
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm_common::DownloadScheduler;
+///
+/// // Download at most two files at a time, and use at most 10 MB/s in total
+/// DownloadScheduler::global().set_max_concurrent_downloads(2);
+/// DownloadScheduler::global().set_bandwidth_limit(Some(10_000_000));
+/// ```
+#[derive(Debug, Clone)]
+pub struct DownloadScheduler {
+    inner: Arc<SchedulerInner>,
+}
+
+#[derive(Debug)]
+struct SchedulerInner {
+    downloads: Mutex<DownloadSlots>,
+    slot_freed: Notify,
+    bandwidth: Mutex<Bandwidth>,
+}
+
+#[derive(Debug)]
+struct DownloadSlots {
+    active: usize,
+    max: usize,
+}
+
+/// A token bucket that holds up to one second of bandwidth
+#[derive(Debug)]
+struct Bandwidth {
+    bytes_per_second: Option<u64>,
+    available: f64,
+    last_refill: Instant,
+}
+
+impl Default for DownloadScheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DownloadScheduler {
+    /// Create a new scheduler that downloads up to 4 files at a time with no bandwidth limit. This scheduler is not shared with other caches
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(SchedulerInner {
+                downloads: Mutex::new(DownloadSlots { active: 0, max: 4 }),
+                slot_freed: Notify::new(),
+                bandwidth: Mutex::new(Bandwidth {
+                    bytes_per_second: None,
+                    available: 0.,
+                    last_refill: Instant::now(),
+                }),
+            }),
+        }
+    }
+
+    /// Get the scheduler shared by every cache in the process
+    pub fn global() -> &'static Self {
+        static GLOBAL: OnceLock<DownloadScheduler> = OnceLock::new();
+        GLOBAL.get_or_init(Self::new)
+    }
+
+    /// Set the maximum number of files that can be downloaded at the same time. (defaults to 4)
+    ///
+    /// Downloads that are already running are not interrupted if the limit is lowered.
+    pub fn set_max_concurrent_downloads(&self, max: usize) {
+        self.inner.downloads.lock().unwrap().max = max.max(1);
+        self.inner.slot_freed.notify_waiters();
+    }
+
+    /// Get the maximum number of files that can be downloaded at the same time
+    pub fn max_concurrent_downloads(&self) -> usize {
+        self.inner.downloads.lock().unwrap().max
+    }
+
+    /// Get the number of files that are currently being downloaded
+    pub fn active_downloads(&self) -> usize {
+        self.inner.downloads.lock().unwrap().active
+    }
+
+    /// Set the maximum number of bytes per second all downloads can use together, or `None` for no limit. (defaults to `None`)
+    pub fn set_bandwidth_limit(&self, bytes_per_second: Option<u64>) {
+        let mut bandwidth = self.inner.bandwidth.lock().unwrap();
+        bandwidth.bytes_per_second = bytes_per_second.map(|limit| limit.max(1));
+        bandwidth.available = 0.;
+        bandwidth.last_refill = Instant::now();
+    }
+
+    /// Get the maximum number of bytes per second all downloads can use together
+    pub fn bandwidth_limit(&self) -> Option<u64> {
+        self.inner.bandwidth.lock().unwrap().bytes_per_second
+    }
+
+    /// Wait until a download slot is free. The slot is released when the returned permit is dropped
+    pub(crate) async fn acquire(&self) -> DownloadPermit {
+        loop {
+            let slot_freed = self.inner.slot_freed.notified();
+            {
+                let mut downloads = self.inner.downloads.lock().unwrap();
+                if downloads.active < downloads.max {
+                    downloads.active += 1;
+                    return DownloadPermit {
+                        scheduler: self.clone(),
+                    };
+                }
+            }
+            tracing::trace!("Waiting for a free download slot");
+            slot_freed.await;
+        }
+    }
+
+    /// Record that some bytes were downloaded, and wait if the downloads are over the bandwidth limit
+    pub(crate) async fn throttle(&self, bytes: usize) {
+        let wait = {
+            let mut bandwidth = self.inner.bandwidth.lock().unwrap();
+            let Some(limit) = bandwidth.bytes_per_second else {
+                return;
+            };
+            let limit = limit as f64;
+            let now = Instant::now();
+            let elapsed = now.duration_since(bandwidth.last_refill).as_secs_f64();
+            bandwidth.last_refill = now;
+            bandwidth.available = (bandwidth.available + elapsed * limit).min(limit);
+            bandwidth.available -= bytes as f64;
+            // The bytes have already been received, so wait until the bucket is paid back
+            (bandwidth.available < 0.).then(|| -bandwidth.available / limit)
+        };
+        if let Some(wait) = wait {
+            tokio::time::sleep(Duration::from_secs_f64(wait)).await;
+        }
+    }
+}
+
+/// A download slot from a [`DownloadScheduler`]
+pub(crate) struct DownloadPermit {
+    scheduler: DownloadScheduler,
+}
+
+impl Drop for DownloadPermit {
+    fn drop(&mut self) {
+        self.scheduler.inner.downloads.lock().unwrap().active -= 1;
+        self.scheduler.inner.slot_freed.notify_waiters();
+    }
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn scheduler_limits_concurrent_downloads() {
+    let scheduler = DownloadScheduler::new();
+    scheduler.set_max_concurrent_downloads(1);
+    let first = scheduler.acquire().await;
+    let second = scheduler.acquire();
+    tokio::pin!(second);
+    assert!(tokio::time::timeout(Duration::from_millis(50), &mut second)
+        .await
+        .is_err());
+    drop(first);
+    let _second = tokio::time::timeout(Duration::from_secs(1), second)
+        .await
+        .unwrap();
+    assert_eq!(scheduler.active_downloads(), 1);
+
+    scheduler.set_bandwidth_limit(Some(1000));
+    let start = Instant::now();
+    scheduler.throttle(1200).await;
+    assert!(start.elapsed() >= Duration::from_millis(1000));
+}
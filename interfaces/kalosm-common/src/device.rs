@@ -0,0 +1,176 @@
+use candle_core::Device;
+
+/// The kind of hardware a [`DeviceInfo`] describes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DeviceKind {
+    /// The CPU
+    Cpu,
+    /// An NVIDIA GPU
+    Cuda,
+    /// An Apple GPU
+    Metal,
+}
+
+/// Information about a device models can run on
+#[derive(Debug, Clone)]
+pub struct DeviceInfo {
+    kind: DeviceKind,
+    index: usize,
+    name: String,
+    total_memory: Option<u64>,
+    free_memory: Option<u64>,
+}
+
+impl DeviceInfo {
+    /// Get the kind of the device
+    pub fn kind(&self) -> DeviceKind {
+        self.kind
+    }
+
+    /// Get the index of the device among the devices of the same kind
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Get the name of the device
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get the total memory of the device in bytes if it is known
+    pub fn total_memory(&self) -> Option<u64> {
+        self.total_memory
+    }
+
+    /// Get the memory that is currently free on the device in bytes if it is known
+    pub fn free_memory(&self) -> Option<u64> {
+        self.free_memory
+    }
+
+    /// Create a candle device for this device
+    pub fn device(&self) -> candle_core::Result<Device> {
+        match self.kind {
+            DeviceKind::Cpu => Ok(Device::Cpu),
+            DeviceKind::Cuda => Device::new_cuda(self.index),
+            DeviceKind::Metal => Device::new_metal(self.index),
+        }
+    }
+}
+
+/// List every device models can run on. Accelerators are listed first, and the CPU is always the last device.
+///
+/// Accelerators are only listed if kalosm was built with the `cuda` or `metal` feature.
+///
+/// # Example
+/// ```rust, no_run
+/// for device in kalosm_common::devices() {
+///     println!(
+///         "{:?} {} ({}): {:?} bytes free",
+///         device.kind(),
+///         device.index(),
+///         device.name(),
+///         device.free_memory()
+///     );
+/// }
+/// ```
+pub fn devices() -> Vec<DeviceInfo> {
+    let mut devices = Vec::new();
+    devices.extend(cuda_devices());
+    devices.extend(metal_devices());
+    devices.push(DeviceInfo {
+        kind: DeviceKind::Cpu,
+        index: 0,
+        name: "CPU".to_string(),
+        total_memory: None,
+        free_memory: None,
+    });
+    devices
+}
+
+/// Create a candle device for the accelerator with the most free memory. If the free memory of the accelerators is unknown, this falls back to [`crate::accelerated_device_if_available`].
+///
+/// This is useful for applications that load multiple models at once. Loading each model on the device with the most free memory spreads the models out over every accelerator.
+pub fn device_with_most_free_memory() -> candle_core::Result<Device> {
+    let best = devices()
+        .into_iter()
+        .filter(|device| device.kind != DeviceKind::Cpu)
+        .filter_map(|device| Some((device.free_memory?, device)))
+        .max_by_key(|(free_memory, _)| *free_memory);
+    match best {
+        Some((_, device)) => device.device(),
+        None => crate::accelerated_device_if_available(),
+    }
+}
+
+/// Create a candle device for the GPU at the given index. This uses CUDA if it is available and Metal otherwise.
+pub fn gpu_device(index: usize) -> candle_core::Result<Device> {
+    if candle_core::utils::cuda_is_available() {
+        Device::new_cuda(index)
+    } else if candle_core::utils::metal_is_available() {
+        Device::new_metal(index)
+    } else {
+        Err(candle_core::Error::Msg(format!(
+            "Unable to create GPU {index}: no GPU is available. Make sure kalosm is built with the cuda or metal feature"
+        )))
+    }
+}
+
+#[cfg(feature = "cuda")]
+fn cuda_devices() -> Vec<DeviceInfo> {
+    use candle_core::cuda_backend::cudarc::driver::{result, CudaDevice};
+
+    let count = CudaDevice::count().unwrap_or_default().max(0) as usize;
+    (0..count)
+        .filter_map(|index| {
+            let device = CudaDevice::new(index).ok()?;
+            device.bind_to_thread().ok()?;
+            let (free_memory, total_memory) = result::mem_get_info().ok()?;
+            Some(DeviceInfo {
+                kind: DeviceKind::Cuda,
+                index,
+                name: device.name().unwrap_or_else(|_| format!("CUDA {index}")),
+                total_memory: Some(total_memory as u64),
+                free_memory: Some(free_memory as u64),
+            })
+        })
+        .collect()
+}
+
+#[cfg(not(feature = "cuda"))]
+fn cuda_devices() -> Vec<DeviceInfo> {
+    Vec::new()
+}
+
+#[cfg(feature = "metal")]
+fn metal_devices() -> Vec<DeviceInfo> {
+    metal::Device::all()
+        .into_iter()
+        .enumerate()
+        .map(|(index, device)| {
+            // Metal doesn't report free memory directly. The recommended working set is the memory the GPU can use without hurting performance
+            let total_memory = device.recommended_max_working_set_size();
+            let allocated = device.current_allocated_size();
+            DeviceInfo {
+                kind: DeviceKind::Metal,
+                index,
+                name: device.name().to_string(),
+                total_memory: Some(total_memory),
+                free_memory: Some(total_memory.saturating_sub(allocated)),
+            }
+        })
+        .collect()
+}
+
+#[cfg(not(feature = "metal"))]
+fn metal_devices() -> Vec<DeviceInfo> {
+    Vec::new()
+}
+
+#[cfg(test)]
+#[test]
+fn the_cpu_is_always_listed() {
+    let devices = devices();
+    let cpu = devices.last().unwrap();
+    assert_eq!(cpu.kind(), DeviceKind::Cpu);
+    assert!(matches!(cpu.device().unwrap(), Device::Cpu));
+}
@@ -2,12 +2,21 @@ use std::sync::OnceLock;
 
 use candle_core::{backend::BackendStorage, utils::*, Device, Storage, Tensor, WithDType};
 
+#[cfg(not(target_arch = "wasm32"))]
+mod cache;
+#[cfg(target_arch = "wasm32")]
+#[path = "browser_cache.rs"]
 mod cache;
 pub use cache::*;
+mod device;
+pub use device::*;
 mod kv_cache;
 pub use kv_cache::*;
 mod mask;
 pub use mask::*;
+pub mod metrics;
+mod weights;
+pub use weights::*;
 
 /// Create a candle device that uses any available accelerator.
 pub fn accelerated_device_if_available() -> candle_core::Result<Device> {
@@ -0,0 +1,227 @@
+//! Tracing spans and metrics for model loading and inference.
+//!
+//! Spans are only recorded if the `instrument` feature is enabled. Without the feature, every type in this module is zero sized and every method is a no-op.
+//!
+//! The span, event and field names are stable so they can be used in dashboards and alerts:
+//!
+//! | Name                    | Kind  | Fields                                                                                                      |
+//! | ----------------------- | ----- | ----------------------------------------------------------------------------------------------------------- |
+//! | `kalosm.load`           | span  | `model`, `load_ms`                                                                                          |
+//! | `kalosm.generate`       | span  | `model`, `structured`, `queue_wait_ms`, `prompt_tokens`, `prefill_ms`, `generated_tokens`, `sampler_ms`, `tokens_per_second` |
+//! | `kalosm.diffusion`      | span  | `model`, `queue_wait_ms`, `steps`, `total_ms`                                                               |
+//! | `kalosm.diffusion_step` | event | `step`, `step_ms`                                                                                           |
+//!
+//! `sampler_ms` is the total time spent choosing tokens from the logits. For structured generation, this includes the time spent checking the constraints.
+
+#[cfg(feature = "instrument")]
+use std::time::{Duration, Instant};
+
+/// A timer that measures the time since it was started if the `instrument` feature is enabled.
+#[derive(Debug, Clone, Copy)]
+pub struct Timer {
+    #[cfg(feature = "instrument")]
+    start: Instant,
+}
+
+impl Timer {
+    /// Start a new timer.
+    pub fn start() -> Self {
+        Self {
+            #[cfg(feature = "instrument")]
+            start: Instant::now(),
+        }
+    }
+
+    #[cfg(feature = "instrument")]
+    fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+}
+
+#[cfg(feature = "instrument")]
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.
+}
+
+/// Metrics for loading a model. The metrics are recorded in the `kalosm.load` span.
+pub struct LoadMetrics {
+    #[cfg(feature = "instrument")]
+    span: tracing::Span,
+    #[cfg(feature = "instrument")]
+    timer: Timer,
+}
+
+impl LoadMetrics {
+    /// Start recording metrics for loading a model.
+    #[allow(unused_variables)]
+    pub fn start(model: &'static str) -> Self {
+        Self {
+            #[cfg(feature = "instrument")]
+            span: tracing::info_span!("kalosm.load", model, load_ms = tracing::field::Empty),
+            #[cfg(feature = "instrument")]
+            timer: Timer::start(),
+        }
+    }
+
+    /// The span the model loading future should be instrumented with. This is a disabled span if the `instrument` feature is not enabled.
+    pub fn span(&self) -> tracing::Span {
+        #[cfg(feature = "instrument")]
+        {
+            self.span.clone()
+        }
+        #[cfg(not(feature = "instrument"))]
+        {
+            tracing::Span::none()
+        }
+    }
+
+    /// Record that the model finished loading.
+    pub fn finish(self) {
+        #[cfg(feature = "instrument")]
+        self.span.record("load_ms", millis(self.timer.elapsed()));
+    }
+}
+
+/// Metrics for one text generation run. The `kalosm.generate` span is entered until the metrics are dropped.
+pub struct GenerationMetrics {
+    #[cfg(feature = "instrument")]
+    span: tracing::span::EnteredSpan,
+    #[cfg(feature = "instrument")]
+    timer: Timer,
+    #[cfg(feature = "instrument")]
+    prefill_finished: Option<Instant>,
+    #[cfg(feature = "instrument")]
+    sampler_time: Duration,
+    #[cfg(feature = "instrument")]
+    generated_tokens: usize,
+}
+
+impl GenerationMetrics {
+    /// Start recording metrics for a generation run that was queued when `queued` was started.
+    #[allow(unused_variables)]
+    pub fn start(model: &'static str, structured: bool, queued: Timer) -> Self {
+        Self {
+            #[cfg(feature = "instrument")]
+            span: tracing::info_span!(
+                "kalosm.generate",
+                model,
+                structured,
+                queue_wait_ms = millis(queued.elapsed()),
+                prompt_tokens = tracing::field::Empty,
+                prefill_ms = tracing::field::Empty,
+                generated_tokens = tracing::field::Empty,
+                sampler_ms = tracing::field::Empty,
+                tokens_per_second = tracing::field::Empty,
+            )
+            .entered(),
+            #[cfg(feature = "instrument")]
+            timer: Timer::start(),
+            #[cfg(feature = "instrument")]
+            prefill_finished: None,
+            #[cfg(feature = "instrument")]
+            sampler_time: Duration::ZERO,
+            #[cfg(feature = "instrument")]
+            generated_tokens: 0,
+        }
+    }
+
+    /// Record that the prompt finished processing. Only the first call is recorded.
+    #[allow(unused_variables)]
+    pub fn prefill_finished(&mut self, prompt_tokens: usize) {
+        #[cfg(feature = "instrument")]
+        if self.prefill_finished.is_none() {
+            self.prefill_finished = Some(Instant::now());
+            self.span.record("prompt_tokens", prompt_tokens);
+            self.span.record("prefill_ms", millis(self.timer.elapsed()));
+        }
+    }
+
+    /// Record the time since `sampling` was started as time spent in the sampler.
+    #[allow(unused_variables)]
+    pub fn sampler_finished(&mut self, sampling: Timer) {
+        #[cfg(feature = "instrument")]
+        {
+            self.sampler_time += sampling.elapsed();
+        }
+    }
+
+    /// Record that a new token was generated.
+    pub fn token_generated(&mut self) {
+        #[cfg(feature = "instrument")]
+        {
+            self.generated_tokens += 1;
+        }
+    }
+}
+
+#[cfg(feature = "instrument")]
+impl Drop for GenerationMetrics {
+    fn drop(&mut self) {
+        self.span.record("generated_tokens", self.generated_tokens);
+        self.span.record("sampler_ms", millis(self.sampler_time));
+        if let Some(prefill_finished) = self.prefill_finished {
+            let seconds = prefill_finished.elapsed().as_secs_f64();
+            if seconds > 0. {
+                self.span
+                    .record("tokens_per_second", self.generated_tokens as f64 / seconds);
+            }
+        }
+    }
+}
+
+/// Metrics for one diffusion run. The `kalosm.diffusion` span is entered until the metrics are dropped.
+pub struct DiffusionMetrics {
+    #[cfg(feature = "instrument")]
+    span: tracing::span::EnteredSpan,
+    #[cfg(feature = "instrument")]
+    timer: Timer,
+    #[cfg(feature = "instrument")]
+    last_step: Timer,
+    #[cfg(feature = "instrument")]
+    step: usize,
+}
+
+impl DiffusionMetrics {
+    /// Start recording metrics for a diffusion run with `steps` total steps that was queued when `queued` was started.
+    #[allow(unused_variables)]
+    pub fn start(model: &'static str, steps: usize, queued: Timer) -> Self {
+        Self {
+            #[cfg(feature = "instrument")]
+            span: tracing::info_span!(
+                "kalosm.diffusion",
+                model,
+                queue_wait_ms = millis(queued.elapsed()),
+                steps,
+                total_ms = tracing::field::Empty,
+            )
+            .entered(),
+            #[cfg(feature = "instrument")]
+            timer: Timer::start(),
+            #[cfg(feature = "instrument")]
+            last_step: Timer::start(),
+            #[cfg(feature = "instrument")]
+            step: 0,
+        }
+    }
+
+    /// Record that a diffusion step finished.
+    pub fn step_finished(&mut self) {
+        #[cfg(feature = "instrument")]
+        {
+            self.step += 1;
+            tracing::info!(
+                name: "kalosm.diffusion_step",
+                step = self.step,
+                step_ms = millis(self.last_step.elapsed())
+            );
+            self.last_step = Timer::start();
+        }
+    }
+}
+
+#[cfg(feature = "instrument")]
+impl Drop for DiffusionMetrics {
+    fn drop(&mut self) {
+        self.span.record("total_ms", millis(self.timer.elapsed()));
+    }
+}
@@ -0,0 +1,159 @@
+use crate::CachedFile;
+use kalosm_model_types::InMemoryFile;
+use memmap2::{Mmap, MmapMut};
+use std::io::Read;
+use std::ops::Deref;
+
+/// Options for how model weights are read from disk.
+///
+/// Memory mapping the weights makes the model start faster, but the weights are only read from disk when they are first used, so the first generations can be slower. Reading the whole file up front takes longer to start but avoids those page faults later.
+///
+/// # Example
+/// ```rust
+/// use kalosm_common::WeightLoadingOptions;
+///
+/// // Read the whole file up front and keep it from being swapped out
+/// let options = WeightLoadingOptions::new()
+///     .with_mmap(false)
+///     .with_lock_in_memory(true);
+/// assert!(!options.mmap());
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WeightLoadingOptions {
+    mmap: bool,
+    lock_in_memory: bool,
+}
+
+impl Default for WeightLoadingOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WeightLoadingOptions {
+    /// Create the default options. Weights are memory mapped and not locked in memory
+    pub fn new() -> Self {
+        Self {
+            mmap: true,
+            lock_in_memory: false,
+        }
+    }
+
+    /// Set if the weights are memory mapped instead of read into memory up front. (defaults to true)
+    pub fn with_mmap(mut self, mmap: bool) -> Self {
+        self.mmap = mmap;
+        self
+    }
+
+    /// Set if the weights are locked in memory so the operating system can't swap them out. (defaults to false)
+    ///
+    /// Locking the weights also reads every page of a memory mapped file up front. Locking is only supported on unix, and if the lock fails (usually because of the `RLIMIT_MEMLOCK` limit) a warning is logged and the weights are loaded without it.
+    pub fn with_lock_in_memory(mut self, lock_in_memory: bool) -> Self {
+        self.lock_in_memory = lock_in_memory;
+        self
+    }
+
+    /// Check if the weights are memory mapped
+    pub fn mmap(&self) -> bool {
+        self.mmap
+    }
+
+    /// Check if the weights are locked in memory
+    pub fn lock_in_memory(&self) -> bool {
+        self.lock_in_memory
+    }
+}
+
+/// The contents of a weights file loaded with [`CachedFile::load_weights`]
+pub struct WeightsData(WeightsRepr);
+
+enum WeightsRepr {
+    Mmap(Mmap),
+    Bytes(Vec<u8>),
+    InMemory(InMemoryFile),
+}
+
+impl Deref for WeightsData {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match &self.0 {
+            WeightsRepr::Mmap(mmap) => mmap,
+            WeightsRepr::Bytes(bytes) => bytes,
+            WeightsRepr::InMemory(file) => file.as_bytes(),
+        }
+    }
+}
+
+impl AsRef<[u8]> for WeightsData {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+impl CachedFile {
+    /// Load the contents of a weights file with the given options. In memory files are used directly
+    pub fn load_weights(&self, options: WeightLoadingOptions) -> std::io::Result<WeightsData> {
+        let path = match self {
+            CachedFile::Path(path) => path,
+            CachedFile::InMemory(file) => {
+                return Ok(WeightsData(WeightsRepr::InMemory(file.clone())))
+            }
+        };
+        let mut file = std::fs::File::open(path)?;
+        let repr = match (options.mmap, options.lock_in_memory) {
+            (true, lock) => {
+                // Safety: The file is in the cache and is not modified while the model is loaded
+                let mmap = unsafe { Mmap::map(&file)? };
+                if lock {
+                    lock_in_memory(&mmap);
+                }
+                WeightsRepr::Mmap(mmap)
+            }
+            (false, false) => {
+                let mut bytes = Vec::new();
+                file.read_to_end(&mut bytes)?;
+                WeightsRepr::Bytes(bytes)
+            }
+            (false, true) => {
+                // An anonymous map can be locked, unlike the allocation of a Vec
+                let mut mmap = MmapMut::map_anon(file.metadata()?.len() as usize)?;
+                file.read_exact(&mut mmap)?;
+                let mmap = mmap.make_read_only()?;
+                lock_in_memory(&mmap);
+                WeightsRepr::Mmap(mmap)
+            }
+        };
+        Ok(WeightsData(repr))
+    }
+}
+
+fn lock_in_memory(mmap: &Mmap) {
+    #[cfg(unix)]
+    if let Err(err) = mmap.lock() {
+        tracing::warn!("Failed to lock the model weights in memory: {err}");
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = mmap;
+        tracing::warn!("Locking the model weights in memory is only supported on unix");
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn weights_load_the_same_with_every_option() {
+    let path = std::env::temp_dir().join("kalosm-weight-loading-test.bin");
+    let contents: Vec<u8> = (0..=255).cycle().take(10_000).collect();
+    std::fs::write(&path, &contents).unwrap();
+    let file = CachedFile::Path(path.clone());
+    for mmap in [true, false] {
+        for lock in [true, false] {
+            let options = WeightLoadingOptions::new()
+                .with_mmap(mmap)
+                .with_lock_in_memory(lock);
+            assert_eq!(&*file.load_weights(options).unwrap(), &contents[..]);
+        }
+    }
+    std::fs::remove_file(path).unwrap();
+}
@@ -8,6 +8,8 @@ pub struct Document {
     title: String,
     body: String,
     summary: Option<String>,
+    #[serde(default)]
+    source: Option<String>,
     created_at: Option<chrono::DateTime<chrono::Utc>>,
     updated_at: Option<chrono::DateTime<chrono::Utc>>,
 }
@@ -29,6 +31,7 @@ impl Document {
             title: title.into(),
             body: body.into(),
             summary: None,
+            source: None,
             created_at: None,
             updated_at: None,
         }
@@ -39,6 +42,11 @@ impl Document {
         self.summary = Some(summary.into());
     }
 
+    /// Set the location the document was read from, like a URL or a file path.
+    pub fn set_source(&mut self, source: impl Into<String>) {
+        self.source = Some(source.into());
+    }
+
     /// Set the created at time of the document.
     pub fn set_created_at(&mut self, created_at: chrono::DateTime<chrono::Utc>) {
         self.created_at = Some(created_at);
@@ -58,6 +66,36 @@ impl Document {
     pub fn body(&self) -> &str {
         &self.body
     }
+
+    /// Get the created at time of the document.
+    pub fn created_at(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.created_at
+    }
+
+    /// Get the updated at time of the document.
+    pub fn updated_at(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.updated_at
+    }
+
+    /// Get the location the document was read from, like a URL or a file path.
+    pub fn source(&self) -> Option<&str> {
+        self.source.as_deref()
+    }
+
+    /// Get a hash of the title and body of the document.
+    ///
+    /// The hash is stable between runs, so it can be stored to detect when the content of a document changes.
+    pub fn content_hash(&self) -> u64 {
+        // FNV-1a is simple and doesn't change between versions of rust like the default hasher
+        const OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+        const PRIME: u64 = 0x100000001b3;
+        let mut hash = OFFSET_BASIS;
+        for byte in self.title.bytes().chain([0]).chain(self.body.bytes()) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(PRIME);
+        }
+        hash
+    }
 }
 
 impl From<String> for Document {
@@ -166,7 +204,9 @@ pub enum ExtractDocumentError {
 
 pub(crate) async fn get_article(url: Url) -> Result<Document, ExtractDocumentError> {
     let html = reqwest::get(url.clone()).await?.text().await?;
-    extract_article(&html)
+    let mut document = extract_article(&html)?;
+    document.set_source(url);
+    Ok(document)
 }
 
 pub(crate) fn extract_article(html: &str) -> Result<Document, ExtractDocumentError> {
@@ -183,3 +223,13 @@ impl IntoDocument for Url {
         get_article(self).await
     }
 }
+
+#[test]
+fn test_content_hash() {
+    let document = Document::from_parts("Title", "Body");
+    assert_eq!(document.content_hash(), 0x691b012dfb26d693);
+    assert_ne!(
+        document.content_hash(),
+        Document::from_parts("TitleBody", "").content_hash()
+    );
+}
@@ -1,13 +1,20 @@
-use docx_rs::{Document as DocxFile, FromXML};
+use std::collections::HashMap;
 use std::path::PathBuf;
 
-use std::fs::File;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
 
 use crate::context::document::{Document, IntoDocument};
 
-use super::FsDocumentError;
+use super::package::{
+    attribute, blocks_to_markdown, first_heading, parse_date, Package, StyledText, TextBlock,
+    TextStyle,
+};
+use super::{FsDocumentError, PackageDecodeError};
 
 /// A docx document that can be read from the file system.
+///
+/// The body of the document is markdown with a heading for each paragraph with a heading style, bold and italic text, lists and tables. The title and the created and modified dates are read from the metadata of the document.
 #[derive(Debug, Clone)]
 pub struct DocxDocument {
     path: PathBuf,
@@ -28,59 +35,344 @@ impl TryFrom<PathBuf> for DocxDocument {
 }
 
 impl IntoDocument for DocxDocument {
-    type Error = FsDocumentError<docx_rs::ReaderError>;
+    type Error = FsDocumentError<PackageDecodeError>;
 
     async fn into_document(self) -> Result<Document, Self::Error> {
-        let file = File::open(self.path)?;
-        let reader = std::io::BufReader::new(file);
-        let docx = DocxFile::from_xml(reader).map_err(FsDocumentError::Decode)?;
-        let mut text = String::new();
-        for section in docx.children {
-            match section {
-                docx_rs::DocumentChild::Paragraph(paragraph) => {
-                    for child in paragraph.children {
-                        match child {
-                            docx_rs::ParagraphChild::Run(run) => {
-                                for child in run.children {
-                                    match child {
-                                        docx_rs::RunChild::Text(text_child) => {
-                                            text += &text_child.text;
-                                        }
-                                        docx_rs::RunChild::Sym(_) => {}
-                                        docx_rs::RunChild::DeleteText(_) => {}
-                                        docx_rs::RunChild::Tab(_) => {}
-                                        docx_rs::RunChild::Break(_) => {}
-                                        docx_rs::RunChild::Drawing(_) => {}
-                                        docx_rs::RunChild::Shape(_) => {}
-                                        docx_rs::RunChild::CommentStart(_) => {}
-                                        docx_rs::RunChild::CommentEnd(_) => {}
-                                        docx_rs::RunChild::FieldChar(_) => {}
-                                        docx_rs::RunChild::InstrText(_) => {}
-                                        docx_rs::RunChild::DeleteInstrText(_) => {}
-                                        docx_rs::RunChild::InstrTextString(_) => {}
-                                    }
-                                }
-                            }
-                            docx_rs::ParagraphChild::Insert(_) => {}
-                            docx_rs::ParagraphChild::Delete(_) => {}
-                            docx_rs::ParagraphChild::BookmarkStart(_) => {}
-                            docx_rs::ParagraphChild::Hyperlink(_) => {}
-                            docx_rs::ParagraphChild::BookmarkEnd(_) => {}
-                            docx_rs::ParagraphChild::CommentStart(_) => {}
-                            docx_rs::ParagraphChild::CommentEnd(_) => {}
-                            docx_rs::ParagraphChild::StructuredDataTag(_) => {}
+        let mut package = Package::open(&self.path).await?;
+        let mut document = read_docx(&mut package).map_err(FsDocumentError::Decode)?;
+        document.set_source(self.path.display().to_string());
+        Ok(document)
+    }
+}
+
+fn read_docx(package: &mut Package) -> Result<Document, PackageDecodeError> {
+    let heading_levels = match package.read("word/styles.xml")? {
+        Some(styles) => heading_levels(&styles)?,
+        None => HashMap::new(),
+    };
+    let blocks = docx_blocks(
+        &package.read_required("word/document.xml")?,
+        &heading_levels,
+    )?;
+    let properties = match package.read("docProps/core.xml")? {
+        Some(core) => core_properties(&core)?,
+        None => CoreProperties::default(),
+    };
+
+    let title = properties
+        .title
+        .filter(|title| !title.is_empty())
+        .or_else(|| first_heading(&blocks))
+        .unwrap_or_default();
+    let mut document = Document::from_parts(title, blocks_to_markdown(&blocks));
+    if let Some(created) = properties.created.as_deref().and_then(parse_date) {
+        document.set_created_at(created);
+    }
+    if let Some(modified) = properties.modified.as_deref().and_then(parse_date) {
+        document.set_updated_at(modified);
+    }
+    Ok(document)
+}
+
+/// Find the heading level of each paragraph style in `word/styles.xml`
+fn heading_levels(xml: &str) -> Result<HashMap<String, u8>, PackageDecodeError> {
+    let mut reader = Reader::from_str(xml);
+    let mut levels = HashMap::new();
+    let mut style = None;
+    loop {
+        match reader.read_event()? {
+            Event::Start(element) | Event::Empty(element) => match element.local_name().as_ref() {
+                b"style" => style = attribute(&element, "styleId"),
+                b"name" => {
+                    let level = attribute(&element, "val").and_then(|name| {
+                        let name = name.to_lowercase();
+                        if name == "title" {
+                            return Some(1);
+                        }
+                        name.strip_prefix("heading ")?.parse::<u8>().ok()
+                    });
+                    if let (Some(style), Some(level)) = (&style, level) {
+                        levels.insert(style.clone(), level.clamp(1, 6));
+                    }
+                }
+                b"outlineLvl" => {
+                    let level = outline_level(&element);
+                    if let (Some(style), Some(level)) = (&style, level) {
+                        levels.entry(style.clone()).or_insert(level);
+                    }
+                }
+                _ => {}
+            },
+            Event::End(element) if element.local_name().as_ref() == b"style" => style = None,
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(levels)
+}
+
+/// Get the heading level of an outline level element. Outline levels start at 0, and level 9 is body text
+fn outline_level(element: &BytesStart) -> Option<u8> {
+    let level = attribute(element, "val")?.parse::<u8>().ok()?;
+    (level < 9).then(|| (level + 1).min(6))
+}
+
+/// Check if a toggle property like bold or italic is turned on
+fn toggle(element: &BytesStart) -> bool {
+    !matches!(
+        attribute(element, "val").as_deref(),
+        Some("0" | "false" | "off" | "none")
+    )
+}
+
+#[derive(Default)]
+struct Paragraph {
+    text: StyledText,
+    style: Option<String>,
+    outline_level: Option<u8>,
+    list_depth: Option<usize>,
+}
+
+/// Read the headings, paragraphs, lists and tables in `word/document.xml`
+fn docx_blocks(
+    xml: &str,
+    heading_levels: &HashMap<String, u8>,
+) -> Result<Vec<TextBlock>, PackageDecodeError> {
+    let mut reader = Reader::from_str(xml);
+    let mut blocks = Vec::new();
+    let mut paragraph: Option<Paragraph> = None;
+    let mut in_paragraph_properties = false;
+    let mut in_run_properties = false;
+    let mut in_text = false;
+    let mut run_style = TextStyle::default();
+    // Tables nested in a cell are flattened into the text of the cell
+    let mut table_depth = 0;
+    let mut rows: Vec<Vec<String>> = Vec::new();
+    let mut cell: Vec<String> = Vec::new();
+
+    loop {
+        match reader.read_event()? {
+            Event::Start(element) => match element.local_name().as_ref() {
+                b"p" => paragraph = Some(Paragraph::default()),
+                b"pPr" => in_paragraph_properties = true,
+                b"rPr" => in_run_properties = !in_paragraph_properties,
+                b"r" => run_style = TextStyle::default(),
+                b"t" => in_text = true,
+                b"tbl" => {
+                    table_depth += 1;
+                    if table_depth == 1 {
+                        rows.clear();
+                    }
+                }
+                b"tr" if table_depth == 1 => rows.push(Vec::new()),
+                b"tc" if table_depth == 1 => cell.clear(),
+                _ => property(
+                    &element,
+                    paragraph.as_mut(),
+                    in_paragraph_properties,
+                    in_run_properties,
+                    &mut run_style,
+                ),
+            },
+            Event::Empty(element) => match element.local_name().as_ref() {
+                b"tab" if !in_paragraph_properties => {
+                    if let Some(paragraph) = &mut paragraph {
+                        paragraph.text.push(" ", run_style);
+                    }
+                }
+                b"br" | b"cr" => {
+                    if let Some(paragraph) = &mut paragraph {
+                        paragraph.text.push("\n", run_style);
+                    }
+                }
+                _ => property(
+                    &element,
+                    paragraph.as_mut(),
+                    in_paragraph_properties,
+                    in_run_properties,
+                    &mut run_style,
+                ),
+            },
+            Event::Text(text) if in_text => {
+                if let Some(paragraph) = &mut paragraph {
+                    paragraph.text.push(&text.unescape()?, run_style);
+                }
+            }
+            Event::End(element) => match element.local_name().as_ref() {
+                b"pPr" => in_paragraph_properties = false,
+                b"rPr" => in_run_properties = false,
+                b"t" => in_text = false,
+                b"p" => {
+                    let Some(paragraph) = paragraph.take() else {
+                        continue;
+                    };
+                    if table_depth > 0 {
+                        let text = paragraph.text.plain();
+                        if !text.is_empty() {
+                            cell.push(text);
                         }
+                        continue;
+                    }
+                    let level = paragraph.outline_level.or_else(|| {
+                        paragraph
+                            .style
+                            .as_ref()
+                            .and_then(|style| heading_levels.get(style).copied())
+                    });
+                    let block = match (level, paragraph.list_depth) {
+                        (Some(level), _) => TextBlock::Heading {
+                            level,
+                            text: paragraph.text.plain(),
+                        },
+                        (None, Some(depth)) => TextBlock::ListItem {
+                            depth,
+                            text: paragraph.text.markdown(),
+                        },
+                        (None, None) => TextBlock::Paragraph(paragraph.text.markdown()),
+                    };
+                    let empty = match &block {
+                        TextBlock::Heading { text, .. }
+                        | TextBlock::ListItem { text, .. }
+                        | TextBlock::Paragraph(text) => text.is_empty(),
+                        TextBlock::Table(_) => false,
+                    };
+                    if !empty {
+                        blocks.push(block);
+                    }
+                }
+                b"tc" if table_depth == 1 => {
+                    if let Some(row) = rows.last_mut() {
+                        row.push(cell.join(" "));
+                    }
+                }
+                b"tbl" => {
+                    table_depth -= 1;
+                    if table_depth == 0 && !rows.is_empty() {
+                        blocks.push(TextBlock::Table(std::mem::take(&mut rows)));
                     }
                 }
-                docx_rs::DocumentChild::Table(_) => {}
-                docx_rs::DocumentChild::BookmarkStart(_) => {}
-                docx_rs::DocumentChild::BookmarkEnd(_) => {}
-                docx_rs::DocumentChild::CommentStart(_) => {}
-                docx_rs::DocumentChild::CommentEnd(_) => {}
-                docx_rs::DocumentChild::StructuredDataTag(_) => {}
-                docx_rs::DocumentChild::TableOfContents(_) => {}
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(blocks)
+}
+
+/// Apply a paragraph or run property element
+fn property(
+    element: &BytesStart,
+    paragraph: Option<&mut Paragraph>,
+    in_paragraph_properties: bool,
+    in_run_properties: bool,
+    run_style: &mut TextStyle,
+) {
+    match element.local_name().as_ref() {
+        b"b" if in_run_properties => run_style.bold = toggle(element),
+        b"i" if in_run_properties => run_style.italic = toggle(element),
+        name if in_paragraph_properties => {
+            let Some(paragraph) = paragraph else {
+                return;
+            };
+            match name {
+                b"pStyle" => paragraph.style = attribute(element, "val"),
+                b"outlineLvl" => paragraph.outline_level = outline_level(element),
+                b"numPr" => {
+                    paragraph.list_depth.get_or_insert(0);
+                }
+                b"ilvl" => {
+                    paragraph.list_depth = attribute(element, "val")
+                        .and_then(|level| level.parse().ok())
+                        .or(Some(0));
+                }
+                _ => {}
             }
         }
-        Ok(Document::from_parts("", text))
+        _ => {}
     }
 }
+
+#[derive(Default)]
+struct CoreProperties {
+    title: Option<String>,
+    created: Option<String>,
+    modified: Option<String>,
+}
+
+/// Read the title and dates from `docProps/core.xml`
+fn core_properties(xml: &str) -> Result<CoreProperties, PackageDecodeError> {
+    let mut reader = Reader::from_str(xml);
+    let mut properties = CoreProperties::default();
+    let mut current = None;
+    loop {
+        match reader.read_event()? {
+            Event::Start(element) => current = Some(element.local_name().as_ref().to_vec()),
+            Event::Text(text) => {
+                let text = text.unescape()?.trim().to_string();
+                match current.as_deref() {
+                    Some(b"title") => properties.title = Some(text),
+                    Some(b"created") => properties.created = Some(text),
+                    Some(b"modified") => properties.modified = Some(text),
+                    _ => {}
+                }
+            }
+            Event::End(_) => current = None,
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(properties)
+}
+
+#[test]
+fn test_docx_blocks() {
+    let styles = r#"<w:styles xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main">
+        <w:style w:type="paragraph" w:styleId="Heading1"><w:name w:val="heading 1"/></w:style>
+        <w:style w:type="paragraph" w:styleId="Custom"><w:name w:val="Custom"/><w:pPr><w:outlineLvl w:val="1"/></w:pPr></w:style>
+    </w:styles>"#;
+    let document = r#"<w:document xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main"><w:body>
+        <w:p><w:pPr><w:pStyle w:val="Heading1"/><w:rPr><w:b/></w:rPr></w:pPr><w:r><w:t>Kalosm</w:t></w:r></w:p>
+        <w:p><w:r><w:t xml:space="preserve">Kalosm is a </w:t></w:r><w:r><w:rPr><w:b/></w:rPr><w:t>local</w:t></w:r><w:r><w:rPr><w:i/><w:b w:val="0"/></w:rPr><w:t xml:space="preserve"> AI </w:t></w:r><w:r><w:t>library &amp; more.</w:t></w:r></w:p>
+        <w:p><w:pPr><w:pStyle w:val="Custom"/></w:pPr><w:r><w:t>Features</w:t></w:r></w:p>
+        <w:p><w:pPr><w:numPr><w:ilvl w:val="0"/><w:numId w:val="1"/></w:numPr></w:pPr><w:r><w:t>Language</w:t></w:r></w:p>
+        <w:p><w:pPr><w:numPr><w:ilvl w:val="1"/><w:numId w:val="1"/></w:numPr></w:pPr><w:r><w:t>Audio</w:t></w:r></w:p>
+        <w:p/>
+        <w:tbl>
+            <w:tr><w:tc><w:p><w:r><w:t>Model</w:t></w:r></w:p></w:tc><w:tc><w:p><w:r><w:t>Size</w:t></w:r></w:p></w:tc></w:tr>
+            <w:tr><w:tc><w:p><w:r><w:t>Phi</w:t></w:r></w:p></w:tc><w:tc><w:p><w:r><w:t>3B</w:t></w:r></w:p></w:tc></w:tr>
+        </w:tbl>
+    </w:body></w:document>"#;
+
+    let blocks = docx_blocks(document, &heading_levels(styles).unwrap()).unwrap();
+    assert_eq!(
+        blocks,
+        [
+            TextBlock::Heading {
+                level: 1,
+                text: "Kalosm".to_string()
+            },
+            TextBlock::Paragraph("Kalosm is a **local** *AI* library & more.".to_string()),
+            TextBlock::Heading {
+                level: 2,
+                text: "Features".to_string()
+            },
+            TextBlock::ListItem {
+                depth: 0,
+                text: "Language".to_string()
+            },
+            TextBlock::ListItem {
+                depth: 1,
+                text: "Audio".to_string()
+            },
+            TextBlock::Table(vec![
+                vec!["Model".to_string(), "Size".to_string()],
+                vec!["Phi".to_string(), "3B".to_string()],
+            ]),
+        ]
+    );
+    assert_eq!(
+        blocks_to_markdown(&blocks),
+        "# Kalosm\n\nKalosm is a **local** *AI* library & more.\n\n## Features\n\n- Language\n  - Audio\n\n| Model | Size |\n| --- | --- |\n| Phi | 3B |"
+    );
+}
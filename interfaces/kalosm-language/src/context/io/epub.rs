@@ -0,0 +1,432 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use ego_tree::NodeRef;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use scraper::{ElementRef, Html, Node};
+
+use crate::context::document::{Document, IntoDocument};
+
+use super::package::{
+    attribute, blocks_to_markdown, first_heading, parse_date, Package, StyledText, TextBlock,
+    TextStyle,
+};
+use super::{FsDocumentError, PackageDecodeError};
+
+/// An epub book that can be read from the file system.
+///
+/// The chapters of the book are read in reading order. The body of the document is markdown with the headings, bold and italic text, lists and tables in each chapter. The title, publication date and modified date are read from the metadata of the book.
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm_language::prelude::*;
+/// use std::path::PathBuf;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let document = EpubDocument::try_from(PathBuf::from("./book.epub"))
+///         .unwrap()
+///         .into_document()
+///         .await
+///         .unwrap();
+///     println!("{}", document.title());
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct EpubDocument {
+    path: PathBuf,
+}
+
+impl TryFrom<PathBuf> for EpubDocument {
+    type Error = FsDocumentError;
+
+    fn try_from(path: PathBuf) -> Result<Self, Self::Error> {
+        if !path.is_file() {
+            return Err(std::io::Error::from(std::io::ErrorKind::NotFound).into());
+        }
+        if path.extension().unwrap() != "epub" {
+            return Err(FsDocumentError::WrongFileType);
+        }
+        Ok(Self { path })
+    }
+}
+
+impl IntoDocument for EpubDocument {
+    type Error = FsDocumentError<PackageDecodeError>;
+
+    async fn into_document(self) -> Result<Document, Self::Error> {
+        let mut package = Package::open(&self.path).await?;
+        let mut document = read_epub(&mut package).map_err(FsDocumentError::Decode)?;
+        document.set_source(self.path.display().to_string());
+        Ok(document)
+    }
+}
+
+fn read_epub(package: &mut Package) -> Result<Document, PackageDecodeError> {
+    let container = package.read_required("META-INF/container.xml")?;
+    let package_path = package_path(&container)?.ok_or_else(|| {
+        PackageDecodeError::MissingPart("the rootfile in META-INF/container.xml".to_string())
+    })?;
+    let book = book(&package.read_required(&package_path)?)?;
+
+    let mut blocks = Vec::new();
+    for href in book.chapters() {
+        let path = resolve_path(&package_path, href);
+        match package.read(&path)? {
+            Some(chapter) => blocks.extend(html_blocks(&chapter)),
+            None => tracing::error!("The epub is missing the chapter {path}"),
+        }
+    }
+
+    let title = book
+        .title
+        .filter(|title| !title.is_empty())
+        .or_else(|| first_heading(&blocks))
+        .unwrap_or_default();
+    let mut document = Document::from_parts(title, blocks_to_markdown(&blocks));
+    if let Some(date) = book.date.as_deref().and_then(parse_date) {
+        document.set_created_at(date);
+    }
+    if let Some(modified) = book.modified.as_deref().and_then(parse_date) {
+        document.set_updated_at(modified);
+    }
+    Ok(document)
+}
+
+/// Find the path of the package document in `META-INF/container.xml`
+fn package_path(xml: &str) -> Result<Option<String>, PackageDecodeError> {
+    let mut reader = Reader::from_str(xml);
+    loop {
+        match reader.read_event()? {
+            Event::Start(element) | Event::Empty(element)
+                if element.local_name().as_ref() == b"rootfile" =>
+            {
+                if let Some(path) = attribute(&element, "full-path") {
+                    return Ok(Some(path));
+                }
+            }
+            Event::Eof => return Ok(None),
+            _ => {}
+        }
+    }
+}
+
+/// The metadata and reading order of an epub from its package document
+#[derive(Debug, Default)]
+struct Book {
+    title: Option<String>,
+    date: Option<String>,
+    modified: Option<String>,
+    /// The path of each item in the manifest by id
+    manifest: HashMap<String, String>,
+    /// The ids of the items in reading order
+    spine: Vec<String>,
+}
+
+impl Book {
+    /// Get the paths of the chapters relative to the package document in reading order
+    fn chapters(&self) -> impl Iterator<Item = &str> {
+        self.spine
+            .iter()
+            .filter_map(|id| self.manifest.get(id).map(String::as_str))
+    }
+}
+
+fn book(xml: &str) -> Result<Book, PackageDecodeError> {
+    let mut reader = Reader::from_str(xml);
+    let mut book = Book::default();
+    let mut current = None;
+    loop {
+        match reader.read_event()? {
+            Event::Start(element) => {
+                let name = element.local_name().as_ref().to_vec();
+                current = match name.as_slice() {
+                    b"meta"
+                        if attribute(&element, "property").as_deref()
+                            == Some("dcterms:modified") =>
+                    {
+                        Some(b"modified".to_vec())
+                    }
+                    _ => Some(name),
+                };
+            }
+            Event::Empty(element) => match element.local_name().as_ref() {
+                b"item" => {
+                    if let (Some(id), Some(href)) =
+                        (attribute(&element, "id"), attribute(&element, "href"))
+                    {
+                        book.manifest.insert(id, href);
+                    }
+                }
+                b"itemref" => book.spine.extend(attribute(&element, "idref")),
+                _ => {}
+            },
+            Event::Text(text) => {
+                let text = text.unescape()?.trim().to_string();
+                // Books can have multiple titles and dates. The first one is the main title or publication date
+                match current.as_deref() {
+                    Some(b"title") => _ = book.title.get_or_insert(text),
+                    Some(b"date") => _ = book.date.get_or_insert(text),
+                    Some(b"modified") => book.modified = Some(text),
+                    _ => {}
+                }
+            }
+            Event::End(_) => current = None,
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(book)
+}
+
+/// Resolve a percent encoded href relative to the file at `base`
+fn resolve_path(base: &str, href: &str) -> String {
+    let href = href.split('#').next().unwrap_or_default();
+    let mut segments: Vec<&str> = base.split('/').collect();
+    // Remove the file name of the base path
+    segments.pop();
+    for segment in href.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+    percent_decode(&segments.join("/"))
+}
+
+fn percent_decode(text: &str) -> String {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut rest = text.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let decoded = (byte == b'%')
+            .then(|| tail.get(..2))
+            .flatten()
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match decoded {
+            Some(decoded) => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            None => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// Read the headings, paragraphs, lists and tables in a chapter
+fn html_blocks(html: &str) -> Vec<TextBlock> {
+    let html = Html::parse_document(html);
+    let mut reader = HtmlBlockReader::default();
+    reader.read(html.tree.root(), TextStyle::default());
+    reader.flush();
+    reader.blocks
+}
+
+#[derive(Default)]
+struct HtmlBlockReader {
+    blocks: Vec<TextBlock>,
+    text: StyledText,
+    kind: HtmlBlockKind,
+    list_depth: usize,
+}
+
+#[derive(Default, Clone, Copy)]
+enum HtmlBlockKind {
+    #[default]
+    Paragraph,
+    Heading(u8),
+    ListItem(usize),
+}
+
+impl HtmlBlockReader {
+    fn read(&mut self, node: NodeRef<Node>, style: TextStyle) {
+        match node.value() {
+            Node::Text(text) => self.text.push(&collapse_whitespace(text), style),
+            Node::Element(element) => match element.name() {
+                "head" | "script" | "style" => {}
+                "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                    let level = element.name()[1..].parse().unwrap_or(1);
+                    self.read_block(node, style, HtmlBlockKind::Heading(level));
+                }
+                "ul" | "ol" => {
+                    self.flush();
+                    self.list_depth += 1;
+                    self.read_children(node, style);
+                    self.list_depth -= 1;
+                }
+                "li" => {
+                    let depth = self.list_depth.saturating_sub(1);
+                    self.read_block(node, style, HtmlBlockKind::ListItem(depth));
+                }
+                "table" => {
+                    self.flush();
+                    if let Some(table) = ElementRef::wrap(node) {
+                        let rows = table_rows(table);
+                        if !rows.is_empty() {
+                            self.blocks.push(TextBlock::Table(rows));
+                        }
+                    }
+                }
+                "br" => self.text.push("\n", style),
+                "b" | "strong" => self.read_children(
+                    node,
+                    TextStyle {
+                        bold: true,
+                        ..style
+                    },
+                ),
+                "i" | "em" => self.read_children(
+                    node,
+                    TextStyle {
+                        italic: true,
+                        ..style
+                    },
+                ),
+                "p" | "div" | "section" | "article" | "blockquote" | "pre" | "dt" | "dd"
+                | "figcaption" | "hr" | "body" => {
+                    self.flush();
+                    self.read_children(node, style);
+                    self.flush();
+                }
+                _ => self.read_children(node, style),
+            },
+            _ => self.read_children(node, style),
+        }
+    }
+
+    fn read_children(&mut self, node: NodeRef<Node>, style: TextStyle) {
+        for child in node.children() {
+            self.read(child, style);
+        }
+    }
+
+    /// Read an element that is a block of its own kind. Paragraphs inside the element are part of the block
+    fn read_block(&mut self, node: NodeRef<Node>, style: TextStyle, kind: HtmlBlockKind) {
+        self.flush();
+        let parent = std::mem::replace(&mut self.kind, kind);
+        self.read_children(node, style);
+        self.flush();
+        self.kind = parent;
+    }
+
+    /// Finish the current block
+    fn flush(&mut self) {
+        let text = std::mem::take(&mut self.text);
+        let block = match self.kind {
+            HtmlBlockKind::Heading(level) => TextBlock::Heading {
+                level,
+                text: text.plain(),
+            },
+            HtmlBlockKind::ListItem(depth) => TextBlock::ListItem {
+                depth,
+                text: text.markdown(),
+            },
+            HtmlBlockKind::Paragraph => TextBlock::Paragraph(text.markdown()),
+        };
+        if let TextBlock::Heading { text, .. }
+        | TextBlock::ListItem { text, .. }
+        | TextBlock::Paragraph(text) = &block
+        {
+            if text.is_empty() {
+                return;
+            }
+        }
+        self.blocks.push(block);
+    }
+}
+
+/// Get the text of each cell in each row of a table
+fn table_rows(table: ElementRef) -> Vec<Vec<String>> {
+    let row_selector = scraper::Selector::parse("tr").unwrap();
+    table
+        .select(&row_selector)
+        .map(|row| {
+            row.children()
+                .filter_map(ElementRef::wrap)
+                .filter(|cell| matches!(cell.value().name(), "td" | "th"))
+                .map(|cell| {
+                    collapse_whitespace(&cell.text().collect::<String>())
+                        .trim()
+                        .to_string()
+                })
+                .collect::<Vec<_>>()
+        })
+        .filter(|row| !row.is_empty())
+        .collect()
+}
+
+/// Collapse runs of whitespace into a single space like a browser does
+fn collapse_whitespace(text: &str) -> String {
+    let mut collapsed = String::with_capacity(text.len());
+    let mut whitespace = false;
+    for c in text.chars() {
+        if c.is_whitespace() {
+            whitespace = true;
+        } else {
+            if whitespace {
+                collapsed.push(' ');
+            }
+            whitespace = false;
+            collapsed.push(c);
+        }
+    }
+    if whitespace {
+        collapsed.push(' ');
+    }
+    collapsed
+}
+
+#[test]
+fn test_read_epub_chapters() {
+    let book = book(
+        r#"<package xmlns="http://www.idpf.org/2007/opf" xmlns:dc="http://purl.org/dc/elements/1.1/" version="3.0">
+            <metadata>
+                <dc:title>The Kalosm Book</dc:title>
+                <dc:title>A Subtitle</dc:title>
+                <dc:date>2024-03-01</dc:date>
+                <meta property="dcterms:modified">2024-05-02T10:00:00Z</meta>
+            </metadata>
+            <manifest>
+                <item id="nav" href="nav.xhtml" media-type="application/xhtml+xml" properties="nav"/>
+                <item id="chapter-2" href="text/chapter%202.xhtml" media-type="application/xhtml+xml"/>
+                <item id="chapter-1" href="text/chapter1.xhtml" media-type="application/xhtml+xml"/>
+            </manifest>
+            <spine><itemref idref="chapter-1"/><itemref idref="chapter-2"/></spine>
+        </package>"#,
+    )
+    .unwrap();
+    assert_eq!(book.title.as_deref(), Some("The Kalosm Book"));
+    assert_eq!(book.modified.as_deref(), Some("2024-05-02T10:00:00Z"));
+    let chapters: Vec<_> = book
+        .chapters()
+        .map(|href| resolve_path("OEBPS/content.opf", href))
+        .collect();
+    assert_eq!(
+        chapters,
+        ["OEBPS/text/chapter1.xhtml", "OEBPS/text/chapter 2.xhtml"]
+    );
+
+    let blocks = html_blocks(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+        <html xmlns="http://www.w3.org/1999/xhtml"><head><title>Chapter 1</title></head>
+        <body>
+            <h1>Chapter <em>One</em></h1>
+            <p>Kalosm runs   <strong>local</strong>
+               models.</p>
+            <ul><li><p>Language</p><ul><li>Llama</li></ul></li></ul>
+            <table><tr><th>Model</th><th>Size</th></tr><tr><td>Phi</td><td>3B</td></tr></table>
+        </body></html>"#,
+    );
+    assert_eq!(
+        blocks_to_markdown(&blocks),
+        "# Chapter One\n\nKalosm runs **local** models.\n\n- Language\n  - Llama\n\n| Model | Size |\n| --- | --- |\n| Phi | 3B |"
+    );
+}
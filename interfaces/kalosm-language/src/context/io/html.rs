@@ -33,11 +33,13 @@ impl IntoDocument for HtmlDocument {
     type Error = FsDocumentError<ExtractDocumentError>;
 
     async fn into_document(self) -> Result<Document, Self::Error> {
-        let file = File::open(self.path).await?;
+        let file = File::open(&self.path).await?;
         let mut html = String::new();
         tokio::io::BufReader::new(file)
             .read_to_string(&mut html)
             .await?;
-        extract_article(&html).map_err(FsDocumentError::Decode)
+        let mut document = extract_article(&html).map_err(FsDocumentError::Decode)?;
+        document.set_source(self.path.display().to_string());
+        Ok(document)
     }
 }
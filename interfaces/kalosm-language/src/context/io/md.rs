@@ -33,7 +33,7 @@ impl IntoDocument for MdDocument {
     type Error = FsDocumentError<ExtractDocumentError>;
 
     async fn into_document(self) -> Result<Document, Self::Error> {
-        let file = File::open(self.path).await?;
+        let file = File::open(&self.path).await?;
         let mut md = String::new();
         tokio::io::BufReader::new(file)
             .read_to_string(&mut md)
@@ -42,6 +42,8 @@ impl IntoDocument for MdDocument {
 
         let mut html_output = String::new();
         pulldown_cmark::html::push_html(&mut html_output, parser);
-        extract_article(&html_output).map_err(FsDocumentError::Decode)
+        let mut document = extract_article(&html_output).map_err(FsDocumentError::Decode)?;
+        document.set_source(self.path.display().to_string());
+        Ok(document)
     }
 }
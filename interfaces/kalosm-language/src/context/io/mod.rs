@@ -5,10 +5,14 @@ use std::path::PathBuf;
 use tokio::task::JoinSet;
 mod docx;
 pub use docx::*;
+mod epub;
+pub use epub::*;
 mod html;
 pub use html::*;
 mod md;
 pub use md::*;
+mod package;
+pub use package::PackageDecodeError;
 mod pdf;
 pub use self::pdf::*;
 mod txt;
@@ -55,7 +59,10 @@ pub enum TextFileDecodeError {
     Pdf(#[from] lopdf::Error),
     /// An error reading the docx file
     #[error("Failed to read docx file: {0}")]
-    Docx(#[from] docx_rs::ReaderError),
+    Docx(PackageDecodeError),
+    /// An error reading the epub file
+    #[error("Failed to read epub file: {0}")]
+    Epub(PackageDecodeError),
 }
 
 /// A document that can be read from the file system.
@@ -79,6 +86,8 @@ pub enum TextFileDecodeError {
 pub enum FsDocument {
     /// A docx document.
     Docx(DocxDocument),
+    /// An epub book.
+    Epub(EpubDocument),
     /// An html document.
     Html(HtmlDocument),
     /// A markdown document.
@@ -98,6 +107,7 @@ impl TryFrom<PathBuf> for FsDocument {
         }
         match path.extension().and_then(|ext| ext.to_str()) {
             Some("docx") => Ok(Self::Docx(DocxDocument::try_from(path)?)),
+            Some("epub") => Ok(Self::Epub(EpubDocument::try_from(path)?)),
             Some("html") => Ok(Self::Html(HtmlDocument::try_from(path)?)),
             Some("md") => Ok(Self::Md(MdDocument::try_from(path)?)),
             Some("pdf") => Ok(Self::Pdf(PdfDocument::try_from(path)?)),
@@ -116,6 +126,10 @@ impl IntoDocument for FsDocument {
                 .into_document()
                 .await
                 .map_err(|err| err.map_decode(TextFileDecodeError::Docx)),
+            Self::Epub(epub) => epub
+                .into_document()
+                .await
+                .map_err(|err| err.map_decode(TextFileDecodeError::Epub)),
             Self::Html(html) => html
                 .into_document()
                 .await
@@ -210,6 +224,25 @@ impl DocumentFolder {
         Self::try_from(path.into())
     }
 
+    /// Get the paths of every file in the folder and its subfolders that can be read as a [`FsDocument`].
+    pub async fn files(&self) -> Result<Vec<PathBuf>, std::io::Error> {
+        let mut files = Vec::new();
+        let mut folders = vec![self.path.clone()];
+        while let Some(folder) = folders.pop() {
+            let mut read_dir = tokio::fs::read_dir(&folder).await?;
+            while let Some(entry) = read_dir.next_entry().await? {
+                let path = entry.path();
+                if path.is_dir() {
+                    folders.push(path);
+                } else if FsDocument::try_from(path.clone()).is_ok() {
+                    files.push(path);
+                }
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+
     fn start_into_documents<'a>(
         &'a self,
         set: &'a mut JoinSet<Result<Document, FsDocumentError<TextFileDecodeError>>>,
@@ -0,0 +1,186 @@
+use std::io::{Cursor, Read};
+use std::path::Path;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use quick_xml::events::BytesStart;
+use zip::result::ZipError;
+use zip::ZipArchive;
+
+use super::{markdown_table, FsDocumentError};
+
+/// An error that can occur when decoding a document that is stored as a zip package of xml files, like a docx or epub file.
+#[derive(Debug, thiserror::Error)]
+pub enum PackageDecodeError {
+    /// An error reading the zip archive
+    #[error("Failed to read zip archive: {0}")]
+    Zip(#[from] ZipError),
+    /// An error parsing one of the xml files in the package
+    #[error("Failed to parse xml: {0}")]
+    Xml(#[from] quick_xml::Error),
+    /// The package is missing a file that is required to read the document
+    #[error("The package is missing {0}")]
+    MissingPart(String),
+}
+
+/// A zip package of xml files.
+pub(crate) struct Package {
+    archive: ZipArchive<Cursor<Vec<u8>>>,
+}
+
+impl Package {
+    /// Read the package at the path.
+    pub(crate) async fn open(path: &Path) -> Result<Self, FsDocumentError<PackageDecodeError>> {
+        let bytes = tokio::fs::read(path).await?;
+        Self::from_bytes(bytes).map_err(FsDocumentError::Decode)
+    }
+
+    /// Read a package from the bytes of the zip archive.
+    pub(crate) fn from_bytes(bytes: Vec<u8>) -> Result<Self, PackageDecodeError> {
+        Ok(Self {
+            archive: ZipArchive::new(Cursor::new(bytes))?,
+        })
+    }
+
+    /// Read a file in the package as text, or return `None` if the file doesn't exist.
+    pub(crate) fn read(&mut self, name: &str) -> Result<Option<String>, PackageDecodeError> {
+        let mut file = match self.archive.by_name(name) {
+            Ok(file) => file,
+            Err(ZipError::FileNotFound) => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        let mut text = String::new();
+        file.read_to_string(&mut text).map_err(ZipError::Io)?;
+        Ok(Some(text))
+    }
+
+    /// Read a file in the package as text, or return an error if the file doesn't exist.
+    pub(crate) fn read_required(&mut self, name: &str) -> Result<String, PackageDecodeError> {
+        self.read(name)?
+            .ok_or_else(|| PackageDecodeError::MissingPart(name.to_string()))
+    }
+}
+
+/// Get the value of the attribute with the local name, ignoring the namespace prefix.
+pub(crate) fn attribute(element: &BytesStart, name: &str) -> Option<String> {
+    element
+        .attributes()
+        .flatten()
+        .find(|attribute| attribute.key.local_name().as_ref() == name.as_bytes())
+        .and_then(|attribute| attribute.unescape_value().ok())
+        .map(|value| value.into_owned())
+}
+
+/// Parse a date in the W3C date format used by docx and epub metadata. This is either a full timestamp or a date with only the year, month and day.
+pub(crate) fn parse_date(text: &str) -> Option<DateTime<Utc>> {
+    let text = text.trim();
+    if let Ok(date) = DateTime::parse_from_rfc3339(text) {
+        return Some(date.with_timezone(&Utc));
+    }
+    let date = NaiveDate::parse_from_str(text, "%Y-%m-%d")
+        .or_else(|_| NaiveDate::parse_from_str(&format!("{text}-01"), "%Y-%m-%d"))
+        .or_else(|_| NaiveDate::parse_from_str(&format!("{text}-01-01"), "%Y-%m-%d"))
+        .ok()?;
+    Some(date.and_hms_opt(0, 0, 0)?.and_utc())
+}
+
+/// The inline style of a run of text.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub(crate) struct TextStyle {
+    pub(crate) bold: bool,
+    pub(crate) italic: bool,
+}
+
+/// A paragraph of text with inline styles.
+#[derive(Debug, Default)]
+pub(crate) struct StyledText {
+    runs: Vec<(String, TextStyle)>,
+}
+
+impl StyledText {
+    /// Add text to the end of the paragraph.
+    pub(crate) fn push(&mut self, text: &str, style: TextStyle) {
+        match self.runs.last_mut() {
+            Some((last, last_style)) if *last_style == style => last.push_str(text),
+            _ => self.runs.push((text.to_string(), style)),
+        }
+    }
+
+    /// Get the text without styles.
+    pub(crate) fn plain(&self) -> String {
+        let text: String = self.runs.iter().map(|(text, _)| text.as_str()).collect();
+        text.trim().to_string()
+    }
+
+    /// Get the text with bold and italic runs marked with markdown emphasis.
+    pub(crate) fn markdown(&self) -> String {
+        let mut markdown = String::new();
+        for (text, style) in &self.runs {
+            let marker = match (style.bold, style.italic) {
+                (true, true) => "***",
+                (true, false) => "**",
+                (false, true) => "*",
+                (false, false) => "",
+            };
+            let trimmed = text.trim();
+            if marker.is_empty() || trimmed.is_empty() {
+                markdown.push_str(text);
+                continue;
+            }
+            // Emphasis markers must touch the text they wrap
+            let start = text.len() - text.trim_start().len();
+            let end = start + trimmed.len();
+            markdown.push_str(&text[..start]);
+            markdown.push_str(marker);
+            markdown.push_str(trimmed);
+            markdown.push_str(marker);
+            markdown.push_str(&text[end..]);
+        }
+        markdown.trim().to_string()
+    }
+}
+
+/// A heading, paragraph, list item or table in a docx or epub document.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum TextBlock {
+    Heading { level: u8, text: String },
+    Paragraph(String),
+    ListItem { depth: usize, text: String },
+    Table(Vec<Vec<String>>),
+}
+
+/// Write the blocks as markdown.
+pub(crate) fn blocks_to_markdown(blocks: &[TextBlock]) -> String {
+    let mut markdown = String::new();
+    let mut previous_list_item = false;
+    for block in blocks {
+        let list_item = matches!(block, TextBlock::ListItem { .. });
+        if !markdown.is_empty() {
+            // Items in the same list are only separated by a newline
+            markdown.push_str(if list_item && previous_list_item {
+                "\n"
+            } else {
+                "\n\n"
+            });
+        }
+        previous_list_item = list_item;
+        match block {
+            TextBlock::Heading { level, text } => {
+                markdown.push_str(&format!("{} {text}", "#".repeat(*level as usize)))
+            }
+            TextBlock::Paragraph(text) => markdown.push_str(text),
+            TextBlock::ListItem { depth, text } => {
+                markdown.push_str(&format!("{}- {text}", "  ".repeat(*depth)))
+            }
+            TextBlock::Table(rows) => markdown.push_str(&markdown_table(rows)),
+        }
+    }
+    markdown
+}
+
+/// Get the text of the first top level heading.
+pub(crate) fn first_heading(blocks: &[TextBlock]) -> Option<String> {
+    blocks.iter().find_map(|block| match block {
+        TextBlock::Heading { level: 1, text } => Some(text.clone()),
+        _ => None,
+    })
+}
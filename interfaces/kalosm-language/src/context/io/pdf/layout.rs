@@ -0,0 +1,929 @@
+use std::collections::{BTreeMap, HashMap};
+use std::ops::{Range, RangeInclusive};
+
+use lopdf::content::Content;
+use lopdf::{Dictionary, Document as PdfDoc, Encoding, Object, ObjectId};
+
+use crate::context::document::Document;
+
+/// Spans on the same baseline that are closer than this many ems are part of the same fragment of text
+const FRAGMENT_GAP: f32 = 1.0;
+/// Spans in the same fragment that are further apart than this many ems are separated by a space
+const WORD_GAP: f32 = 0.15;
+/// The minimum width of the space between two columns in ems
+const GUTTER_WIDTH: f32 = 1.0;
+/// The minimum number of rows with text on both sides of a gutter before it is treated as a column break
+const MIN_COLUMN_ROWS: usize = 3;
+/// The minimum number of characters on each side of a gutter in a row of two columns of text
+const MIN_COLUMN_CHARS: usize = 10;
+/// Lines with a font this many times larger than the body text are headings
+const HEADING_SCALE: f32 = 1.15;
+/// Lines longer than this are never headings
+const MAX_HEADING_CHARS: usize = 150;
+/// Lines further apart than this many ems start a new paragraph
+const PARAGRAPH_SPACING: f32 = 1.6;
+
+/// A 2d affine transformation in the `[a b c d e f]` form pdf content streams use
+type Matrix = [f32; 6];
+
+const IDENTITY: Matrix = [1.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+
+/// Multiply two matrices. The result applies `first` and then `second`
+fn multiply(first: Matrix, second: Matrix) -> Matrix {
+    let [a1, b1, c1, d1, e1, f1] = first;
+    let [a2, b2, c2, d2, e2, f2] = second;
+    [
+        a1 * a2 + b1 * c2,
+        a1 * b2 + b1 * d2,
+        c1 * a2 + d1 * c2,
+        c1 * b2 + d1 * d2,
+        e1 * a2 + f1 * c2 + e2,
+        e1 * b2 + f1 * d2 + f2,
+    ]
+}
+
+fn translate(x: f32, y: f32) -> Matrix {
+    [1.0, 0.0, 0.0, 1.0, x, y]
+}
+
+/// Read a matrix from the operands of a `cm` or `Tm` operator
+fn matrix(operands: &[Object]) -> Matrix {
+    let mut matrix = IDENTITY;
+    if operands.len() == 6 {
+        for (value, operand) in matrix.iter_mut().zip(operands) {
+            *value = operand.as_float().unwrap_or_default();
+        }
+    }
+    matrix
+}
+
+/// A run of text drawn at a position on a page
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct TextSpan {
+    /// The left edge of the text in page space
+    pub(crate) x: f32,
+    /// The baseline of the text in page space
+    pub(crate) y: f32,
+    /// The estimated width of the text in page space
+    pub(crate) width: f32,
+    pub(crate) font_size: f32,
+    pub(crate) text: String,
+}
+
+impl TextSpan {
+    fn right(&self) -> f32 {
+        self.x + self.width
+    }
+
+    fn center(&self) -> f32 {
+        self.x + self.width / 2.0
+    }
+}
+
+/// The information about a font needed to decode and position text
+struct PdfFont<'a> {
+    encoding: Encoding<'a>,
+    first_char: i64,
+    /// The width of each character starting at `first_char` in thousandths of an em
+    widths: Vec<f32>,
+    /// Composite fonts use two bytes per character
+    two_byte: bool,
+}
+
+impl<'a> PdfFont<'a> {
+    fn new(doc: &'a PdfDoc, font: &'a Dictionary) -> Result<Self, lopdf::Error> {
+        let encoding = font.get_font_encoding(doc)?;
+        let two_byte = font
+            .get(b"Subtype")
+            .and_then(Object::as_name)
+            .is_ok_and(|subtype| subtype == b"Type0");
+        let first_char = font
+            .get(b"FirstChar")
+            .and_then(Object::as_i64)
+            .unwrap_or_default();
+        let widths = font
+            .get(b"Widths")
+            .and_then(|widths| doc.dereference(widths))
+            .and_then(|(_, widths)| widths.as_array())
+            .map(|widths| {
+                widths
+                    .iter()
+                    .map(|width| width.as_float().unwrap_or_default())
+                    .collect()
+            })
+            .unwrap_or_default();
+        Ok(Self {
+            encoding,
+            first_char,
+            widths,
+            two_byte,
+        })
+    }
+
+    /// Returns the width of the text in ems, the number of characters and the number of spaces
+    fn measure(&self, bytes: &[u8]) -> (f32, usize, usize) {
+        if self.two_byte {
+            let characters = bytes.len() / 2;
+            return (characters as f32 * 0.5, characters, 0);
+        }
+        let width = bytes
+            .iter()
+            .map(|byte| {
+                usize::try_from(*byte as i64 - self.first_char)
+                    .ok()
+                    .and_then(|index| self.widths.get(index))
+                    .filter(|width| **width > 0.0)
+                    .map_or(0.5, |width| width / 1000.0)
+            })
+            .sum();
+        let spaces = bytes.iter().filter(|byte| **byte == b' ').count();
+        (width, bytes.len(), spaces)
+    }
+}
+
+/// The graphics and text state while reading a content stream
+struct TextState<'a> {
+    ctm: Matrix,
+    ctm_stack: Vec<Matrix>,
+    text_matrix: Matrix,
+    line_matrix: Matrix,
+    font: Option<&'a PdfFont<'a>>,
+    font_size: f32,
+    leading: f32,
+    char_spacing: f32,
+    word_spacing: f32,
+    horizontal_scale: f32,
+}
+
+impl TextState<'_> {
+    fn new() -> Self {
+        Self {
+            ctm: IDENTITY,
+            ctm_stack: Vec::new(),
+            text_matrix: IDENTITY,
+            line_matrix: IDENTITY,
+            font: None,
+            font_size: 0.0,
+            leading: 0.0,
+            char_spacing: 0.0,
+            word_spacing: 0.0,
+            horizontal_scale: 1.0,
+        }
+    }
+
+    fn next_line(&mut self, x: f32, y: f32) {
+        self.line_matrix = multiply(translate(x, y), self.line_matrix);
+        self.text_matrix = self.line_matrix;
+    }
+
+    fn advance(&mut self, x: f32) {
+        self.text_matrix = multiply(translate(x, 0.0), self.text_matrix);
+    }
+
+    fn show(&mut self, text: Option<&Object>, spans: &mut Vec<TextSpan>) {
+        let (Some(font), Some(Object::String(bytes, _))) = (self.font, text) else {
+            return;
+        };
+        let (ems, characters, spaces) = font.measure(bytes);
+        let width = (ems * self.font_size
+            + self.char_spacing * characters as f32
+            + self.word_spacing * spaces as f32)
+            * self.horizontal_scale;
+        let rendering = multiply(self.text_matrix, self.ctm);
+        if let Ok(text) = PdfDoc::decode_text(&font.encoding, bytes) {
+            if !text.trim().is_empty() {
+                spans.push(TextSpan {
+                    x: rendering[4],
+                    y: rendering[5],
+                    width: width * rendering[0].hypot(rendering[1]),
+                    font_size: self.font_size * rendering[2].hypot(rendering[3]),
+                    text,
+                });
+            }
+        }
+        self.advance(width);
+    }
+}
+
+/// Read the position and size of each run of text on a page
+pub(crate) fn page_spans(doc: &PdfDoc, page_id: ObjectId) -> Result<Vec<TextSpan>, lopdf::Error> {
+    let fonts: BTreeMap<Vec<u8>, PdfFont> = doc
+        .get_page_fonts(page_id)?
+        .into_iter()
+        .filter_map(|(name, font)| Some((name, PdfFont::new(doc, font).ok()?)))
+        .collect();
+    let content = Content::decode(&doc.get_page_content(page_id)?)?;
+
+    let mut state = TextState::new();
+    let mut spans = Vec::new();
+    for operation in &content.operations {
+        let operands = &operation.operands;
+        let number = |index: usize| {
+            operands
+                .get(index)
+                .and_then(|operand| operand.as_float().ok())
+                .unwrap_or_default()
+        };
+        match operation.operator.as_str() {
+            "q" => state.ctm_stack.push(state.ctm),
+            "Q" => {
+                if let Some(ctm) = state.ctm_stack.pop() {
+                    state.ctm = ctm;
+                }
+            }
+            "cm" => state.ctm = multiply(matrix(operands), state.ctm),
+            "BT" => {
+                state.text_matrix = IDENTITY;
+                state.line_matrix = IDENTITY;
+            }
+            "Tf" => {
+                state.font = operands
+                    .first()
+                    .and_then(|name| name.as_name().ok())
+                    .and_then(|name| fonts.get(name));
+                state.font_size = number(1);
+            }
+            "Tc" => state.char_spacing = number(0),
+            "Tw" => state.word_spacing = number(0),
+            "Tz" => state.horizontal_scale = number(0) / 100.0,
+            "TL" => state.leading = number(0),
+            "Td" => state.next_line(number(0), number(1)),
+            "TD" => {
+                state.leading = -number(1);
+                state.next_line(number(0), number(1));
+            }
+            "Tm" => {
+                state.line_matrix = matrix(operands);
+                state.text_matrix = state.line_matrix;
+            }
+            "T*" => state.next_line(0.0, -state.leading),
+            "Tj" => state.show(operands.first(), &mut spans),
+            "'" => {
+                state.next_line(0.0, -state.leading);
+                state.show(operands.first(), &mut spans);
+            }
+            "\"" => {
+                state.word_spacing = number(0);
+                state.char_spacing = number(1);
+                state.next_line(0.0, -state.leading);
+                state.show(operands.get(2), &mut spans);
+            }
+            "TJ" => {
+                let Some(Ok(items)) = operands.first().map(Object::as_array) else {
+                    continue;
+                };
+                for item in items {
+                    match item {
+                        Object::String(..) => state.show(Some(item), &mut spans),
+                        other => {
+                            if let Ok(offset) = other.as_float() {
+                                state.advance(
+                                    -offset / 1000.0 * state.font_size * state.horizontal_scale,
+                                );
+                            }
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(spans)
+}
+
+/// The fragments of text that share a baseline
+#[derive(Debug, Clone, PartialEq)]
+struct Row {
+    y: f32,
+    /// The runs of text in the row from left to right. Fragments are separated by large gaps like the space between columns or table cells
+    fragments: Vec<TextSpan>,
+}
+
+impl Row {
+    fn font_size(&self) -> f32 {
+        self.fragments
+            .first()
+            .map(|fragment| fragment.font_size)
+            .unwrap_or_default()
+    }
+
+    fn text(&self) -> String {
+        self.fragments
+            .iter()
+            .map(|fragment| fragment.text.trim())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Check if there is no text in the row between `start` and `end`
+    fn is_open(&self, start: f32, end: f32) -> bool {
+        self.fragments
+            .iter()
+            .all(|fragment| fragment.right() <= start || fragment.x >= end)
+    }
+}
+
+/// Group spans into rows from the top of the page to the bottom
+fn rows(mut spans: Vec<TextSpan>) -> Vec<Row> {
+    spans.sort_by(|a, b| b.y.total_cmp(&a.y));
+    let mut grouped: Vec<Vec<TextSpan>> = Vec::new();
+    for span in spans {
+        match grouped.last_mut() {
+            Some(row) if row[0].y - span.y < 0.5 * row[0].font_size.min(span.font_size) => {
+                row.push(span)
+            }
+            _ => grouped.push(vec![span]),
+        }
+    }
+
+    grouped
+        .into_iter()
+        .map(|mut spans| {
+            spans.sort_by(|a, b| a.x.total_cmp(&b.x));
+            Row {
+                y: spans[0].y,
+                fragments: merge_spans(spans),
+            }
+        })
+        .collect()
+}
+
+/// Merge spans on the same baseline into fragments of text separated by large gaps
+fn merge_spans(spans: Vec<TextSpan>) -> Vec<TextSpan> {
+    let mut fragments: Vec<TextSpan> = Vec::new();
+    for span in spans {
+        if let Some(last) = fragments.last_mut() {
+            let gap = span.x - last.right();
+            let size = last.font_size.max(span.font_size);
+            if gap < FRAGMENT_GAP * size {
+                if gap > WORD_GAP * size
+                    && !last.text.ends_with(char::is_whitespace)
+                    && !span.text.starts_with(char::is_whitespace)
+                {
+                    last.text.push(' ');
+                }
+                last.text.push_str(&span.text);
+                last.width = last.width.max(span.right() - last.x);
+                continue;
+            }
+        }
+        fragments.push(span);
+    }
+    fragments
+}
+
+/// A vertical gap between columns of text
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Gutter {
+    x: f32,
+    half_width: f32,
+}
+
+impl Gutter {
+    /// Check if no text in the row crosses the gutter
+    fn is_open(&self, row: &Row) -> bool {
+        row.is_open(self.x - self.half_width, self.x + self.half_width)
+    }
+
+    /// Check if the row has lines of text on both sides of the gutter. Rows with only a few characters on one side are more likely to be part of a table
+    fn splits(&self, row: &Row) -> bool {
+        let chars = |left: bool| -> usize {
+            row.fragments
+                .iter()
+                .filter(|fragment| (fragment.center() < self.x) == left)
+                .map(|fragment| fragment.text.trim().chars().count())
+                .sum()
+        };
+        self.is_open(row) && chars(true) >= MIN_COLUMN_CHARS && chars(false) >= MIN_COLUMN_CHARS
+    }
+}
+
+/// Find the space between two columns of text if the rows have multiple columns
+fn find_gutter(rows: &[Row]) -> Option<Gutter> {
+    let fragments = || rows.iter().flat_map(|row| &row.fragments);
+    let left = fragments().map(|fragment| fragment.x).reduce(f32::min)?;
+    let right = fragments().map(TextSpan::right).reduce(f32::max)?;
+    let mut sizes: Vec<f32> = fragments().map(|fragment| fragment.font_size).collect();
+    sizes.sort_by(f32::total_cmp);
+    let size = sizes[sizes.len() / 2];
+    let width = right - left;
+    if width <= 0.0 || size <= 0.0 {
+        return None;
+    }
+    let half_width = GUTTER_WIDTH * size / 2.0;
+
+    // Only look for gutters in the middle of the text so ragged line endings are not mistaken for columns
+    let mut best: Option<(usize, Range<f32>)> = None;
+    let mut x = left + width * 0.25;
+    while x <= left + width * 0.75 {
+        let gutter = Gutter { x, half_width };
+        let split_rows = rows.iter().filter(|row| gutter.splits(row)).count();
+        match &mut best {
+            Some((count, range)) if *count == split_rows && range.end + size / 2.0 >= x => {
+                range.end = x
+            }
+            Some((count, _)) if *count >= split_rows => {}
+            _ => best = Some((split_rows, x..x)),
+        }
+        x += size / 2.0;
+    }
+    let (count, range) = best?;
+    (count >= MIN_COLUMN_ROWS).then_some(Gutter {
+        x: (range.start + range.end) / 2.0,
+        half_width,
+    })
+}
+
+/// Sort rows into reading order.
+///
+/// Rows in the part of the page with multiple columns are read one column at a time. Rows above or below the columns, and rows that cross the columns like figure captions, are read in order from top to bottom.
+fn reading_order(rows: Vec<Row>) -> Vec<Row> {
+    let Some(gutter) = find_gutter(&rows) else {
+        return rows;
+    };
+
+    // The columns start at the first row with text on both sides of the gutter and end at the last row, including any lines close to those rows that don't cross the gutter
+    let close = |first: &Row, second: &Row| {
+        gutter.is_open(first)
+            && gutter.is_open(second)
+            && first.y - second.y <= PARAGRAPH_SPACING * first.font_size().max(second.font_size())
+    };
+    let mut start = rows
+        .iter()
+        .position(|row| gutter.splits(row))
+        .unwrap_or_default();
+    let mut end = rows
+        .iter()
+        .rposition(|row| gutter.splits(row))
+        .unwrap_or_default()
+        + 1;
+    while start > 0 && close(&rows[start - 1], &rows[start]) {
+        start -= 1;
+    }
+    while end < rows.len() && close(&rows[end - 1], &rows[end]) {
+        end += 1;
+    }
+
+    let mut rows = rows;
+    let after = rows.split_off(end);
+    let columns = rows.split_off(start);
+    let mut ordered = reading_order(rows);
+
+    let mut left = Vec::new();
+    let mut right = Vec::new();
+    let flush = |ordered: &mut Vec<Row>, left: &mut Vec<Row>, right: &mut Vec<Row>| {
+        ordered.extend(reading_order(std::mem::take(left)));
+        ordered.extend(reading_order(std::mem::take(right)));
+    };
+    for row in columns {
+        if !gutter.is_open(&row) {
+            flush(&mut ordered, &mut left, &mut right);
+            ordered.push(row);
+            continue;
+        }
+        let (left_fragments, right_fragments): (Vec<_>, Vec<_>) = row
+            .fragments
+            .into_iter()
+            .partition(|fragment| fragment.center() < gutter.x);
+        for (fragments, column) in [(left_fragments, &mut left), (right_fragments, &mut right)] {
+            if !fragments.is_empty() {
+                column.push(Row {
+                    y: row.y,
+                    fragments,
+                });
+            }
+        }
+    }
+    flush(&mut ordered, &mut left, &mut right);
+    ordered.extend(reading_order(after));
+
+    ordered
+}
+
+/// Round a font size so sizes that only differ by rounding errors are grouped together
+fn round_size(size: f32) -> i32 {
+    (size * 2.0).round() as i32
+}
+
+/// Find the most common font size of the text in the document
+fn body_font_size<'a>(rows: impl Iterator<Item = &'a Row>) -> f32 {
+    let mut chars_per_size: HashMap<i32, usize> = HashMap::new();
+    for fragment in rows.flat_map(|row| &row.fragments) {
+        *chars_per_size
+            .entry(round_size(fragment.font_size))
+            .or_default() += fragment.text.chars().count();
+    }
+    chars_per_size
+        .into_iter()
+        .max_by_key(|(size, chars)| (*chars, -size))
+        .map(|(size, _)| size as f32 / 2.0)
+        .unwrap_or_default()
+}
+
+/// Check if a row looks like a heading
+fn is_heading(row: &Row, body_size: f32) -> bool {
+    row.fragments.len() == 1
+        && row.font_size() >= body_size * HEADING_SCALE
+        && row.fragments[0].text.trim().chars().count() <= MAX_HEADING_CHARS
+}
+
+/// Check if the fragments in two rows line up like the cells in a table
+fn is_aligned(previous: &Row, row: &Row) -> bool {
+    let tolerance = row.font_size();
+    row.fragments
+        .iter()
+        .filter(|fragment| {
+            previous.fragments.iter().any(|other| {
+                (other.x - fragment.x).abs() < tolerance
+                    || (other.right() - fragment.right()).abs() < tolerance
+            })
+        })
+        .count()
+        >= 2
+}
+
+/// Check if a row continues the paragraph on the previous row
+fn continues_paragraph(previous: &Row, row: &Row) -> bool {
+    let size = row.font_size();
+    (size - previous.font_size()).abs() < 1.0
+        && previous.y > row.y
+        && previous.y - row.y <= PARAGRAPH_SPACING * size
+}
+
+/// Add a line to the end of a paragraph, joining words that were hyphenated across the line break
+fn push_line(paragraph: &mut String, line: &str) {
+    let mut chars = paragraph.chars().rev();
+    let hyphenated = chars.next() == Some('-')
+        && chars.next().is_some_and(char::is_alphabetic)
+        && line.starts_with(char::is_lowercase);
+    if hyphenated {
+        paragraph.pop();
+    } else if !paragraph.is_empty() {
+        paragraph.push(' ');
+    }
+    paragraph.push_str(line);
+}
+
+/// Group the rows of a page into headings, paragraphs and tables
+fn page_blocks(rows: &[Row], body_size: f32, heading_sizes: &[i32]) -> Vec<PdfBlockKind> {
+    let mut blocks = Vec::new();
+    let mut index = 0;
+    while index < rows.len() {
+        let row = &rows[index];
+
+        // Consecutive rows with aligned cells are a table
+        let mut table_end = index + 1;
+        while row.fragments.len() > 1
+            && table_end < rows.len()
+            && rows[table_end].fragments.len() > 1
+            && is_aligned(&rows[table_end - 1], &rows[table_end])
+        {
+            table_end += 1;
+        }
+        if table_end - index > 1 {
+            blocks.push(PdfBlockKind::Table(
+                rows[index..table_end]
+                    .iter()
+                    .map(|row| {
+                        row.fragments
+                            .iter()
+                            .map(|fragment| fragment.text.trim().to_string())
+                            .collect()
+                    })
+                    .collect(),
+            ));
+            index = table_end;
+            continue;
+        }
+
+        let text = row.text();
+        let previous = index.checked_sub(1).map(|index| &rows[index]);
+        let continues = previous.is_some_and(|previous| continues_paragraph(previous, row));
+        if is_heading(row, body_size) {
+            let size = round_size(row.font_size());
+            let level = heading_sizes
+                .iter()
+                .position(|heading_size| *heading_size == size)
+                .map_or(6, |position| (position + 1).min(6)) as u8;
+            match blocks.last_mut() {
+                // Headings that wrap onto multiple lines are one heading
+                Some(PdfBlockKind::Heading {
+                    level: last_level,
+                    text: last_text,
+                }) if continues && *last_level == level => push_line(last_text, &text),
+                _ => blocks.push(PdfBlockKind::Heading { level, text }),
+            }
+        } else {
+            match blocks.last_mut() {
+                Some(PdfBlockKind::Paragraph(paragraph)) if continues => {
+                    push_line(paragraph, &text)
+                }
+                _ => blocks.push(PdfBlockKind::Paragraph(text)),
+            }
+        }
+        index += 1;
+    }
+    blocks
+}
+
+/// Lay out the text on each page into headings, paragraphs and tables in reading order
+pub(crate) fn layout_pages(pages: Vec<(u32, Vec<TextSpan>)>) -> BTreeMap<u32, Vec<PdfBlockKind>> {
+    let pages: Vec<(u32, Vec<Row>)> = pages
+        .into_iter()
+        .map(|(page, spans)| (page, reading_order(rows(spans))))
+        .collect();
+    let all_rows = || pages.iter().flat_map(|(_, rows)| rows);
+
+    // Heading levels are based on the font sizes of headings across the whole document
+    let body_size = body_font_size(all_rows());
+    let mut heading_sizes: Vec<i32> = all_rows()
+        .filter(|row| is_heading(row, body_size))
+        .map(|row| round_size(row.font_size()))
+        .collect();
+    heading_sizes.sort_by(|a, b| b.cmp(a));
+    heading_sizes.dedup();
+
+    pages
+        .iter()
+        .map(|(page, rows)| (*page, page_blocks(rows, body_size, &heading_sizes)))
+        .collect()
+}
+
+/// The content of a [`PdfBlock`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum PdfBlockKind {
+    /// A heading. Level 1 is the heading with the largest font in the document
+    Heading {
+        /// The level of the heading from 1 to 6
+        level: u8,
+        /// The text of the heading
+        text: String,
+    },
+    /// A paragraph of text
+    Paragraph(String),
+    /// A table with the text of each cell in each row
+    Table(Vec<Vec<String>>),
+}
+
+impl PdfBlockKind {
+    /// Write the block as markdown
+    fn to_markdown(&self) -> String {
+        match self {
+            Self::Heading { level, text } => format!("{} {text}", "#".repeat(*level as usize)),
+            Self::Paragraph(text) => text.clone(),
+            Self::Table(rows) => markdown_table(rows),
+        }
+    }
+}
+
+/// Write a table as markdown. The first row is the header of the table.
+pub(crate) fn markdown_table(rows: &[Vec<String>]) -> String {
+    let columns = rows.iter().map(Vec::len).max().unwrap_or_default();
+    let mut markdown = String::new();
+    for (index, row) in rows.iter().enumerate() {
+        if index == 1 {
+            markdown.push_str(&"| --- ".repeat(columns));
+            markdown.push_str("|\n");
+        }
+        for column in 0..columns {
+            let cell = row.get(column).map(String::as_str).unwrap_or_default();
+            markdown.push_str("| ");
+            markdown.push_str(&cell.replace('|', "\\|"));
+            markdown.push(' ');
+        }
+        markdown.push_str("|\n");
+    }
+    markdown.truncate(markdown.trim_end().len());
+    markdown
+}
+
+/// A heading, paragraph or table in a [`PdfLayout`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct PdfBlock {
+    page: u32,
+    headings: Vec<String>,
+    byte_range: Range<usize>,
+    kind: PdfBlockKind,
+}
+
+impl PdfBlock {
+    /// Get the page number the block is on, starting at 1.
+    pub fn page(&self) -> u32 {
+        self.page
+    }
+
+    /// Get the titles of the headings the block is under from the outermost heading to the innermost heading. If the block is a heading, it includes the block itself.
+    pub fn headings(&self) -> &[String] {
+        &self.headings
+    }
+
+    /// Get the byte range of the block in the [`PdfLayout::body`].
+    pub fn byte_range(&self) -> Range<usize> {
+        self.byte_range.clone()
+    }
+
+    /// Get the content of the block.
+    pub fn kind(&self) -> &PdfBlockKind {
+        &self.kind
+    }
+}
+
+/// Information about where a chunk of a [`PdfLayout`] came from in the original pdf.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PdfChunkMetadata {
+    /// The pages the chunk spans
+    pub pages: RangeInclusive<u32>,
+    /// The titles of the headings the start of the chunk is under
+    pub headings: Vec<String>,
+    /// If the chunk contains part of a table
+    pub contains_table: bool,
+}
+
+/// The structure of a pdf document with the text in reading order.
+///
+/// The body of the layout is markdown with a heading for each heading in the pdf, a paragraph for each paragraph and a table for each table, so it works well with the [`MarkdownChunker`](crate::prelude::MarkdownChunker). You can look up the page and heading a chunk came from with [`PdfLayout::chunk_metadata`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct PdfLayout {
+    title: String,
+    body: String,
+    blocks: Vec<PdfBlock>,
+}
+
+impl PdfLayout {
+    /// Create a layout from the blocks on each page
+    pub(crate) fn from_pages(
+        title: String,
+        pages: impl IntoIterator<Item = (u32, Vec<PdfBlockKind>)>,
+    ) -> Self {
+        let mut body = String::new();
+        let mut blocks = Vec::new();
+        let mut heading_stack: Vec<(u8, String)> = Vec::new();
+        for (page, kinds) in pages {
+            for kind in kinds {
+                if let PdfBlockKind::Heading { level, text } = &kind {
+                    while heading_stack
+                        .last()
+                        .filter(|(parent_level, _)| parent_level >= level)
+                        .is_some()
+                    {
+                        heading_stack.pop();
+                    }
+                    heading_stack.push((*level, text.clone()));
+                }
+                if !body.is_empty() {
+                    body.push_str("\n\n");
+                }
+                let start = body.len();
+                body.push_str(&kind.to_markdown());
+                blocks.push(PdfBlock {
+                    page,
+                    headings: heading_stack.iter().map(|(_, text)| text.clone()).collect(),
+                    byte_range: start..body.len(),
+                    kind,
+                });
+            }
+        }
+
+        // Fall back to the first top level heading if the pdf doesn't have a table of contents
+        let title = if title.is_empty() {
+            blocks
+                .iter()
+                .find_map(|block| match &block.kind {
+                    PdfBlockKind::Heading { level: 1, text } => Some(text.clone()),
+                    _ => None,
+                })
+                .unwrap_or_default()
+        } else {
+            title
+        };
+
+        Self {
+            title,
+            body,
+            blocks,
+        }
+    }
+
+    /// Get the title of the pdf.
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    /// Get the text of the pdf as markdown in reading order.
+    pub fn body(&self) -> &str {
+        &self.body
+    }
+
+    /// Get the headings, paragraphs and tables in the pdf in reading order.
+    pub fn blocks(&self) -> &[PdfBlock] {
+        &self.blocks
+    }
+
+    /// Get the pages and headings a byte range of the [`PdfLayout::body`] came from. This is useful to attach the provenance of a chunk of the document to search results.
+    ///
+    /// Returns `None` if the range doesn't overlap any text in the pdf.
+    ///
+    /// # Example
+    /// ```rust, no_run
+    /// use kalosm_language::prelude::*;
+    /// use std::path::PathBuf;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let layout = PdfDocument::try_from(PathBuf::from("./paper.pdf"))
+    ///         .unwrap()
+    ///         .into_layout()
+    ///         .await
+    ///         .unwrap();
+    ///     let document = Document::from(layout.clone());
+    ///     let bert = Bert::new().await.unwrap();
+    ///     let chunks = MarkdownChunker::new().chunk(&document, &bert).await.unwrap();
+    ///     for chunk in chunks {
+    ///         let metadata = layout.chunk_metadata(chunk.byte_range.clone()).unwrap();
+    ///         println!("pages {:?} under {:?}", metadata.pages, metadata.headings);
+    ///     }
+    /// }
+    /// ```
+    pub fn chunk_metadata(&self, byte_range: Range<usize>) -> Option<PdfChunkMetadata> {
+        let mut overlapping = self.blocks.iter().filter(|block| {
+            block.byte_range.start < byte_range.end.max(byte_range.start + 1)
+                && byte_range.start < block.byte_range.end
+        });
+        let first = overlapping.next()?;
+        let mut metadata = PdfChunkMetadata {
+            pages: first.page..=first.page,
+            headings: first.headings.clone(),
+            contains_table: matches!(first.kind, PdfBlockKind::Table(_)),
+        };
+        for block in overlapping {
+            metadata.pages = *metadata.pages.start()..=block.page;
+            metadata.contains_table |= matches!(block.kind, PdfBlockKind::Table(_));
+        }
+        Some(metadata)
+    }
+}
+
+impl From<PdfLayout> for Document {
+    fn from(layout: PdfLayout) -> Self {
+        Document::from_parts(layout.title, layout.body)
+    }
+}
+
+#[test]
+fn test_pdf_layout() {
+    let span = |x: f32, y: f32, font_size: f32, text: &str| TextSpan {
+        x,
+        y,
+        width: text.len() as f32 * font_size * 0.5,
+        font_size,
+        text: text.to_string(),
+    };
+    let mut spans = vec![span(50.0, 750.0, 20.0, "Multi Column Paper")];
+    // Two columns of body text. The right column starts after the left column in the content stream, but is drawn next to it
+    let left = [
+        "The left column starts here and",
+        "continues on the next line with a hyphen-",
+        "ated word before the column ends",
+        "with one more line of text.",
+    ];
+    let right = [
+        "The right column is read after the",
+        "left column even though the lines are",
+        "drawn on the same baselines as the",
+        "lines in the left column.",
+    ];
+    for (i, line) in left.iter().enumerate() {
+        spans.push(span(50.0, 720.0 - i as f32 * 12.0, 10.0, line));
+    }
+    spans.push(span(300.0, 720.0, 14.0, "Results"));
+    for (i, line) in right.iter().enumerate() {
+        spans.push(span(300.0, 708.0 - i as f32 * 12.0, 10.0, line));
+    }
+    // A table below both columns
+    for (i, row) in [["Model", "Score"], ["Small", "0.5"], ["Large", "0.9"]]
+        .iter()
+        .enumerate()
+    {
+        spans.push(span(50.0, 600.0 - i as f32 * 12.0, 10.0, row[0]));
+        spans.push(span(350.0, 600.0 - i as f32 * 12.0, 10.0, row[1]));
+    }
+
+    let pages = layout_pages(vec![(1, spans)]);
+    let layout = PdfLayout::from_pages(String::new(), pages);
+    assert_eq!(layout.title(), "Multi Column Paper");
+    assert_eq!(
+        layout.body(),
+        "# Multi Column Paper\n\n\
+        The left column starts here and continues on the next line with a hyphenated word before the column ends with one more line of text.\n\n\
+        ## Results\n\n\
+        The right column is read after the left column even though the lines are drawn on the same baselines as the lines in the left column.\n\n\
+        | Model | Score |\n| --- | --- |\n| Small | 0.5 |\n| Large | 0.9 |"
+    );
+
+    let table = layout.body().find("| Model").unwrap();
+    let metadata = layout.chunk_metadata(table..layout.body().len()).unwrap();
+    assert_eq!(metadata.pages, 1..=1);
+    assert_eq!(metadata.headings, ["Multi Column Paper", "Results"]);
+    assert!(metadata.contains_table);
+}
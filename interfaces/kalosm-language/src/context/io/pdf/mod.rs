@@ -0,0 +1,186 @@
+use crate::context::document::Document;
+use crate::context::document::IntoDocument;
+use lopdf::{Document as PdfDoc, Object};
+use std::fmt::Debug;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use super::FsDocumentError;
+
+mod layout;
+pub use layout::*;
+
+/// A pdf document that can be read from the file system.
+#[derive(Debug, Clone)]
+pub struct PdfDocument {
+    path: PathBuf,
+}
+
+impl TryFrom<PathBuf> for PdfDocument {
+    type Error = FsDocumentError;
+
+    fn try_from(path: PathBuf) -> Result<Self, Self::Error> {
+        if !path.is_file() {
+            return Err(std::io::Error::from(std::io::ErrorKind::NotFound).into());
+        }
+        if path.extension().unwrap() != "pdf" {
+            return Err(FsDocumentError::WrongFileType);
+        }
+        Ok(Self { path })
+    }
+}
+
+impl PdfDocument {
+    /// Read the pdf and extract the text on each page in reading order with the headings, paragraphs and tables on the page.
+    ///
+    /// Multi-column pages are read one column at a time. Headings are detected by font size, and rows of text with aligned cells are detected as tables.
+    ///
+    /// # Example
+    /// ```rust, no_run
+    /// use kalosm_language::prelude::*;
+    /// use std::path::PathBuf;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let layout = PdfDocument::try_from(PathBuf::from("./paper.pdf"))
+    ///         .unwrap()
+    ///         .into_layout()
+    ///         .await
+    ///         .unwrap();
+    ///     for block in layout.blocks() {
+    ///         println!("page {}: {:?}", block.page(), block.kind());
+    ///     }
+    /// }
+    /// ```
+    pub async fn into_layout(self) -> Result<PdfLayout, FsDocumentError<lopdf::Error>> {
+        let path = &self.path;
+
+        let doc = load_pdf(&self.path).await?;
+        let title = doc
+            .get_toc()
+            .map_err(FsDocumentError::Decode)?
+            .toc
+            .into_iter()
+            .min_by_key(|toc| toc.level)
+            .map(|toc| toc.title.to_string())
+            .unwrap_or_default();
+
+        let mut pages = Vec::new();
+        let mut fallback_pages = Vec::new();
+        let mut errors = Vec::new();
+        for (page_number, page_id) in doc.get_pages() {
+            match page_spans(&doc, page_id) {
+                Ok(spans) if !spans.is_empty() => pages.push((page_number, spans)),
+                result => {
+                    if let Err(error) = result {
+                        errors.push(format!(
+                            "Failed to read the layout of page {page_number} id={page_id:?}: {error}"
+                        ));
+                    }
+                    // Fall back to the plain text of the page if the layout can't be read
+                    match get_page_text(&doc, page_number) {
+                        Ok(blocks) => fallback_pages.push((page_number, blocks)),
+                        Err(error) => errors.push(error.to_string()),
+                    }
+                }
+            }
+        }
+        for error in errors.iter().take(10) {
+            tracing::error!(
+                "Encountered error while extracting text from PDF at {path:?}: {error}"
+            );
+        }
+
+        let mut pages = layout_pages(pages);
+        pages.extend(fallback_pages);
+
+        Ok(PdfLayout::from_pages(title, pages))
+    }
+}
+
+impl IntoDocument for PdfDocument {
+    type Error = FsDocumentError<lopdf::Error>;
+
+    async fn into_document(self) -> Result<Document, Self::Error> {
+        let source = self.path.display().to_string();
+        let mut document: Document = self.into_layout().await?.into();
+        document.set_source(source);
+        Ok(document)
+    }
+}
+
+static IGNORE: &[&[u8]] = &[
+    b"Length",
+    b"BBox",
+    b"FormType",
+    b"Matrix",
+    b"Type",
+    b"XObject",
+    b"Subtype",
+    b"Filter",
+    b"ColorSpace",
+    b"Width",
+    b"Height",
+    b"BitsPerComponent",
+    b"Length1",
+    b"Length2",
+    b"Length3",
+    b"PTEX.FileName",
+    b"PTEX.PageNumber",
+    b"PTEX.InfoDict",
+    b"FontDescriptor",
+    b"ExtGState",
+    b"MediaBox",
+    b"Annot",
+];
+
+fn filter_func(object_id: (u32, u16), object: &mut Object) -> Option<((u32, u16), Object)> {
+    if IGNORE.contains(&object.type_name().unwrap_or_default()) {
+        return None;
+    }
+    if let Ok(d) = object.as_dict_mut() {
+        d.remove(b"Producer");
+        d.remove(b"ModDate");
+        d.remove(b"Creator");
+        d.remove(b"ProcSet");
+        d.remove(b"Procset");
+        d.remove(b"XObject");
+        d.remove(b"MediaBox");
+        d.remove(b"Annots");
+        if d.is_empty() {
+            return None;
+        }
+    }
+    Some((object_id, object.to_owned()))
+}
+
+async fn load_pdf<P: AsRef<Path>>(path: P) -> Result<PdfDoc, Error> {
+    PdfDoc::load_filtered(path, filter_func)
+        .await
+        .map_err(|e| Error::new(ErrorKind::Other, e.to_string()))
+}
+
+/// Extract the text of a page without layout information as paragraphs
+fn get_page_text(doc: &PdfDoc, page_number: u32) -> Result<Vec<PdfBlockKind>, Error> {
+    let text = doc.extract_text(&[page_number]).map_err(|e| {
+        Error::new(
+            ErrorKind::Other,
+            format!("Failed to extract text from page {page_number}: {e:}"),
+        )
+    })?;
+    let mut paragraphs = Vec::new();
+    let mut paragraph = String::new();
+    for line in text.lines().map(str::trim).chain([""]) {
+        if line.is_empty() {
+            if !paragraph.is_empty() {
+                paragraphs.push(PdfBlockKind::Paragraph(std::mem::take(&mut paragraph)));
+            }
+        } else {
+            if !paragraph.is_empty() {
+                paragraph.push(' ');
+            }
+            paragraph.push_str(line);
+        }
+    }
+    Ok(paragraphs)
+}
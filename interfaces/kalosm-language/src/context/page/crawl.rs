@@ -18,7 +18,7 @@ use tokio::time::Instant;
 use url::Origin;
 use url::Url;
 
-const COOLDOWN: Duration = Duration::from_secs(5);
+pub(crate) const COOLDOWN: Duration = Duration::from_secs(5);
 
 /// Feedback that can be given to the crawler after visiting a page.
 pub enum CrawlFeedback {
@@ -269,22 +269,24 @@ impl<T: CrawlingCallback> Crawler<T> {
     }
 }
 
-async fn try_get_robot(origin: &Origin) -> Option<Robot> {
+/// The user agent used to find the rules for the crawler in robots.txt files
+pub(crate) fn default_user_agent() -> &'static str {
+    option_env!("CARGO_BIN_NAME").unwrap_or("Crawler")
+}
+
+pub(crate) async fn try_get_robot(
+    client: &reqwest::Client,
+    origin: &Origin,
+    user_agent: &str,
+) -> Option<Robot> {
     let robots_txt_url = origin.ascii_serialization() + "/robots.txt";
     let robots_txt_url = Url::parse(&robots_txt_url).ok()?;
-    let robots_txt_content = match reqwest::get(robots_txt_url.clone()).await {
-        Ok(response) => match response.text().await {
-            Ok(text) => text,
-            Err(_) => {
-                return None;
-            }
-        },
-        Err(_) => {
-            return None;
-        }
-    };
-    let current_package_name = option_env!("CARGO_BIN_NAME").unwrap_or("Crawler");
-    let robots_txt = Robot::new(&robots_txt_content, current_package_name.as_bytes()).ok()?;
+    let response = client.get(robots_txt_url).send().await.ok()?;
+    if !response.status().is_success() {
+        return None;
+    }
+    let robots_txt_content = response.text().await.ok()?;
+    let robots_txt = Robot::new(user_agent, robots_txt_content.as_bytes()).ok()?;
     Some(robots_txt)
 }
 
@@ -297,7 +299,8 @@ struct DomainQueue<T> {
 
 impl<T: CrawlingCallback> DomainQueue<T> {
     async fn new(origin: Origin, crawler: Crawler<T>) -> Self {
-        let robots_txt = try_get_robot(&origin).await;
+        let robots_txt =
+            try_get_robot(&reqwest::Client::new(), &origin, default_user_agent()).await;
         let (queue, mut rx) = tokio::sync::mpsc::unbounded_channel::<Url>();
 
         let pool = get_local_pool();
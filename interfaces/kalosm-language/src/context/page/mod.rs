@@ -7,3 +7,5 @@ pub use node::*;
 #[allow(clippy::module_inception)]
 mod page;
 pub use page::*;
+mod website;
+pub use website::*;
@@ -0,0 +1,412 @@
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use scraper::{Html, Selector};
+use texting_robots::Robot;
+use tokio::time::{Duration, Instant};
+use url::{Origin, Url};
+
+use super::crawl::{default_user_agent, try_get_robot, COOLDOWN};
+use crate::context::document::{Document, IntoDocuments};
+
+/// The maximum number of sitemaps that are read from a sitemap index
+const MAX_SITEMAPS: usize = 10;
+
+/// An error that can occur when crawling a [`Website`].
+#[derive(Debug, thiserror::Error)]
+pub enum WebsiteCrawlError {
+    /// An error occurred when fetching the start page of the website.
+    #[error("Failed to fetch start page: {0}")]
+    FetchStartPage(#[from] reqwest::Error),
+    /// The robots.txt file of the website doesn't allow crawling the start page.
+    #[error("The robots.txt file doesn't allow crawling {0}")]
+    Disallowed(Url),
+}
+
+/// A pattern that matches urls. `*` matches any number of characters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct UrlPattern(String);
+
+impl UrlPattern {
+    fn matches(&self, url: &Url) -> bool {
+        let mut parts = self.0.split('*');
+        let mut remaining = url.as_str();
+        // The first part must be at the start of the url
+        let first = parts.next().unwrap_or_default();
+        let Some(rest) = remaining.strip_prefix(first) else {
+            return false;
+        };
+        remaining = rest;
+        let parts: Vec<_> = parts.collect();
+        let Some((last, middle)) = parts.split_last() else {
+            // There are no wildcards, so the pattern must match the whole url
+            return remaining.is_empty();
+        };
+        for part in middle {
+            match remaining.find(part) {
+                Some(index) => remaining = &remaining[index + part.len()..],
+                None => return false,
+            }
+        }
+        // The last part must be at the end of the url
+        remaining.ends_with(last)
+    }
+}
+
+/// A website that can be crawled to add documents to a search index.
+///
+/// The crawler is polite by default. It follows the robots.txt rules for the website, waits between requests to the same domain, and only follows links on the same domain as the start page. The main article of each page is extracted with readability to remove navigation, ads and other boilerplate.
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm_language::prelude::*;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let website = Website::new(Url::parse("https://docs.rs/kalosm/latest/kalosm/").unwrap())
+///         .with_max_depth(2)
+///         .with_max_pages(20)
+///         .with_include_pattern("https://docs.rs/kalosm/*")
+///         .with_exclude_pattern("*/src/*");
+///     let documents = website.into_documents().await.unwrap();
+///     for document in documents {
+///         println!("{}", document.title());
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Website {
+    start: Url,
+    max_depth: usize,
+    max_pages: usize,
+    include: Vec<UrlPattern>,
+    exclude: Vec<UrlPattern>,
+    delay: Duration,
+    user_agent: String,
+    robots_txt: bool,
+    sitemaps: bool,
+    external_links: bool,
+}
+
+impl From<Url> for Website {
+    fn from(url: Url) -> Self {
+        Self::new(url)
+    }
+}
+
+impl IntoDocuments for Website {
+    type Error = WebsiteCrawlError;
+
+    async fn into_documents(self) -> Result<Vec<Document>, Self::Error> {
+        self.crawl().await
+    }
+}
+
+impl Website {
+    /// Create a new website crawler that starts at the given URL.
+    pub fn new(start: Url) -> Self {
+        Self {
+            start,
+            max_depth: 3,
+            max_pages: 100,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            delay: COOLDOWN,
+            user_agent: default_user_agent().to_string(),
+            robots_txt: true,
+            sitemaps: true,
+            external_links: false,
+        }
+    }
+
+    /// Get the URL the crawler starts at.
+    pub fn url(&self) -> &Url {
+        &self.start
+    }
+
+    /// Set the maximum number of links to follow from the start page. Pages from the sitemap count as one link away from the start page. (default: 3)
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Set the maximum number of pages to read. (default: 100)
+    pub fn with_max_pages(mut self, max_pages: usize) -> Self {
+        self.max_pages = max_pages;
+        self
+    }
+
+    /// Only follow links that match a pattern. `*` in the pattern matches any number of characters. If you add multiple include patterns, links that match any of them are followed.
+    pub fn with_include_pattern(mut self, pattern: impl Into<String>) -> Self {
+        self.include.push(UrlPattern(pattern.into()));
+        self
+    }
+
+    /// Never follow links that match a pattern. `*` in the pattern matches any number of characters. Exclude patterns take priority over include patterns.
+    pub fn with_exclude_pattern(mut self, pattern: impl Into<String>) -> Self {
+        self.exclude.push(UrlPattern(pattern.into()));
+        self
+    }
+
+    /// Set the minimum delay between requests to the same domain. If the robots.txt file sets a longer crawl delay, that delay is used instead. (default: 5 seconds)
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// Set the user agent the crawler sends with each request and uses to find rules in robots.txt files. (default: the name of the current binary)
+    pub fn with_user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = user_agent.into();
+        self
+    }
+
+    /// Set if the crawler should follow the rules in the robots.txt file of each domain. (default: true)
+    pub fn with_robots_txt(mut self, robots_txt: bool) -> Self {
+        self.robots_txt = robots_txt;
+        self
+    }
+
+    /// Set if the crawler should read the sitemap of the website to discover pages that are not linked from the start page. (default: true)
+    pub fn with_sitemaps(mut self, sitemaps: bool) -> Self {
+        self.sitemaps = sitemaps;
+        self
+    }
+
+    /// Set if the crawler should follow links to other domains. (default: false)
+    pub fn with_external_links(mut self, external_links: bool) -> Self {
+        self.external_links = external_links;
+        self
+    }
+
+    /// Check if a link should be added to the crawl queue
+    fn should_follow(&self, url: &Url) -> bool {
+        matches!(url.scheme(), "http" | "https")
+            && (self.external_links || url.origin() == self.start.origin())
+            && (self.include.is_empty() || self.include.iter().any(|p| p.matches(url)))
+            && !self.exclude.iter().any(|p| p.matches(url))
+    }
+
+    /// Crawl the website and extract the main article from each page.
+    ///
+    /// Pages that fail to load are skipped. This only returns an error if the start page can't be read.
+    pub async fn crawl(&self) -> Result<Vec<Document>, WebsiteCrawlError> {
+        let client = reqwest::Client::builder()
+            .user_agent(&self.user_agent)
+            .build()?;
+
+        let mut robots: HashMap<Origin, Option<Robot>> = HashMap::new();
+        let mut next_request: HashMap<Origin, Instant> = HashMap::new();
+        let mut queue = VecDeque::from([(normalize(self.start.clone()), 0)]);
+        let mut queued: HashSet<Url> = queue.iter().map(|(url, _)| url.clone()).collect();
+        let mut documents = Vec::new();
+        let mut read_sitemaps = !self.sitemaps;
+
+        while let Some((url, depth)) = queue.pop_front() {
+            if documents.len() >= self.max_pages {
+                break;
+            }
+            let is_start = depth == 0;
+            let origin = url.origin();
+            let robot = match robots.entry(origin.clone()) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => entry.insert(if self.robots_txt {
+                    try_get_robot(&client, &origin, &self.user_agent).await
+                } else {
+                    None
+                }),
+            };
+            if robot
+                .as_ref()
+                .is_some_and(|robot| !robot.allowed(url.as_str()))
+            {
+                if is_start {
+                    return Err(WebsiteCrawlError::Disallowed(url));
+                }
+                continue;
+            }
+
+            // Wait between requests to the same domain
+            let crawl_delay = robot
+                .as_ref()
+                .and_then(|robot| robot.delay)
+                .map(|delay| Duration::from_secs_f32(delay.max(0.0)))
+                .unwrap_or_default();
+            if let Some(next) = next_request.get(&origin) {
+                tokio::time::sleep_until(*next).await;
+            }
+            next_request.insert(origin.clone(), Instant::now() + self.delay.max(crawl_delay));
+
+            // Pages from the sitemap are queued after the start page
+            if !read_sitemaps {
+                read_sitemaps = true;
+                let sitemaps = match robot {
+                    Some(robot) if !robot.sitemaps.is_empty() => robot
+                        .sitemaps
+                        .iter()
+                        .filter_map(|sitemap| Url::parse(sitemap).ok())
+                        .collect(),
+                    _ => Url::parse(&(origin.ascii_serialization() + "/sitemap.xml"))
+                        .into_iter()
+                        .collect(),
+                };
+                if self.max_depth > 0 {
+                    for page in read_sitemaps_pages(&client, sitemaps).await {
+                        let page = normalize(page);
+                        if self.should_follow(&page) && queued.insert(page.clone()) {
+                            queue.push_back((page, 1));
+                        }
+                    }
+                }
+            }
+
+            let html = match fetch_html(&client, &url).await {
+                Ok(Some(html)) => html,
+                Ok(None) => continue,
+                Err(err) if is_start => return Err(err.into()),
+                Err(err) => {
+                    tracing::warn!("Failed to fetch {url}: {err}");
+                    continue;
+                }
+            };
+
+            if depth < self.max_depth {
+                for link in links(&url, &html) {
+                    if self.should_follow(&link) && queued.insert(link.clone()) {
+                        queue.push_back((link, depth + 1));
+                    }
+                }
+            }
+
+            match readability::extractor::extract(&mut html.as_bytes(), &url) {
+                Ok(article) if !article.text.trim().is_empty() => {
+                    let mut document = Document::from_parts(article.title, article.text);
+                    document.set_source(url.to_string());
+                    documents.push(document);
+                }
+                Ok(_) => {}
+                Err(err) => tracing::warn!("Failed to extract article from {url}: {err}"),
+            }
+        }
+
+        Ok(documents)
+    }
+}
+
+/// Strip the fragment and query from a url to avoid duplicates
+fn normalize(mut url: Url) -> Url {
+    url.set_fragment(None);
+    url.set_query(None);
+    url
+}
+
+/// Fetch the html of a page. Returns `None` if the page is not html
+async fn fetch_html(client: &reqwest::Client, url: &Url) -> Result<Option<String>, reqwest::Error> {
+    let response = client.get(url.clone()).send().await?.error_for_status()?;
+    let is_html = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .is_none_or(|content_type| content_type.contains("html"));
+    if !is_html {
+        return Ok(None);
+    }
+    Ok(Some(response.text().await?))
+}
+
+/// Find all links in a page
+fn links(url: &Url, html: &str) -> Vec<Url> {
+    let html = Html::parse_document(html);
+    let selector = Selector::parse("a").unwrap();
+    html.select(&selector)
+        .filter_map(|element| url.join(element.value().attr("href")?).ok())
+        .map(normalize)
+        .collect()
+}
+
+/// Read the pages listed in a set of sitemaps, following sitemap indexes
+async fn read_sitemaps_pages(client: &reqwest::Client, sitemaps: Vec<Url>) -> Vec<Url> {
+    let mut pages = Vec::new();
+    let mut sitemaps = VecDeque::from(sitemaps);
+    let mut read = 0;
+    while let Some(sitemap) = sitemaps.pop_front() {
+        if read >= MAX_SITEMAPS {
+            break;
+        }
+        read += 1;
+        let xml = match client.get(sitemap.clone()).send().await {
+            Ok(response) if response.status().is_success() => response.text().await,
+            _ => continue,
+        };
+        let Ok(xml) = xml else {
+            continue;
+        };
+        let (is_index, locations) = parse_sitemap(&xml);
+        if is_index {
+            sitemaps.extend(locations);
+        } else {
+            pages.extend(locations);
+        }
+    }
+    pages
+}
+
+/// Parse the urls in a sitemap. Returns true if the sitemap is an index of other sitemaps
+fn parse_sitemap(xml: &str) -> (bool, Vec<Url>) {
+    let is_index = xml.contains("<sitemapindex");
+    let mut locations = Vec::new();
+    let mut remaining = xml;
+    while let Some(start) = remaining.find("<loc>") {
+        remaining = &remaining[start + "<loc>".len()..];
+        let Some(end) = remaining.find("</loc>") else {
+            break;
+        };
+        let location = remaining[..end].trim();
+        let location = location
+            .strip_prefix("<![CDATA[")
+            .and_then(|location| location.strip_suffix("]]>"))
+            .unwrap_or(location)
+            .replace("&amp;", "&")
+            .replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&quot;", "\"")
+            .replace("&apos;", "'");
+        if let Ok(url) = Url::parse(location.trim()) {
+            locations.push(url);
+        }
+        remaining = &remaining[end..];
+    }
+    (is_index, locations)
+}
+
+#[test]
+fn test_website_filters() {
+    let pattern = |pattern: &str| UrlPattern(pattern.to_string());
+    let url = Url::parse("https://docs.rs/kalosm/latest/kalosm/index.html").unwrap();
+    assert!(pattern("https://docs.rs/kalosm/*").matches(&url));
+    assert!(pattern("*/latest/*.html").matches(&url));
+    assert!(pattern("https://docs.rs/kalosm/latest/kalosm/index.html").matches(&url));
+    assert!(!pattern("https://docs.rs/kalosm/").matches(&url));
+    assert!(!pattern("*/src/*").matches(&url));
+
+    let website = Website::new(Url::parse("https://docs.rs/kalosm/latest/kalosm/").unwrap())
+        .with_include_pattern("https://docs.rs/kalosm/*")
+        .with_exclude_pattern("*/src/*");
+    assert!(website.should_follow(&url));
+    assert!(!website
+        .should_follow(&Url::parse("https://docs.rs/kalosm/latest/src/lib.rs.html").unwrap()));
+    assert!(!website.should_follow(&Url::parse("https://docs.rs/tokio/latest/tokio/").unwrap()));
+    assert!(!website.should_follow(&Url::parse("https://example.com/kalosm/").unwrap()));
+
+    let (is_index, locations) = parse_sitemap(
+        "<?xml version=\"1.0\"?><urlset><url><loc> https://docs.rs/a?x=1&amp;y=2 </loc></url><url><loc><![CDATA[https://docs.rs/b]]></loc></url></urlset>",
+    );
+    assert!(!is_index);
+    assert_eq!(
+        locations,
+        [
+            Url::parse("https://docs.rs/a?x=1&y=2").unwrap(),
+            Url::parse("https://docs.rs/b").unwrap()
+        ]
+    );
+    assert!(parse_sitemap("<sitemapindex><sitemap><loc>https://docs.rs/sitemap.xml</loc></sitemap></sitemapindex>").0);
+}
@@ -1,3 +1,7 @@
+use std::collections::HashSet;
+use std::time::Duration;
+
+use futures_util::{Stream, StreamExt};
 use rss::Channel;
 use url::Url;
 
@@ -14,7 +18,7 @@ pub enum RssFeedError {
     ParseFeed(#[from] rss::Error),
 }
 
-/// A RSS feed that can be used to add documents to a search index.
+/// A RSS or Atom feed that can be used to add documents to a search index.
 ///
 /// # Example
 /// ```rust, no_run
@@ -46,6 +50,70 @@ impl IntoDocuments for RssFeed {
     }
 }
 
+/// An item in a RSS or Atom feed
+#[derive(Debug, Clone, PartialEq)]
+struct FeedItem {
+    /// A unique id for the item. This is the guid of RSS items or the id of Atom entries, or the link if the feed doesn't include an id
+    guid: String,
+    title: Option<String>,
+    link: Option<String>,
+    content: Option<String>,
+}
+
+/// Parse the items in a RSS or Atom feed
+fn parse_feed(xml: &str) -> Result<Vec<FeedItem>, RssFeedError> {
+    let channel = match Channel::read_from(xml.as_bytes()) {
+        Ok(channel) => channel,
+        Err(err) => {
+            // Fall back to reading the feed as an Atom feed
+            let Ok(feed) = xml.parse::<atom_syndication::Feed>() else {
+                return Err(err.into());
+            };
+            return Ok(feed
+                .entries()
+                .iter()
+                .map(|entry| {
+                    let link = entry
+                        .links()
+                        .iter()
+                        .find(|link| link.rel() == "alternate")
+                        .or_else(|| entry.links().first())
+                        .map(|link| link.href().to_string());
+                    FeedItem {
+                        guid: entry.id().to_string(),
+                        title: Some(entry.title().value.clone()),
+                        link,
+                        content: entry
+                            .content()
+                            .and_then(|content| content.value())
+                            .map(ToString::to_string),
+                    }
+                })
+                .collect());
+        }
+    };
+
+    Ok(channel
+        .items()
+        .iter()
+        .map(|item| {
+            let link = item.link().map(ToString::to_string);
+            let guid = item
+                .guid()
+                .map(|guid| guid.value().to_string())
+                .or_else(|| link.clone())
+                .or_else(|| item.title().map(ToString::to_string))
+                .unwrap_or_default();
+            FeedItem {
+                guid,
+                title: item.title().map(ToString::to_string),
+                link,
+                content: item.content().map(ToString::to_string),
+            }
+        })
+        .collect())
+}
+
 impl RssFeed {
     /// Create a new RSS feed from the given URL.
     pub fn new(url: Url) -> Self {
@@ -57,38 +125,209 @@ impl RssFeed {
         &self.0
     }
 
+    /// Fetch the items currently in the feed
+    async fn items(&self) -> Result<Vec<FeedItem>, RssFeedError> {
+        let xml = reqwest::get(self.0.clone()).await?.text().await?;
+        parse_feed(&xml)
+    }
+
+    /// Fetch the article for an item and extract the main text
+    async fn read_item(&self, item: &FeedItem) -> Result<Option<Document>, RssFeedError> {
+        let (source_url, content) = if let Some(content) = &item.content {
+            (None, content.clone())
+        } else if let Some(source_url) = &item.link {
+            (
+                Some(source_url),
+                reqwest::get(source_url).await?.text().await?,
+            )
+        } else {
+            (None, String::new())
+        };
+
+        let url = source_url
+            .and_then(|url| Url::parse(url).ok())
+            .unwrap_or_else(|| self.0.clone());
+
+        Ok(
+            readability::extractor::extract(&mut std::io::Cursor::new(&content), &url)
+                .ok()
+                .map(|article| {
+                    let title = match (article.title.is_empty(), &item.title) {
+                        (true, Some(title)) => title.clone(),
+                        _ => article.title,
+                    };
+                    let mut document = Document::from_parts(title, article.text);
+                    document.set_source(item.link.clone().unwrap_or_else(|| url.to_string()));
+                    document
+                }),
+        )
+    }
+
     /// Read the top N documents from the RSS feed.
     pub async fn read_top_n(&self, top_n: usize) -> Result<Vec<Document>, RssFeedError> {
-        let xml = reqwest::get(self.0.clone()).await?.text().await?;
-        let channel = Channel::read_from(xml.as_bytes())?;
         let mut documents = Vec::new();
-        for item in channel.items().iter().take(top_n) {
-            let mut message = String::new();
-            if let Some(title) = item.title() {
-                message.push_str(&format!("### {}\n", title));
+        for item in self.items().await?.iter().take(top_n) {
+            if let Some(document) = self.read_item(item).await? {
+                documents.push(document);
             }
-            let (source_url, content) = if let Some(content) = item.content() {
-                (None, content.to_string())
-            } else if let Some(source_url) = item.link() {
-                (
-                    Some(source_url),
-                    reqwest::get(source_url).await?.text().await?,
-                )
-            } else {
-                (None, String::new())
-            };
+        }
+        Ok(documents)
+    }
+}
 
-            let url = match source_url {
-                Some(url) => Url::parse(url).unwrap(),
-                None => self.0.clone(),
-            };
+/// A document from a new item in a feed that is watched by a [`FeedWatcher`].
+#[derive(Debug, Clone)]
+pub struct FeedDocument {
+    /// The URL of the feed the item is from
+    pub feed: Url,
+    /// The unique id of the item in the feed
+    pub guid: String,
+    /// The article the item links to with the boilerplate removed
+    pub document: Document,
+}
+
+impl AsRef<Document> for FeedDocument {
+    fn as_ref(&self) -> &Document {
+        &self.document
+    }
+}
+
+impl From<FeedDocument> for Document {
+    fn from(value: FeedDocument) -> Self {
+        value.document
+    }
+}
 
-            if let Ok(article) =
-                readability::extractor::extract(&mut std::io::Cursor::new(&content), &url)
-            {
-                documents.push(Document::from_parts(article.title, article.text));
+/// Watches a set of RSS or Atom feeds and streams documents for new items as they are published.
+///
+/// Items are deduplicated by their guid, so each item is only read once even if it stays in the feed for multiple polls. Errors while fetching a feed or an article are logged and the feed is retried on the next poll.
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm_language::prelude::*;
+/// use std::time::Duration;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let watcher = FeedWatcher::new([RssFeed::new(
+///         Url::parse("https://www.nytimes.com/services/xml/rss/nyt/HomePage.xml").unwrap(),
+///     )])
+///     .with_interval(Duration::from_secs(60 * 30));
+///     let mut documents = std::pin::pin!(watcher.into_stream());
+///     while let Some(document) = documents.next().await {
+///         println!("New article: {}", document.document.title());
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct FeedWatcher {
+    feeds: Vec<RssFeed>,
+    interval: Duration,
+    seen: HashSet<String>,
+}
+
+impl FeedWatcher {
+    /// Create a new watcher for a set of feeds.
+    pub fn new(feeds: impl IntoIterator<Item = RssFeed>) -> Self {
+        Self {
+            feeds: feeds.into_iter().collect(),
+            interval: Duration::from_secs(15 * 60),
+            seen: HashSet::new(),
+        }
+    }
+
+    /// Set the time to wait between polls of the feeds. (default: 15 minutes)
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Mark items as already read so they are skipped. You can use this with [`FeedWatcher::seen`] to avoid reading the same items again after restarting.
+    pub fn with_seen(mut self, guids: impl IntoIterator<Item = String>) -> Self {
+        self.seen.extend(guids);
+        self
+    }
+
+    /// Get the guids of every item that has been read.
+    pub fn seen(&self) -> impl Iterator<Item = &str> {
+        self.seen.iter().map(String::as_str)
+    }
+
+    /// Poll every feed once and read any items that have not been seen before.
+    pub async fn poll(&mut self) -> Vec<FeedDocument> {
+        let mut documents = Vec::new();
+        for feed in &self.feeds {
+            let items = match feed.items().await {
+                Ok(items) => items,
+                Err(err) => {
+                    tracing::warn!("Failed to poll feed {}: {err}", feed.url());
+                    continue;
+                }
+            };
+            for item in items {
+                if self.seen.contains(&item.guid) {
+                    continue;
+                }
+                match feed.read_item(&item).await {
+                    Ok(document) => {
+                        if let Some(document) = document {
+                            documents.push(FeedDocument {
+                                feed: feed.url().clone(),
+                                guid: item.guid.clone(),
+                                document,
+                            });
+                        }
+                        self.seen.insert(item.guid);
+                    }
+                    // Leave the item unseen so it is retried on the next poll
+                    Err(err) => tracing::warn!("Failed to read feed item {}: {err}", item.guid),
+                }
             }
         }
-        Ok(documents)
+        documents
+    }
+
+    /// Poll the feeds forever and stream a document for each new item. The first poll happens immediately.
+    pub fn into_stream(self) -> impl Stream<Item = FeedDocument> + Send {
+        futures_util::stream::unfold((self, true), |(mut watcher, first)| async move {
+            if !first {
+                tokio::time::sleep(watcher.interval).await;
+            }
+            let documents = watcher.poll().await;
+            Some((futures_util::stream::iter(documents), (watcher, false)))
+        })
+        .flatten()
     }
 }
+
+#[test]
+fn test_parse_feeds() {
+    let rss = r#"<?xml version="1.0"?>
+<rss version="2.0"><channel><title>News</title><link>https://example.com</link><description>News</description>
+<item><title>First</title><link>https://example.com/first</link><guid>first-id</guid></item>
+<item><title>Second</title><link>https://example.com/second</link></item>
+</channel></rss>"#;
+    let items = parse_feed(rss).unwrap();
+    assert_eq!(
+        items
+            .iter()
+            .map(|item| item.guid.as_str())
+            .collect::<Vec<_>>(),
+        ["first-id", "https://example.com/second"]
+    );
+
+    let atom = r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom"><title>Blog</title><id>urn:blog</id><updated>2024-01-01T00:00:00Z</updated>
+<entry><title>Post</title><id>urn:post:1</id><updated>2024-01-01T00:00:00Z</updated><link rel="alternate" href="https://example.com/post"/></entry>
+</feed>"#;
+    let items = parse_feed(atom).unwrap();
+    assert_eq!(
+        items,
+        [FeedItem {
+            guid: "urn:post:1".to_string(),
+            title: Some("Post".to_string()),
+            link: Some("https://example.com/post".to_string()),
+            content: None,
+        }]
+    );
+}
@@ -3,6 +3,8 @@
 #![doc = include_str!("../README.md")]
 
 pub mod context;
+pub mod memory;
+pub mod redact;
 pub mod search;
 pub mod vector_db;
 
@@ -16,6 +18,8 @@ pub use rbert;
 /// A prelude of commonly used items in kalosm-language
 pub mod prelude {
     pub use crate::context::*;
+    pub use crate::memory::*;
+    pub use crate::redact::*;
     pub use crate::search::*;
     pub use crate::vector_db::*;
     pub use futures_util::StreamExt as _;
@@ -25,6 +29,10 @@ pub mod prelude {
     pub use kalosm_sample::*;
     pub use kalosm_streams::text_stream::*;
     #[cfg(feature = "bert")]
-    pub use rbert::{Bert, BertBuilder, BertSource};
+    pub use rbert::{
+        Bert, BertBuilder, BertSource, Entity, EntityRecognizer, EntityRecognizerBuilder,
+        EntityRecognizerSource, ZeroShotClassification, ZeroShotClassifier,
+        ZeroShotClassifierBuilder, ZeroShotClassifierSource,
+    };
     pub use scraper::Html;
 }
@@ -0,0 +1,431 @@
+//! Long-term memory for chat sessions.
+//!
+//! A [`ChatMemory`] stores past exchanges and facts about the user as embeddings. Before each turn, the memories that are most relevant to the new message are recalled and added to the prompt, so an assistant can remember the user across sessions.
+
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+use chrono::{DateTime, Utc};
+use kalosm_language_model::{
+    Chat, ChatModel, Embedder, EmbedderExt, GenerationParameters, PromptCompressor,
+};
+use serde::{Deserialize, Serialize};
+use tokenizers::Tokenizer;
+
+use crate::vector_db::{EmbeddingId, VectorStore};
+
+/// The kind of a [`Memory`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MemoryKind {
+    /// A message from the user and the response from the assistant.
+    Exchange,
+    /// A fact about the user or the conversation that is worth remembering.
+    Fact,
+}
+
+/// A memory stored in a [`ChatMemory`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Memory {
+    /// The id of the embedding of the memory in the vector store.
+    pub id: EmbeddingId,
+    /// The kind of the memory.
+    pub kind: MemoryKind,
+    /// The text of the memory.
+    pub text: String,
+    /// The time the memory was stored.
+    pub created_at: DateTime<Utc>,
+}
+
+/// A memory recalled by [`ChatMemory::recall`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecalledMemory {
+    /// The memory.
+    pub memory: Memory,
+    /// The distance from the message to the memory.
+    pub distance: f32,
+}
+
+/// An error that can occur while storing or recalling memories in a [`ChatMemory`].
+#[derive(Debug, thiserror::Error)]
+pub enum ChatMemoryError<E, S, M = Infallible> {
+    /// An error occurred while embedding a memory or a message.
+    #[error("Failed to embed text: {0}")]
+    Embed(E),
+    /// An error occurred in the vector store.
+    #[error("Vector store error: {0}")]
+    Store(S),
+    /// An error occurred while generating facts with the model.
+    #[error("Failed to generate facts: {0}")]
+    Generate(M),
+    /// An error occurred while counting the tokens in a memory.
+    #[error("Failed to tokenize memory: {0}")]
+    Tokenize(tokenizers::Error),
+    /// An error occurred while compressing the recalled memories.
+    #[error("Failed to compress memories: {0}")]
+    Compress(Box<dyn std::error::Error + Send + Sync>),
+    /// An error occurred while reading or writing the memory file.
+    #[error("Failed to read or write memories: {0}")]
+    Io(#[from] std::io::Error),
+    /// The memory file could not be parsed.
+    #[error("Failed to parse memories: {0}")]
+    Parse(#[from] serde_json::Error),
+}
+
+/// Long-term memory for a [`Chat`] session.
+///
+/// Past exchanges and facts about the user are embedded and stored in a [`VectorStore`]. [`ChatMemory::prompt`] recalls the memories that are most relevant to a new message and adds them to the message, so the model can use them in the response. The text of each memory is saved in a json file, so memories persist across sessions when the store is persisted too.
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm::language::*;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let model = Llama::new_chat().await.unwrap();
+///     let mut chat = model.chat();
+///     let store = VectorDB::new_at("./memory/embeddings").unwrap();
+///     let memory = ChatMemory::open(Bert::new().await.unwrap(), store, "./memory/memories.json")
+///         .await
+///         .unwrap()
+///         .with_token_budget(model.tokenizer().clone(), 512);
+///
+///     loop {
+///         let message = prompt_input("\n> ").unwrap();
+///         let response = chat(&memory.prompt(&message).await.unwrap()).await.unwrap();
+///         println!("{response}");
+///         memory.remember_exchange(&message, &response).await.unwrap();
+///         memory
+///             .remember_facts_from(&model, &message, &response)
+///             .await
+///             .unwrap();
+///     }
+/// }
+/// ```
+pub struct ChatMemory<E, S> {
+    embedder: E,
+    store: S,
+    memories: RwLock<BTreeMap<EmbeddingId, Memory>>,
+    path: Option<PathBuf>,
+    results: usize,
+    budget: Option<(Arc<Tokenizer>, usize)>,
+    compressor: Option<PromptCompressor>,
+}
+
+impl<E: Embedder, S: VectorStore> ChatMemory<E, S> {
+    /// Create a new memory that stores embeddings in the store. The text of each memory is only kept in memory, so memories are lost when the memory is dropped. Use [`ChatMemory::open`] to keep memories across sessions.
+    pub fn new(embedder: E, store: S) -> Self {
+        Self {
+            embedder,
+            store,
+            memories: RwLock::new(BTreeMap::new()),
+            path: None,
+            results: 5,
+            budget: None,
+            compressor: None,
+        }
+    }
+
+    /// Open a memory that stores embeddings in the store and saves the text of each memory to a json file at the path. If the file already exists, the memories in it are loaded.
+    ///
+    /// The store should persist embeddings at least as long as the file, like a [`VectorDB`](crate::vector_db::VectorDB) created with [`VectorDB::new_at`](crate::vector_db::VectorDB::new_at).
+    pub async fn open(
+        embedder: E,
+        store: S,
+        path: impl AsRef<Path>,
+    ) -> Result<Self, ChatMemoryError<E::Error, S::Error>> {
+        let path = path.as_ref().to_path_buf();
+        let memories: Vec<Memory> = match tokio::fs::read(&path).await {
+            Ok(bytes) => serde_json::from_slice(&bytes)?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(err.into()),
+        };
+        let mut memory = Self::new(embedder, store);
+        memory.memories = RwLock::new(
+            memories
+                .into_iter()
+                .map(|memory| (memory.id, memory))
+                .collect(),
+        );
+        memory.path = Some(path);
+        Ok(memory)
+    }
+
+    /// Set the maximum number of memories to recall for each message. (default: 5)
+    pub fn with_results(mut self, results: usize) -> Self {
+        self.results = results;
+        self
+    }
+
+    /// Only recall memories that fit in `budget` tokens of the tokenizer. The most relevant memories are kept first.
+    pub fn with_token_budget(
+        mut self,
+        tokenizer: impl Into<Arc<Tokenizer>>,
+        budget: usize,
+    ) -> Self {
+        self.budget = Some((tokenizer.into(), budget));
+        self
+    }
+
+    /// Compress the recalled memories with the compressor before they are added to the prompt in [`ChatMemory::prompt`]. The message itself is not compressed.
+    pub fn with_compressor(mut self, compressor: PromptCompressor) -> Self {
+        self.compressor = Some(compressor);
+        self
+    }
+
+    /// Get every stored memory in the order they were added.
+    pub fn memories(&self) -> Vec<Memory> {
+        let mut memories: Vec<_> = self.memories.read().unwrap().values().cloned().collect();
+        memories.sort_by_key(|memory| memory.created_at);
+        memories
+    }
+
+    /// Store a memory.
+    pub async fn remember(
+        &self,
+        kind: MemoryKind,
+        text: impl ToString,
+    ) -> Result<Memory, ChatMemoryError<E::Error, S::Error>> {
+        let text = text.to_string();
+        let embedding = self
+            .embedder
+            .embed(&text)
+            .await
+            .map_err(ChatMemoryError::Embed)?;
+        let ids = self
+            .store
+            .add_embeddings(vec![embedding])
+            .await
+            .map_err(ChatMemoryError::Store)?;
+        let memory = Memory {
+            id: ids[0],
+            kind,
+            text,
+            created_at: Utc::now(),
+        };
+        self.memories
+            .write()
+            .unwrap()
+            .insert(memory.id, memory.clone());
+        self.save().await?;
+        Ok(memory)
+    }
+
+    /// Store a message from the user and the response from the assistant.
+    pub async fn remember_exchange(
+        &self,
+        user: &str,
+        assistant: &str,
+    ) -> Result<Memory, ChatMemoryError<E::Error, S::Error>> {
+        self.remember(
+            MemoryKind::Exchange,
+            format!("User: {user}\nAssistant: {assistant}"),
+        )
+        .await
+    }
+
+    /// Store a fact about the user or the conversation.
+    pub async fn remember_fact(
+        &self,
+        fact: &str,
+    ) -> Result<Memory, ChatMemoryError<E::Error, S::Error>> {
+        self.remember(MemoryKind::Fact, fact).await
+    }
+
+    /// Ask the model to distill facts worth remembering from an exchange and store each fact. Facts are short and self-contained, so they are often recalled more reliably than the full exchange.
+    pub async fn remember_facts_from<M>(
+        &self,
+        model: &M,
+        user: &str,
+        assistant: &str,
+    ) -> Result<Vec<Memory>, ChatMemoryError<E::Error, S::Error, M::Error>>
+    where
+        M: ChatModel + Send + Sync + Unpin + Clone + 'static,
+        M::ChatSession: Clone + Send + Sync + Unpin + 'static,
+    {
+        let mut chat = Chat::new(model.clone()).with_system_prompt(
+            "You extract facts about the user from a conversation that are worth remembering in future conversations, like their name, preferences, and plans. Respond with one short, self-contained fact on each line. Respond with nothing if there are no facts worth remembering.",
+        );
+        let facts = chat
+            .add_message(format!("User: {user}\nAssistant: {assistant}"))
+            .with_sampler(GenerationParameters::default().with_max_length(256))
+            .await
+            .map_err(ChatMemoryError::Generate)?;
+
+        let mut memories = Vec::new();
+        for fact in facts.lines() {
+            let fact = fact.trim().trim_start_matches(['-', '*']).trim();
+            if fact.is_empty() {
+                continue;
+            }
+            let memory = self
+                .remember_fact(fact)
+                .await
+                .map_err(ChatMemoryError::without_generate)?;
+            memories.push(memory);
+        }
+        Ok(memories)
+    }
+
+    /// Remove a memory.
+    pub async fn forget(&self, id: EmbeddingId) -> Result<(), ChatMemoryError<E::Error, S::Error>> {
+        self.store
+            .remove_embeddings(vec![id])
+            .await
+            .map_err(ChatMemoryError::Store)?;
+        self.memories.write().unwrap().remove(&id);
+        self.save().await
+    }
+
+    /// Remove every memory.
+    pub async fn clear(&self) -> Result<(), ChatMemoryError<E::Error, S::Error>> {
+        self.store.clear().await.map_err(ChatMemoryError::Store)?;
+        self.memories.write().unwrap().clear();
+        self.save().await
+    }
+
+    /// Recall the memories that are most relevant to the message, from the most to the least relevant. If a token budget is set, only the memories that fit in the budget are returned.
+    pub async fn recall(
+        &self,
+        message: &str,
+    ) -> Result<Vec<RecalledMemory>, ChatMemoryError<E::Error, S::Error>> {
+        let empty = self.memories.read().unwrap().is_empty();
+        if empty {
+            return Ok(Vec::new());
+        }
+        let embedding = self
+            .embedder
+            .embed_query(message)
+            .await
+            .map_err(ChatMemoryError::Embed)?;
+        let results = self
+            .store
+            .search_embeddings(&embedding, self.results, None)
+            .await
+            .map_err(ChatMemoryError::Store)?;
+
+        let recalled = {
+            let memories = self.memories.read().unwrap();
+            results
+                .into_iter()
+                .filter_map(|result| {
+                    Some(RecalledMemory {
+                        memory: memories.get(&result.value)?.clone(),
+                        distance: result.distance,
+                    })
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let Some((tokenizer, budget)) = &self.budget else {
+            return Ok(recalled);
+        };
+        let mut remaining = *budget;
+        let mut packed = Vec::new();
+        for memory in recalled {
+            let tokens = tokenizer
+                .encode(format_memory(&memory.memory), false)
+                .map_err(ChatMemoryError::Tokenize)?
+                .len();
+            if tokens <= remaining {
+                remaining -= tokens;
+                packed.push(memory);
+            }
+        }
+        Ok(packed)
+    }
+
+    /// Recall the memories that are most relevant to the message and add them before the message. If there are no relevant memories, the message is returned unchanged.
+    pub async fn prompt(
+        &self,
+        message: &str,
+    ) -> Result<String, ChatMemoryError<E::Error, S::Error>> {
+        let recalled = self.recall(message).await?;
+        let memories = format_memories(&recalled);
+        let memories = match &self.compressor {
+            Some(compressor) if !memories.is_empty() => compressor
+                .compress(&memories)
+                .await
+                .map_err(ChatMemoryError::Compress)?,
+            _ => memories,
+        };
+        Ok(memory_prompt(&memories, message))
+    }
+
+    /// Save the text of every memory to the memory file, if there is one.
+    async fn save(&self) -> Result<(), ChatMemoryError<E::Error, S::Error>> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let json = serde_json::to_vec(&self.memories())?;
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::write(path, json).await?;
+        Ok(())
+    }
+}
+
+impl<E, S> ChatMemoryError<E, S> {
+    fn without_generate<M>(self) -> ChatMemoryError<E, S, M> {
+        match self {
+            Self::Embed(err) => ChatMemoryError::Embed(err),
+            Self::Store(err) => ChatMemoryError::Store(err),
+            Self::Generate(never) => match never {},
+            Self::Tokenize(err) => ChatMemoryError::Tokenize(err),
+            Self::Compress(err) => ChatMemoryError::Compress(err),
+            Self::Io(err) => ChatMemoryError::Io(err),
+            Self::Parse(err) => ChatMemoryError::Parse(err),
+        }
+    }
+}
+
+/// Format a memory as a line in the prompt.
+fn format_memory(memory: &Memory) -> String {
+    format!(
+        "- ({}) {}\n",
+        memory.created_at.format("%Y-%m-%d"),
+        memory.text.replace('\n', " ")
+    )
+}
+
+/// Format the recalled memories as lines in the prompt.
+fn format_memories(recalled: &[RecalledMemory]) -> String {
+    recalled
+        .iter()
+        .map(|recalled| format_memory(&recalled.memory))
+        .collect()
+}
+
+/// Add the formatted memories before the message.
+fn memory_prompt(memories: &str, message: &str) -> String {
+    if memories.is_empty() {
+        return message.to_string();
+    }
+    format!("Things you remember from earlier conversations with the user:\n{memories}\n{message}")
+}
+
+#[test]
+fn test_memory_prompt() {
+    let memory = |text: &str| RecalledMemory {
+        memory: Memory {
+            id: EmbeddingId(0),
+            kind: MemoryKind::Fact,
+            text: text.to_string(),
+            created_at: "2024-06-01T12:00:00Z".parse().unwrap(),
+        },
+        distance: 0.0,
+    };
+    assert_eq!(memory_prompt(&format_memories(&[]), "Hello!"), "Hello!");
+    assert_eq!(
+        memory_prompt(
+            &format_memories(&[
+                memory("The user's name is Ferris."),
+                memory("User: Hi\nAssistant: Hello")
+            ]),
+            "What is my name?"
+        ),
+        "Things you remember from earlier conversations with the user:\n- (2024-06-01) The user's name is Ferris.\n- (2024-06-01) User: Hi Assistant: Hello\n\nWhat is my name?"
+    );
+}
@@ -0,0 +1,421 @@
+//! Redaction of personally identifiable information (PII) in text.
+//!
+//! A [`PiiRedactor`] finds emails, phone numbers and credit card numbers with regular expressions, and names with an optional [`NameRecognizer`] like a small named entity recognition model. [`RedactPiiExt::redact_pii`] applies a redactor to any stream of text, like the token stream of a model, so PII is masked before it reaches the consumer.
+
+use std::fmt::Display;
+use std::future::Future;
+use std::ops::Range;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use futures_util::{FutureExt, Stream};
+use pin_project_lite::pin_project;
+use regex::Regex;
+
+type BoxedFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// The text a stream emits in place of a segment that failed to redact.
+const REDACTION_FAILED: &str = "[REDACTED]";
+
+/// The number of bytes a [`RedactedStream`] buffers before it redacts text that doesn't end a sentence or line.
+const MAX_BUFFERED: usize = 1024;
+
+/// The longest pattern match that can contain whitespace: a credit card number with a separator between each of its 19 digits. A [`RedactedStream`] never cuts the buffer at whitespace this close to the end, because more tokens could still complete a match there.
+const MAX_MATCH_WITH_WHITESPACE: usize = 37;
+
+/// A kind of personally identifiable information found by a [`PiiRedactor`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PiiKind {
+    /// An email address.
+    Email,
+    /// A phone number.
+    PhoneNumber,
+    /// A credit card number that passes the Luhn checksum.
+    CreditCard,
+    /// The name of a person.
+    Name,
+}
+
+impl Display for PiiKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Email => write!(f, "EMAIL"),
+            Self::PhoneNumber => write!(f, "PHONE"),
+            Self::CreditCard => write!(f, "CREDIT_CARD"),
+            Self::Name => write!(f, "NAME"),
+        }
+    }
+}
+
+/// Personally identifiable information found in a text by a [`PiiRedactor`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PiiMatch {
+    /// The kind of information.
+    pub kind: PiiKind,
+    /// The byte range of the information in the text.
+    pub byte_range: Range<usize>,
+}
+
+/// A model that can find the names of people in a text.
+pub trait NameRecognizer: Send + Sync + 'static {
+    /// The error type returned when finding names fails.
+    type Error: std::error::Error + Send + Sync + 'static;
+
+    /// Find the byte ranges of the names in the text.
+    fn find_names(
+        &self,
+        text: &str,
+    ) -> impl Future<Output = Result<Vec<Range<usize>>, Self::Error>> + Send;
+}
+
+#[cfg(feature = "bert")]
+impl NameRecognizer for rbert::EntityRecognizer {
+    type Error = rbert::BertError;
+
+    async fn find_names(&self, text: &str) -> Result<Vec<Range<usize>>, Self::Error> {
+        Ok(self
+            .recognize(text)
+            .await?
+            .into_iter()
+            .filter(|entity| entity.label == "PER")
+            .map(|entity| entity.byte_range)
+            .collect())
+    }
+}
+
+trait BoxedNameRecognizer: Send + Sync {
+    fn find_names_boxed<'a>(
+        &'a self,
+        text: &'a str,
+    ) -> BoxedFuture<'a, Result<Vec<Range<usize>>, Box<dyn std::error::Error + Send + Sync>>>;
+}
+
+struct AnyNameRecognizer<R>(R);
+
+impl<R: NameRecognizer> BoxedNameRecognizer for AnyNameRecognizer<R> {
+    fn find_names_boxed<'a>(
+        &'a self,
+        text: &'a str,
+    ) -> BoxedFuture<'a, Result<Vec<Range<usize>>, Box<dyn std::error::Error + Send + Sync>>> {
+        let future = self.0.find_names(text);
+        Box::pin(async move { future.await.map_err(|e| e.into()) })
+    }
+}
+
+/// A redactor that masks personally identifiable information (PII) in text.
+///
+/// By default, the redactor finds emails, phone numbers and credit card numbers with regular expressions. Names are only found if a [`NameRecognizer`] is added with [`PiiRedactor::with_name_recognizer`]. Each match is replaced with a mask like `[EMAIL]`.
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm::language::*;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let model = Llama::new_chat().await.unwrap();
+///     let redactor = PiiRedactor::new().with_name_recognizer(EntityRecognizer::new().await.unwrap());
+///     let mut chat = model.chat();
+///     let mut stream = chat("Write a short email from Jane Doe with her phone number and email address")
+///         .redact_pii(redactor);
+///     stream.to_std_out().await.unwrap();
+/// }
+/// ```
+#[derive(Clone)]
+pub struct PiiRedactor {
+    patterns: Vec<(PiiKind, Regex)>,
+    names: Option<Arc<dyn BoxedNameRecognizer>>,
+    mask: Arc<dyn Fn(PiiKind) -> String + Send + Sync>,
+}
+
+impl Default for PiiRedactor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PiiRedactor {
+    /// Create a new redactor that finds emails, phone numbers and credit card numbers.
+    pub fn new() -> Self {
+        let pattern = |pattern: &str| Regex::new(pattern).expect("the pattern is valid");
+        Self {
+            // Credit cards come before phone numbers so the longer match wins when they overlap
+            patterns: vec![
+                (PiiKind::CreditCard, pattern(r"\b\d(?:[ -]?\d){12,18}\b")),
+                (
+                    PiiKind::Email,
+                    pattern(r"\b[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}\b"),
+                ),
+                (
+                    PiiKind::PhoneNumber,
+                    pattern(
+                        r"(?:\+\d{1,3}[\s.-]?)?(?:\(\d{3}\)|\b\d{3})[\s.-]?\d{3}[\s.-]?\d{4}\b",
+                    ),
+                ),
+            ],
+            names: None,
+            mask: Arc::new(|kind| format!("[{kind}]")),
+        }
+    }
+
+    /// Find names with the recognizer, like the `EntityRecognizer` from rbert.
+    pub fn with_name_recognizer(mut self, recognizer: impl NameRecognizer) -> Self {
+        self.names = Some(Arc::new(AnyNameRecognizer(recognizer)));
+        self
+    }
+
+    /// Set the text each match is replaced with. (default: the kind in brackets, like `[EMAIL]`)
+    pub fn with_mask(mut self, mask: impl Fn(PiiKind) -> String + Send + Sync + 'static) -> Self {
+        self.mask = Arc::new(mask);
+        self
+    }
+
+    /// Find the personally identifiable information in the text. The matches are sorted by their position in the text and never overlap.
+    pub async fn find(
+        &self,
+        text: &str,
+    ) -> Result<Vec<PiiMatch>, Box<dyn std::error::Error + Send + Sync>> {
+        let mut matches = self.find_patterns(text);
+        if let Some(names) = &self.names {
+            matches.extend(
+                names
+                    .find_names_boxed(text)
+                    .await?
+                    .into_iter()
+                    .map(|byte_range| PiiMatch {
+                        kind: PiiKind::Name,
+                        byte_range,
+                    }),
+            );
+        }
+        Ok(without_overlaps(matches))
+    }
+
+    /// Replace the personally identifiable information in the text with masks.
+    pub async fn redact(
+        &self,
+        text: &str,
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        let matches = self.find(text).await?;
+        Ok(self.apply(text, &matches))
+    }
+
+    fn find_patterns(&self, text: &str) -> Vec<PiiMatch> {
+        let mut matches = Vec::new();
+        for (kind, pattern) in &self.patterns {
+            for found in pattern.find_iter(text) {
+                if *kind == PiiKind::CreditCard && !luhn_valid(found.as_str()) {
+                    continue;
+                }
+                matches.push(PiiMatch {
+                    kind: *kind,
+                    byte_range: found.range(),
+                });
+            }
+        }
+        matches
+    }
+
+    /// Find the end of the last complete sentence or line in the text. If the text is longer than [`MAX_BUFFERED`] without a complete sentence, this is the end of the last whitespace that doesn't split a pattern match instead.
+    fn segment_end(&self, text: &str) -> Option<usize> {
+        let mut sentence_end = None;
+        let mut whitespace_ends = Vec::new();
+        let mut previous = None;
+        for (i, c) in text.char_indices() {
+            if c.is_whitespace() {
+                let end = i + c.len_utf8();
+                whitespace_ends.push(end);
+                if c == '\n' || matches!(previous, Some('.' | '!' | '?')) {
+                    sentence_end = Some(end);
+                }
+            }
+            previous = Some(c);
+        }
+        if sentence_end.is_some() || text.len() <= MAX_BUFFERED {
+            return sentence_end;
+        }
+
+        // Don't cut inside a number that is already complete or could still be completed by the next tokens
+        let matches = self.find_patterns(text);
+        let limit = text.len().saturating_sub(MAX_MATCH_WITH_WHITESPACE);
+        whitespace_ends.into_iter().rev().find(|&end| {
+            end <= limit
+                && !matches
+                    .iter()
+                    .any(|found| found.byte_range.start < end && end < found.byte_range.end)
+        })
+    }
+
+    fn apply(&self, text: &str, matches: &[PiiMatch]) -> String {
+        let mut redacted = String::with_capacity(text.len());
+        let mut last = 0;
+        for found in matches {
+            redacted.push_str(&text[last..found.byte_range.start]);
+            redacted.push_str(&(self.mask)(found.kind));
+            last = found.byte_range.end;
+        }
+        redacted.push_str(&text[last..]);
+        redacted
+    }
+}
+
+/// Sort the matches and drop any match that overlaps an earlier or longer match.
+fn without_overlaps(mut matches: Vec<PiiMatch>) -> Vec<PiiMatch> {
+    matches.sort_by_key(|found| {
+        (
+            found.byte_range.start,
+            std::cmp::Reverse(found.byte_range.end),
+        )
+    });
+    let mut end = 0;
+    matches.retain(|found| {
+        let keep = found.byte_range.start >= end && !found.byte_range.is_empty();
+        if keep {
+            end = found.byte_range.end;
+        }
+        keep
+    });
+    matches
+}
+
+/// Check if the digits in the number pass the Luhn checksum used by credit cards.
+fn luhn_valid(number: &str) -> bool {
+    let digits: Vec<u32> = number.chars().filter_map(|c| c.to_digit(10)).collect();
+    if !(13..=19).contains(&digits.len()) {
+        return false;
+    }
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, &digit)| match (i % 2 == 1, digit * 2) {
+            (true, doubled) if doubled > 9 => doubled - 9,
+            (true, doubled) => doubled,
+            (false, _) => digit,
+        })
+        .sum();
+    sum.is_multiple_of(10)
+}
+
+/// An extension trait for streams of text that masks personally identifiable information.
+pub trait RedactPiiExt<I: AsRef<str> = String>: Stream<Item = I> {
+    /// Mask personally identifiable information in the stream with the redactor.
+    ///
+    /// The text is buffered until a sentence or line ends so information that is split across tokens is still found. If a segment fails to redact, `[REDACTED]` is emitted in place of the whole segment.
+    fn redact_pii(self, redactor: PiiRedactor) -> RedactedStream<Self, I>
+    where
+        Self: Sized,
+    {
+        RedactedStream {
+            backing: self,
+            redactor,
+            buffer: String::new(),
+            redacting: None,
+            finished: false,
+        }
+    }
+}
+
+impl<S: Stream<Item = I>, I: AsRef<str>> RedactPiiExt<I> for S {}
+
+pin_project! {
+    /// A stream of text with personally identifiable information masked. Created with [`RedactPiiExt::redact_pii`].
+    pub struct RedactedStream<S: Stream<Item = I>, I: AsRef<str>> {
+        #[pin]
+        backing: S,
+        redactor: PiiRedactor,
+        buffer: String,
+        redacting: Option<BoxedFuture<'static, String>>,
+        finished: bool,
+    }
+}
+
+impl<S: Stream<Item = I>, I: AsRef<str>> Stream for RedactedStream<S, I> {
+    type Item = String;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        loop {
+            if let Some(redacting) = this.redacting {
+                let redacted = match redacting.poll_unpin(cx) {
+                    Poll::Ready(redacted) => redacted,
+                    Poll::Pending => return Poll::Pending,
+                };
+                *this.redacting = None;
+                if !redacted.is_empty() {
+                    return Poll::Ready(Some(redacted));
+                }
+                continue;
+            }
+            if *this.finished {
+                return Poll::Ready(None);
+            }
+
+            let segment = match this.backing.as_mut().poll_next(cx) {
+                Poll::Ready(Some(text)) => {
+                    this.buffer.push_str(text.as_ref());
+                    match this.redactor.segment_end(this.buffer) {
+                        Some(end) => this.buffer.drain(..end).collect::<String>(),
+                        None => continue,
+                    }
+                }
+                Poll::Ready(None) => {
+                    *this.finished = true;
+                    std::mem::take(this.buffer)
+                }
+                Poll::Pending => return Poll::Pending,
+            };
+            let redactor = this.redactor.clone();
+            *this.redacting = Some(Box::pin(async move {
+                match redactor.redact(&segment).await {
+                    Ok(redacted) => redacted,
+                    Err(err) => {
+                        tracing::error!("Failed to redact PII: {err}");
+                        REDACTION_FAILED.to_string()
+                    }
+                }
+            }));
+        }
+    }
+}
+
+#[test]
+fn test_redact_patterns() {
+    let redactor = PiiRedactor::new();
+    let text = "Reach me at jane.doe@example.com or (555) 123-4567. My card is 4111 1111 1111 1111, not 1234 5678 9012 3456.";
+    let matches = without_overlaps(redactor.find_patterns(text));
+    assert_eq!(
+        redactor.apply(text, &matches),
+        "Reach me at [EMAIL] or [PHONE]. My card is [CREDIT_CARD], not 1234 5678 9012 3456."
+    );
+
+    assert_eq!(redactor.segment_end("Hello there"), None);
+    assert_eq!(redactor.segment_end("Hello. There"), Some(7));
+    assert_eq!(redactor.segment_end("Mail a.b@c.com now\nok"), Some(19));
+}
+
+#[tokio::test]
+async fn test_redact_stream_without_sentences() {
+    use futures_util::StreamExt;
+
+    let redactor = PiiRedactor::new();
+    let words = "word ".repeat(MAX_BUFFERED / 5);
+    // The buffer grows past the limit while the card number is only partly streamed
+    let mut tokens: Vec<String> = words.split_inclusive(' ').map(String::from).collect();
+    tokens.extend(
+        [
+            "4111", " 1111", " 1111", " 1111", " and", " call", " (555)", " 123", " 4567", " later",
+        ]
+        .map(String::from),
+    );
+
+    let segments: Vec<String> = futures_util::stream::iter(tokens)
+        .redact_pii(redactor)
+        .collect()
+        .await;
+    assert!(segments.len() > 1);
+    assert_eq!(
+        segments.concat(),
+        format!("{words}[CREDIT_CARD] and call [PHONE] later")
+    );
+}
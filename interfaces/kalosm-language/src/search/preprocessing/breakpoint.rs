@@ -0,0 +1,147 @@
+use std::ops::Range;
+
+use kalosm_language_model::Embedder;
+
+use super::{ChunkStrategy, Chunker};
+use crate::{prelude::Document, search::Chunk};
+
+/// A [`Chunker`] that splits a document where the meaning of the text changes.
+///
+/// It embeds each sentence and measures the distance between the embeddings of adjacent sentences. The document is split between sentences where the distance is larger than the given percentile of all distances in the document. Unlike [`SemanticChunker`](super::SemanticChunker), this only embeds each sentence and each final chunk once, so it is much faster for large documents.
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm::language::*;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let document = Document::from_parts(
+///         "Notes",
+///         "Cats are small mammals. They are often kept as pets. Rust is a programming language. It is memory safe.",
+///     );
+///     let bert = Bert::new().await.unwrap();
+///     let chunks = SemanticBreakpointChunker::new()
+///         .with_percentile(90.0)
+///         .chunk(&document, &bert)
+///         .await
+///         .unwrap();
+///     for chunk in chunks {
+///         println!("{}", &document.body()[chunk.byte_range]);
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SemanticBreakpointChunker {
+    percentile: f32,
+}
+
+impl Default for SemanticBreakpointChunker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SemanticBreakpointChunker {
+    /// Create a new [`SemanticBreakpointChunker`].
+    pub const fn new() -> Self {
+        Self { percentile: 95.0 }
+    }
+
+    /// Set the percentile of distances between sentences the document is split at. A lower percentile creates more, smaller chunks. (default: 95.0)
+    pub fn with_percentile(mut self, percentile: f32) -> Self {
+        self.percentile = percentile;
+        self
+    }
+}
+
+/// Find the indexes of the gaps between sentences where the distance is larger than the percentile of all distances
+fn breakpoints(distances: &[f32], percentile: f32) -> Vec<usize> {
+    if distances.is_empty() {
+        return Vec::new();
+    }
+    let mut sorted = distances.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let index = ((percentile / 100.0).clamp(0.0, 1.0) * (sorted.len() - 1) as f32).round();
+    let threshold = sorted[index as usize];
+
+    distances
+        .iter()
+        .enumerate()
+        .filter(|(_, distance)| **distance > threshold)
+        .map(|(i, _)| i)
+        .collect()
+}
+
+impl Chunker for SemanticBreakpointChunker {
+    type Error<E: Send + Sync + 'static> = E;
+
+    async fn chunk<E: Embedder + Send>(
+        &self,
+        document: &Document,
+        embedder: &E,
+    ) -> Result<Vec<Chunk>, E::Error> {
+        let text = document.body();
+
+        // First chunk by sentences
+        let sentences: Vec<Range<usize>> = ChunkStrategy::Sentence {
+            sentence_count: 1,
+            overlap: 0,
+        }
+        .chunk_str(text)
+        .into_iter()
+        .filter(|range| !text[range.clone()].trim().is_empty())
+        .collect();
+        if sentences.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let embeddings = embedder
+            .embed_vec(
+                sentences
+                    .iter()
+                    .map(|range| text[range.clone()].trim().to_string())
+                    .collect(),
+            )
+            .await?;
+
+        // Split after each sentence that is far away from the next sentence
+        let distances: Vec<f32> = embeddings
+            .windows(2)
+            .map(|pair| 1.0 - pair[0].cosine_similarity(&pair[1]))
+            .collect();
+        let mut ranges = Vec::new();
+        let mut start = 0;
+        for breakpoint in breakpoints(&distances, self.percentile) {
+            ranges.push(sentences[start].start..sentences[breakpoint].end);
+            start = breakpoint + 1;
+        }
+        ranges.push(sentences[start].start..sentences[sentences.len() - 1].end);
+
+        let embeddings = embedder
+            .embed_vec(
+                ranges
+                    .iter()
+                    .map(|range| text[range.clone()].trim().to_string())
+                    .collect(),
+            )
+            .await?;
+
+        Ok(ranges
+            .into_iter()
+            .zip(embeddings)
+            .map(|(byte_range, embedding)| Chunk {
+                byte_range,
+                embeddings: vec![embedding],
+            })
+            .collect())
+    }
+}
+
+#[test]
+fn test_breakpoints() {
+    let distances = [0.1, 0.8, 0.2, 0.15, 0.9, 0.05];
+    assert_eq!(breakpoints(&distances, 50.0), vec![1, 4]);
+    assert_eq!(breakpoints(&distances, 80.0), vec![4]);
+    assert_eq!(breakpoints(&distances, 100.0), Vec::<usize>::new());
+    assert_eq!(breakpoints(&[], 95.0), Vec::<usize>::new());
+}
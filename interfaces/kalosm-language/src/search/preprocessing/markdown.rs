@@ -0,0 +1,224 @@
+use std::ops::Range;
+
+use kalosm_language_model::Embedder;
+use pulldown_cmark::{Event, Parser, Tag};
+
+use super::Chunker;
+use crate::{prelude::Document, search::Chunk};
+
+/// A [`Chunker`] that splits a markdown document into sections under each heading.
+///
+/// The titles of the headings a section is nested under are added to the text that is embedded for the section, so chunks like "## Installation" under "# Kalosm" can be found by searching for either heading. Sections that are longer than the maximum chunk size are split between paragraphs.
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm::language::*;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let document = Document::from_parts(
+///         "README",
+///         "# Kalosm\n\nA simple interface for pretrained AI models.\n\n## Installation\n\nRun `cargo add kalosm`.",
+///     );
+///     let bert = Bert::new().await.unwrap();
+///     let chunks = MarkdownChunker::new()
+///         .with_max_chunk_size(1000)
+///         .chunk(&document, &bert)
+///         .await
+///         .unwrap();
+///     for chunk in chunks {
+///         println!("{}", &document.body()[chunk.byte_range]);
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MarkdownChunker {
+    max_chunk_size: usize,
+}
+
+impl Default for MarkdownChunker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A section of a markdown document
+#[derive(Debug, Clone, PartialEq)]
+struct MarkdownSection {
+    /// The titles of the headings the section is under, including the heading of the section itself
+    headings: Vec<String>,
+    range: Range<usize>,
+}
+
+impl MarkdownChunker {
+    /// Create a new [`MarkdownChunker`].
+    pub const fn new() -> Self {
+        Self {
+            max_chunk_size: 2000,
+        }
+    }
+
+    /// Set the maximum number of bytes in each chunk. Sections that are longer are split between paragraphs. (default: 2000)
+    pub fn with_max_chunk_size(mut self, max_chunk_size: usize) -> Self {
+        self.max_chunk_size = max_chunk_size;
+        self
+    }
+
+    /// Split a markdown string into sections under each heading
+    fn split_sections(&self, markdown: &str) -> Vec<MarkdownSection> {
+        let mut sections = Vec::new();
+        // The level and title of each heading the current position is under
+        let mut heading_stack: Vec<(usize, String)> = Vec::new();
+        let mut section_start = 0;
+        let mut current_heading: Option<(usize, String)> = None;
+
+        let mut push_section = |heading_stack: &[(usize, String)], range: Range<usize>| {
+            if !markdown[range.clone()].trim().is_empty() {
+                sections.push(MarkdownSection {
+                    headings: heading_stack
+                        .iter()
+                        .map(|(_, title)| title.clone())
+                        .collect(),
+                    range,
+                });
+            }
+        };
+
+        for (event, range) in Parser::new(markdown).into_offset_iter() {
+            match event {
+                Event::Start(Tag::Heading(level, _, _)) => {
+                    push_section(&heading_stack, section_start..range.start);
+                    section_start = range.start;
+                    current_heading = Some((level as usize, String::new()));
+                }
+                Event::Text(text) | Event::Code(text) => {
+                    if let Some((_, title)) = &mut current_heading {
+                        title.push_str(&text);
+                    }
+                }
+                Event::End(Tag::Heading(..)) => {
+                    if let Some((level, title)) = current_heading.take() {
+                        // Pop any headings at the same or a deeper level than the new heading
+                        while heading_stack
+                            .last()
+                            .filter(|(parent_level, _)| *parent_level >= level)
+                            .is_some()
+                        {
+                            heading_stack.pop();
+                        }
+                        heading_stack.push((level, title.trim().to_string()));
+                    }
+                }
+                _ => {}
+            }
+        }
+        push_section(&heading_stack, section_start..markdown.len());
+
+        sections
+    }
+
+    /// Split a section into ranges no longer than the maximum chunk size between paragraphs if possible
+    fn split_section(&self, markdown: &str, range: Range<usize>) -> Vec<Range<usize>> {
+        if range.len() <= self.max_chunk_size {
+            return vec![range];
+        }
+        let mut paragraph_ends: Vec<usize> = markdown[range.clone()]
+            .match_indices("\n\n")
+            .map(|(i, separator)| range.start + i + separator.len())
+            .collect();
+        paragraph_ends.push(range.end);
+
+        let mut chunks = Vec::new();
+        let mut start = range.start;
+        let mut end = start;
+        for paragraph_end in paragraph_ends {
+            if paragraph_end - start > self.max_chunk_size && end > start {
+                chunks.push(start..end);
+                start = end;
+            }
+            end = paragraph_end;
+        }
+        chunks.push(start..end);
+
+        chunks
+            .into_iter()
+            .filter(|range| !markdown[range.clone()].trim().is_empty())
+            .collect()
+    }
+
+    /// Split a markdown string into pairs of the text to embed and the byte range of the chunk
+    fn split_chunks(&self, markdown: &str) -> Vec<(String, Range<usize>)> {
+        let mut chunks = Vec::new();
+        for section in self.split_sections(markdown) {
+            let context = section.headings.join(" > ");
+            for range in self.split_section(markdown, section.range) {
+                let text = markdown[range.clone()].trim();
+                let text = if context.is_empty() {
+                    text.to_string()
+                } else {
+                    format!("{context}\n\n{text}")
+                };
+                chunks.push((text, range));
+            }
+        }
+        chunks
+    }
+}
+
+impl Chunker for MarkdownChunker {
+    type Error<E: Send + Sync + 'static> = E;
+
+    async fn chunk<E: Embedder + Send>(
+        &self,
+        document: &Document,
+        embedder: &E,
+    ) -> Result<Vec<Chunk>, E::Error> {
+        let (texts, ranges): (Vec<_>, Vec<_>) =
+            self.split_chunks(document.body()).into_iter().unzip();
+
+        let embeddings = embedder.embed_vec(texts).await?;
+
+        Ok(ranges
+            .into_iter()
+            .zip(embeddings)
+            .map(|(byte_range, embedding)| Chunk {
+                byte_range,
+                embeddings: vec![embedding],
+            })
+            .collect())
+    }
+}
+
+#[test]
+fn test_markdown_chunking() {
+    let markdown = "Intro text\n\n# Kalosm\n\nA simple interface.\n\n## Installation\n\nRun `cargo add`.\n\n## Usage\n\nFirst paragraph.\n\nSecond paragraph.\n\n# Floneum\n\nA graph editor.";
+    let chunker = MarkdownChunker::new().with_max_chunk_size(40);
+    let chunks = chunker.split_chunks(markdown);
+    let chunks: Vec<_> = chunks
+        .iter()
+        .map(|(text, range)| (text.as_str(), markdown[range.clone()].trim()))
+        .collect();
+    assert_eq!(
+        chunks,
+        [
+            ("Intro text", "Intro text"),
+            (
+                "Kalosm\n\n# Kalosm\n\nA simple interface.",
+                "# Kalosm\n\nA simple interface."
+            ),
+            (
+                "Kalosm > Installation\n\n## Installation\n\nRun `cargo add`.",
+                "## Installation\n\nRun `cargo add`."
+            ),
+            (
+                "Kalosm > Usage\n\n## Usage\n\nFirst paragraph.",
+                "## Usage\n\nFirst paragraph."
+            ),
+            ("Kalosm > Usage\n\nSecond paragraph.", "Second paragraph."),
+            (
+                "Floneum\n\n# Floneum\n\nA graph editor.",
+                "# Floneum\n\nA graph editor."
+            ),
+        ]
+    );
+}
@@ -28,6 +28,16 @@ mod sentence;
 pub use sentence::*;
 mod semantic;
 pub use semantic::*;
+mod breakpoint;
+pub use breakpoint::*;
+mod window;
+pub use window::*;
+mod multi_vector;
+pub use multi_vector::*;
+mod token;
+pub use token::*;
+mod markdown;
+pub use markdown::*;
 mod html;
 pub use html::*;
 
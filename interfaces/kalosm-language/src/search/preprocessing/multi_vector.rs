@@ -0,0 +1,135 @@
+use std::ops::Range;
+
+use kalosm_language_model::{Embedder, Embedding};
+
+use super::{ChunkStrategy, Chunker, SentenceChunker};
+use crate::{prelude::Document, search::Chunk};
+
+/// A [`Chunker`] that stores one vector for each sentence in a chunk instead of a single vector for the whole chunk.
+///
+/// Long technical chunks often mix several topics, and a single vector blurs them together. Searching with [`max_sim`] scores a chunk by its best matching sentence for each part of the query (ColBERT-style late interaction), which keeps the detail of each sentence while returning the whole chunk.
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm::language::*;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let document = Document::from_parts(
+///         "Floneum",
+///         "Floneum is a graph editor. It runs models locally.\n\nPlugins are sandboxed. They are written in WASM.",
+///     );
+///     let bert = Bert::new().await.unwrap();
+///     let chunks = MultiVectorChunker::new()
+///         .with_chunking(ChunkStrategy::Paragraph {
+///             paragraph_count: 1,
+///             overlap: 0,
+///         })
+///         .chunk(&document, &bert)
+///         .await
+///         .unwrap();
+///     for chunk in chunks {
+///         println!("{} vectors: {}", chunk.embeddings.len(), &document.body()[chunk.byte_range]);
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct MultiVectorChunker {
+    chunking: ChunkStrategy,
+}
+
+impl MultiVectorChunker {
+    /// Create a new [`MultiVectorChunker`] that splits documents with the default [`ChunkStrategy`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the strategy used to split the document into chunks. Each chunk is then split into sentences to embed. (default: [`ChunkStrategy::default`])
+    pub fn with_chunking(mut self, chunking: ChunkStrategy) -> Self {
+        self.chunking = chunking;
+        self
+    }
+
+    /// Split a string into pairs of the range of each chunk and the ranges of the sentences in that chunk.
+    pub fn split_chunks(&self, string: &str) -> Vec<(Range<usize>, Vec<Range<usize>>)> {
+        let sentence_chunker = SentenceChunker::default();
+        self.chunking
+            .chunk_str(string)
+            .into_iter()
+            .filter_map(|chunk| {
+                let sentences: Vec<_> = sentence_chunker
+                    .split_sentences(&string[chunk.clone()])
+                    .into_iter()
+                    .map(|sentence| chunk.start + sentence.start..chunk.start + sentence.end)
+                    .filter(|sentence| !string[sentence.clone()].trim().is_empty())
+                    .collect();
+                (!sentences.is_empty()).then_some((chunk, sentences))
+            })
+            .collect()
+    }
+}
+
+impl Chunker for MultiVectorChunker {
+    type Error<E: Send + Sync + 'static> = E;
+
+    async fn chunk<E: Embedder + Send>(
+        &self,
+        document: &Document,
+        embedder: &E,
+    ) -> Result<Vec<Chunk>, E::Error> {
+        let body = document.body();
+        let chunks = self.split_chunks(body);
+        let sentences = chunks
+            .iter()
+            .flat_map(|(_, sentences)| sentences)
+            .map(|sentence| body[sentence.clone()].trim().to_string())
+            .collect();
+
+        let mut embeddings = embedder.embed_vec(sentences).await?.into_iter();
+
+        Ok(chunks
+            .into_iter()
+            .map(|(byte_range, sentences)| Chunk {
+                byte_range,
+                embeddings: embeddings.by_ref().take(sentences.len()).collect(),
+            })
+            .collect())
+    }
+}
+
+/// Score a document against a query with MaxSim late interaction: the sum of the best cosine similarity of each query vector with any of the document vectors.
+///
+/// A higher score is a better match. Returns 0 if either side has no vectors.
+pub fn max_sim(query: &[Embedding], document: &[Embedding]) -> f32 {
+    if document.is_empty() {
+        return 0.0;
+    }
+    query
+        .iter()
+        .map(|query| {
+            document
+                .iter()
+                .map(|vector| query.cosine_similarity(vector))
+                .fold(f32::NEG_INFINITY, f32::max)
+        })
+        .sum()
+}
+
+#[test]
+fn test_max_sim() {
+    let query = [
+        Embedding::new(vec![1.0, 0.0].into()),
+        Embedding::new(vec![0.0, 1.0].into()),
+    ];
+    let covers_both = [
+        Embedding::new(vec![1.0, 0.0].into()),
+        Embedding::new(vec![0.0, 2.0].into()),
+    ];
+    let covers_one = [
+        Embedding::new(vec![1.0, 0.0].into()),
+        Embedding::new(vec![1.0, 0.1].into()),
+    ];
+    assert!((max_sim(&query, &covers_both) - 2.0).abs() < 1e-6);
+    assert!(max_sim(&query, &covers_one) < max_sim(&query, &covers_both));
+    assert_eq!(max_sim(&query, &[]), 0.0);
+}
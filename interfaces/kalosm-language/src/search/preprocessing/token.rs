@@ -0,0 +1,130 @@
+use std::ops::Range;
+
+use kalosm_language_model::Embedder;
+use tokenizers::Tokenizer;
+
+use super::Chunker;
+use crate::{prelude::Document, search::Chunk};
+
+/// An error that can occur when chunking a document with [`TokenChunker`].
+#[derive(Debug, thiserror::Error)]
+pub enum TokenChunkerError<E: Send + Sync + 'static> {
+    /// An error from the tokenizer.
+    #[error("Tokenizer error: {0}")]
+    TokenizerError(tokenizers::Error),
+    /// An error from the embedding model.
+    #[error("Embedding model error: {0}")]
+    EmbeddingModelError(E),
+}
+
+/// A [`Chunker`] that splits a document into chunks with a fixed number of tokens.
+///
+/// Embedding models can only read a limited number of tokens. Chunking with the tokenizer of the embedding model makes sure every chunk fits in the context of the model without truncating the text.
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm::language::*;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let document = Document::from_parts("Floneum", "Floneum is a graph editor for AI workflows.");
+///     let bert = Bert::new().await.unwrap();
+///     let chunker = TokenChunker::load("tokenizer.json")
+///         .unwrap()
+///         .with_chunk_size(128)
+///         .with_overlap(16);
+///     let chunks = chunker.chunk(&document, &bert).await.unwrap();
+///     println!("{:?}", chunks);
+/// }
+/// ```
+#[derive(Clone)]
+pub struct TokenChunker {
+    tokenizer: Tokenizer,
+    chunk_size: usize,
+    overlap: usize,
+}
+
+impl TokenChunker {
+    /// Create a new [`TokenChunker`] with a tokenizer. Chunks have 256 tokens with 32 tokens of overlap by default.
+    pub fn new(tokenizer: Tokenizer) -> Self {
+        Self {
+            tokenizer,
+            chunk_size: 256,
+            overlap: 32,
+        }
+    }
+
+    /// Load a [`TokenChunker`] from a huggingface tokenizer json file.
+    pub fn load(path: impl AsRef<std::path::Path>) -> Result<Self, tokenizers::Error> {
+        Ok(Self::new(Tokenizer::from_file(path)?))
+    }
+
+    /// Set the number of tokens in each chunk. (default: 256)
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    /// Set the number of tokens shared between adjacent chunks. This must be smaller than the chunk size. (default: 32)
+    pub fn with_overlap(mut self, overlap: usize) -> Self {
+        self.overlap = overlap;
+        self
+    }
+
+    /// Chunk a string into byte ranges with the chunk size in tokens.
+    pub fn chunk_str(&self, string: &str) -> Result<Vec<Range<usize>>, tokenizers::Error> {
+        let encoding = self.tokenizer.encode(string, false)?;
+        let offsets: Vec<_> = encoding
+            .get_offsets()
+            .iter()
+            .filter(|(start, end)| start < end)
+            .collect();
+        let step = self.chunk_size.saturating_sub(self.overlap).max(1);
+
+        let mut chunks = Vec::new();
+        let mut start = 0;
+        while start < offsets.len() {
+            let end = (start + self.chunk_size).min(offsets.len());
+            chunks.push(offsets[start].0..offsets[end - 1].1);
+            if end == offsets.len() {
+                break;
+            }
+            start += step;
+        }
+
+        Ok(chunks)
+    }
+}
+
+impl Chunker for TokenChunker {
+    type Error<E: Send + Sync + 'static> = TokenChunkerError<E>;
+
+    async fn chunk<E: Embedder + Send>(
+        &self,
+        document: &Document,
+        embedder: &E,
+    ) -> Result<Vec<Chunk>, Self::Error<E::Error>> {
+        let body = document.body();
+        let ranges = self
+            .chunk_str(body)
+            .map_err(TokenChunkerError::TokenizerError)?;
+        let texts = ranges
+            .iter()
+            .map(|range| body[range.clone()].to_string())
+            .collect();
+
+        let embeddings = embedder
+            .embed_vec(texts)
+            .await
+            .map_err(TokenChunkerError::EmbeddingModelError)?;
+
+        Ok(ranges
+            .into_iter()
+            .zip(embeddings)
+            .map(|(byte_range, embedding)| Chunk {
+                byte_range,
+                embeddings: vec![embedding],
+            })
+            .collect())
+    }
+}
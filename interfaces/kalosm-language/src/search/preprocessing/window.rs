@@ -0,0 +1,106 @@
+use std::ops::Range;
+
+use kalosm_language_model::Embedder;
+
+use super::{Chunker, SentenceChunker};
+use crate::{prelude::Document, search::Chunk};
+
+/// A [`Chunker`] that embeds each sentence on its own, but returns a window of the surrounding sentences as the chunk.
+///
+/// Single sentences make precise search targets, but they often don't have enough context to be useful on their own. This chunker searches by sentence and returns the window around the matching sentence.
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm::language::*;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let document = Document::from_parts(
+///         "Floneum",
+///         "Floneum is a graph editor. It runs models locally. Plugins are sandboxed.",
+///     );
+///     let bert = Bert::new().await.unwrap();
+///     // Embed each sentence, but include one sentence on each side in the chunk
+///     let chunks = SentenceWindowChunker::new()
+///         .with_window_size(1)
+///         .chunk(&document, &bert)
+///         .await
+///         .unwrap();
+///     for chunk in chunks {
+///         println!("{}", &document.body()[chunk.byte_range]);
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SentenceWindowChunker {
+    window_size: usize,
+}
+
+impl Default for SentenceWindowChunker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SentenceWindowChunker {
+    /// Create a new [`SentenceWindowChunker`] with a window of 2 sentences on each side of the embedded sentence.
+    pub const fn new() -> Self {
+        Self { window_size: 2 }
+    }
+
+    /// Set the number of sentences to include on each side of the embedded sentence. (default: 2)
+    pub fn with_window_size(mut self, window_size: usize) -> Self {
+        self.window_size = window_size;
+        self
+    }
+
+    /// Split a string into pairs of the range of each sentence and the range of the window around that sentence.
+    pub fn split_windows(&self, string: &str) -> Vec<(Range<usize>, Range<usize>)> {
+        let sentences: Vec<_> = SentenceChunker::default()
+            .split_sentences(string)
+            .into_iter()
+            .filter(|range| !string[range.clone()].trim().is_empty())
+            .collect();
+
+        sentences
+            .iter()
+            .enumerate()
+            .map(|(i, sentence)| {
+                let first = i.saturating_sub(self.window_size);
+                let last = (i + self.window_size).min(sentences.len() - 1);
+                (
+                    sentence.clone(),
+                    sentences[first].start..sentences[last].end,
+                )
+            })
+            .collect()
+    }
+}
+
+impl Chunker for SentenceWindowChunker {
+    type Error<E: Send + Sync + 'static> = E;
+
+    async fn chunk<E: Embedder + Send>(
+        &self,
+        document: &Document,
+        embedder: &E,
+    ) -> Result<Vec<Chunk>, E::Error> {
+        let body = document.body();
+        let windows = self.split_windows(body);
+        let sentences = windows
+            .iter()
+            .map(|(sentence, _)| body[sentence.clone()].trim().to_string())
+            .collect();
+
+        let embeddings = embedder.embed_vec(sentences).await?;
+
+        Ok(windows
+            .into_iter()
+            .zip(embeddings)
+            .map(|((_, window), embedding)| Chunk {
+                byte_range: window,
+                embeddings: vec![embedding],
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,553 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::sync::RwLock;
+
+use kalosm_language_model::Embedding;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use thiserror::Error;
+
+use super::{Candidates, EmbeddingId, VectorDBSearchResult, VectorStore};
+
+/// An in memory [`VectorStore`] that uses a [Hierarchical Navigable Small World](https://arxiv.org/abs/1603.09320) graph for approximate nearest neighbor search.
+///
+/// Embeddings are inserted into the graph one at a time, so adding embeddings doesn't rebuild the index like [`VectorDB`](crate::vector_db::VectorDB) does. Search time grows roughly logarithmically with the number of embeddings, which keeps search fast for collections with millions of chunks. The recall and speed of the index can be tuned with [`HnswStore::with_m`], [`HnswStore::with_ef_construction`] and [`HnswStore::with_ef_search`].
+///
+/// Distances are the negative dot product of the embeddings, so lower distances are closer like the other stores.
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm::language::*;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let bert = Bert::new().await.unwrap();
+///     let store = HnswStore::new().with_m(32).with_ef_search(128);
+///     let embedding = bert.embed("Kalosm is a library for local AI").await.unwrap();
+///     let ids = store.add_embeddings(vec![embedding.clone()]).await.unwrap();
+///     let results = store.search_embeddings(&embedding, 1, None).await.unwrap();
+///     assert_eq!(results[0].value, ids[0]);
+/// }
+/// ```
+pub struct HnswStore {
+    m: usize,
+    ef_construction: usize,
+    ef_search: usize,
+    graph: RwLock<Graph>,
+}
+
+impl Default for HnswStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HnswStore {
+    /// Create a new empty store.
+    pub fn new() -> Self {
+        Self {
+            m: 16,
+            ef_construction: 200,
+            ef_search: 64,
+            graph: RwLock::new(Graph::new()),
+        }
+    }
+
+    /// Set the number of neighbors each embedding is connected to in the graph (defaults to 16). Nodes in the bottom layer of the graph are connected to twice as many neighbors.
+    ///
+    /// Larger values improve recall, especially for high dimensional embeddings, but use more memory and make inserts slower.
+    pub fn with_m(mut self, m: usize) -> Self {
+        self.m = m.max(2);
+        self
+    }
+
+    /// Set the number of candidates considered when inserting an embedding (defaults to 200).
+    ///
+    /// Larger values build a higher quality graph with better recall, but make inserts slower.
+    pub fn with_ef_construction(mut self, ef_construction: usize) -> Self {
+        self.ef_construction = ef_construction.max(1);
+        self
+    }
+
+    /// Set the number of candidates considered when searching (defaults to 64). At least as many candidates as the number of requested results are always considered.
+    ///
+    /// Larger values improve recall, but make searches slower.
+    pub fn with_ef_search(mut self, ef_search: usize) -> Self {
+        self.ef_search = ef_search.max(1);
+        self
+    }
+
+    /// Get the number of embeddings in the store.
+    pub fn len(&self) -> usize {
+        self.graph.read().unwrap().nodes.len()
+    }
+
+    /// Check if the store is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn check_dims(graph: &mut Graph, embedding: &Embedding) -> Result<(), HnswStoreError> {
+        let found = embedding.vector().len();
+        match graph.dims {
+            Some(expected) if expected != found => {
+                Err(HnswStoreError::DimensionMismatch { expected, found })
+            }
+            _ => {
+                graph.dims = Some(found);
+                Ok(())
+            }
+        }
+    }
+}
+
+impl VectorStore for HnswStore {
+    type Error = HnswStoreError;
+
+    async fn add_embeddings(
+        &self,
+        embeddings: Vec<Embedding>,
+    ) -> Result<Vec<EmbeddingId>, Self::Error> {
+        let mut graph = self.graph.write().unwrap();
+        let mut ids = Vec::with_capacity(embeddings.len());
+        for embedding in embeddings {
+            Self::check_dims(&mut graph, &embedding)?;
+            let id = graph.take_id();
+            graph.insert(id, embedding.vector().into(), self);
+            ids.push(EmbeddingId(id));
+        }
+        Ok(ids)
+    }
+
+    async fn upsert_embeddings(
+        &self,
+        embeddings: Vec<(EmbeddingId, Embedding)>,
+    ) -> Result<(), Self::Error> {
+        let mut graph = self.graph.write().unwrap();
+        for (id, embedding) in embeddings {
+            Self::check_dims(&mut graph, &embedding)?;
+            if graph.remove(id.0, self) {
+                graph.prune_links(&HashSet::from([id.0]));
+            }
+            graph.reserve_id(id.0);
+            graph.insert(id.0, embedding.vector().into(), self);
+        }
+        Ok(())
+    }
+
+    async fn remove_embeddings(&self, ids: Vec<EmbeddingId>) -> Result<(), Self::Error> {
+        let mut graph = self.graph.write().unwrap();
+        let mut removed = HashSet::new();
+        for id in ids {
+            if graph.remove(id.0, self) {
+                graph.free.push(id.0);
+                removed.insert(id.0);
+            }
+        }
+        if !removed.is_empty() {
+            graph.prune_links(&removed);
+        }
+        Ok(())
+    }
+
+    async fn get_embedding(&self, id: EmbeddingId) -> Result<Option<Embedding>, Self::Error> {
+        let graph = self.graph.read().unwrap();
+        Ok(graph
+            .nodes
+            .get(&id.0)
+            .map(|node| Embedding::from(node.vector.iter().copied())))
+    }
+
+    async fn search_embeddings(
+        &self,
+        query: &Embedding,
+        results: usize,
+        filter: Option<Candidates>,
+    ) -> Result<Vec<VectorDBSearchResult>, Self::Error> {
+        let graph = self.graph.read().unwrap();
+        if let Some(expected) = graph.dims {
+            let found = query.vector().len();
+            if expected != found {
+                return Err(HnswStoreError::DimensionMismatch { expected, found });
+            }
+        }
+        Ok(graph
+            .search(query.vector(), results, self.ef_search, filter.as_ref())
+            .into_iter()
+            .map(|(distance, id)| VectorDBSearchResult {
+                distance,
+                value: EmbeddingId(id),
+            })
+            .collect())
+    }
+
+    async fn clear(&self) -> Result<(), Self::Error> {
+        *self.graph.write().unwrap() = Graph::new();
+        Ok(())
+    }
+}
+
+/// An error that can occur when interacting with a [`HnswStore`].
+#[derive(Error, Debug)]
+pub enum HnswStoreError {
+    /// The embedding has a different number of dimensions than the embeddings already in the store.
+    #[error("Expected an embedding with {expected} dimensions, but found {found} dimensions")]
+    DimensionMismatch {
+        /// The number of dimensions of the embeddings in the store.
+        expected: usize,
+        /// The number of dimensions of the embedding that was passed in.
+        found: usize,
+    },
+}
+
+struct Node {
+    vector: Box<[f32]>,
+    /// The neighbors of the node in each layer it is part of, starting with the bottom layer.
+    neighbors: Vec<Vec<u32>>,
+}
+
+struct Graph {
+    nodes: HashMap<u32, Node>,
+    entry_point: Option<u32>,
+    dims: Option<usize>,
+    next_id: u32,
+    free: Vec<u32>,
+    rng: StdRng,
+}
+
+/// A distance to a node that is ordered by the distance.
+#[derive(Clone, Copy, PartialEq)]
+struct Scored(f32, u32);
+
+impl Eq for Scored {}
+
+impl PartialOrd for Scored {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Scored {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0).then(self.1.cmp(&other.1))
+    }
+}
+
+fn distance(a: &[f32], b: &[f32]) -> f32 {
+    -a.iter().zip(b).map(|(a, b)| a * b).sum::<f32>()
+}
+
+impl Graph {
+    fn new() -> Self {
+        Self {
+            nodes: HashMap::new(),
+            entry_point: None,
+            dims: None,
+            next_id: 0,
+            free: Vec::new(),
+            rng: StdRng::from_entropy(),
+        }
+    }
+
+    fn take_id(&mut self) -> u32 {
+        self.free.pop().unwrap_or_else(|| {
+            let id = self.next_id;
+            self.next_id += 1;
+            id
+        })
+    }
+
+    /// Mark an id as used when an embedding is inserted with an id chosen by the caller.
+    fn reserve_id(&mut self, id: u32) {
+        if id >= self.next_id {
+            // Any ids skipped over are still free
+            self.free.extend(self.next_id..id);
+            self.next_id = id + 1;
+        } else {
+            self.free.retain(|free| *free != id);
+        }
+    }
+
+    fn distance_to(&self, query: &[f32], id: u32) -> f32 {
+        distance(query, &self.nodes[&id].vector)
+    }
+
+    fn top_layer(&self, id: u32) -> usize {
+        self.nodes[&id].neighbors.len() - 1
+    }
+
+    fn max_neighbors(store: &HnswStore, layer: usize) -> usize {
+        if layer == 0 {
+            store.m * 2
+        } else {
+            store.m
+        }
+    }
+
+    /// Move greedily towards the query in the layers above `target_layer` and return the closest node found.
+    fn descend(&self, query: &[f32], target_layer: usize) -> Option<Scored> {
+        let entry = self.entry_point?;
+        let mut closest = Scored(self.distance_to(query, entry), entry);
+        for layer in (target_layer + 1..=self.top_layer(entry)).rev() {
+            let mut changed = true;
+            while changed {
+                changed = false;
+                for &neighbor in &self.nodes[&closest.1].neighbors[layer] {
+                    let scored = Scored(self.distance_to(query, neighbor), neighbor);
+                    if scored < closest {
+                        closest = scored;
+                        changed = true;
+                    }
+                }
+            }
+        }
+        Some(closest)
+    }
+
+    /// Search one layer of the graph for the `ef` closest nodes to the query that pass the filter, sorted from closest to furthest.
+    fn search_layer(
+        &self,
+        query: &[f32],
+        entry_points: &[Scored],
+        ef: usize,
+        layer: usize,
+        filter: Option<&Candidates>,
+    ) -> Vec<Scored> {
+        let passes = |id: u32| filter.is_none_or(|filter| filter.contains(id));
+        let mut visited: HashSet<u32> = entry_points.iter().map(|scored| scored.1).collect();
+        let mut candidates: BinaryHeap<Reverse<Scored>> =
+            entry_points.iter().copied().map(Reverse).collect();
+        let mut found: BinaryHeap<Scored> = entry_points
+            .iter()
+            .copied()
+            .filter(|scored| passes(scored.1))
+            .collect();
+
+        while let Some(Reverse(candidate)) = candidates.pop() {
+            if found.len() >= ef && found.peek().is_some_and(|worst| candidate > *worst) {
+                break;
+            }
+            for &neighbor in &self.nodes[&candidate.1].neighbors[layer] {
+                if !visited.insert(neighbor) {
+                    continue;
+                }
+                let scored = Scored(self.distance_to(query, neighbor), neighbor);
+                if found.len() < ef || found.peek().is_some_and(|worst| scored < *worst) {
+                    candidates.push(Reverse(scored));
+                    if passes(neighbor) {
+                        found.push(scored);
+                        if found.len() > ef {
+                            found.pop();
+                        }
+                    }
+                }
+            }
+        }
+
+        found.into_sorted_vec()
+    }
+
+    /// Pick up to `max` neighbors from the candidates sorted from closest to furthest. Candidates that are closer to an already selected neighbor than to the node are skipped first so the node stays connected to different parts of the graph.
+    fn select_neighbors(&self, candidates: &[Scored], max: usize) -> Vec<u32> {
+        let mut selected: Vec<Scored> = Vec::with_capacity(max);
+        let mut skipped = Vec::new();
+        for &candidate in candidates {
+            if selected.len() >= max {
+                break;
+            }
+            let vector = &self.nodes[&candidate.1].vector;
+            let diverse = selected
+                .iter()
+                .all(|selected| distance(vector, &self.nodes[&selected.1].vector) > candidate.0);
+            if diverse {
+                selected.push(candidate);
+            } else {
+                skipped.push(candidate);
+            }
+        }
+        selected.extend(skipped.into_iter().take(max - selected.len()));
+        selected.into_iter().map(|scored| scored.1).collect()
+    }
+
+    /// Replace the neighbors of a node in a layer with the best `max` of the candidates.
+    fn set_neighbors(&mut self, id: u32, layer: usize, candidates: &[u32], max: usize) {
+        let vector = &self.nodes[&id].vector;
+        let mut scored: Vec<_> = candidates
+            .iter()
+            .filter(|candidate| **candidate != id && self.nodes.contains_key(*candidate))
+            .map(|&candidate| Scored(distance(vector, &self.nodes[&candidate].vector), candidate))
+            .collect();
+        scored.sort();
+        scored.dedup();
+        let neighbors = self.select_neighbors(&scored, max);
+        self.nodes.get_mut(&id).unwrap().neighbors[layer] = neighbors;
+    }
+
+    fn insert(&mut self, id: u32, vector: Box<[f32]>, store: &HnswStore) {
+        let level_multiplier = 1.0 / (store.m as f64).ln();
+        let uniform: f64 = self.rng.gen_range(f64::EPSILON..1.0);
+        let level = (-uniform.ln() * level_multiplier) as usize;
+
+        let entry = self.descend(&vector, level);
+        self.nodes.insert(
+            id,
+            Node {
+                vector,
+                neighbors: vec![Vec::new(); level + 1],
+            },
+        );
+        let Some(entry) = entry else {
+            self.entry_point = Some(id);
+            return;
+        };
+
+        let query = self.nodes[&id].vector.clone();
+        let mut entry_points = vec![entry];
+        let top = level.min(self.top_layer(entry.1));
+        for layer in (0..=top).rev() {
+            let found =
+                self.search_layer(&query, &entry_points, store.ef_construction, layer, None);
+            let max = Self::max_neighbors(store, layer);
+            let neighbors = self.select_neighbors(&found, max);
+            for &neighbor in &neighbors {
+                let mut links = self.nodes[&neighbor].neighbors[layer].clone();
+                links.push(id);
+                if links.len() > max {
+                    self.set_neighbors(neighbor, layer, &links, max);
+                } else {
+                    self.nodes.get_mut(&neighbor).unwrap().neighbors[layer] = links;
+                }
+            }
+            self.nodes.get_mut(&id).unwrap().neighbors[layer] = neighbors;
+            entry_points = found;
+        }
+
+        if level > self.top_layer(entry.1) {
+            self.entry_point = Some(id);
+        }
+    }
+
+    /// Remove a node and reconnect the nodes that linked to it. Links to the node from other nodes are left until [`Graph::prune_links`] is called. Returns false if the node doesn't exist.
+    fn remove(&mut self, id: u32, store: &HnswStore) -> bool {
+        let Some(removed) = self.nodes.remove(&id) else {
+            return false;
+        };
+
+        // Reconnect every node that linked to the removed node with the neighbors of the removed node
+        for (layer, neighbors) in removed.neighbors.iter().enumerate() {
+            let max = Self::max_neighbors(store, layer);
+            for &neighbor in neighbors {
+                let Some(node) = self.nodes.get(&neighbor) else {
+                    continue;
+                };
+                if !node.neighbors[layer].contains(&id) {
+                    continue;
+                }
+                let links: Vec<_> = node.neighbors[layer]
+                    .iter()
+                    .chain(neighbors)
+                    .copied()
+                    .collect();
+                self.set_neighbors(neighbor, layer, &links, max);
+            }
+        }
+
+        true
+    }
+
+    /// Remove every link to the removed nodes and pick a new entry point if it was removed.
+    fn prune_links(&mut self, removed: &HashSet<u32>) {
+        // Links are not always symmetric, so some nodes that link to a removed node were not reconnected
+        for node in self.nodes.values_mut() {
+            for links in node.neighbors.iter_mut() {
+                links.retain(|link| !removed.contains(link));
+            }
+        }
+
+        if self
+            .entry_point
+            .is_some_and(|entry| !self.nodes.contains_key(&entry))
+        {
+            self.entry_point = self
+                .nodes
+                .iter()
+                .max_by_key(|(id, node)| (node.neighbors.len(), Reverse(**id)))
+                .map(|(id, _)| *id);
+        }
+    }
+
+    fn search(
+        &self,
+        query: &[f32],
+        results: usize,
+        ef: usize,
+        filter: Option<&Candidates>,
+    ) -> Vec<(f32, u32)> {
+        let mut ef = ef.max(results);
+        if let Some(filter) = filter {
+            // If only a few embeddings pass the filter, comparing against all of them is faster and exact
+            if filter.len() as usize <= ef {
+                let mut found: Vec<_> = filter
+                    .iter()
+                    .filter(|id| self.nodes.contains_key(id))
+                    .map(|id| Scored(self.distance_to(query, id), id))
+                    .collect();
+                found.sort();
+                found.truncate(results);
+                return found.into_iter().map(|Scored(d, id)| (d, id)).collect();
+            }
+            // Otherwise search more of the graph so enough of the nodes we find pass the filter
+            let ratio = self.nodes.len() as f64 / filter.len() as f64;
+            ef = ((ef as f64 * ratio) as usize).min(self.nodes.len());
+        }
+
+        let Some(entry) = self.descend(query, 0) else {
+            return Vec::new();
+        };
+        let mut found = self.search_layer(query, &[entry], ef, 0, filter);
+        found.truncate(results);
+        found.into_iter().map(|Scored(d, id)| (d, id)).collect()
+    }
+}
+
+#[tokio::test]
+async fn test_hnsw_store_search() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut random_embedding = || {
+        let vector: Vec<f32> = (0..32).map(|_| rng.gen_range(-1.0..1.0)).collect();
+        let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+        Embedding::from(vector.into_iter().map(|x| x / norm))
+    };
+    let embeddings: Vec<_> = (0..1000).map(|_| random_embedding()).collect();
+
+    let store = HnswStore::new();
+    let ids = store.add_embeddings(embeddings.clone()).await.unwrap();
+    let mut found = 0;
+    for (id, embedding) in ids.iter().zip(&embeddings) {
+        let results = store.search_embeddings(embedding, 1, None).await.unwrap();
+        found += (results[0].value == *id) as usize;
+    }
+    assert!(found >= 990, "only found {found} of 1000 embeddings");
+
+    // Removed embeddings are never returned and their ids are recycled
+    store.remove_embeddings(ids[..500].to_vec()).await.unwrap();
+    for embedding in &embeddings[..500] {
+        let results = store.search_embeddings(embedding, 10, None).await.unwrap();
+        assert!(results.iter().all(|result| result.value.0 >= 500));
+    }
+    let new_ids = store
+        .add_embeddings(vec![embeddings[0].clone()])
+        .await
+        .unwrap();
+    assert!(new_ids[0].0 < 500);
+
+    let filter = Candidates::from_iter([ids[600].0, ids[700].0]);
+    let results = store
+        .search_embeddings(&embeddings[600], 10, Some(filter))
+        .await
+        .unwrap();
+    assert_eq!(
+        results.iter().map(|r| r.value).collect::<Vec<_>>(),
+        vec![ids[600], ids[700]]
+    );
+}
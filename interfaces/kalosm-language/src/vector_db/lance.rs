@@ -0,0 +1,318 @@
+use std::sync::{Arc, Mutex, RwLock};
+
+use arrow_array::types::Float32Type;
+use arrow_array::{
+    Array, FixedSizeListArray, Float32Array, RecordBatch, RecordBatchIterator, UInt32Array,
+};
+use arrow_schema::{ArrowError, DataType, Field, Schema};
+use futures_util::TryStreamExt;
+use kalosm_language_model::Embedding;
+use lancedb::query::{ExecutableQuery, QueryBase, Select};
+use lancedb::{Connection, DistanceType, Table};
+use thiserror::Error;
+
+use super::{Candidates, EmbeddingId, VectorDBSearchResult, VectorStore};
+
+/// A [`VectorStore`] backed by a table in a [LanceDB](https://lancedb.com) database.
+///
+/// LanceDB databases can live in a local folder or in object storage like S3, so the index can grow beyond the memory and disk of one machine. The table is created the first time embeddings are added. New ids are allocated by this store, so only one [`LanceDbStore`] should add embeddings to a table at a time.
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm::language::*;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let bert = Bert::new().await.unwrap();
+///     let store = LanceDbStore::new("./db/lance", "documents").await.unwrap();
+///     let embedding = bert.embed("Kalosm is a library for local AI").await.unwrap();
+///     let ids = store.add_embeddings(vec![embedding.clone()]).await.unwrap();
+///     let results = store.search_embeddings(&embedding, 1, None).await.unwrap();
+///     assert_eq!(results[0].value, ids[0]);
+/// }
+/// ```
+pub struct LanceDbStore {
+    connection: Connection,
+    table_name: String,
+    table: RwLock<Option<Table>>,
+    next_id: Mutex<Option<u32>>,
+}
+
+impl LanceDbStore {
+    /// Connect to the LanceDB database at the uri and use the table with the given name. The uri can be a local path or an object storage url like `s3://bucket/path`.
+    pub async fn new(uri: &str, table_name: impl ToString) -> Result<Self, LanceDbStoreError> {
+        Ok(Self::from_connection(
+            lancedb::connect(uri).execute().await?,
+            table_name,
+        ))
+    }
+
+    /// Use the table with the given name in an existing LanceDB connection.
+    pub fn from_connection(connection: Connection, table_name: impl ToString) -> Self {
+        Self {
+            connection,
+            table_name: table_name.to_string(),
+            table: RwLock::new(None),
+            next_id: Mutex::new(None),
+        }
+    }
+
+    /// Open the table if it exists.
+    async fn table(&self) -> Result<Option<Table>, LanceDbStoreError> {
+        let cached = self.table.read().unwrap().clone();
+        if let Some(table) = cached {
+            return Ok(Some(table));
+        }
+        let names = self.connection.table_names().execute().await?;
+        if !names.contains(&self.table_name) {
+            return Ok(None);
+        }
+        let table = self
+            .connection
+            .open_table(&self.table_name)
+            .execute()
+            .await?;
+        *self.table.write().unwrap() = Some(table.clone());
+        Ok(Some(table))
+    }
+
+    /// Open the table, or create it if it doesn't exist yet.
+    async fn create(&self, dims: usize) -> Result<Table, LanceDbStoreError> {
+        if let Some(table) = self.table().await? {
+            return Ok(table);
+        }
+        let table = self
+            .connection
+            .create_empty_table(&self.table_name, schema(dims))
+            .execute()
+            .await?;
+        *self.table.write().unwrap() = Some(table.clone());
+        Ok(table)
+    }
+
+    /// Reserve `count` new ids and return the first one.
+    async fn allocate_ids(&self, count: u32) -> Result<u32, LanceDbStoreError> {
+        let missing = self.next_id.lock().unwrap().is_none();
+        if missing {
+            let next = self.max_id().await?.map_or(0, |id| id + 1);
+            self.next_id.lock().unwrap().get_or_insert(next);
+        }
+        let mut next_id = self.next_id.lock().unwrap();
+        let next_id = next_id.as_mut().unwrap();
+        let first = *next_id;
+        *next_id += count;
+        Ok(first)
+    }
+
+    /// Find the largest id in the table.
+    async fn max_id(&self) -> Result<Option<u32>, LanceDbStoreError> {
+        let Some(table) = self.table().await? else {
+            return Ok(None);
+        };
+        let batches: Vec<RecordBatch> = table
+            .query()
+            .select(Select::columns(&["id"]))
+            .execute()
+            .await?
+            .try_collect()
+            .await?;
+        let mut max = None;
+        for batch in &batches {
+            max = max.max(ids(batch)?.iter().flatten().max());
+        }
+        Ok(max)
+    }
+}
+
+impl VectorStore for LanceDbStore {
+    type Error = LanceDbStoreError;
+
+    async fn add_embeddings(
+        &self,
+        embeddings: Vec<Embedding>,
+    ) -> Result<Vec<EmbeddingId>, Self::Error> {
+        if embeddings.is_empty() {
+            return Ok(Vec::new());
+        }
+        let first = self.allocate_ids(embeddings.len() as u32).await?;
+        let embeddings: Vec<_> = embeddings
+            .into_iter()
+            .enumerate()
+            .map(|(i, embedding)| (EmbeddingId(first + i as u32), embedding))
+            .collect();
+        let ids = embeddings.iter().map(|(id, _)| *id).collect();
+        self.upsert_embeddings(embeddings).await?;
+        Ok(ids)
+    }
+
+    async fn upsert_embeddings(
+        &self,
+        embeddings: Vec<(EmbeddingId, Embedding)>,
+    ) -> Result<(), Self::Error> {
+        let Some((_, first)) = embeddings.first() else {
+            return Ok(());
+        };
+        let dims = first.vector().len();
+        let table = self.create(dims).await?;
+
+        // Keep ids allocated by this store ahead of ids that were inserted directly
+        let max = embeddings.iter().map(|(id, _)| id.0).max().unwrap_or(0);
+        if let Some(next_id) = self.next_id.lock().unwrap().as_mut() {
+            *next_id = (*next_id).max(max + 1);
+        }
+
+        let schema = schema(dims);
+        let ids = UInt32Array::from_iter_values(embeddings.iter().map(|(id, _)| id.0));
+        let vectors = FixedSizeListArray::from_iter_primitive::<Float32Type, _, _>(
+            embeddings
+                .iter()
+                .map(|(_, embedding)| Some(embedding.vector().iter().copied().map(Some))),
+            dims as i32,
+        );
+        let batch = RecordBatch::try_new(schema.clone(), vec![Arc::new(ids), Arc::new(vectors)])?;
+
+        let mut merge = table.merge_insert(&["id"]);
+        merge
+            .when_matched_update_all(None)
+            .when_not_matched_insert_all();
+        merge
+            .execute(Box::new(RecordBatchIterator::new([Ok(batch)], schema)))
+            .await?;
+        Ok(())
+    }
+
+    async fn remove_embeddings(&self, ids: Vec<EmbeddingId>) -> Result<(), Self::Error> {
+        if ids.is_empty() {
+            return Ok(());
+        }
+        let Some(table) = self.table().await? else {
+            return Ok(());
+        };
+        table
+            .delete(&id_predicate(ids.iter().map(|id| id.0)))
+            .await?;
+        Ok(())
+    }
+
+    async fn get_embedding(&self, id: EmbeddingId) -> Result<Option<Embedding>, Self::Error> {
+        let Some(table) = self.table().await? else {
+            return Ok(None);
+        };
+        let batches: Vec<RecordBatch> = table
+            .query()
+            .only_if(format!("id = {}", id.0))
+            .select(Select::columns(&["vector"]))
+            .limit(1)
+            .execute()
+            .await?
+            .try_collect()
+            .await?;
+        let Some(batch) = batches.iter().find(|batch| batch.num_rows() > 0) else {
+            return Ok(None);
+        };
+        let vectors = column::<FixedSizeListArray>(batch, "vector")?;
+        let vector = vectors.value(0);
+        let vector = vector
+            .as_any()
+            .downcast_ref::<Float32Array>()
+            .ok_or(LanceDbStoreError::InvalidResponse)?;
+        Ok(Some(Embedding::new(vector.values().to_vec().into())))
+    }
+
+    async fn search_embeddings(
+        &self,
+        query: &Embedding,
+        results: usize,
+        filter: Option<Candidates>,
+    ) -> Result<Vec<VectorDBSearchResult>, Self::Error> {
+        let Some(table) = self.table().await? else {
+            return Ok(Vec::new());
+        };
+        let mut search = table
+            .query()
+            .nearest_to(query.vector())?
+            .distance_type(DistanceType::Dot)
+            .limit(results);
+        if let Some(filter) = filter {
+            search = search.only_if(id_predicate(filter.iter()));
+        }
+        let batches: Vec<RecordBatch> = search.execute().await?.try_collect().await?;
+
+        let mut results = Vec::new();
+        for batch in &batches {
+            let ids = ids(batch)?;
+            let distances = column::<Float32Array>(batch, "_distance")?;
+            results.extend(
+                ids.iter()
+                    .zip(distances.iter())
+                    .filter_map(|(id, distance)| {
+                        Some(VectorDBSearchResult {
+                            distance: distance?,
+                            value: EmbeddingId(id?),
+                        })
+                    }),
+            );
+        }
+        Ok(results)
+    }
+
+    async fn clear(&self) -> Result<(), Self::Error> {
+        if self.table().await?.is_some() {
+            self.connection.drop_table(&self.table_name).await?;
+        }
+        *self.table.write().unwrap() = None;
+        *self.next_id.lock().unwrap() = Some(0);
+        Ok(())
+    }
+}
+
+/// The schema of the table for embeddings with the given number of dimensions.
+fn schema(dims: usize) -> Arc<Schema> {
+    Arc::new(Schema::new(vec![
+        Field::new("id", DataType::UInt32, false),
+        Field::new(
+            "vector",
+            DataType::FixedSizeList(
+                Arc::new(Field::new("item", DataType::Float32, true)),
+                dims as i32,
+            ),
+            false,
+        ),
+    ]))
+}
+
+/// A SQL predicate that matches rows with any of the ids.
+fn id_predicate(ids: impl IntoIterator<Item = u32>) -> String {
+    let ids: Vec<_> = ids.into_iter().map(|id| id.to_string()).collect();
+    if ids.is_empty() {
+        return "false".to_string();
+    }
+    format!("id IN ({})", ids.join(", "))
+}
+
+fn ids(batch: &RecordBatch) -> Result<&UInt32Array, LanceDbStoreError> {
+    column(batch, "id")
+}
+
+fn column<'a, T: 'static>(batch: &'a RecordBatch, name: &str) -> Result<&'a T, LanceDbStoreError> {
+    batch
+        .column_by_name(name)
+        .and_then(|column| column.as_any().downcast_ref::<T>())
+        .ok_or(LanceDbStoreError::InvalidResponse)
+}
+
+/// An error that can occur when interacting with a [`LanceDbStore`].
+#[derive(Error, Debug)]
+pub enum LanceDbStoreError {
+    /// An error from the lancedb crate.
+    #[error("LanceDB error: {0}")]
+    LanceDb(#[from] lancedb::Error),
+    /// An error building or reading arrow data.
+    #[error("Arrow error: {0}")]
+    Arrow(#[from] ArrowError),
+    /// The table did not contain the columns kalosm expected.
+    #[error(
+        "Invalid response from LanceDB. The table does not have the expected id and vector columns"
+    )]
+    InvalidResponse,
+}
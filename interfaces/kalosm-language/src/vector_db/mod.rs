@@ -13,6 +13,19 @@ use rand::rngs::StdRng;
 use rand::SeedableRng;
 use serde::{Deserialize, Serialize};
 
+mod store;
+pub use store::*;
+mod hnsw;
+pub use hnsw::*;
+#[cfg(feature = "qdrant")]
+mod qdrant;
+#[cfg(feature = "qdrant")]
+pub use qdrant::*;
+#[cfg(feature = "lancedb")]
+mod lance;
+#[cfg(feature = "lancedb")]
+pub use lance::*;
+
 /// A set of candidates for a vector search.
 pub type Candidates = roaring::RoaringBitmap;
 
@@ -171,6 +184,26 @@ impl VectorDB {
         Ok(())
     }
 
+    /// Mark an id as used when an embedding is inserted with an id chosen by the caller.
+    fn reserve_id(&self, id: EmbeddingId, wtxn: &mut RwTxn) -> Result<(), heed::Error> {
+        let mut free = self.metadata.get(wtxn, "free")?.unwrap_or_default();
+        let max = self
+            .metadata
+            .get(wtxn, "max")?
+            .and_then(|max| max.first().copied())
+            .unwrap_or_default();
+        if id.0 >= max {
+            // Any ids skipped over are still free
+            free.extend(max..id.0);
+            self.metadata.put(wtxn, "max", &vec![id.0 + 1])?;
+        } else {
+            free.retain(|free| *free != id.0);
+        }
+        self.metadata.put(wtxn, "free", &free)?;
+
+        Ok(())
+    }
+
     /// Get the underlying database.
     pub fn raw(&self) -> (&ArroyDatabase<DotProduct>, &heed::Env) {
         (&self.database, &self.env)
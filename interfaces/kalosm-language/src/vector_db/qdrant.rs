@@ -0,0 +1,358 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use kalosm_language_model::Embedding;
+use serde::Deserialize;
+use serde_json::json;
+use thiserror::Error;
+
+use super::{Candidates, EmbeddingId, VectorDBSearchResult, VectorStore};
+
+/// The payload key that stores the id of each point. Qdrant can only order points by an indexed payload key, so the id is stored in the payload as well to find the largest id.
+const ID_KEY: &str = "kalosm_id";
+
+/// A [`VectorStore`] backed by a collection in a [Qdrant](https://qdrant.tech) server.
+///
+/// The collection is created the first time embeddings are added. New ids are allocated by this client, so only one [`QdrantStore`] should add embeddings to a collection at a time.
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm::language::*;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let bert = Bert::new().await.unwrap();
+///     let store = QdrantStore::new("http://localhost:6333", "documents");
+///     let embedding = bert.embed("Kalosm is a library for local AI").await.unwrap();
+///     let ids = store.add_embeddings(vec![embedding.clone()]).await.unwrap();
+///     let results = store.search_embeddings(&embedding, 1, None).await.unwrap();
+///     assert_eq!(results[0].value, ids[0]);
+/// }
+/// ```
+#[derive(Debug)]
+pub struct QdrantStore {
+    reqwest_client: reqwest::Client,
+    base_url: String,
+    collection: String,
+    api_key: Option<String>,
+    created: AtomicBool,
+    next_id: Mutex<Option<u32>>,
+}
+
+impl QdrantStore {
+    /// Create a new store for the collection in the Qdrant server at the url.
+    pub fn new(base_url: impl ToString, collection: impl ToString) -> Self {
+        Self {
+            reqwest_client: reqwest::Client::new(),
+            base_url: base_url.to_string(),
+            collection: collection.to_string(),
+            api_key: None,
+            created: AtomicBool::new(false),
+            next_id: Mutex::new(None),
+        }
+    }
+
+    /// Sets the API key that is sent with every request. (defaults to the environment variable `QDRANT_API_KEY` if it is set)
+    pub fn with_api_key(mut self, api_key: impl ToString) -> Self {
+        self.api_key = Some(api_key.to_string());
+        self
+    }
+
+    /// Set the reqwest client for the store.
+    pub fn with_reqwest_client(mut self, client: reqwest::Client) -> Self {
+        self.reqwest_client = client;
+        self
+    }
+
+    /// Send a request to a route of the collection and deserialize the result.
+    async fn request<T: serde::de::DeserializeOwned>(
+        &self,
+        method: reqwest::Method,
+        route: &str,
+        body: Option<serde_json::Value>,
+    ) -> Result<T, QdrantStoreError> {
+        let url = format!(
+            "{}/collections/{}{route}",
+            self.base_url.trim_end_matches('/'),
+            self.collection
+        );
+        let mut request = self.reqwest_client.request(method, url);
+        if let Some(body) = body {
+            request = request.json(&body);
+        }
+        let api_key = self
+            .api_key
+            .clone()
+            .or_else(|| std::env::var("QDRANT_API_KEY").ok());
+        if let Some(api_key) = api_key {
+            request = request.header("api-key", api_key);
+        }
+        let response = request.send().await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let message = response
+                .json::<QdrantErrorResponse>()
+                .await
+                .map(|error| error.status.error)
+                .unwrap_or_else(|_| status.to_string());
+            return Err(QdrantStoreError::Server {
+                status: status.as_u16(),
+                message,
+            });
+        }
+
+        Ok(response.json::<QdrantResponse<T>>().await?.result)
+    }
+
+    /// Check if the collection exists on the server.
+    async fn exists(&self) -> Result<bool, QdrantStoreError> {
+        if self.created.load(Ordering::SeqCst) {
+            return Ok(true);
+        }
+        let exists = self
+            .request::<ExistsResult>(reqwest::Method::GET, "/exists", None)
+            .await?
+            .exists;
+        self.created.store(exists, Ordering::SeqCst);
+        Ok(exists)
+    }
+
+    /// Create the collection if it doesn't already exist.
+    async fn create(&self, dims: usize) -> Result<(), QdrantStoreError> {
+        if self.exists().await? {
+            return Ok(());
+        }
+        self.request::<serde_json::Value>(
+            reqwest::Method::PUT,
+            "",
+            Some(json!({ "vectors": { "size": dims, "distance": "Dot" } })),
+        )
+        .await?;
+        self.request::<serde_json::Value>(
+            reqwest::Method::PUT,
+            "/index?wait=true",
+            Some(json!({ "field_name": ID_KEY, "field_schema": "integer" })),
+        )
+        .await?;
+        self.created.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Reserve `count` new ids and return the first one.
+    async fn allocate_ids(&self, count: u32) -> Result<u32, QdrantStoreError> {
+        if self.next_id.lock().unwrap().is_none() {
+            let next = self.max_id().await?.map_or(0, |id| id + 1);
+            self.next_id.lock().unwrap().get_or_insert(next);
+        }
+        let mut next_id = self.next_id.lock().unwrap();
+        let next_id = next_id.as_mut().unwrap();
+        let first = *next_id;
+        *next_id += count;
+        Ok(first)
+    }
+
+    /// Find the largest id in the collection.
+    async fn max_id(&self) -> Result<Option<u32>, QdrantStoreError> {
+        if !self.exists().await? {
+            return Ok(None);
+        }
+        let result: ScrollResult = self
+            .request(
+                reqwest::Method::POST,
+                "/points/scroll",
+                Some(json!({
+                    "limit": 1,
+                    "order_by": { "key": ID_KEY, "direction": "desc" },
+                    "with_payload": false,
+                    "with_vector": false,
+                })),
+            )
+            .await?;
+        Ok(result.points.first().map(|point| point.id))
+    }
+}
+
+impl VectorStore for QdrantStore {
+    type Error = QdrantStoreError;
+
+    async fn add_embeddings(
+        &self,
+        embeddings: Vec<Embedding>,
+    ) -> Result<Vec<EmbeddingId>, Self::Error> {
+        if embeddings.is_empty() {
+            return Ok(Vec::new());
+        }
+        let first = self.allocate_ids(embeddings.len() as u32).await?;
+        let embeddings: Vec<_> = embeddings
+            .into_iter()
+            .enumerate()
+            .map(|(i, embedding)| (EmbeddingId(first + i as u32), embedding))
+            .collect();
+        let ids = embeddings.iter().map(|(id, _)| *id).collect();
+        self.upsert_embeddings(embeddings).await?;
+        Ok(ids)
+    }
+
+    async fn upsert_embeddings(
+        &self,
+        embeddings: Vec<(EmbeddingId, Embedding)>,
+    ) -> Result<(), Self::Error> {
+        let Some((_, first)) = embeddings.first() else {
+            return Ok(());
+        };
+        self.create(first.vector().len()).await?;
+
+        // Keep ids allocated by this client ahead of ids that were inserted directly
+        let max = embeddings.iter().map(|(id, _)| id.0).max().unwrap_or(0);
+        if let Some(next_id) = self.next_id.lock().unwrap().as_mut() {
+            *next_id = (*next_id).max(max + 1);
+        }
+
+        let points: Vec<_> = embeddings
+            .iter()
+            .map(|(id, embedding)| {
+                json!({
+                    "id": id.0,
+                    "vector": embedding.vector(),
+                    "payload": { ID_KEY: id.0 },
+                })
+            })
+            .collect();
+        self.request::<serde_json::Value>(
+            reqwest::Method::PUT,
+            "/points?wait=true",
+            Some(json!({ "points": points })),
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn remove_embeddings(&self, ids: Vec<EmbeddingId>) -> Result<(), Self::Error> {
+        if ids.is_empty() || !self.exists().await? {
+            return Ok(());
+        }
+        let ids: Vec<_> = ids.iter().map(|id| id.0).collect();
+        self.request::<serde_json::Value>(
+            reqwest::Method::POST,
+            "/points/delete?wait=true",
+            Some(json!({ "points": ids })),
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn get_embedding(&self, id: EmbeddingId) -> Result<Option<Embedding>, Self::Error> {
+        if !self.exists().await? {
+            return Ok(None);
+        }
+        let points: Vec<Point> = self
+            .request(
+                reqwest::Method::POST,
+                "/points",
+                Some(json!({ "ids": [id.0], "with_payload": false, "with_vector": true })),
+            )
+            .await?;
+        Ok(points
+            .into_iter()
+            .next()
+            .and_then(|point| point.vector)
+            .map(|vector| Embedding::new(vector.into())))
+    }
+
+    async fn search_embeddings(
+        &self,
+        query: &Embedding,
+        results: usize,
+        filter: Option<Candidates>,
+    ) -> Result<Vec<VectorDBSearchResult>, Self::Error> {
+        if !self.exists().await? {
+            return Ok(Vec::new());
+        }
+        let mut body = json!({
+            "vector": query.vector(),
+            "limit": results,
+            "with_payload": false,
+        });
+        if let Some(filter) = filter {
+            let ids: Vec<_> = filter.iter().collect();
+            body["filter"] = json!({ "must": [{ "has_id": ids }] });
+        }
+        let points: Vec<ScoredPoint> = self
+            .request(reqwest::Method::POST, "/points/search", Some(body))
+            .await?;
+
+        // Qdrant scores dot products where higher is better. Kalosm distances are lower is better like the dot product distance in VectorDB
+        Ok(points
+            .into_iter()
+            .map(|point| VectorDBSearchResult {
+                distance: -point.score,
+                value: EmbeddingId(point.id),
+            })
+            .collect())
+    }
+
+    async fn clear(&self) -> Result<(), Self::Error> {
+        if self.exists().await? {
+            self.request::<serde_json::Value>(reqwest::Method::DELETE, "", None)
+                .await?;
+        }
+        self.created.store(false, Ordering::SeqCst);
+        *self.next_id.lock().unwrap() = Some(0);
+        Ok(())
+    }
+}
+
+#[derive(Deserialize)]
+struct QdrantResponse<T> {
+    result: T,
+}
+
+#[derive(Deserialize)]
+struct QdrantErrorResponse {
+    status: QdrantErrorStatus,
+}
+
+#[derive(Deserialize)]
+struct QdrantErrorStatus {
+    error: String,
+}
+
+#[derive(Deserialize)]
+struct ExistsResult {
+    exists: bool,
+}
+
+#[derive(Deserialize)]
+struct ScrollResult {
+    points: Vec<Point>,
+}
+
+#[derive(Deserialize)]
+struct Point {
+    id: u32,
+    #[serde(default)]
+    vector: Option<Vec<f32>>,
+}
+
+#[derive(Deserialize)]
+struct ScoredPoint {
+    id: u32,
+    score: f32,
+}
+
+/// An error that can occur when interacting with a [`QdrantStore`].
+#[derive(Error, Debug)]
+pub enum QdrantStoreError {
+    /// An error occurred while making a request to the server.
+    #[error("Error making request: {0}")]
+    ReqwestError(#[from] reqwest::Error),
+    /// The server returned an error.
+    #[error("The server returned an error ({status}): {message}")]
+    Server {
+        /// The http status code of the response.
+        status: u16,
+        /// The error message the server returned.
+        message: String,
+    },
+}
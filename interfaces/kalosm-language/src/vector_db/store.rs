@@ -0,0 +1,132 @@
+use std::future::Future;
+
+use arroy::distances::DotProduct;
+use arroy::Writer;
+use kalosm_language_model::Embedding;
+
+use super::{Candidates, EmbeddingId, VectorDB, VectorDBSearchResult, VectorDbError};
+
+/// A store for embeddings that supports nearest neighbor search.
+///
+/// [`VectorDB`] stores embeddings in an embedded database on disk. [`HnswStore`](crate::vector_db::HnswStore) keeps an approximate nearest neighbor graph in memory that stays fast for very large collections. Other stores like [`QdrantStore`](crate::vector_db::QdrantStore) (with the `qdrant` feature) or [`LanceDbStore`](crate::vector_db::LanceDbStore) (with the `lancedb` feature) let indexes grow beyond a single embedded database.
+pub trait VectorStore: Send + Sync + 'static {
+    /// The error type that can occur when interacting with the store.
+    type Error: std::error::Error + Send + Sync + 'static;
+
+    /// Add embeddings to the store and return the new id of each embedding.
+    fn add_embeddings(
+        &self,
+        embeddings: Vec<Embedding>,
+    ) -> impl Future<Output = Result<Vec<EmbeddingId>, Self::Error>> + Send;
+
+    /// Insert embeddings with the given ids, replacing any embeddings that already have those ids.
+    fn upsert_embeddings(
+        &self,
+        embeddings: Vec<(EmbeddingId, Embedding)>,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send;
+
+    /// Remove the embeddings with the given ids from the store.
+    fn remove_embeddings(
+        &self,
+        ids: Vec<EmbeddingId>,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send;
+
+    /// Get the embedding with the given id, if it exists.
+    fn get_embedding(
+        &self,
+        id: EmbeddingId,
+    ) -> impl Future<Output = Result<Option<Embedding>, Self::Error>> + Send;
+
+    /// Find the closest embeddings to the query. If a filter is set, only embeddings with an id in the filter are returned.
+    fn search_embeddings(
+        &self,
+        query: &Embedding,
+        results: usize,
+        filter: Option<Candidates>,
+    ) -> impl Future<Output = Result<Vec<VectorDBSearchResult>, Self::Error>> + Send;
+
+    /// Remove every embedding from the store.
+    fn clear(&self) -> impl Future<Output = Result<(), Self::Error>> + Send;
+
+    /// Make sure every change is written to durable storage. Stores that write changes as they are made don't need to do anything.
+    fn flush(&self) -> impl Future<Output = Result<(), Self::Error>> + Send {
+        async { Ok(()) }
+    }
+}
+
+impl VectorStore for VectorDB {
+    type Error = VectorDbError;
+
+    async fn add_embeddings(
+        &self,
+        embeddings: Vec<Embedding>,
+    ) -> Result<Vec<EmbeddingId>, Self::Error> {
+        VectorDB::add_embeddings(self, embeddings)
+    }
+
+    async fn upsert_embeddings(
+        &self,
+        embeddings: Vec<(EmbeddingId, Embedding)>,
+    ) -> Result<(), Self::Error> {
+        let Some((_, first)) = embeddings.first() else {
+            return Ok(());
+        };
+        let dims = first.vector().len();
+        self.set_dim(dims);
+
+        let mut wtxn = self.env.write_txn()?;
+        let mut writer = Writer::<DotProduct>::new(self.database, 0, dims);
+        for (id, embedding) in &embeddings {
+            self.reserve_id(*id, &mut wtxn)?;
+            writer.add_item(&mut wtxn, id.0, embedding.vector())?;
+        }
+        self.rebuild(&mut writer, &mut wtxn)?;
+        wtxn.commit()?;
+
+        Ok(())
+    }
+
+    async fn remove_embeddings(&self, ids: Vec<EmbeddingId>) -> Result<(), Self::Error> {
+        if ids.is_empty() {
+            return Ok(());
+        }
+        let dims = self.get_dim()?;
+
+        let mut wtxn = self.env.write_txn()?;
+        let mut writer = Writer::<DotProduct>::new(self.database, 0, dims);
+        for id in ids {
+            if writer.del_item(&mut wtxn, id.0)? {
+                self.recycle_id(id, &mut wtxn)?;
+            }
+        }
+        self.rebuild(&mut writer, &mut wtxn)?;
+        wtxn.commit()?;
+
+        Ok(())
+    }
+
+    async fn get_embedding(&self, id: EmbeddingId) -> Result<Option<Embedding>, Self::Error> {
+        match VectorDB::get_embedding(self, id) {
+            Ok(embedding) => Ok(Some(embedding)),
+            Err(VectorDbError::EmbeddingNotFound(_)) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    async fn search_embeddings(
+        &self,
+        query: &Embedding,
+        results: usize,
+        filter: Option<Candidates>,
+    ) -> Result<Vec<VectorDBSearchResult>, Self::Error> {
+        let mut search = self.search(query).with_results(results);
+        if let Some(filter) = filter {
+            search = search.with_filter(filter);
+        }
+        search.run()
+    }
+
+    async fn clear(&self) -> Result<(), Self::Error> {
+        Ok(VectorDB::clear(self).await?)
+    }
+}
@@ -0,0 +1,316 @@
+use rand::prelude::SliceRandom;
+use std::{collections::HashMap, sync::OnceLock};
+
+use candle_core::{
+    safetensors::{self, Load},
+    DType, Device, Result, Tensor, Var,
+};
+use candle_nn::{Dropout, Linear, Module, ModuleT, Optimizer, VarBuilder, VarMap};
+use kalosm_common::maybe_autoreleasepool;
+
+/// Tensors with this prefix store the configuration of the head instead of trainable weights.
+const CONFIG_PREFIX: &str = "__config.";
+
+/// The kind of model a [`MlpHead`] is used for. This is saved with the weights so a head
+/// can't accidentally be loaded as the wrong kind of model.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum HeadKind {
+    Classifier,
+    MultiLabelClassifier,
+    Regressor,
+}
+
+impl HeadKind {
+    fn to_u32(self) -> u32 {
+        match self {
+            HeadKind::Classifier => 0,
+            HeadKind::MultiLabelClassifier => 1,
+            HeadKind::Regressor => 2,
+        }
+    }
+
+    fn from_u32(kind: u32) -> Result<Self> {
+        match kind {
+            0 => Ok(HeadKind::Classifier),
+            1 => Ok(HeadKind::MultiLabelClassifier),
+            2 => Ok(HeadKind::Regressor),
+            _ => Err(candle_core::Error::Msg(format!("Unknown head kind {kind}"))),
+        }
+    }
+}
+
+/// An event emitted while fitting a [`MlpHead`].
+pub(crate) enum FitEvent {
+    BatchFinished { batch: usize, loss: f32 },
+    EpochFinished { epoch: usize },
+}
+
+/// The config of a head that was saved alongside the weights.
+#[derive(Debug, Clone)]
+pub(crate) struct SavedHeadConfig {
+    pub(crate) layers_dims: Vec<usize>,
+    pub(crate) dropout_rate: f32,
+    pub(crate) outputs: u32,
+}
+
+/// A small multi-layer perceptron that sits on top of embeddings. The classifier, multi-label classifier
+/// and regressor all share this head and only differ in how the outputs are interpreted.
+pub(crate) struct MlpHead {
+    device: Device,
+    varmap: VarMap,
+    layers_dims: Vec<usize>,
+    layers: OnceLock<Vec<Linear>>,
+    dropout: Dropout,
+    dropout_rate: f32,
+    outputs: u32,
+    kind: HeadKind,
+}
+
+impl MlpHead {
+    pub(crate) fn new(
+        device: Device,
+        varmap: VarMap,
+        layers_dims: Vec<usize>,
+        dropout_rate: f32,
+        outputs: u32,
+        kind: HeadKind,
+    ) -> Self {
+        Self {
+            device,
+            varmap,
+            layers_dims,
+            layers: OnceLock::new(),
+            dropout: Dropout::new(dropout_rate),
+            dropout_rate,
+            outputs,
+            kind,
+        }
+    }
+
+    pub(crate) fn device(&self) -> &Device {
+        &self.device
+    }
+
+    pub(crate) fn layers_dims(&self) -> &[usize] {
+        &self.layers_dims
+    }
+
+    pub(crate) fn dropout_rate(&self) -> f32 {
+        self.dropout_rate
+    }
+
+    pub(crate) fn outputs(&self) -> u32 {
+        self.outputs
+    }
+
+    fn layers(&self, input_dim: usize) -> Result<&Vec<Linear>> {
+        if let Some(layers) = self.layers.get() {
+            return Ok(layers);
+        }
+        let vs = VarBuilder::from_varmap(&self.varmap, DType::F32, &self.device);
+        let output_dim = self.outputs;
+        let mut layers = Vec::with_capacity(self.layers_dims.len() + 1);
+        if self.layers_dims.is_empty() {
+            let layer = candle_nn::linear(input_dim, output_dim as usize, vs.pp("ln0"))?;
+            layers.push(layer);
+        } else {
+            layers.push(candle_nn::linear(
+                input_dim,
+                *self.layers_dims.first().unwrap(),
+                vs.pp("ln0"),
+            )?);
+            for (i, (in_dim, out_dim)) in self
+                .layers_dims
+                .iter()
+                .zip(self.layers_dims.iter().skip(1))
+                .enumerate()
+            {
+                layers.push(candle_nn::linear(
+                    *in_dim,
+                    *out_dim,
+                    vs.pp(format!("ln{}", i + 1)),
+                )?);
+            }
+            layers.push(candle_nn::linear(
+                *self.layers_dims.last().unwrap(),
+                output_dim as usize,
+                vs.pp(format!("ln{}", self.layers_dims.len() + 1)),
+            )?);
+        }
+
+        Ok(self.layers.get_or_init(|| layers))
+    }
+
+    pub(crate) fn forward_t(&self, xs: &Tensor, train: bool) -> Result<Tensor> {
+        let mut xs = xs.clone();
+        let input_dim = *xs.dims().last().unwrap();
+        let layers = self.layers(input_dim)?;
+        let last = layers.len() - 1;
+        for (i, layer) in layers.iter().enumerate() {
+            xs = self.dropout.forward_t(&xs, train)?;
+            xs = layer.forward(&xs)?;
+            // The single label classifier has always applied the activation to the logits. The
+            // multi-label and regression heads need the raw output of the last layer to represent
+            // negative values
+            if i != last || self.kind == HeadKind::Classifier {
+                xs = xs.gelu_erf()?;
+            }
+        }
+        Ok(xs)
+    }
+
+    /// Run a training loop over the inputs and targets. The loss function receives the output of the
+    /// head and the targets for each batch.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn fit(
+        &self,
+        inputs: &Tensor,
+        targets: &Tensor,
+        epochs: usize,
+        learning_rate: f64,
+        batch_size: usize,
+        mut loss: impl FnMut(&Tensor, &Tensor) -> Result<Tensor>,
+        mut event: impl FnMut(FitEvent) -> Result<()>,
+    ) -> Result<()> {
+        let train_len = inputs.dims()[0];
+        if train_len == 0 {
+            return Err(candle_core::Error::Msg(
+                "The dataset doesn't contain any training examples".to_string(),
+            ));
+        }
+        let inputs = inputs.to_device(&self.device)?;
+        let targets = targets.to_device(&self.device)?;
+
+        // Force the layers to be initialized before we use the varmap
+        self.forward_t(&inputs.narrow(0, 0, 1)?, true)?;
+
+        let mut optimizer = candle_nn::AdamW::new_lr(self.varmap.all_vars(), learning_rate)?;
+        let mut rng = rand::thread_rng();
+        let mut batch = 0;
+        for epoch in 1..epochs + 1 {
+            // create a random batch of indices
+            let mut indices = (0..train_len as u32).collect::<Vec<_>>();
+            indices.shuffle(&mut rng);
+            maybe_autoreleasepool(|| {
+                for indices in indices.chunks(batch_size) {
+                    let indices = Tensor::new(indices, &self.device)?;
+                    let batch_inputs = inputs.index_select(&indices, 0)?;
+                    let batch_targets = targets.index_select(&indices, 0)?;
+
+                    let output = self.forward_t(&batch_inputs, true)?;
+                    let loss = loss(&output, &batch_targets)?;
+                    optimizer.backward_step(&loss)?;
+                    event(FitEvent::BatchFinished {
+                        batch,
+                        loss: loss.to_scalar::<f32>()?,
+                    })?;
+                    batch += 1;
+                }
+                event(FitEvent::EpochFinished { epoch })
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Save the weights of the head along with the config required to load it again.
+    pub(crate) fn save(&self, path: impl AsRef<std::path::Path>) -> Result<()> {
+        let mut tensors: HashMap<String, Tensor> = {
+            let data = self.varmap.data().lock().unwrap();
+            data.iter()
+                .map(|(name, var)| (name.clone(), var.as_tensor().clone()))
+                .collect()
+        };
+        let layers_dims = self
+            .layers_dims
+            .iter()
+            .map(|dim| *dim as u32)
+            .collect::<Vec<_>>();
+        let layers_dims_len = layers_dims.len();
+        tensors.insert(
+            format!("{CONFIG_PREFIX}kind"),
+            Tensor::new(&[self.kind.to_u32()], &Device::Cpu)?,
+        );
+        tensors.insert(
+            format!("{CONFIG_PREFIX}layers_dims"),
+            Tensor::from_vec(layers_dims, layers_dims_len, &Device::Cpu)?,
+        );
+        tensors.insert(
+            format!("{CONFIG_PREFIX}dropout_rate"),
+            Tensor::new(&[self.dropout_rate], &Device::Cpu)?,
+        );
+        tensors.insert(
+            format!("{CONFIG_PREFIX}outputs"),
+            Tensor::new(&[self.outputs], &Device::Cpu)?,
+        );
+        safetensors::save(&tensors, path)
+    }
+
+    /// Load the weights of a head from a file along with the config that was saved with the weights if it exists.
+    pub(crate) fn load_weights(
+        path: impl AsRef<std::path::Path>,
+        dev: &Device,
+        kind: HeadKind,
+    ) -> Result<(VarMap, Option<SavedHeadConfig>)> {
+        let varmap = VarMap::new();
+        let mut config = HashMap::new();
+        {
+            let safetensors = unsafe { candle_core::safetensors::MmapedSafetensors::new(path) }?;
+            let tensors = safetensors.tensors();
+            let mut tensor_data = varmap.data().lock().unwrap();
+            for (name, value) in tensors {
+                if let Some(key) = name.strip_prefix(CONFIG_PREFIX) {
+                    config.insert(key.to_string(), value.load(&Device::Cpu)?);
+                    continue;
+                }
+                let tensor = value.load(dev)?;
+                tensor_data.insert(name.to_string(), Var::from_tensor(&tensor)?);
+            }
+        }
+
+        if config.is_empty() {
+            return Ok((varmap, None));
+        }
+
+        let mut read = |key: &str| {
+            config.remove(key).ok_or_else(|| {
+                candle_core::Error::Msg(format!("Saved head is missing the {key} config"))
+            })
+        };
+        let saved_kind = HeadKind::from_u32(read("kind")?.to_vec1::<u32>()?[0])?;
+        if saved_kind != kind {
+            return Err(candle_core::Error::Msg(format!(
+                "Expected a saved {kind:?} head, but found a {saved_kind:?} head"
+            )));
+        }
+        let layers_dims = read("layers_dims")?
+            .to_vec1::<u32>()?
+            .into_iter()
+            .map(|dim| dim as usize)
+            .collect();
+        let dropout_rate = read("dropout_rate")?.to_vec1::<f32>()?[0];
+        let outputs = read("outputs")?.to_vec1::<u32>()?[0];
+
+        Ok((
+            varmap,
+            Some(SavedHeadConfig {
+                layers_dims,
+                dropout_rate,
+                outputs,
+            }),
+        ))
+    }
+}
+
+/// Choose which examples go in the test set. Roughly 1/4 of the examples are used for testing.
+pub(crate) fn test_split(len: usize) -> Result<Vec<bool>> {
+    // Both the train and test set need at least one example
+    if len < 2 {
+        return Err(candle_core::Error::Msg(format!(
+            "At least 2 examples are required to split the dataset into a train and test set, but only {len} were added"
+        )));
+    }
+    let test_len = 1.max(len / 4);
+    let mut is_test = (0..len).map(|i| i < test_len).collect::<Vec<_>>();
+    is_test.shuffle(&mut rand::thread_rng());
+    Ok(is_test)
+}
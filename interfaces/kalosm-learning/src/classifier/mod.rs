@@ -1,4 +1,9 @@
+mod head;
 mod model;
 pub use model::*;
+mod multi_label;
+pub use multi_label::*;
+mod regression;
+pub use regression::*;
 mod text_classifier;
 pub use text_classifier::*;
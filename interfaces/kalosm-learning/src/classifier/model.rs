@@ -1,14 +1,11 @@
-use rand::prelude::SliceRandom;
-use std::{collections::HashMap, sync::OnceLock};
-
-use candle_core::{
-    safetensors::{self, Load},
-    DType, Device, Result, Tensor, Var, D,
-};
-use candle_nn::{loss, ops, Dropout, Linear, Module, ModuleT, Optimizer, VarBuilder, VarMap};
-use kalosm_common::maybe_autoreleasepool;
+use std::collections::HashMap;
+
+use candle_core::{safetensors, DType, Device, Result, Tensor, D};
+use candle_nn::{loss, ops, VarMap};
 use rand::Rng;
 
+use super::head::{FitEvent, HeadKind, MlpHead};
+
 /// A class that a [`Classifier`] can predict. You can derive this trait for any enum with only unit values.
 ///
 /// # Example
@@ -78,6 +75,37 @@ impl ClassificationDataset {
         Ok(())
     }
 
+    /// Compute class weights inversely proportional to how often each class appears in the training set. The
+    /// result can be passed to [`ClassifierConfig::class_weights`] to train on an imbalanced dataset.
+    ///
+    /// # Example
+    /// ```rust, no_run
+    /// # use kalosm_learning::*;
+    /// let dev = candle_core::Device::Cpu;
+    /// let dataset = ClassificationDataset::load("dataset.safetensors", &dev).unwrap();
+    /// let config = ClassifierConfig::new().class_weights(dataset.balanced_class_weights(2).unwrap());
+    /// ```
+    pub fn balanced_class_weights(&self, classes: u32) -> Result<Vec<f32>> {
+        let train_classes = self.train_classes.to_vec1::<u32>()?;
+        let mut counts = vec![0usize; classes as usize];
+        for class in &train_classes {
+            if let Some(count) = counts.get_mut(*class as usize) {
+                *count += 1;
+            }
+        }
+        let total = train_classes.len() as f32;
+        Ok(counts
+            .into_iter()
+            .map(|count| {
+                if count == 0 {
+                    1.0
+                } else {
+                    total / (classes as f32 * count as f32)
+                }
+            })
+            .collect())
+    }
+
     /// Load the dataset from the given path.
     ///
     /// # Example
@@ -230,13 +258,8 @@ impl<C: Class> ClassificationDatasetBuilder<C> {
 
 /// A classifier.
 pub struct Classifier<C: Class> {
-    device: Device,
-    varmap: VarMap,
-    layers_dims: Vec<usize>,
-    layers: OnceLock<Vec<Linear>>,
-    dropout: Dropout,
-    dropout_rate: f32,
-    classes: u32,
+    head: MlpHead,
+    class_weights: Option<Vec<f32>>,
     phantom: std::marker::PhantomData<C>,
 }
 
@@ -264,9 +287,10 @@ impl<C: Class> Classifier<C> {
     /// Get the config of the classifier.
     pub fn config(&self) -> ClassifierConfig {
         ClassifierConfig {
-            layers_dims: self.layers_dims.clone(),
-            dropout_rate: self.dropout_rate,
-            classes: Some(self.classes),
+            layers_dims: self.head.layers_dims().to_vec(),
+            dropout_rate: self.head.dropout_rate(),
+            classes: Some(self.head.outputs()),
+            class_weights: self.class_weights.clone(),
         }
     }
 
@@ -275,70 +299,33 @@ impl<C: Class> Classifier<C> {
             layers_dims,
             dropout_rate,
             classes,
+            class_weights,
         } = config;
+        let classes = classes.or(C::CLASSES).ok_or_else(|| {
+            candle_core::Error::Msg("No number of classes specified for classifier".to_string())
+        })?;
+        if let Some(class_weights) = &class_weights {
+            if class_weights.len() != classes as usize {
+                return Err(candle_core::Error::Msg(format!(
+                    "Expected {classes} class weights, but found {}",
+                    class_weights.len()
+                )));
+            }
+        }
         Ok(Self {
-            device: dev,
-            layers: OnceLock::new(),
-            layers_dims,
-            varmap,
-            dropout: Dropout::new(dropout_rate),
-            dropout_rate,
-            classes: classes.or(C::CLASSES).ok_or_else(|| {
-                candle_core::Error::Msg("No number of classes specified for classifier".to_string())
-            })?,
+            head: MlpHead::new(
+                dev,
+                varmap,
+                layers_dims,
+                dropout_rate,
+                classes,
+                HeadKind::Classifier,
+            ),
+            class_weights,
             phantom: std::marker::PhantomData,
         })
     }
 
-    fn layers(&self, input_dim: usize) -> candle_core::Result<&Vec<Linear>> {
-        if let Some(layers) = self.layers.get() {
-            return Ok(layers);
-        }
-        let vs = VarBuilder::from_varmap(&self.varmap, DType::F32, &self.device);
-        let output_dim = self.classes;
-        let mut layers = Vec::with_capacity(self.layers_dims.len() + 1);
-        if self.layers_dims.is_empty() {
-            let layer = candle_nn::linear(input_dim, output_dim as usize, vs.pp("ln0"))?;
-            layers.push(layer);
-        } else {
-            layers.push(candle_nn::linear(
-                input_dim,
-                *self.layers_dims.first().unwrap(),
-                vs.pp("ln0"),
-            )?);
-            for (i, (in_dim, out_dim)) in self
-                .layers_dims
-                .iter()
-                .zip(self.layers_dims.iter().skip(1))
-                .enumerate()
-            {
-                layers.push(candle_nn::linear(
-                    *in_dim,
-                    *out_dim,
-                    vs.pp(format!("ln{}", i + 1)),
-                )?);
-            }
-            layers.push(candle_nn::linear(
-                *self.layers_dims.last().unwrap(),
-                output_dim as usize,
-                vs.pp(format!("ln{}", self.layers_dims.len() + 1)),
-            )?);
-        }
-
-        Ok(self.layers.get_or_init(|| layers))
-    }
-
-    fn forward_t(&self, xs: &Tensor, train: bool) -> Result<Tensor> {
-        let mut xs = xs.clone();
-        let input_dim = *xs.dims().last().unwrap();
-        for layer in self.layers(input_dim)? {
-            xs = self.dropout.forward_t(&xs, train)?;
-            xs = layer.forward(&xs)?;
-            xs = xs.gelu_erf()?;
-        }
-        Ok(xs)
-    }
-
     /// Train the model on the given dataset.
     ///
     /// # Example
@@ -369,80 +356,62 @@ impl<C: Class> Classifier<C> {
         batch_size: usize,
         mut progress: impl FnMut(ClassifierProgress),
     ) -> Result<f32> {
-        // unstack both tensors into a list of tensors
-        let train_len = m.train_inputs.dims()[0];
-        let train_results = m.train_classes.chunk(train_len, 0)?;
-        let train_votes = m.train_inputs.chunk(train_len, 0)?;
-
-        // Force the layers to be initialized before we use the varmap
-        self.forward_t(&train_votes[0].to_device(&self.device)?, true)?;
-
-        let mut sgd = candle_nn::AdamW::new_lr(self.varmap.all_vars(), learning_rate)?;
-        let test_votes = m.test_inputs.to_device(&self.device)?;
-        let test_results = m.test_classes.to_device(&self.device)?;
+        let device = self.head.device();
+        let test_votes = m.test_inputs.to_device(device)?;
+        let test_results = m.test_classes.to_device(device)?;
+        let class_weights = self
+            .class_weights
+            .as_ref()
+            .map(|weights| Tensor::new(weights.as_slice(), device))
+            .transpose()?;
         let mut final_accuracy: f32 = 0.0;
-        let mut rng = rand::thread_rng();
-        let mut batch = 0;
-        for epoch in 1..epochs + 1 {
-            // create a random batch of indices
-            let mut indices = (0..train_len).collect::<Vec<_>>();
-            indices.shuffle(&mut rng);
-            maybe_autoreleasepool(|| {
-                for indices in indices.chunks(batch_size) {
-                    let train_results = Tensor::cat(
-                        &indices
-                            .iter()
-                            .copied()
-                            .map(|i| train_results[i].clone())
-                            .collect::<Vec<_>>(),
-                        0,
-                    )?
-                    .to_device(&self.device)?;
-                    let train_votes = Tensor::cat(
-                        &indices
-                            .iter()
-                            .copied()
-                            .map(|i| train_votes[i].clone())
-                            .collect::<Vec<_>>(),
-                        0,
-                    )?
-                    .to_device(&self.device)?;
-
-                    let logits = self.forward_t(&train_votes, true)?;
-                    let log_sm = ops::log_softmax(&logits, D::Minus1)?;
-                    let loss = loss::nll(&log_sm, &train_results)?;
-                    sgd.backward_step(&loss)?;
-                    progress(ClassifierProgress::BatchFinished {
-                        batch,
-                        loss: loss.to_scalar::<f32>()?,
-                    });
-                    batch += 1;
+        self.head.fit(
+            &m.train_inputs,
+            &m.train_classes,
+            epochs,
+            learning_rate,
+            batch_size,
+            |logits, classes| {
+                let log_sm = ops::log_softmax(logits, D::Minus1)?;
+                match &class_weights {
+                    Some(class_weights) => weighted_nll(&log_sm, classes, class_weights),
+                    None => loss::nll(&log_sm, classes),
                 }
-                let test_logits = self.forward_t(&test_votes, false)?;
-                let test_cases_passed = test_logits
-                    .argmax(D::Minus1)?
-                    .eq(&test_results)?
-                    .to_dtype(DType::U32)?
-                    .sum_all()?
-                    .to_scalar::<u32>()?;
-                let test_cases = test_results.dims1()?;
-                let test_accuracy: f32 = test_cases_passed as f32 / test_cases as f32;
-                final_accuracy = f32::from(100u8) * test_accuracy;
-                progress(ClassifierProgress::EpochFinished {
-                    epoch,
-                    accuracy: test_accuracy,
-                });
-                println!(
-                    "Epoch: {epoch:5} Test accuracy: {:5.5}% ({}/{})",
-                    final_accuracy, test_cases_passed, test_cases,
-                );
-                Ok::<_, candle_core::Error>(())
-            })?;
-        }
+            },
+            |event| {
+                match event {
+                    FitEvent::BatchFinished { batch, loss } => {
+                        progress(ClassifierProgress::BatchFinished { batch, loss });
+                    }
+                    FitEvent::EpochFinished { epoch } => {
+                        let test_logits = self.head.forward_t(&test_votes, false)?;
+                        let test_cases_passed = test_logits
+                            .argmax(D::Minus1)?
+                            .eq(&test_results)?
+                            .to_dtype(DType::U32)?
+                            .sum_all()?
+                            .to_scalar::<u32>()?;
+                        let test_cases = test_results.dims1()?;
+                        let test_accuracy: f32 = test_cases_passed as f32 / test_cases as f32;
+                        final_accuracy = f32::from(100u8) * test_accuracy;
+                        progress(ClassifierProgress::EpochFinished {
+                            epoch,
+                            accuracy: test_accuracy,
+                        });
+                        println!(
+                            "Epoch: {epoch:5} Test accuracy: {:5.5}% ({}/{})",
+                            final_accuracy, test_cases_passed, test_cases,
+                        );
+                    }
+                }
+                Ok(())
+            },
+        )?;
         Ok(final_accuracy)
     }
 
-    /// Save the model to a safetensors file at the given path.
+    /// Save the model to a safetensors file at the given path. The config of the classifier is saved
+    /// alongside the weights so the classifier can be loaded again with [`Classifier::load_saved`].
     ///
     /// # Example
     ///
@@ -460,7 +429,7 @@ impl<C: Class> Classifier<C> {
     /// classifier.save("classifier.safetensors").unwrap();
     /// ```
     pub fn save(&self, path: impl AsRef<std::path::Path>) -> Result<()> {
-        self.varmap.save(path)
+        self.head.save(path)
     }
 
     /// Load the model from a safetensors file at the given path.
@@ -486,19 +455,45 @@ impl<C: Class> Classifier<C> {
         dev: &Device,
         config: ClassifierConfig,
     ) -> Result<Self> {
-        let varmap = VarMap::new();
-        {
-            let safetensors = unsafe { candle_core::safetensors::MmapedSafetensors::new(path) }?;
-            let tensors = safetensors.tensors();
-            let mut tensor_data = varmap.data().lock().unwrap();
-            for (name, value) in tensors {
-                let tensor = value.load(dev)?;
-                tensor_data.insert(name.to_string(), Var::from_tensor(&tensor)?);
-            }
-        }
+        let (varmap, _) = MlpHead::load_weights(path, dev, HeadKind::Classifier)?;
         Self::new_inner(dev.clone(), varmap, config)
     }
 
+    /// Load a model that was saved with [`Classifier::save`] using the config stored in the file.
+    ///
+    /// # Example
+    ///
+    /// ```rust, no_run
+    /// use kalosm_learning::{Class, Classifier};
+    ///
+    /// #[derive(Debug, Clone, Copy, Class)]
+    /// enum MyClass {
+    ///     Person,
+    ///     Thing,
+    /// }
+    ///
+    /// let dev = candle_core::Device::Cpu;
+    /// let classifier = Classifier::<MyClass>::load_saved("classifier.safetensors", &dev).unwrap();
+    /// ```
+    pub fn load_saved(path: impl AsRef<std::path::Path>, dev: &Device) -> Result<Self> {
+        let (varmap, config) = MlpHead::load_weights(path, dev, HeadKind::Classifier)?;
+        let config = config.ok_or_else(|| {
+            candle_core::Error::Msg(
+                "The file doesn't contain a saved config. Use Classifier::load instead".to_string(),
+            )
+        })?;
+        Self::new_inner(
+            dev.clone(),
+            varmap,
+            ClassifierConfig {
+                layers_dims: config.layers_dims,
+                dropout_rate: config.dropout_rate,
+                classes: Some(config.outputs),
+                class_weights: None,
+            },
+        )
+    }
+
     /// Run the model on the given input.
     ///
     /// # Example
@@ -518,8 +513,8 @@ impl<C: Class> Classifier<C> {
     /// println!("Result: {:?}", result);
     /// ```
     pub fn run(&self, input: &[f32]) -> Result<ClassifierOutput<C>> {
-        let input = Tensor::from_vec(input.to_vec(), (1, input.len()), &self.device)?;
-        let logits = self.forward_t(&input, false)?;
+        let input = Tensor::from_vec(input.to_vec(), (1, input.len()), self.head.device())?;
+        let logits = self.head.forward_t(&input, false)?;
         let classes = logits.flatten_all()?;
         let classes = ops::softmax(&classes, D::Minus1)?;
         let classes = classes.to_vec1()?;
@@ -533,6 +528,17 @@ impl<C: Class> Classifier<C> {
     }
 }
 
+/// The negative log likelihood loss with each example weighted by the weight of its class.
+fn weighted_nll(log_sm: &Tensor, classes: &Tensor, class_weights: &Tensor) -> Result<Tensor> {
+    let picked = log_sm.gather(&classes.unsqueeze(1)?, 1)?.squeeze(1)?;
+    let weights = class_weights.index_select(classes, 0)?;
+    let total_weight = weights.sum_all()?;
+    (picked * weights)?
+        .sum_all()?
+        .neg()?
+        .broadcast_div(&total_weight)
+}
+
 /// The output of a classifier.
 #[derive(Debug, Clone)]
 pub struct ClassifierOutput<C: Class> {
@@ -582,11 +588,13 @@ pub enum ClassifierProgress {
 /// A config for a [`Classifier`].
 pub struct ClassifierConfig {
     /// The dimensions of the layers.
-    layers_dims: Vec<usize>,
+    pub(crate) layers_dims: Vec<usize>,
     /// The dropout rate.
-    dropout_rate: f32,
+    pub(crate) dropout_rate: f32,
     /// The number of classes.
-    classes: Option<u32>,
+    pub(crate) classes: Option<u32>,
+    /// The weight of each class in the loss function.
+    pub(crate) class_weights: Option<Vec<f32>>,
 }
 
 impl Default for ClassifierConfig {
@@ -602,6 +610,7 @@ impl ClassifierConfig {
             layers_dims: vec![4, 8, 4],
             dropout_rate: 0.1,
             classes: None,
+            class_weights: None,
         }
     }
 
@@ -622,4 +631,11 @@ impl ClassifierConfig {
         self.classes = Some(classes);
         self
     }
+
+    /// Set the weight of each class in the loss function. Giving rare classes a larger weight helps the classifier
+    /// learn imbalanced datasets. [`ClassificationDataset::balanced_class_weights`] can compute weights that balance every class in a dataset.
+    pub fn class_weights(mut self, class_weights: impl IntoIterator<Item = f32>) -> Self {
+        self.class_weights = Some(class_weights.into_iter().collect());
+        self
+    }
 }
@@ -0,0 +1,412 @@
+use std::collections::HashMap;
+
+use candle_core::{safetensors, DType, Device, Result, Tensor};
+use candle_nn::{ops, VarMap};
+
+use super::head::{test_split, FitEvent, HeadKind, MlpHead};
+use crate::{Class, ClassifierConfig, ClassifierProgress};
+
+/// A dataset to train a [`MultiLabelClassifier`]. Each example can belong to any number of classes.
+#[derive(Clone, Debug)]
+pub struct MultiLabelClassificationDataset {
+    train_inputs: Tensor,
+    train_labels: Tensor,
+    test_inputs: Tensor,
+    test_labels: Tensor,
+}
+
+impl MultiLabelClassificationDataset {
+    /// Create a builder for a multi-label classification dataset.
+    pub fn builder<C: Class>() -> MultiLabelClassificationDatasetBuilder<C> {
+        MultiLabelClassificationDatasetBuilder::default()
+    }
+
+    /// Save the dataset to the given path.
+    pub fn save<P: AsRef<std::path::Path>>(&self, path: P) -> Result<()> {
+        let safetensors = HashMap::from([
+            ("train_inputs".to_string(), self.train_inputs.clone()),
+            ("train_labels".to_string(), self.train_labels.clone()),
+            ("test_inputs".to_string(), self.test_inputs.clone()),
+            ("test_labels".to_string(), self.test_labels.clone()),
+        ]);
+
+        safetensors::save(&safetensors, path)?;
+        Ok(())
+    }
+
+    /// Load the dataset from the given path.
+    pub fn load<P: AsRef<std::path::Path>>(path: P, dev: &Device) -> Result<Self> {
+        let mut safetensors = safetensors::load(path, dev)?;
+        Ok(Self {
+            train_inputs: safetensors.remove("train_inputs").unwrap(),
+            train_labels: safetensors.remove("train_labels").unwrap(),
+            test_inputs: safetensors.remove("test_inputs").unwrap(),
+            test_labels: safetensors.remove("test_labels").unwrap(),
+        })
+    }
+
+    /// Compute per-class weights inversely proportional to how often each class appears in the training set. The
+    /// result can be passed to [`ClassifierConfig::class_weights`] to boost rare labels.
+    pub fn balanced_class_weights(&self) -> Result<Vec<f32>> {
+        let (examples, classes) = self.train_labels.dims2()?;
+        let counts = self.train_labels.sum(0)?.to_vec1::<f32>()?;
+        Ok(counts
+            .into_iter()
+            .map(|count| {
+                if count == 0. {
+                    1.0
+                } else {
+                    examples as f32 / (classes as f32 * count)
+                }
+            })
+            .collect())
+    }
+}
+
+/// A builder for [`MultiLabelClassificationDataset`].
+pub struct MultiLabelClassificationDatasetBuilder<C: Class> {
+    input_size: Option<usize>,
+    classes: Option<u32>,
+    inputs: Vec<Box<[f32]>>,
+    labels: Vec<Vec<C>>,
+}
+
+impl<C: Class> Default for MultiLabelClassificationDatasetBuilder<C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C: Class> MultiLabelClassificationDatasetBuilder<C> {
+    /// Create a new dataset builder.
+    pub fn new() -> Self {
+        Self {
+            input_size: None,
+            classes: C::CLASSES,
+            inputs: Vec::new(),
+            labels: Vec::new(),
+        }
+    }
+
+    /// Set the number of classes. This is required if [`Class::CLASSES`] is not defined for the type you are classifying.
+    pub fn with_classes(mut self, classes: u32) -> Self {
+        self.classes = Some(classes);
+        self
+    }
+
+    /// Adds an input with all of the classes it belongs to.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use kalosm_learning::*;
+    /// # #[derive(Debug, Clone, Copy, Class)]
+    /// # enum Topic {
+    /// #     Sports,
+    /// #     Politics,
+    /// # }
+    /// let mut dataset = MultiLabelClassificationDatasetBuilder::new();
+    /// dataset.add(vec![1.0, 2.0, 3.0, 4.0], [Topic::Sports]);
+    /// dataset.add(vec![4.0, 3.0, 2.0, 1.0], [Topic::Sports, Topic::Politics]);
+    /// ```
+    pub fn add(&mut self, input: impl Into<Box<[f32]>>, labels: impl IntoIterator<Item = C>) {
+        let input = input.into();
+        if let Some(input_size) = self.input_size {
+            debug_assert_eq!(input.len(), input_size, "input size mismatch");
+        } else {
+            self.input_size = Some(input.len());
+        }
+        self.inputs.push(input);
+        self.labels.push(labels.into_iter().collect());
+    }
+
+    /// Builds the dataset and copies the data to the device passed in.
+    pub fn build(self, dev: &Device) -> Result<MultiLabelClassificationDataset> {
+        let classes = self.classes.ok_or_else(|| {
+            candle_core::Error::Msg("No number of classes specified for dataset".to_string())
+        })? as usize;
+        let input_size = self.input_size.unwrap_or_default();
+
+        let mut train_inputs = Vec::new();
+        let mut train_labels = Vec::new();
+        let mut test_inputs = Vec::new();
+        let mut test_labels = Vec::new();
+        let split = test_split(self.inputs.len())?;
+        for ((input, labels), is_test) in self.inputs.iter().zip(&self.labels).zip(split) {
+            let mut row = vec![0f32; classes];
+            for label in labels {
+                let class = label.to_class() as usize;
+                if class >= classes {
+                    return Err(candle_core::Error::Msg(format!(
+                        "Found a label for class {class}, but the dataset only has {classes} classes"
+                    )));
+                }
+                row[class] = 1.;
+            }
+            if is_test {
+                test_inputs.extend_from_slice(input);
+                test_labels.extend(row);
+            } else {
+                train_inputs.extend_from_slice(input);
+                train_labels.extend(row);
+            }
+        }
+
+        let train_len = train_labels.len() / classes;
+        let test_len = test_labels.len() / classes;
+        Ok(MultiLabelClassificationDataset {
+            train_inputs: Tensor::from_vec(train_inputs, (train_len, input_size), dev)?,
+            train_labels: Tensor::from_vec(train_labels, (train_len, classes), dev)?,
+            test_inputs: Tensor::from_vec(test_inputs, (test_len, input_size), dev)?,
+            test_labels: Tensor::from_vec(test_labels, (test_len, classes), dev)?,
+        })
+    }
+}
+
+/// A classifier that predicts an independent probability for every class. Unlike [`crate::Classifier`], the
+/// probabilities don't need to sum to one, so an input can belong to many (or none) of the classes.
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm_learning::*;
+///
+/// #[derive(Debug, Clone, Copy, Class)]
+/// enum Topic {
+///     Sports,
+///     Politics,
+/// }
+///
+/// let dev = candle_core::Device::Cpu;
+/// let classifier = MultiLabelClassifier::<Topic>::new(&dev, ClassifierConfig::new()).unwrap();
+/// let mut dataset = MultiLabelClassificationDatasetBuilder::new();
+/// dataset.add(vec![1.0, 2.0, 3.0, 4.0], [Topic::Sports]);
+/// dataset.add(vec![4.0, 3.0, 2.0, 1.0], [Topic::Sports, Topic::Politics]);
+/// classifier
+///     .train(&dataset.build(&dev).unwrap(), 20, 0.05, 3, |_| {})
+///     .unwrap();
+/// let output = classifier.run(&[1.0, 2.0, 3.0, 4.0]).unwrap();
+/// println!("{:?}", output.labels(0.5));
+/// ```
+pub struct MultiLabelClassifier<C: Class> {
+    head: MlpHead,
+    class_weights: Option<Vec<f32>>,
+    phantom: std::marker::PhantomData<C>,
+}
+
+impl<C: Class> MultiLabelClassifier<C> {
+    /// Create a new multi-label classifier.
+    pub fn new(dev: &Device, config: ClassifierConfig) -> Result<Self> {
+        Self::new_inner(dev.clone(), VarMap::new(), config)
+    }
+
+    fn new_inner(dev: Device, varmap: VarMap, config: ClassifierConfig) -> Result<Self> {
+        let ClassifierConfig {
+            layers_dims,
+            dropout_rate,
+            classes,
+            class_weights,
+        } = config;
+        let classes = classes.or(C::CLASSES).ok_or_else(|| {
+            candle_core::Error::Msg("No number of classes specified for classifier".to_string())
+        })?;
+        if let Some(class_weights) = &class_weights {
+            if class_weights.len() != classes as usize {
+                return Err(candle_core::Error::Msg(format!(
+                    "Expected {classes} class weights, but found {}",
+                    class_weights.len()
+                )));
+            }
+        }
+        Ok(Self {
+            head: MlpHead::new(
+                dev,
+                varmap,
+                layers_dims,
+                dropout_rate,
+                classes,
+                HeadKind::MultiLabelClassifier,
+            ),
+            class_weights,
+            phantom: std::marker::PhantomData,
+        })
+    }
+
+    /// Get the config of the classifier.
+    pub fn config(&self) -> ClassifierConfig {
+        ClassifierConfig {
+            layers_dims: self.head.layers_dims().to_vec(),
+            dropout_rate: self.head.dropout_rate(),
+            classes: Some(self.head.outputs()),
+            class_weights: self.class_weights.clone(),
+        }
+    }
+
+    /// Train the model on the given dataset. Returns the final per-label test accuracy as a percentage.
+    pub fn train(
+        &self,
+        m: &MultiLabelClassificationDataset,
+        epochs: usize,
+        learning_rate: f64,
+        batch_size: usize,
+        mut progress: impl FnMut(ClassifierProgress),
+    ) -> Result<f32> {
+        let device = self.head.device();
+        let test_inputs = m.test_inputs.to_device(device)?;
+        let test_labels = m.test_labels.to_device(device)?;
+        let class_weights = self
+            .class_weights
+            .as_ref()
+            .map(|weights| Tensor::from_slice(weights, (1, weights.len()), device))
+            .transpose()?;
+        let mut final_accuracy: f32 = 0.0;
+        self.head.fit(
+            &m.train_inputs,
+            &m.train_labels,
+            epochs,
+            learning_rate,
+            batch_size,
+            |logits, labels| {
+                let loss = binary_cross_entropy_with_logits(logits, labels)?;
+                let loss = match &class_weights {
+                    Some(class_weights) => loss.broadcast_mul(class_weights)?,
+                    None => loss,
+                };
+                loss.mean_all()
+            },
+            |event| {
+                match event {
+                    FitEvent::BatchFinished { batch, loss } => {
+                        progress(ClassifierProgress::BatchFinished { batch, loss });
+                    }
+                    FitEvent::EpochFinished { epoch } => {
+                        let test_logits = self.head.forward_t(&test_inputs, false)?;
+                        // A positive logit means the probability of the label is above 0.5
+                        let correct = test_logits
+                            .ge(0f32)?
+                            .to_dtype(DType::F32)?
+                            .eq(&test_labels)?
+                            .to_dtype(DType::U32)?
+                            .sum_all()?
+                            .to_scalar::<u32>()?;
+                        let total = test_labels.elem_count();
+                        let test_accuracy = correct as f32 / total as f32;
+                        final_accuracy = f32::from(100u8) * test_accuracy;
+                        progress(ClassifierProgress::EpochFinished {
+                            epoch,
+                            accuracy: test_accuracy,
+                        });
+                    }
+                }
+                Ok(())
+            },
+        )?;
+        Ok(final_accuracy)
+    }
+
+    /// Save the model and its config to a safetensors file at the given path.
+    pub fn save(&self, path: impl AsRef<std::path::Path>) -> Result<()> {
+        self.head.save(path)
+    }
+
+    /// Load a model that was saved with [`MultiLabelClassifier::save`] using the config stored in the file.
+    pub fn load_saved(path: impl AsRef<std::path::Path>, dev: &Device) -> Result<Self> {
+        let (varmap, config) = MlpHead::load_weights(path, dev, HeadKind::MultiLabelClassifier)?;
+        let config = config.ok_or_else(|| {
+            candle_core::Error::Msg("The file doesn't contain a saved config".to_string())
+        })?;
+        Self::new_inner(
+            dev.clone(),
+            varmap,
+            ClassifierConfig::new()
+                .layers_dims(config.layers_dims)
+                .dropout_rate(config.dropout_rate)
+                .classes(config.outputs),
+        )
+    }
+
+    /// Run the model on the given input.
+    pub fn run(&self, input: &[f32]) -> Result<MultiLabelClassifierOutput<C>> {
+        let input = Tensor::from_vec(input.to_vec(), (1, input.len()), self.head.device())?;
+        let logits = self.head.forward_t(&input, false)?;
+        let probabilities = ops::sigmoid(&logits.flatten_all()?)?.to_vec1()?;
+        Ok(MultiLabelClassifierOutput {
+            classes: probabilities
+                .into_iter()
+                .enumerate()
+                .map(|(i, c)| (C::from_class(i as u32), c))
+                .collect(),
+        })
+    }
+}
+
+fn binary_cross_entropy_with_logits(logits: &Tensor, labels: &Tensor) -> Result<Tensor> {
+    // max(x, 0) - x * y + log(1 + exp(-|x|)) is a numerically stable version of the binary cross entropy
+    let stable_log = (logits.abs()?.neg()?.exp()? + 1.)?.log()?;
+    (logits.relu()? - (logits * labels)?)? + stable_log
+}
+
+/// The output of a [`MultiLabelClassifier`].
+#[derive(Debug, Clone)]
+pub struct MultiLabelClassifierOutput<C: Class> {
+    /// The classes along with their independent probabilities.
+    classes: Box<[(C, f32)]>,
+}
+
+impl<C: Class> MultiLabelClassifierOutput<C> {
+    /// Get the probability of each class.
+    pub fn classes(&self) -> &[(C, f32)] {
+        &self.classes
+    }
+
+    /// Get every class with a probability at or above the threshold.
+    pub fn labels(&self, threshold: f32) -> Vec<C>
+    where
+        C: Clone,
+    {
+        self.classes
+            .iter()
+            .filter(|(_, probability)| *probability >= threshold)
+            .map(|(c, _)| c.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn saved_multi_label_classifier_round_trips() -> Result<()> {
+    let dev = Device::Cpu;
+    let mut dataset = MultiLabelClassificationDatasetBuilder::<u32>::new().with_classes(3);
+    for i in 0..16 {
+        let x = i as f32 / 16.;
+        let labels = (0..3).filter(|label| (i >> label) & 1 == 1);
+        dataset.add(vec![x, 1. - x], labels);
+    }
+    let classifier = MultiLabelClassifier::<u32>::new(
+        &dev,
+        ClassifierConfig::new().layers_dims([4]).classes(3),
+    )?;
+    classifier.train(&dataset.build(&dev)?, 5, 0.05, 4, |_| {})?;
+
+    let path = std::env::temp_dir().join("kalosm-learning-multi-label.safetensors");
+    classifier.save(&path)?;
+    let loaded = MultiLabelClassifier::<u32>::load_saved(&path, &dev)?;
+    std::fs::remove_file(&path)?;
+
+    assert_eq!(loaded.config().layers_dims, vec![4]);
+    assert_eq!(loaded.config().classes, Some(3));
+    assert_eq!(
+        classifier.run(&[0.5, 0.5])?.classes(),
+        loaded.run(&[0.5, 0.5])?.classes()
+    );
+
+    // A single example can't be split into a train and test set
+    let mut dataset = MultiLabelClassificationDatasetBuilder::<u32>::new().with_classes(3);
+    dataset.add(vec![0.5, 0.5], [1]);
+    assert!(dataset.build(&dev).is_err());
+
+    // Labels must be one of the classes of the dataset
+    let mut dataset = MultiLabelClassificationDatasetBuilder::<u32>::new().with_classes(3);
+    dataset.add(vec![0.5, 0.5], [1]);
+    dataset.add(vec![0.5, 0.5], [3]);
+    assert!(dataset.build(&dev).is_err());
+    Ok(())
+}
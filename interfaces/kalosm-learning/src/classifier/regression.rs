@@ -0,0 +1,327 @@
+use std::collections::HashMap;
+
+use candle_core::{safetensors, Device, Result, Tensor};
+use candle_nn::{loss, VarMap};
+
+use super::head::{test_split, FitEvent, HeadKind, MlpHead};
+
+/// A dataset to train a [`Regressor`].
+#[derive(Clone, Debug)]
+pub struct RegressionDataset {
+    train_inputs: Tensor,
+    train_targets: Tensor,
+    test_inputs: Tensor,
+    test_targets: Tensor,
+}
+
+impl RegressionDataset {
+    /// Create a builder for a regression dataset.
+    pub fn builder() -> RegressionDatasetBuilder {
+        RegressionDatasetBuilder::default()
+    }
+
+    /// Save the dataset to the given path.
+    pub fn save<P: AsRef<std::path::Path>>(&self, path: P) -> Result<()> {
+        let safetensors = HashMap::from([
+            ("train_inputs".to_string(), self.train_inputs.clone()),
+            ("train_targets".to_string(), self.train_targets.clone()),
+            ("test_inputs".to_string(), self.test_inputs.clone()),
+            ("test_targets".to_string(), self.test_targets.clone()),
+        ]);
+
+        safetensors::save(&safetensors, path)?;
+        Ok(())
+    }
+
+    /// Load the dataset from the given path.
+    pub fn load<P: AsRef<std::path::Path>>(path: P, dev: &Device) -> Result<Self> {
+        let mut safetensors = safetensors::load(path, dev)?;
+        Ok(Self {
+            train_inputs: safetensors.remove("train_inputs").unwrap(),
+            train_targets: safetensors.remove("train_targets").unwrap(),
+            test_inputs: safetensors.remove("test_inputs").unwrap(),
+            test_targets: safetensors.remove("test_targets").unwrap(),
+        })
+    }
+}
+
+/// A builder for [`RegressionDataset`].
+#[derive(Default)]
+pub struct RegressionDatasetBuilder {
+    input_size: Option<usize>,
+    output_size: Option<usize>,
+    inputs: Vec<Box<[f32]>>,
+    targets: Vec<Box<[f32]>>,
+}
+
+impl RegressionDatasetBuilder {
+    /// Create a new dataset builder.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a pair of input and target values to the dataset.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use kalosm_learning::*;
+    /// let mut dataset = RegressionDatasetBuilder::new();
+    /// dataset.add(vec![1.0, 2.0, 3.0, 4.0], vec![0.25]);
+    /// dataset.add(vec![4.0, 3.0, 2.0, 1.0], vec![0.75]);
+    /// ```
+    pub fn add(&mut self, input: impl Into<Box<[f32]>>, target: impl Into<Box<[f32]>>) {
+        let input = input.into();
+        let target = target.into();
+        if let Some(input_size) = self.input_size {
+            debug_assert_eq!(input.len(), input_size, "input size mismatch");
+        } else {
+            self.input_size = Some(input.len());
+        }
+        if let Some(output_size) = self.output_size {
+            debug_assert_eq!(target.len(), output_size, "target size mismatch");
+        } else {
+            self.output_size = Some(target.len());
+        }
+        self.inputs.push(input);
+        self.targets.push(target);
+    }
+
+    /// Builds the dataset and copies the data to the device passed in.
+    pub fn build(self, dev: &Device) -> Result<RegressionDataset> {
+        let input_size = self.input_size.unwrap_or_default();
+        let output_size = self.output_size.unwrap_or_default();
+
+        let mut train_inputs = Vec::new();
+        let mut train_targets = Vec::new();
+        let mut test_inputs = Vec::new();
+        let mut test_targets = Vec::new();
+        let split = test_split(self.inputs.len())?;
+        for ((input, target), is_test) in self.inputs.iter().zip(&self.targets).zip(split) {
+            if is_test {
+                test_inputs.extend_from_slice(input);
+                test_targets.extend_from_slice(target);
+            } else {
+                train_inputs.extend_from_slice(input);
+                train_targets.extend_from_slice(target);
+            }
+        }
+
+        let train_len = train_inputs.len() / input_size.max(1);
+        let test_len = test_inputs.len() / input_size.max(1);
+        Ok(RegressionDataset {
+            train_inputs: Tensor::from_vec(train_inputs, (train_len, input_size), dev)?,
+            train_targets: Tensor::from_vec(train_targets, (train_len, output_size), dev)?,
+            test_inputs: Tensor::from_vec(test_inputs, (test_len, input_size), dev)?,
+            test_targets: Tensor::from_vec(test_targets, (test_len, output_size), dev)?,
+        })
+    }
+}
+
+/// A config for a [`Regressor`].
+#[derive(Debug, Clone)]
+pub struct RegressorConfig {
+    /// The dimensions of the layers.
+    layers_dims: Vec<usize>,
+    /// The dropout rate.
+    dropout_rate: f32,
+    /// The number of values the regressor predicts.
+    outputs: u32,
+}
+
+impl Default for RegressorConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RegressorConfig {
+    /// Create a new config.
+    pub fn new() -> Self {
+        Self {
+            layers_dims: vec![4, 8, 4],
+            dropout_rate: 0.1,
+            outputs: 1,
+        }
+    }
+
+    /// Set the dimensions of the layers.
+    pub fn layers_dims(mut self, layers_dims: impl IntoIterator<Item = usize>) -> Self {
+        self.layers_dims = layers_dims.into_iter().collect();
+        self
+    }
+
+    /// Set the dropout rate.
+    pub fn dropout_rate(mut self, dropout_rate: f32) -> Self {
+        self.dropout_rate = dropout_rate;
+        self
+    }
+
+    /// Set the number of values the regressor predicts. Defaults to 1.
+    pub fn outputs(mut self, outputs: u32) -> Self {
+        self.outputs = outputs;
+        self
+    }
+}
+
+/// Progress of training a [`Regressor`].
+#[derive(Debug, Clone, Copy)]
+pub enum RegressorProgress {
+    /// Progress after an epoch has finished.
+    EpochFinished {
+        /// The current epoch.
+        epoch: usize,
+        /// The mean squared error on the test set after the current epoch.
+        test_loss: f32,
+    },
+    /// Progress after a batch has finished.
+    BatchFinished {
+        /// The current batch.
+        batch: usize,
+        /// The current loss.
+        loss: f32,
+    },
+}
+
+/// A regression head that predicts continuous values from an input like an embedding.
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm_learning::*;
+///
+/// let dev = candle_core::Device::Cpu;
+/// let regressor = Regressor::new(&dev, RegressorConfig::new()).unwrap();
+/// let mut dataset = RegressionDatasetBuilder::new();
+/// dataset.add(vec![1.0, 2.0, 3.0, 4.0], vec![0.25]);
+/// dataset.add(vec![4.0, 3.0, 2.0, 1.0], vec![0.75]);
+/// regressor
+///     .train(&dataset.build(&dev).unwrap(), 20, 0.05, 3, |_| {})
+///     .unwrap();
+/// let output = regressor.run(&[1.0, 2.0, 3.0, 4.0]).unwrap();
+/// println!("{:?}", output);
+/// ```
+pub struct Regressor {
+    head: MlpHead,
+}
+
+impl Regressor {
+    /// Create a new regressor.
+    pub fn new(dev: &Device, config: RegressorConfig) -> Result<Self> {
+        Ok(Self::new_inner(dev.clone(), VarMap::new(), config))
+    }
+
+    fn new_inner(dev: Device, varmap: VarMap, config: RegressorConfig) -> Self {
+        let RegressorConfig {
+            layers_dims,
+            dropout_rate,
+            outputs,
+        } = config;
+        Self {
+            head: MlpHead::new(
+                dev,
+                varmap,
+                layers_dims,
+                dropout_rate,
+                outputs,
+                HeadKind::Regressor,
+            ),
+        }
+    }
+
+    /// Get the config of the regressor.
+    pub fn config(&self) -> RegressorConfig {
+        RegressorConfig {
+            layers_dims: self.head.layers_dims().to_vec(),
+            dropout_rate: self.head.dropout_rate(),
+            outputs: self.head.outputs(),
+        }
+    }
+
+    /// Train the model on the given dataset. Returns the final mean squared error on the test set.
+    pub fn train(
+        &self,
+        m: &RegressionDataset,
+        epochs: usize,
+        learning_rate: f64,
+        batch_size: usize,
+        mut progress: impl FnMut(RegressorProgress),
+    ) -> Result<f32> {
+        let device = self.head.device();
+        let test_inputs = m.test_inputs.to_device(device)?;
+        let test_targets = m.test_targets.to_device(device)?;
+        let mut final_loss = f32::INFINITY;
+        self.head.fit(
+            &m.train_inputs,
+            &m.train_targets,
+            epochs,
+            learning_rate,
+            batch_size,
+            loss::mse,
+            |event| {
+                match event {
+                    FitEvent::BatchFinished { batch, loss } => {
+                        progress(RegressorProgress::BatchFinished { batch, loss });
+                    }
+                    FitEvent::EpochFinished { epoch } => {
+                        let test_output = self.head.forward_t(&test_inputs, false)?;
+                        let test_loss = loss::mse(&test_output, &test_targets)?.to_scalar()?;
+                        final_loss = test_loss;
+                        progress(RegressorProgress::EpochFinished { epoch, test_loss });
+                    }
+                }
+                Ok(())
+            },
+        )?;
+        Ok(final_loss)
+    }
+
+    /// Save the model and its config to a safetensors file at the given path.
+    pub fn save(&self, path: impl AsRef<std::path::Path>) -> Result<()> {
+        self.head.save(path)
+    }
+
+    /// Load a model that was saved with [`Regressor::save`] using the config stored in the file.
+    pub fn load_saved(path: impl AsRef<std::path::Path>, dev: &Device) -> Result<Self> {
+        let (varmap, config) = MlpHead::load_weights(path, dev, HeadKind::Regressor)?;
+        let config = config.ok_or_else(|| {
+            candle_core::Error::Msg("The file doesn't contain a saved config".to_string())
+        })?;
+        Ok(Self::new_inner(
+            dev.clone(),
+            varmap,
+            RegressorConfig {
+                layers_dims: config.layers_dims,
+                dropout_rate: config.dropout_rate,
+                outputs: config.outputs,
+            },
+        ))
+    }
+
+    /// Run the model on the given input.
+    pub fn run(&self, input: &[f32]) -> Result<Vec<f32>> {
+        let input = Tensor::from_vec(input.to_vec(), (1, input.len()), self.head.device())?;
+        let output = self.head.forward_t(&input, false)?;
+        output.flatten_all()?.to_vec1()
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn saved_regressor_round_trips() -> Result<()> {
+    let dev = Device::Cpu;
+    let mut dataset = RegressionDatasetBuilder::new();
+    for i in 0..16 {
+        let x = i as f32 / 16.;
+        dataset.add(vec![x, 1. - x], vec![x * 2. - 1.]);
+    }
+    let regressor = Regressor::new(&dev, RegressorConfig::new().layers_dims([4]))?;
+    regressor.train(&dataset.build(&dev)?, 5, 0.05, 4, |_| {})?;
+
+    let path = std::env::temp_dir().join("kalosm-learning-regressor.safetensors");
+    regressor.save(&path)?;
+    let loaded = Regressor::load_saved(&path, &dev)?;
+    std::fs::remove_file(&path)?;
+
+    assert_eq!(loaded.config().layers_dims, vec![4]);
+    assert_eq!(regressor.run(&[0.5, 0.5])?, loaded.run(&[0.5, 0.5])?);
+    Ok(())
+}
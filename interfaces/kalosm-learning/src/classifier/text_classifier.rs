@@ -286,6 +286,15 @@ impl<T: Class> TextClassifier<T> {
         let model = Classifier::load(path, device, config)?;
         Ok(Self::new(model))
     }
+
+    /// Loads a classifier saved with [`TextClassifier::save`] using the config stored in the file.
+    pub fn load_saved<P: AsRef<std::path::Path>>(
+        path: P,
+        device: &Device,
+    ) -> candle_core::Result<Self> {
+        let model = Classifier::load_saved(path, device)?;
+        Ok(Self::new(model))
+    }
 }
 
 #[cfg(test)]
@@ -7,6 +7,8 @@
 //!
 //! Supported models:
 //! - [`Classifier`]
+//! - [`MultiLabelClassifier`]
+//! - [`Regressor`]
 
 mod classifier;
 pub use classifier::*;
@@ -1,6 +1,6 @@
 //! Common types for Kalosm models
 
-use std::{fmt::Display, path::PathBuf};
+use std::{fmt::Display, path::PathBuf, sync::Arc};
 
 /// The progress starting a model
 #[derive(Clone, Debug)]
@@ -23,7 +23,7 @@ pub enum ModelLoadingProgress {
 pub struct FileLoadingProgress {
     /// The time stamp the download started
     pub start_time: std::time::Instant,
-    /// The size of the cached part of the download in bytes
+    /// The size of the cached part of the download in bytes. If an interrupted download is being resumed, this is the byte the download resumed from
     pub cached_size: u64,
     /// The size of the download in bytes
     pub size: u64,
@@ -31,6 +31,44 @@ pub struct FileLoadingProgress {
     pub progress: u64,
 }
 
+impl FileLoadingProgress {
+    /// The byte an interrupted download was resumed from, if the download is being resumed
+    pub fn resumed_from(&self) -> Option<u64> {
+        (self.cached_size > 0 && self.cached_size < self.size).then_some(self.cached_size)
+    }
+
+    /// The number of bytes of the file that are available locally, including any cached part of the download
+    pub fn downloaded_bytes(&self) -> u64 {
+        self.progress
+    }
+
+    /// The total size of the file in bytes
+    pub fn total_bytes(&self) -> u64 {
+        self.size
+    }
+
+    /// The average download speed in bytes per second since the download started. Cached bytes don't count towards the speed
+    pub fn bytes_per_second(&self) -> f64 {
+        let elapsed = self.start_time.elapsed().as_secs_f64();
+        if elapsed == 0. {
+            return 0.;
+        }
+        self.progress.saturating_sub(self.cached_size) as f64 / elapsed
+    }
+
+    /// Try to estimate the time remaining for the download based on the average download speed
+    pub fn estimate_time_remaining(&self) -> Option<std::time::Duration> {
+        let bytes_per_second = self.bytes_per_second();
+        if bytes_per_second <= 0. {
+            return None;
+        }
+        let remaining = self.size.saturating_sub(self.progress);
+        Some(std::time::Duration::from_secs_f64(
+            remaining as f64 / bytes_per_second,
+        ))
+    }
+}
+
 impl ModelLoadingProgress {
     /// Create a new downloading progress
     pub fn downloading(source: String, file_loading_progress: FileLoadingProgress) -> Self {
@@ -69,23 +107,143 @@ impl ModelLoadingProgress {
         }
     }
 
+    /// The number of bytes that are available locally, if this is the progress of a download
+    pub fn downloaded_bytes(&self) -> Option<u64> {
+        match self {
+            Self::Downloading { progress, .. } => Some(progress.downloaded_bytes()),
+            Self::Loading { .. } => None,
+        }
+    }
+
+    /// The total number of bytes being downloaded, if this is the progress of a download
+    pub fn total_bytes(&self) -> Option<u64> {
+        match self {
+            Self::Downloading { progress, .. } => Some(progress.total_bytes()),
+            Self::Loading { .. } => None,
+        }
+    }
+
+    /// The average download speed in bytes per second, if this is the progress of a download
+    pub fn bytes_per_second(&self) -> Option<f64> {
+        match self {
+            Self::Downloading { progress, .. } => Some(progress.bytes_per_second()),
+            Self::Loading { .. } => None,
+        }
+    }
+
+    /// The byte an interrupted download was resumed from, if this is the progress of a resumed download
+    pub fn resumed_from(&self) -> Option<u64> {
+        match self {
+            Self::Downloading { progress, .. } => progress.resumed_from(),
+            Self::Loading { .. } => None,
+        }
+    }
+
     /// Try to estimate the time remaining for a download
     pub fn estimate_time_remaining(&self) -> Option<std::time::Duration> {
         match self {
-            Self::Downloading {
-                progress: FileLoadingProgress { start_time, .. },
-                ..
-            } => {
-                let elapsed = start_time.elapsed();
-                let progress = self.progress();
-                let remaining = (1. - progress) * elapsed.as_secs_f32();
-                Some(std::time::Duration::from_secs_f32(remaining))
-            }
+            Self::Downloading { progress, .. } => progress.estimate_time_remaining(),
             _ => None,
         }
     }
 
-    #[cfg(feature = "loading-progress-bar")]
+    /// Combine the progress of every file a model downloads into a single download named `name`. The combined progress is passed to `handler`, so applications can show one progress bar per model instead of one per file.
+    ///
+    /// Files are added to the total when they start downloading, so the total size can grow until the last file starts. Use [`ModelLoadingProgress::aggregate_with_size`] if the total size is known up front. Loading progress is passed through unchanged.
+    ///
+    /// # Example
+    /// ```rust
+    /// use kalosm_model_types::{FileLoadingProgress, ModelLoadingProgress};
+    ///
+    /// let mut events = Vec::new();
+    /// let mut handler = ModelLoadingProgress::aggregate("my model", |progress| events.push(progress));
+    /// let start_time = std::time::Instant::now();
+    /// for (file, progress) in [("weights", 50), ("tokenizer", 10)] {
+    ///     handler(ModelLoadingProgress::downloading(
+    ///         file.to_string(),
+    ///         FileLoadingProgress { start_time, cached_size: 0, size: 100, progress },
+    ///     ));
+    /// }
+    /// drop(handler);
+    /// let last = events.last().unwrap();
+    /// assert_eq!(last.downloaded_bytes(), Some(60));
+    /// assert_eq!(last.total_bytes(), Some(200));
+    /// ```
+    pub fn aggregate(
+        name: impl ToString,
+        handler: impl FnMut(ModelLoadingProgress),
+    ) -> impl FnMut(ModelLoadingProgress) {
+        Self::aggregate_with_size(name, 0, handler)
+    }
+
+    /// Like [`ModelLoadingProgress::aggregate`], but the total size starts at `size` bytes instead of growing as files start downloading. The size of the files that still need to be downloaded can be found with `Cache::download_size`.
+    ///
+    /// If the files turn out to be larger than `size`, the total grows to the combined size of the files.
+    ///
+    /// # Example
+    /// ```rust
+    /// use kalosm_model_types::{FileLoadingProgress, ModelLoadingProgress};
+    ///
+    /// let mut events = Vec::new();
+    /// let mut handler = ModelLoadingProgress::aggregate_with_size("my model", 200, |progress| {
+    ///     events.push(progress)
+    /// });
+    /// let start_time = std::time::Instant::now();
+    /// handler(ModelLoadingProgress::downloading(
+    ///     "weights".to_string(),
+    ///     FileLoadingProgress { start_time, cached_size: 0, size: 100, progress: 50 },
+    /// ));
+    /// drop(handler);
+    /// // The tokenizer hasn't started downloading yet, but it is already part of the total
+    /// assert_eq!(events[0].total_bytes(), Some(200));
+    /// assert_eq!(events[0].progress(), 0.25);
+    /// ```
+    pub fn aggregate_with_size(
+        name: impl ToString,
+        size: u64,
+        mut handler: impl FnMut(ModelLoadingProgress),
+    ) -> impl FnMut(ModelLoadingProgress) {
+        let name = name.to_string();
+        let mut files: Vec<(String, FileLoadingProgress)> = Vec::new();
+        move |progress| match progress {
+            Self::Downloading { source, progress } => {
+                match files.iter_mut().find(|(file, _)| *file == source) {
+                    Some((_, file)) => *file = progress,
+                    None => files.push((source, progress)),
+                }
+                let combined = files.iter().fold(
+                    FileLoadingProgress {
+                        start_time: std::time::Instant::now(),
+                        cached_size: 0,
+                        size: 0,
+                        progress: 0,
+                    },
+                    |mut combined, (_, file)| {
+                        combined.start_time = combined.start_time.min(file.start_time);
+                        combined.cached_size += file.cached_size;
+                        combined.size += file.size;
+                        combined.progress += file.progress;
+                        combined
+                    },
+                );
+                let combined = FileLoadingProgress {
+                    size: combined.size.max(size),
+                    ..combined
+                };
+                handler(Self::downloading(name.clone(), combined))
+            }
+            loading => handler(loading),
+        }
+    }
+
+    #[cfg(all(feature = "loading-progress-bar", target_arch = "wasm32"))]
+    /// A default loading progress bar. There is no terminal in the browser, so the progress is ignored
+    pub fn multi_bar_loading_indicator() -> impl FnMut(ModelLoadingProgress) + Send + Sync + 'static
+    {
+        |_| {}
+    }
+
+    #[cfg(all(feature = "loading-progress-bar", not(target_arch = "wasm32")))]
     /// A default loading progress bar
     pub fn multi_bar_loading_indicator() -> impl FnMut(ModelLoadingProgress) + Send + Sync + 'static
     {
@@ -112,7 +270,11 @@ impl ModelLoadingProgress {
             } => {
                 let progress_bar = progress_bars.entry(source.clone()).or_insert_with(|| {
                     let pb = m.add(ProgressBar::new(size));
-                    pb.set_message(format!("Downloading {source}"));
+                    if cached_size > 0 && cached_size < size {
+                        pb.set_message(format!("Resuming download of {source}"));
+                    } else {
+                        pb.set_message(format!("Downloading {source}"));
+                    }
                     pb.set_style(sty.clone());
                     pb.set_position(cached_size);
                     pb
@@ -131,7 +293,7 @@ impl ModelLoadingProgress {
     }
 }
 
-/// A source for a file, either from Hugging Face or a local path
+/// A source for a file, either from Hugging Face, a URL, a cloud object store or a local path
 #[derive(Clone, Debug)]
 pub enum FileSource {
     /// A file from Hugging Face
@@ -145,6 +307,144 @@ pub enum FileSource {
     },
     /// A local file
     Local(PathBuf),
+    /// A file that is downloaded from a URL
+    Url(String),
+    /// A file that is already in memory, like weights embedded in the binary with `include_bytes!`. In memory files are never written to the cache
+    InMemory(InMemoryFile),
+    /// A file in an Amazon S3 bucket. Credentials are resolved from the standard `AWS_*` environment variables or the instance metadata service
+    S3 {
+        /// The name of the bucket
+        bucket: String,
+        /// The key of the file in the bucket
+        key: String,
+    },
+    /// A file in a Google Cloud Storage bucket. Credentials are resolved from the standard `GOOGLE_*` environment variables or the instance metadata service
+    Gcs {
+        /// The name of the bucket
+        bucket: String,
+        /// The name of the object in the bucket
+        object: String,
+    },
+    /// A file in an Azure Blob Storage container. Credentials are resolved from the standard `AZURE_*` environment variables or a managed identity
+    AzureBlob {
+        /// The name of the storage account
+        account: String,
+        /// The name of the container in the storage account
+        container: String,
+        /// The name of the blob in the container
+        blob: String,
+    },
+    /// A file that is downloaded with an authentication token, like a file in a gated or private Hugging Face repo
+    Authenticated {
+        /// The source that requires authentication
+        source: Box<FileSource>,
+        /// The token sent as a bearer token with requests for the source
+        token: AuthToken,
+    },
+    /// A file that can be downloaded from fallback locations if the primary source fails. The file is cached as if it came from the primary source
+    Mirrored {
+        /// The source that is tried first
+        source: Box<FileSource>,
+        /// The locations that are tried in order if the primary source fails
+        mirrors: Vec<Mirror>,
+    },
+}
+
+/// The contents of a [`FileSource::InMemory`] file. Cloning the file is cheap because the bytes are shared
+#[derive(Clone)]
+pub struct InMemoryFile(InMemoryBytes);
+
+#[derive(Clone)]
+enum InMemoryBytes {
+    Static(&'static [u8]),
+    Shared(Arc<[u8]>),
+}
+
+impl InMemoryFile {
+    /// Get the contents of the file
+    pub fn as_bytes(&self) -> &[u8] {
+        match &self.0 {
+            InMemoryBytes::Static(bytes) => bytes,
+            InMemoryBytes::Shared(bytes) => bytes,
+        }
+    }
+
+    /// Get the size of the file in bytes
+    pub fn len(&self) -> usize {
+        self.as_bytes().len()
+    }
+
+    /// Check if the file is empty
+    pub fn is_empty(&self) -> bool {
+        self.as_bytes().is_empty()
+    }
+}
+
+impl AsRef<[u8]> for InMemoryFile {
+    fn as_ref(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+impl From<&'static [u8]> for InMemoryFile {
+    fn from(bytes: &'static [u8]) -> Self {
+        Self(InMemoryBytes::Static(bytes))
+    }
+}
+
+impl<const N: usize> From<&'static [u8; N]> for InMemoryFile {
+    fn from(bytes: &'static [u8; N]) -> Self {
+        Self(InMemoryBytes::Static(bytes))
+    }
+}
+
+impl From<Vec<u8>> for InMemoryFile {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self(InMemoryBytes::Shared(bytes.into()))
+    }
+}
+
+impl From<Arc<[u8]>> for InMemoryFile {
+    fn from(bytes: Arc<[u8]>) -> Self {
+        Self(InMemoryBytes::Shared(bytes))
+    }
+}
+
+impl std::fmt::Debug for InMemoryFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "InMemoryFile({} bytes)", self.len())
+    }
+}
+
+/// A token used to authenticate downloads. The token is hidden in the debug output
+#[derive(Clone, PartialEq, Eq)]
+pub struct AuthToken(String);
+
+impl AuthToken {
+    /// Create a new token
+    pub fn new(token: impl ToString) -> Self {
+        Self(token.to_string())
+    }
+
+    /// Get the token as a string
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Debug for AuthToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("AuthToken(..)")
+    }
+}
+
+/// A fallback location a [`FileSource`] can be downloaded from
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Mirror {
+    /// A Hugging Face compatible endpoint that hosts the same repositories, like `https://hf-mirror.com`. This mirror is only used for Hugging Face sources
+    HuggingFaceEndpoint(String),
+    /// A URL the file can be downloaded from directly
+    Url(String),
 }
 
 impl Display for FileSource {
@@ -156,6 +456,17 @@ impl Display for FileSource {
                 file,
             } => write!(f, "hf://{}/{}/{}", model_id, revision, file),
             FileSource::Local(path) => write!(f, "{}", path.display()),
+            FileSource::Url(url) => write!(f, "{}", url),
+            FileSource::InMemory(file) => write!(f, "in memory file ({} bytes)", file.len()),
+            FileSource::S3 { bucket, key } => write!(f, "s3://{}/{}", bucket, key),
+            FileSource::Gcs { bucket, object } => write!(f, "gs://{}/{}", bucket, object),
+            FileSource::AzureBlob {
+                account,
+                container,
+                blob,
+            } => write!(f, "az://{}/{}/{}", account, container, blob),
+            FileSource::Authenticated { source, .. } => write!(f, "{}", source),
+            FileSource::Mirrored { source, .. } => write!(f, "{}", source),
         }
     }
 }
@@ -178,4 +489,154 @@ impl FileSource {
     pub fn local(path: PathBuf) -> Self {
         Self::Local(path)
     }
+
+    /// Create a new source for a file that is embedded in the binary. The file is loaded directly from memory without touching the cache
+    ///
+    /// # Example
+    /// ```rust
+    /// use kalosm_model_types::FileSource;
+    ///
+    /// static CONFIG: &[u8] = br#"{"hidden_size": 384}"#;
+    /// let source = FileSource::from_bytes(CONFIG);
+    /// assert_eq!(source.to_string(), "in memory file (20 bytes)");
+    /// ```
+    pub fn from_bytes(bytes: &'static [u8]) -> Self {
+        Self::InMemory(bytes.into())
+    }
+
+    /// Create a new source for a file the application has already loaded into memory
+    pub fn from_shared_bytes(bytes: impl Into<InMemoryFile>) -> Self {
+        Self::InMemory(bytes.into())
+    }
+
+    /// Create a new source by reading a file into memory from a reader
+    pub fn from_reader(mut reader: impl std::io::Read) -> std::io::Result<Self> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        Ok(Self::InMemory(bytes.into()))
+    }
+
+    /// Create a new source for a file that is downloaded from a URL
+    pub fn url(url: impl ToString) -> Self {
+        Self::Url(url.to_string())
+    }
+
+    /// Create a new source for a file in an Amazon S3 bucket
+    pub fn s3(bucket: impl ToString, key: impl ToString) -> Self {
+        Self::S3 {
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+        }
+    }
+
+    /// Create a new source for a file in a Google Cloud Storage bucket
+    pub fn gcs(bucket: impl ToString, object: impl ToString) -> Self {
+        Self::Gcs {
+            bucket: bucket.to_string(),
+            object: object.to_string(),
+        }
+    }
+
+    /// Create a new source for a file in an Azure Blob Storage container
+    pub fn azure_blob(
+        account: impl ToString,
+        container: impl ToString,
+        blob: impl ToString,
+    ) -> Self {
+        Self::AzureBlob {
+            account: account.to_string(),
+            container: container.to_string(),
+            blob: blob.to_string(),
+        }
+    }
+
+    /// Add a mirror that is tried if this source and any mirrors added before it fail
+    ///
+    /// # Example
+    /// ```rust
+    /// use kalosm_model_types::{FileSource, Mirror};
+    ///
+    /// let source = FileSource::huggingface("bert-base-uncased", "main", "config.json")
+    ///     .with_mirror(Mirror::HuggingFaceEndpoint("https://hf-mirror.com".to_string()))
+    ///     .with_mirror(Mirror::Url(
+    ///         "https://models.example.com/bert-base-uncased/config.json".to_string(),
+    ///     ));
+    /// assert_eq!(source.mirrors().len(), 2);
+    /// ```
+    pub fn with_mirror(self, mirror: Mirror) -> Self {
+        match self {
+            Self::Mirrored {
+                source,
+                mut mirrors,
+            } => {
+                mirrors.push(mirror);
+                Self::Mirrored { source, mirrors }
+            }
+            source => Self::Mirrored {
+                source: Box::new(source),
+                mirrors: vec![mirror],
+            },
+        }
+    }
+
+    /// Download the source with an authentication token. For Hugging Face sources, this token is used instead of the token set on the cache or the `HF_TOKEN` environment variable
+    ///
+    /// The token is only sent to the primary source, never to mirrors.
+    ///
+    /// # Example
+    /// ```rust
+    /// use kalosm_model_types::FileSource;
+    ///
+    /// let source = FileSource::huggingface(
+    ///     "meta-llama/Llama-3.2-1B-Instruct",
+    ///     "main",
+    ///     "model.safetensors",
+    /// )
+    /// .with_token("hf_...");
+    /// assert_eq!(source.token(), Some("hf_..."));
+    /// ```
+    pub fn with_token(self, token: impl ToString) -> Self {
+        let token = AuthToken::new(token);
+        match self {
+            Self::Mirrored { source, mirrors } => Self::Mirrored {
+                source: Box::new(source.with_token(token.0)),
+                mirrors,
+            },
+            Self::Authenticated { source, .. } => Self::Authenticated { source, token },
+            source => Self::Authenticated {
+                source: Box::new(source),
+                token,
+            },
+        }
+    }
+
+    /// Get the authentication token for the primary source if one was set with [`FileSource::with_token`]
+    pub fn token(&self) -> Option<&str> {
+        match self {
+            Self::Authenticated { token, .. } => Some(token.as_str()),
+            Self::Mirrored { source, .. } => source.token(),
+            _ => None,
+        }
+    }
+
+    /// Add multiple mirrors that are tried in order if this source fails
+    pub fn with_mirrors(self, mirrors: impl IntoIterator<Item = Mirror>) -> Self {
+        mirrors.into_iter().fold(self, Self::with_mirror)
+    }
+
+    /// Get the primary source without any mirrors or authentication
+    pub fn primary(&self) -> &FileSource {
+        match self {
+            Self::Mirrored { source, .. } | Self::Authenticated { source, .. } => source.primary(),
+            source => source,
+        }
+    }
+
+    /// Get the mirrors of this source
+    pub fn mirrors(&self) -> &[Mirror] {
+        match self {
+            Self::Mirrored { mirrors, .. } => mirrors,
+            _ => &[],
+        }
+    }
 }
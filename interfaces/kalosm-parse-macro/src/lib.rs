@@ -113,6 +113,34 @@ use syn::{DataEnum, Fields, FieldsNamed, LitInt, Path, TypePath, Variant};
 /// }
 /// ```
 ///
+/// - `#[parse(format = "email")]` only accepts strings in a format. The formats `email`, `uuid`, `url`, `date`, `time` and `date-time` are supported. URLs can be limited to a list of schemes with `#[parse(format = "url", schemes = ["https"])]`
+///
+/// ```rust
+/// # use kalosm::language::*;
+/// #[derive(Parse, Schema, Clone)]
+/// struct Contact {
+///     #[parse(format = "email")]
+///     email: String,
+///     #[parse(format = "url", schemes = ["http", "https"])]
+///     website: String,
+///     #[parse(format = "uuid")]
+///     id: String,
+/// }
+/// ```
+///
+/// - `#[parse(range = 0.0..=1.0)]` limits a number field to a range. Float fields also accept `#[parse(max_decimal_places = 3)]` to limit the number of digits after the decimal point and `#[parse(scientific_notation)]` to allow numbers like `1.5e-3`
+///
+/// ```rust
+/// # use kalosm::language::*;
+/// #[derive(Parse, Schema, Clone)]
+/// struct Review {
+///     #[parse(range = 0.0..=1.0, max_decimal_places = 2)]
+///     score: f64,
+///     #[parse(range = 1..=5)]
+///     stars: u8,
+/// }
+/// ```
+///
 /// - `#[parse(tag = "tag")]` changes the name of the tag for enum variants (defaults to "type")
 ///
 /// ```rust
@@ -136,6 +164,83 @@ use syn::{DataEnum, Fields, FieldsNamed, LitInt, Path, TypePath, Variant};
 ///     Quit,
 /// }
 /// ```
+///
+/// - `#[parse(internally_tagged)]` puts the tag in the same object as the fields of the variant. Only unit variants and variants with named fields are supported
+///
+/// ```rust
+/// # use kalosm::language::*;
+/// #[derive(Parse, Schema, Debug, Clone, PartialEq)]
+/// #[parse(internally_tagged)]
+/// enum Action {
+///     Search { query: String },
+///     Quit,
+/// }
+///
+/// let parser = Action::new_parser();
+/// let state = parser.create_parser_state();
+/// let action = parser
+///     .parse(&state, b"{ \"type\": \"Search\", \"query\": \"my query\" } ")
+///     .unwrap()
+///     .unwrap_finished();
+/// assert_eq!(
+///     action,
+///     Action::Search {
+///         query: "my query".to_string()
+///     }
+/// );
+/// ```
+///
+/// - `#[parse(untagged)]` parses the data of any variant without a tag. Unit variants are parsed as a string with the name of the variant
+///
+/// ```rust
+/// # use kalosm::language::*;
+/// #[derive(Parse, Schema, Debug, Clone, PartialEq)]
+/// #[parse(untagged)]
+/// enum Response {
+///     Answer { answer: String },
+///     Number(i64),
+///     Unknown,
+/// }
+///
+/// let parser = Response::new_parser();
+/// let state = parser.create_parser_state();
+/// let response = parser.parse(&state, b"42 ").unwrap().unwrap_finished();
+/// assert_eq!(response, Response::Number(42));
+/// ```
+///
+/// - `#[parse(max_depth = 5)]` changes how deeply a recursive type can be nested inside itself (defaults to 10). Types that contain themselves are detected automatically. If a type is only recursive through another type, add this attribute to one of the types in the cycle
+///
+/// ```rust
+/// # use kalosm::language::*;
+/// #[derive(Parse, Clone)]
+/// #[parse(max_depth = 3)]
+/// struct Comment {
+///     text: String,
+///     replies: Vec<Comment>,
+/// }
+/// ```
+///
+/// - `#[parse(partial)]` on a struct creates a `{Type}Partial` struct where every field is optional and implements `PartialParse` for the type. You can read the fields that have been parsed so far from the state of the parser to show the struct while it is being generated
+///
+/// ```rust
+/// # use kalosm::language::*;
+/// #[derive(Parse, Clone)]
+/// #[parse(partial)]
+/// struct Pet {
+///     name: String,
+///     age: u32,
+/// }
+///
+/// let parser = Pet::new_partial_parser();
+/// let state = parser.create_parser_state();
+/// let (state, _) = parser
+///     .parse(&state, b"{ \"name\": \"Buddy\", \"age\": ")
+///     .unwrap()
+///     .unwrap_incomplete();
+/// let partial = Pet::partial(&state);
+/// assert_eq!(partial.name.as_deref(), Some("Buddy"));
+/// assert_eq!(partial.current_field, Some("age"));
+/// ```
 #[proc_macro_derive(Parse, attributes(parse))]
 pub fn derive_parse(input: TokenStream) -> TokenStream {
     // Parse the input tokens into a syntax tree
@@ -152,7 +257,7 @@ pub fn derive_parse(input: TokenStream) -> TokenStream {
                         quote! { Self {} },
                     ));
                 }
-                let struct_parser = match StructParser::new(input.attrs, fields, ty) {
+                let struct_parser = match StructParser::new(input.attrs, input.vis, fields, ty) {
                     Ok(parser) => parser,
                     Err(err) => return err.to_compile_error().into(),
                 };
@@ -216,7 +321,7 @@ pub fn derive_schema(input: TokenStream) -> TokenStream {
                 if fields.named.is_empty() {
                     return TokenStream::from(unit_schema(&input.attrs, &ty));
                 }
-                let struct_parser = match StructParser::new(input.attrs, fields, ty) {
+                let struct_parser = match StructParser::new(input.attrs, input.vis, fields, ty) {
                     Ok(parser) => parser,
                     Err(err) => return err.to_compile_error().into(),
                 };
@@ -270,34 +375,61 @@ pub fn derive_schema(input: TokenStream) -> TokenStream {
 
 struct StructParser {
     attributes: Vec<syn::Attribute>,
+    vis: syn::Visibility,
     ty: Ident,
     name: String,
     fields: FieldsParser,
+    recursion: Recursion,
+    partial: bool,
 }
 
 impl StructParser {
-    fn new(attributes: Vec<syn::Attribute>, fields: FieldsNamed, ty: Ident) -> syn::Result<Self> {
+    fn new(
+        attributes: Vec<syn::Attribute>,
+        vis: syn::Visibility,
+        fields: FieldsNamed,
+        ty: Ident,
+    ) -> syn::Result<Self> {
         let named = fields.named.into_iter().collect::<Vec<_>>();
 
         let mut name = ty.unraw().to_string();
+        let mut recursion = Recursion::new(named.iter().map(|field| &field.ty), &ty);
+        let mut partial = false;
         for attr in &attributes {
             if attr.path().is_ident("parse") {
                 attr.parse_nested_meta(|meta| {
                     if let Some(value) = parse_rename_attribute(&meta)? {
                         name = value.value();
-                    } else {
-                        return Err(meta.error("expected `rename`"));
+                    } else if meta.path.is_ident("partial") {
+                        partial = true;
+                    } else if !recursion.apply_attribute(&meta)? {
+                        return Err(meta.error("expected `rename`, `max_depth` or `partial`"));
                     }
                     Ok(())
                 })?;
             }
         }
 
+        if partial {
+            if let Some(field) = named
+                .iter()
+                .find(|field| field.ident.as_ref().unwrap() == "current_field")
+            {
+                return Err(syn::Error::new(
+                    field.span(),
+                    "Partial structs can't have a field named `current_field`",
+                ));
+            }
+        }
+
         Ok(Self {
             attributes,
+            vis,
             name,
             ty,
             fields: FieldsParser::new(&named)?,
+            recursion,
+            partial,
         })
     }
 
@@ -315,12 +447,14 @@ impl StructParser {
             }
         };
 
-        let parser = match self.fields.parser(construct) {
+        let parser = match self.fields.parser("{ ", construct) {
             Ok(parser) => parser,
             Err(err) => return err.to_compile_error(),
         };
+        let parser = self.recursion.wrap_parser(parser);
 
         let ty = &self.ty;
+        let partial = self.partial.then(|| self.quote_partial());
 
         quote! {
             impl kalosm_sample::Parse for #ty {
@@ -328,10 +462,93 @@ impl StructParser {
                     #parser
                 }
             }
+
+            #partial
+        }
+    }
+
+    /// Create a `{Type}Partial` struct with optional fields and implement `PartialParse` for the type
+    fn quote_partial(&self) -> TokenStream2 {
+        let ty = &self.ty;
+        let vis = &self.vis;
+        let partial_ty = format_ident!("{}Partial", ty);
+        let doc = format!(
+            " A partially parsed [`{ty}`]. Fields that haven't been parsed yet are `None`."
+        );
+
+        let fields = &self.fields.fields;
+        let idents: Vec<_> = fields
+            .iter()
+            .map(|f| f.field.ident.as_ref().unwrap())
+            .collect();
+        let indexes = (0..fields.len()).map(syn::Index::from).collect::<Vec<_>>();
+        let partial_fields = fields.iter().map(|f| {
+            let field_vis = &f.field.vis;
+            let ident = f.field.ident.as_ref().unwrap();
+            let field_ty = &f.field.ty;
+            let docs = f
+                .field
+                .attrs
+                .iter()
+                .filter(|attr| attr.path().is_ident("doc"));
+            quote! {
+                #(#docs)*
+                #field_vis #ident: ::std::option::Option<#field_ty>
+            }
+        });
+        let with_fields = fields.iter().enumerate().map(|(i, f)| {
+            let name = &f.name;
+            let prefix = if i == 0 {
+                format!("{{ \"{name}\": ")
+            } else {
+                format!(", \"{name}\": ")
+            };
+            let parser = &f.parser;
+            quote! {
+                .with_field(#name, #prefix, #parser)
+            }
+        });
+
+        quote! {
+            #[doc = #doc]
+            #[derive(Clone, Default)]
+            #vis struct #partial_ty {
+                #(#partial_fields,)*
+                /// The name of the field that is currently being parsed
+                pub current_field: ::std::option::Option<&'static str>,
+            }
+
+            impl kalosm_sample::PartialParse for #ty {
+                type Partial = #partial_ty;
+
+                fn new_partial_parser() -> impl kalosm_sample::SendCreateParserState<
+                    Output = Self,
+                    PartialState = kalosm_sample::FieldsParserState,
+                > {
+                    kalosm_sample::ParserExt::map_output(
+                        kalosm_sample::FieldsParser::new(" }")
+                            #(#with_fields)*,
+                        // The fields parser always outputs the type of each field parser
+                        |values| Self {
+                            #(#idents: values.get(#indexes).unwrap(),)*
+                        }
+                    )
+                }
+
+                fn partial(state: &kalosm_sample::FieldsParserState) -> Self::Partial {
+                    #partial_ty {
+                        #(#idents: state.field(#indexes),)*
+                        current_field: state.current_field_name(),
+                    }
+                }
+            }
         }
     }
 
     fn quote_schema(&self) -> proc_macro2::TokenStream {
+        if let Err(err) = self.recursion.check_schema(&self.ty) {
+            return err.to_compile_error();
+        }
         let title = &self.name;
         let ty = &self.ty;
         let description = doc_comment(&self.attributes);
@@ -352,6 +569,69 @@ impl StructParser {
     }
 }
 
+/// Tracks if a type contains itself. The parser for a recursive type is built lazily with a maximum depth
+struct Recursion {
+    recursive: bool,
+    max_depth: Option<TokenStream2>,
+}
+
+impl Recursion {
+    fn new<'a>(field_types: impl IntoIterator<Item = &'a syn::Type>, ty: &Ident) -> Self {
+        Self {
+            recursive: field_types
+                .into_iter()
+                .any(|field_ty| mentions_type(field_ty.to_token_stream(), ty)),
+            max_depth: None,
+        }
+    }
+
+    /// Apply the `#[parse(max_depth = 5)]` attribute. This also marks types that are only recursive through another type as recursive
+    fn apply_attribute(&mut self, meta: &ParseNestedMeta) -> syn::Result<bool> {
+        if meta.path.is_ident("max_depth") {
+            let value = meta.value()?.parse::<syn::Expr>()?;
+            self.max_depth = Some(value.to_token_stream());
+            self.recursive = true;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    fn wrap_parser(&self, parser: TokenStream2) -> TokenStream2 {
+        if !self.recursive {
+            return parser;
+        }
+        let max_depth = self.max_depth.as_ref().map(|max_depth| {
+            quote! {
+                .with_max_depth(#max_depth)
+            }
+        });
+        quote! {
+            kalosm_sample::RecursiveParser::new(|| #parser)
+                #max_depth
+        }
+    }
+
+    fn check_schema(&self, ty: &Ident) -> syn::Result<()> {
+        if self.recursive {
+            return Err(syn::Error::new(
+                ty.span(),
+                "Schema can't be derived for recursive types",
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Check if a type refers to `Self` or the type with the name `ty`
+fn mentions_type(tokens: TokenStream2, ty: &Ident) -> bool {
+    tokens.into_iter().any(|token| match token {
+        proc_macro2::TokenTree::Ident(ident) => ident == *ty || ident == "Self",
+        proc_macro2::TokenTree::Group(group) => mentions_type(group.stream(), ty),
+        _ => false,
+    })
+}
+
 fn quote_fields(fields: Fields) -> TokenStream2 {
     match fields {
         Fields::Named(fields) => {
@@ -451,18 +731,37 @@ fn unit_parser(attrs: &[syn::Attribute], ty: &Ident) -> TokenStream2 {
     }
 }
 
+/// How the variant of an enum is represented in JSON
+enum EnumTagging {
+    /// `{ "type": "Variant", "data": { "field": 1 } }`
+    Adjacent { tag: String, content: String },
+    /// `{ "type": "Variant", "field": 1 }`
+    Internal { tag: String },
+    /// `{ "field": 1 }`
+    Untagged,
+}
+
 struct EnumParser {
     ty: Ident,
-    tag: String,
-    data: String,
+    tagging: EnumTagging,
     variants: Vec<EnumVariant>,
+    recursion: Recursion,
 }
 
 impl EnumParser {
     fn new(attrs: Vec<syn::Attribute>, data: DataEnum, ty: Ident) -> syn::Result<Self> {
-        // Look for the tag and content attributes within the #[parse] attribute
+        // Look for the tag, content, internally_tagged and untagged attributes within the #[parse] attribute
         let mut tag = "type".to_string();
         let mut content = "data".to_string();
+        let mut content_span = None;
+        let mut internally_tagged = None;
+        let mut untagged = None;
+        let mut recursion = Recursion::new(
+            data.variants
+                .iter()
+                .flat_map(|variant| variant.fields.iter().map(|field| &field.ty)),
+            &ty,
+        );
         for attr in attrs.iter() {
             if attr.path().is_ident("parse") {
                 attr.parse_nested_meta(|meta| {
@@ -477,14 +776,49 @@ impl EnumParser {
                             .value()
                             .and_then(|value| value.parse::<syn::LitStr>())?;
                         content = value.value();
+                        content_span = Some(value.span());
+                        Ok(())
+                    } else if meta.path.is_ident("internally_tagged") {
+                        internally_tagged = Some(meta.path.span());
+                        Ok(())
+                    } else if meta.path.is_ident("untagged") {
+                        untagged = Some(meta.path.span());
+                        Ok(())
+                    } else if recursion.apply_attribute(&meta)? {
                         Ok(())
                     } else {
-                        Err(meta.error("expected `tag` or `content`"))
+                        Err(meta.error(expected_attributes_error([
+                            "tag",
+                            "content",
+                            "internally_tagged",
+                            "untagged",
+                            "max_depth",
+                        ])))
                     }
                 })?;
             }
         }
 
+        if let Some(span) = content_span {
+            if internally_tagged.is_some() || untagged.is_some() {
+                return Err(syn::Error::new(
+                    span,
+                    "`content` is only supported for adjacently tagged enums",
+                ));
+            }
+        }
+        let tagging = match (internally_tagged, untagged) {
+            (Some(_), Some(span)) => {
+                return Err(syn::Error::new(
+                    span,
+                    "`untagged` can't be combined with `internally_tagged`",
+                ))
+            }
+            (Some(_), None) => EnumTagging::Internal { tag },
+            (None, Some(_)) => EnumTagging::Untagged,
+            (None, None) => EnumTagging::Adjacent { tag, content },
+        };
+
         let variants = data
             .variants
             .iter()
@@ -493,20 +827,18 @@ impl EnumParser {
 
         Ok(EnumParser {
             ty,
-            tag,
-            data: content,
+            tagging,
             variants,
+            recursion,
         })
     }
 
     fn quote_parser(&self) -> syn::Result<TokenStream2> {
-        let tag = &self.tag;
         let ty = &self.ty;
-        let content = &self.data;
         let mut parser = None;
 
         for variant in &self.variants {
-            let parse_variant = variant.quote_parser(content)?;
+            let parse_variant = variant.quote_parser(&self.tagging)?;
             match &mut parser {
                 Some(current) => {
                     *current = quote! {
@@ -522,11 +854,10 @@ impl EnumParser {
             }
         }
 
-        let struct_start = format!("{{ \"{tag}\": \"");
-
-        Ok(quote! {
-            impl kalosm_sample::Parse for #ty {
-                fn new_parser() -> impl kalosm_sample::SendCreateParserState<Output = Self> {
+        let parser = match &self.tagging {
+            EnumTagging::Adjacent { tag, .. } => {
+                let struct_start = format!("{{ \"{tag}\": \"");
+                quote! {
                     kalosm_sample::ParserExt::then_literal(
                         kalosm_sample::ParserExt::ignore_output_then(
                             kalosm_sample::LiteralParser::from(#struct_start),
@@ -536,24 +867,37 @@ impl EnumParser {
                     )
                 }
             }
+            // The variants of internally tagged enums parse the rest of the object
+            EnumTagging::Internal { tag } => {
+                let struct_start = format!("{{ \"{tag}\": \"");
+                quote! {
+                    kalosm_sample::ParserExt::ignore_output_then(
+                        kalosm_sample::LiteralParser::from(#struct_start),
+                        #parser
+                    )
+                }
+            }
+            EnumTagging::Untagged => quote! { #parser },
+        };
+        let parser = self.recursion.wrap_parser(parser);
+
+        Ok(quote! {
+            impl kalosm_sample::Parse for #ty {
+                fn new_parser() -> impl kalosm_sample::SendCreateParserState<Output = Self> {
+                    #parser
+                }
+            }
         })
     }
 
     fn quote_schema(&self) -> syn::Result<proc_macro2::TokenStream> {
-        let tag = &self.tag;
-        let content = &self.data;
+        self.recursion.check_schema(&self.ty)?;
         let ty = &self.ty;
 
         let variants: Vec<_> = self
             .variants
             .iter()
-            .map(|variant| {
-                let variant_name = &variant.name;
-                let variant_parser = variant.quote_schema(tag, content, variant_name)?;
-                Ok(quote! {
-                    #variant_parser
-                })
-            })
+            .map(|variant| variant.quote_schema(&self.tagging))
             .collect::<syn::Result<_>>()?;
 
         Ok(quote! {
@@ -595,6 +939,10 @@ impl EnumVariant {
         }
 
         let parse_variant = match &variant.fields {
+            // Variants with no fields are parsed like unit variants
+            syn::Fields::Named(fields) if fields.named.is_empty() => {
+                EnumVariantType::Unit(UnitEnumVariantParser::new())
+            }
             syn::Fields::Named(fields) => {
                 EnumVariantType::Struct(StructEnumVariantParser::new(fields)?)
             }
@@ -606,7 +954,6 @@ impl EnumVariant {
                         "Unnamed enum variants with more or less than one field are not supported",
                     ));
                 };
-
                 EnumVariantType::Tuple(TupleEnumVariantParser::new(inner))
             }
             // If this is a unit variant, we can just parse the type
@@ -628,33 +975,45 @@ impl EnumVariant {
         }
     }
 
-    fn quote_parser(&self, content_name: &str) -> syn::Result<TokenStream2> {
+    fn quote_parser(&self, tagging: &EnumTagging) -> syn::Result<TokenStream2> {
         let construct_variant = self.construct_variant();
         match &self.ty {
             EnumVariantType::Struct(parser) => {
-                parser.quote_parser(&self.name, content_name, construct_variant)
+                parser.quote_parser(&self.name, tagging, construct_variant)
             }
             EnumVariantType::Tuple(parser) => {
-                parser.quote_parser(&self.name, content_name, construct_variant)
+                parser.quote_parser(&self.name, tagging, construct_variant)
+            }
+            EnumVariantType::Unit(parser) => {
+                parser.quote_parser(&self.name, tagging, construct_variant)
             }
-            EnumVariantType::Unit(parser) => parser.quote_parser(&self.name, construct_variant),
         }
     }
 
-    fn quote_schema(
-        &self,
-        tag: &str,
-        content: &str,
-        variant_name: &str,
-    ) -> syn::Result<proc_macro2::TokenStream> {
+    fn quote_schema(&self, tagging: &EnumTagging) -> syn::Result<proc_macro2::TokenStream> {
         match &self.ty {
-            EnumVariantType::Struct(parser) => parser.quote_schema(tag, content, variant_name),
-            EnumVariantType::Tuple(parser) => parser.quote_schema(tag, content, variant_name),
-            EnumVariantType::Unit(parser) => parser.quote_schema(tag, variant_name),
+            EnumVariantType::Struct(parser) => parser.quote_schema(tagging, &self.name),
+            EnumVariantType::Tuple(parser) => parser.quote_schema(tagging, &self.name),
+            EnumVariantType::Unit(parser) => parser.quote_schema(tagging, &self.name),
         }
     }
 }
 
+/// The schema for the property that holds the name of the variant
+fn quote_tag_property_schema(tag: &str, variant_name: &str) -> TokenStream2 {
+    quote! {
+        kalosm_sample::JsonPropertySchema::new(
+            #tag,
+            kalosm_sample::SchemaType::Enum(
+                kalosm_sample::EnumSchema::new([
+                    kalosm_sample::SchemaLiteral::String(#variant_name.to_string())
+                ])
+            )
+        )
+        .with_required(true)
+    }
+}
+
 enum EnumVariantType {
     Unit(UnitEnumVariantParser),
     Tuple(TupleEnumVariantParser),
@@ -671,9 +1030,16 @@ impl UnitEnumVariantParser {
     fn quote_parser(
         &self,
         variant_name: &str,
+        tagging: &EnumTagging,
         construct_variant: TokenStream2,
     ) -> syn::Result<TokenStream2> {
-        let lit_str_name = LitStr::new(&format!("{variant_name}\""), Span::call_site());
+        let literal = match tagging {
+            EnumTagging::Adjacent { .. } => format!("{variant_name}\""),
+            EnumTagging::Internal { .. } => format!("{variant_name}\" }}"),
+            // Untagged unit variants are written as a string with the name of the variant
+            EnumTagging::Untagged => format!("\"{variant_name}\""),
+        };
+        let lit_str_name = LitStr::new(&literal, Span::call_site());
         Ok(quote! {
             kalosm_sample::ParserExt::map_output(
                 kalosm_sample::LiteralParser::from(#lit_str_name),
@@ -682,22 +1048,24 @@ impl UnitEnumVariantParser {
         })
     }
 
-    fn quote_schema(&self, tag: &str, variant_name: &str) -> syn::Result<proc_macro2::TokenStream> {
-        Ok(quote! {
-            kalosm_sample::SchemaType::Object(
-                kalosm_sample::JsonObjectSchema::new([
-                    kalosm_sample::JsonPropertySchema::new(
-                        #tag,
-                        kalosm_sample::SchemaType::Enum(
-                            kalosm_sample::EnumSchema::new([
-                                kalosm_sample::SchemaLiteral::String(#variant_name.to_string())
-                            ])
-                        )
+    fn quote_schema(
+        &self,
+        tagging: &EnumTagging,
+        variant_name: &str,
+    ) -> syn::Result<proc_macro2::TokenStream> {
+        match tagging {
+            EnumTagging::Adjacent { tag, .. } | EnumTagging::Internal { tag } => {
+                let tag_property = quote_tag_property_schema(tag, variant_name);
+                Ok(quote! {
+                    kalosm_sample::SchemaType::Object(
+                        kalosm_sample::JsonObjectSchema::new([
+                            #tag_property
+                        ])
                     )
-                    .with_required(true)
-                ])
-            )
-        })
+                })
+            }
+            EnumTagging::Untagged => Ok(unit_enum_schema_type([variant_name.to_string()])),
+        }
     }
 }
 
@@ -716,51 +1084,76 @@ impl StructEnumVariantParser {
     fn quote_parser(
         &self,
         variant_name: &str,
-        content_name: &str,
+        tagging: &EnumTagging,
         construct_variant: TokenStream2,
     ) -> syn::Result<TokenStream2> {
-        let parse_name_and_data = LitStr::new(
-            &format!("{variant_name}\", \"{content_name}\": "),
-            Span::call_site(),
-        );
-        let field_parser = self.fields.parser(construct_variant)?;
-        Ok(quote! {
-            kalosm_sample::ParserExt::ignore_output_then(
-                kalosm_sample::LiteralParser::from(#parse_name_and_data),
-                #field_parser
-            )
-        })
+        match tagging {
+            EnumTagging::Adjacent { content, .. } => {
+                let parse_name_and_data = LitStr::new(
+                    &format!("{variant_name}\", \"{content}\": "),
+                    Span::call_site(),
+                );
+                let field_parser = self.fields.parser("{ ", construct_variant)?;
+                Ok(quote! {
+                    kalosm_sample::ParserExt::ignore_output_then(
+                        kalosm_sample::LiteralParser::from(#parse_name_and_data),
+                        #field_parser
+                    )
+                })
+            }
+            // The fields follow the tag in the same object
+            EnumTagging::Internal { .. } => self
+                .fields
+                .parser(&format!("{variant_name}\", "), construct_variant),
+            EnumTagging::Untagged => self.fields.parser("{ ", construct_variant),
+        }
     }
 
     fn quote_schema(
         &self,
-        tag: &str,
-        content: &str,
+        tagging: &EnumTagging,
         variant_name: &str,
     ) -> syn::Result<proc_macro2::TokenStream> {
-        let variant_parser = self.fields.quote_schema();
-        Ok(quote! {
-            kalosm_sample::SchemaType::Object(
-                kalosm_sample::JsonObjectSchema::new([
-                    kalosm_sample::JsonPropertySchema::new(
-                        #tag,
-                        kalosm_sample::SchemaType::Enum(
-                            kalosm_sample::EnumSchema::new([
-                                kalosm_sample::SchemaLiteral::String(#variant_name.to_string())
-                            ])
-                        )
+        match tagging {
+            EnumTagging::Adjacent { tag, content } => {
+                let tag_property = quote_tag_property_schema(tag, variant_name);
+                let variant_parser = self.fields.quote_schema();
+                Ok(quote! {
+                    kalosm_sample::SchemaType::Object(
+                        kalosm_sample::JsonObjectSchema::new([
+                            #tag_property,
+                            kalosm_sample::JsonPropertySchema::new(
+                                #content,
+                                kalosm_sample::SchemaType::Object(
+                                    #variant_parser
+                                )
+                            )
+                            .with_required(true)
+                        ])
                     )
-                    .with_required(true),
-                    kalosm_sample::JsonPropertySchema::new(
-                        #content,
-                        kalosm_sample::SchemaType::Object(
-                            #variant_parser
-                        )
+                })
+            }
+            EnumTagging::Internal { tag } => {
+                let tag_property = quote_tag_property_schema(tag, variant_name);
+                let properties = self.fields.quote_properties();
+                Ok(quote! {
+                    kalosm_sample::SchemaType::Object(
+                        kalosm_sample::JsonObjectSchema::new([
+                            #tag_property,
+                            #(#properties),*
+                        ])
                     )
-                    .with_required(true)
-                ])
-            )
-        })
+                })
+            }
+            EnumTagging::Untagged => {
+                let variant_parser = self.fields.quote_schema();
+                Ok(quote! {
+                    kalosm_sample::SchemaType::Object(
+                        #variant_parser
+                    )
+                })
+            }
+        }
     }
 }
 
@@ -778,20 +1171,36 @@ impl TupleEnumVariantParser {
     fn quote_parser(
         &self,
         variant_name: &str,
-        content: &str,
+        tagging: &EnumTagging,
         construct_variant: TokenStream2,
     ) -> syn::Result<TokenStream2> {
-        let parse_name_and_data = LitStr::new(
-            &format!("{variant_name}\", \"{content}\": "),
-            Span::call_site(),
-        );
         let ty = &self.field.ty;
+        let parser = match tagging {
+            EnumTagging::Adjacent { content, .. } => {
+                let parse_name_and_data = LitStr::new(
+                    &format!("{variant_name}\", \"{content}\": "),
+                    Span::call_site(),
+                );
+                quote! {
+                    kalosm_sample::ParserExt::ignore_output_then(
+                        kalosm_sample::LiteralParser::from(#parse_name_and_data),
+                        <#ty as kalosm_sample::Parse>::new_parser()
+                    )
+                }
+            }
+            EnumTagging::Internal { .. } => {
+                return Err(syn::Error::new(
+                    self.field.span(),
+                    "Unnamed enum variants are not supported in internally tagged enums",
+                ))
+            }
+            EnumTagging::Untagged => quote! {
+                <#ty as kalosm_sample::Parse>::new_parser()
+            },
+        };
         Ok(quote! {
             kalosm_sample::ParserExt::map_output(
-                kalosm_sample::ParserExt::ignore_output_then(
-                    kalosm_sample::LiteralParser::from(#parse_name_and_data),
-                    <#ty as kalosm_sample::Parse>::new_parser()
-                ),
+                #parser,
                 |data0| #construct_variant
             )
         })
@@ -799,31 +1208,34 @@ impl TupleEnumVariantParser {
 
     fn quote_schema(
         &self,
-        tag: &str,
-        content: &str,
+        tagging: &EnumTagging,
         variant_name: &str,
     ) -> syn::Result<proc_macro2::TokenStream> {
         let ty = &self.field.ty;
-        Ok(quote! {
-            kalosm_sample::SchemaType::Object(
-                kalosm_sample::JsonObjectSchema::new([
-                    kalosm_sample::JsonPropertySchema::new(
-                        #tag,
-                        kalosm_sample::SchemaType::Enum(
-                            kalosm_sample::EnumSchema::new([
-                                kalosm_sample::SchemaLiteral::String(#variant_name.to_string())
-                            ])
-                        )
-                    )
-                    .with_required(true),
-                    kalosm_sample::JsonPropertySchema::new(
-                        #content,
-                        <#ty as kalosm_sample::Schema>::schema()
+        match tagging {
+            EnumTagging::Adjacent { tag, content } => {
+                let tag_property = quote_tag_property_schema(tag, variant_name);
+                Ok(quote! {
+                    kalosm_sample::SchemaType::Object(
+                        kalosm_sample::JsonObjectSchema::new([
+                            #tag_property,
+                            kalosm_sample::JsonPropertySchema::new(
+                                #content,
+                                <#ty as kalosm_sample::Schema>::schema()
+                            )
+                            .with_required(true)
+                        ])
                     )
-                    .with_required(true)
-                ])
-            )
-        })
+                })
+            }
+            EnumTagging::Internal { .. } => Err(syn::Error::new(
+                self.field.span(),
+                "Unnamed enum variants are not supported in internally tagged enums",
+            )),
+            EnumTagging::Untagged => Ok(quote! {
+                <#ty as kalosm_sample::Schema>::schema()
+            }),
+        }
     }
 }
 
@@ -1061,6 +1473,16 @@ fn wrap_tuple(ident: &Ident, current: TokenStream2) -> TokenStream2 {
     }
 }
 
+/// Parse the tokens after `=` in an attribute up to the next comma so the value can be followed by other attributes
+fn parse_attribute_value(meta: &ParseNestedMeta) -> syn::Result<TokenStream2> {
+    let input = meta.value()?;
+    let mut tokens = TokenStream2::new();
+    while !input.is_empty() && !input.peek(syn::Token![,]) {
+        tokens.extend([input.parse::<proc_macro2::TokenTree>()?]);
+    }
+    Ok(tokens)
+}
+
 fn parse_rename_attribute(meta: &ParseNestedMeta) -> syn::Result<Option<LitStr>> {
     if meta.path.is_ident("rename") {
         let value = meta
@@ -1085,7 +1507,8 @@ impl FieldsParser {
         })
     }
 
-    fn parser(&self, construct: TokenStream2) -> syn::Result<TokenStream2> {
+    /// Create a parser for the fields. The text before the first field is `start` (usually `{ `)
+    fn parser(&self, start: &str, construct: TokenStream2) -> syn::Result<TokenStream2> {
         let mut parsers = Vec::new();
         let idents: Vec<_> = self
             .fields
@@ -1095,7 +1518,7 @@ impl FieldsParser {
         for (i, (field, parser_ident)) in self.fields.iter().zip(idents.iter()).enumerate() {
             let mut literal_text = String::new();
             if i == 0 {
-                literal_text.push_str("{ ");
+                literal_text.push_str(start);
             } else {
                 literal_text.push_str(", ");
             }
@@ -1156,8 +1579,12 @@ impl FieldsParser {
         })
     }
 
+    fn quote_properties(&self) -> impl Iterator<Item = proc_macro2::TokenStream> + '_ {
+        self.fields.iter().map(|field| field.quote_schema())
+    }
+
     fn quote_schema(&self) -> proc_macro2::TokenStream {
-        let properties = self.fields.iter().map(|field| field.quote_schema());
+        let properties = self.quote_properties();
         quote! {
             kalosm_sample::JsonObjectSchema::new(
                 vec![#(#properties),*]
@@ -1351,9 +1778,8 @@ impl Parser {
         let mut attributes = vec!["with", "schema"];
         match &self.ty {
             ParserType::String(_) => attributes.extend(StringParserOptions::ATTRIBUTES),
-            ParserType::Integer(_) | ParserType::Number(_) => {
-                attributes.extend(NumberParserOptions::ATTRIBUTES)
-            }
+            ParserType::Integer(_) => attributes.extend(NumberParserOptions::ATTRIBUTES),
+            ParserType::Number(_) => attributes.extend(NumberParserOptions::FLOAT_ATTRIBUTES),
             ParserType::Boolean(_) => attributes.extend(BoolOptions::ATTRIBUTES),
             _ => {}
         }
@@ -1441,11 +1867,15 @@ impl ToTokens for Parser {
 // - #[parse(character_filter = |c| ...)]
 // - #[parse(len = 1..=10)]
 // - #[parse(pattern = "a+")]
+// - #[parse(format = "email")]
+// - #[parse(format = "url", schemes = ["https"])]
 struct StringParserOptions {
     path: Path,
     character_filter: Option<proc_macro2::TokenStream>,
     len: Option<proc_macro2::TokenStream>,
     pattern: Option<LitStr>,
+    format: Option<LitStr>,
+    schemes: Option<proc_macro2::TokenStream>,
 }
 
 impl Debug for StringParserOptions {
@@ -1454,6 +1884,8 @@ impl Debug for StringParserOptions {
             .field("character_filter", &self.character_filter)
             .field("len", &self.len)
             .field("pattern", &self.pattern.as_ref().map(|p| p.value()))
+            .field("format", &self.format.as_ref().map(|f| f.value()))
+            .field("schemes", &self.schemes)
             .finish()
     }
 }
@@ -1466,7 +1898,9 @@ impl Parse for StringParserOptions {
 }
 
 impl StringParserOptions {
-    const ATTRIBUTES: &'static [&'static str] = &["character_filter", "len", "pattern"];
+    const ATTRIBUTES: &'static [&'static str] =
+        &["character_filter", "len", "pattern", "format", "schemes"];
+    const FORMATS: &'static [&'static str] = &["email", "uuid", "url", "date", "time", "date-time"];
 
     fn apply_attribute(&mut self, input: &syn::meta::ParseNestedMeta) -> syn::Result<bool> {
         if input.path.is_ident("character_filter") {
@@ -1478,6 +1912,19 @@ impl StringParserOptions {
         } else if input.path.is_ident("pattern") {
             self.pattern = Some(input.value()?.parse()?);
             Ok(true)
+        } else if input.path.is_ident("format") {
+            let format: LitStr = input.value()?.parse()?;
+            if !Self::FORMATS.contains(&format.value().as_str()) {
+                return Err(syn::Error::new(
+                    format.span(),
+                    format!("Expected one of the formats {}", Self::FORMATS.join(", ")),
+                ));
+            }
+            self.format = Some(format);
+            Ok(true)
+        } else if input.path.is_ident("schemes") {
+            self.schemes = Some(parse_attribute_value(input)?);
+            Ok(true)
         } else {
             Ok(false)
         }
@@ -1492,6 +1939,8 @@ impl StringParserOptions {
             character_filter: None,
             len: None,
             pattern: None,
+            format: None,
+            schemes: None,
         })
     }
 
@@ -1508,18 +1957,73 @@ impl StringParserOptions {
                 .with_pattern(#pattern)
             }
         });
+        let format = self.format.as_ref().map(|format| {
+            // JSON schema calls urls uris
+            let format = match format.value().as_str() {
+                "url" => LitStr::new("uri", format.span()),
+                _ => format.clone(),
+            };
+            quote_spanned! {
+                format.span() =>
+                .with_format(#format)
+            }
+        });
         let quote = quote_spanned! {
             self.path.span() =>
             kalosm_sample::StringSchema::new()
             #len
             #pattern
+            #format
         };
         quote
     }
+
+    /// Get the parser for the format of the string without quotes
+    fn format_parser(&self, format: &LitStr) -> TokenStream2 {
+        if let Some(schemes) = &self.schemes {
+            if format.value() != "url" {
+                return syn::Error::new(
+                    schemes.span(),
+                    "`schemes` can only be used with the `url` format",
+                )
+                .to_compile_error();
+            }
+        }
+        match format.value().as_str() {
+            "email" => quote! { kalosm_sample::EmailParser::new() },
+            "uuid" => quote! { kalosm_sample::UuidParser::new() },
+            "url" => {
+                let schemes = self.schemes.as_ref().map(|schemes| {
+                    quote! {
+                        .with_schemes(#schemes)
+                    }
+                });
+                quote! { kalosm_sample::UrlParser::new() #schemes }
+            }
+            "date" => quote! { kalosm_sample::DateParser::new() },
+            "time" => quote! { kalosm_sample::TimeParser::new() },
+            _ => quote! { kalosm_sample::DateTimeParser::new() },
+        }
+    }
 }
 
 impl ToTokens for StringParserOptions {
     fn to_tokens(&self, tokens: &mut TokenStream2) {
+        if let Some(format) = &self.format {
+            let parser = self.format_parser(format);
+            let quote = quote_spanned! {
+                format.span() =>
+                kalosm_sample::ParserExt::then_literal(
+                    kalosm_sample::ParserExt::ignore_output_then(
+                        kalosm_sample::LiteralParser::from("\""),
+                        #parser
+                    ),
+                    "\""
+                )
+            };
+            tokens.extend(quote);
+            return;
+        }
         if let Some(pattern) = &self.pattern {
             let pattern_str = pattern.value();
             let mut pattern_str = pattern_str.as_str();
@@ -1703,10 +2207,15 @@ impl ToTokens for NumberType {
 
 // Numbers accept these attributes:
 // - #[parse(range = 0.0..=100.0)]
+// Floats also accept these attributes:
+// - #[parse(max_decimal_places = 3)]
+// - #[parse(scientific_notation)]
 struct NumberParserOptions {
     path: Path,
     ty: NumberType,
     range: Option<proc_macro2::TokenStream>,
+    max_decimal_places: Option<proc_macro2::TokenStream>,
+    scientific_notation: bool,
 }
 
 impl Debug for NumberParserOptions {
@@ -1714,6 +2223,8 @@ impl Debug for NumberParserOptions {
         f.debug_struct("NumberParserOptions")
             .field("ty", &self.ty)
             .field("range", &self.range)
+            .field("max_decimal_places", &self.max_decimal_places)
+            .field("scientific_notation", &self.scientific_notation)
             .finish()
     }
 }
@@ -1733,6 +2244,8 @@ impl NumberParserOptions {
             path: path.clone(),
             ty,
             range: None,
+            max_decimal_places: None,
+            scientific_notation: false,
         })
     }
 }
@@ -1746,11 +2259,24 @@ impl ToTokens for NumberParserOptions {
             };
             quote
         });
+        let max_decimal_places = self.max_decimal_places.as_ref().map(|max_decimal_places| {
+            quote_spanned! {
+                max_decimal_places.span() =>
+                .with_max_decimal_places(#max_decimal_places)
+            }
+        });
+        let scientific_notation = self.scientific_notation.then(|| {
+            quote! {
+                .with_scientific_notation(true)
+            }
+        });
         let ty = &self.ty;
         let quote = quote_spanned! {
             self.path.span() =>
             #ty
             #range
+            #max_decimal_places
+            #scientific_notation
         };
         tokens.extend(quote);
     }
@@ -1758,10 +2284,22 @@ impl ToTokens for NumberParserOptions {
 
 impl NumberParserOptions {
     const ATTRIBUTES: &'static [&'static str] = &["range"];
+    const FLOAT_ATTRIBUTES: &'static [&'static str] =
+        &["range", "max_decimal_places", "scientific_notation"];
+
+    fn is_float(&self) -> bool {
+        matches!(self.ty, NumberType::F64 | NumberType::F32)
+    }
 
     fn apply_attribute(&mut self, input: &syn::meta::ParseNestedMeta) -> syn::Result<bool> {
         if input.path.is_ident("range") {
-            self.range = Some(input.value()?.parse()?);
+            self.range = Some(parse_attribute_value(input)?);
+            Ok(true)
+        } else if self.is_float() && input.path.is_ident("max_decimal_places") {
+            self.max_decimal_places = Some(parse_attribute_value(input)?);
+            Ok(true)
+        } else if self.is_float() && input.path.is_ident("scientific_notation") {
+            self.scientific_notation = true;
             Ok(true)
         } else {
             Ok(false)
@@ -1776,8 +2314,8 @@ impl NumberParserOptions {
                         range.span() =>
                             .with_range({
                                 let range = #range;
-                                let start = range.start() as f64;
-                                let end = range.end() as f64;
+                                let start = *range.start() as f64;
+                                let end = *range.end() as f64;
                                 start..=end
                             })
                     }
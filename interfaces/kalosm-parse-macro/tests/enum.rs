@@ -208,3 +208,118 @@ fn unit_enum_parses() {
         assert_eq!(color, Color::Red);
     }
 }
+
+#[derive(Parse, Schema, Debug, Clone, PartialEq)]
+#[parse(internally_tagged, tag = "kind")]
+enum InternallyTaggedEnum {
+    Person { name: String, age: u32 },
+    Animal,
+}
+
+#[test]
+fn internally_tagged_enum() {
+    use kalosm::language::{CreateParserState, Parser};
+
+    let parser = InternallyTaggedEnum::new_parser();
+    let state = parser.create_parser_state();
+    let person = parser
+        .parse(
+            &state,
+            b"{ \"kind\": \"Person\", \"name\": \"John\", \"age\": 30 } ",
+        )
+        .unwrap()
+        .unwrap_finished();
+    assert_eq!(
+        person,
+        InternallyTaggedEnum::Person {
+            name: "John".to_string(),
+            age: 30
+        }
+    );
+    let animal = parser
+        .parse(&state, b"{ \"kind\": \"Animal\" } ")
+        .unwrap()
+        .unwrap_finished();
+    assert_eq!(animal, InternallyTaggedEnum::Animal);
+
+    let schema = InternallyTaggedEnum::schema();
+    let json = serde_json::from_str::<serde_json::Value>(&schema.to_string()).unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!({
+            "anyOf": [
+                {
+                    "type": "object",
+                    "properties": {
+                        "kind": { "enum": ["Person"] },
+                        "name": { "type": "string" },
+                        "age": { "type": "integer" }
+                    },
+                    "required": ["kind", "name", "age"],
+                    "additionalProperties": false
+                },
+                {
+                    "type": "object",
+                    "properties": {
+                        "kind": { "enum": ["Animal"] }
+                    },
+                    "required": ["kind"],
+                    "additionalProperties": false
+                }
+            ]
+        })
+    );
+}
+
+#[derive(Parse, Schema, Debug, Clone, PartialEq)]
+#[parse(untagged)]
+enum UntaggedEnum {
+    Person { name: String },
+    Count(u32),
+    Nothing,
+}
+
+#[test]
+fn untagged_enum() {
+    use kalosm::language::{CreateParserState, Parser};
+
+    let parser = UntaggedEnum::new_parser();
+    let state = parser.create_parser_state();
+    let person = parser
+        .parse(&state, b"{ \"name\": \"John\" } ")
+        .unwrap()
+        .unwrap_finished();
+    assert_eq!(
+        person,
+        UntaggedEnum::Person {
+            name: "John".to_string()
+        }
+    );
+    let count = parser.parse(&state, b"12 ").unwrap().unwrap_finished();
+    assert_eq!(count, UntaggedEnum::Count(12));
+    let nothing = parser
+        .parse(&state, b"\"Nothing\" ")
+        .unwrap()
+        .unwrap_finished();
+    assert_eq!(nothing, UntaggedEnum::Nothing);
+
+    let schema = UntaggedEnum::schema();
+    let json = serde_json::from_str::<serde_json::Value>(&schema.to_string()).unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!({
+            "anyOf": [
+                {
+                    "type": "object",
+                    "properties": {
+                        "name": { "type": "string" }
+                    },
+                    "required": ["name"],
+                    "additionalProperties": false
+                },
+                { "type": "number" },
+                { "enum": ["Nothing"] }
+            ]
+        })
+    );
+}
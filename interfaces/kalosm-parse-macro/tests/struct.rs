@@ -129,3 +129,180 @@ async fn with_struct() {
     assert!(output.contains("\"name\":"));
     assert!(output.contains("\"field name\":"));
 }
+
+#[derive(Parse, Clone, PartialEq, Debug)]
+#[parse(max_depth = 1)]
+struct TreeNode {
+    value: i64,
+    children: Vec<Self>,
+}
+
+#[test]
+fn recursive_struct() {
+    use kalosm::language::*;
+
+    let parser = TreeNode::new_parser();
+    let state = parser.create_parser_state();
+    let result = parser
+        .parse(
+            &state,
+            br#"{ "value": 1, "children": [{ "value": 2, "children": [] }] }"#,
+        )
+        .unwrap()
+        .unwrap_finished();
+    assert_eq!(
+        result,
+        TreeNode {
+            value: 1,
+            children: vec![TreeNode {
+                value: 2,
+                children: vec![]
+            }]
+        }
+    );
+
+    // Nodes nested deeper than the maximum depth can't have children
+    assert!(parser
+        .parse(
+            &state,
+            br#"{ "value": 1, "children": [{ "value": 2, "children": [{ "value": 3, "children": [{"#
+        )
+        .is_err());
+}
+
+#[derive(Parse, Schema, Clone, PartialEq, Debug)]
+struct FloatStruct {
+    #[parse(range = 0.0..=1.0, max_decimal_places = 2)]
+    score: f64,
+    #[parse(scientific_notation)]
+    distance: f32,
+}
+
+#[test]
+fn float_struct() {
+    use kalosm::language::*;
+
+    let parser = FloatStruct::new_parser();
+    let state = parser.create_parser_state();
+    let result = parser
+        .parse(&state, br#"{ "score": 0.25, "distance": 1.5e3 }"#)
+        .unwrap()
+        .unwrap_finished();
+    assert_eq!(
+        result,
+        FloatStruct {
+            score: 0.25,
+            distance: 1500.0
+        }
+    );
+
+    assert!(parser.parse(&state, br#"{ "score": 0.255"#).is_err());
+    assert!(parser.parse(&state, br#"{ "score": 1.5"#).is_err());
+}
+
+#[derive(Parse, Schema, Clone, PartialEq, Debug)]
+struct Contact {
+    #[parse(format = "email")]
+    email: String,
+    #[parse(format = "url", schemes = ["https"])]
+    website: String,
+}
+
+#[test]
+fn format_struct() {
+    use kalosm::language::*;
+
+    let parser = Contact::new_parser();
+    let state = parser.create_parser_state();
+    let result = parser
+        .parse(
+            &state,
+            br#"{ "email": "jane@example.com", "website": "https://example.com/jane" }"#,
+        )
+        .unwrap()
+        .unwrap_finished();
+    assert_eq!(
+        result,
+        Contact {
+            email: "jane@example.com".to_string(),
+            website: "https://example.com/jane".to_string()
+        }
+    );
+
+    assert!(parser.parse(&state, br#"{ "email": "jane@""#).is_err());
+    assert!(parser
+        .parse(
+            &state,
+            br#"{ "email": "jane@example.com", "website": "http:"#
+        )
+        .is_err());
+
+    assert_eq!(
+        Contact::schema().to_string(),
+        r#"{
+	"title": "Contact",
+	"type": "object",
+	"properties": {
+		"email": {
+			"type": "string",
+			"format": "email"
+		},
+		"website": {
+			"type": "string",
+			"format": "uri"
+		}
+	},
+	"required": ["email", "website"],
+	"additionalProperties": false
+}"#
+    );
+}
+
+#[derive(Parse, Clone, PartialEq, Debug)]
+#[parse(partial)]
+struct Recipe {
+    #[parse(rename = "title")]
+    name: String,
+    #[parse(range = 1..=10)]
+    servings: u32,
+    steps: Vec<String>,
+}
+
+#[test]
+fn partial_struct() {
+    use kalosm::language::*;
+
+    let parser = Recipe::new_partial_parser();
+    let state = parser.create_parser_state();
+    let partial = Recipe::partial(&state);
+    assert_eq!(partial.name, None);
+    assert_eq!(partial.current_field, Some("title"));
+
+    let (state, _) = parser
+        .parse(&state, br#"{ "title": "Toast", "servings": 2, "steps": ["#)
+        .unwrap()
+        .unwrap_incomplete();
+    let partial = Recipe::partial(&state);
+    assert_eq!(partial.name.as_deref(), Some("Toast"));
+    assert_eq!(partial.servings, Some(2));
+    assert_eq!(partial.steps, None);
+    assert_eq!(partial.current_field, Some("steps"));
+
+    // The partial parser uses the same attributes as the normal parser
+    assert!(parser
+        .parse(&state, br#"{ "title": "Toast", "servings": 20"#)
+        .is_err());
+
+    let result = parser
+        .parse(&state, br#""Toast the bread"] }"#)
+        .unwrap()
+        .unwrap_finished();
+    assert_eq!(
+        result,
+        Recipe {
+            name: "Toast".to_string(),
+            servings: 2,
+            steps: vec!["Toast the bread".to_string()]
+        }
+    );
+}
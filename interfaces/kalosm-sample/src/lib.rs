@@ -7,3 +7,5 @@
 
 mod structured_parser;
 pub use structured_parser::*;
+
+pub use serde_json;
@@ -0,0 +1,388 @@
+use std::borrow::Cow;
+
+use crate::{CreateParserState, ParseStatus, Parser};
+
+/// The fence that starts a code block
+const OPENING_FENCE: &[u8] = b"```";
+/// The text that ends a code block
+const CLOSING_FENCE: &[u8] = b"\n```";
+
+/// A markdown code block parsed by a [`CodeBlockParser`] or [`ValidatedCodeBlockParser`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodeBlock<T = ()> {
+    /// The language tag after the opening fence like `json` in `` ```json``. `None` if the code block doesn't have a language tag.
+    pub language: Option<String>,
+    /// The raw text inside of the code block.
+    pub code: String,
+    /// The output of the parser for the code. This is `()` for a [`CodeBlockParser`] that accepts any text.
+    pub value: T,
+}
+
+/// A parser for a markdown fenced code block like:
+///
+/// ````text
+/// ```rust
+/// fn main() {}
+/// ```
+/// ````
+///
+/// The parser captures the language tag and the raw text inside of the code block. Any text is allowed inside of the code block. You can validate the text with [`CodeBlockParser::with_body`].
+///
+/// # Example
+/// ```rust
+/// use kalosm_sample::*;
+///
+/// let parser = CodeBlockParser::new();
+/// let state = parser.create_parser_state();
+/// let block = parser
+///     .parse(&state, b"```rust\nfn main() {}\n```")
+///     .unwrap()
+///     .unwrap_finished();
+/// assert_eq!(block.language.as_deref(), Some("rust"));
+/// assert_eq!(block.code, "fn main() {}");
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CodeBlockParser;
+
+impl CodeBlockParser {
+    /// Create a new code block parser that allows any text inside of the code block.
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Only allow text inside of the code block that the parser accepts. The code block must end immediately after the parser finishes.
+    pub fn with_body<P: CreateParserState>(self, parser: P) -> ValidatedCodeBlockParser<P> {
+        ValidatedCodeBlockParser { body: parser }
+    }
+}
+
+/// A parser for a markdown fenced code block where the text inside of the code block is parsed by another parser.
+///
+/// # Example
+/// ```rust
+/// use kalosm_sample::*;
+///
+/// // Only allow an integer inside the code block
+/// let parser = CodeBlockParser::new().with_body(i64::new_parser());
+/// let state = parser.create_parser_state();
+/// let block = parser
+///     .parse(&state, b"```json\n42\n```")
+///     .unwrap()
+///     .unwrap_finished();
+/// assert_eq!(block.language.as_deref(), Some("json"));
+/// assert_eq!(block.code, "42");
+/// assert_eq!(block.value, 42);
+///
+/// assert!(parser.parse(&state, b"```json\nhello").is_err());
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct ValidatedCodeBlockParser<P> {
+    body: P,
+}
+
+/// The state of a [`CodeBlockParser`] or [`ValidatedCodeBlockParser`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodeBlockParserState<S = (), O = ()> {
+    progress: CodeBlockProgress<S, O>,
+    language: Vec<u8>,
+    code: Vec<u8>,
+}
+
+/// The progress of a code block parser through the code block
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum CodeBlockProgress<S, O> {
+    /// Parsing the opening fence. This is the number of backticks that have been parsed
+    OpeningFence(usize),
+    /// Parsing the language tag until the end of the line
+    Language,
+    /// Parsing any text until the closing fence
+    Text {
+        /// The number of bytes of the closing fence that have been matched
+        fence: usize,
+        /// The number of matched bytes that belong to the code if the closing fence doesn't match
+        held: usize,
+    },
+    /// Parsing the code with the body parser
+    Body(S),
+    /// Parsing the closing fence after the body parser finished
+    ClosingFence {
+        /// The number of bytes of the closing fence that have been parsed
+        offset: usize,
+        /// The output of the body parser
+        value: O,
+    },
+}
+
+impl<S, O> CodeBlockParserState<S, O> {
+    fn new() -> Self {
+        Self {
+            progress: CodeBlockProgress::OpeningFence(0),
+            language: Vec::new(),
+            code: Vec::new(),
+        }
+    }
+
+    /// Parse a byte of the opening fence or the language tag. Returns true once the line with the opening fence is finished.
+    fn parse_header(&mut self, byte: u8) -> Result<bool, CodeBlockParseError> {
+        match &mut self.progress {
+            CodeBlockProgress::OpeningFence(offset) => {
+                if byte != OPENING_FENCE[*offset] {
+                    return Err(CodeBlockParseError::MissingOpeningFence);
+                }
+                *offset += 1;
+                if *offset == OPENING_FENCE.len() {
+                    self.progress = CodeBlockProgress::Language;
+                }
+                Ok(false)
+            }
+            CodeBlockProgress::Language => match byte {
+                b'\n' => Ok(true),
+                b'`' => Err(CodeBlockParseError::InvalidLanguage),
+                _ => {
+                    self.language.push(byte);
+                    Ok(false)
+                }
+            },
+            _ => Ok(true),
+        }
+    }
+
+    /// Get the text that must come next in the code block
+    fn required_next(&self) -> Cow<'static, str> {
+        let required = match &self.progress {
+            CodeBlockProgress::OpeningFence(offset) => &OPENING_FENCE[*offset..],
+            CodeBlockProgress::Text { fence, .. } if *fence > 0 => &CLOSING_FENCE[*fence..],
+            CodeBlockProgress::ClosingFence { offset, .. } => &CLOSING_FENCE[*offset..],
+            _ => &[],
+        };
+        String::from_utf8_lossy(required).into_owned().into()
+    }
+
+    fn finish<T>(self, value: T) -> CodeBlock<T> {
+        let language = String::from_utf8_lossy(&self.language);
+        // Only the first word of the line is the language. The rest of the line may contain other information like a file name
+        let language = language.split_whitespace().next().map(ToString::to_string);
+        CodeBlock {
+            language,
+            code: String::from_utf8_lossy(&self.code).into_owned(),
+            value,
+        }
+    }
+}
+
+/// An error that can occur while parsing a code block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CodeBlockParseError {
+    /// The code block didn't start with a fence
+    MissingOpeningFence,
+    /// The language tag contains a backtick
+    InvalidLanguage,
+    /// The body parser finished but the code block didn't end
+    MissingClosingFence,
+}
+
+impl std::fmt::Display for CodeBlockParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingOpeningFence => write!(f, "Expected a code block to start with ```"),
+            Self::InvalidLanguage => {
+                write!(f, "The language of a code block cannot contain a backtick")
+            }
+            Self::MissingClosingFence => {
+                write!(f, "Expected a code block to end with ``` after the code")
+            }
+        }
+    }
+}
+
+impl std::error::Error for CodeBlockParseError {}
+
+impl CreateParserState for CodeBlockParser {
+    fn create_parser_state(&self) -> <Self as Parser>::PartialState {
+        CodeBlockParserState::new()
+    }
+}
+
+impl Parser for CodeBlockParser {
+    type Output = CodeBlock;
+    type PartialState = CodeBlockParserState;
+
+    fn parse<'a>(
+        &self,
+        state: &Self::PartialState,
+        input: &'a [u8],
+    ) -> crate::ParseResult<ParseStatus<'a, Self::PartialState, Self::Output>> {
+        let mut state = state.clone();
+        for (index, &byte) in input.iter().enumerate() {
+            if let CodeBlockProgress::Text { fence, held } = &mut state.progress {
+                if byte == CLOSING_FENCE[*fence] {
+                    *fence += 1;
+                    *held += 1;
+                    if *fence == CLOSING_FENCE.len() {
+                        return Ok(ParseStatus::Finished {
+                            result: state.finish(()),
+                            remaining: &input[index + 1..],
+                        });
+                    }
+                } else {
+                    // The bytes that looked like the start of the closing fence are part of the code
+                    state
+                        .code
+                        .extend_from_slice(&CLOSING_FENCE[*fence - *held..*fence]);
+                    if byte == CLOSING_FENCE[0] {
+                        *fence = 1;
+                        *held = 1;
+                    } else {
+                        *fence = 0;
+                        *held = 0;
+                        state.code.push(byte);
+                    }
+                }
+            } else if state.parse_header(byte)? {
+                // The newline after the language tag is the start of the closing fence if the code block is empty
+                state.progress = CodeBlockProgress::Text { fence: 1, held: 0 };
+            }
+        }
+
+        Ok(ParseStatus::Incomplete {
+            required_next: state.required_next(),
+            new_state: state,
+        })
+    }
+}
+
+impl<P: CreateParserState> CreateParserState for ValidatedCodeBlockParser<P> {
+    fn create_parser_state(&self) -> <Self as Parser>::PartialState {
+        CodeBlockParserState::new()
+    }
+}
+
+impl<P: CreateParserState> Parser for ValidatedCodeBlockParser<P> {
+    type Output = CodeBlock<P::Output>;
+    type PartialState = CodeBlockParserState<P::PartialState, P::Output>;
+
+    fn parse<'a>(
+        &self,
+        state: &Self::PartialState,
+        mut input: &'a [u8],
+    ) -> crate::ParseResult<ParseStatus<'a, Self::PartialState, Self::Output>> {
+        let mut state = state.clone();
+        while let Some((&byte, rest)) = input.split_first() {
+            match &mut state.progress {
+                CodeBlockProgress::Body(body_state) => {
+                    match self.body.parse(body_state, input)? {
+                        ParseStatus::Finished { result, remaining } => {
+                            let consumed = input.len() - remaining.len();
+                            state.code.extend_from_slice(&input[..consumed]);
+                            state.progress = CodeBlockProgress::ClosingFence {
+                                offset: 0,
+                                value: result,
+                            };
+                            input = remaining;
+                        }
+                        ParseStatus::Incomplete {
+                            new_state,
+                            required_next,
+                        } => {
+                            state.code.extend_from_slice(input);
+                            state.progress = CodeBlockProgress::Body(new_state);
+                            return Ok(ParseStatus::Incomplete {
+                                new_state: state,
+                                required_next,
+                            });
+                        }
+                    }
+                    continue;
+                }
+                CodeBlockProgress::ClosingFence { offset, .. } => {
+                    if byte != CLOSING_FENCE[*offset] {
+                        crate::bail!(CodeBlockParseError::MissingClosingFence);
+                    }
+                    *offset += 1;
+                    if *offset == CLOSING_FENCE.len() {
+                        let CodeBlockProgress::ClosingFence { value, .. } = std::mem::replace(
+                            &mut state.progress,
+                            CodeBlockProgress::OpeningFence(0),
+                        ) else {
+                            unreachable!()
+                        };
+                        return Ok(ParseStatus::Finished {
+                            result: state.finish(value),
+                            remaining: rest,
+                        });
+                    }
+                }
+                _ => {
+                    if state.parse_header(byte)? {
+                        state.progress = CodeBlockProgress::Body(self.body.create_parser_state());
+                    }
+                }
+            }
+            input = rest;
+        }
+
+        Ok(ParseStatus::Incomplete {
+            required_next: state.required_next(),
+            new_state: state,
+        })
+    }
+}
+
+#[test]
+fn code_block_parser() {
+    let parser = CodeBlockParser::new();
+    let state = parser.create_parser_state();
+    for (input, language, code) in [
+        ("```\n```", None, ""),
+        ("```python\nprint(1)\n```", Some("python"), "print(1)"),
+        (
+            "```rust main.rs\n\n\nfn main() {}\n\n```",
+            Some("rust"),
+            "\n\nfn main() {}\n",
+        ),
+        (
+            "```md\n`code` and ``\n`` `\n```",
+            Some("md"),
+            "`code` and ``\n`` `",
+        ),
+    ] {
+        let block = parser
+            .parse(&state, input.as_bytes())
+            .unwrap()
+            .unwrap_finished();
+        assert_eq!(block.language.as_deref(), language);
+        assert_eq!(block.code, code);
+    }
+    let (state, required_next) = parser
+        .parse(&state, b"```\nhello\n`")
+        .unwrap()
+        .unwrap_incomplete();
+    assert_eq!(required_next, "``");
+    assert_eq!(
+        parser.parse(&state, b"``\n").unwrap(),
+        ParseStatus::Finished {
+            result: CodeBlock {
+                language: None,
+                code: "hello".to_string(),
+                value: ()
+            },
+            remaining: b"\n"
+        }
+    );
+    let state = parser.create_parser_state();
+    assert!(parser.parse(&state, b"``json`\n").is_err());
+    assert!(parser.parse(&state, b"\n```").is_err());
+
+    let parser = CodeBlockParser::new().with_body(crate::LiteralParser::new("{}"));
+    let state = parser.create_parser_state();
+    let (state, _) = parser
+        .parse(&state, b"```json\n{")
+        .unwrap()
+        .unwrap_incomplete();
+    let (state, required_next) = parser.parse(&state, b"}\n").unwrap().unwrap_incomplete();
+    assert_eq!(required_next, "```");
+    let block = parser.parse(&state, b"```").unwrap().unwrap_finished();
+    assert_eq!(block.language.as_deref(), Some("json"));
+    assert_eq!(block.code, "{}");
+    assert!(parser.parse(&state, b"``\n").is_err());
+}
@@ -0,0 +1,341 @@
+use std::time::Duration;
+
+use crate::{
+    CreateParserState, LiteralParser, ParseStatus, Parser, ParserExt, RegexParser,
+    RegexParserState, Schema, SchemaType, SendCreateParserState, StringSchema,
+};
+
+/// A leap year in the proleptic Gregorian calendar
+const LEAP_YEAR: &str =
+    r"(?:[0-9]{2}(?:0[48]|[2468][048]|[13579][26])|(?:0[48]|[2468][048]|[13579][26])00|0000)";
+/// A month and day that exist in every year
+const MONTH_DAY: &str = r"(?:(?:0[1-9]|1[0-2])-(?:0[1-9]|1[0-9]|2[0-8])|(?:0[13-9]|1[0-2])-(?:29|30)|(?:0[13578]|1[02])-31)";
+/// A time with optional fractional seconds
+const TIME: &str = r"(?:[01][0-9]|2[0-3]):[0-5][0-9]:[0-5][0-9](?:\.[0-9]{1,9})?";
+/// The offset of a time from UTC
+const OFFSET: &str = r"(?:Z|[+-](?:[01][0-9]|2[0-3]):[0-5][0-9])";
+/// The hours, minutes and seconds of a duration. At least one part is required
+const DURATION_TIME: &str = r"T(?:[0-9]+H(?:[0-9]+M)?(?:[0-9]+(?:\.[0-9]+)?S)?|[0-9]+M(?:[0-9]+(?:\.[0-9]+)?S)?|[0-9]+(?:\.[0-9]+)?S)";
+
+/// A regex for an ISO-8601 date (`2024-02-29`). Only dates that exist are matched.
+pub(crate) fn date_pattern() -> String {
+    format!("(?:[0-9]{{4}}-{MONTH_DAY}|{LEAP_YEAR}-02-29)")
+}
+
+/// A regex for an ISO-8601 time (`13:45:00`) with an optional offset.
+pub(crate) fn time_pattern(offset: bool) -> String {
+    if offset {
+        format!("{TIME}{OFFSET}")
+    } else {
+        TIME.to_string()
+    }
+}
+
+/// A regex for an ISO-8601 date and time (`2024-02-29T13:45:00Z`) with an optional offset.
+pub(crate) fn date_time_pattern(offset: bool) -> String {
+    format!("{}T{}", date_pattern(), time_pattern(offset))
+}
+
+/// A regex for an ISO-8601 duration in weeks, days, hours, minutes and seconds (`P1DT2H30M`).
+pub(crate) fn duration_pattern() -> String {
+    format!("P(?:[0-9]+W|[0-9]+D(?:{DURATION_TIME})?|{DURATION_TIME})")
+}
+
+/// Create a parser that wraps a [`RegexParser`] and outputs the text it matched
+macro_rules! pattern_parser {
+    ($(#[$attr:meta])* $ty:ident) => {
+        $(#[$attr])*
+        pub struct $ty {
+            parser: crate::RegexParser,
+        }
+
+        impl Default for $ty {
+            fn default() -> Self {
+                Self::new()
+            }
+        }
+
+        impl crate::CreateParserState for $ty {
+            fn create_parser_state(&self) -> <Self as crate::Parser>::PartialState {
+                self.parser.create_parser_state()
+            }
+        }
+
+        impl crate::Parser for $ty {
+            type Output = String;
+            type PartialState = crate::RegexParserState;
+
+            fn parse<'a>(
+                &self,
+                state: &Self::PartialState,
+                input: &'a [u8],
+            ) -> crate::ParseResult<crate::ParseStatus<'a, Self::PartialState, Self::Output>> {
+                self.parser.parse(state, input)
+            }
+        }
+    };
+}
+pub(crate) use pattern_parser;
+
+pattern_parser! {
+    /// A parser for an ISO-8601 calendar date like `2024-02-29`.
+    ///
+    /// The month and day are validated, so the parser only accepts dates that exist, including leap days.
+    ///
+    /// # Example
+    /// ```rust
+    /// use kalosm_sample::*;
+    ///
+    /// let parser = DateParser::new();
+    /// let state = parser.create_parser_state();
+    /// let date = parser.parse(&state, b"2024-02-29\"").unwrap().unwrap_finished();
+    /// assert_eq!(date, "2024-02-29");
+    /// // 2023 is not a leap year
+    /// assert!(parser.parse(&state, b"2023-02-29").is_err());
+    /// ```
+    DateParser
+}
+
+impl DateParser {
+    /// Create a new date parser.
+    pub fn new() -> Self {
+        Self {
+            parser: RegexParser::new(&date_pattern()).unwrap(),
+        }
+    }
+}
+
+pattern_parser! {
+    /// A parser for an ISO-8601 time like `13:45:00` or `13:45:00.250`.
+    TimeParser
+}
+
+impl TimeParser {
+    /// Create a new time parser.
+    pub fn new() -> Self {
+        Self {
+            parser: RegexParser::new(&time_pattern(false)).unwrap(),
+        }
+    }
+}
+
+pattern_parser! {
+    /// A parser for an ISO-8601 date and time like `2024-02-29T13:45:00Z`.
+    ///
+    /// By default, the time must end with an offset from UTC like `Z` or `+02:00`. Use [`DateTimeParser::naive`] to parse a date and time without an offset.
+    DateTimeParser
+}
+
+impl DateTimeParser {
+    /// Create a new date and time parser that requires an offset from UTC.
+    pub fn new() -> Self {
+        Self {
+            parser: RegexParser::new(&date_time_pattern(true)).unwrap(),
+        }
+    }
+
+    /// Create a new date and time parser that does not accept an offset from UTC.
+    pub fn naive() -> Self {
+        Self {
+            parser: RegexParser::new(&date_time_pattern(false)).unwrap(),
+        }
+    }
+}
+
+/// A parser for an ISO-8601 duration like `P3D`, `PT1H30M` or `PT0.5S`.
+///
+/// Weeks, days, hours, minutes and seconds are supported. Years and months are not supported because their length depends on the calendar.
+///
+/// # Example
+/// ```rust
+/// use kalosm_sample::*;
+/// use std::time::Duration;
+///
+/// let parser = DurationParser::new();
+/// let state = parser.create_parser_state();
+/// let duration = parser.parse(&state, b"PT1H30M\"").unwrap().unwrap_finished();
+/// assert_eq!(duration, Duration::from_secs(90 * 60));
+/// ```
+pub struct DurationParser {
+    parser: RegexParser,
+}
+
+impl Default for DurationParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DurationParser {
+    /// Create a new duration parser.
+    pub fn new() -> Self {
+        Self {
+            parser: RegexParser::new(&duration_pattern()).unwrap(),
+        }
+    }
+}
+
+impl CreateParserState for DurationParser {
+    fn create_parser_state(&self) -> <Self as Parser>::PartialState {
+        self.parser.create_parser_state()
+    }
+}
+
+impl Parser for DurationParser {
+    type Output = Duration;
+    type PartialState = RegexParserState;
+
+    fn parse<'a>(
+        &self,
+        state: &Self::PartialState,
+        input: &'a [u8],
+    ) -> crate::ParseResult<ParseStatus<'a, Self::PartialState, Self::Output>> {
+        self.parser
+            .parse(state, input)
+            .map(|result| result.map(|duration| parse_duration(&duration)))
+    }
+}
+
+/// Convert a duration that matches the duration pattern into a [`Duration`]. Durations that are too long saturate to [`Duration::MAX`].
+fn parse_duration(duration: &str) -> Duration {
+    let mut total = Duration::ZERO;
+    let mut number = String::new();
+    for char in duration.chars().skip(1) {
+        let seconds_per_unit = match char {
+            'W' => 7 * 24 * 60 * 60,
+            'D' => 24 * 60 * 60,
+            'H' => 60 * 60,
+            'M' => 60,
+            'S' => 1,
+            'T' => continue,
+            _ => {
+                number.push(char);
+                continue;
+            }
+        };
+        let (whole, fraction) = number.split_once('.').unwrap_or((&number, ""));
+        let whole = whole.parse::<u64>().unwrap_or(u64::MAX);
+        let fraction = format!("0.{fraction}").parse::<f64>().unwrap_or_default();
+        total = total
+            .saturating_add(Duration::from_secs(whole).saturating_mul(seconds_per_unit))
+            .saturating_add(Duration::from_secs_f64(fraction * seconds_per_unit as f64));
+        number.clear();
+    }
+    total
+}
+
+/// Durations are parsed as an ISO-8601 duration string like `"PT1H30M"`.
+impl crate::Parse for Duration {
+    fn new_parser() -> impl SendCreateParserState<Output = Self> {
+        LiteralParser::new("\"")
+            .ignore_output_then(DurationParser::new())
+            .then_literal("\"")
+    }
+}
+
+impl Schema for Duration {
+    fn schema() -> SchemaType {
+        SchemaType::String(StringSchema::new().with_format("duration"))
+    }
+}
+
+#[cfg(feature = "chrono")]
+macro_rules! impl_chrono {
+    ($ty:ty, $parser:expr, $format:literal) => {
+        impl crate::Parse for $ty {
+            fn new_parser() -> impl SendCreateParserState<Output = Self> {
+                LiteralParser::new("\"")
+                    .ignore_output_then($parser)
+                    .then_literal("\"")
+                    // The parser only accepts valid values
+                    .map_output(|value| value.parse().unwrap())
+            }
+        }
+
+        impl Schema for $ty {
+            fn schema() -> SchemaType {
+                SchemaType::String(StringSchema::new().with_format($format))
+            }
+        }
+    };
+}
+
+#[cfg(feature = "chrono")]
+impl_chrono!(chrono::NaiveDate, DateParser::new(), "date");
+#[cfg(feature = "chrono")]
+impl_chrono!(chrono::NaiveTime, TimeParser::new(), "time");
+#[cfg(feature = "chrono")]
+impl_chrono!(chrono::NaiveDateTime, DateTimeParser::naive(), "date-time");
+#[cfg(feature = "chrono")]
+impl_chrono!(
+    chrono::DateTime<chrono::Utc>,
+    DateTimeParser::new(),
+    "date-time"
+);
+#[cfg(feature = "chrono")]
+impl_chrono!(
+    chrono::DateTime<chrono::FixedOffset>,
+    DateTimeParser::new(),
+    "date-time"
+);
+
+#[test]
+fn date_time_parsers() {
+    let parser = DateParser::new();
+    let state = parser.create_parser_state();
+    for valid in ["2024-02-29", "2000-02-29", "2023-12-31", "1999-04-30"] {
+        let input = format!("{valid}\"");
+        assert_eq!(
+            parser
+                .parse(&state, input.as_bytes())
+                .unwrap()
+                .unwrap_finished(),
+            valid
+        );
+    }
+    for invalid in [
+        "2023-02-29",
+        "1900-02-29",
+        "2024-04-31",
+        "2024-13-01",
+        "2024-00",
+    ] {
+        assert!(parser.parse(&state, invalid.as_bytes()).is_err());
+    }
+
+    let parser = DateTimeParser::new();
+    let state = parser.create_parser_state();
+    let input = b"2024-02-29T23:59:59.5+05:30\"";
+    assert_eq!(
+        parser.parse(&state, input).unwrap().unwrap_finished(),
+        "2024-02-29T23:59:59.5+05:30"
+    );
+    assert!(parser.parse(&state, b"2024-02-29T24").is_err());
+    assert!(parser.parse(&state, b"2024-02-29T12:00:00\"").is_err());
+    let parser = DateTimeParser::naive();
+    let state = parser.create_parser_state();
+    // The offset isn't part of a naive date and time
+    assert_eq!(
+        parser.parse(&state, b"2024-02-29T12:00:00Z").unwrap(),
+        ParseStatus::Finished {
+            result: "2024-02-29T12:00:00".to_string(),
+            remaining: b"Z"
+        }
+    );
+
+    let parser = DurationParser::new();
+    let state = parser.create_parser_state();
+    assert_eq!(
+        parser.parse(&state, b"P1W\"").unwrap().unwrap_finished(),
+        Duration::from_secs(7 * 24 * 60 * 60)
+    );
+    assert_eq!(
+        parser
+            .parse(&state, b"P1DT2H3M4.5S\"")
+            .unwrap()
+            .unwrap_finished(),
+        Duration::from_secs_f64(((24 + 2) * 60 * 60 + 3 * 60) as f64 + 4.5)
+    );
+    assert!(parser.parse(&state, b"PT\"").is_err());
+    assert!(parser.parse(&state, b"P1Y").is_err());
+}
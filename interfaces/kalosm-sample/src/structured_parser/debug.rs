@@ -0,0 +1,165 @@
+use std::fmt::{Display, Formatter};
+
+use crate::{ParseResult, ParseStatus, Parser};
+
+/// The number of tokens of each kind that are shown when a [`ConstraintReport`] is displayed
+const DISPLAYED_TOKENS: usize = 20;
+
+/// A token that a parser accepted during one step of structured generation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AllowedToken {
+    /// The id of the token.
+    pub token_id: u32,
+    /// The text of the token.
+    pub text: String,
+    /// If the parser finished after the token.
+    pub finished: bool,
+}
+
+/// A token that a parser rejected during one step of structured generation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MaskedToken {
+    /// The id of the token.
+    pub token_id: u32,
+    /// The text of the token.
+    pub text: String,
+    /// Why the token was rejected.
+    pub reason: String,
+}
+
+/// A report of the tokens a parser allowed and masked in one step of structured generation.
+///
+/// Displaying the report shows the text that was generated so far, the text the parser requires next and the reason each token was masked which makes it easier to find out why a constraint can't be satisfied.
+///
+/// # Example
+/// ```rust
+/// use kalosm_sample::*;
+///
+/// let parser = LiteralParser::new("Hello").then(i64::new_parser());
+/// let state = parser.create_parser_state();
+/// let mut report = ConstraintReport::new(&parser, &state, "");
+/// for (token_id, text) in ["Hello", "Hi", "Hello1"].into_iter().enumerate() {
+///     _ = report.check(&parser, &state, token_id as u32, text);
+/// }
+/// assert_eq!(report.required_next, "Hello");
+/// assert_eq!(report.allowed.len(), 2);
+/// assert_eq!(report.masked[0].text, "Hi");
+/// println!("{report}");
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConstraintReport {
+    /// The text that was generated before this step.
+    pub generated: String,
+    /// The text the parser requires next. This is empty if more than one continuation is possible.
+    pub required_next: String,
+    /// The tokens the parser accepted.
+    pub allowed: Vec<AllowedToken>,
+    /// The tokens the parser rejected.
+    pub masked: Vec<MaskedToken>,
+}
+
+impl ConstraintReport {
+    /// Create a new report for the state of a parser after the generated text.
+    pub fn new<P: Parser>(parser: &P, state: &P::PartialState, generated: impl ToString) -> Self {
+        // Parsing no text returns the text that must come next without changing the state
+        let required_next = match parser.parse(state, &[]) {
+            Ok(ParseStatus::Incomplete { required_next, .. }) => required_next.to_string(),
+            _ => String::new(),
+        };
+        Self {
+            generated: generated.to_string(),
+            required_next,
+            allowed: Vec::new(),
+            masked: Vec::new(),
+        }
+    }
+
+    /// Parse the text of a token and record if the parser allowed or masked the token.
+    pub fn check<'a, P: Parser>(
+        &mut self,
+        parser: &P,
+        state: &P::PartialState,
+        token_id: u32,
+        text: &'a str,
+    ) -> ParseResult<ParseStatus<'a, P::PartialState, P::Output>> {
+        let result = parser.parse(state, text.as_bytes());
+        match &result {
+            Ok(status) => self.allowed.push(AllowedToken {
+                token_id,
+                text: text.to_string(),
+                finished: matches!(status, ParseStatus::Finished { .. }),
+            }),
+            Err(err) => self.mask(token_id, text, &**err),
+        }
+        result
+    }
+
+    /// Record a token that was masked for a reason other than the parser rejecting it.
+    pub fn mask(&mut self, token_id: u32, text: impl ToString, reason: impl ToString) {
+        self.masked.push(MaskedToken {
+            token_id,
+            text: text.to_string(),
+            reason: reason.to_string(),
+        });
+    }
+}
+
+impl Display for ConstraintReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Constraint step after {:?}", self.generated)?;
+        if !self.required_next.is_empty() {
+            writeln!(f, "Required next: {:?}", self.required_next)?;
+        }
+
+        writeln!(f, "Allowed {} tokens:", self.allowed.len())?;
+        for token in self.allowed.iter().take(DISPLAYED_TOKENS) {
+            let finished = if token.finished { " (finished)" } else { "" };
+            writeln!(f, "  {:?} ({}){finished}", token.text, token.token_id)?;
+        }
+        if self.allowed.len() > DISPLAYED_TOKENS {
+            writeln!(f, "  ...and {} more", self.allowed.len() - DISPLAYED_TOKENS)?;
+        }
+
+        writeln!(f, "Masked {} tokens:", self.masked.len())?;
+        for token in self.masked.iter().take(DISPLAYED_TOKENS) {
+            writeln!(
+                f,
+                "  {:?} ({}): {}",
+                token.text, token.token_id, token.reason
+            )?;
+        }
+        if self.masked.len() > DISPLAYED_TOKENS {
+            writeln!(f, "  ...and {} more", self.masked.len() - DISPLAYED_TOKENS)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[test]
+fn constraint_report() {
+    use crate::{CreateParserState, LiteralParser, ParserExt};
+
+    let parser = LiteralParser::new("{ \"name\": ").then(crate::StringParser::new(0..=5));
+    let state = parser.create_parser_state();
+    let mut report = ConstraintReport::new(&parser, &state, "prompt");
+    assert_eq!(report.required_next, "{ \"name\": ");
+
+    assert!(report.check(&parser, &state, 0, "{ \"").is_ok());
+    assert!(report.check(&parser, &state, 1, "[").is_err());
+    report.mask(2, "\u{fffd}", "The token isn't valid UTF-8");
+    assert_eq!(
+        report.allowed,
+        vec![AllowedToken {
+            token_id: 0,
+            text: "{ \"".to_string(),
+            finished: false
+        }]
+    );
+    assert_eq!(report.masked.len(), 2);
+    assert_eq!(report.masked[1].reason, "The token isn't valid UTF-8");
+
+    let display = report.to_string();
+    assert!(display.starts_with("Constraint step after \"prompt\"\n"));
+    assert!(display.contains("Masked 2 tokens:\n  \"[\" (1): "));
+}
@@ -1,60 +1,216 @@
 use crate::{CreateParserState, ParseStatus, Parser};
 use std::ops::RangeInclusive;
 
+/// The largest exponent a number written in scientific notation can have. Larger exponents are outside of the range of a f64
+const MAX_EXPONENT: u32 = 400;
+
 #[derive(Debug, PartialEq, Eq, Default, Copy, Clone)]
 enum FloatParserProgress {
     #[default]
     Initial,
     AfterSign,
     AfterDigit,
-    AfterDecimalPoint {
-        digits_after_decimal_point: u32,
-    },
+    AfterDecimalPoint,
+    AfterDecimalDigit,
+    AfterExponent,
+    AfterExponentSign,
+    AfterExponentDigit,
 }
 
 impl FloatParserProgress {
     fn is_after_digit(&self) -> bool {
         matches!(
             self,
-            FloatParserProgress::AfterDigit | FloatParserProgress::AfterDecimalPoint { .. }
+            FloatParserProgress::AfterDigit
+                | FloatParserProgress::AfterDecimalDigit
+                | FloatParserProgress::AfterExponentDigit
+        )
+    }
+
+    fn is_after_exponent(&self) -> bool {
+        matches!(
+            self,
+            FloatParserProgress::AfterExponent
+                | FloatParserProgress::AfterExponentSign
+                | FloatParserProgress::AfterExponentDigit
         )
     }
 }
 
-/// The state of an integer parser.
-#[derive(Debug, PartialEq, Copy, Clone)]
+/// The state of a float parser.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
 pub struct FloatParserState {
     state: FloatParserProgress,
-    value: f64,
+    // The digits of the number without the decimal point
+    mantissa: u64,
+    // The power of ten the mantissa is multiplied by
+    mantissa_exponent: i32,
+    decimal_places: u32,
     positive: bool,
+    exponent: u32,
+    exponent_positive: bool,
 }
 
 impl Default for FloatParserState {
     fn default() -> Self {
         Self {
             state: FloatParserProgress::Initial,
-            value: 0.0,
+            mantissa: 0,
+            mantissa_exponent: 0,
+            decimal_places: 0,
             positive: true,
+            exponent: 0,
+            exponent_positive: true,
+        }
+    }
+}
+
+impl FloatParserState {
+    /// The magnitude of the number with the given exponent.
+    fn magnitude_with_exponent(&self, exponent: i64) -> f64 {
+        // Parsing the digits is exact where adding up the digits would accumulate rounding errors
+        format!(
+            "{}e{}",
+            self.mantissa,
+            i64::from(self.mantissa_exponent) + exponent
+        )
+        .parse()
+        .unwrap()
+    }
+
+    /// The magnitude of the number that has been parsed so far.
+    fn magnitude(&self) -> f64 {
+        let exponent = i64::from(self.exponent);
+        self.magnitude_with_exponent(if self.exponent_positive {
+            exponent
+        } else {
+            -exponent
+        })
+    }
+
+    /// The number that has been parsed so far.
+    fn value(&self) -> f64 {
+        let magnitude = self.magnitude();
+        if self.positive {
+            magnitude
+        } else {
+            -magnitude
         }
     }
+
+    fn push_digit(&mut self, digit: u8) {
+        match self
+            .mantissa
+            .checked_mul(10)
+            .and_then(|mantissa| mantissa.checked_add(u64::from(digit)))
+        {
+            Some(mantissa) => {
+                self.mantissa = mantissa;
+                if self.state == FloatParserProgress::AfterDecimalPoint
+                    || self.state == FloatParserProgress::AfterDecimalDigit
+                {
+                    self.mantissa_exponent -= 1;
+                }
+            }
+            // If the mantissa is full, the rest of the digits are past the precision of a f64. Digits before the decimal point still change the magnitude
+            None => {
+                if self.state == FloatParserProgress::AfterDigit {
+                    self.mantissa_exponent += 1;
+                }
+            }
+        }
+    }
+}
+
+/// An interval of magnitudes that a number could still become.
+struct MagnitudeInterval {
+    start: f64,
+    end: f64,
+    end_inclusive: bool,
+}
+
+impl MagnitudeInterval {
+    fn point(value: f64) -> Self {
+        Self {
+            start: value,
+            end: value,
+            end_inclusive: true,
+        }
+    }
+
+    fn half_open(start: f64, end: f64) -> Self {
+        Self {
+            start,
+            end,
+            end_inclusive: false,
+        }
+    }
+
+    fn overlaps(&self, start: f64, end: f64) -> bool {
+        self.start <= end
+            && if self.end_inclusive {
+                self.end >= start
+            } else {
+                self.end > start
+            }
+    }
 }
 
 /// A parser for a float.
+///
+/// By default, the parser accepts any number of digits after the decimal point and does not accept scientific notation.
+///
+/// # Example
+/// ```rust
+/// use kalosm_sample::*;
+///
+/// let parser = FloatParser::new(0.0..=1.0).with_max_decimal_places(2);
+/// let state = parser.create_parser_state();
+/// let result = parser.parse(&state, b"0.25,").unwrap().unwrap_finished();
+/// assert_eq!(result, 0.25);
+/// // The number has too many digits after the decimal point
+/// assert!(parser.parse(&state, b"0.255").is_err());
+/// // The number is out of range
+/// assert!(parser.parse(&state, b"1.5").is_err());
+/// ```
 #[derive(Debug, PartialEq, Clone)]
 pub struct FloatParser {
     range: RangeInclusive<f64>,
+    max_decimal_places: Option<u32>,
+    scientific_notation: bool,
 }
 
 impl FloatParser {
     /// Create a new float parser.
     pub fn new(range: RangeInclusive<f64>) -> Self {
-        if range.start() > range.end() {
-            Self {
-                range: *range.end()..=*range.start(),
-            }
-        } else {
-            Self { range }
+        Self {
+            range: 0.0..=0.0,
+            max_decimal_places: None,
+            scientific_notation: false,
         }
+        .with_range(range)
+    }
+
+    /// Set the range of numbers the parser accepts.
+    pub(crate) fn with_range(mut self, range: RangeInclusive<f64>) -> Self {
+        self.range = if range.start() > range.end() {
+            *range.end()..=*range.start()
+        } else {
+            range
+        };
+        self
+    }
+
+    /// Set the maximum number of digits the parser accepts after the decimal point. If the maximum is zero, the parser only accepts whole numbers.
+    pub fn with_max_decimal_places(mut self, max_decimal_places: u32) -> Self {
+        self.max_decimal_places = Some(max_decimal_places);
+        self
+    }
+
+    /// Set if the parser accepts numbers in scientific notation like `1.5e-3`. (default: false)
+    pub fn with_scientific_notation(mut self, scientific_notation: bool) -> Self {
+        self.scientific_notation = scientific_notation;
+        self
     }
 }
 
@@ -67,68 +223,112 @@ impl CreateParserState for FloatParser {
 impl FloatParser {
     fn sign_valid(&self, positive: bool) -> bool {
         if positive {
-            *self.range.start() >= 0.0
+            *self.range.end() >= 0.0
         } else {
-            *self.range.end() <= 0.0
+            *self.range.start() < 0.0
         }
     }
 
-    fn is_number_valid(&self, value: f64) -> bool {
-        self.range.contains(&value)
+    fn decimal_point_allowed(&self) -> bool {
+        self.max_decimal_places != Some(0)
     }
 
-    fn could_number_become_valid_before_decimal(
-        &self,
-        value: f64,
-        state: FloatParserProgress,
-    ) -> bool {
-        if self.is_number_valid(value) {
-            true
+    /// The range of magnitudes that are valid for a number with the given sign.
+    fn magnitude_range(&self, positive: bool) -> (f64, f64) {
+        if positive {
+            (self.range.start().max(0.0), *self.range.end())
         } else {
-            let num_with_extra_digit = value * 10.;
-            if value < 0. {
-                if *self.range.start() > num_with_extra_digit {
-                    return false;
+            ((-*self.range.end()).max(0.0), -*self.range.start())
+        }
+    }
+
+    /// Get the magnitudes a number could still become after more characters are added.
+    fn possible_magnitudes(&self, state: &FloatParserState) -> Vec<MagnitudeInterval> {
+        let any = || vec![MagnitudeInterval::half_open(0.0, f64::INFINITY)];
+        let magnitude = state.magnitude();
+        match state.state {
+            FloatParserProgress::Initial | FloatParserProgress::AfterSign => any(),
+            // Any non-zero number can be scaled to any magnitude with an exponent
+            _ if self.scientific_notation && !state.state.is_after_exponent() => any(),
+            FloatParserProgress::AfterDigit => {
+                let mut magnitudes = vec![if self.decimal_point_allowed() {
+                    MagnitudeInterval::half_open(magnitude, magnitude + 1.0)
+                } else {
+                    MagnitudeInterval::point(magnitude)
+                }];
+                // Leading zeros are not allowed, so zero can't have any more digits before the decimal point
+                if state.mantissa != 0 {
+                    magnitudes.push(MagnitudeInterval::half_open(
+                        magnitude * 10.0,
+                        f64::INFINITY,
+                    ));
                 }
-            } else if *self.range.end() < num_with_extra_digit {
-                return false;
+                magnitudes
             }
-            let value_string = value.abs().to_string();
-            let start_value_string = self.range.start().abs().to_string();
-            let end_value_string = self.range.end().abs().to_string();
-            match state {
-                FloatParserProgress::AfterDigit | FloatParserProgress::AfterSign => {
-                    // Check if the digits are within the range so far
-                    let digits = value_string.chars();
-                    let start_digits = start_value_string.chars();
-                    let end_digits = end_value_string.chars();
-                    for (digit, (start_digit, end_digit)) in
-                        digits.zip(start_digits.zip(end_digits))
-                    {
-                        if digit < start_digit || digit > end_digit {
-                            return false;
-                        }
-                    }
+            FloatParserProgress::AfterDecimalPoint | FloatParserProgress::AfterDecimalDigit => {
+                if Some(state.decimal_places) == self.max_decimal_places {
+                    vec![MagnitudeInterval::point(magnitude)]
+                } else {
+                    vec![MagnitudeInterval::half_open(
+                        magnitude,
+                        magnitude + 10.0_f64.powi(-(state.decimal_places as i32)),
+                    )]
                 }
-                _ => {}
             }
-            true
+            FloatParserProgress::AfterExponent | FloatParserProgress::AfterExponentSign => {
+                if state.mantissa == 0 {
+                    return vec![MagnitudeInterval::point(0.0)];
+                }
+                let unscaled = state.magnitude_with_exponent(0);
+                let mut magnitudes = Vec::new();
+                if state.state == FloatParserProgress::AfterExponent || state.exponent_positive {
+                    magnitudes.push(MagnitudeInterval::half_open(unscaled, f64::INFINITY));
+                }
+                if state.state == FloatParserProgress::AfterExponent || !state.exponent_positive {
+                    magnitudes.push(MagnitudeInterval {
+                        start: 0.0,
+                        end: unscaled,
+                        end_inclusive: true,
+                    });
+                }
+                magnitudes
+            }
+            FloatParserProgress::AfterExponentDigit => {
+                let mut magnitudes = vec![MagnitudeInterval::point(magnitude)];
+                let exponent = i64::from(state.exponent);
+                // Leading zeros are allowed in the exponent, so more digits can increase the exponent to any value up to the maximum
+                let smallest_larger_exponent = (exponent * 10).max(1);
+                if state.mantissa != 0 && smallest_larger_exponent <= i64::from(MAX_EXPONENT) {
+                    let largest_larger_exponent = i64::from(MAX_EXPONENT);
+                    let (start, end) = if state.exponent_positive {
+                        (
+                            state.magnitude_with_exponent(smallest_larger_exponent),
+                            state.magnitude_with_exponent(largest_larger_exponent),
+                        )
+                    } else {
+                        (
+                            state.magnitude_with_exponent(-largest_larger_exponent),
+                            state.magnitude_with_exponent(-smallest_larger_exponent),
+                        )
+                    };
+                    magnitudes.push(MagnitudeInterval {
+                        start,
+                        end,
+                        end_inclusive: true,
+                    });
+                }
+                magnitudes
+            }
         }
     }
 
-    fn could_number_become_valid_after_decimal(
-        &self,
-        value: f64,
-        digits_after_decimal_point: u32,
-    ) -> bool {
-        let distance = if value < 0.0 {
-            *self.range.start() - value
-        } else {
-            *self.range.end() - value
-        };
-        println!("Distance: {}", distance);
-
-        distance < 10.0_f64.powi(-(digits_after_decimal_point as i32))
+    fn could_number_become_valid(&self, state: &FloatParserState) -> bool {
+        let (start, end) = self.magnitude_range(state.positive);
+        start <= end
+            && self
+                .possible_magnitudes(state)
+                .iter()
+                .any(|magnitudes| magnitudes.overlaps(start, end))
     }
 }
 
@@ -189,6 +389,39 @@ impl std::fmt::Display for InvalidSignLocation {
 
 impl std::error::Error for InvalidSignLocation {}
 
+/// An error that can occur while parsing a float literal when the number contains an exponent in the wrong place.
+#[derive(Debug)]
+pub struct InvalidExponentLocation;
+
+impl std::fmt::Display for InvalidExponentLocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Failed to parse a number with an exponent before the first digit or multiple exponents"
+        )
+    }
+}
+
+impl std::error::Error for InvalidExponentLocation {}
+
+/// An error that can occur while parsing a float literal when the number has more digits after the decimal point than the parser allows.
+#[derive(Debug)]
+pub struct TooManyDecimalPlaces {
+    max_decimal_places: u32,
+}
+
+impl std::fmt::Display for TooManyDecimalPlaces {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Failed to parse a number with more than {} digits after the decimal point",
+            self.max_decimal_places
+        )
+    }
+}
+
+impl std::error::Error for TooManyDecimalPlaces {}
+
 /// An error that can occur while parsing a float literal when trying to parse a number with no characters.
 #[derive(Debug)]
 pub struct EmptyNumber;
@@ -210,119 +443,100 @@ impl Parser for FloatParser {
         state: &FloatParserState,
         input: &'a [u8],
     ) -> crate::ParseResult<ParseStatus<'a, Self::PartialState, Self::Output>> {
-        let mut value = state.value;
-        let mut positive = state.positive;
-        let mut state = state.state;
+        let mut state = *state;
 
-        for index in 0..input.len() {
-            let input_byte = input[index];
-            let digit = match input_byte {
+        for (index, &input_byte) in input.iter().enumerate() {
+            match input_byte {
                 b'0'..=b'9' => {
-                    if (state == FloatParserProgress::Initial
-                        || state == FloatParserProgress::AfterSign)
-                        && input_byte == b'0'
-                    {
-                        crate::bail!(LeadingZeroError);
+                    let digit = input_byte - b'0';
+                    match state.state {
+                        FloatParserProgress::Initial | FloatParserProgress::AfterSign => {
+                            state.state = FloatParserProgress::AfterDigit;
+                            state.push_digit(digit);
+                        }
+                        FloatParserProgress::AfterDigit => {
+                            if state.mantissa == 0 {
+                                crate::bail!(LeadingZeroError);
+                            }
+                            state.push_digit(digit);
+                        }
+                        FloatParserProgress::AfterDecimalPoint
+                        | FloatParserProgress::AfterDecimalDigit => {
+                            if let Some(max_decimal_places) = self.max_decimal_places {
+                                if state.decimal_places >= max_decimal_places {
+                                    crate::bail!(TooManyDecimalPlaces { max_decimal_places });
+                                }
+                            }
+                            state.state = FloatParserProgress::AfterDecimalDigit;
+                            state.push_digit(digit);
+                            state.decimal_places += 1;
+                        }
+                        FloatParserProgress::AfterExponent
+                        | FloatParserProgress::AfterExponentSign
+                        | FloatParserProgress::AfterExponentDigit => {
+                            state.state = FloatParserProgress::AfterExponentDigit;
+                            state.exponent = state.exponent * 10 + u32::from(digit);
+                            if state.exponent > MAX_EXPONENT {
+                                crate::bail!(OutOfRangeError);
+                            }
+                        }
                     }
-                    input_byte - b'0'
                 }
                 b'.' => {
-                    let value_digits = value.abs().log10() + 1.;
-                    let start_digits = self.range.start().abs().log10() + 1.;
-                    let end_digits = self.range.end().abs().log10() + 1.;
-                    if positive {
-                        if value_digits > end_digits {
-                            crate::bail!(OutOfRangeError);
-                        }
-                    } else if value_digits > start_digits {
-                        crate::bail!(OutOfRangeError);
-                    }
-                    if state == FloatParserProgress::AfterDigit {
-                        state = FloatParserProgress::AfterDecimalPoint {
-                            digits_after_decimal_point: 0,
-                        };
-                        continue;
-                    } else {
+                    if state.state != FloatParserProgress::AfterDigit
+                        || !self.decimal_point_allowed()
+                    {
                         crate::bail!(InvalidDecimalLocation);
                     }
+                    state.state = FloatParserProgress::AfterDecimalPoint;
+                }
+                b'e' | b'E' if self.scientific_notation => {
+                    if state.state != FloatParserProgress::AfterDigit
+                        && state.state != FloatParserProgress::AfterDecimalDigit
+                    {
+                        crate::bail!(InvalidExponentLocation);
+                    }
+                    state.state = FloatParserProgress::AfterExponent;
                 }
                 b'+' | b'-' => {
-                    if state == FloatParserProgress::Initial {
-                        state = FloatParserProgress::AfterSign;
-                        positive = input_byte == b'+';
-
-                        if !self.sign_valid(positive) {
-                            crate::bail!(InvalidSignLocation);
+                    let positive = input_byte == b'+';
+                    match state.state {
+                        FloatParserProgress::Initial => {
+                            if !self.sign_valid(positive) {
+                                crate::bail!(OutOfRangeError);
+                            }
+                            state.state = FloatParserProgress::AfterSign;
+                            state.positive = positive;
                         }
-                        continue;
-                    } else {
-                        crate::bail!(InvalidSignLocation);
+                        FloatParserProgress::AfterExponent => {
+                            state.state = FloatParserProgress::AfterExponentSign;
+                            state.exponent_positive = positive;
+                        }
+                        _ => crate::bail!(InvalidSignLocation),
                     }
                 }
                 _ => {
-                    if state.is_after_digit() {
-                        let result = value * if positive { 1.0 } else { -1.0 };
-                        if self.is_number_valid(result) {
-                            return Ok(ParseStatus::Finished {
-                                result,
-                                remaining: &input[index..],
-                            });
-                        }
-                        return Ok(ParseStatus::Finished {
-                            result,
-                            remaining: &input[index..],
-                        });
-                    } else {
+                    if !state.state.is_after_digit() {
                         crate::bail!(EmptyNumber)
                     }
-                }
-            };
-
-            match &mut state {
-                FloatParserProgress::Initial => {
-                    state = FloatParserProgress::AfterDigit;
-                    value = f64::from(digit);
-                }
-                FloatParserProgress::AfterSign => {
-                    state = FloatParserProgress::AfterDigit;
-                    value = f64::from(digit);
-                }
-                FloatParserProgress::AfterDigit => {
-                    value = value * 10.0 + f64::from(digit);
-
-                    if !self.could_number_become_valid_before_decimal(
-                        value * if positive { 1.0 } else { -1.0 },
-                        FloatParserProgress::AfterDigit,
-                    ) {
-                        crate::bail!(OutOfRangeError);
-                    }
-                }
-                FloatParserProgress::AfterDecimalPoint {
-                    digits_after_decimal_point,
-                } => {
-                    value +=
-                        f64::from(digit) / 10.0_f64.powi(*digits_after_decimal_point as i32 + 1);
-                    *digits_after_decimal_point += 1;
-
-                    let signed_value = value * if positive { 1.0 } else { -1.0 };
-                    if !self.range.contains(&signed_value)
-                        && !self.could_number_become_valid_after_decimal(
-                            signed_value,
-                            *digits_after_decimal_point,
-                        )
-                    {
+                    let result = state.value();
+                    if !self.range.contains(&result) {
                         crate::bail!(OutOfRangeError);
                     }
+                    return Ok(ParseStatus::Finished {
+                        result,
+                        remaining: &input[index..],
+                    });
                 }
             }
+
+            if !self.could_number_become_valid(&state) {
+                crate::bail!(OutOfRangeError);
+            }
         }
 
         Ok(ParseStatus::Incomplete {
-            new_state: FloatParserState {
-                state,
-                value,
-                positive,
-            },
+            new_state: state,
             required_next: Default::default(),
         })
     }
@@ -330,34 +544,20 @@ impl Parser for FloatParser {
 
 #[test]
 fn float_parser() {
-    let parser = FloatParser {
-        range: -100.0..=200.0,
-    };
+    let parser = FloatParser::new(-100.0..=200.0);
     let state = FloatParserState::default();
-    assert_eq!(
-        parser.parse(&state, b"123").unwrap(),
-        ParseStatus::Incomplete {
-            new_state: FloatParserState {
-                state: FloatParserProgress::AfterDigit,
-                value: 123.0,
-                positive: true
-            },
-            required_next: Default::default()
-        }
-    );
-    assert_eq!(
-        parser.parse(&state, b"123.456").unwrap(),
-        ParseStatus::Incomplete {
-            new_state: FloatParserState {
-                state: FloatParserProgress::AfterDecimalPoint {
-                    digits_after_decimal_point: 3
-                },
-                value: 123.456,
-                positive: true
-            },
-            required_next: Default::default()
-        }
-    );
+    let (new_state, required_next) = parser.parse(&state, b"123").unwrap().unwrap_incomplete();
+    assert_eq!(new_state.state, FloatParserProgress::AfterDigit);
+    assert_eq!(new_state.value(), 123.0);
+    assert!(required_next.is_empty());
+    let (new_state, required_next) = parser
+        .parse(&state, b"123.456")
+        .unwrap()
+        .unwrap_incomplete();
+    assert_eq!(new_state.state, FloatParserProgress::AfterDecimalDigit);
+    assert_eq!(new_state.decimal_places, 3);
+    assert_eq!(new_state.value(), 123.456);
+    assert!(required_next.is_empty());
     assert_eq!(
         parser
             .parse(
@@ -382,4 +582,64 @@ fn float_parser() {
         }
     );
     assert!(parser.parse(&state, b"abc").is_err());
+    assert_eq!(
+        parser.parse(&state, b"-0.5x").unwrap(),
+        ParseStatus::Finished {
+            result: -0.5,
+            remaining: b"x"
+        }
+    );
+    assert!(parser.parse(&state, b"01").is_err());
+    assert!(parser.parse(&state, b"-101").is_err());
+    assert!(parser.parse(&state, b"200.1").is_err());
+    // A number that can only reach the range with infinitely many digits is invalid
+    let parser = FloatParser::new(6.0..=10.0);
+    assert!(parser.parse(&state, b"5.").is_err());
+
+    // The number of decimal places can be limited
+    let parser = FloatParser::new(0.0..=1.0).with_max_decimal_places(3);
+    assert_eq!(
+        parser.parse(&state, b"0.125x").unwrap(),
+        ParseStatus::Finished {
+            result: 0.125,
+            remaining: b"x"
+        }
+    );
+    assert!(parser.parse(&state, b"0.1234").is_err());
+    assert!(parser.parse(&state, b"1.001").is_err());
+    let parser = FloatParser::new(0.0..=10.0).with_max_decimal_places(0);
+    assert!(parser.parse(&state, b"1.").is_err());
+
+    // Scientific notation is only allowed if it is enabled
+    let parser = FloatParser::new(-1e10..=1e10);
+    assert_eq!(
+        parser.parse(&state, b"1.5e3").unwrap(),
+        ParseStatus::Finished {
+            result: 1.5,
+            remaining: b"e3"
+        }
+    );
+    let parser = parser.with_scientific_notation(true);
+    assert_eq!(
+        parser.parse(&state, b"1.5e3x").unwrap(),
+        ParseStatus::Finished {
+            result: 1500.0,
+            remaining: b"x"
+        }
+    );
+    assert_eq!(
+        parser.parse(&state, b"-25E-2x").unwrap(),
+        ParseStatus::Finished {
+            result: -0.25,
+            remaining: b"x"
+        }
+    );
+    assert!(parser.parse(&state, b"1e11").is_err());
+    assert!(parser
+        .parse(&state, b"1e")
+        .unwrap()
+        .unwrap_incomplete()
+        .1
+        .is_empty());
+    assert!(parser.parse(&state, b"1ee").is_err());
 }
@@ -0,0 +1,199 @@
+use super::date_time::pattern_parser;
+use crate::RegexParser;
+
+/// A regex for a UUID in the 8-4-4-4-12 hex format.
+pub(crate) fn uuid_pattern() -> String {
+    r"[0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{12}".to_string()
+}
+
+/// A domain name with at least two labels that ends with an alphabetic top level domain
+const DOMAIN: &str = r"(?:[a-zA-Z0-9](?:[a-zA-Z0-9-]{0,61}[a-zA-Z0-9])?\.)+[a-zA-Z](?:[a-zA-Z0-9-]{0,61}[a-zA-Z0-9])?";
+/// The characters allowed in the local part of an email address without quoting
+const EMAIL_ATOM: &str = r"[a-zA-Z0-9!#$%&'*+/=?^_`{|}~-]+";
+/// A character in a URL path, query or fragment
+const URL_CHARACTER: &str = r"(?:[a-zA-Z0-9\-._~!$&'()*+,;=:@]|%[0-9a-fA-F]{2})";
+/// An IPv4 address
+const IPV4: &str = r"(?:(?:25[0-5]|2[0-4][0-9]|1[0-9][0-9]|[1-9]?[0-9])\.){3}(?:25[0-5]|2[0-4][0-9]|1[0-9][0-9]|[1-9]?[0-9])";
+
+/// A regex for an email address with a dot-atom local part like `first.last+tag@example.com`.
+pub(crate) fn email_pattern() -> String {
+    format!(r"{EMAIL_ATOM}(?:\.{EMAIL_ATOM})*@{DOMAIN}")
+}
+
+/// A regex for a URL with a host like `https://example.com/path?query#fragment`. If schemes is `None`, any scheme is allowed.
+pub(crate) fn url_pattern(schemes: Option<&[String]>) -> String {
+    let scheme = match schemes {
+        Some(schemes) => {
+            let schemes = schemes
+                .iter()
+                .map(|scheme| escape(scheme))
+                .collect::<Vec<_>>()
+                .join("|");
+            format!("(?:{schemes})")
+        }
+        None => r"[a-zA-Z][a-zA-Z0-9+.\-]*".to_string(),
+    };
+    format!(
+        r"{scheme}://(?:{DOMAIN}|{IPV4}|localhost)(?::[0-9]{{1,5}})?(?:/{URL_CHARACTER}*)*(?:\?(?:{URL_CHARACTER}|[/?])*)?(?:#(?:{URL_CHARACTER}|[/?])*)?"
+    )
+}
+
+/// Escape any characters in the text that have a special meaning in a regex
+fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for char in text.chars() {
+        if char.is_ascii_punctuation() {
+            escaped.push('\\');
+        }
+        escaped.push(char);
+    }
+    escaped
+}
+
+pattern_parser! {
+    /// A parser for a UUID like `67e55044-10b1-426f-9247-bb680e5fe0c8`.
+    UuidParser
+}
+
+impl UuidParser {
+    /// Create a new UUID parser.
+    pub fn new() -> Self {
+        Self {
+            parser: RegexParser::new(&uuid_pattern()).unwrap(),
+        }
+    }
+}
+
+pattern_parser! {
+    /// A parser for an email address like `first.last@example.com`.
+    ///
+    /// The parser accepts the unquoted form of addresses from RFC 5322. The domain must have a top level domain.
+    ///
+    /// # Example
+    /// ```rust
+    /// use kalosm_sample::*;
+    ///
+    /// let parser = EmailParser::new();
+    /// let state = parser.create_parser_state();
+    /// let email = parser
+    ///     .parse(&state, b"first.last+tag@example.com\"")
+    ///     .unwrap()
+    ///     .unwrap_finished();
+    /// assert_eq!(email, "first.last+tag@example.com");
+    /// assert!(parser.parse(&state, b"first..last@example.com").is_err());
+    /// ```
+    EmailParser
+}
+
+impl EmailParser {
+    /// Create a new email parser.
+    pub fn new() -> Self {
+        Self {
+            parser: RegexParser::new(&email_pattern()).unwrap(),
+        }
+    }
+}
+
+pattern_parser! {
+    /// A parser for a URL with a host like `https://example.com/path?query#fragment`.
+    ///
+    /// By default any scheme is allowed. You can limit the schemes with [`UrlParser::with_schemes`].
+    ///
+    /// # Example
+    /// ```rust
+    /// use kalosm_sample::*;
+    ///
+    /// let parser = UrlParser::new().with_schemes(["https"]);
+    /// let state = parser.create_parser_state();
+    /// let url = parser
+    ///     .parse(&state, b"https://example.com/docs?page=1\"")
+    ///     .unwrap()
+    ///     .unwrap_finished();
+    /// assert_eq!(url, "https://example.com/docs?page=1");
+    /// assert!(parser.parse(&state, b"ftp://example.com").is_err());
+    /// ```
+    UrlParser
+}
+
+impl UrlParser {
+    /// Create a new URL parser that accepts any scheme.
+    pub fn new() -> Self {
+        Self {
+            parser: RegexParser::new(&url_pattern(None)).unwrap(),
+        }
+    }
+
+    /// Only accept URLs with one of these schemes like `https`.
+    pub fn with_schemes(mut self, schemes: impl IntoIterator<Item = impl ToString>) -> Self {
+        let schemes = schemes
+            .into_iter()
+            .map(|scheme| scheme.to_string())
+            .collect::<Vec<_>>();
+        self.parser = RegexParser::new(&url_pattern(Some(&schemes))).unwrap();
+        self
+    }
+}
+
+#[test]
+fn format_parsers() {
+    use crate::{CreateParserState, Parser};
+
+    let parser = UuidParser::new();
+    let state = parser.create_parser_state();
+    assert_eq!(
+        parser
+            .parse(&state, b"67e55044-10b1-426f-9247-bb680e5fe0c8\"")
+            .unwrap()
+            .unwrap_finished(),
+        "67e55044-10b1-426f-9247-bb680e5fe0c8"
+    );
+    assert!(parser.parse(&state, b"67e55044-10b1-426f-9247-bb6").is_ok());
+    assert!(parser.parse(&state, b"67e55044-10b1-426g").is_err());
+
+    let parser = EmailParser::new();
+    let state = parser.create_parser_state();
+    for invalid in [
+        ".first@example.com",
+        "first.@example.com",
+        "first@example",
+        "first@-example.com",
+        "first last@example.com",
+    ] {
+        let input = format!("{invalid}\"");
+        assert!(
+            parser.parse(&state, input.as_bytes()).is_err(),
+            "{invalid} should be invalid"
+        );
+    }
+
+    let parser = UrlParser::new();
+    let state = parser.create_parser_state();
+    for valid in [
+        "http://localhost:8080/",
+        "https://example.com",
+        "git+ssh://192.168.0.1/repo",
+        "https://example.com/a%20b/c?query=1&other=/2#section",
+    ] {
+        let input = format!("{valid}\"");
+        assert_eq!(
+            parser
+                .parse(&state, input.as_bytes())
+                .unwrap()
+                .unwrap_finished(),
+            valid
+        );
+    }
+    // A space ends the URL
+    assert_eq!(
+        parser.parse(&state, b"https://example.com/a b").unwrap(),
+        crate::ParseStatus::Finished {
+            result: "https://example.com/a".to_string(),
+            remaining: b" b"
+        }
+    );
+    assert!(parser.parse(&state, b"https://exa_mple.com").is_err());
+    let parser = UrlParser::new().with_schemes(["https", "git+ssh"]);
+    let state = parser.create_parser_state();
+    assert!(parser.parse(&state, b"git+ssh://example.com\"").is_ok());
+    assert!(parser.parse(&state, b"http://").is_err());
+}
@@ -0,0 +1,575 @@
+use std::{
+    collections::BTreeSet,
+    fmt::{Display, Formatter},
+};
+
+use serde_json::{Map, Number, Value};
+
+use crate::{
+    date_pattern, date_time_pattern, duration_pattern, email_pattern, time_pattern, uuid_pattern,
+    ArcParser, CreateParserState, FloatParser, IntegerParser, LiteralParser, ParseStatus, Parser,
+    ParserExt, RegexParser, SeparatedParser, StringParser,
+};
+
+/// A parser for JSON that matches a [JSON Schema](https://json-schema.org/) document.
+///
+/// This lets schemas that come from other systems constrain generation without writing a [`Parse`](crate::Parse) type by hand. The parser outputs the parsed [`serde_json::Value`].
+///
+/// The parser supports:
+/// - The `string`, `number`, `integer`, `boolean`, `null`, `array` and `object` types, and a list of types
+/// - `minLength`, `maxLength`, `pattern` and the `date-time`, `date`, `time`, `duration`, `email`, `uuid`, `uri`, `hostname` and `ipv4` formats for strings. Patterns must match the whole string. Other formats are treated as plain strings
+/// - `minimum`, `maximum`, `exclusiveMinimum` and `exclusiveMaximum` for numbers. Exclusive bounds are only exact for integers. For other numbers they are treated as inclusive
+/// - `items`, `minItems` and `maxItems` for arrays
+/// - `properties` and `required` for objects. Required properties are generated in the order they are listed in `required`, followed by the optional properties in alphabetical order. Additional properties are never generated
+/// - `enum`, `const`, `oneOf`, `anyOf` and `allOf` with a single schema
+/// - `$ref` to other parts of the same document. Recursive references are not supported
+///
+/// A schema without a type matches any string, number, boolean or null.
+///
+/// # Example
+/// ```rust
+/// use kalosm_sample::*;
+///
+/// let schema = serde_json::json!({
+///     "type": "object",
+///     "properties": {
+///         "name": { "type": "string", "maxLength": 20 },
+///         "age": { "type": "integer", "minimum": 0, "maximum": 150 },
+///         "email": { "type": "string", "format": "email" }
+///     },
+///     "required": ["name", "age"]
+/// });
+/// let parser = JsonSchemaParser::new(&schema).unwrap();
+/// let state = parser.create_parser_state();
+/// let result = parser
+///     .parse(&state, b"{ \"name\": \"Alice\", \"age\": 30 }")
+///     .unwrap()
+///     .unwrap_finished();
+/// assert_eq!(result, serde_json::json!({ "name": "Alice", "age": 30 }));
+/// ```
+#[derive(Clone)]
+pub struct JsonSchemaParser {
+    parser: ArcParser<Value>,
+}
+
+impl JsonSchemaParser {
+    /// Create a new parser from a JSON Schema document.
+    pub fn new(schema: &Value) -> Result<Self, JsonSchemaError> {
+        let compiler = SchemaCompiler {
+            root: schema,
+            references: Vec::new(),
+        };
+        Ok(Self {
+            parser: compiler.compile(schema)?,
+        })
+    }
+}
+
+impl CreateParserState for JsonSchemaParser {
+    fn create_parser_state(&self) -> <Self as Parser>::PartialState {
+        self.parser.create_parser_state()
+    }
+}
+
+impl Parser for JsonSchemaParser {
+    type Output = Value;
+    type PartialState = <ArcParser<Value> as Parser>::PartialState;
+
+    fn parse<'a>(
+        &self,
+        state: &Self::PartialState,
+        input: &'a [u8],
+    ) -> crate::ParseResult<ParseStatus<'a, Self::PartialState, Self::Output>> {
+        self.parser.parse(state, input)
+    }
+}
+
+/// An error that can occur when creating a parser from a JSON Schema document.
+#[derive(Debug)]
+pub enum JsonSchemaError {
+    /// The schema uses a type or keyword that the parser doesn't support.
+    Unsupported(String),
+    /// The schema is not valid.
+    InvalidSchema(String),
+    /// A `$ref` points to a part of the document that doesn't exist.
+    UnresolvedReference(String),
+    /// A `$ref` points back to a schema that contains it.
+    RecursiveReference(String),
+    /// A string pattern or format could not be compiled.
+    Regex(Box<regex_automata::dfa::dense::BuildError>),
+}
+
+impl Display for JsonSchemaError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JsonSchemaError::Unsupported(feature) => {
+                write!(f, "Unsupported JSON Schema feature: {feature}")
+            }
+            JsonSchemaError::InvalidSchema(message) => write!(f, "Invalid JSON Schema: {message}"),
+            JsonSchemaError::UnresolvedReference(reference) => {
+                write!(f, "Failed to resolve the reference {reference}")
+            }
+            JsonSchemaError::RecursiveReference(reference) => {
+                write!(f, "The reference {reference} is recursive")
+            }
+            JsonSchemaError::Regex(err) => write!(f, "Failed to compile string pattern: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for JsonSchemaError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            JsonSchemaError::Regex(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// The fields of an object that have been parsed so far.
+type Fields = Vec<(String, Value)>;
+
+struct SchemaCompiler<'a> {
+    root: &'a Value,
+    /// The references that are being compiled. Used to detect recursive references
+    references: Vec<String>,
+}
+
+impl<'a> SchemaCompiler<'a> {
+    fn compile(&self, schema: &'a Value) -> Result<ArcParser<Value>, JsonSchemaError> {
+        let schema = match schema {
+            Value::Bool(true) => return Ok(scalar_parser()),
+            Value::Bool(false) => {
+                return Err(JsonSchemaError::Unsupported(
+                    "the false schema which matches nothing".to_string(),
+                ))
+            }
+            Value::Object(schema) => schema,
+            _ => {
+                return Err(JsonSchemaError::InvalidSchema(format!(
+                    "expected an object or boolean but found {schema}"
+                )))
+            }
+        };
+
+        if let Some(reference) = schema.get("$ref") {
+            return self.compile_reference(reference);
+        }
+        if let Some(value) = schema.get("const") {
+            return Ok(literal_value_parser(value));
+        }
+        if let Some(values) = schema.get("enum") {
+            let values = values.as_array().ok_or_else(|| {
+                JsonSchemaError::InvalidSchema("enum must be an array".to_string())
+            })?;
+            return self.choice(values.iter().map(|value| Ok(literal_value_parser(value))));
+        }
+        for keyword in ["oneOf", "anyOf"] {
+            if let Some(schemas) = schema.get(keyword) {
+                let schemas = schemas.as_array().ok_or_else(|| {
+                    JsonSchemaError::InvalidSchema(format!("{keyword} must be an array"))
+                })?;
+                return self.choice(schemas.iter().map(|schema| self.compile(schema)));
+            }
+        }
+        if let Some(schemas) = schema.get("allOf") {
+            return match schemas.as_array().map(Vec::as_slice) {
+                Some([schema]) => self.compile(schema),
+                _ => Err(JsonSchemaError::Unsupported(
+                    "allOf with more than one schema".to_string(),
+                )),
+            };
+        }
+
+        match schema.get("type") {
+            Some(Value::String(ty)) => self.compile_type(ty, schema),
+            Some(Value::Array(types)) => self.choice(types.iter().map(|ty| match ty {
+                Value::String(ty) => self.compile_type(ty, schema),
+                _ => Err(JsonSchemaError::InvalidSchema(format!(
+                    "expected a type name but found {ty}"
+                ))),
+            })),
+            Some(ty) => Err(JsonSchemaError::InvalidSchema(format!(
+                "expected a type name but found {ty}"
+            ))),
+            // Infer the type from the keywords used in the schema
+            None if schema.contains_key("properties") => self.compile_type("object", schema),
+            None if schema.contains_key("items") => self.compile_type("array", schema),
+            None => Ok(scalar_parser()),
+        }
+    }
+
+    fn compile_reference(&self, reference: &Value) -> Result<ArcParser<Value>, JsonSchemaError> {
+        let reference = reference.as_str().ok_or_else(|| {
+            JsonSchemaError::InvalidSchema(format!("expected a string $ref but found {reference}"))
+        })?;
+        if self.references.iter().any(|parent| parent == reference) {
+            return Err(JsonSchemaError::RecursiveReference(reference.to_string()));
+        }
+        let pointer = reference
+            .strip_prefix('#')
+            .ok_or_else(|| JsonSchemaError::UnresolvedReference(reference.to_string()))?;
+        let schema = self
+            .root
+            .pointer(pointer)
+            .ok_or_else(|| JsonSchemaError::UnresolvedReference(reference.to_string()))?;
+        let mut references = self.references.clone();
+        references.push(reference.to_string());
+        SchemaCompiler {
+            root: self.root,
+            references,
+        }
+        .compile(schema)
+    }
+
+    fn compile_type(
+        &self,
+        ty: &str,
+        schema: &'a Map<String, Value>,
+    ) -> Result<ArcParser<Value>, JsonSchemaError> {
+        match ty {
+            "string" => string_parser(schema),
+            "number" => {
+                let (minimum, maximum) = bounds(schema, f64::MIN, f64::MAX, 0.0)?;
+                Ok(FloatParser::new(minimum..=maximum)
+                    .map_output(|number| {
+                        Number::from_f64(number).map_or(Value::Null, Value::Number)
+                    })
+                    .boxed())
+            }
+            "integer" => {
+                let (minimum, maximum) = bounds(schema, i64::MIN as f64, i64::MAX as f64, 1.0)?;
+                Ok(
+                    IntegerParser::new(minimum.ceil() as i128..=maximum.floor() as i128)
+                        .map_output(|number| Value::Number((number as i64).into()))
+                        .boxed(),
+                )
+            }
+            "boolean" => Ok(boolean_parser()),
+            "null" => Ok(literal_value_parser(&Value::Null)),
+            "array" => self.array_parser(schema),
+            "object" => self.object_parser(schema),
+            _ => Err(JsonSchemaError::InvalidSchema(format!(
+                "unknown type {ty:?}"
+            ))),
+        }
+    }
+
+    fn array_parser(
+        &self,
+        schema: &'a Map<String, Value>,
+    ) -> Result<ArcParser<Value>, JsonSchemaError> {
+        if schema.contains_key("prefixItems") {
+            return Err(JsonSchemaError::Unsupported("prefixItems".to_string()));
+        }
+        let items = match schema.get("items") {
+            Some(items) => self.compile(items)?,
+            None => scalar_parser(),
+        };
+        let min_items = usize_keyword(schema, "minItems")?.unwrap_or(0);
+        let max_items = usize_keyword(schema, "maxItems")?.unwrap_or(usize::MAX);
+        Ok(LiteralParser::new("[")
+            .ignore_output_then(SeparatedParser::new(
+                items,
+                LiteralParser::new(", "),
+                min_items..=max_items,
+            ))
+            .then_literal("]")
+            .map_output(Value::Array)
+            .boxed())
+    }
+
+    fn object_parser(
+        &self,
+        schema: &'a Map<String, Value>,
+    ) -> Result<ArcParser<Value>, JsonSchemaError> {
+        let empty = Map::new();
+        let properties = match schema.get("properties") {
+            Some(Value::Object(properties)) => properties,
+            Some(_) => {
+                return Err(JsonSchemaError::InvalidSchema(
+                    "properties must be an object".to_string(),
+                ))
+            }
+            None => &empty,
+        };
+        let mut required = Vec::new();
+        if let Some(names) = schema.get("required") {
+            let names = names.as_array().ok_or_else(|| {
+                JsonSchemaError::InvalidSchema("required must be an array".to_string())
+            })?;
+            for name in names {
+                let name = name.as_str().ok_or_else(|| {
+                    JsonSchemaError::InvalidSchema(format!(
+                        "expected a property name but found {name}"
+                    ))
+                })?;
+                if !required.contains(&name) {
+                    required.push(name);
+                }
+            }
+        }
+        let required_set: BTreeSet<_> = required.iter().copied().collect();
+
+        let mut ordered = Vec::new();
+        for name in &required {
+            // Required properties that are not listed in the properties can be any value
+            let parser = match properties.get(*name) {
+                Some(property) => self.compile(property)?,
+                None => scalar_parser(),
+            };
+            ordered.push((name.to_string(), parser, true));
+        }
+        for (name, property) in properties {
+            if !required_set.contains(name.as_str()) {
+                ordered.push((name.clone(), self.compile(property)?, false));
+            }
+        }
+
+        // Build the parser for the properties from the end of the object to the start. Each optional property can be skipped,
+        // so we keep a parser for the rest of the object both after some properties have been written and before any property has been written
+        let mut rest_after_property: ArcParser<Fields> =
+            LiteralParser::new(" }").map_output(|_| Vec::new()).boxed();
+        let mut rest_before_any_property: ArcParser<Fields> =
+            LiteralParser::new("{}").map_output(|_| Vec::new()).boxed();
+        for (name, parser, required) in ordered.into_iter().rev() {
+            let key = Value::String(name.clone()).to_string();
+            let property = |prefix: &str, rest: ArcParser<Fields>| {
+                let name = name.clone();
+                LiteralParser::new(format!("{prefix}{key}: "))
+                    .ignore_output_then(parser.clone())
+                    .then(rest)
+                    .map_output(move |(value, mut fields)| {
+                        fields.insert(0, (name.clone(), value));
+                        fields
+                    })
+                    .boxed()
+            };
+            let after_property = property(", ", rest_after_property.clone());
+            let before_any_property = property("{ ", rest_after_property.clone());
+            if required {
+                rest_after_property = after_property;
+                rest_before_any_property = before_any_property;
+            } else {
+                rest_after_property = after_property.or(rest_after_property).boxed();
+                rest_before_any_property = before_any_property.or(rest_before_any_property).boxed();
+            }
+        }
+
+        Ok(rest_before_any_property
+            .map_output(|fields| Value::Object(fields.into_iter().collect()))
+            .boxed())
+    }
+
+    fn choice(
+        &self,
+        parsers: impl IntoIterator<Item = Result<ArcParser<Value>, JsonSchemaError>>,
+    ) -> Result<ArcParser<Value>, JsonSchemaError> {
+        let mut parsers = parsers.into_iter();
+        let mut choice = parsers.next().ok_or_else(|| {
+            JsonSchemaError::InvalidSchema("expected at least one option".to_string())
+        })??;
+        for parser in parsers {
+            choice = choice.or(parser?).boxed();
+        }
+        Ok(choice)
+    }
+}
+
+/// Get the inclusive range of a number from the minimum and maximum keywords. Exclusive bounds are moved inwards by `exclusive_step`
+fn bounds(
+    schema: &Map<String, Value>,
+    default_minimum: f64,
+    default_maximum: f64,
+    exclusive_step: f64,
+) -> Result<(f64, f64), JsonSchemaError> {
+    let number = |keyword: &str| {
+        schema
+            .get(keyword)
+            .map(|value| {
+                value.as_f64().ok_or_else(|| {
+                    JsonSchemaError::InvalidSchema(format!(
+                        "{keyword} must be a number but found {value}"
+                    ))
+                })
+            })
+            .transpose()
+    };
+    let mut minimum = number("minimum")?.unwrap_or(default_minimum);
+    let mut maximum = number("maximum")?.unwrap_or(default_maximum);
+    if let Some(exclusive_minimum) = number("exclusiveMinimum")? {
+        minimum = minimum.max(exclusive_minimum + exclusive_step);
+    }
+    if let Some(exclusive_maximum) = number("exclusiveMaximum")? {
+        maximum = maximum.min(exclusive_maximum - exclusive_step);
+    }
+    if minimum > maximum {
+        return Err(JsonSchemaError::InvalidSchema(format!(
+            "the minimum {minimum} is larger than the maximum {maximum}"
+        )));
+    }
+    Ok((minimum, maximum))
+}
+
+fn usize_keyword(
+    schema: &Map<String, Value>,
+    keyword: &str,
+) -> Result<Option<usize>, JsonSchemaError> {
+    schema
+        .get(keyword)
+        .map(|value| {
+            value.as_u64().map(|value| value as usize).ok_or_else(|| {
+                JsonSchemaError::InvalidSchema(format!(
+                    "{keyword} must be a non-negative integer but found {value}"
+                ))
+            })
+        })
+        .transpose()
+}
+
+fn string_parser(schema: &Map<String, Value>) -> Result<ArcParser<Value>, JsonSchemaError> {
+    let pattern = match schema.get("pattern") {
+        Some(Value::String(pattern)) => {
+            // Patterns must match the whole string, so the anchors are redundant
+            let pattern = pattern.strip_prefix('^').unwrap_or(pattern);
+            let pattern = match pattern.strip_suffix('$') {
+                Some(stripped) if !stripped.ends_with('\\') => stripped,
+                _ => pattern,
+            };
+            Some(pattern.to_string())
+        }
+        Some(pattern) => {
+            return Err(JsonSchemaError::InvalidSchema(format!(
+                "pattern must be a string but found {pattern}"
+            )))
+        }
+        None => schema
+            .get("format")
+            .and_then(Value::as_str)
+            .and_then(format_pattern),
+    };
+
+    match pattern {
+        Some(pattern) => {
+            let parser = RegexParser::new(&format!("\"(?:{pattern})\""))
+                .map_err(|err| JsonSchemaError::Regex(Box::new(err)))?;
+            Ok(parser
+                .map_output(|string| {
+                    serde_json::from_str(&string)
+                        .unwrap_or_else(|_| Value::String(string[1..string.len() - 1].to_string()))
+                })
+                .boxed())
+        }
+        None => {
+            let min_length = usize_keyword(schema, "minLength")?.unwrap_or(0);
+            let max_length = usize_keyword(schema, "maxLength")?.unwrap_or(usize::MAX);
+            Ok(StringParser::new(min_length..=max_length)
+                .map_output(Value::String)
+                .boxed())
+        }
+    }
+}
+
+/// Get the regex for a string format
+fn format_pattern(format: &str) -> Option<String> {
+    Some(match format {
+        "date" => date_pattern(),
+        "time" => time_pattern(true),
+        "date-time" => date_time_pattern(true),
+        "duration" => duration_pattern(),
+        "email" => email_pattern(),
+        "uuid" => uuid_pattern(),
+        "uri" => r#"[a-zA-Z][a-zA-Z0-9+.-]*:[^\s"\\]+"#.to_string(),
+        "hostname" => {
+            r"[a-zA-Z0-9]([a-zA-Z0-9-]{0,61}[a-zA-Z0-9])?(\.[a-zA-Z0-9]([a-zA-Z0-9-]{0,61}[a-zA-Z0-9])?)*".to_string()
+        }
+        "ipv4" => {
+            r"((25[0-5]|2[0-4][0-9]|1[0-9][0-9]|[1-9]?[0-9])\.){3}(25[0-5]|2[0-4][0-9]|1[0-9][0-9]|[1-9]?[0-9])".to_string()
+        }
+        _ => return None,
+    })
+}
+
+fn literal_value_parser(value: &Value) -> ArcParser<Value> {
+    let value = value.clone();
+    LiteralParser::new(value.to_string())
+        .map_output(move |_| value.clone())
+        .boxed()
+}
+
+fn boolean_parser() -> ArcParser<Value> {
+    literal_value_parser(&Value::Bool(true))
+        .or(literal_value_parser(&Value::Bool(false)))
+        .boxed()
+}
+
+/// A parser for any string, number, boolean or null
+fn scalar_parser() -> ArcParser<Value> {
+    StringParser::new(0..=usize::MAX)
+        .map_output(Value::String)
+        .boxed()
+        .or(FloatParser::new(f64::MIN..=f64::MAX)
+            .map_output(|number| Number::from_f64(number).map_or(Value::Null, Value::Number))
+            .boxed())
+        .or(boolean_parser())
+        .or(literal_value_parser(&Value::Null))
+        .boxed()
+}
+
+#[test]
+fn test_json_schema_parser() {
+    use serde_json::json;
+
+    let schema = json!({
+        "$defs": {
+            "tag": { "enum": ["red", "green", 3] }
+        },
+        "type": "object",
+        "properties": {
+            "id": { "type": "string", "format": "uuid" },
+            "score": { "type": "integer", "minimum": 0, "exclusiveMaximum": 10 },
+            "tags": { "type": "array", "items": { "$ref": "#/$defs/tag" }, "minItems": 1, "maxItems": 2 },
+            "nickname": { "type": "string" },
+            "extra": { "oneOf": [{ "type": "null" }, { "type": "boolean" }] }
+        },
+        "required": ["score", "tags"]
+    });
+    let parser = JsonSchemaParser::new(&schema).unwrap();
+    let state = parser.create_parser_state();
+
+    let parse = |input: &str| {
+        parser
+            .parse(&state, input.as_bytes())
+            .map(|result| result.unwrap_finished())
+    };
+
+    assert_eq!(
+        parse(r#"{ "score": 9, "tags": ["red", 3] }"#).unwrap(),
+        json!({ "score": 9, "tags": ["red", 3] })
+    );
+    assert_eq!(
+        parse(r#"{ "score": 0, "tags": ["green"], "extra": null, "id": "123e4567-e89b-12d3-a456-426614174000" }"#)
+            .unwrap(),
+        json!({ "score": 0, "tags": ["green"], "extra": null, "id": "123e4567-e89b-12d3-a456-426614174000" })
+    );
+    assert_eq!(
+        parse(r#"{ "score": 1, "tags": ["red"], "nickname": "Al" }"#).unwrap(),
+        json!({ "score": 1, "tags": ["red"], "nickname": "Al" })
+    );
+    // Out of range numbers, unknown enum values, too many items and invalid formats are rejected
+    assert!(parse(r#"{ "score": 10"#).is_err());
+    assert!(parse(r#"{ "score": 1, "tags": ["blue"#).is_err());
+    assert!(parse(r#"{ "score": 1, "tags": ["red", "red", "#).is_err());
+    assert!(parse(r#"{ "score": 1, "tags": ["red"], "id": "xyz"#).is_err());
+    // Required properties must come first
+    assert!(parse(r#"{ "tags""#).is_err());
+
+    let recursive = json!({
+        "$defs": { "node": { "type": "array", "items": { "$ref": "#/$defs/node" } } },
+        "$ref": "#/$defs/node"
+    });
+    assert!(matches!(
+        JsonSchemaParser::new(&recursive),
+        Err(JsonSchemaError::RecursiveReference(_))
+    ));
+}
@@ -46,6 +46,20 @@ mod index;
 pub use index::*;
 mod one_line;
 pub use one_line::*;
+mod json_schema;
+pub use json_schema::*;
+mod recursive;
+pub use recursive::*;
+mod date_time;
+pub use date_time::*;
+mod formats;
+pub use formats::*;
+mod partial;
+pub use partial::*;
+mod code_block;
+pub use code_block::*;
+mod debug;
+pub use debug::*;
 
 /// An error that occurred while parsing.
 #[derive(Debug, Clone)]
@@ -1,6 +1,7 @@
 use crate::{CreateParserState, SendCreateParserState, SeparatedParser};
 use crate::{
-    IntegerParser, LiteralParser, ParseStatus, Parser, ParserExt, SequenceParser, StringParser,
+    FloatParser, IntegerParser, LiteralParser, ParseStatus, Parser, ParserExt, SequenceParser,
+    StringParser,
 };
 
 /// Data that can be parsed incrementally.
@@ -152,6 +153,103 @@ int_parser!(I16Parser, i16, test_i16);
 int_parser!(I32Parser, i32, test_i32);
 int_parser!(I64Parser, i64, test_i64);
 
+macro_rules! float_parser {
+    ($ty:ident, $num:ty, $test:ident) => {
+        #[doc = "A parser for `"]
+        #[doc = stringify!($num)]
+        #[doc = "`."]
+        #[derive(Clone, Debug)]
+        pub struct $ty {
+            parser: FloatParser,
+        }
+
+        impl $ty {
+            /// Create a new parser.
+            pub fn new() -> Self {
+                Self::default()
+            }
+
+            /// Set the range of the numbers that this parser can parse.
+            pub fn with_range(mut self, range: std::ops::RangeInclusive<$num>) -> Self {
+                let start = range.start();
+                let end = range.end();
+                self.parser = self.parser.with_range(*start as f64..=*end as f64);
+                self
+            }
+
+            /// Set the maximum number of digits this parser accepts after the decimal point.
+            pub fn with_max_decimal_places(mut self, max_decimal_places: u32) -> Self {
+                self.parser = self.parser.with_max_decimal_places(max_decimal_places);
+                self
+            }
+
+            /// Set if this parser accepts numbers in scientific notation like `1.5e-3`. (default: false)
+            pub fn with_scientific_notation(mut self, scientific_notation: bool) -> Self {
+                self.parser = self.parser.with_scientific_notation(scientific_notation);
+                self
+            }
+        }
+
+        impl Default for $ty {
+            fn default() -> Self {
+                Self {
+                    parser: FloatParser::new((<$num>::MIN as f64)..=(<$num>::MAX as f64)),
+                }
+            }
+        }
+
+        impl CreateParserState for $ty {
+            fn create_parser_state(&self) -> <Self as Parser>::PartialState {
+                self.parser.create_parser_state()
+            }
+        }
+
+        impl Parser for $ty {
+            type Output = $num;
+            type PartialState = <FloatParser as Parser>::PartialState;
+
+            fn parse<'a>(
+                &self,
+                state: &Self::PartialState,
+                input: &'a [u8],
+            ) -> crate::ParseResult<ParseStatus<'a, Self::PartialState, Self::Output>> {
+                self.parser
+                    .parse(state, input)
+                    .map(|result| result.map(|output| output as $num))
+            }
+        }
+
+        impl Parse for $num {
+            fn new_parser() -> impl SendCreateParserState<Output = Self> {
+                $ty::default()
+            }
+        }
+
+        #[test]
+        fn $test() {
+            let parser = <$num as Parse>::new_parser();
+            let state = parser.create_parser_state();
+            for _ in 0..100 {
+                let input = rand::random::<$num>() * 1000.0 - 500.0;
+                let input_str = input.to_string() + "\n";
+                let result = parser.parse(&state, input_str.as_bytes());
+                if let ParseStatus::Finished {
+                    result,
+                    remaining: b"\n",
+                } = result.unwrap()
+                {
+                    assert_eq!(result, input);
+                } else {
+                    panic!("Parser did not finish");
+                }
+            }
+        }
+    };
+}
+
+float_parser!(F64Parser, f64, test_f64);
+float_parser!(F32Parser, f32, test_f32);
+
 impl Parse for String {
     fn new_parser() -> impl SendCreateParserState<Output = Self> {
         StringParser::new(0..=usize::MAX)
@@ -0,0 +1,301 @@
+use std::{any::Any, borrow::Cow, sync::Arc};
+
+use crate::{
+    ArcParser, CreateParserState, LiteralParser, LiteralParserOffset, Parse, ParseStatus, Parser,
+    ParserExt, SendCreateParserState,
+};
+
+/// The value of a field. Values are stored without their type so fields of different types can be stored together
+type AnyValue = Arc<dyn Any + Send + Sync>;
+
+/// Data that can be read while it is still being parsed.
+///
+/// Streaming UIs can use this to render the fields of a struct as soon as they are generated instead of waiting for the whole struct to finish.
+///
+/// You can derive this trait for structs with named fields with the `#[parse(partial)]` attribute. The derive creates a struct with the same name followed by `Partial` where every field is optional.
+///
+/// # Example
+/// ```rust
+/// use kalosm_sample::*;
+///
+/// #[derive(Parse, Clone)]
+/// #[parse(partial)]
+/// struct Person {
+///     name: String,
+///     age: u32,
+/// }
+///
+/// let parser = Person::new_partial_parser();
+/// let state = parser.create_parser_state();
+/// let (state, _) = parser
+///     .parse(&state, br#"{ "name": "Alice", "age": 3"#)
+///     .unwrap()
+///     .unwrap_incomplete();
+/// let partial: PersonPartial = Person::partial(&state);
+/// assert_eq!(partial.name.as_deref(), Some("Alice"));
+/// assert_eq!(partial.age, None);
+/// assert_eq!(partial.current_field, Some("age"));
+/// ```
+pub trait PartialParse: Parse {
+    /// A version of the type where every field is optional.
+    type Partial: Clone + Default + Send + Sync;
+
+    /// Create a new parser for the type that keeps track of the fields that are finished.
+    fn new_partial_parser(
+    ) -> impl SendCreateParserState<Output = Self, PartialState = FieldsParserState>;
+
+    /// Read the fields that have been parsed so far from the state of the [`PartialParse::new_partial_parser`] parser.
+    fn partial(state: &FieldsParserState) -> Self::Partial;
+}
+
+/// A field in a [`FieldsParser`].
+struct Field {
+    name: &'static str,
+    prefix: LiteralParser,
+    parser: ArcParser<AnyValue>,
+}
+
+/// A parser for a list of named fields like the fields of a struct. The state of the parser keeps the values of the fields that are finished.
+///
+/// Each field is parsed as a literal prefix (like `, "name": `) followed by the value of the field. After the last field, the end literal is parsed.
+///
+/// # Example
+/// ```rust
+/// use kalosm_sample::*;
+///
+/// let parser = FieldsParser::new(" }")
+///     .with_field("name", "{ \"name\": ", String::new_parser())
+///     .with_field("age", ", \"age\": ", u32::new_parser());
+/// let state = parser.create_parser_state();
+/// let (state, _) = parser
+///     .parse(&state, br#"{ "name": "Alice", "#)
+///     .unwrap()
+///     .unwrap_incomplete();
+/// assert_eq!(state.field::<String>(0).as_deref(), Some("Alice"));
+/// assert_eq!(state.current_field(), Some(1));
+/// ```
+pub struct FieldsParser {
+    fields: Vec<Field>,
+    end: LiteralParser,
+}
+
+impl FieldsParser {
+    /// Create a new parser with no fields that ends with the literal `end`.
+    pub fn new(end: impl Into<Cow<'static, str>>) -> Self {
+        Self {
+            fields: Vec::new(),
+            end: LiteralParser::new(end),
+        }
+    }
+
+    /// Add a field with a name that is parsed as the literal `prefix` followed by the parser.
+    pub fn with_field<P>(
+        mut self,
+        name: &'static str,
+        prefix: impl Into<Cow<'static, str>>,
+        parser: P,
+    ) -> Self
+    where
+        P: CreateParserState + Send + Sync + 'static,
+        P::Output: Send + Sync + 'static,
+        P::PartialState: Send + Sync + 'static,
+    {
+        self.fields.push(Field {
+            name,
+            prefix: LiteralParser::new(prefix),
+            parser: parser
+                .map_output(|value| Arc::new(value) as AnyValue)
+                .boxed(),
+        });
+        self
+    }
+
+    /// Get the state of the parser after the field at the index starts.
+    fn start_field(&self, index: usize) -> FieldProgress {
+        if index < self.fields.len() {
+            FieldProgress::Prefix(LiteralParserOffset::default())
+        } else {
+            FieldProgress::End(LiteralParserOffset::default())
+        }
+    }
+}
+
+/// The output of a [`FieldsParser`].
+#[derive(Debug, Clone)]
+pub struct FieldValues {
+    values: Vec<AnyValue>,
+}
+
+impl FieldValues {
+    /// Get the value of the field at the index. Returns `None` if the field doesn't exist or has a different type.
+    pub fn get<T: Clone + 'static>(&self, index: usize) -> Option<T> {
+        self.values.get(index)?.downcast_ref::<T>().cloned()
+    }
+}
+
+/// The state of a [`FieldsParser`].
+#[derive(Debug, Clone)]
+pub struct FieldsParserState {
+    finished: FieldValues,
+    progress: FieldProgress,
+    names: Arc<[&'static str]>,
+}
+
+impl FieldsParserState {
+    /// Get the value of a field that is finished. Returns `None` if the field isn't finished or has a different type.
+    pub fn field<T: Clone + 'static>(&self, index: usize) -> Option<T> {
+        self.finished.get(index)
+    }
+
+    /// Get the number of fields that are finished.
+    pub fn finished_fields(&self) -> usize {
+        self.finished.values.len()
+    }
+
+    /// Get the index of the field that is currently being parsed. Returns `None` once all fields are finished.
+    pub fn current_field(&self) -> Option<usize> {
+        match self.progress {
+            FieldProgress::End(_) => None,
+            _ => Some(self.finished_fields()),
+        }
+    }
+
+    /// Get the name of the field that is currently being parsed. Returns `None` once all fields are finished.
+    pub fn current_field_name(&self) -> Option<&'static str> {
+        self.current_field().map(|index| self.names[index])
+    }
+}
+
+/// The progress of a [`FieldsParser`] inside of the current field.
+#[derive(Debug, Clone)]
+enum FieldProgress {
+    /// The prefix of the field is incomplete
+    Prefix(LiteralParserOffset),
+    /// The value of the field is incomplete
+    Value(AnyValue),
+    /// All fields are finished and the end literal is incomplete
+    End(LiteralParserOffset),
+}
+
+impl CreateParserState for FieldsParser {
+    fn create_parser_state(&self) -> <Self as Parser>::PartialState {
+        FieldsParserState {
+            finished: FieldValues { values: Vec::new() },
+            progress: self.start_field(0),
+            names: self.fields.iter().map(|field| field.name).collect(),
+        }
+    }
+}
+
+impl Parser for FieldsParser {
+    type Output = FieldValues;
+    type PartialState = FieldsParserState;
+
+    fn parse<'a>(
+        &self,
+        state: &Self::PartialState,
+        mut input: &'a [u8],
+    ) -> crate::ParseResult<ParseStatus<'a, Self::PartialState, Self::Output>> {
+        let mut state = state.clone();
+        loop {
+            let field_index = state.finished_fields();
+            match &state.progress {
+                FieldProgress::Prefix(offset) => {
+                    let field = &self.fields[field_index];
+                    match field.prefix.parse(offset, input)? {
+                        ParseStatus::Finished { remaining, .. } => {
+                            state.progress =
+                                FieldProgress::Value(field.parser.create_parser_state());
+                            input = remaining;
+                        }
+                        ParseStatus::Incomplete {
+                            new_state,
+                            required_next,
+                        } => {
+                            state.progress = FieldProgress::Prefix(new_state);
+                            return Ok(ParseStatus::Incomplete {
+                                new_state: state,
+                                required_next,
+                            });
+                        }
+                    }
+                }
+                FieldProgress::Value(value_state) => {
+                    let field = &self.fields[field_index];
+                    match field.parser.parse(value_state, input)? {
+                        ParseStatus::Finished { result, remaining } => {
+                            state.finished.values.push(result);
+                            state.progress = self.start_field(field_index + 1);
+                            input = remaining;
+                        }
+                        ParseStatus::Incomplete {
+                            new_state,
+                            required_next,
+                        } => {
+                            state.progress = FieldProgress::Value(new_state);
+                            return Ok(ParseStatus::Incomplete {
+                                new_state: state,
+                                required_next,
+                            });
+                        }
+                    }
+                }
+                FieldProgress::End(offset) => match self.end.parse(offset, input)? {
+                    ParseStatus::Finished { remaining, .. } => {
+                        return Ok(ParseStatus::Finished {
+                            result: state.finished,
+                            remaining,
+                        });
+                    }
+                    ParseStatus::Incomplete {
+                        new_state,
+                        required_next,
+                    } => {
+                        state.progress = FieldProgress::End(new_state);
+                        return Ok(ParseStatus::Incomplete {
+                            new_state: state,
+                            required_next,
+                        });
+                    }
+                },
+            }
+        }
+    }
+}
+
+#[test]
+fn fields_parser() {
+    let parser = FieldsParser::new(" }")
+        .with_field("name", "{ \"name\": ", String::new_parser())
+        .with_field("age", ", \"age\": ", u32::new_parser());
+    let state = parser.create_parser_state();
+    assert_eq!(state.current_field_name(), Some("name"));
+
+    let (state, _) = parser
+        .parse(&state, br#"{ "name": "Al"#)
+        .unwrap()
+        .unwrap_incomplete();
+    assert_eq!(state.finished_fields(), 0);
+    assert_eq!(state.field::<String>(0), None);
+
+    let (state, required_next) = parser
+        .parse(&state, br#"ice", "age": 30"#)
+        .unwrap()
+        .unwrap_incomplete();
+    assert_eq!(state.field::<String>(0).as_deref(), Some("Alice"));
+    // The field has a different type
+    assert_eq!(state.field::<u32>(0), None);
+    assert_eq!(state.current_field_name(), Some("age"));
+    assert_eq!(required_next, "");
+
+    let (state, required_next) = parser.parse(&state, b" ").unwrap().unwrap_incomplete();
+    assert_eq!(state.field::<u32>(1), Some(30));
+    assert_eq!(state.current_field(), None);
+    assert_eq!(required_next, "}");
+
+    let result = parser.parse(&state, b"}").unwrap().unwrap_finished();
+    assert_eq!(result.get::<String>(0).as_deref(), Some("Alice"));
+    assert_eq!(result.get::<u32>(1), Some(30));
+
+    assert!(parser.parse(&state, b"!").is_err());
+}
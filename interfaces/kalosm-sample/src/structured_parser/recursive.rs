@@ -0,0 +1,208 @@
+use std::{
+    any::Any,
+    cell::Cell,
+    sync::{Arc, OnceLock},
+};
+
+use crate::{ArcParser, CreateParserState, ParseStatus, Parser, ParserExt};
+
+thread_local! {
+    /// The number of recursive parsers that are being built on this thread
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+/// A parser for a recursive grammar like a tree or an expression.
+///
+/// The parser is only built the first time it is used, so a parser can refer to itself by creating a new `RecursiveParser` with the same function. Each time a recursive parser is nested inside another recursive parser, its depth increases by one. Once the depth is larger than the maximum depth, the parser will fail to parse any input which forces the grammar to take a branch that doesn't recurse.
+///
+/// `#[derive(Parse)]` uses this parser automatically for types that contain themselves.
+///
+/// # Example
+/// ```rust
+/// use kalosm_sample::*;
+///
+/// #[derive(Clone, Debug, PartialEq)]
+/// enum Expression {
+///     Number(i64),
+///     Add(Box<Expression>, Box<Expression>),
+/// }
+///
+/// fn expression() -> ArcParser<Expression> {
+///     // The parser for the nested expressions is only built once it is needed
+///     let nested = || RecursiveParser::new(expression).with_max_depth(4);
+///     let add = LiteralParser::new("(")
+///         .ignore_output_then(nested())
+///         .then_literal(" + ")
+///         .then(nested())
+///         .then_literal(")")
+///         .map_output(|(left, right)| Expression::Add(Box::new(left), Box::new(right)));
+///     let number = i64::new_parser().map_output(Expression::Number);
+///     add.or(number).boxed()
+/// }
+///
+/// let parser = expression();
+/// let state = parser.create_parser_state();
+/// let result = parser
+///     .parse(&state, b"(1 + (2 + 3))")
+///     .unwrap()
+///     .unwrap_finished();
+/// assert_eq!(
+///     result,
+///     Expression::Add(
+///         Box::new(Expression::Number(1)),
+///         Box::new(Expression::Add(
+///             Box::new(Expression::Number(2)),
+///             Box::new(Expression::Number(3))
+///         ))
+///     )
+/// );
+/// ```
+pub struct RecursiveParser<O> {
+    parser: Arc<OnceLock<ArcParser<O>>>,
+    parser_fn: Arc<dyn Fn() -> ArcParser<O> + Send + Sync>,
+    depth: usize,
+    max_depth: usize,
+}
+
+impl<O> Clone for RecursiveParser<O> {
+    fn clone(&self) -> Self {
+        Self {
+            parser: self.parser.clone(),
+            parser_fn: self.parser_fn.clone(),
+            depth: self.depth,
+            max_depth: self.max_depth,
+        }
+    }
+}
+
+impl<O: Clone + Send + Sync + 'static> RecursiveParser<O> {
+    /// The default maximum depth of a recursive parser.
+    pub const DEFAULT_MAX_DEPTH: usize = 10;
+
+    /// Create a new recursive parser from a function that builds the parser the first time it is used.
+    pub fn new<P, F>(parser_fn: F) -> Self
+    where
+        F: Fn() -> P + Send + Sync + 'static,
+        P: CreateParserState<Output = O> + Send + Sync + 'static,
+        P::PartialState: Send + Sync + 'static,
+    {
+        Self {
+            parser: Default::default(),
+            parser_fn: Arc::new(move || parser_fn().boxed()),
+            depth: DEPTH.get(),
+            max_depth: Self::DEFAULT_MAX_DEPTH,
+        }
+    }
+
+    /// Set the maximum number of recursive parsers this parser can be nested inside. (default: 10)
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+}
+
+impl<O> RecursiveParser<O> {
+    /// Get the number of recursive parsers this parser is nested inside.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Get the parser, or `None` if the parser is nested too deeply.
+    fn get_parser(&self) -> Option<&ArcParser<O>> {
+        if self.depth > self.max_depth {
+            return None;
+        }
+        Some(self.parser.get_or_init(|| {
+            // Any recursive parsers created while building this parser are nested inside of it
+            struct ResetDepth(usize);
+            impl Drop for ResetDepth {
+                fn drop(&mut self) {
+                    DEPTH.set(self.0);
+                }
+            }
+            let _reset = ResetDepth(DEPTH.replace(self.depth + 1));
+            (self.parser_fn)()
+        }))
+    }
+}
+
+/// The state of a recursive parser.
+#[derive(Debug, Clone)]
+pub struct RecursiveParserState(Option<Arc<dyn Any + Send + Sync>>);
+
+impl<O: Clone> CreateParserState for RecursiveParser<O> {
+    fn create_parser_state(&self) -> <Self as Parser>::PartialState {
+        RecursiveParserState(self.get_parser().map(|parser| parser.create_parser_state()))
+    }
+}
+
+impl<O: Clone> Parser for RecursiveParser<O> {
+    type Output = O;
+    type PartialState = RecursiveParserState;
+
+    fn parse<'a>(
+        &self,
+        state: &Self::PartialState,
+        input: &'a [u8],
+    ) -> crate::ParseResult<ParseStatus<'a, Self::PartialState, Self::Output>> {
+        let (Some(parser), Some(state)) = (self.get_parser(), &state.0) else {
+            crate::bail!(RecursionLimitError {
+                max_depth: self.max_depth
+            });
+        };
+        parser
+            .parse(state, input)
+            .map(|result| result.map_state(|state| RecursiveParserState(Some(state))))
+    }
+}
+
+/// An error that occurs when a recursive parser is nested deeper than its maximum depth.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct RecursionLimitError {
+    max_depth: usize,
+}
+
+impl std::fmt::Display for RecursionLimitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Recursive parser nested deeper than the maximum depth of {}",
+            self.max_depth
+        )
+    }
+}
+
+impl std::error::Error for RecursionLimitError {}
+
+#[test]
+fn recursive_parser_depth_limit() {
+    use crate::{LiteralParser, SeparatedParser};
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct Tree(Vec<Tree>);
+
+    fn tree(max_depth: usize) -> ArcParser<Tree> {
+        LiteralParser::new("[")
+            .ignore_output_then(SeparatedParser::new(
+                RecursiveParser::new(move || tree(max_depth)).with_max_depth(max_depth),
+                LiteralParser::new(", "),
+                0..=usize::MAX,
+            ))
+            .then_literal("]")
+            .map_output(Tree)
+            .boxed()
+    }
+
+    let parser = tree(2);
+    let state = parser.create_parser_state();
+    let result = parser
+        .parse(&state, b"[[], [[[]]]]")
+        .unwrap()
+        .unwrap_finished();
+    assert_eq!(
+        result,
+        Tree(vec![Tree(vec![]), Tree(vec![Tree(vec![Tree(vec![])])])])
+    );
+    // The trees nested inside three other trees can't contain any trees
+    assert!(parser.parse(&state, b"[[[[[").is_err());
+}
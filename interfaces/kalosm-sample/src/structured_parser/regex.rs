@@ -1,24 +1,42 @@
-use std::{collections::HashMap, sync::RwLock};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::RwLock,
+};
 
 use crate::{CreateParserState, Parser};
 use regex_automata::{
-    dfa::{dense, Automaton},
+    dfa::{dense, Automaton, StartKind},
     util::primitives::StateID,
+    MatchKind,
 };
 
 /// A parser that uses a regex pattern to parse input.
+///
+/// The regex is compiled to a DFA once when the parser is created, so character classes, bounded repetition and alternation can be checked one byte at a time without backtracking. The parser accepts any text in the language of the regex: alternatives that share a prefix like `cat|category` are both allowed, and the parser keeps reading for as long as the text could still be extended into a longer match.
+///
+/// Look-around assertions and backreferences are not supported by the DFA and return an error when the parser is created.
 pub struct RegexParser {
     dfa: dense::DFA<Vec<u32>>,
     config: regex_automata::util::start::Config,
-    // A cache for the required next bytes for each state
-    jump_table: RwLock<HashMap<StateID, String>>,
+    // A cache for the required next text for each state
+    jump_table: RwLock<HashMap<StateID, Option<String>>>,
 }
 
 impl RegexParser {
     /// Create a new `RegexParser` from a regex pattern.
     #[allow(clippy::result_large_err)]
     pub fn new(regex: &str) -> std::result::Result<Self, regex_automata::dfa::dense::BuildError> {
-        let dfa = dense::DFA::new(regex)?;
+        let dfa = dense::Builder::new()
+            .configure(
+                dense::DFA::config()
+                    // Keep every match instead of stopping at the first alternative that matches
+                    .match_kind(MatchKind::All)
+                    // The parser always matches from the start of the input
+                    .start_kind(StartKind::Anchored)
+                    // Allow unicode word boundaries. The DFA will quit if it sees a non-ascii byte next to a word boundary
+                    .unicode_word_boundary(true),
+            )
+            .build(regex)?;
 
         let config =
             regex_automata::util::start::Config::new().anchored(regex_automata::Anchored::Yes);
@@ -29,6 +47,164 @@ impl RegexParser {
             jump_table: Default::default(),
         })
     }
+
+    /// Check if the text that lead to this state matches the whole regex.
+    fn is_match(&self, state: StateID) -> bool {
+        self.dfa.is_match_state(self.dfa.next_eoi_state(state))
+    }
+
+    /// Check if the regex can never match after this state.
+    fn is_dead(&self, state: StateID) -> bool {
+        self.dfa.is_dead_state(state) || self.dfa.is_quit_state(state)
+    }
+
+    /// Check if the byte that lead to this state can be part of a match.
+    fn is_alive(&self, state: StateID) -> bool {
+        if self.is_dead(state) {
+            return false;
+        }
+        // Matches are delayed by one byte in the DFA. A match state may only record that the text before the last byte matched, in which case the last byte is not part of the match
+        if !self.dfa.is_match_state(state) {
+            return true;
+        }
+        self.is_match(state)
+            || (0..=255).any(|byte| !self.is_dead(self.dfa.next_state(state, byte)))
+    }
+
+    /// Get the bytes that can come after this state along with the state after each byte.
+    fn valid_next_bytes(&self, state: StateID) -> impl Iterator<Item = (u8, StateID)> + '_ {
+        (0..=255)
+            .map(move |byte| (byte, self.dfa.next_state(state, byte)))
+            .filter(|(_, next_state)| self.is_alive(*next_state))
+    }
+
+    /// Get the text that must come after this state, or `None` if the text can't be extended.
+    fn next_text(&self, state: StateID) -> Option<String> {
+        if let Some(next_text) = self.jump_table.read().unwrap().get(&state) {
+            return next_text.clone();
+        }
+
+        let next_text = if self.valid_next_bytes(state).next().is_none() {
+            None
+        } else {
+            let mut required_next = Vec::new();
+            let mut required_next_state = state;
+            let mut visited = HashSet::new();
+            // Follow the DFA while there is only one byte that can come next. If the text already matches, the regex could also end here
+            while !self.is_match(required_next_state) && visited.insert(required_next_state) {
+                let mut valid_next_bytes = self.valid_next_bytes(required_next_state);
+                match (valid_next_bytes.next(), valid_next_bytes.next()) {
+                    (Some((byte, new_state)), None) => {
+                        required_next.push(byte);
+                        required_next_state = new_state;
+                    }
+                    _ => break,
+                }
+            }
+
+            // Only keep complete characters. The required bytes may end in the middle of a multi-byte character
+            Some(match String::from_utf8(required_next) {
+                Ok(string) => string,
+                Err(err) => {
+                    let valid_up_to = err.utf8_error().valid_up_to();
+                    let mut bytes = err.into_bytes();
+                    bytes.truncate(valid_up_to);
+                    String::from_utf8(bytes).unwrap()
+                }
+            })
+        };
+
+        self.jump_table
+            .write()
+            .unwrap()
+            .insert(state, next_text.clone());
+
+        next_text
+    }
+
+    /// Export the DFA the regex is compiled to in the [DOT](https://graphviz.org/doc/info/lang.html) format.
+    ///
+    /// Each edge is labeled with the bytes that move the parser between two states. States where the text matches the whole regex are drawn with a double circle. Transitions into states that can never match are left out. You can render the graph with graphviz: `dot -Tsvg regex.dot -o regex.svg`
+    ///
+    /// # Example
+    /// ```rust
+    /// use kalosm_sample::*;
+    ///
+    /// let parser = RegexParser::new("a[0-9]+").unwrap();
+    /// let dot = parser.to_dot();
+    /// assert!(dot.starts_with("digraph"));
+    /// assert!(dot.contains("0-9"));
+    /// ```
+    pub fn to_dot(&self) -> String {
+        let start = self.dfa.start_state(&self.config).unwrap();
+        let mut ids = HashMap::from([(start, 0)]);
+        let mut queue = vec![start];
+        let mut nodes = String::new();
+        let mut edges = String::new();
+        while let Some(state) = queue.pop() {
+            let id = ids[&state];
+            let shape = if self.is_match(state) {
+                "doublecircle"
+            } else {
+                "circle"
+            };
+            nodes += &format!("    {id} [shape={shape}];\n");
+
+            // Group the bytes by the state they lead to so each pair of states only has one edge
+            let mut transitions: Vec<(StateID, Vec<(u8, u8)>)> = Vec::new();
+            for (byte, next_state) in self.valid_next_bytes(state) {
+                let index = match transitions.iter().position(|(to, _)| *to == next_state) {
+                    Some(index) => index,
+                    None => {
+                        transitions.push((next_state, Vec::new()));
+                        transitions.len() - 1
+                    }
+                };
+                let ranges = &mut transitions[index].1;
+                match ranges.last_mut() {
+                    Some((_, end)) if *end as u16 + 1 == byte as u16 => *end = byte,
+                    _ => ranges.push((byte, byte)),
+                }
+            }
+
+            for (next_state, ranges) in transitions {
+                let next_id = match ids.get(&next_state) {
+                    Some(next_id) => *next_id,
+                    None => {
+                        let next_id = ids.len();
+                        ids.insert(next_state, next_id);
+                        queue.push(next_state);
+                        next_id
+                    }
+                };
+                let label = ranges
+                    .iter()
+                    .map(|&(start, end)| {
+                        if start == end {
+                            dot_escape_byte(start)
+                        } else {
+                            format!("{}-{}", dot_escape_byte(start), dot_escape_byte(end))
+                        }
+                    })
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                edges += &format!("    {id} -> {next_id} [label=\"{label}\"];\n");
+            }
+        }
+
+        format!("digraph regex {{\n    rankdir=LR;\n    start [shape=point];\n    start -> 0;\n{nodes}{edges}}}\n")
+    }
+}
+
+/// Format a byte so it can be used in a quoted DOT label
+fn dot_escape_byte(byte: u8) -> String {
+    match byte {
+        b'"' => "\\\"".to_string(),
+        b'\\' => "\\\\".to_string(),
+        b' ' => "' '".to_string(),
+        byte if byte.is_ascii_graphic() => (byte as char).to_string(),
+        byte => format!("\\\\x{byte:02X}"),
+    }
 }
 
 impl CreateParserState for RegexParser {
@@ -51,69 +227,34 @@ impl Parser for RegexParser {
         input: &'a [u8],
     ) -> crate::ParseResult<crate::ParseStatus<'a, Self::PartialState, Self::Output>> {
         let mut state = state.clone();
-        let mut iter = input.iter();
-        while let Some(&b) = iter.next() {
-            state.state = self.dfa.next_state(state.state, b);
-            state.value.push(b);
-            // If this is a match state, accept it only if it matches the whole regex
-            let finish_state = self.dfa.next_eoi_state(state.state);
-            if self.dfa.is_match_state(finish_state) {
-                return Ok(crate::ParseStatus::Finished {
-                    result: String::from_utf8_lossy(&state.value).to_string(),
-                    remaining: iter.as_slice(),
-                });
-            } else if self.dfa.is_dead_state(state.state) || self.dfa.is_quit_state(state.state) {
-                crate::bail!(regex_automata::MatchError::quit(b, 0));
-            }
-        }
-
-        let mut required_next = String::new();
-        let mut required_next_state = state.state;
-        let jump_table_read = self.jump_table.read().unwrap();
-
-        if let Some(string) = jump_table_read.get(&required_next_state) {
-            required_next.push_str(string);
-        } else {
-            'required_next: loop {
-                let mut one_valid_byte = None;
-
-                if let Some(string) = jump_table_read.get(&required_next_state) {
-                    required_next.push_str(string);
-                    break;
-                }
-
-                for byte in 0..=255 {
-                    let next_state = self.dfa.next_state(required_next_state, byte);
-                    if self.dfa.is_dead_state(next_state) || self.dfa.is_quit_state(next_state) {
-                        continue;
-                    }
-                    if one_valid_byte.is_some() {
-                        break 'required_next;
-                    }
-                    one_valid_byte = Some((byte, next_state));
-                }
-
-                if let Some((byte, new_state)) = one_valid_byte {
-                    required_next.push(byte.into());
-                    required_next_state = new_state;
-                } else {
-                    break;
+        for (index, &b) in input.iter().enumerate() {
+            let next_state = self.dfa.next_state(state.state, b);
+            if !self.is_alive(next_state) {
+                // If the text so far matches the whole regex, the regex is finished and the rest of the input is left for the next parser
+                if self.is_match(state.state) {
+                    return Ok(crate::ParseStatus::Finished {
+                        result: String::from_utf8_lossy(&state.value).to_string(),
+                        remaining: &input[index..],
+                    });
                 }
+                crate::bail!(regex_automata::MatchError::quit(b, state.value.len()));
             }
-
-            if !required_next.is_empty() {
-                drop(jump_table_read);
-                self.jump_table
-                    .write()
-                    .unwrap()
-                    .insert(state.state, required_next.clone());
-            }
+            state.state = next_state;
+            state.value.push(b);
         }
 
-        Ok(crate::ParseStatus::Incomplete {
-            new_state: state,
-            required_next: required_next.into(),
-        })
+        match self.next_text(state.state) {
+            Some(required_next) => Ok(crate::ParseStatus::Incomplete {
+                new_state: state,
+                required_next: required_next.into(),
+            }),
+            // If the text matches and can't be extended into a longer match, the regex is finished
+            None if self.is_match(state.state) => Ok(crate::ParseStatus::Finished {
+                result: String::from_utf8_lossy(&state.value).to_string(),
+                remaining: &[],
+            }),
+            None => crate::bail!("The regex can never match"),
+        }
     }
 }
 
@@ -194,3 +335,72 @@ fn required_next_regex() {
         _ => panic!("unexpected result to be incomplete: {result:?}"),
     }
 }
+
+#[test]
+fn regex_longest_match() {
+    use crate::ParseStatus;
+
+    // Alternatives that share a prefix are both allowed
+    let parser = RegexParser::new(r"cat|category").unwrap();
+    let state = parser.create_parser_state();
+    let (new_state, required_next) = parser.parse(&state, b"cat").unwrap().unwrap_incomplete();
+    assert_eq!(new_state.value, b"cat");
+    assert!(required_next.is_empty());
+    assert_eq!(
+        parser.parse(&state, b"cat.").unwrap(),
+        ParseStatus::Finished {
+            result: "cat".to_string(),
+            remaining: b"."
+        }
+    );
+    assert_eq!(
+        parser.parse(&state, b"category.").unwrap(),
+        ParseStatus::Finished {
+            result: "category".to_string(),
+            remaining: b"."
+        }
+    );
+    assert!(parser.parse(&state, b"cab").is_err());
+
+    // Bounded repetition keeps reading until the upper bound
+    let parser = RegexParser::new(r"[0-9]{2,3}").unwrap();
+    let state = parser.create_parser_state();
+    assert!(parser.parse(&state, b"1a").is_err());
+    assert_eq!(
+        parser.parse(&state, b"12a").unwrap(),
+        ParseStatus::Finished {
+            result: "12".to_string(),
+            remaining: b"a"
+        }
+    );
+    assert_eq!(
+        parser.parse(&state, b"123").unwrap(),
+        ParseStatus::Finished {
+            result: "123".to_string(),
+            remaining: b""
+        }
+    );
+
+    // The required next text only contains complete characters
+    let parser = RegexParser::new(r"café (é|è)").unwrap();
+    let state = parser.create_parser_state();
+    let (new_state, required_next) = parser.parse(&state, b"").unwrap().unwrap_incomplete();
+    assert_eq!(required_next, "café ");
+    let (_, required_next) = parser
+        .parse(&new_state, "café ".as_bytes())
+        .unwrap()
+        .unwrap_incomplete();
+    assert!(required_next.is_empty());
+}
+
+#[test]
+fn regex_to_dot() {
+    let parser = RegexParser::new(r#"a[0-9]+|"b""#).unwrap();
+    let dot = parser.to_dot();
+    assert!(dot.starts_with("digraph regex {"));
+    assert!(dot.ends_with("}\n"));
+    assert!(dot.contains("[label=\"a\"]"));
+    assert!(dot.contains("[label=\"0-9\"]"));
+    assert!(dot.contains("[label=\"\\\"\"]"));
+    assert!(dot.contains("doublecircle"));
+}
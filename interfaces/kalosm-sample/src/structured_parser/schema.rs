@@ -364,6 +364,8 @@ pub struct StringSchema {
     length: Option<std::ops::RangeInclusive<usize>>,
     /// The regex pattern that the string must match
     pattern: Option<String>,
+    /// The format of the string like `date` or `email`
+    format: Option<String>,
 }
 
 impl Schema for String {
@@ -384,6 +386,7 @@ impl StringSchema {
         Self {
             length: None,
             pattern: None,
+            format: None,
         }
     }
 
@@ -402,6 +405,12 @@ impl StringSchema {
         self
     }
 
+    /// Set the format of the string like `date` or `email`
+    pub fn with_format(mut self, format: impl ToString) -> Self {
+        self.format = Some(format.to_string());
+        self
+    }
+
     fn display_with_description(
         &self,
         f: &mut std::fmt::Formatter<'_>,
@@ -425,6 +434,9 @@ impl StringSchema {
             if let Some(pattern) = &self.pattern {
                 writer.write_fmt(format_args!(",\n\"pattern\": \"{}\"", pattern))?;
             }
+            if let Some(format) = &self.format {
+                writer.write_fmt(format_args!(",\n\"format\": \"{}\"", format))?;
+            }
         }
         f.write_str("\n}")
     }
@@ -699,6 +711,7 @@ fn test_array_schema() {
         items: Box::new(SchemaType::String(StringSchema {
             length: Some(1..=10),
             pattern: None,
+            format: None,
         })),
         length: Some(0..=10),
     };
@@ -709,6 +722,7 @@ fn test_array_schema() {
         items: Box::new(SchemaType::String(StringSchema {
             length: None,
             pattern: None,
+            format: None,
         })),
         length: Some(1..=usize::MAX),
     };
@@ -718,6 +732,7 @@ fn test_array_schema() {
         items: Box::new(SchemaType::String(StringSchema {
             length: None,
             pattern: None,
+            format: None,
         })),
         length: None,
     };
@@ -832,6 +847,7 @@ fn test_object_schema() {
                 ty: SchemaType::String(StringSchema {
                     length: Some(1..=10),
                     pattern: None,
+                    format: None,
                 }),
             },
             JsonPropertySchema {
@@ -0,0 +1,17 @@
+use futures_util::StreamExt;
+use kalosm_sound::*;
+
+#[tokio::main]
+async fn main() -> Result<(), anyhow::Error> {
+    // Create a new whisper model.
+    let model = Whisper::new().await?;
+
+    // Transcribe the microphone one utterance at a time
+    let mut utterances = MicInput::default().transcribe(model);
+
+    while let Some(utterance) = utterances.next().await {
+        println!("[{:.1}s] {}", utterance.duration().as_secs_f32(), utterance);
+    }
+
+    Ok(())
+}
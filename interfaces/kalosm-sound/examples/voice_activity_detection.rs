@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use futures_util::StreamExt;
 use kalosm_sound::*;
 use rodio::OutputStream;
@@ -6,16 +8,19 @@ use rodio::OutputStream;
 async fn main() -> Result<(), anyhow::Error> {
     let mic_input = MicInput::default();
     let stream = mic_input.stream();
-    let mut vad = stream.voice_activity_stream();
+    // Keep playing the audio for half a second after the speaker stops talking
+    let detector = SileroVad::new(16000)?.with_hangover(Duration::from_millis(500));
+    let mut vad = stream.voice_activity_stream_with(detector).detect_speech();
     let (_device, stream_handle) = OutputStream::try_default().unwrap();
     let sink = rodio::Sink::try_new(&stream_handle)?;
-    while let Some(VoiceActivityDetectorOutput {
+    while let Some(SpeechDetectorOutput {
         probability,
+        is_speech,
         samples,
     }) = vad.next().await
     {
         println!("probability: {probability}");
-        if probability > 0.5 {
+        if is_speech {
             sink.append(samples);
         }
     }
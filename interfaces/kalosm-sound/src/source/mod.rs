@@ -85,6 +85,14 @@ impl<S: AsyncSource> ResampledAsyncSource<S> {
             resampler: dasp::interpolate::linear::Linear::new(0.0, 0.0),
         }
     }
+
+    /// Change the output sample rate. This should only be called before the stream is polled
+    #[cfg(feature = "voice_detection")]
+    pub(crate) fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.source_output_sample_ratio = self.source.sample_rate() as f64 / sample_rate as f64;
+        self.sample_position = self.source_output_sample_ratio;
+        self.sample_rate = sample_rate;
+    }
 }
 
 impl<S: AsyncSource + Unpin> Stream for ResampledAsyncSource<S> {
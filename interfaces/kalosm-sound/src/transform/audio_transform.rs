@@ -0,0 +1,132 @@
+use std::{collections::VecDeque, task::Poll};
+
+use futures_core::{ready, Stream};
+
+use crate::{AsyncSource, ResampledAsyncSource};
+
+/// A transformation that processes mono audio one frame at a time, like a denoiser or a filter.
+///
+/// Transforms can be inserted anywhere in an audio pipeline with [`AudioTransformExt::transform`]. The transformed audio is an [`AsyncSource`], so it can be passed to voice activity detection, transcription or another transform.
+pub trait AudioTransform: Send + Unpin + 'static {
+    /// The sample rate the transform expects. Audio is resampled to this rate before it is passed to the transform, and the transformed audio has the same sample rate
+    fn sample_rate(&self) -> u32;
+
+    /// The number of samples in each frame passed to [`AudioTransform::process`]
+    fn frame_size(&self) -> usize;
+
+    /// Process one frame of audio. The input and output are both [`AudioTransform::frame_size`] samples long
+    fn process(&mut self, input: &[f32], output: &mut [f32]);
+}
+
+/// An extension trait for audio streams that applies [`AudioTransform`]s
+pub trait AudioTransformExt: AsyncSource {
+    /// Apply a transform to the audio stream.
+    ///
+    /// ```rust, no_run
+    /// use kalosm::sound::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<(), anyhow::Error> {
+    ///     let model = Whisper::new().await?;
+    ///     // Remove background noise before the audio is transcribed
+    ///     let mut text = MicInput::default()
+    ///         .stream()
+    ///         .transform(Denoiser::new())
+    ///         .transcribe(model);
+    ///     text.to_std_out().await?;
+    ///     Ok(())
+    /// }
+    /// ```
+    fn transform<T: AudioTransform>(self, transform: T) -> TransformedSource<Self, T>
+    where
+        Self: Sized + Unpin,
+    {
+        TransformedSource::new(self, transform)
+    }
+}
+
+impl<S: AsyncSource> AudioTransformExt for S {}
+
+/// An audio stream with an [`AudioTransform`] applied. Created with [`AudioTransformExt::transform`]
+pub struct TransformedSource<S: AsyncSource + Unpin, T> {
+    source: ResampledAsyncSource<S>,
+    transform: T,
+    input: Vec<f32>,
+    output: VecDeque<f32>,
+    finished: bool,
+}
+
+impl<S: AsyncSource + Unpin, T: AudioTransform> TransformedSource<S, T> {
+    fn new(source: S, transform: T) -> Self {
+        let frame_size = transform.frame_size();
+        Self {
+            source: source.resample(transform.sample_rate()),
+            transform,
+            input: Vec::with_capacity(frame_size),
+            output: VecDeque::with_capacity(frame_size),
+            finished: false,
+        }
+    }
+
+    /// Get the transform applied to the stream
+    pub fn inner(&self) -> &T {
+        &self.transform
+    }
+
+    fn process_input(&mut self) {
+        let samples = self.input.len();
+        // Pad the last frame with silence and only keep the samples that came from the source
+        self.input.resize(self.transform.frame_size(), 0.);
+        let mut output = vec![0.; self.input.len()];
+        self.transform.process(&self.input, &mut output);
+        self.output.extend(&output[..samples]);
+        self.input.clear();
+    }
+}
+
+impl<S: AsyncSource + Unpin, T: AudioTransform> Stream for TransformedSource<S, T> {
+    type Item = f32;
+
+    fn poll_next(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(sample) = this.output.pop_front() {
+                return Poll::Ready(Some(sample));
+            }
+            if this.finished {
+                return Poll::Ready(None);
+            }
+
+            let frame_size = this.transform.frame_size();
+            {
+                let stream = this.source.as_stream();
+                let mut stream = std::pin::pin!(stream);
+                while this.input.len() < frame_size {
+                    match ready!(stream.as_mut().poll_next(cx)) {
+                        Some(sample) => this.input.push(sample),
+                        None => {
+                            this.finished = true;
+                            break;
+                        }
+                    }
+                }
+            }
+            if !this.input.is_empty() {
+                this.process_input();
+            }
+        }
+    }
+}
+
+impl<S: AsyncSource + Unpin, T: AudioTransform> AsyncSource for TransformedSource<S, T> {
+    fn as_stream(&mut self) -> impl Stream<Item = f32> + '_ {
+        self
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.source.sample_rate()
+    }
+}
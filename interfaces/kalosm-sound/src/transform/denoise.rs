@@ -8,7 +8,10 @@ use futures_core::{ready, Stream};
 use nnnoiseless::DenoiseState;
 use rodio::buffer::SamplesBuffer;
 
-use crate::{AsyncSource, ResampledAsyncSource, VoiceActivityDetectorOutput};
+use crate::{
+    AsyncSource, AudioTransform, AudioTransformExt, ResampledAsyncSource, TransformedSource,
+    VoiceActivityDetectorOutput,
+};
 
 /// An extension trait for audio streams for denoising. Based on the [nnnoiseless](https://github.com/rust-dsp/nnnoiseless) crate.
 pub trait DenoisedExt: AsyncSource {
@@ -21,6 +24,14 @@ pub trait DenoisedExt: AsyncSource {
     {
         DenoisedStream::new(self)
     }
+
+    /// Remove background noise from the audio stream. The denoised audio can be passed to voice activity detection or transcription like any other [`AsyncSource`]
+    fn denoise(self) -> TransformedSource<Self, Denoiser>
+    where
+        Self: Sized + Unpin,
+    {
+        self.transform(Denoiser::new())
+    }
 }
 
 impl<S: AsyncSource> DenoisedExt for S {}
@@ -28,6 +39,58 @@ impl<S: AsyncSource> DenoisedExt for S {}
 const SAMPLE_RATE: u32 = 48_000;
 const SCALE_FACTOR: f32 = i16::MAX as f32;
 
+/// An [`AudioTransform`] that removes background noise from speech with the [RNNoise](https://jmvalin.ca/demo/rnnoise/) model from the [nnnoiseless](https://github.com/rust-dsp/nnnoiseless) crate.
+///
+/// Denoising audio before voice activity detection and transcription can improve the accuracy of transcriptions from noisy microphones like the ones built into laptops. See [`AudioTransformExt::transform`] for an example.
+pub struct Denoiser {
+    denoiser: Box<DenoiseState<'static>>,
+    input: [f32; DenoiseState::FRAME_SIZE],
+    voice_activity: f32,
+}
+
+impl Default for Denoiser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Denoiser {
+    /// Create a new denoiser
+    pub fn new() -> Self {
+        Self {
+            denoiser: DenoiseState::new(),
+            input: [0.; DenoiseState::FRAME_SIZE],
+            voice_activity: 0.,
+        }
+    }
+
+    /// Get the probability of voice activity (between 0 and 1) in the last frame that was denoised
+    pub fn voice_activity(&self) -> f32 {
+        self.voice_activity
+    }
+}
+
+impl AudioTransform for Denoiser {
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn frame_size(&self) -> usize {
+        DenoiseState::FRAME_SIZE
+    }
+
+    fn process(&mut self, input: &[f32], output: &mut [f32]) {
+        // RNNoise expects samples in the range of an i16
+        for (scaled, sample) in self.input.iter_mut().zip(input) {
+            *scaled = sample * SCALE_FACTOR;
+        }
+        self.voice_activity = self.denoiser.process_frame(output, &self.input);
+        for sample in output {
+            *sample = (*sample / SCALE_FACTOR).clamp(-1.0, 1.0);
+        }
+    }
+}
+
 /// A stream of [`SamplesBuffer`]s with voice activity detection information
 pub struct DenoisedStream<S: AsyncSource + Unpin> {
     source: ResampledAsyncSource<S>,
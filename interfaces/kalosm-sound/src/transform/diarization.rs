@@ -0,0 +1,366 @@
+use std::{fmt::Display, task::Poll};
+
+use futures_core::{ready, Stream};
+use futures_util::StreamExt;
+use rodio::{buffer::SamplesBuffer, source::UniformSourceIterator, Source};
+use rwhisper::{Segment, TranscriptionTask, Whisper};
+
+use super::mfcc::{MfccExtractor, CEPSTRAL_COEFFICIENTS, MFCC_SAMPLE_RATE};
+
+/// The sample rate audio is resampled to before it is passed to a [`SpeakerEmbedder`]
+pub const DIARIZATION_SAMPLE_RATE: u32 = MFCC_SAMPLE_RATE;
+
+/// A model that turns a clip of speech into an embedding of the speaker's voice. Clips from the same speaker should have embeddings with a high cosine similarity.
+///
+/// [`WeSpeaker`](crate::WeSpeaker) is the default neural speaker embedding model. [`SpectralSpeakerEmbedder`] is a lightweight option that doesn't need to download any weights. You can implement this trait to use a different model.
+pub trait SpeakerEmbedder: Send + 'static {
+    /// Embed a clip of mono audio sampled at [`DIARIZATION_SAMPLE_RATE`]
+    fn embed(&mut self, samples: &[f32]) -> Vec<f32>;
+}
+
+/// A lightweight speaker embedder based on the mean and standard deviation of the mel frequency cepstral coefficients of the clip.
+///
+/// This embedder doesn't need to download any weights, but it is less accurate than a neural speaker embedding model like [`WeSpeaker`](crate::WeSpeaker), especially with more than a few speakers or noisy audio.
+pub struct SpectralSpeakerEmbedder {
+    extractor: MfccExtractor,
+}
+
+impl Default for SpectralSpeakerEmbedder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SpectralSpeakerEmbedder {
+    /// Create a new spectral speaker embedder
+    pub fn new() -> Self {
+        Self {
+            extractor: MfccExtractor::new(),
+        }
+    }
+}
+
+impl SpeakerEmbedder for SpectralSpeakerEmbedder {
+    fn embed(&mut self, samples: &[f32]) -> Vec<f32> {
+        let frames = self.extractor.frames(samples);
+        if frames.is_empty() {
+            return vec![0.; 2 * (CEPSTRAL_COEFFICIENTS - 1)];
+        }
+        // Only use the louder frames so pauses between words don't affect the embedding
+        let mean_energy =
+            frames.iter().map(|(_, energy)| energy).sum::<f32>() / frames.len() as f32;
+        let voiced: Vec<_> = frames
+            .iter()
+            .filter(|(_, energy)| *energy >= mean_energy * 0.1)
+            .map(|(cepstrum, _)| cepstrum)
+            .collect();
+        // The first coefficient is the loudness of the frame, which says more about the microphone than the speaker
+        let mut embedding = Vec::with_capacity(2 * (CEPSTRAL_COEFFICIENTS - 1));
+        let count = voiced.len() as f32;
+        let means: Vec<f32> = (1..CEPSTRAL_COEFFICIENTS)
+            .map(|i| voiced.iter().map(|c| c[i]).sum::<f32>() / count)
+            .collect();
+        let deviations = (1..CEPSTRAL_COEFFICIENTS).map(|i| {
+            let mean = means[i - 1];
+            (voiced.iter().map(|c| (c[i] - mean).powi(2)).sum::<f32>() / count).sqrt()
+        });
+        embedding.extend(means.iter().copied());
+        embedding.extend(deviations);
+        embedding
+    }
+}
+
+/// The id of a speaker found by a [`SpeakerClusterer`]. Ids start at 0 and are assigned in the order the speakers are first heard.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SpeakerId(usize);
+
+impl SpeakerId {
+    /// Get the index of the speaker
+    pub fn index(&self) -> usize {
+        self.0
+    }
+}
+
+impl Display for SpeakerId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Speaker {}", self.0 + 1)
+    }
+}
+
+/// Groups speaker embeddings into speakers as they arrive. Each embedding is assigned to the most similar speaker, or a new speaker if no existing speaker is similar enough.
+#[derive(Debug, Clone)]
+pub struct SpeakerClusterer {
+    similarity_threshold: f32,
+    max_speakers: Option<usize>,
+    // The sum of the normalized embeddings of each speaker
+    speakers: Vec<Vec<f32>>,
+}
+
+impl Default for SpeakerClusterer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SpeakerClusterer {
+    /// Create a new clusterer with no known speakers
+    pub fn new() -> Self {
+        Self {
+            similarity_threshold: 0.9,
+            max_speakers: None,
+            speakers: Vec::new(),
+        }
+    }
+
+    /// Set the cosine similarity an embedding needs with a speaker to be assigned to that speaker. (defaults to 0.9)
+    pub fn with_similarity_threshold(mut self, similarity_threshold: f32) -> Self {
+        self.similarity_threshold = similarity_threshold;
+        self
+    }
+
+    /// Set the maximum number of speakers. Once the limit is reached, every embedding is assigned to the most similar speaker. (defaults to no limit)
+    pub fn with_max_speakers(mut self, max_speakers: Option<usize>) -> Self {
+        self.max_speakers = max_speakers;
+        self
+    }
+
+    /// Get the number of speakers found so far
+    pub fn speaker_count(&self) -> usize {
+        self.speakers.len()
+    }
+
+    /// Assign an embedding to a speaker
+    pub fn assign(&mut self, embedding: &[f32]) -> SpeakerId {
+        let embedding = normalize(embedding);
+        let closest = self
+            .speakers
+            .iter()
+            .enumerate()
+            .map(|(index, sum)| (index, dot(&normalize(sum), &embedding)))
+            .max_by(|(_, a), (_, b)| a.total_cmp(b));
+        let at_limit = self
+            .max_speakers
+            .is_some_and(|max| self.speakers.len() >= max.max(1));
+        match closest {
+            Some((index, similarity)) if similarity >= self.similarity_threshold || at_limit => {
+                for (sum, value) in self.speakers[index].iter_mut().zip(&embedding) {
+                    *sum += value;
+                }
+                SpeakerId(index)
+            }
+            _ => {
+                self.speakers.push(embedding);
+                SpeakerId(self.speakers.len() - 1)
+            }
+        }
+    }
+}
+
+fn normalize(embedding: &[f32]) -> Vec<f32> {
+    let norm = dot(embedding, embedding).sqrt().max(f32::EPSILON);
+    embedding.iter().map(|value| value / norm).collect()
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(a, b)| a * b).sum()
+}
+
+/// A transcribed segment labeled with the speaker that said it
+#[derive(Debug, Clone)]
+pub struct DiarizedSegment {
+    speaker: SpeakerId,
+    segment: Segment,
+}
+
+impl DiarizedSegment {
+    /// Get the speaker of the segment
+    pub fn speaker(&self) -> SpeakerId {
+        self.speaker
+    }
+
+    /// Get the transcribed segment
+    pub fn segment(&self) -> &Segment {
+        &self.segment
+    }
+
+    /// Get the text of the segment
+    pub fn text(&self) -> &str {
+        self.segment.text()
+    }
+}
+
+impl Display for DiarizedSegment {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.speaker, self.segment.text().trim())
+    }
+}
+
+/// An extension trait for streams of audio chunks that transcribes each chunk and labels the segments with speakers
+pub trait TranscribeDiarizedExt: Stream + Sized {
+    /// Transcribe each chunk of the audio stream with whisper, and label each segment with the speaker that said it. Speakers are told apart by the embeddings of their voices from the [`SpeakerEmbedder`]. Use [`WeSpeaker`](crate::WeSpeaker) for accurate diarization, or [`SpectralSpeakerEmbedder`] for a lightweight embedder that doesn't need to download any weights.
+    ///
+    /// ```rust, no_run
+    /// use futures_util::StreamExt;
+    /// use kalosm::sound::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<(), anyhow::Error> {
+    ///     let model = Whisper::new().await?;
+    ///     let embedder = WeSpeaker::new().await?;
+    ///     let chunks = MicInput::default()
+    ///         .stream()
+    ///         .voice_activity_stream()
+    ///         .rechunk_voice_activity();
+    ///     let mut segments = chunks.transcribe_diarized(model, embedder);
+    ///     while let Some(segment) = segments.next().await {
+    ///         println!("{segment}");
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    fn transcribe_diarized(
+        self,
+        model: Whisper,
+        embedder: impl SpeakerEmbedder,
+    ) -> DiarizedTranscription<Self> {
+        DiarizedTranscription {
+            stream: self,
+            whisper: model,
+            embedder: Box::new(embedder),
+            clusterer: SpeakerClusterer::new(),
+            word_level_time_stamps: false,
+            current_task: None,
+            current_audio: Vec::new(),
+            last_speaker: None,
+        }
+    }
+}
+
+impl<S> TranscribeDiarizedExt for S
+where
+    S: Stream + Unpin,
+    S::Item: Source,
+    <S::Item as Iterator>::Item: rodio::Sample,
+{
+}
+
+/// Segments shorter than this don't have enough audio for a reliable embedding, so they are assigned to the previous speaker
+const MIN_EMBEDDING_SAMPLES: usize = DIARIZATION_SAMPLE_RATE as usize / 2;
+
+/// A stream of [`DiarizedSegment`]s created with [`TranscribeDiarizedExt::transcribe_diarized`]
+pub struct DiarizedTranscription<S> {
+    stream: S,
+    whisper: Whisper,
+    embedder: Box<dyn SpeakerEmbedder>,
+    clusterer: SpeakerClusterer,
+    word_level_time_stamps: bool,
+    current_task: Option<TranscriptionTask>,
+    // The 16khz mono audio of the chunk that is currently being transcribed
+    current_audio: Vec<f32>,
+    last_speaker: Option<SpeakerId>,
+}
+
+impl<S> DiarizedTranscription<S> {
+    /// Include word level timestamps in the transcription.
+    pub fn timestamped(mut self) -> Self {
+        self.word_level_time_stamps = true;
+        self
+    }
+
+    /// Set the clusterer used to group segments into speakers. This can be used to change the similarity threshold or limit the number of speakers
+    pub fn with_clusterer(mut self, clusterer: SpeakerClusterer) -> Self {
+        self.clusterer = clusterer;
+        self
+    }
+
+    fn label(&mut self, segment: Segment, audio: &[f32]) -> DiarizedSegment {
+        let range = segment.sample_range();
+        let clip = &audio[range.start.min(audio.len())..range.end.min(audio.len())];
+        let speaker = match self.last_speaker {
+            Some(last) if clip.len() < MIN_EMBEDDING_SAMPLES => last,
+            _ => self.clusterer.assign(&self.embedder.embed(clip)),
+        };
+        self.last_speaker = Some(speaker);
+        DiarizedSegment { speaker, segment }
+    }
+}
+
+impl<S> Stream for DiarizedTranscription<S>
+where
+    S: Stream + Unpin,
+    S::Item: Source,
+    <S::Item as Iterator>::Item: rodio::Sample,
+{
+    type Item = DiarizedSegment;
+
+    fn poll_next(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(task) = &mut this.current_task {
+                match ready!(task.poll_next_unpin(cx)) {
+                    Some(segment) => {
+                        let audio = std::mem::take(&mut this.current_audio);
+                        let labeled = this.label(segment, &audio);
+                        this.current_audio = audio;
+                        return Poll::Ready(Some(labeled));
+                    }
+                    None => this.current_task = None,
+                }
+            }
+
+            match ready!(this.stream.poll_next_unpin(cx)) {
+                Some(source) => {
+                    // Segments index into the 16khz mono audio whisper transcribes
+                    let audio: Vec<f32> =
+                        UniformSourceIterator::<_, f32>::new(source, 1, DIARIZATION_SAMPLE_RATE)
+                            .collect();
+                    let mut task = this.whisper.transcribe(SamplesBuffer::new(
+                        1,
+                        DIARIZATION_SAMPLE_RATE,
+                        audio.clone(),
+                    ));
+                    if this.word_level_time_stamps {
+                        task = task.timestamped();
+                    }
+                    this.current_task = Some(task);
+                    this.current_audio = audio;
+                }
+                None => return Poll::Ready(None),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn different_voices_are_different_speakers() {
+    let tone = |frequencies: &[f32]| -> Vec<f32> {
+        (0..DIARIZATION_SAMPLE_RATE)
+            .map(|i| {
+                let t = i as f32 / DIARIZATION_SAMPLE_RATE as f32;
+                frequencies
+                    .iter()
+                    .map(|f| (2. * std::f32::consts::PI * f * t).sin())
+                    .sum::<f32>()
+            })
+            .collect()
+    };
+    let low = tone(&[120., 240., 360., 700.]);
+    let high = tone(&[2200., 3100., 4500.]);
+
+    let mut embedder = SpectralSpeakerEmbedder::new();
+    let mut clusterer = SpeakerClusterer::new();
+    let first = clusterer.assign(&embedder.embed(&low));
+    let second = clusterer.assign(&embedder.embed(&high));
+    let third = clusterer.assign(&embedder.embed(&low[4000..]));
+    assert_ne!(first, second);
+    assert_eq!(first, third);
+    assert_eq!(clusterer.speaker_count(), 2);
+
+    let mut limited = SpeakerClusterer::new().with_max_speakers(Some(1));
+    limited.assign(&embedder.embed(&low));
+    assert_eq!(limited.assign(&embedder.embed(&high)).index(), 0);
+}
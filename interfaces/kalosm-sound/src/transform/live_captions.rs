@@ -0,0 +1,318 @@
+use std::{collections::VecDeque, task::Poll, time::Duration};
+
+use futures_core::Stream;
+use futures_util::StreamExt;
+use rodio::buffer::SamplesBuffer;
+use rwhisper::{TranscriptionTask, Whisper};
+
+use super::voice_audio_detector::*;
+use super::voice_audio_detector_ext::VoiceActivityDetectorOutput;
+use crate::{AsyncSource, MicInput, MicStream};
+
+/// Whisper can only transcribe 30 seconds of audio at a time, so longer utterances are split
+const MAX_UTTERANCE_DURATION: Duration = Duration::from_secs(30);
+
+/// A caption from a [`LiveCaptions`] stream.
+///
+/// Each utterance produces any number of provisional captions while the speaker is talking, followed by one final caption once they stop. Provisional captions may change as more audio is heard, so a caption display should replace the text of the utterance with each new caption.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Caption {
+    utterance: usize,
+    start: Duration,
+    text: String,
+    is_final: bool,
+}
+
+impl Caption {
+    /// Get the index of the utterance this caption is for. Captions with the same index replace each other
+    pub fn utterance(&self) -> usize {
+        self.utterance
+    }
+
+    /// Get the time the utterance started, relative to the start of the audio stream
+    pub fn start(&self) -> Duration {
+        self.start
+    }
+
+    /// Get the text of the caption
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Check if this is the final caption for the utterance. If it is not, the text is unstable and may change in a later caption
+    pub fn is_final(&self) -> bool {
+        self.is_final
+    }
+}
+
+impl std::fmt::Display for Caption {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+struct RunningTranscription {
+    task: TranscriptionTask,
+    utterance: usize,
+    start: Duration,
+    is_final: bool,
+    text: String,
+}
+
+/// A stream of low latency [`Caption`]s for a live audio source. Created with [`MicInput::live_captions`] or [`AsyncSourceTranscribeExt::live_captions`](crate::AsyncSourceTranscribeExt::live_captions).
+///
+/// While someone is speaking, the audio heard so far in the utterance is transcribed every [`LiveCaptions::with_partial_interval`] to create provisional captions. Once the speaker pauses, the whole utterance is transcribed again to create the final caption.
+pub struct LiveCaptions<S: AsyncSource + Unpin> {
+    source: VoiceActivityDetectorStream<S>,
+    source_finished: bool,
+    whisper: Whisper,
+    start_threshold: f32,
+    end_threshold: f32,
+    end_window: Duration,
+    partial_interval: Duration,
+    time_before_speech: Duration,
+    // The audio heard before the current utterance started
+    before_speech: VecDeque<SamplesBuffer<f32>>,
+    // The audio of the current utterance
+    utterance_audio: Vec<f32>,
+    sample_rate: u32,
+    in_speech: bool,
+    silence: Duration,
+    since_partial: Duration,
+    elapsed: Duration,
+    utterance: usize,
+    utterance_start: Duration,
+    finished_utterances: VecDeque<(usize, Duration, Vec<f32>)>,
+    running: Option<RunningTranscription>,
+}
+
+impl<S: AsyncSource + Unpin> LiveCaptions<S> {
+    pub(crate) fn new(source: S, whisper: Whisper) -> Self {
+        Self {
+            source: source.voice_activity_stream(),
+            source_finished: false,
+            whisper,
+            start_threshold: 0.6,
+            end_threshold: 0.2,
+            end_window: Duration::from_millis(700),
+            partial_interval: Duration::from_secs(1),
+            time_before_speech: Duration::from_millis(300),
+            before_speech: VecDeque::new(),
+            utterance_audio: Vec::new(),
+            sample_rate: 0,
+            in_speech: false,
+            silence: Duration::ZERO,
+            since_partial: Duration::ZERO,
+            elapsed: Duration::ZERO,
+            utterance: 0,
+            utterance_start: Duration::ZERO,
+            finished_utterances: VecDeque::new(),
+            running: None,
+        }
+    }
+
+    /// Set how often provisional captions are created while someone is speaking. Shorter intervals lower the latency, but use more compute. (defaults to 1 second)
+    pub fn with_partial_interval(mut self, partial_interval: Duration) -> Self {
+        self.partial_interval = partial_interval;
+        self
+    }
+
+    /// Set how much silence ends an utterance. (defaults to 700 milliseconds)
+    pub fn with_end_of_speech_window(mut self, end_window: Duration) -> Self {
+        self.end_window = end_window;
+        self
+    }
+
+    /// Set the voice activity probability that starts an utterance. (defaults to 0.6)
+    pub fn with_start_threshold(mut self, start_threshold: f32) -> Self {
+        self.start_threshold = start_threshold;
+        self
+    }
+
+    /// Set the voice activity probability audio must fall below to count as silence. (defaults to 0.2)
+    pub fn with_end_threshold(mut self, end_threshold: f32) -> Self {
+        self.end_threshold = end_threshold;
+        self
+    }
+
+    /// Set the [`VoiceActivityDetector`] used to find speech in the audio. (defaults to [`SileroVad`])
+    pub fn with_voice_activity_detector(mut self, vad: impl VoiceActivityDetector) -> Self {
+        self.source.set_detector(vad);
+        self
+    }
+
+    fn push_audio(&mut self, output: VoiceActivityDetectorOutput) {
+        let duration = rodio::Source::total_duration(&output.samples).unwrap_or_default();
+        self.sample_rate = rodio::Source::sample_rate(&output.samples);
+        self.elapsed += duration;
+
+        if !self.in_speech {
+            if output.probability > self.start_threshold {
+                self.in_speech = true;
+                self.silence = Duration::ZERO;
+                self.since_partial = Duration::ZERO;
+                let before: Duration = self
+                    .before_speech
+                    .iter()
+                    .filter_map(rodio::Source::total_duration)
+                    .sum();
+                self.utterance_start = self.elapsed.saturating_sub(before + duration);
+                self.utterance_audio
+                    .extend(self.before_speech.drain(..).flatten());
+            } else {
+                self.before_speech.push_back(output.samples);
+                let mut before: Duration = self
+                    .before_speech
+                    .iter()
+                    .filter_map(rodio::Source::total_duration)
+                    .sum();
+                while before > self.time_before_speech {
+                    let removed = self.before_speech.pop_front().unwrap();
+                    before -= rodio::Source::total_duration(&removed).unwrap_or_default();
+                }
+                return;
+            }
+        }
+
+        self.utterance_audio.extend(output.samples);
+        self.since_partial += duration;
+        if output.probability < self.end_threshold {
+            self.silence += duration;
+        } else {
+            self.silence = Duration::ZERO;
+        }
+
+        let utterance_duration = Duration::from_secs_f64(
+            self.utterance_audio.len() as f64 / self.sample_rate.max(1) as f64,
+        );
+        if self.silence >= self.end_window {
+            self.finish_utterance();
+            self.in_speech = false;
+        } else if utterance_duration >= MAX_UTTERANCE_DURATION {
+            // Keep listening, but start a new utterance so each one fits in whisper's window
+            self.finish_utterance();
+            self.utterance_start = self.elapsed;
+        }
+    }
+
+    fn finish_utterance(&mut self) {
+        let audio = std::mem::take(&mut self.utterance_audio);
+        self.finished_utterances
+            .push_back((self.utterance, self.utterance_start, audio));
+        self.utterance += 1;
+        self.since_partial = Duration::ZERO;
+        // A provisional caption for the utterance is no longer useful once the final caption is queued
+        if self
+            .running
+            .as_ref()
+            .is_some_and(|running| !running.is_final)
+        {
+            self.running = None;
+        }
+    }
+
+    fn start_transcription(&mut self) {
+        if self.running.is_some() {
+            return;
+        }
+        let (utterance, start, audio, is_final) =
+            if let Some((utterance, start, audio)) = self.finished_utterances.pop_front() {
+                (utterance, start, audio, true)
+            } else if self.in_speech && self.since_partial >= self.partial_interval {
+                self.since_partial = Duration::ZERO;
+                let audio = self.utterance_audio.clone();
+                (self.utterance, self.utterance_start, audio, false)
+            } else {
+                return;
+            };
+        let task = self
+            .whisper
+            .transcribe(SamplesBuffer::new(1, self.sample_rate, audio));
+        self.running = Some(RunningTranscription {
+            task,
+            utterance,
+            start,
+            is_final,
+            text: String::new(),
+        });
+    }
+}
+
+impl<S: AsyncSource + Unpin> Stream for LiveCaptions<S> {
+    type Item = Caption;
+
+    fn poll_next(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            // Read all of the audio that is ready so the captions don't fall behind
+            while !this.source_finished {
+                match this.source.poll_next_unpin(cx) {
+                    Poll::Ready(Some(output)) => this.push_audio(output),
+                    Poll::Ready(None) => {
+                        this.source_finished = true;
+                        if this.in_speech {
+                            this.finish_utterance();
+                            this.in_speech = false;
+                        }
+                    }
+                    Poll::Pending => break,
+                }
+            }
+
+            this.start_transcription();
+            let Some(running) = &mut this.running else {
+                if this.source_finished {
+                    return Poll::Ready(None);
+                }
+                return Poll::Pending;
+            };
+            match running.task.poll_next_unpin(cx) {
+                Poll::Ready(Some(segment)) => running.text += segment.as_ref(),
+                Poll::Ready(None) => {
+                    let running = this.running.take().unwrap();
+                    let text = running.text.trim();
+                    if !text.is_empty() || running.is_final {
+                        return Poll::Ready(Some(Caption {
+                            utterance: running.utterance,
+                            start: running.start,
+                            text: text.to_string(),
+                            is_final: running.is_final,
+                        }));
+                    }
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+impl MicInput {
+    /// Create low latency captions for the microphone. See [`LiveCaptions`] for more details.
+    ///
+    /// ```rust, no_run
+    /// use futures_util::StreamExt;
+    /// use kalosm::sound::*;
+    /// use std::io::Write;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<(), anyhow::Error> {
+    ///     let model = Whisper::new().await?;
+    ///     let mut captions = MicInput::default().live_captions(model);
+    ///     while let Some(caption) = captions.next().await {
+    ///         // Rewrite the current line until the caption is final
+    ///         print!("\r\x1b[2K{caption}");
+    ///         if caption.is_final() {
+    ///             println!();
+    ///         }
+    ///         std::io::stdout().flush()?;
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn live_captions(&self, model: Whisper) -> LiveCaptions<MicStream> {
+        LiveCaptions::new(self.stream(), model)
+    }
+}
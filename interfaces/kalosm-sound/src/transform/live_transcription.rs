@@ -0,0 +1,163 @@
+use std::{task::Poll, time::Duration};
+
+use futures_core::{ready, Stream};
+use futures_util::StreamExt;
+use rodio::buffer::SamplesBuffer;
+use rwhisper::{Segment, TranscriptionTask, Whisper};
+
+use super::voice_audio_detector::*;
+use super::voice_audio_detector_ext::*;
+use crate::{AsyncSource, MicInput, MicStream};
+
+/// A finished utterance from a [`LiveTranscription`] stream. An utterance is a run of speech surrounded by silence.
+#[derive(Debug, Clone)]
+pub struct Utterance {
+    text: String,
+    duration: Duration,
+    segments: Vec<Segment>,
+}
+
+impl Utterance {
+    /// Get the text of the utterance
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Get the duration of the audio the utterance was transcribed from
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// Get the whisper segments that make up the utterance
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+}
+
+impl AsRef<str> for Utterance {
+    fn as_ref(&self) -> &str {
+        &self.text
+    }
+}
+
+impl std::fmt::Display for Utterance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+/// A stream of finished [`Utterance`]s from a live audio source. Created with [`MicInput::transcribe`] or [`AsyncSourceTranscribeExt::transcribe_utterances`].
+///
+/// The audio is resampled for the voice activity detector, split into runs of speech, and each run is transcribed with whisper once the speaker stops talking.
+pub struct LiveTranscription<S: AsyncSource + Unpin> {
+    chunks: VoiceActivityRechunkerStream<VoiceActivityDetectorStream<S>>,
+    whisper: Whisper,
+    word_level_time_stamps: bool,
+    current: Option<(TranscriptionTask, Utterance)>,
+}
+
+impl<S: AsyncSource + Unpin> LiveTranscription<S> {
+    pub(crate) fn new(source: S, whisper: Whisper) -> Self {
+        Self {
+            chunks: source.voice_activity_stream().rechunk_voice_activity(),
+            whisper,
+            word_level_time_stamps: false,
+            current: None,
+        }
+    }
+
+    /// Include word level timestamps in the segments of each utterance.
+    pub fn timestamped(mut self) -> Self {
+        self.word_level_time_stamps = true;
+        self
+    }
+
+    /// Set how much silence ends an utterance. (defaults to 2 seconds)
+    pub fn with_end_of_speech_window(mut self, window: Duration) -> Self {
+        self.chunks = self.chunks.with_end_window(window);
+        self
+    }
+
+    /// Set how much audio from before the speech starts is included in each utterance. (defaults to 500 milliseconds)
+    pub fn with_time_before_speech(mut self, time_before_speech: Duration) -> Self {
+        self.chunks = self.chunks.with_time_before_speech(time_before_speech);
+        self
+    }
+
+    /// Set the [`VoiceActivityDetector`] used to find speech in the audio. (defaults to [`SileroVad`])
+    pub fn with_voice_activity_detector(mut self, vad: impl VoiceActivityDetector) -> Self {
+        self.chunks.source_mut().set_detector(vad);
+        self
+    }
+
+    fn start_utterance(&mut self, samples: SamplesBuffer<f32>) {
+        let duration = rodio::Source::total_duration(&samples).unwrap_or_default();
+        let mut task = self.whisper.transcribe(samples);
+        if self.word_level_time_stamps {
+            task = task.timestamped();
+        }
+        let utterance = Utterance {
+            text: String::new(),
+            duration,
+            segments: Vec::new(),
+        };
+        self.current = Some((task, utterance));
+    }
+}
+
+impl<S: AsyncSource + Unpin> Stream for LiveTranscription<S> {
+    type Item = Utterance;
+
+    fn poll_next(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            if let Some((task, utterance)) = &mut this.current {
+                match ready!(task.poll_next_unpin(cx)) {
+                    Some(segment) => {
+                        utterance.text += segment.text();
+                        utterance.segments.push(segment);
+                        continue;
+                    }
+                    None => {
+                        let (_, mut utterance) = this.current.take().unwrap();
+                        // Skip runs that looked like speech to the detector, but whisper didn't find any words in
+                        if !utterance.text.trim().is_empty() {
+                            utterance.text = utterance.text.trim().to_string();
+                            return Poll::Ready(Some(utterance));
+                        }
+                    }
+                }
+            }
+
+            match ready!(this.chunks.poll_next_unpin(cx)) {
+                Some(samples) => this.start_utterance(samples),
+                None => return Poll::Ready(None),
+            }
+        }
+    }
+}
+
+impl MicInput {
+    /// Transcribe the microphone live. Speech is split into utterances when the speaker pauses, and each utterance is returned once it is transcribed.
+    ///
+    /// ```rust, no_run
+    /// use futures_util::StreamExt;
+    /// use kalosm::sound::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<(), anyhow::Error> {
+    ///     let model = Whisper::new().await?;
+    ///     let mut utterances = MicInput::default().transcribe(model);
+    ///     while let Some(utterance) = utterances.next().await {
+    ///         println!("{utterance}");
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn transcribe(&self, model: Whisper) -> LiveTranscription<MicStream> {
+        LiveTranscription::new(self.stream(), model)
+    }
+}
@@ -0,0 +1,119 @@
+//! Mel frequency cepstral coefficients shared by the lightweight audio models
+
+use std::sync::Arc;
+
+use rustfft::{num_complex::Complex, Fft, FftPlanner};
+
+/// The sample rate audio needs to be resampled to before it is passed to [`MfccExtractor`]
+pub(crate) const MFCC_SAMPLE_RATE: u32 = 16000;
+/// The number of samples in each frame (25ms)
+pub(crate) const FRAME_LENGTH: usize = 400;
+/// The number of samples between the start of each frame (10ms)
+pub(crate) const HOP_LENGTH: usize = 160;
+const FFT_SIZE: usize = 512;
+const MEL_BANDS: usize = 26;
+pub(crate) const CEPSTRAL_COEFFICIENTS: usize = 13;
+
+/// The cepstral coefficients of one frame of audio
+pub(crate) type Cepstrum = [f32; CEPSTRAL_COEFFICIENTS];
+
+/// Turns frames of mono audio sampled at [`MFCC_SAMPLE_RATE`] into mel frequency cepstral coefficients
+pub(crate) struct MfccExtractor {
+    fft: Arc<dyn Fft<f32>>,
+    window: Vec<f32>,
+    mel_filters: Vec<Vec<f32>>,
+}
+
+impl MfccExtractor {
+    pub(crate) fn new() -> Self {
+        let fft = FftPlanner::new().plan_fft_forward(FFT_SIZE);
+        let window = (0..FRAME_LENGTH)
+            .map(|i| 0.5 - 0.5 * (2. * std::f32::consts::PI * i as f32 / FRAME_LENGTH as f32).cos())
+            .collect();
+        Self {
+            fft,
+            window,
+            mel_filters: mel_filters(),
+        }
+    }
+
+    /// Get the mel frequency cepstral coefficients and energy of one frame of [`FRAME_LENGTH`] samples
+    pub(crate) fn cepstrum(&self, frame: &[f32]) -> (Cepstrum, f32) {
+        let mut buffer: Vec<_> = frame
+            .iter()
+            .zip(&self.window)
+            .map(|(sample, window)| Complex::new(sample * window, 0.))
+            .chain(std::iter::repeat(Complex::new(0., 0.)))
+            .take(FFT_SIZE)
+            .collect();
+        self.fft.process(&mut buffer);
+        let power: Vec<f32> = buffer[..FFT_SIZE / 2 + 1]
+            .iter()
+            .map(|bin| bin.norm_sqr())
+            .collect();
+        let energy = power.iter().sum();
+        let log_mel: Vec<f32> = self
+            .mel_filters
+            .iter()
+            .map(|filter| {
+                let band: f32 = filter.iter().zip(&power).map(|(w, p)| w * p).sum();
+                (band + 1e-10).ln()
+            })
+            .collect();
+        // A DCT-II of the log mel energies
+        let mut cepstrum = [0.; CEPSTRAL_COEFFICIENTS];
+        for (k, coefficient) in cepstrum.iter_mut().enumerate() {
+            *coefficient = log_mel
+                .iter()
+                .enumerate()
+                .map(|(n, value)| {
+                    value
+                        * (std::f32::consts::PI * k as f32 * (n as f32 + 0.5) / MEL_BANDS as f32)
+                            .cos()
+                })
+                .sum();
+        }
+        (cepstrum, energy)
+    }
+
+    /// Get the cepstrum and energy of every frame in a clip of audio
+    pub(crate) fn frames(&self, samples: &[f32]) -> Vec<(Cepstrum, f32)> {
+        samples
+            .windows(FRAME_LENGTH)
+            .step_by(HOP_LENGTH)
+            .map(|frame| self.cepstrum(frame))
+            .collect()
+    }
+}
+
+fn mel_filters() -> Vec<Vec<f32>> {
+    fn hz_to_mel(hz: f32) -> f32 {
+        2595. * (1. + hz / 700.).log10()
+    }
+    fn mel_to_hz(mel: f32) -> f32 {
+        700. * (10f32.powf(mel / 2595.) - 1.)
+    }
+    let max_mel = hz_to_mel(MFCC_SAMPLE_RATE as f32 / 2.);
+    let bin_of = |mel: f32| mel_to_hz(mel) * FFT_SIZE as f32 / MFCC_SAMPLE_RATE as f32;
+    let points: Vec<f32> = (0..MEL_BANDS + 2)
+        .map(|i| bin_of(max_mel * i as f32 / (MEL_BANDS + 1) as f32))
+        .collect();
+    points
+        .windows(3)
+        .map(|edges| {
+            let [low, center, high] = [edges[0], edges[1], edges[2]];
+            (0..FFT_SIZE / 2 + 1)
+                .map(|bin| {
+                    let bin = bin as f32;
+                    if bin < low || bin > high {
+                        0.
+                    } else if bin <= center {
+                        (bin - low) / (center - low).max(f32::EPSILON)
+                    } else {
+                        (high - bin) / (high - center).max(f32::EPSILON)
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
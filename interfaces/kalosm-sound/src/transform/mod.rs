@@ -1,3 +1,17 @@
+mod audio_transform;
+pub use audio_transform::*;
+
+mod mfcc;
+
+mod diarization;
+pub use diarization::*;
+
+mod wespeaker;
+pub use wespeaker::*;
+
+mod wake_word;
+pub use wake_word::*;
+
 #[cfg(feature = "denoise")]
 mod denoise;
 #[cfg(feature = "denoise")]
@@ -17,3 +31,13 @@ pub use voice_audio_detector_ext::*;
 mod transcribe;
 #[cfg(feature = "voice_detection")]
 pub use transcribe::*;
+
+#[cfg(feature = "voice_detection")]
+mod live_captions;
+#[cfg(feature = "voice_detection")]
+pub use live_captions::*;
+
+#[cfg(feature = "voice_detection")]
+mod live_transcription;
+#[cfg(feature = "voice_detection")]
+pub use live_transcription::*;
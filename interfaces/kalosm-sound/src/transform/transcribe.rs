@@ -1,5 +1,7 @@
 use rwhisper::ChunkedTranscriptionTask;
 
+use super::live_captions::LiveCaptions;
+use super::live_transcription::LiveTranscription;
 use super::voice_audio_detector::*;
 use super::voice_audio_detector_ext::*;
 use crate::AsyncSource;
@@ -39,6 +41,18 @@ pub trait AsyncSourceTranscribeExt: AsyncSource + Unpin + Send + Sized + 'static
             model,
         )
     }
+
+    /// Chunk the audio stream into utterances based on voice activity and transcribe each utterance. Unlike [`AsyncSourceTranscribeExt::transcribe`], the stream only returns an utterance once the whole run of speech is transcribed.
+    ///
+    /// See [`crate::MicInput::transcribe`] for transcribing the microphone directly.
+    fn transcribe_utterances(self, model: rwhisper::Whisper) -> LiveTranscription<Self> {
+        LiveTranscription::new(self, model)
+    }
+
+    /// Create low latency captions for the audio stream. Provisional captions are created while someone is speaking, and a final caption is created once they pause. See [`LiveCaptions`] for more details.
+    fn live_captions(self, model: rwhisper::Whisper) -> LiveCaptions<Self> {
+        LiveCaptions::new(self, model)
+    }
 }
 
 impl<S: AsyncSource + Unpin + Send + Sized + 'static> AsyncSourceTranscribeExt for S {}
@@ -1,16 +1,148 @@
 //! Handles chunking audio with a voice audio detection model
 use std::{
     pin::Pin,
-    sync::{Arc, RwLock},
+    sync::{Arc, Mutex},
     task::{Context, Poll},
+    time::Duration,
 };
 
 use futures_core::{ready, Stream};
 use futures_util::FutureExt;
 use rodio::buffer::SamplesBuffer;
-use voice_activity_detector::VoiceActivityDetector;
 
-use crate::{AsyncSource, ResampledAsyncSource, VoiceActivityDetectorOutput};
+use crate::{AsyncSource, ResampledAsyncSource, SpeechDetectorOutput, VoiceActivityDetectorOutput};
+
+/// A model that detects voice activity in chunks of mono audio.
+///
+/// Implement this trait to use a custom model with [`VoiceActivityDetectorExt::voice_activity_stream_with`] or the live transcription pipelines. [`SileroVad`] is used by default.
+pub trait VoiceActivityDetector: Send + 'static {
+    /// The sample rate the model expects. Audio is resampled to this rate before it is passed to the model
+    fn sample_rate(&self) -> u32;
+
+    /// The number of samples in each chunk passed to [`VoiceActivityDetector::predict`]
+    fn chunk_size(&self) -> usize;
+
+    /// Predict the probability of voice activity (between 0 and 1) in the next chunk of audio
+    fn predict(&mut self, samples: &[f32]) -> f32;
+
+    /// Decide if the chunk of audio that was just predicted is speech. This is called once after every call to [`VoiceActivityDetector::predict`], so models can keep state between chunks. (defaults to a probability above 0.5)
+    fn is_speech(&mut self, probability: f32) -> bool {
+        probability > 0.5
+    }
+
+    /// Reset any state the model keeps between chunks
+    fn reset(&mut self) {}
+}
+
+/// A [`VoiceActivityDetector`] based on the [Silero VAD](https://github.com/snakers4/silero-vad) model from the [voice_activity_detector](https://github.com/nkeenan38/voice_activity_detector) crate.
+///
+/// Speech starts once the probability rises above [`SileroVad::with_threshold`]. It only ends once the probability stays below [`SileroVad::with_end_threshold`] for longer than [`SileroVad::with_hangover`], so short pauses between words don't split up speech.
+///
+/// ```rust, no_run
+/// use kalosm::sound::*;
+///
+/// # fn main() -> Result<(), anyhow::Error> {
+/// let mut vad = SileroVad::new(16000)?
+///     .with_threshold(0.6)
+///     .with_hangover(std::time::Duration::from_millis(300));
+/// let silence = vec![0.0; vad.chunk_size()];
+/// let probability = vad.predict(&silence);
+/// assert!(!vad.is_speech(probability));
+/// # Ok(())
+/// # }
+/// ```
+pub struct SileroVad {
+    model: voice_activity_detector::VoiceActivityDetector,
+    sample_rate: u32,
+    chunk_size: usize,
+    threshold: f32,
+    end_threshold: f32,
+    hangover: Duration,
+    speaking: bool,
+    silence: Duration,
+}
+
+impl SileroVad {
+    /// Create a new Silero VAD model for audio with the given sample rate. Only 8000 and 16000 hz audio is supported.
+    pub fn new(sample_rate: u32) -> Result<Self, voice_activity_detector::Error> {
+        let chunk_size = SupportedSampleRate::closest(sample_rate).chunk_sizes[0];
+        let model = voice_activity_detector::VoiceActivityDetector::builder()
+            .sample_rate(sample_rate)
+            .chunk_size(chunk_size)
+            .build()?;
+        Ok(Self {
+            model,
+            sample_rate,
+            chunk_size,
+            threshold: 0.5,
+            end_threshold: 0.35,
+            hangover: Duration::from_millis(100),
+            speaking: false,
+            silence: Duration::ZERO,
+        })
+    }
+
+    /// Create a Silero VAD model for the supported sample rate closest to the given sample rate
+    fn for_sample_rate(sample_rate: u32) -> Self {
+        Self::new(SupportedSampleRate::closest(sample_rate).sample_rate).unwrap()
+    }
+
+    /// Set the probability that starts speech. (defaults to 0.5)
+    pub fn with_threshold(mut self, threshold: f32) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Set the probability audio must fall below to count as silence. (defaults to 0.35)
+    pub fn with_end_threshold(mut self, end_threshold: f32) -> Self {
+        self.end_threshold = end_threshold;
+        self
+    }
+
+    /// Set how long the audio must be silent before speech ends. (defaults to 100 milliseconds)
+    pub fn with_hangover(mut self, hangover: Duration) -> Self {
+        self.hangover = hangover;
+        self
+    }
+
+    fn chunk_duration(&self) -> Duration {
+        Duration::from_secs_f64(self.chunk_size as f64 / self.sample_rate as f64)
+    }
+}
+
+impl VoiceActivityDetector for SileroVad {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+
+    fn predict(&mut self, samples: &[f32]) -> f32 {
+        self.model.predict(samples.iter().copied())
+    }
+
+    fn is_speech(&mut self, probability: f32) -> bool {
+        if probability >= self.threshold {
+            self.speaking = true;
+            self.silence = Duration::ZERO;
+        } else if self.speaking && probability < self.end_threshold {
+            self.silence += self.chunk_duration();
+            if self.silence >= self.hangover {
+                self.speaking = false;
+                self.silence = Duration::ZERO;
+            }
+        }
+        self.speaking
+    }
+
+    fn reset(&mut self) {
+        self.model.reset();
+        self.speaking = false;
+        self.silence = Duration::ZERO;
+    }
+}
 
 /// An extension trait for audio streams that adds a voice activity detection information. Based on the [voice_activity_detector](https://github.com/nkeenan38/voice_activity_detector) crate.
 pub trait VoiceActivityDetectorExt: AsyncSource {
@@ -19,10 +151,20 @@ pub trait VoiceActivityDetectorExt: AsyncSource {
     where
         Self: Sized + Unpin,
     {
-        let (source, closest) = resample_to_nearest_supported_rate(self);
-        let vad = closest.vad();
+        let vad = SileroVad::for_sample_rate(self.sample_rate());
+        self.voice_activity_stream_with(vad)
+    }
 
-        VoiceActivityDetectorStream::new(source, vad, closest.chunk_sizes[0])
+    /// Transform the audio stream to a stream of [`SamplesBuffer`]s with voice activity detection information from a custom [`VoiceActivityDetector`]
+    fn voice_activity_stream_with(
+        self,
+        vad: impl VoiceActivityDetector,
+    ) -> VoiceActivityDetectorStream<Self>
+    where
+        Self: Sized + Unpin,
+    {
+        let source = self.resample(vad.sample_rate());
+        VoiceActivityDetectorStream::new(source, Box::new(vad))
     }
 }
 
@@ -33,72 +175,101 @@ pub struct VoiceActivityDetectorStream<S: AsyncSource + Unpin> {
     source: ResampledAsyncSource<S>,
     buffer: Vec<f32>,
     chunk_size: usize,
-    vad: Arc<RwLock<VoiceActivityDetector>>,
-    task: Option<tokio::task::JoinHandle<VoiceActivityDetectorOutput>>,
+    vad: Arc<Mutex<Box<dyn VoiceActivityDetector>>>,
+    task: Option<tokio::task::JoinHandle<SpeechDetectorOutput>>,
 }
 
 impl<S: AsyncSource + Unpin> VoiceActivityDetectorStream<S> {
-    fn new(source: ResampledAsyncSource<S>, vad: VoiceActivityDetector, chunk_size: usize) -> Self {
+    fn new(source: ResampledAsyncSource<S>, vad: Box<dyn VoiceActivityDetector>) -> Self {
+        let chunk_size = vad.chunk_size();
         Self {
             source,
             buffer: Vec::with_capacity(chunk_size),
             chunk_size,
-            vad: Arc::new(RwLock::new(vad)),
+            vad: Arc::new(Mutex::new(vad)),
             task: None,
         }
     }
-}
 
-impl<S: AsyncSource + Unpin> Stream for VoiceActivityDetectorStream<S> {
-    type Item = VoiceActivityDetectorOutput;
+    /// Replace the [`VoiceActivityDetector`] used for the stream. This should be called before the stream is polled.
+    pub fn with_detector(mut self, vad: impl VoiceActivityDetector) -> Self {
+        self.set_detector(vad);
+        self
+    }
 
-    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        let this = self.get_mut();
-        let sample_rate = this.source.sample_rate();
+    pub(crate) fn set_detector(&mut self, vad: impl VoiceActivityDetector) {
+        self.source.set_sample_rate(vad.sample_rate());
+        self.chunk_size = vad.chunk_size();
+        self.buffer.clear();
+        self.vad = Arc::new(Mutex::new(Box::new(vad)));
+    }
+
+    /// Include the decision of the [`VoiceActivityDetector`] if each chunk of audio is speech in the output. The decision can take the audio around the chunk into account, like the hangover of [`SileroVad`]
+    pub fn detect_speech(self) -> SpeechDetectorStream<S> {
+        SpeechDetectorStream { inner: self }
+    }
+
+    fn poll_next_speech(&mut self, cx: &mut Context<'_>) -> Poll<Option<SpeechDetectorOutput>> {
+        let sample_rate = self.source.sample_rate();
 
         loop {
-            if let Some(task) = &mut this.task {
+            if let Some(task) = &mut self.task {
                 let output = ready!(task.poll_unpin(cx));
-                this.task = None;
+                self.task = None;
                 match output {
                     Ok(output) => return Poll::Ready(Some(output)),
                     Err(err) => tracing::error!("Error in voice activity detector: {err}"),
                 }
             }
 
-            let stream = this.source.as_stream();
+            let stream = self.source.as_stream();
             let mut stream = std::pin::pin!(stream);
-            while this.buffer.len() < this.chunk_size {
+            while self.buffer.len() < self.chunk_size {
                 let sample = ready!(stream.as_mut().poll_next(cx));
                 if let Some(sample) = sample {
-                    this.buffer.push(sample);
+                    self.buffer.push(sample);
                 } else {
                     return Poll::Ready(None);
                 }
             }
-            let data = this.buffer.drain(..).collect::<Vec<_>>();
-            let model = this.vad.clone();
+            let data = self.buffer.drain(..).collect::<Vec<_>>();
+            let model = self.vad.clone();
             let vad = tokio::task::spawn_blocking(move || {
-                let mut locked = model.write().unwrap();
-                let vad = locked.predict(data.iter().copied());
-                VoiceActivityDetectorOutput {
-                    probability: vad,
+                let mut locked = model.lock().unwrap();
+                let probability = locked.predict(&data);
+                let is_speech = locked.is_speech(probability);
+                SpeechDetectorOutput {
+                    probability,
+                    is_speech,
                     samples: SamplesBuffer::new(1, sample_rate, data),
                 }
             });
-            this.task = Some(vad);
+            self.task = Some(vad);
         }
     }
 }
 
-/// Resample the audio to the closest supported sample rate
-fn resample_to_nearest_supported_rate<S: AsyncSource + Unpin>(
-    source: S,
-) -> (ResampledAsyncSource<S>, SupportedSampleRate) {
-    let sample_rate = source.sample_rate();
-    let closet = SupportedSampleRate::closest(sample_rate);
-    let resampled = source.resample(closet.sample_rate);
-    (resampled, closet)
+impl<S: AsyncSource + Unpin> Stream for VoiceActivityDetectorStream<S> {
+    type Item = VoiceActivityDetectorOutput;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut()
+            .poll_next_speech(cx)
+            .map(|output| output.map(Into::into))
+    }
+}
+
+/// A stream of [`SamplesBuffer`]s with voice activity detection information and the decision of the [`VoiceActivityDetector`] if the audio is speech. Created with [`VoiceActivityDetectorStream::detect_speech`]
+pub struct SpeechDetectorStream<S: AsyncSource + Unpin> {
+    inner: VoiceActivityDetectorStream<S>,
+}
+
+impl<S: AsyncSource + Unpin> Stream for SpeechDetectorStream<S> {
+    type Item = SpeechDetectorOutput;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.get_mut().inner.poll_next_speech(cx)
+    }
 }
 
 #[derive(Clone, Copy)]
@@ -114,14 +285,6 @@ impl SupportedSampleRate {
             .min_by_key(|sr| (sr.sample_rate as i64 - sample_rate as i64).abs())
             .unwrap()
     }
-
-    fn vad(&self) -> VoiceActivityDetector {
-        VoiceActivityDetector::builder()
-            .sample_rate(self.sample_rate)
-            .chunk_size(self.chunk_sizes[0])
-            .build()
-            .unwrap()
-    }
 }
 
 const SUPPORTED_SAMPLE_RATES: [SupportedSampleRate; 2] = [
@@ -10,6 +10,36 @@ pub struct VoiceActivityDetectorOutput {
     pub samples: rodio::buffer::SamplesBuffer<f32>,
 }
 
+/// The output of a voice activity detector stream with the decision of the detector if the audio is speech
+pub struct SpeechDetectorOutput {
+    /// The probability of voice activity (between 0 and 1)
+    pub probability: f32,
+    /// If the detector decided the audio is speech. Unlike the probability, this may take the audio around the chunk into account
+    pub is_speech: bool,
+    /// The audio sample associated with the voice activity probability
+    pub samples: rodio::buffer::SamplesBuffer<f32>,
+}
+
+impl From<VoiceActivityDetectorOutput> for SpeechDetectorOutput {
+    /// Treat audio with a probability of voice activity above 0.5 as speech
+    fn from(output: VoiceActivityDetectorOutput) -> Self {
+        Self {
+            probability: output.probability,
+            is_speech: output.probability > 0.5,
+            samples: output.samples,
+        }
+    }
+}
+
+impl From<SpeechDetectorOutput> for VoiceActivityDetectorOutput {
+    fn from(output: SpeechDetectorOutput) -> Self {
+        Self {
+            probability: output.probability,
+            samples: output.samples,
+        }
+    }
+}
+
 /// An extension trait for audio streams with voice activity detection information
 pub trait VoiceActivityStreamExt: futures_core::Stream<Item = VoiceActivityDetectorOutput> {
     /// Only keep audio chunks that have a probability of voice activity above the given threshold
@@ -127,6 +157,11 @@ impl<S> VoiceActivityRechunkerStream<S> {
         self.include_duration_before = time_before_speech;
         self
     }
+
+    #[cfg(feature = "voice_detection")]
+    pub(crate) fn source_mut(&mut self) -> &mut S {
+        &mut self.source
+    }
 }
 
 impl<S> VoiceActivityRechunkerStream<S> {
@@ -0,0 +1,372 @@
+use std::{collections::VecDeque, task::Poll, time::Duration};
+
+use futures_core::{ready, Stream};
+use rodio::{source::UniformSourceIterator, Source};
+
+use super::mfcc::{
+    Cepstrum, MfccExtractor, CEPSTRAL_COEFFICIENTS, FRAME_LENGTH, HOP_LENGTH, MFCC_SAMPLE_RATE,
+};
+use crate::{AsyncSource, ResampledAsyncSource};
+
+/// The sample rate audio is resampled to before wake words are detected
+pub const WAKE_WORD_SAMPLE_RATE: u32 = MFCC_SAMPLE_RATE;
+
+/// The features of one frame of audio. The first cepstral coefficient is skipped because it only measures the loudness of the frame
+type Features = [f32; CEPSTRAL_COEFFICIENTS - 1];
+
+/// A user-enrolled keyword that wakes a [`WakeWordDetector`].
+///
+/// There is no pretrained keyword model: each wake word is defined by a few recordings of someone saying the keyword. Audio is matched against the mel frequency cepstral coefficients of each recording with dynamic time warping, so the keyword can be said a bit faster or slower than in the recordings. Three to five recordings by the people who will use the detector, in the environment it will run in, usually work well. Detection is less reliable for voices that are not in the recordings.
+///
+/// ```rust, no_run
+/// use kalosm::sound::*;
+/// use std::{fs::File, io::BufReader};
+///
+/// # fn main() -> Result<(), anyhow::Error> {
+/// let mut wake_word = WakeWord::new("hey kalosm");
+/// for path in ["hey_kalosm_1.wav", "hey_kalosm_2.wav", "hey_kalosm_3.wav"] {
+///     let audio = rodio::Decoder::new(BufReader::new(File::open(path)?))?;
+///     wake_word = wake_word.with_example(audio);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct WakeWord {
+    name: String,
+    templates: Vec<Vec<Features>>,
+}
+
+impl WakeWord {
+    /// Create a new wake word with no examples. Add recordings of the keyword with [`WakeWord::with_example`]
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            templates: Vec::new(),
+        }
+    }
+
+    /// Add a recording of someone saying the wake word. Silence before and after the keyword is trimmed
+    pub fn with_example<S>(mut self, audio: S) -> Self
+    where
+        S: Source,
+        <S as Iterator>::Item: rodio::Sample,
+    {
+        let samples: Vec<f32> =
+            UniformSourceIterator::<_, f32>::new(audio, 1, WAKE_WORD_SAMPLE_RATE).collect();
+        let frames = MfccExtractor::new().frames(&samples);
+        if frames.is_empty() {
+            tracing::warn!("Wake word example for {:?} is too short", self.name);
+            return self;
+        }
+        let mean_energy =
+            frames.iter().map(|(_, energy)| energy).sum::<f32>() / frames.len() as f32;
+        let is_voiced = |(_, energy): &(_, f32)| *energy >= mean_energy * 0.1;
+        let start = frames.iter().position(is_voiced).unwrap();
+        let end = frames.iter().rposition(is_voiced).unwrap();
+        self.templates.push(
+            frames[start..=end]
+                .iter()
+                .map(|(cepstrum, _)| features(cepstrum))
+                .collect(),
+        );
+        self
+    }
+
+    /// Get the name of the wake word
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+fn features(cepstrum: &Cepstrum) -> Features {
+    let mut features = [0.; CEPSTRAL_COEFFICIENTS - 1];
+    features.copy_from_slice(&cepstrum[1..]);
+    features
+}
+
+fn cosine_similarity(a: &Features, b: &Features) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(a, b)| a * b).sum();
+    let norm_a: f32 = a.iter().map(|a| a * a).sum::<f32>().sqrt();
+    let norm_b: f32 = b.iter().map(|b| b * b).sum::<f32>().sqrt();
+    dot / (norm_a * norm_b).max(f32::EPSILON)
+}
+
+/// Find how well the template matches the end of the window with dynamic time warping. The match can start anywhere in the window, but must end at the last frame. Returns the average similarity of the aligned frames
+fn match_template(template: &[Features], window: &[Features]) -> f32 {
+    let width = window.len();
+    // The total cost and length of the best path to each cell in the previous and current row
+    let mut previous = vec![(0., 0usize); width];
+    let mut current = vec![(0., 0usize); width];
+    for (i, template_frame) in template.iter().enumerate() {
+        for (j, window_frame) in window.iter().enumerate() {
+            let cost = 1. - cosine_similarity(template_frame, window_frame);
+            let best = if i == 0 {
+                // The match can start at any frame in the window
+                (0., 0)
+            } else {
+                let mut best = previous[j];
+                if j > 0 {
+                    for candidate in [previous[j - 1], current[j - 1]] {
+                        if candidate.0 < best.0 {
+                            best = candidate;
+                        }
+                    }
+                }
+                best
+            };
+            current[j] = (best.0 + cost, best.1 + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    let (cost, length) = previous[width - 1];
+    1. - cost / length.max(1) as f32
+}
+
+/// Listens for user-enrolled [`WakeWord`]s in a stream of audio.
+///
+/// The detector is small enough to run on every sample from the microphone, so it can be used to only start more expensive models like [`crate::Whisper`] once the user says the wake word. See [`WakeWordExt::wake_word_gate`] for a stream that does this for you.
+pub struct WakeWordDetector {
+    wake_words: Vec<WakeWord>,
+    threshold: f32,
+    extractor: MfccExtractor,
+    samples: Vec<f32>,
+    frames: VecDeque<Features>,
+    max_frames: usize,
+    frames_since_check: usize,
+}
+
+/// How many frames are read between each check for a wake word
+const CHECK_INTERVAL: usize = 3;
+
+impl WakeWordDetector {
+    /// Create a detector that listens for any of the given wake words
+    pub fn new(wake_words: impl IntoIterator<Item = WakeWord>) -> Self {
+        let wake_words: Vec<_> = wake_words.into_iter().collect();
+        let longest = wake_words
+            .iter()
+            .flat_map(|wake_word| &wake_word.templates)
+            .map(Vec::len)
+            .max()
+            .unwrap_or(0);
+        Self {
+            wake_words,
+            threshold: 0.8,
+            extractor: MfccExtractor::new(),
+            samples: Vec::new(),
+            frames: VecDeque::new(),
+            // Leave room for the keyword to be said slower than in the examples
+            max_frames: longest * 3 / 2,
+            frames_since_check: 0,
+        }
+    }
+
+    /// Set the similarity (between 0 and 1) the audio needs with an example of a wake word to wake the detector. Higher values cause fewer false wake ups, but may miss the wake word more often. (defaults to 0.8)
+    pub fn with_threshold(mut self, threshold: f32) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Get the wake words the detector listens for
+    pub fn wake_words(&self) -> &[WakeWord] {
+        &self.wake_words
+    }
+
+    /// Read the next samples of mono audio sampled at [`WAKE_WORD_SAMPLE_RATE`]. Returns the wake word if it was said in the audio heard so far
+    pub fn detect(&mut self, samples: &[f32]) -> Option<&WakeWord> {
+        self.samples.extend_from_slice(samples);
+        let mut detected = None;
+        let mut read = 0;
+        while self.samples.len() - read >= FRAME_LENGTH {
+            let (cepstrum, _) = self
+                .extractor
+                .cepstrum(&self.samples[read..read + FRAME_LENGTH]);
+            read += HOP_LENGTH;
+            self.frames.push_back(features(&cepstrum));
+            if self.frames.len() > self.max_frames {
+                self.frames.pop_front();
+            }
+            self.frames_since_check += 1;
+            if detected.is_none() && self.frames_since_check >= CHECK_INTERVAL {
+                self.frames_since_check = 0;
+                detected = self.check();
+            }
+        }
+        self.samples.drain(..read);
+        let index = detected?;
+        // Start listening from scratch so the same wake word isn't detected again
+        self.reset();
+        self.wake_words.get(index)
+    }
+
+    fn check(&mut self) -> Option<usize> {
+        let window = self.frames.make_contiguous();
+        self.wake_words.iter().position(|wake_word| {
+            wake_word.templates.iter().any(|template| {
+                // Wait until there is enough audio to hold the keyword
+                template.len() <= window.len() && match_template(template, window) >= self.threshold
+            })
+        })
+    }
+
+    /// Forget all of the audio heard so far
+    pub fn reset(&mut self) {
+        self.samples.clear();
+        self.frames.clear();
+        self.frames_since_check = 0;
+    }
+}
+
+/// An extension trait for audio streams that only lets audio through after a wake word is said
+pub trait WakeWordExt: AsyncSource {
+    /// Only pass audio through after one of the detector's wake words is said. Once the detector wakes up, the audio after the wake word is passed through for [`WakeWordGate::with_listen_duration`] before the gate starts listening for the wake word again.
+    ///
+    /// The gated stream is an [`AsyncSource`], so it can be used with the rest of the microphone pipeline. Models after the gate only run after the wake word is said:
+    ///
+    /// ```rust, no_run
+    /// use futures_util::StreamExt;
+    /// use kalosm::sound::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<(), anyhow::Error> {
+    ///     let wake_word = WakeWord::new("hey kalosm")
+    ///         .with_example(rodio::Decoder::new(std::fs::File::open("hey_kalosm_1.wav")?)?)
+    ///         .with_example(rodio::Decoder::new(std::fs::File::open("hey_kalosm_2.wav")?)?);
+    ///     let model = Whisper::new().await?;
+    ///     let mut commands = MicInput::default()
+    ///         .stream()
+    ///         .wake_word_gate(WakeWordDetector::new([wake_word]))
+    ///         .transcribe_utterances(model);
+    ///     while let Some(command) = commands.next().await {
+    ///         println!("{command}");
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    fn wake_word_gate(self, detector: WakeWordDetector) -> WakeWordGate<Self>
+    where
+        Self: Sized + Unpin,
+    {
+        WakeWordGate {
+            source: self.resample(WAKE_WORD_SAMPLE_RATE),
+            detector,
+            listen_duration: Duration::from_secs(5),
+            pending: Vec::with_capacity(HOP_LENGTH),
+            remaining: 0,
+        }
+    }
+}
+
+impl<S: AsyncSource> WakeWordExt for S {}
+
+/// An audio stream that only passes audio through after a wake word is said. Created with [`WakeWordExt::wake_word_gate`]
+pub struct WakeWordGate<S: AsyncSource + Unpin> {
+    source: ResampledAsyncSource<S>,
+    detector: WakeWordDetector,
+    listen_duration: Duration,
+    // Samples that have not been passed to the detector yet
+    pending: Vec<f32>,
+    // The number of samples left to pass through before listening for the wake word again
+    remaining: usize,
+}
+
+impl<S: AsyncSource + Unpin> WakeWordGate<S> {
+    /// Set how long audio is passed through after the wake word is said. (defaults to 5 seconds)
+    pub fn with_listen_duration(mut self, listen_duration: Duration) -> Self {
+        self.listen_duration = listen_duration;
+        self
+    }
+}
+
+impl<S: AsyncSource + Unpin> Stream for WakeWordGate<S> {
+    type Item = f32;
+
+    fn poll_next(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        let stream = this.source.as_stream();
+        let mut stream = std::pin::pin!(stream);
+        loop {
+            let Some(sample) = ready!(stream.as_mut().poll_next(cx)) else {
+                return Poll::Ready(None);
+            };
+            if this.remaining > 0 {
+                this.remaining -= 1;
+                return Poll::Ready(Some(sample));
+            }
+            this.pending.push(sample);
+            if this.pending.len() >= HOP_LENGTH {
+                if let Some(wake_word) = this.detector.detect(&this.pending) {
+                    tracing::trace!("Heard wake word {:?}", wake_word.name());
+                    this.remaining = (this.listen_duration.as_secs_f64()
+                        * WAKE_WORD_SAMPLE_RATE as f64)
+                        as usize;
+                }
+                this.pending.clear();
+            }
+        }
+    }
+}
+
+impl<S: AsyncSource + Unpin> AsyncSource for WakeWordGate<S> {
+    fn as_stream(&mut self) -> impl Stream<Item = f32> + '_ {
+        self
+    }
+
+    fn sample_rate(&self) -> u32 {
+        WAKE_WORD_SAMPLE_RATE
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn detects_the_wake_word() {
+    use rodio::buffer::SamplesBuffer;
+
+    // A "word" made of a few tones with a little noise so each recording is different
+    fn say(tones: &[f32], seed: u32) -> Vec<f32> {
+        let mut state = seed;
+        let mut noise = move || {
+            state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+            (state >> 8) as f32 / (1 << 24) as f32 - 0.5
+        };
+        tones
+            .iter()
+            .flat_map(|frequency| {
+                (0..WAKE_WORD_SAMPLE_RATE as usize / 5).map(move |i| {
+                    let t = i as f32 / WAKE_WORD_SAMPLE_RATE as f32;
+                    (2. * std::f32::consts::PI * frequency * t).sin() * 0.5
+                })
+            })
+            .map(|sample| sample + noise() * 0.02)
+            .collect()
+    }
+    let silence = vec![0.; WAKE_WORD_SAMPLE_RATE as usize / 2];
+    let keyword = [300., 1200., 600.];
+    let other = [2000., 450., 3000.];
+
+    let mut wake_word = WakeWord::new("tones");
+    for seed in 0..3 {
+        let example = [silence.clone(), say(&keyword, seed), silence.clone()].concat();
+        wake_word = wake_word.with_example(SamplesBuffer::new(1, WAKE_WORD_SAMPLE_RATE, example));
+    }
+    let mut detector = WakeWordDetector::new([wake_word]);
+
+    let heard = |detector: &mut WakeWordDetector, audio: Vec<f32>| {
+        detector.reset();
+        audio
+            .chunks(HOP_LENGTH)
+            .any(|chunk| detector.detect(chunk).is_some())
+    };
+    assert!(!heard(&mut detector, silence.clone()));
+    assert!(!heard(
+        &mut detector,
+        [silence.clone(), say(&other, 10), silence.clone()].concat()
+    ));
+    assert!(heard(
+        &mut detector,
+        [silence.clone(), say(&keyword, 20), silence.clone()].concat()
+    ));
+}
@@ -0,0 +1,419 @@
+//! A neural speaker embedding model
+//!
+//! Based on the [WeSpeaker](https://github.com/wenet-e2e/wespeaker) ResNet34 model with the weights from <https://huggingface.co/pyannote/wespeaker-voxceleb-resnet34-LM>
+
+use std::sync::Arc;
+
+use candle_core::{DType, Device, Module, Tensor, D};
+use candle_nn::{
+    batch_norm, conv2d_no_bias, linear, BatchNorm, Conv2d, Conv2dConfig, Linear, VarBuilder,
+};
+use kalosm_common::*;
+use kalosm_model_types::{FileSource, ModelLoadingProgress};
+use rustfft::{num_complex::Complex, Fft, FftPlanner};
+
+use super::diarization::{SpeakerEmbedder, DIARIZATION_SAMPLE_RATE};
+use super::mfcc::{FRAME_LENGTH, HOP_LENGTH};
+
+const FFT_SIZE: usize = 512;
+const MEL_BINS: usize = 80;
+const PREEMPHASIS: f32 = 0.97;
+/// The number of channels in the first layer of the ResNet. Each layer after the first doubles the channels
+const CHANNELS: usize = 32;
+/// The number of blocks in each layer of the ResNet
+const BLOCKS: [usize; 4] = [3, 4, 6, 3];
+/// The length of the embeddings [`WeSpeaker`] creates
+pub const WESPEAKER_EMBEDDING_SIZE: usize = 256;
+/// Clips shorter than this are repeated until they are this long so the pooling layer has more than one frame to work with
+const MIN_SAMPLES: usize = FRAME_LENGTH + 16 * HOP_LENGTH;
+
+/// A builder for [`WeSpeaker`].
+#[derive(Default)]
+pub struct WeSpeakerBuilder {
+    source: WeSpeakerSource,
+    cache: Cache,
+}
+
+impl WeSpeakerBuilder {
+    /// Sets the source of the model.
+    pub fn with_source(mut self, source: WeSpeakerSource) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// Set the cache location to use for the model (defaults DATA_DIR/kalosm/cache)
+    pub fn with_cache(mut self, cache: Cache) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    /// Builds the [`WeSpeaker`] model.
+    pub async fn build(self) -> Result<WeSpeaker, LoadWeSpeakerError> {
+        self.build_with_loading_handler(ModelLoadingProgress::multi_bar_loading_indicator())
+            .await
+    }
+
+    /// Builds the [`WeSpeaker`] model with a handler for the progress of the download.
+    pub async fn build_with_loading_handler(
+        self,
+        handler: impl FnMut(ModelLoadingProgress) + Send + Sync + 'static,
+    ) -> Result<WeSpeaker, LoadWeSpeakerError> {
+        WeSpeaker::from_builder(self, handler).await
+    }
+}
+
+/// The source of the model.
+pub struct WeSpeakerSource {
+    model: FileSource,
+}
+
+impl WeSpeakerSource {
+    /// Creates a new [`WeSpeakerSource`] from a PyTorch checkpoint with the weights of a WeSpeaker ResNet34 model trained on 80 mel bins.
+    pub fn new(model: FileSource) -> Self {
+        Self { model }
+    }
+
+    /// Create the ResNet34 model source trained on VoxCeleb.
+    pub fn resnet34() -> Self {
+        Self::new(FileSource::huggingface(
+            "pyannote/wespeaker-voxceleb-resnet34-LM",
+            "main",
+            "pytorch_model.bin",
+        ))
+    }
+}
+
+impl Default for WeSpeakerSource {
+    fn default() -> Self {
+        Self::resnet34()
+    }
+}
+
+/// An error that can occur when loading a [`WeSpeaker`] model.
+#[derive(Debug, thiserror::Error)]
+pub enum LoadWeSpeakerError {
+    /// An error that can occur when trying to load a [`WeSpeaker`] model into a device.
+    #[error("Failed to load model into device: {0}")]
+    LoadModel(#[from] candle_core::Error),
+    /// An error that can occur when downloading a [`WeSpeaker`] model from the cache.
+    #[error("Failed to download model: {0}")]
+    DownloadModel(#[from] CacheError),
+}
+
+/// A neural [`SpeakerEmbedder`] based on the [WeSpeaker](https://github.com/wenet-e2e/wespeaker) ResNet34 model.
+///
+/// This is the recommended embedder for diarization. It is much more accurate than the [`SpectralSpeakerEmbedder`](crate::SpectralSpeakerEmbedder) with many speakers or noisy audio, but it needs to download about 30MB of weights.
+///
+/// ```rust, no_run
+/// use kalosm::sound::*;
+///
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), anyhow::Error> {
+/// let model = WeSpeaker::new().await?;
+/// let audio = vec![0.; DIARIZATION_SAMPLE_RATE as usize];
+/// let embedding = model.try_embed(&audio)?;
+/// assert_eq!(embedding.len(), WESPEAKER_EMBEDDING_SIZE);
+/// # Ok(())
+/// # }
+/// ```
+pub struct WeSpeaker {
+    device: Device,
+    model: ResNet,
+    fbank: Fbank,
+}
+
+impl WeSpeaker {
+    /// Creates a new [`WeSpeakerBuilder`].
+    pub fn builder() -> WeSpeakerBuilder {
+        WeSpeakerBuilder::default()
+    }
+
+    /// Create a new default WeSpeaker model.
+    pub async fn new() -> Result<Self, LoadWeSpeakerError> {
+        Self::builder().build().await
+    }
+
+    async fn from_builder(
+        builder: WeSpeakerBuilder,
+        mut handler: impl FnMut(ModelLoadingProgress) + Send + Sync + 'static,
+    ) -> Result<Self, LoadWeSpeakerError> {
+        let WeSpeakerBuilder { source, cache } = builder;
+        cache.ensure_available([&source.model])?;
+
+        let mut create_progress =
+            ModelLoadingProgress::downloading_progress(format!("Model ({})", source.model));
+        let filename = cache
+            .get(&source.model, |progress| handler(create_progress(progress)))
+            .await?;
+
+        let device = accelerated_device_if_available()?;
+        // The weights are stored in a PyTorch Lightning checkpoint
+        let vb = VarBuilder::from_pth_with_state(filename, DType::F32, "state_dict", &device)?;
+        let model = ResNet::load(vb.pp("resnet"))?;
+
+        Ok(Self {
+            device,
+            model,
+            fbank: Fbank::new(),
+        })
+    }
+
+    /// Embed a clip of mono audio sampled at [`DIARIZATION_SAMPLE_RATE`]. Returns an embedding with [`WESPEAKER_EMBEDDING_SIZE`] values.
+    pub fn try_embed(&self, samples: &[f32]) -> candle_core::Result<Vec<f32>> {
+        if samples.is_empty() {
+            return Ok(vec![0.; WESPEAKER_EMBEDDING_SIZE]);
+        }
+        let repeated: Vec<f32>;
+        let samples = if samples.len() < MIN_SAMPLES {
+            repeated = samples.iter().cycle().take(MIN_SAMPLES).copied().collect();
+            &repeated
+        } else {
+            samples
+        };
+        let (features, frames) = self.fbank.features(samples);
+        let features = Tensor::from_vec(features, (frames, MEL_BINS), &self.device)?;
+        // Remove the mean of each mel bin over the clip
+        let features = features.broadcast_sub(&features.mean_keepdim(0)?)?;
+        self.model
+            .forward(&features.unsqueeze(0)?)?
+            .flatten_all()?
+            .to_vec1()
+    }
+}
+
+impl SpeakerEmbedder for WeSpeaker {
+    fn embed(&mut self, samples: &[f32]) -> Vec<f32> {
+        match self.try_embed(samples) {
+            Ok(embedding) => embedding,
+            Err(err) => {
+                tracing::error!("Failed to embed speaker: {err}");
+                vec![0.; WESPEAKER_EMBEDDING_SIZE]
+            }
+        }
+    }
+}
+
+/// Kaldi compatible log mel filter bank features, which is what WeSpeaker was trained on
+struct Fbank {
+    fft: Arc<dyn Fft<f32>>,
+    window: Vec<f32>,
+    mel_banks: Vec<Vec<f32>>,
+}
+
+impl Fbank {
+    fn new() -> Self {
+        let fft = FftPlanner::new().plan_fft_forward(FFT_SIZE);
+        // A symmetric hamming window
+        let window = (0..FRAME_LENGTH)
+            .map(|i| {
+                0.54 - 0.46
+                    * (2. * std::f32::consts::PI * i as f32 / (FRAME_LENGTH - 1) as f32).cos()
+            })
+            .collect();
+        Self {
+            fft,
+            window,
+            mel_banks: kaldi_mel_banks(),
+        }
+    }
+
+    /// Get the log mel energies of every frame in the clip. Returns the features in time major order and the number of frames
+    fn features(&self, samples: &[f32]) -> (Vec<f32>, usize) {
+        let mut features = Vec::new();
+        let mut frames = 0;
+        for frame in samples.windows(FRAME_LENGTH).step_by(HOP_LENGTH) {
+            // Kaldi expects the samples to be 16 bit integers
+            let mut frame: Vec<f32> = frame.iter().map(|sample| sample * 32768.).collect();
+            let mean = frame.iter().sum::<f32>() / FRAME_LENGTH as f32;
+            for sample in &mut frame {
+                *sample -= mean;
+            }
+            for i in (1..FRAME_LENGTH).rev() {
+                frame[i] -= PREEMPHASIS * frame[i - 1];
+            }
+            frame[0] -= PREEMPHASIS * frame[0];
+
+            let mut buffer: Vec<_> = frame
+                .iter()
+                .zip(&self.window)
+                .map(|(sample, window)| Complex::new(sample * window, 0.))
+                .chain(std::iter::repeat(Complex::new(0., 0.)))
+                .take(FFT_SIZE)
+                .collect();
+            self.fft.process(&mut buffer);
+            features.extend(self.mel_banks.iter().map(|bank| {
+                let energy: f32 = bank
+                    .iter()
+                    .zip(&buffer)
+                    .map(|(weight, bin)| weight * bin.norm_sqr())
+                    .sum();
+                energy.max(f32::EPSILON).ln()
+            }));
+            frames += 1;
+        }
+        (features, frames)
+    }
+}
+
+/// Triangular filters spaced evenly on the mel scale between 20hz and the nyquist frequency. Kaldi never uses the nyquist bin, so each filter has [`FFT_SIZE`] / 2 weights
+fn kaldi_mel_banks() -> Vec<Vec<f32>> {
+    fn mel(hz: f32) -> f32 {
+        1127. * (1. + hz / 700.).ln()
+    }
+    let low = mel(20.);
+    let high = mel(DIARIZATION_SAMPLE_RATE as f32 / 2.);
+    let delta = (high - low) / (MEL_BINS + 1) as f32;
+    let bin_width = DIARIZATION_SAMPLE_RATE as f32 / FFT_SIZE as f32;
+    (0..MEL_BINS)
+        .map(|bank| {
+            let left = low + bank as f32 * delta;
+            let center = left + delta;
+            let right = center + delta;
+            (0..FFT_SIZE / 2)
+                .map(|bin| {
+                    let mel = mel(bin_width * bin as f32);
+                    let up = (mel - left) / (center - left);
+                    let down = (right - mel) / (right - center);
+                    up.min(down).max(0.)
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn conv(
+    in_channels: usize,
+    out_channels: usize,
+    kernel_size: usize,
+    stride: usize,
+    vb: VarBuilder,
+) -> candle_core::Result<Conv2d> {
+    let config = Conv2dConfig {
+        padding: kernel_size / 2,
+        stride,
+        ..Default::default()
+    };
+    conv2d_no_bias(in_channels, out_channels, kernel_size, config, vb)
+}
+
+#[derive(Debug)]
+struct BasicBlock {
+    conv1: Conv2d,
+    bn1: BatchNorm,
+    conv2: Conv2d,
+    bn2: BatchNorm,
+    shortcut: Option<(Conv2d, BatchNorm)>,
+}
+
+impl BasicBlock {
+    fn load(
+        in_channels: usize,
+        channels: usize,
+        stride: usize,
+        vb: VarBuilder,
+    ) -> candle_core::Result<Self> {
+        let shortcut = if stride != 1 || in_channels != channels {
+            Some((
+                conv(in_channels, channels, 1, stride, vb.pp("shortcut.0"))?,
+                batch_norm(channels, 1e-5, vb.pp("shortcut.1"))?,
+            ))
+        } else {
+            None
+        };
+        Ok(Self {
+            conv1: conv(in_channels, channels, 3, stride, vb.pp("conv1"))?,
+            bn1: batch_norm(channels, 1e-5, vb.pp("bn1"))?,
+            conv2: conv(channels, channels, 3, 1, vb.pp("conv2"))?,
+            bn2: batch_norm(channels, 1e-5, vb.pp("bn2"))?,
+            shortcut,
+        })
+    }
+}
+
+impl Module for BasicBlock {
+    fn forward(&self, xs: &Tensor) -> candle_core::Result<Tensor> {
+        let ys = xs
+            .apply(&self.conv1)?
+            .apply_t(&self.bn1, false)?
+            .relu()?
+            .apply(&self.conv2)?
+            .apply_t(&self.bn2, false)?;
+        let shortcut = match &self.shortcut {
+            Some((conv, bn)) => xs.apply(conv)?.apply_t(bn, false)?,
+            None => xs.clone(),
+        };
+        (ys + shortcut)?.relu()
+    }
+}
+
+/// The WeSpeaker ResNet34 with temporal statistics pooling and a single embedding layer
+#[derive(Debug)]
+struct ResNet {
+    conv1: Conv2d,
+    bn1: BatchNorm,
+    blocks: Vec<BasicBlock>,
+    seg_1: Linear,
+}
+
+impl ResNet {
+    fn load(vb: VarBuilder) -> candle_core::Result<Self> {
+        let mut blocks = Vec::new();
+        let mut in_channels = CHANNELS;
+        for (layer, count) in BLOCKS.into_iter().enumerate() {
+            let channels = CHANNELS << layer;
+            for block in 0..count {
+                let stride = if layer > 0 && block == 0 { 2 } else { 1 };
+                blocks.push(BasicBlock::load(
+                    in_channels,
+                    channels,
+                    stride,
+                    vb.pp(format!("layer{}.{block}", layer + 1)),
+                )?);
+                in_channels = channels;
+            }
+        }
+        // The frequency axis is halved by each of the last three layers
+        let stats_size = MEL_BINS / 8 * in_channels;
+        Ok(Self {
+            conv1: conv(1, CHANNELS, 3, 1, vb.pp("conv1"))?,
+            bn1: batch_norm(CHANNELS, 1e-5, vb.pp("bn1"))?,
+            blocks,
+            seg_1: linear(2 * stats_size, WESPEAKER_EMBEDDING_SIZE, vb.pp("seg_1"))?,
+        })
+    }
+}
+
+impl Module for ResNet {
+    /// Embed features with the shape (batch, frames, mel bins)
+    fn forward(&self, features: &Tensor) -> candle_core::Result<Tensor> {
+        let xs = features.transpose(1, 2)?.unsqueeze(1)?.contiguous()?;
+        let xs = xs.apply(&self.conv1)?.apply_t(&self.bn1, false)?.relu()?;
+        let xs = self
+            .blocks
+            .iter()
+            .try_fold(xs, |xs, block| xs.apply(block))?;
+        let (batch, channels, bins, frames) = xs.dims4()?;
+        let xs = xs.reshape((batch, channels * bins, frames))?;
+        // Temporal statistics pooling
+        let mean = xs.mean(D::Minus1)?;
+        let std = (xs.var(D::Minus1)? + 1e-7)?.sqrt()?;
+        Tensor::cat(&[mean, std], 1)?.apply(&self.seg_1)
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn embeds_any_length_clip() {
+    let device = Device::Cpu;
+    let model = WeSpeaker {
+        model: ResNet::load(VarBuilder::zeros(DType::F32, &device)).unwrap(),
+        device,
+        fbank: Fbank::new(),
+    };
+    for length in [0, 100, DIARIZATION_SAMPLE_RATE as usize] {
+        let samples: Vec<f32> = (0..length).map(|i| (i as f32 * 0.05).sin()).collect();
+        let embedding = model.try_embed(&samples).unwrap();
+        assert_eq!(embedding.len(), WESPEAKER_EMBEDDING_SIZE);
+        assert!(embedding.iter().all(|value| value.is_finite()));
+    }
+}
@@ -1,6 +1,8 @@
 #![warn(missing_docs)]
 #![doc = include_str!("../README.md")]
 
+pub use kalosm_clip::*;
+pub use kalosm_detection::*;
 pub use kalosm_ocr::*;
 pub use rwuerstchen::*;
 pub use segment_anything_rs::*;
@@ -0,0 +1,22 @@
+use kalosm::language::*;
+use kalosm::Benchmark;
+
+#[tokio::main]
+async fn main() {
+    let llm = Llama::builder()
+        .with_source(LlamaSource::phi_3_5_mini_4k_instruct())
+        .build()
+        .await
+        .unwrap();
+    let bert = Bert::new().await.unwrap();
+
+    let mut benchmark = Benchmark::new("phi-3.5-mini-4k-instruct")
+        .with_batch_sizes([1, 4])
+        .with_context_lengths([128, 512, 2048]);
+    benchmark.text_completion(&llm).await.unwrap();
+    benchmark.embedder(&bert).await.unwrap();
+
+    let report = benchmark.to_json();
+    std::fs::write("benchmark.json", &report).unwrap();
+    println!("{report}");
+}
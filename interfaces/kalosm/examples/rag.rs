@@ -44,23 +44,16 @@ async fn main() -> anyhow::Result<()> {
         let user_question = prompt_input("\n> ")?;
 
         // Search for relevant context in the document engine
-        let context = document_table
+        let results = document_table
             .search(&user_question)
-            .with_results(1)
-            .await?
-            .into_iter()
-            .map(|document| {
-                format!(
-                    "Title: {}\nBody: {}\n",
-                    document.record.title(),
-                    document.record.body()
-                )
-            })
-            .collect::<Vec<_>>()
-            .join("\n");
+            .with_results(3)
+            .await?;
+
+        // Number the results so the model can cite them
+        let sources = CitedSources::new(&results);
 
         // Format a prompt with the question and context
-        let prompt = format!("{context}\n{user_question}");
+        let prompt = sources.prompt(&user_question);
 
         // Display the prompt to the user for debugging purposes
         println!("{}", prompt);
@@ -69,5 +62,16 @@ async fn main() -> anyhow::Result<()> {
         let mut output_stream = chat(&prompt);
         print!("Bot: ");
         output_stream.to_std_out().await?;
+
+        // Show the sources the answer cited
+        let answer = sources.annotate(output_stream.await?);
+        for citation in answer.citations() {
+            println!(
+                "[{}] {} {}",
+                citation.index,
+                citation.title,
+                citation.source.as_deref().unwrap_or_default()
+            );
+        }
     }
 }
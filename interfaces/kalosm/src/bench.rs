@@ -0,0 +1,390 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use futures_util::future::try_join_all;
+use kalosm_language::kalosm_language_model::{Embedder, GenerationParameters, TextCompletionModel};
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "vision")]
+use futures_util::StreamExt;
+#[cfg(feature = "vision")]
+use kalosm_vision::{Wuerstchen, WuerstchenInferenceSettings};
+#[cfg(feature = "vision")]
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+const FILLER: &[&str] = &[
+    "The", "quick", "brown", "fox", "jumps", "over", "the", "lazy", "dog.",
+];
+
+/// Create a prompt with roughly `words` tokens.
+fn filler_text(words: usize) -> String {
+    FILLER
+        .iter()
+        .cycle()
+        .take(words)
+        .copied()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.
+}
+
+fn per_second(count: usize, duration: Duration) -> f64 {
+    let seconds = duration.as_secs_f64();
+    if seconds > 0. {
+        count as f64 / seconds
+    } else {
+        0.
+    }
+}
+
+/// A benchmark that measures the performance of models across batch sizes and context lengths.
+///
+/// Context lengths are measured in words of filler text which is roughly one token per word.
+///
+/// For text completion, the batch size is the number of completions that run at the same time. For embedding, the batch size is the number of texts embedded in one call. For image generation, the batch size is the number of images generated in one run.
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm::language::*;
+/// use kalosm::Benchmark;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let llm = Llama::new().await.unwrap();
+///     let mut benchmark = Benchmark::new("llama")
+///         .with_batch_sizes([1, 4])
+///         .with_context_lengths([128, 1024]);
+///     benchmark.text_completion(&llm).await.unwrap();
+///     println!("{}", benchmark.to_json());
+/// }
+/// ```
+pub struct Benchmark {
+    batch_sizes: Vec<usize>,
+    context_lengths: Vec<usize>,
+    generated_tokens: u32,
+    iterations: usize,
+    report: BenchmarkReport,
+}
+
+impl Benchmark {
+    /// Create a new benchmark. The name is included in the report to tell different models or quantizations apart.
+    pub fn new(name: impl ToString) -> Self {
+        Self {
+            batch_sizes: vec![1],
+            context_lengths: vec![128, 512],
+            generated_tokens: 64,
+            iterations: 3,
+            report: BenchmarkReport {
+                name: name.to_string(),
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Set the batch sizes to benchmark. (Defaults to `[1]`)
+    pub fn with_batch_sizes(mut self, batch_sizes: impl IntoIterator<Item = usize>) -> Self {
+        self.batch_sizes = batch_sizes.into_iter().collect();
+        self
+    }
+
+    /// Set the context lengths to benchmark in words of filler text. (Defaults to `[128, 512]`)
+    pub fn with_context_lengths(
+        mut self,
+        context_lengths: impl IntoIterator<Item = usize>,
+    ) -> Self {
+        self.context_lengths = context_lengths.into_iter().collect();
+        self
+    }
+
+    /// Set the maximum number of tokens to generate for each text completion. (Defaults to 64)
+    pub fn with_generated_tokens(mut self, generated_tokens: u32) -> Self {
+        self.generated_tokens = generated_tokens;
+        self
+    }
+
+    /// Set the number of times each configuration is measured. The report contains the mean of all iterations. (Defaults to 3)
+    pub fn with_iterations(mut self, iterations: usize) -> Self {
+        self.iterations = iterations.max(1);
+        self
+    }
+
+    /// Get the report with every measurement made so far.
+    pub fn report(&self) -> &BenchmarkReport {
+        &self.report
+    }
+
+    /// Serialize the report with every measurement made so far into pretty printed JSON.
+    pub fn to_json(&self) -> String {
+        self.report.to_json()
+    }
+
+    /// Measure the prefill latency and decode speed of a text completion model.
+    pub async fn text_completion<M: TextCompletionModel>(
+        &mut self,
+        model: &M,
+    ) -> Result<(), M::Error> {
+        // Warm up the model so lazy initialization isn't measured
+        self.run_completions(model, &filler_text(8), 1).await?;
+
+        for &context_length in &self.context_lengths {
+            let prompt = filler_text(context_length);
+            for &batch_size in &self.batch_sizes {
+                let mut prefill = Duration::ZERO;
+                let mut tokens_per_second = 0.;
+                let mut generated_tokens = 0;
+                for _ in 0..self.iterations {
+                    let timings = self.run_completions(model, &prompt, batch_size).await?;
+                    let first_token = timings.iter().filter_map(|timing| timing.first_token).min();
+                    let last_token = timings.iter().filter_map(|timing| timing.last_token).max();
+                    prefill += timings
+                        .iter()
+                        .map(|timing| timing.prefill())
+                        .sum::<Duration>()
+                        / batch_size.max(1) as u32;
+                    // The first token of each completion is produced by the prefill, so it isn't counted as a decoded token
+                    let decoded_tokens = timings
+                        .iter()
+                        .map(|timing| timing.tokens.saturating_sub(1))
+                        .sum();
+                    if let (Some(first_token), Some(last_token)) = (first_token, last_token) {
+                        tokens_per_second +=
+                            per_second(decoded_tokens, last_token.duration_since(first_token));
+                    }
+                    generated_tokens += timings.iter().map(|timing| timing.tokens).sum::<usize>();
+                }
+                let iterations = self.iterations as u32;
+                self.report.text_completion.push(TextCompletionBenchmark {
+                    context_length,
+                    batch_size,
+                    prefill_ms: millis(prefill / iterations),
+                    decode_tokens_per_second: tokens_per_second / self.iterations as f64,
+                    generated_tokens: generated_tokens / self.iterations,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn run_completions<M: TextCompletionModel>(
+        &self,
+        model: &M,
+        prompt: &str,
+        batch_size: usize,
+    ) -> Result<Vec<CompletionTiming>, M::Error> {
+        let mut sessions = (0..batch_size)
+            .map(|_| model.new_session())
+            .collect::<Result<Vec<_>, _>>()?;
+        let sampler = GenerationParameters::default().with_max_length(self.generated_tokens);
+        let start = Instant::now();
+        let runs = sessions.iter_mut().map(|session| {
+            let timing = Arc::new(Mutex::new(CompletionTiming::new(start)));
+            let on_token = {
+                let timing = timing.clone();
+                move |_| {
+                    timing.lock().unwrap().token();
+                    Ok(())
+                }
+            };
+            let run = model.stream_text_with_callback(session, prompt, sampler.clone(), on_token);
+            async move {
+                run.await?;
+                let timing = *timing.lock().unwrap();
+                Ok(timing)
+            }
+        });
+        try_join_all(runs).await
+    }
+
+    /// Measure the latency and throughput of an embedding model.
+    pub async fn embedder<E: Embedder>(&mut self, model: &E) -> Result<(), E::Error> {
+        // Warm up the model so lazy initialization isn't measured
+        model.embed_string(filler_text(8)).await?;
+
+        for &context_length in &self.context_lengths {
+            let text = filler_text(context_length);
+            for &batch_size in &self.batch_sizes {
+                let mut latency = Duration::ZERO;
+                for _ in 0..self.iterations {
+                    let start = Instant::now();
+                    model.embed_vec(vec![text.clone(); batch_size]).await?;
+                    latency += start.elapsed();
+                }
+                let latency = latency / self.iterations as u32;
+                self.report.embedding.push(EmbeddingBenchmark {
+                    context_length,
+                    batch_size,
+                    latency_ms: millis(latency),
+                    embeddings_per_second: per_second(batch_size, latency),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Measure the diffusion speed of an image generation model. Context lengths are ignored for image generation.
+    #[cfg(feature = "vision")]
+    pub async fn image_model(&mut self, model: &Wuerstchen) -> Result<(), ImageGenerationError> {
+        for &batch_size in &self.batch_sizes {
+            let mut elapsed = Duration::ZERO;
+            let mut steps = 0;
+            for _ in 0..self.iterations {
+                let finished_steps = Arc::new(AtomicUsize::new(0));
+                let settings = WuerstchenInferenceSettings::new(filler_text(16))
+                    .with_sample_count(batch_size as i64)
+                    .with_progress_handler({
+                        let finished_steps = finished_steps.clone();
+                        move |_| {
+                            finished_steps.fetch_add(1, Ordering::Relaxed);
+                        }
+                    });
+                let start = Instant::now();
+                let mut images = model.run(settings);
+                while let Some(image) = images.next().await {
+                    if let Some(err) = image.error() {
+                        return Err(ImageGenerationError(err.to_string()));
+                    }
+                }
+                elapsed += start.elapsed();
+                steps += finished_steps.load(Ordering::Relaxed);
+            }
+            let iterations = self.iterations as u32;
+            self.report.image.push(ImageBenchmark {
+                batch_size,
+                steps: steps / self.iterations,
+                total_ms: millis(elapsed / iterations),
+                steps_per_second: per_second(steps, elapsed),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// The time each token of one completion was generated.
+#[derive(Clone, Copy)]
+struct CompletionTiming {
+    start: Instant,
+    first_token: Option<Instant>,
+    last_token: Option<Instant>,
+    tokens: usize,
+}
+
+impl CompletionTiming {
+    fn new(start: Instant) -> Self {
+        Self {
+            start,
+            first_token: None,
+            last_token: None,
+            tokens: 0,
+        }
+    }
+
+    fn token(&mut self) {
+        let now = Instant::now();
+        self.first_token.get_or_insert(now);
+        self.last_token = Some(now);
+        self.tokens += 1;
+    }
+
+    fn prefill(&self) -> Duration {
+        self.first_token
+            .map(|first_token| first_token.duration_since(self.start))
+            .unwrap_or_default()
+    }
+}
+
+/// An error that occurred while generating an image during a benchmark.
+#[cfg(feature = "vision")]
+#[derive(Debug, thiserror::Error)]
+#[error("Failed to generate an image: {0}")]
+pub struct ImageGenerationError(String);
+
+/// The results of a [`Benchmark`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BenchmarkReport {
+    /// The name of the benchmark
+    pub name: String,
+    /// The text completion measurements
+    pub text_completion: Vec<TextCompletionBenchmark>,
+    /// The embedding measurements
+    pub embedding: Vec<EmbeddingBenchmark>,
+    /// The image generation measurements
+    pub image: Vec<ImageBenchmark>,
+}
+
+impl BenchmarkReport {
+    /// Serialize the report into pretty printed JSON.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("benchmark reports can always be serialized")
+    }
+
+    /// Parse a report from JSON. This can be used to compare against a previous run.
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+}
+
+/// The text completion performance for one context length and batch size.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TextCompletionBenchmark {
+    /// The length of the prompt in words
+    pub context_length: usize,
+    /// The number of completions that ran at the same time
+    pub batch_size: usize,
+    /// The mean time until the first token of each completion
+    pub prefill_ms: f64,
+    /// The number of tokens generated per second after the first token across the whole batch
+    pub decode_tokens_per_second: f64,
+    /// The number of tokens generated across the whole batch
+    pub generated_tokens: usize,
+}
+
+/// The embedding performance for one context length and batch size.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmbeddingBenchmark {
+    /// The length of each text in words
+    pub context_length: usize,
+    /// The number of texts embedded in one call
+    pub batch_size: usize,
+    /// The time it took to embed the whole batch
+    pub latency_ms: f64,
+    /// The number of texts embedded per second
+    pub embeddings_per_second: f64,
+}
+
+/// The image generation performance for one batch size.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageBenchmark {
+    /// The number of images generated in one run
+    pub batch_size: usize,
+    /// The number of diffusion steps in one run
+    pub steps: usize,
+    /// The time it took to generate the whole batch
+    pub total_ms: f64,
+    /// The number of diffusion steps per second
+    pub steps_per_second: f64,
+}
+
+#[test]
+fn test_report_round_trip() {
+    let report = BenchmarkReport {
+        name: "llama".to_string(),
+        text_completion: vec![TextCompletionBenchmark {
+            context_length: 128,
+            batch_size: 1,
+            prefill_ms: 12.5,
+            decode_tokens_per_second: 40.,
+            generated_tokens: 64,
+        }],
+        ..Default::default()
+    };
+    let parsed = BenchmarkReport::from_json(&report.to_json()).unwrap();
+    assert_eq!(parsed.name, "llama");
+    assert_eq!(parsed.text_completion[0].generated_tokens, 64);
+    assert_eq!(filler_text(10).split(' ').count(), 10);
+}
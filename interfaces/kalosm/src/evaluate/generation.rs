@@ -0,0 +1,106 @@
+use kalosm_language::prelude::*;
+use std::ops::RangeInclusive;
+
+use super::Metric;
+
+/// A metric that scores a generation by the cosine similarity of its embedding with the embedding of the reference answer.
+///
+/// This works with any [`Embedder`]. [`BertDistance`](crate::BertDistance) is the same metric with a [`Bert`] model.
+pub struct EmbeddingSimilarity<E> {
+    embedder: E,
+}
+
+impl<E: Embedder> EmbeddingSimilarity<E> {
+    /// Create a new embedding similarity metric with the embedding model.
+    pub fn new(embedder: E) -> Self {
+        Self { embedder }
+    }
+}
+
+impl<E: Embedder + Send + Sync, S: ToString + Send + Sync> Metric<S> for EmbeddingSimilarity<E> {
+    const RANGE: RangeInclusive<f64> = -1.0..=1.0;
+
+    async fn distance(&mut self, first: &S, other: &S) -> f64 {
+        let embeddings = self
+            .embedder
+            .embed_vec(vec![first.to_string(), other.to_string()])
+            .await
+            .unwrap_or_else(|_| panic!("Failed to embed text"));
+        let [first_embedding, other_embedding] = embeddings.try_into().unwrap_or_else(|_| {
+            panic!("Failed to get two embeddings from the batch of two input texts")
+        });
+        first_embedding.cosine_similarity(&other_embedding).into()
+    }
+}
+
+/// A metric that asks a language model to grade a generation against the reference answer.
+///
+/// The model grades how well the generation matches the meaning of the reference answer from 1 to 5. The grade is scaled to a score between 0 and 1. If the model doesn't respond with a grade, the score is 0.
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm::language::*;
+/// use kalosm::{LlmJudge, TestCases};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let mut test_cases = TestCases::new();
+///     test_cases.push_case(
+///         "Paris is the capital of France.".to_string(),
+///         "The capital of France is Paris.".to_string(),
+///     );
+///
+///     let mut judge = LlmJudge::new(Llama::new_chat().await.unwrap());
+///     let evaluation = test_cases.evaluate(&mut judge).await;
+///     println!("{evaluation}");
+/// }
+/// ```
+pub struct LlmJudge<M> {
+    model: M,
+}
+
+impl<M> LlmJudge<M> {
+    /// Create a new judge that grades generations with the chat model.
+    pub fn new(model: M) -> Self {
+        Self { model }
+    }
+}
+
+impl<M, S> Metric<S> for LlmJudge<M>
+where
+    M: ChatModel + Send + Sync + Unpin + Clone + 'static,
+    M::ChatSession: Clone + Send + Sync + Unpin + 'static,
+    S: ToString + Send + Sync,
+{
+    async fn distance(&mut self, first: &S, other: &S) -> f64 {
+        let mut chat = Chat::new(self.model.clone()).with_system_prompt(
+            "You grade answers against a reference answer. Grade how well the answer matches the meaning of the reference answer from 1 (contradicts or misses the reference) to 5 (has the same meaning). Respond with only the grade.",
+        );
+        let response = chat
+            .add_message(format!(
+                "Reference answer: {}\nAnswer: {}",
+                first.to_string(),
+                other.to_string()
+            ))
+            .with_sampler(GenerationParameters::default().with_max_length(8))
+            .await
+            .unwrap_or_else(|_| panic!("Failed to generate a grade"));
+        parse_grade(&response).map_or(0.0, |grade| (grade - 1.0) / 4.0)
+    }
+}
+
+/// Find the first grade from 1 to 5 in the response of the judge.
+fn parse_grade(response: &str) -> Option<f64> {
+    response
+        .chars()
+        .filter_map(|c| c.to_digit(10))
+        .find(|grade| (1..=5).contains(grade))
+        .map(f64::from)
+}
+
+#[test]
+fn test_parse_grade() {
+    assert_eq!(parse_grade("4"), Some(4.0));
+    assert_eq!(parse_grade("Grade: 5/5"), Some(5.0));
+    assert_eq!(parse_grade("I can't grade this"), None);
+}
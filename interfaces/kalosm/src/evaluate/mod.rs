@@ -12,6 +12,11 @@ use kalosm_language::prelude::Bert;
 #[cfg(feature = "bert")]
 use kalosm_language::prelude::Embedder;
 
+mod generation;
+pub use generation::*;
+mod retrieval;
+pub use retrieval::*;
+
 /// A metric is a way to compare two pieces of data. It is used to evaluate the performance of a model.
 pub trait Metric<T> {
     /// The range of values that this metric can return.
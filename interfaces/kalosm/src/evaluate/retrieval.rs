@@ -0,0 +1,295 @@
+use comfy_table::Cell;
+use comfy_table::Table;
+use std::fmt::Display;
+use std::future::Future;
+
+/// A set of labeled queries to evaluate a retrieval pipeline.
+///
+/// Each case is a query and the passages that are relevant to it. A retrieved chunk matches a relevant passage if one of them contains the other, so the same cases can measure indexes with different chunking strategies.
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm::language::*;
+/// use kalosm::RetrievalTestCases;
+/// use surrealdb::{engine::local::SurrealKv, Surreal};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let db = Surreal::new::<SurrealKv>("./db/temp.db").await.unwrap();
+///     db.use_ns("rag").use_db("rag").await.unwrap();
+///     let document_table = db
+///         .document_table_builder("documents")
+///         .at("./db/embeddings.db")
+///         .build::<Document>()
+///         .await
+///         .unwrap();
+///
+///     let test_cases = RetrievalTestCases::new()
+///         .with_case(
+///             "How are plugins isolated?",
+///             ["Plugins run in a WebAssembly sandbox"],
+///         )
+///         .with_case("What models can I run?", ["Floneum supports Llama"]);
+///     let evaluation = test_cases
+///         .evaluate(5, |query| {
+///             let document_table = &document_table;
+///             async move {
+///                 let results = document_table.search(query).with_results(5).await?;
+///                 Ok::<_, DocumentTableSearchError<_>>(
+///                     results.iter().map(|result| result.text()).collect(),
+///                 )
+///             }
+///         })
+///         .await
+///         .unwrap();
+///     println!("{evaluation}");
+/// }
+/// ```
+pub struct RetrievalTestCases {
+    name: String,
+    cases: Vec<RetrievalTestCase>,
+}
+
+impl Default for RetrievalTestCases {
+    #[track_caller]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RetrievalTestCases {
+    /// Create a new set of retrieval test cases.
+    #[track_caller]
+    pub fn new() -> Self {
+        RetrievalTestCases {
+            name: std::panic::Location::caller().to_string(),
+            cases: Vec::new(),
+        }
+    }
+
+    /// Set the name of this set of test cases.
+    pub fn with_name(mut self, name: impl Display) -> Self {
+        self.name = name.to_string();
+        self
+    }
+
+    /// Add a query and the passages that are relevant to it. Every case should have at least one relevant passage.
+    pub fn with_case(
+        mut self,
+        query: impl ToString,
+        relevant: impl IntoIterator<Item = impl ToString>,
+    ) -> Self {
+        self.push_case(query, relevant);
+        self
+    }
+
+    /// Push a query and the passages that are relevant to it to this set of test cases.
+    pub fn push_case(
+        &mut self,
+        query: impl ToString,
+        relevant: impl IntoIterator<Item = impl ToString>,
+    ) {
+        self.cases.push(RetrievalTestCase {
+            query: query.to_string(),
+            relevant: relevant.into_iter().map(|text| text.to_string()).collect(),
+        });
+    }
+
+    /// Run the search for each query and score the top `k` chunks it returns. The search returns the text of the retrieved chunks from the most to the least relevant.
+    pub async fn evaluate<F, Fut, E>(
+        &self,
+        k: usize,
+        mut search: F,
+    ) -> Result<RetrievalEvaluation, E>
+    where
+        F: FnMut(String) -> Fut,
+        Fut: Future<Output = Result<Vec<String>, E>>,
+    {
+        let mut cases = Vec::with_capacity(self.cases.len());
+        for case in &self.cases {
+            let mut retrieved = search(case.query.clone()).await?;
+            retrieved.truncate(k);
+            cases.push(score_case(&case.query, &retrieved, &case.relevant, k));
+        }
+        Ok(RetrievalEvaluation {
+            name: self.name.clone(),
+            k,
+            cases,
+        })
+    }
+}
+
+struct RetrievalTestCase {
+    query: String,
+    relevant: Vec<String>,
+}
+
+/// The scores of a single query in a [`RetrievalEvaluation`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetrievalCaseScore {
+    /// The query that was searched.
+    pub query: String,
+    /// The fraction of relevant passages that were retrieved.
+    pub recall: f64,
+    /// One over the rank of the first relevant chunk, or zero if no relevant chunk was retrieved.
+    pub reciprocal_rank: f64,
+    /// The normalized discounted cumulative gain of the retrieved chunks.
+    pub ndcg: f64,
+}
+
+/// The result of evaluating a retrieval pipeline with [`RetrievalTestCases`].
+#[derive(Debug, Clone)]
+pub struct RetrievalEvaluation {
+    name: String,
+    k: usize,
+    cases: Vec<RetrievalCaseScore>,
+}
+
+impl RetrievalEvaluation {
+    /// Get the number of chunks that were scored for each query.
+    pub fn k(&self) -> usize {
+        self.k
+    }
+
+    /// Get the scores of each query.
+    pub fn cases(&self) -> &[RetrievalCaseScore] {
+        &self.cases
+    }
+
+    /// Get the mean recall@k over every query.
+    pub fn recall(&self) -> f64 {
+        self.mean(|case| case.recall)
+    }
+
+    /// Get the mean reciprocal rank (MRR) over every query.
+    pub fn mrr(&self) -> f64 {
+        self.mean(|case| case.reciprocal_rank)
+    }
+
+    /// Get the mean nDCG@k over every query.
+    pub fn ndcg(&self) -> f64 {
+        self.mean(|case| case.ndcg)
+    }
+
+    fn mean(&self, score: impl Fn(&RetrievalCaseScore) -> f64) -> f64 {
+        if self.cases.is_empty() {
+            return 0.0;
+        }
+        self.cases.iter().map(score).sum::<f64>() / self.cases.len() as f64
+    }
+}
+
+impl Display for RetrievalEvaluation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{}", self.name)?;
+
+        let mut statistics = Table::new();
+        statistics.set_header(vec!["Statistic", "Value"]);
+        statistics.add_row(vec![
+            Cell::new(format!("Recall@{}", self.k)),
+            Cell::new(format!("{:.2}", self.recall())),
+        ]);
+        statistics.add_row(vec![
+            Cell::new("MRR"),
+            Cell::new(format!("{:.2}", self.mrr())),
+        ]);
+        statistics.add_row(vec![
+            Cell::new(format!("nDCG@{}", self.k)),
+            Cell::new(format!("{:.2}", self.ndcg())),
+        ]);
+        writeln!(f, "{}", statistics)?;
+
+        // Show the worst queries first
+        let mut cases: Vec<_> = self.cases.iter().collect();
+        cases.sort_by(|a, b| a.ndcg.total_cmp(&b.ndcg));
+        let mut table = Table::new();
+        table.set_header(vec!["Query", "Recall", "Reciprocal Rank", "nDCG"]);
+        for case in cases {
+            table.add_row(vec![
+                Cell::new(&case.query),
+                Cell::new(format!("{:.2}", case.recall)),
+                Cell::new(format!("{:.2}", case.reciprocal_rank)),
+                Cell::new(format!("{:.2}", case.ndcg)),
+            ]);
+        }
+        writeln!(f, "{}", table)
+    }
+}
+
+/// Score the retrieved chunks for a query against the relevant passages. A chunk is a hit if it matches a relevant passage that no earlier chunk matched, so overlapping chunks are not counted twice.
+fn score_case(
+    query: &str,
+    retrieved: &[String],
+    relevant: &[String],
+    k: usize,
+) -> RetrievalCaseScore {
+    let matches = |chunk: &str, passage: &str| {
+        let (chunk, passage) = (chunk.trim(), passage.trim());
+        !chunk.is_empty()
+            && !passage.is_empty()
+            && (chunk.contains(passage) || passage.contains(chunk))
+    };
+
+    let mut found = vec![false; relevant.len()];
+    let mut reciprocal_rank = 0.0;
+    let mut dcg = 0.0;
+    for (i, chunk) in retrieved.iter().enumerate() {
+        let mut hit = false;
+        for (found, passage) in found.iter_mut().zip(relevant) {
+            if !*found && matches(chunk, passage) {
+                *found = true;
+                hit = true;
+            }
+        }
+        if hit {
+            dcg += 1.0 / (i as f64 + 2.0).log2();
+            if reciprocal_rank == 0.0 {
+                reciprocal_rank = 1.0 / (i as f64 + 1.0);
+            }
+        }
+    }
+
+    let ideal_hits = relevant.len().min(k);
+    let ideal_dcg: f64 = (0..ideal_hits).map(|i| 1.0 / (i as f64 + 2.0).log2()).sum();
+    let found = found.iter().filter(|found| **found).count();
+    RetrievalCaseScore {
+        query: query.to_string(),
+        recall: if relevant.is_empty() {
+            0.0
+        } else {
+            found as f64 / relevant.len() as f64
+        },
+        reciprocal_rank,
+        ndcg: if ideal_dcg == 0.0 {
+            0.0
+        } else {
+            dcg / ideal_dcg
+        },
+    }
+}
+
+#[test]
+fn test_score_retrieval() {
+    let relevant = [
+        "Plugins run in a WebAssembly sandbox".to_string(),
+        "Floneum supports Llama".to_string(),
+    ];
+    let retrieved = [
+        "Floneum is a graph editor.".to_string(),
+        "Plugins run in a WebAssembly sandbox. They can't read files.".to_string(),
+        // Overlapping chunks that match the same passage only count once
+        "in a WebAssembly sandbox".to_string(),
+        "Floneum supports Llama".to_string(),
+    ];
+    let score = score_case("query", &retrieved, &relevant, 4);
+    assert_eq!(score.recall, 1.0);
+    assert_eq!(score.reciprocal_rank, 0.5);
+    let ideal = 1.0 + 1.0 / 3f64.log2();
+    let dcg = 1.0 / 3f64.log2() + 1.0 / 5f64.log2();
+    assert!((score.ndcg - dcg / ideal).abs() < 1e-9);
+
+    let score = score_case("query", &retrieved[..1], &relevant, 4);
+    assert_eq!(score.recall, 0.0);
+    assert_eq!(score.reciprocal_rank, 0.0);
+    assert_eq!(score.ndcg, 0.0);
+}
@@ -8,7 +8,10 @@ pub use kalosm_streams::timed_stream::*;
 pub mod language {
     #![doc = include_str!("../docs/language.md")]
     #[cfg(any(feature = "bert", feature = "llama"))]
-    pub use kalosm_common::accelerated_device_if_available;
+    pub use kalosm_common::{
+        accelerated_device_if_available, device_with_most_free_memory, devices, gpu_device,
+        DeviceInfo, DeviceKind,
+    };
     pub use kalosm_language::context::*;
     pub use kalosm_language::kalosm_language_model::{
         ChatModel as _, ChatModelExt as _, ChatSession as _, CreateChatSession as _,
@@ -25,14 +28,32 @@ pub mod language {
     pub use kalosm_language::kalosm_sample::{self, *};
     pub use kalosm_language::prelude::Html;
     #[cfg(feature = "bert")]
-    pub use kalosm_language::rbert::{Bert, BertBuilder, BertSource};
+    pub use kalosm_language::rbert::{
+        Bert, BertBuilder, BertSource, Entity, EntityRecognizer, EntityRecognizerBuilder,
+        EntityRecognizerSource, ZeroShotClassification, ZeroShotClassifier,
+        ZeroShotClassifierBuilder, ZeroShotClassifierSource,
+    };
+    pub use kalosm_language::memory::*;
+    pub use kalosm_language::redact::*;
     pub use kalosm_language::search::*;
     pub use kalosm_language::vector_db::*;
-    pub use kalosm_model_types::{FileLoadingProgress, FileSource, ModelLoadingProgress};
+    pub use kalosm_model_types::{
+        AuthToken, FileLoadingProgress, FileSource, InMemoryFile, Mirror, ModelLoadingProgress,
+    };
     pub use kalosm_streams::text_stream::*;
 
+    #[cfg(feature = "surrealdb")]
+    pub use crate::surrealdb_integration::chat_store::*;
+    #[cfg(feature = "surrealdb")]
+    pub use crate::surrealdb_integration::citation::*;
+    #[cfg(feature = "surrealdb")]
+    pub use crate::surrealdb_integration::document_sync::*;
     #[cfg(feature = "surrealdb")]
     pub use crate::surrealdb_integration::document_table::*;
+    #[cfg(feature = "surrealdb")]
+    pub use crate::surrealdb_integration::packing::*;
+    #[cfg(feature = "surrealdb")]
+    pub use crate::surrealdb_integration::query_expansion::*;
 }
 #[cfg(feature = "sound")]
 pub mod sound {
@@ -41,6 +62,9 @@ pub mod sound {
     pub use kalosm_sound::*;
     pub use kalosm_streams::text_stream::*;
     pub use kalosm_streams::timed_stream::*;
+
+    #[cfg(feature = "language")]
+    pub use crate::voice::*;
 }
 #[cfg(feature = "vision")]
 pub mod vision {
@@ -49,6 +73,14 @@ pub mod vision {
     pub use kalosm_vision::*;
 }
 
+#[cfg(all(feature = "language", feature = "sound"))]
+mod voice;
+
+#[cfg(feature = "bench")]
+mod bench;
+#[cfg(feature = "bench")]
+pub use bench::*;
+
 #[cfg(feature = "language")]
 mod evaluate;
 #[cfg(feature = "language")]
@@ -59,6 +91,11 @@ mod prompt_annealing;
 #[cfg(feature = "prompt_annealing")]
 pub use prompt_annealing::*;
 
+#[cfg(feature = "registry")]
+mod registry;
+#[cfg(feature = "registry")]
+pub use registry::*;
+
 #[cfg(feature = "surrealdb")]
 mod surrealdb_integration;
 #[cfg(feature = "surrealdb")]
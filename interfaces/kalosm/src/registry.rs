@@ -0,0 +1,603 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use kalosm_language::kalosm_language_model::*;
+use kalosm_model_types::FileSource;
+use serde::Deserialize;
+
+#[cfg(feature = "llama")]
+use kalosm_language::kalosm_llama::{Llama, LlamaSource, LlamaSourceError};
+#[cfg(feature = "bert")]
+use kalosm_language::rbert::{Bert, BertLoadingError, BertSource};
+#[cfg(feature = "vision")]
+use kalosm_vision::{Wuerstchen, WuerstchenError};
+
+/// A source for a file in a model config.
+///
+/// In TOML, a local file is written as `{ local = "path/to/file" }`, a file from Hugging Face as
+/// `{ hugging_face = { model_id = "...", file = "..." } }` and a file from a URL as `{ url = "..." }`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileSourceConfig {
+    /// A local file
+    Local(PathBuf),
+    /// A file from Hugging Face
+    HuggingFace {
+        /// The model id to use
+        model_id: String,
+        /// The revision to use (defaults to `main`)
+        #[serde(default = "default_revision")]
+        revision: String,
+        /// The file to use
+        file: String,
+    },
+    /// A file that is downloaded from a URL
+    Url(String),
+}
+
+fn default_revision() -> String {
+    "main".to_string()
+}
+
+impl From<FileSourceConfig> for FileSource {
+    fn from(config: FileSourceConfig) -> Self {
+        match config {
+            FileSourceConfig::Local(path) => FileSource::local(path),
+            FileSourceConfig::HuggingFace {
+                model_id,
+                revision,
+                file,
+            } => FileSource::huggingface(model_id, revision, file),
+            FileSourceConfig::Url(url) => FileSource::Url(url),
+        }
+    }
+}
+
+/// The config for a single model in a [`ModelRegistry`].
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum ModelConfig {
+    /// A local llama model. The model can be used as a chat model or a text completion model.
+    #[cfg(feature = "llama")]
+    Llama {
+        /// The name of a [`LlamaSource`] preset like `llama_3_1_8b_chat`
+        #[serde(default)]
+        preset: Option<String>,
+        /// The gguf model file. This overrides the model from the preset
+        #[serde(default)]
+        model: Option<FileSourceConfig>,
+        /// The tokenizer file. If this is not set, the tokenizer from the preset or the gguf file is used
+        #[serde(default)]
+        tokenizer: Option<FileSourceConfig>,
+    },
+    /// A local bert model. The model can be used as an embedder.
+    #[cfg(feature = "bert")]
+    Bert {
+        /// The name of a [`BertSource`] preset like `bge_small_en`
+        #[serde(default)]
+        preset: Option<String>,
+        /// The safetensors model file. This overrides the model from the preset
+        #[serde(default)]
+        model: Option<FileSourceConfig>,
+        /// The tokenizer file. This overrides the tokenizer from the preset
+        #[serde(default)]
+        tokenizer: Option<FileSourceConfig>,
+        /// The config file. This overrides the config from the preset
+        #[serde(default)]
+        config: Option<FileSourceConfig>,
+    },
+    /// A local Wuerstchen image generation model.
+    ///
+    /// Each file overrides the default weights for that part of the model.
+    #[cfg(feature = "vision")]
+    Wuerstchen {
+        /// The decoder weight file, in .safetensors format
+        #[serde(default)]
+        decoder_weights: Option<FileSourceConfig>,
+        /// The CLIP weight file, in .safetensors format
+        #[serde(default)]
+        clip_weights: Option<FileSourceConfig>,
+        /// The CLIP weight file used by the prior model, in .safetensors format
+        #[serde(default)]
+        prior_clip_weights: Option<FileSourceConfig>,
+        /// The prior weight file, in .safetensors format
+        #[serde(default)]
+        prior_weights: Option<FileSourceConfig>,
+        /// The VQGAN weight file, in .safetensors format
+        #[serde(default)]
+        vqgan_weights: Option<FileSourceConfig>,
+        /// The tokenizer file
+        #[serde(default)]
+        tokenizer: Option<FileSourceConfig>,
+        /// The tokenizer file used by the prior model
+        #[serde(default)]
+        prior_tokenizer: Option<FileSourceConfig>,
+    },
+    /// A chat model served by an OpenAI compatible API.
+    #[cfg(feature = "openai")]
+    OpenAi {
+        /// The name of the model, like `gpt-4o-mini`
+        model: String,
+        /// The base URL of the API (defaults to `https://api.openai.com/v1/`)
+        #[serde(default)]
+        base_url: Option<String>,
+        /// The API key (defaults to the environment variable `OPENAI_API_KEY`)
+        #[serde(default)]
+        api_key: Option<String>,
+    },
+    /// An embedding model served by an OpenAI compatible API.
+    #[cfg(feature = "openai")]
+    OpenAiEmbedding {
+        /// The name of the model, like `text-embedding-3-small`
+        model: String,
+        /// The base URL of the API (defaults to `https://api.openai.com/v1/`)
+        #[serde(default)]
+        base_url: Option<String>,
+        /// The API key (defaults to the environment variable `OPENAI_API_KEY`)
+        #[serde(default)]
+        api_key: Option<String>,
+    },
+}
+
+impl ModelConfig {
+    /// The name of the type of model this config creates.
+    pub fn kind(&self) -> &'static str {
+        match *self {
+            #[cfg(feature = "llama")]
+            ModelConfig::Llama { .. } => "llama",
+            #[cfg(feature = "bert")]
+            ModelConfig::Bert { .. } => "bert",
+            #[cfg(feature = "vision")]
+            ModelConfig::Wuerstchen { .. } => "wuerstchen",
+            #[cfg(feature = "openai")]
+            ModelConfig::OpenAi { .. } => "open_ai",
+            #[cfg(feature = "openai")]
+            ModelConfig::OpenAiEmbedding { .. } => "open_ai_embedding",
+        }
+    }
+}
+
+/// An error that can occur when loading a model from a [`ModelRegistry`].
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum ModelRegistryError {
+    /// The config file could not be read.
+    #[error("Failed to read the model config: {0}")]
+    Io(#[from] std::io::Error),
+    /// The TOML config could not be parsed.
+    #[error("Failed to parse the TOML model config: {0}")]
+    Toml(#[from] toml::de::Error),
+    /// The JSON config could not be parsed.
+    #[error("Failed to parse the JSON model config: {0}")]
+    Json(#[from] serde_json::Error),
+    /// No model with the name exists in the registry.
+    #[error("No model named {0:?} exists in the registry")]
+    UnknownModel(String),
+    /// The model exists, but it can't be used for the requested task.
+    #[error("The model {name:?} is a {kind} model which can't be used as a {expected}")]
+    UnsupportedModel {
+        /// The name of the model
+        name: String,
+        /// The type of the model
+        kind: &'static str,
+        /// The type of model that was requested
+        expected: &'static str,
+    },
+    /// The preset named in the config does not exist.
+    #[error("No {kind} preset named {preset:?} exists")]
+    UnknownPreset {
+        /// The type of the model
+        kind: &'static str,
+        /// The name of the preset
+        preset: String,
+    },
+    /// The config for a model doesn't have a preset or a model file.
+    #[error("The model {0:?} needs either a preset or a model file")]
+    MissingSource(String),
+    /// An error that occurred while loading a llama model.
+    #[cfg(feature = "llama")]
+    #[error("Failed to load the llama model: {0}")]
+    Llama(#[from] LlamaSourceError),
+    /// An error that occurred while loading a bert model.
+    #[cfg(feature = "bert")]
+    #[error("Failed to load the bert model: {0}")]
+    Bert(#[from] BertLoadingError),
+    /// An error that occurred while loading a Wuerstchen model.
+    #[cfg(feature = "vision")]
+    #[error("Failed to load the Wuerstchen model: {0}")]
+    Wuerstchen(#[from] WuerstchenError),
+}
+
+/// A set of named model configs that can be loaded into boxed models at runtime.
+///
+/// The registry lets applications switch between models without recompiling. Each entry in the config
+/// describes one model, and the registry builds it into a boxed trait object when it is requested:
+///
+/// ```toml
+/// [models.assistant]
+/// type = "llama"
+/// preset = "phi_3_5_mini_4k_instruct"
+///
+/// [models.local]
+/// type = "llama"
+/// model = { local = "models/my-model.gguf" }
+///
+/// [models.search]
+/// type = "bert"
+/// preset = "snowflake_arctic_embed_extra_small"
+///
+/// [models.remote]
+/// type = "open_ai"
+/// model = "gpt-4o-mini"
+/// base_url = "https://api.openai.com/v1/"
+/// ```
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm::language::*;
+/// use kalosm::ModelRegistry;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let registry = ModelRegistry::from_path("models.toml").unwrap();
+///     let model = registry.chat_model("assistant").await.unwrap();
+///     let mut chat = model.chat();
+///     chat("Hello!").to_std_out().await.unwrap();
+/// }
+/// ```
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct ModelRegistry {
+    #[serde(default)]
+    models: HashMap<String, ModelConfig>,
+}
+
+impl ModelRegistry {
+    /// Create a new empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse a registry from a TOML config. Each model is a table under `models`.
+    pub fn from_toml(config: &str) -> Result<Self, ModelRegistryError> {
+        Ok(toml::from_str(config)?)
+    }
+
+    /// Parse a registry from a JSON config. Each model is an entry in the `models` object.
+    pub fn from_json(config: &str) -> Result<Self, ModelRegistryError> {
+        Ok(serde_json::from_str(config)?)
+    }
+
+    /// Read a registry from a config file. Files ending in `.json` are parsed as JSON and all other files are parsed as TOML.
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self, ModelRegistryError> {
+        let path = path.as_ref();
+        let config = std::fs::read_to_string(path)?;
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("json") => Self::from_json(&config),
+            _ => Self::from_toml(&config),
+        }
+    }
+
+    /// Add a model to the registry, replacing any model with the same name.
+    pub fn with_model(mut self, name: impl ToString, config: ModelConfig) -> Self {
+        self.models.insert(name.to_string(), config);
+        self
+    }
+
+    /// Get the config for a model by name.
+    pub fn get(&self, name: &str) -> Option<&ModelConfig> {
+        self.models.get(name)
+    }
+
+    /// Get the names of all models in the registry.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.models.keys().map(|name| name.as_str())
+    }
+
+    fn config(&self, name: &str) -> Result<&ModelConfig, ModelRegistryError> {
+        self.get(name)
+            .ok_or_else(|| ModelRegistryError::UnknownModel(name.to_string()))
+    }
+
+    /// Load a chat model by name.
+    pub async fn chat_model(&self, name: &str) -> Result<BoxedChatModel, ModelRegistryError> {
+        match self.config(name)? {
+            #[cfg(feature = "llama")]
+            ModelConfig::Llama {
+                preset,
+                model,
+                tokenizer,
+            } => Ok(load_llama(name, preset, model, tokenizer)
+                .await?
+                .boxed_chat_model()),
+            #[cfg(feature = "openai")]
+            ModelConfig::OpenAi {
+                model,
+                base_url,
+                api_key,
+            } => Ok(OpenAICompatibleChatModel::builder()
+                .with_model(model)
+                .with_client(open_ai_client(base_url, api_key))
+                .build()
+                .boxed_chat_model()),
+            #[allow(unreachable_patterns)]
+            config => Err(unsupported(name, config, "chat model")),
+        }
+    }
+
+    /// Load a text completion model by name.
+    pub async fn completion_model(
+        &self,
+        name: &str,
+    ) -> Result<BoxedTextCompletionModel, ModelRegistryError> {
+        match self.config(name)? {
+            #[cfg(feature = "llama")]
+            ModelConfig::Llama {
+                preset,
+                model,
+                tokenizer,
+            } => Ok(load_llama(name, preset, model, tokenizer)
+                .await?
+                .boxed_completion_model()),
+            #[allow(unreachable_patterns)]
+            config => Err(unsupported(name, config, "text completion model")),
+        }
+    }
+
+    /// Load an embedding model by name.
+    pub async fn embedder(&self, name: &str) -> Result<DynEmbedder, ModelRegistryError> {
+        match self.config(name)? {
+            #[cfg(feature = "bert")]
+            ModelConfig::Bert {
+                preset,
+                model,
+                tokenizer,
+                config,
+            } => {
+                let mut source = match preset {
+                    Some(preset) => bert_preset(preset)?,
+                    None => BertSource::default(),
+                };
+                if let Some(model) = model {
+                    source = source.with_model(model.clone().into());
+                }
+                if let Some(tokenizer) = tokenizer {
+                    source = source.with_tokenizer(tokenizer.clone().into());
+                }
+                if let Some(config) = config {
+                    source = source.with_config(config.clone().into());
+                }
+                let bert = Bert::builder().with_source(source).build().await?;
+                Ok(bert.into_any_embedder())
+            }
+            #[cfg(feature = "openai")]
+            ModelConfig::OpenAiEmbedding {
+                model,
+                base_url,
+                api_key,
+            } => Ok(OpenAICompatibleEmbeddingModel::builder()
+                .with_model(model)
+                .with_client(open_ai_client(base_url, api_key))
+                .build()
+                .into_any_embedder()),
+            #[allow(unreachable_patterns)]
+            config => Err(unsupported(name, config, "embedder")),
+        }
+    }
+
+    /// Load an image generation model by name.
+    #[cfg(feature = "vision")]
+    pub async fn image_model(&self, name: &str) -> Result<Wuerstchen, ModelRegistryError> {
+        match self.config(name)? {
+            ModelConfig::Wuerstchen {
+                decoder_weights,
+                clip_weights,
+                prior_clip_weights,
+                prior_weights,
+                vqgan_weights,
+                tokenizer,
+                prior_tokenizer,
+            } => {
+                let mut builder = Wuerstchen::builder();
+                if let Some(decoder_weights) = decoder_weights {
+                    builder = builder.with_decoder_weights(decoder_weights.clone().into());
+                }
+                if let Some(clip_weights) = clip_weights {
+                    builder = builder.with_clip_weights(clip_weights.clone().into());
+                }
+                if let Some(prior_clip_weights) = prior_clip_weights {
+                    builder = builder.with_prior_clip_weights(prior_clip_weights.clone().into());
+                }
+                if let Some(prior_weights) = prior_weights {
+                    builder = builder.with_prior_weights(prior_weights.clone().into());
+                }
+                if let Some(vqgan_weights) = vqgan_weights {
+                    builder = builder.with_vqgan_weights(vqgan_weights.clone().into());
+                }
+                if let Some(tokenizer) = tokenizer {
+                    builder = builder.with_tokenizer(tokenizer.clone().into());
+                }
+                if let Some(prior_tokenizer) = prior_tokenizer {
+                    builder = builder.with_prior_tokenizer(prior_tokenizer.clone().into());
+                }
+                Ok(builder.build().await?)
+            }
+            #[allow(unreachable_patterns)]
+            config => Err(unsupported(name, config, "image model")),
+        }
+    }
+}
+
+fn unsupported(name: &str, config: &ModelConfig, expected: &'static str) -> ModelRegistryError {
+    ModelRegistryError::UnsupportedModel {
+        name: name.to_string(),
+        kind: config.kind(),
+        expected,
+    }
+}
+
+#[cfg(feature = "openai")]
+fn open_ai_client(base_url: &Option<String>, api_key: &Option<String>) -> OpenAICompatibleClient {
+    let mut client = OpenAICompatibleClient::new();
+    if let Some(base_url) = base_url {
+        client = client.with_base_url(base_url);
+    }
+    if let Some(api_key) = api_key {
+        client = client.with_api_key(api_key);
+    }
+    client
+}
+
+#[cfg(feature = "llama")]
+async fn load_llama(
+    name: &str,
+    preset: &Option<String>,
+    model: &Option<FileSourceConfig>,
+    tokenizer: &Option<FileSourceConfig>,
+) -> Result<Llama, ModelRegistryError> {
+    let mut source = match (preset, model) {
+        (Some(preset), _) => llama_preset(preset)?,
+        (None, Some(model)) => LlamaSource::new(model.clone().into()),
+        (None, None) => return Err(ModelRegistryError::MissingSource(name.to_string())),
+    };
+    if let (Some(_), Some(model)) = (preset, model) {
+        source = source.with_model(model.clone().into());
+    }
+    if let Some(tokenizer) = tokenizer {
+        source = source.with_tokenizer(tokenizer.clone().into());
+    }
+    Ok(Llama::builder().with_source(source).build().await?)
+}
+
+#[cfg(feature = "llama")]
+fn llama_preset(preset: &str) -> Result<LlamaSource, ModelRegistryError> {
+    Ok(match preset {
+        "mistral_7b" => LlamaSource::mistral_7b(),
+        "mistral_7b_instruct" => LlamaSource::mistral_7b_instruct(),
+        "mistral_7b_instruct_2" => LlamaSource::mistral_7b_instruct_2(),
+        "neural_hermes_2_5_mistral_7b" => LlamaSource::neural_hermes_2_5_mistral_7b(),
+        "neural_chat_7b_v3_3" => LlamaSource::neural_chat_7b_v3_3(),
+        "zephyr_7b_alpha" => LlamaSource::zephyr_7b_alpha(),
+        "zephyr_7b_beta" => LlamaSource::zephyr_7b_beta(),
+        "open_chat_7b" => LlamaSource::open_chat_7b(),
+        "starling_7b_alpha" => LlamaSource::starling_7b_alpha(),
+        "starling_7b_beta" => LlamaSource::starling_7b_beta(),
+        "wizard_lm_7b_v2" => LlamaSource::wizard_lm_7b_v2(),
+        "tiny_llama_1_1b_chat" => LlamaSource::tiny_llama_1_1b_chat(),
+        "tiny_llama_1_1b" => LlamaSource::tiny_llama_1_1b(),
+        "phi_3_mini_4k_instruct" => LlamaSource::phi_3_mini_4k_instruct(),
+        "phi_3_1_mini_4k_instruct" => LlamaSource::phi_3_1_mini_4k_instruct(),
+        "phi_3_5_mini_4k_instruct" => LlamaSource::phi_3_5_mini_4k_instruct(),
+        "phi_4" => LlamaSource::phi_4(),
+        "llama_7b" => LlamaSource::llama_7b(),
+        "llama_8b" => LlamaSource::llama_8b(),
+        "llama_8b_chat" => LlamaSource::llama_8b_chat(),
+        "llama_3_1_8b_chat" => LlamaSource::llama_3_1_8b_chat(),
+        "llama_8b_chat_q8" => LlamaSource::llama_8b_chat_q8(),
+        "llama_8b_sppo_iter3" => LlamaSource::llama_8b_sppo_iter3(),
+        "llama_3_2_1b_chat" => LlamaSource::llama_3_2_1b_chat(),
+        "llama_3_2_3b_chat" => LlamaSource::llama_3_2_3b_chat(),
+        "llama_13b" => LlamaSource::llama_13b(),
+        "llama_70b" => LlamaSource::llama_70b(),
+        "llama_7b_chat" => LlamaSource::llama_7b_chat(),
+        "llama_13b_chat" => LlamaSource::llama_13b_chat(),
+        "llama_70b_chat" => LlamaSource::llama_70b_chat(),
+        "llama_7b_code" => LlamaSource::llama_7b_code(),
+        "llama_13b_code" => LlamaSource::llama_13b_code(),
+        "llama_34b_code" => LlamaSource::llama_34b_code(),
+        "solar_10_7b" => LlamaSource::solar_10_7b(),
+        "solar_10_7b_instruct" => LlamaSource::solar_10_7b_instruct(),
+        "qwen_2_5_0_5b_instruct" => LlamaSource::qwen_2_5_0_5b_instruct(),
+        "qwen_2_5_1_5b_instruct" => LlamaSource::qwen_2_5_1_5b_instruct(),
+        "qwen_2_5_3b_instruct" => LlamaSource::qwen_2_5_3b_instruct(),
+        "qwen_2_5_7b_instruct" => LlamaSource::qwen_2_5_7b_instruct(),
+        "deepseek_r1_distill_qwen_1_5b" => LlamaSource::deepseek_r1_distill_qwen_1_5b(),
+        "deepseek_r1_distill_qwen_7b" => LlamaSource::deepseek_r1_distill_qwen_7b(),
+        "deepseek_r1_distill_qwen_14b" => LlamaSource::deepseek_r1_distill_qwen_14b(),
+        "deepseek_r1_distill_llama_8b" => LlamaSource::deepseek_r1_distill_llama_8b(),
+        _ => {
+            return Err(ModelRegistryError::UnknownPreset {
+                kind: "llama",
+                preset: preset.to_string(),
+            })
+        }
+    })
+}
+
+#[cfg(feature = "bert")]
+fn bert_preset(preset: &str) -> Result<BertSource, ModelRegistryError> {
+    Ok(match preset {
+        "bge_large_en" => BertSource::bge_large_en(),
+        "bge_base_en" => BertSource::bge_base_en(),
+        "bge_small_en" => BertSource::bge_small_en(),
+        "mini_lm_l6_v2" => BertSource::mini_lm_l6_v2(),
+        "snowflake_arctic_embed_extra_small" => BertSource::snowflake_arctic_embed_extra_small(),
+        "snowflake_arctic_embed_small" => BertSource::snowflake_arctic_embed_small(),
+        "snowflake_arctic_embed_medium" => BertSource::snowflake_arctic_embed_medium(),
+        "snowflake_arctic_embed_medium_long" => BertSource::snowflake_arctic_embed_medium_long(),
+        "snowflake_arctic_embed_large" => BertSource::snowflake_arctic_embed_large(),
+        _ => {
+            return Err(ModelRegistryError::UnknownPreset {
+                kind: "bert",
+                preset: preset.to_string(),
+            })
+        }
+    })
+}
+
+#[cfg(all(feature = "llama", feature = "bert"))]
+#[test]
+fn test_parse_registry() {
+    let toml = r#"
+        [models.assistant]
+        type = "llama"
+        preset = "phi_3_5_mini_4k_instruct"
+
+        [models.local]
+        type = "llama"
+        model = { local = "models/my-model.gguf" }
+        tokenizer = { hugging_face = { model_id = "microsoft/Phi-3.5-mini-instruct", file = "tokenizer.json" } }
+
+        [models.search]
+        type = "bert"
+    "#;
+    let registry = ModelRegistry::from_toml(toml).unwrap();
+    let mut names = registry.names().collect::<Vec<_>>();
+    names.sort();
+    assert_eq!(names, ["assistant", "local", "search"]);
+    assert!(matches!(
+        registry.get("local"),
+        Some(ModelConfig::Llama {
+            preset: None,
+            model: Some(FileSourceConfig::Local(_)),
+            tokenizer: Some(FileSourceConfig::HuggingFace { revision, .. }),
+        }) if revision == "main"
+    ));
+
+    let json = r#"{ "models": { "search": { "type": "bert", "preset": "bge_small_en" } } }"#;
+    let registry = ModelRegistry::from_json(json).unwrap();
+    assert_eq!(registry.get("search").map(ModelConfig::kind), Some("bert"));
+}
+
+#[cfg(feature = "vision")]
+#[test]
+fn test_parse_wuerstchen_sources() {
+    let toml = r#"
+        [models.images]
+        type = "wuerstchen"
+        decoder_weights = { local = "models/decoder.safetensors" }
+        prior_weights = { hugging_face = { model_id = "warp-ai/wuerstchen-prior", revision = "refs/pr/1", file = "prior/diffusion_pytorch_model.safetensors" } }
+        vqgan_weights = { url = "https://example.com/vqgan.safetensors" }
+    "#;
+    let registry = ModelRegistry::from_toml(toml).unwrap();
+    assert!(matches!(
+        registry.get("images"),
+        Some(ModelConfig::Wuerstchen {
+            decoder_weights: Some(FileSourceConfig::Local(_)),
+            prior_weights: Some(FileSourceConfig::HuggingFace { revision, .. }),
+            vqgan_weights: Some(FileSourceConfig::Url(_)),
+            tokenizer: None,
+            ..
+        }) if revision == "refs/pr/1"
+    ));
+}
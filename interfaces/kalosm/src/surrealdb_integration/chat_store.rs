@@ -0,0 +1,458 @@
+use std::any::{Any, TypeId};
+use std::ops::Range;
+use std::time::SystemTime;
+
+use kalosm_language::prelude::*;
+use serde::{Deserialize, Serialize};
+use surrealdb::{Connection, RecordId, RecordIdKey, Surreal};
+
+use super::document_table::DocumentTableCreationError;
+use super::{EmbeddedIndexedTableError, EmbeddingIndexedTable};
+
+/// A chat saved in a [`ChatStore`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StoredChat {
+    title: String,
+    history: Vec<ChatMessage>,
+    saved_at: SystemTime,
+}
+
+impl StoredChat {
+    /// Get the title of the chat.
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    /// Get the messages in the chat.
+    pub fn history(&self) -> &[ChatMessage] {
+        &self.history
+    }
+
+    /// Get the time the chat was saved.
+    pub fn saved_at(&self) -> SystemTime {
+        self.saved_at
+    }
+
+    /// Get the text of the chat with one line for the role of each message followed by the message. The byte ranges of search results from a [`ChatStore`] point into this text.
+    pub fn transcript(&self) -> String {
+        let (transcript, _) = self.transcript_with_ranges();
+        transcript
+    }
+
+    /// Get the transcript and the byte range of the content of each message in the transcript.
+    fn transcript_with_ranges(&self) -> (String, Vec<Range<usize>>) {
+        let mut transcript = String::new();
+        let mut ranges = Vec::with_capacity(self.history.len());
+        for message in &self.history {
+            let role = match message.role() {
+                MessageType::SystemPrompt => "System",
+                MessageType::UserMessage => "User",
+                MessageType::ModelAnswer => "Assistant",
+            };
+            transcript.push_str(role);
+            transcript.push_str(":\n");
+            let start = transcript.len();
+            transcript.push_str(message.content());
+            ranges.push(start..transcript.len());
+            transcript.push_str("\n\n");
+        }
+        (transcript, ranges)
+    }
+}
+
+/// The serialized state of a chat session saved in the [`ChatStore::table_sessions`] table.
+#[derive(Serialize, Deserialize)]
+struct SessionState {
+    bytes: Vec<u8>,
+}
+
+/// A chat in a [`ChatStore`] returned by [`ChatStore::list`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChatSummary {
+    /// The id of the chat in the store.
+    pub id: RecordIdKey,
+    /// The title of the chat.
+    pub title: String,
+    /// The number of messages in the chat.
+    pub messages: usize,
+    /// The time the chat was saved.
+    pub saved_at: SystemTime,
+}
+
+/// A message from a chat in a [`ChatStore`] that matched a search.
+#[derive(Debug, Clone)]
+pub struct ChatSearchResult {
+    /// The distance from the search query.
+    pub distance: f32,
+    /// The id of the chat in the store.
+    pub id: RecordIdKey,
+    /// The byte range of the message in the [`StoredChat::transcript`].
+    pub byte_range: Range<usize>,
+    /// The chat the message is from.
+    pub chat: StoredChat,
+}
+
+impl ChatSearchResult {
+    /// Get the message that matched the search.
+    pub fn message(&self) -> Option<&ChatMessage> {
+        let (_, ranges) = self.chat.transcript_with_ranges();
+        let index = ranges.iter().position(|range| *range == self.byte_range)?;
+        self.chat.history.get(index)
+    }
+}
+
+/// An error that can occur while saving or searching chats in a [`ChatStore`].
+#[derive(Debug, thiserror::Error)]
+pub enum ChatStoreError<E> {
+    /// An error occurred while embedding the messages or search query.
+    #[error("Failed to embed: {0}")]
+    Embed(E),
+    /// An error occurred in the database.
+    #[error("Failed to access chat store: {0}")]
+    Table(#[from] EmbeddedIndexedTableError),
+}
+
+/// A store that saves chat sessions in a surreal database so they can be listed, restored and searched later.
+///
+/// The store works with the session of any chat model, like a [`LlamaChatSession`](kalosm_language::kalosm_llama::LlamaChatSession) or the sessions of the remote chat models. The serialized session is saved with the history of the chat so sessions that include the processed state of the model, like the kv cache of a local model, can be restored without processing the chat again. Each message is embedded to search over past conversations.
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm::language::*;
+/// use surrealdb::{engine::local::SurrealKv, Surreal};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let db = Surreal::new::<SurrealKv>("./db/temp.db").await.unwrap();
+///     db.use_ns("chats").use_db("chats").await.unwrap();
+///     let store = db
+///         .chat_store_builder("chats")
+///         .at("./db/chat-embeddings.db")
+///         .build()
+///         .await
+///         .unwrap();
+///
+///     // Save a chat
+///     let model = Llama::new_chat().await.unwrap();
+///     let mut chat = model.chat();
+///     chat("What is the capital of France?").await.unwrap();
+///     let id = store
+///         .save("Capital of France", &*chat.session().unwrap())
+///         .await
+///         .unwrap();
+///
+///     // Find it again later
+///     for result in store.search("paris", 5).await.unwrap() {
+///         println!("{}: {:?}", result.chat.title(), result.message());
+///     }
+///
+///     // And continue the chat
+///     let mut chat = store.restore(&id, model).await.unwrap();
+///     chat("What is its population?").to_std_out().await.unwrap();
+/// }
+/// ```
+pub struct ChatStore<C: Connection, M: Embedder = Bert> {
+    embedding_model: M,
+    table: EmbeddingIndexedTable<C, StoredChat>,
+    session_state: bool,
+}
+
+impl<C: Connection, M: Embedder> ChatStore<C, M> {
+    /// Get the raw table the chats are stored in.
+    pub fn table(&self) -> &EmbeddingIndexedTable<C, StoredChat> {
+        &self.table
+    }
+
+    /// Get the raw embedding model.
+    pub fn embedding_model(&self) -> &M {
+        &self.embedding_model
+    }
+
+    /// Get the name of the table that stores the serialized chat sessions.
+    pub fn table_sessions(&self) -> String {
+        format!("{}-sessions", self.table.table())
+    }
+
+    /// Save the history and state of a chat session with a title. Returns the id of the chat in the store.
+    ///
+    /// If the session fails to serialize, only the history is saved.
+    pub async fn save<S: ChatSession>(
+        &self,
+        title: impl ToString,
+        session: &S,
+    ) -> Result<RecordIdKey, ChatStoreError<M::Error>> {
+        let chat = StoredChat {
+            title: title.to_string(),
+            history: session.history(),
+            saved_at: SystemTime::now(),
+        };
+
+        // Only the user and model messages are searchable
+        let (_, ranges) = chat.transcript_with_ranges();
+        let (ranges, contents): (Vec<_>, Vec<_>) = chat
+            .history
+            .iter()
+            .zip(ranges)
+            .filter(|(message, _)| message.role() != MessageType::SystemPrompt)
+            .map(|(message, range)| (range, message.content()))
+            .unzip();
+        let embeddings = if contents.is_empty() {
+            Vec::new()
+        } else {
+            self.embedding_model
+                .embed_batch(contents)
+                .await
+                .map_err(ChatStoreError::Embed)?
+        };
+        let chunks = ranges
+            .into_iter()
+            .zip(embeddings)
+            .map(|(byte_range, embedding)| Chunk {
+                byte_range,
+                embeddings: vec![embedding],
+            });
+        let id = self.table.insert(chunks, chat).await?;
+
+        if self.session_state {
+            match session.to_bytes() {
+                Ok(bytes) => {
+                    let record = RecordId::from_table_key(self.table_sessions(), id.clone());
+                    self.table
+                        .db()
+                        .upsert::<Option<SessionState>>(record)
+                        .content(SessionState { bytes })
+                        .await
+                        .map_err(EmbeddedIndexedTableError::from)?;
+                }
+                Err(_) => {
+                    tracing::warn!("Failed to serialize the chat session. Only the history of the chat will be saved.");
+                }
+            }
+        }
+
+        Ok(id)
+    }
+
+    /// Load a chat from the store.
+    pub async fn load(
+        &self,
+        id: impl Into<RecordIdKey>,
+    ) -> Result<StoredChat, EmbeddedIndexedTableError> {
+        self.table.select(id).await
+    }
+
+    /// Load the saved session of a chat. Returns `None` if the session state was not saved.
+    pub async fn load_session<S: ChatSession>(
+        &self,
+        id: impl Into<RecordIdKey>,
+    ) -> Result<Option<Result<S, S::Error>>, EmbeddedIndexedTableError> {
+        let record = RecordId::from_table_key(self.table_sessions(), id);
+        let state: Option<SessionState> = self.table.db().select(record).await?;
+        Ok(state.map(|state| S::from_bytes(&state.bytes)))
+    }
+
+    /// Restore a chat with the model. If the session state was saved and loads, the chat continues from the saved session. Otherwise, the history of the chat is added to a new session.
+    pub async fn restore<Model: CreateChatSession>(
+        &self,
+        id: &RecordIdKey,
+        model: Model,
+    ) -> Result<Chat<Model>, EmbeddedIndexedTableError> {
+        match self.load_session::<Model::ChatSession>(id.clone()).await? {
+            Some(Ok(session)) => return Ok(Chat::new(model).with_session(session)),
+            Some(Err(_)) => {
+                tracing::warn!("Failed to load the saved chat session. The chat will be restored from the history instead.");
+            }
+            None => {}
+        }
+        let chat = self.load(id.clone()).await?;
+        let mut restored = Chat::new(model);
+        for message in chat.history {
+            // The message is queued until the next response is generated
+            _ = restored.add_message(message);
+        }
+        Ok(restored)
+    }
+
+    /// List every chat in the store from the most to least recently saved.
+    pub async fn list(&self) -> Result<Vec<ChatSummary>, EmbeddedIndexedTableError> {
+        #[derive(Deserialize)]
+        struct ListedChat {
+            id: RecordId,
+            object: StoredChat,
+        }
+
+        let chats: Vec<ListedChat> = self
+            .table
+            .db()
+            .query("SELECT id, object FROM type::table($table)")
+            .bind(("table", self.table.table().to_string()))
+            .await?
+            .take(0)?;
+        let mut summaries = chats
+            .into_iter()
+            .map(|chat| ChatSummary {
+                id: chat.id.key().clone(),
+                title: chat.object.title,
+                messages: chat.object.history.len(),
+                saved_at: chat.object.saved_at,
+            })
+            .collect::<Vec<_>>();
+        summaries.sort_by(|a, b| b.saved_at.cmp(&a.saved_at));
+        Ok(summaries)
+    }
+
+    /// Delete a chat and its saved session from the store.
+    pub async fn delete(
+        &self,
+        id: impl Into<RecordIdKey>,
+    ) -> Result<Option<StoredChat>, EmbeddedIndexedTableError> {
+        let id = id.into();
+        let record = RecordId::from_table_key(self.table_sessions(), id.clone());
+        self.table
+            .db()
+            .delete::<Option<SessionState>>(record)
+            .await?;
+        self.table.delete(id).await
+    }
+
+    /// Search for messages in past chats that are close to the query.
+    pub async fn search(
+        &self,
+        query: impl ToString,
+        results: usize,
+    ) -> Result<Vec<ChatSearchResult>, ChatStoreError<M::Error>> {
+        let embedding = self
+            .embedding_model
+            .embed_query(query)
+            .await
+            .map_err(ChatStoreError::Embed)?;
+        let results = self.table.search(&embedding).with_results(results).await?;
+        Ok(results
+            .into_iter()
+            .map(|result| ChatSearchResult {
+                distance: result.distance,
+                id: result.record_id,
+                byte_range: result.byte_range,
+                chat: result.record,
+            })
+            .collect())
+    }
+}
+
+/// A builder for creating a new [`ChatStore`].
+pub struct ChatStoreBuilder<C: Connection, E = Bert> {
+    table: String,
+    db: Surreal<C>,
+    embedding_model: Option<E>,
+    location: Option<std::path::PathBuf>,
+    session_state: bool,
+}
+
+impl<C: Connection> ChatStoreBuilder<C, Bert> {
+    /// Create a new chat store builder.
+    pub(crate) fn new(table: &str, db: Surreal<C>) -> Self {
+        Self {
+            table: table.to_string(),
+            db,
+            embedding_model: None,
+            location: None,
+            session_state: true,
+        }
+    }
+}
+
+impl<C: Connection, E> ChatStoreBuilder<C, E> {
+    /// Set the location of the vector database.
+    pub fn at(mut self, location: impl AsRef<std::path::Path>) -> Self {
+        self.location = Some(location.as_ref().to_path_buf());
+        self
+    }
+
+    /// Set the embedding model used to search the chats.
+    pub fn with_embedding_model<E2>(self, embedding_model: E2) -> ChatStoreBuilder<C, E2> {
+        ChatStoreBuilder {
+            table: self.table,
+            db: self.db,
+            embedding_model: Some(embedding_model),
+            location: self.location,
+            session_state: self.session_state,
+        }
+    }
+
+    /// Set whether the serialized state of each session is saved with the history. The state of local models includes the kv cache which can be large, but lets the chat continue without processing the history again. (default: true)
+    pub fn with_session_state(mut self, session_state: bool) -> Self {
+        self.session_state = session_state;
+        self
+    }
+
+    /// Build the chat store.
+    pub async fn build(self) -> Result<ChatStore<C, E>, DocumentTableCreationError>
+    where
+        E: Embedder,
+    {
+        let vector_db = if let Some(location) = self.location {
+            VectorDB::new_at(location)?
+        } else {
+            VectorDB::new()?
+        };
+        let table = EmbeddingIndexedTable {
+            table: self.table,
+            db: self.db,
+            vector_db,
+            phantom: std::marker::PhantomData,
+        };
+        let embedding_model = match self.embedding_model {
+            Some(embedding_model) => embedding_model,
+            None => {
+                if TypeId::of::<E>() == TypeId::of::<Bert>() {
+                    let embedding_model = Bert::new_for_search().await?;
+                    *(Box::new(embedding_model) as Box<dyn Any>)
+                        .downcast::<E>()
+                        .unwrap()
+                } else {
+                    return Err(DocumentTableCreationError::NoEmbeddingModel);
+                }
+            }
+        };
+        Ok(ChatStore {
+            embedding_model,
+            table,
+            session_state: self.session_state,
+        })
+    }
+}
+
+/// An extension trait for the surreal database to create chat stores.
+pub trait ChatStoreSurrealExt<C: Connection> {
+    /// Create a new chat store builder.
+    fn chat_store_builder(&self, table: &str) -> ChatStoreBuilder<C, Bert>;
+}
+
+impl<C: Connection> ChatStoreSurrealExt<C> for Surreal<C> {
+    fn chat_store_builder(&self, table: &str) -> ChatStoreBuilder<C, Bert> {
+        ChatStoreBuilder::new(table, self.clone())
+    }
+}
+
+#[test]
+fn test_transcript_ranges() {
+    let chat = StoredChat {
+        title: "Greeting".to_string(),
+        history: vec![
+            ChatMessage::new(MessageType::SystemPrompt, "Be brief"),
+            ChatMessage::new(MessageType::UserMessage, "Hi"),
+            ChatMessage::new(MessageType::ModelAnswer, "Hello!"),
+        ],
+        saved_at: SystemTime::UNIX_EPOCH,
+    };
+    let (transcript, ranges) = chat.transcript_with_ranges();
+    assert_eq!(
+        transcript,
+        "System:\nBe brief\n\nUser:\nHi\n\nAssistant:\nHello!\n\n"
+    );
+    let contents = ranges
+        .into_iter()
+        .map(|range| &transcript[range])
+        .collect::<Vec<_>>();
+    assert_eq!(contents, ["Be brief", "Hi", "Hello!"]);
+}
@@ -0,0 +1,235 @@
+use std::fmt::Write;
+use std::ops::Range;
+
+use kalosm_language::prelude::*;
+use surrealdb::RecordIdKey;
+
+use super::EmbeddingIndexedTableSearchResult;
+
+/// A chunk that was given to the model as a source, with the location of the chunk in the original document.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Citation {
+    /// The number of the source in the prompt. Answers refer to the source with this number in square brackets, like `[1]`.
+    pub index: usize,
+    /// The id of the record the chunk is from.
+    pub record_id: RecordIdKey,
+    /// The title of the document the chunk is from.
+    pub title: String,
+    /// The location the document was read from, like a URL or a file path. See [`Document::source`].
+    pub source: Option<String>,
+    /// The byte range of the chunk in the body of the document.
+    pub byte_range: Range<usize>,
+    /// The character range of the chunk in the body of the document.
+    pub char_range: Range<usize>,
+    /// The text of the chunk.
+    pub text: String,
+    /// The distance from the query to the chunk in the search that returned it.
+    pub distance: f32,
+}
+
+impl Citation {
+    pub(crate) fn new(
+        index: usize,
+        record_id: RecordIdKey,
+        document: &Document,
+        byte_range: Range<usize>,
+        distance: f32,
+    ) -> Self {
+        let body = document.body();
+        let start = body[..byte_range.start].chars().count();
+        let text = body[byte_range.clone()].to_string();
+        Self {
+            index,
+            record_id,
+            title: document.title().to_string(),
+            source: document.source().map(ToString::to_string),
+            byte_range,
+            char_range: start..start + text.chars().count(),
+            text,
+            distance,
+        }
+    }
+
+    /// Format the citation as a numbered source in the context for the model.
+    pub(crate) fn format(&self) -> String {
+        let mut formatted = format!("[{}] {}", self.index, self.title);
+        if let Some(source) = &self.source {
+            _ = write!(formatted, " ({source})");
+        }
+        _ = writeln!(formatted, "\n{}\n", self.text.trim());
+        formatted
+    }
+}
+
+/// Numbered sources from a search that can be used as the context for an answer. The sources keep track of where each chunk came from, so the answer can be annotated with the chunks that support it.
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm::language::*;
+/// use surrealdb::{engine::local::SurrealKv, Surreal};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let db = Surreal::new::<SurrealKv>("./db/temp.db").await.unwrap();
+///     db.use_ns("rag").use_db("rag").await.unwrap();
+///     let document_table = db
+///         .document_table_builder("documents")
+///         .at("./db/embeddings.db")
+///         .build::<Document>()
+///         .await
+///         .unwrap();
+///     let model = Llama::new_chat().await.unwrap();
+///     let mut chat = model.chat();
+///
+///     let question = prompt_input("Question: ").unwrap();
+///     let results = document_table.search(&question).with_results(5).await.unwrap();
+///     let sources = CitedSources::new(&results);
+///     let answer = chat(&sources.prompt(&question)).await.unwrap();
+///     let answer = sources.annotate(answer);
+///
+///     println!("{}", answer.text());
+///     for citation in answer.citations() {
+///         println!("[{}] {} ({:?})", citation.index, citation.title, citation.source);
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CitedSources {
+    citations: Vec<Citation>,
+}
+
+impl CitedSources {
+    /// Number the results of a search as sources, starting at 1.
+    pub fn new<'a, R: AsRef<Document> + 'a>(
+        results: impl IntoIterator<Item = &'a EmbeddingIndexedTableSearchResult<R>>,
+    ) -> Self {
+        Self {
+            citations: results
+                .into_iter()
+                .enumerate()
+                .map(|(i, result)| {
+                    Citation::new(
+                        i + 1,
+                        result.record_id.clone(),
+                        result.record.as_ref(),
+                        result.byte_range.clone(),
+                        result.distance,
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    pub(crate) fn from_citations(citations: Vec<Citation>) -> Self {
+        Self { citations }
+    }
+
+    /// Get every source in the order they are numbered.
+    pub fn citations(&self) -> &[Citation] {
+        &self.citations
+    }
+
+    /// Get the source with the number used in the prompt.
+    pub fn get(&self, index: usize) -> Option<&Citation> {
+        self.citations.get(index.checked_sub(1)?)
+    }
+
+    /// Format the sources as numbered context for the model.
+    pub fn context(&self) -> String {
+        self.citations.iter().map(Citation::format).collect()
+    }
+
+    /// Create a prompt that asks the model to answer the question with the sources and cite them with their number in square brackets.
+    pub fn prompt(&self, question: &str) -> String {
+        format!(
+            "Answer the question using the sources below. After each sentence, cite the sources that support it with their number in square brackets, like [1] or [1, 2].\n\n{}Question: {question}",
+            self.context()
+        )
+    }
+
+    /// Annotate an answer with the sources it cites. Citations of numbers that are not a source are ignored.
+    pub fn annotate(&self, answer: impl Into<String>) -> CitedAnswer {
+        let text = answer.into();
+        let mut citations: Vec<Citation> = Vec::new();
+        for index in cited_indexes(&text) {
+            if let Some(citation) = self.get(index) {
+                if !citations.iter().any(|cited| cited.index == index) {
+                    citations.push(citation.clone());
+                }
+            }
+        }
+        CitedAnswer { text, citations }
+    }
+}
+
+/// An answer annotated with the sources that support it. This is created with [`CitedSources::annotate`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct CitedAnswer {
+    text: String,
+    citations: Vec<Citation>,
+}
+
+impl CitedAnswer {
+    /// Get the text of the answer, including the citation markers.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Get the sources the answer cites in the order they are first cited.
+    pub fn citations(&self) -> &[Citation] {
+        &self.citations
+    }
+}
+
+impl std::fmt::Display for CitedAnswer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+/// Find the numbers in citation markers like `[1]` or `[1, 2]` in the order they appear.
+fn cited_indexes(text: &str) -> Vec<usize> {
+    let mut indexes = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('[') {
+        rest = &rest[start + 1..];
+        let Some(end) = rest.find(']') else {
+            break;
+        };
+        let marker: Option<Vec<usize>> = rest[..end]
+            .split(',')
+            .map(|index| index.trim().parse().ok())
+            .collect();
+        if let Some(marker) = marker {
+            indexes.extend(marker);
+        }
+    }
+    indexes
+}
+
+#[test]
+fn test_annotate_answer() {
+    let document = Document::from_parts(
+        "Rust",
+        "Rust is fast. Rust is memory safe. Ferris is a crab.",
+    );
+    let results = [0..13, 14..34, 35..52].map(|byte_range| EmbeddingIndexedTableSearchResult {
+        distance: 0.0,
+        id: EmbeddingId(0),
+        record_id: RecordIdKey::from(1i64),
+        byte_range,
+        record: document.clone(),
+    });
+    let sources = CitedSources::new(&results);
+    assert!(sources
+        .prompt("Is rust safe?")
+        .contains("[2] Rust\nRust is memory safe."));
+
+    let answer = sources.annotate("Rust is memory safe [2, 1]. See [array] and [7]. [2]");
+    let cited: Vec<_> = answer
+        .citations()
+        .iter()
+        .map(|citation| (citation.index, citation.char_range.clone()))
+        .collect();
+    assert_eq!(cited, [(2, 14..34), (1, 0..13)]);
+}
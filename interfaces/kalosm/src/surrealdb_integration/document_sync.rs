@@ -0,0 +1,227 @@
+use std::collections::{HashMap, HashSet};
+use std::time::SystemTime;
+
+use kalosm_language::prelude::*;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use surrealdb::{Connection, RecordId, RecordIdKey};
+
+use super::document_table::{DocumentTable, DocumentTableModifyError};
+use super::EmbeddedIndexedTableError;
+
+/// The content hash and modification time of a document that was added to a [`DocumentTable`] with [`DocumentTable::sync`] or [`DocumentTable::sync_folder`].
+///
+/// This type is stored in the [`super::EmbeddingIndexedTable::table_sources`] table.
+#[derive(Serialize, Deserialize)]
+pub struct DocumentSource {
+    key: String,
+    document_id: RecordIdKey,
+    content_hash: String,
+    modified_at: Option<SystemTime>,
+}
+
+/// A summary of the changes made to a [`DocumentTable`] while syncing documents.
+#[derive(Debug, Clone, Default)]
+pub struct DocumentTableSyncSummary {
+    /// The ids of documents that were added to the table.
+    pub added: Vec<RecordIdKey>,
+    /// The ids of documents that changed and were re-chunked and re-embedded.
+    pub updated: Vec<RecordIdKey>,
+    /// The ids of documents that were removed from the table because they are no longer in the source.
+    pub removed: Vec<RecordIdKey>,
+    /// The number of documents that didn't change.
+    pub unchanged: usize,
+}
+
+/// An error that can occur while syncing a [`DocumentFolder`] with a [`DocumentTable`].
+#[derive(Debug, thiserror::Error)]
+pub enum DocumentTableSyncFolderError<E> {
+    /// An error occurred while listing the files in the folder.
+    #[error("Failed to read folder: {0}")]
+    ReadFolder(#[from] std::io::Error),
+    /// An error occurred while converting a file to a document.
+    #[error("Failed to convert file to document: {0}")]
+    ConvertItem(FsDocumentError<TextFileDecodeError>),
+    /// An error occurred while modifying the table.
+    #[error("Failed to modify table: {0}")]
+    ModifyTable(#[from] DocumentTableModifyError<E>),
+}
+
+impl<C: Connection, R, M: Embedder, K: Chunker> DocumentTable<C, R, M, K> {
+    /// Sync the table with a set of documents identified by a unique key like a path or url.
+    ///
+    /// Only documents that are new or changed since the last sync are chunked and embedded. Documents that were added in a previous sync, but are missing from `documents` are removed from the table. If a key appears more than once, only the first document with that key is used.
+    ///
+    /// # Example
+    /// ```rust, no_run
+    /// use kalosm::language::*;
+    /// use surrealdb::{engine::local::SurrealKv, Surreal};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let db = Surreal::new::<SurrealKv>("./db/temp.db").await.unwrap();
+    ///     db.use_ns("rag").use_db("rag").await.unwrap();
+    ///     let document_table = db
+    ///         .document_table_builder("documents")
+    ///         .at("./db/embeddings.db")
+    ///         .build::<Document>()
+    ///         .await
+    ///         .unwrap();
+    ///
+    ///     let notes = [
+    ///         ("notes/monday", Document::from_parts("Monday", "Fixed the build")),
+    ///         ("notes/tuesday", Document::from_parts("Tuesday", "Wrote the docs")),
+    ///     ];
+    ///     let summary = document_table.sync(notes).await.unwrap();
+    ///     println!("{summary:?}");
+    /// }
+    /// ```
+    pub async fn sync<I: Into<String>>(
+        &self,
+        documents: impl IntoIterator<Item = (I, R)>,
+    ) -> Result<DocumentTableSyncSummary, DocumentTableModifyError<K::Error<M::Error>>>
+    where
+        R: AsRef<Document> + Serialize + DeserializeOwned + 'static,
+    {
+        let mut sources = self.sources().await?;
+        let mut summary = DocumentTableSyncSummary::default();
+        let mut seen = HashSet::new();
+        for (key, value) in documents {
+            let key = key.into();
+            if !seen.insert(key.clone()) {
+                continue;
+            }
+            let existing = sources.remove(&key);
+            self.sync_document(key, value, None, existing, &mut summary)
+                .await?;
+        }
+        self.remove_sources(sources.into_values(), &mut summary)
+            .await?;
+        Ok(summary)
+    }
+
+    /// Sync the table with the files in a [`DocumentFolder`]. Each document is identified by the path of the file.
+    ///
+    /// Files that have the same modification time as the last sync are skipped without reading them. Files that changed are re-chunked and re-embedded, and documents for files that were deleted are removed from the table.
+    pub async fn sync_folder(
+        &self,
+        folder: &DocumentFolder,
+    ) -> Result<DocumentTableSyncSummary, DocumentTableSyncFolderError<K::Error<M::Error>>>
+    where
+        R: From<Document> + AsRef<Document> + Serialize + DeserializeOwned + 'static,
+    {
+        let mut sources = self
+            .sources()
+            .await
+            .map_err(DocumentTableModifyError::from)?;
+        let mut summary = DocumentTableSyncSummary::default();
+        for path in folder.files().await? {
+            let key = path.display().to_string();
+            let modified_at = std::fs::metadata(&path)?.modified().ok();
+            let existing = sources.remove(&key);
+            if let Some(existing) = &existing {
+                if modified_at.is_some() && existing.modified_at == modified_at {
+                    summary.unchanged += 1;
+                    continue;
+                }
+            }
+            let document = FsDocument::try_from(path)
+                .map_err(|err| match err {
+                    FsDocumentError::Read(err) => DocumentTableSyncFolderError::ReadFolder(err),
+                    FsDocumentError::Decode(never) => match never {},
+                    FsDocumentError::WrongFileType => {
+                        DocumentTableSyncFolderError::ConvertItem(FsDocumentError::WrongFileType)
+                    }
+                })?
+                .into_document()
+                .await
+                .map_err(DocumentTableSyncFolderError::ConvertItem)?;
+            self.sync_document(key, document.into(), modified_at, existing, &mut summary)
+                .await?;
+        }
+        self.remove_sources(sources.into_values(), &mut summary)
+            .await
+            .map_err(DocumentTableModifyError::from)?;
+        Ok(summary)
+    }
+
+    /// Get every source that was synced with the table by key
+    async fn sources(&self) -> Result<HashMap<String, DocumentSource>, EmbeddedIndexedTableError> {
+        let sources: Vec<DocumentSource> = self
+            .table()
+            .db()
+            .select(self.table().table_sources())
+            .await?;
+        Ok(sources
+            .into_iter()
+            .map(|source| (source.key.clone(), source))
+            .collect())
+    }
+
+    /// Add, update or skip a single document depending on the content hash of the last sync
+    async fn sync_document(
+        &self,
+        key: String,
+        value: R,
+        modified_at: Option<SystemTime>,
+        existing: Option<DocumentSource>,
+        summary: &mut DocumentTableSyncSummary,
+    ) -> Result<(), DocumentTableModifyError<K::Error<M::Error>>>
+    where
+        R: AsRef<Document> + Serialize + DeserializeOwned + 'static,
+    {
+        let content_hash = format!("{:016x}", value.as_ref().content_hash());
+        let document_id = match existing {
+            Some(existing) if existing.content_hash == content_hash => {
+                summary.unchanged += 1;
+                existing.document_id
+            }
+            Some(existing) => {
+                self.delete(existing.document_id).await?;
+                let id = self.insert(value).await?;
+                summary.updated.push(id.clone());
+                id
+            }
+            None => {
+                let id = self.insert(value).await?;
+                summary.added.push(id.clone());
+                id
+            }
+        };
+
+        let record = RecordId::from_table_key(self.table().table_sources(), key.clone());
+        self.table()
+            .db()
+            .upsert::<Option<DocumentSource>>(record)
+            .content(DocumentSource {
+                key,
+                document_id,
+                content_hash,
+                modified_at,
+            })
+            .await
+            .map_err(EmbeddedIndexedTableError::from)?;
+        Ok(())
+    }
+
+    /// Remove the documents for sources that are no longer part of the synced documents
+    async fn remove_sources(
+        &self,
+        sources: impl IntoIterator<Item = DocumentSource>,
+        summary: &mut DocumentTableSyncSummary,
+    ) -> Result<(), EmbeddedIndexedTableError>
+    where
+        R: Serialize + DeserializeOwned + 'static,
+    {
+        for source in sources {
+            self.delete(source.document_id.clone()).await?;
+            let record = RecordId::from_table_key(self.table().table_sources(), source.key);
+            self.table()
+                .db()
+                .delete::<Option<DocumentSource>>(record)
+                .await?;
+            summary.removed.push(source.document_id);
+        }
+        Ok(())
+    }
+}
@@ -1,13 +1,19 @@
 use std::any::Any;
 use std::any::TypeId;
+use std::convert::Infallible;
 use std::future::Future;
 use std::future::IntoFuture;
 use std::pin::Pin;
 
 use super::EmbeddedIndexedTableError;
 
+use super::query_expansion::{
+    reciprocal_rank_fusion, QueryExpansion, SearchQueryExpander, SearchQueryExpansion,
+};
 use super::IntoEmbeddingIndexedTableSearchFilter;
+use super::Metadata;
 use super::{EmbeddingIndexedTable, EmbeddingIndexedTableSearchResult};
+use futures_util::{Stream, StreamExt};
 use kalosm_language::prelude::*;
 use kalosm_language::rbert::BertLoadingError;
 use serde::de::DeserializeOwned;
@@ -148,6 +154,61 @@ impl<C: Connection, R, M: Embedder, K: Chunker> DocumentTable<C, R, M, K> {
         Ok(self.insert_with_chunks(value, chunks).await?)
     }
 
+    /// Insert a new record into the table and attach the metadata to each of its chunks. Searches can be filtered by the metadata with a [`MetadataFilter`](crate::MetadataFilter).
+    ///
+    /// # Example
+    /// ```rust, no_run
+    /// use kalosm::language::*;
+    /// use kalosm::{Metadata, MetadataFilter};
+    /// use surrealdb::{engine::local::SurrealKv, Surreal};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let db = Surreal::new::<SurrealKv>("./db/temp.db").await.unwrap();
+    ///     db.use_ns("rag").use_db("rag").await.unwrap();
+    ///     let document_table = db
+    ///         .document_table_builder("documents")
+    ///         .at("./db/embeddings.db")
+    ///         .build::<Document>()
+    ///         .await
+    ///         .unwrap();
+    ///
+    ///     let document = Document::from_parts("Billing", "Invoices are sent on the first of each month.");
+    ///     let metadata = Metadata::new()
+    ///         .with("tenant", "acme")
+    ///         .with("tags", vec!["billing"]);
+    ///     document_table
+    ///         .insert_with_metadata(document, metadata)
+    ///         .await
+    ///         .unwrap();
+    ///
+    ///     let results = document_table
+    ///         .search("When are invoices sent?")
+    ///         .with_filter(MetadataFilter::eq("tenant", "acme"))
+    ///         .await
+    ///         .unwrap();
+    ///     println!("{:?}", results);
+    /// }
+    /// ```
+    pub async fn insert_with_metadata(
+        &self,
+        value: R,
+        metadata: Metadata,
+    ) -> Result<RecordIdKey, DocumentTableModifyError<K::Error<M::Error>>>
+    where
+        R: AsRef<Document> + Serialize + DeserializeOwned + 'static,
+    {
+        let chunks = self
+            .chunker
+            .chunk(value.as_ref(), &self.embedding_model)
+            .await
+            .map_err(DocumentTableModifyError::EmbedItem)?;
+        Ok(self
+            .table
+            .insert_with_metadata(chunks, value, metadata)
+            .await?)
+    }
+
     /// Extend the table with a iterator of new records.
     pub async fn extend<T: IntoIterator<Item = R> + Send>(
         &self,
@@ -224,6 +285,9 @@ impl<C: Connection, R, M: Embedder, K: Chunker> DocumentTable<C, R, M, K> {
             embedding,
             results: None,
             filter: None,
+            max_sim: false,
+            reranker: (),
+            expander: (),
             phantom: std::marker::PhantomData,
         }
     }
@@ -259,6 +323,44 @@ impl<C: Connection, R, M: Embedder, K: Chunker> DocumentTable<C, R, M, K> {
             .await
             .map_err(DocumentTableAddContextError::ModifyTable)
     }
+
+    /// Add items from a stream to the table as they arrive. This runs until the stream ends, so it works well with sources that produce documents over time like a [`FeedWatcher`].
+    ///
+    /// # Example
+    /// ```rust, no_run
+    /// use kalosm::language::*;
+    /// use surrealdb::{engine::local::SurrealKv, Surreal};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let db = Surreal::new::<SurrealKv>("./db/temp.db").await.unwrap();
+    ///     db.use_ns("rag").use_db("rag").await.unwrap();
+    ///     let document_table = db
+    ///         .document_table_builder("news")
+    ///         .at("./db/embeddings.db")
+    ///         .build::<Document>()
+    ///         .await
+    ///         .unwrap();
+    ///
+    ///     let watcher = FeedWatcher::new([RssFeed::new(
+    ///         Url::parse("https://www.nytimes.com/services/xml/rss/nyt/HomePage.xml").unwrap(),
+    ///     )]);
+    ///     document_table.add_stream(watcher.into_stream()).await.unwrap();
+    /// }
+    /// ```
+    pub async fn add_stream<T: Into<R>>(
+        &self,
+        stream: impl Stream<Item = T>,
+    ) -> Result<(), DocumentTableModifyError<K::Error<M::Error>>>
+    where
+        R: AsRef<Document> + Serialize + DeserializeOwned + 'static,
+    {
+        let mut stream = std::pin::pin!(stream);
+        while let Some(item) = stream.next().await {
+            self.insert(item.into()).await?;
+        }
+        Ok(())
+    }
 }
 
 /// A builder for searching for embeddings in a vector database.
@@ -271,23 +373,101 @@ pub struct DocumentTableSearchBuilder<
     E = Embedding,
     F = Candidates,
     M = (),
+    Rr = (),
+    Qe = (),
 > {
     table: &'a DocumentTable<Conn, Doc, Model, Chkr>,
     embedding: E,
     results: Option<usize>,
     filter: Option<F>,
+    max_sim: bool,
+    reranker: Rr,
+    expander: Qe,
     phantom: std::marker::PhantomData<M>,
 }
 
 /// An error that can occur while searching a [`DocumentTable`].
 #[derive(Debug, thiserror::Error)]
-pub enum DocumentTableSearchError<E> {
+pub enum DocumentTableSearchError<E, R = Infallible, Q = Infallible> {
     /// An error occurred while embedding the search query.
     #[error("Failed to embed search query: {0}")]
     EmbedQuery(E),
     /// An error occurred while running the search on the underlying table.
     #[error("Failed to run search on table: {0}")]
     SearchTable(#[from] EmbeddedIndexedTableError),
+    /// An error occurred while reranking the results of the search.
+    #[error("Failed to rerank search results: {0}")]
+    Rerank(R),
+    /// An error occurred while expanding the search query.
+    #[error("Failed to expand search query: {0}")]
+    ExpandQuery(Q),
+}
+
+/// A stage that reorders the results of the vector search in a [`DocumentTableSearchBuilder`].
+///
+/// This is implemented for `()`, which keeps the order of the vector search, and [`SearchReranking`], which is created with [`DocumentTableSearchBuilder::with_reranker`].
+pub trait SearchReranker<Doc>: Send + Sync {
+    /// The error type that can occur when reranking results.
+    type Error: Send + Sync + 'static;
+
+    /// Get the number of results to fetch from the vector search to return `results` results.
+    fn candidates(&self, results: usize) -> usize;
+
+    /// Reorder the results of the vector search and keep the `results` most relevant results.
+    fn rerank(
+        &self,
+        candidates: Vec<EmbeddingIndexedTableSearchResult<Doc>>,
+        results: usize,
+    ) -> impl Future<Output = Result<Vec<EmbeddingIndexedTableSearchResult<Doc>>, Self::Error>> + Send;
+}
+
+impl<Doc: Send> SearchReranker<Doc> for () {
+    type Error = Infallible;
+
+    fn candidates(&self, results: usize) -> usize {
+        results
+    }
+
+    async fn rerank(
+        &self,
+        candidates: Vec<EmbeddingIndexedTableSearchResult<Doc>>,
+        _: usize,
+    ) -> Result<Vec<EmbeddingIndexedTableSearchResult<Doc>>, Self::Error> {
+        Ok(candidates)
+    }
+}
+
+/// Reranks the results of a vector search with a [`Reranker`]. This is created with [`DocumentTableSearchBuilder::with_reranker`].
+pub struct SearchReranking<'a, R> {
+    reranker: &'a R,
+    query: String,
+    candidates: usize,
+}
+
+impl<Doc: AsRef<Document> + Send, R: Reranker> SearchReranker<Doc> for SearchReranking<'_, R> {
+    type Error = R::Error;
+
+    fn candidates(&self, results: usize) -> usize {
+        self.candidates.max(results)
+    }
+
+    async fn rerank(
+        &self,
+        candidates: Vec<EmbeddingIndexedTableSearchResult<Doc>>,
+        results: usize,
+    ) -> Result<Vec<EmbeddingIndexedTableSearchResult<Doc>>, Self::Error> {
+        let texts = candidates
+            .iter()
+            .map(|result| result.record.as_ref().body()[result.byte_range.clone()].to_string())
+            .collect();
+        let ranked = self.reranker.rerank_vec(self.query.clone(), texts).await?;
+        let mut candidates: Vec<_> = candidates.into_iter().map(Some).collect();
+        Ok(ranked
+            .into_iter()
+            .filter_map(|ranked| candidates.get_mut(ranked.index)?.take())
+            .take(results)
+            .collect())
+    }
 }
 
 impl<
@@ -298,7 +478,9 @@ impl<
         F: IntoEmbeddingIndexedTableSearchFilter<Conn, Doc, M>,
         Chkr: Chunker,
         M,
-    > DocumentTableSearchBuilder<'_, Conn, Doc, Model, Chkr, E, F, M>
+        Rr: SearchReranker<Doc>,
+        Qe: SearchQueryExpander,
+    > DocumentTableSearchBuilder<'_, Conn, Doc, Model, Chkr, E, F, M, Rr, Qe>
 {
     /// Set the number of results to return. Defaults to 10.
     pub fn with_results(mut self, results: usize) -> Self {
@@ -306,25 +488,108 @@ impl<
         self
     }
 
+    /// Score each chunk with MaxSim over all of the vectors stored for it instead of returning the nearest vectors. Each chunk is returned at most once.
+    ///
+    /// Use this with a table that stores several vectors for each chunk, like a table built with a [`MultiVectorChunker`]. See [`EmbeddingIndexedTable::search_max_sim`] to search with more than one query vector.
+    pub fn with_max_sim(mut self) -> Self {
+        self.max_sim = true;
+        self
+    }
+
     /// Run the search and return the results.
     pub async fn run(
         self,
-    ) -> Result<Vec<EmbeddingIndexedTableSearchResult<Doc>>, DocumentTableSearchError<Model::Error>>
-    {
-        let embedding = self
-            .embedding
-            .into_embedding(&self.table.embedding_model)
+    ) -> Result<
+        Vec<EmbeddingIndexedTableSearchResult<Doc>>,
+        DocumentTableSearchError<Model::Error, Rr::Error, Qe::Error>,
+    > {
+        let results = self.results.unwrap_or(10);
+        let candidate_count = self.reranker.candidates(results);
+        let queries = self
+            .expander
+            .expand()
+            .await
+            .map_err(DocumentTableSearchError::ExpandQuery)?;
+        let candidates = if queries.is_empty() {
+            let embedding = self
+                .embedding
+                .into_embedding(&self.table.embedding_model)
+                .await
+                .map_err(DocumentTableSearchError::EmbedQuery)?;
+            search_table(
+                &self.table.table,
+                &embedding,
+                candidate_count,
+                self.max_sim,
+                self.filter,
+            )
+            .await?
+        } else {
+            // The filter is resolved once and shared by the search for each query
+            let filter = match self.filter {
+                Some(filter) => Some(
+                    filter
+                        .into_embedding_indexed_table_search_filter(&self.table.table)
+                        .await?,
+                ),
+                None => None,
+            };
+            let mut ranked = Vec::with_capacity(queries.len());
+            for query in &queries {
+                let embedding = query
+                    .embed(&self.table.embedding_model)
+                    .await
+                    .map_err(DocumentTableSearchError::EmbedQuery)?;
+                ranked.push(
+                    search_table(
+                        &self.table.table,
+                        &embedding,
+                        candidate_count,
+                        self.max_sim,
+                        filter.clone(),
+                    )
+                    .await?,
+                );
+            }
+            let mut fused = reciprocal_rank_fusion(ranked, self.expander.rrf_k());
+            fused.truncate(candidate_count);
+            fused
+        };
+        self.reranker
+            .rerank(candidates, results)
             .await
-            .map_err(DocumentTableSearchError::EmbedQuery)?;
-        let mut query = self.table.table.search(&embedding);
-        if let Some(results) = self.results {
-            query = query.with_results(results);
+            .map_err(DocumentTableSearchError::Rerank)
+    }
+}
+
+/// Search the table for the nearest chunks to the embedding, or score chunks with MaxSim if `max_sim` is set.
+async fn search_table<Conn, Doc, F, M>(
+    table: &EmbeddingIndexedTable<Conn, Doc>,
+    embedding: &Embedding,
+    results: usize,
+    max_sim: bool,
+    filter: Option<F>,
+) -> Result<Vec<EmbeddingIndexedTableSearchResult<Doc>>, EmbeddedIndexedTableError>
+where
+    Conn: Connection,
+    Doc: DeserializeOwned + Send + Sync,
+    F: IntoEmbeddingIndexedTableSearchFilter<Conn, Doc, M>,
+{
+    if max_sim {
+        let query = table
+            .search_max_sim(std::slice::from_ref(embedding))
+            .with_results(results);
+        if let Some(filter) = filter {
+            query.with_filter(filter).run().await
+        } else {
+            query.run().await
         }
-        if let Some(filter) = self.filter {
-            let query = query.with_filter(filter);
-            Ok(query.run().await?)
+    } else {
+        let query = table.search(embedding).with_results(results);
+        if let Some(filter) = filter {
+            query.with_filter(filter).run().await
         } else {
-            Ok(query.run().await?)
+            query.run().await
         }
     }
 }
@@ -338,11 +603,15 @@ impl<
         F: IntoEmbeddingIndexedTableSearchFilter<Conn, Doc, M> + Send + Sync + 'a,
         Chkr: Chunker + Send + Sync + 'a,
         M: Send + 'a,
-    > IntoFuture for DocumentTableSearchBuilder<'a, Conn, Doc, Model, Chkr, E, F, M>
+        Rr: SearchReranker<Doc> + 'a,
+        Qe: SearchQueryExpander + 'a,
+    > IntoFuture for DocumentTableSearchBuilder<'a, Conn, Doc, Model, Chkr, E, F, M, Rr, Qe>
 {
     type IntoFuture = Pin<Box<dyn Future<Output = Self::Output> + Send + 'a>>;
-    type Output =
-        Result<Vec<EmbeddingIndexedTableSearchResult<Doc>>, DocumentTableSearchError<Model::Error>>;
+    type Output = Result<
+        Vec<EmbeddingIndexedTableSearchResult<Doc>>,
+        DocumentTableSearchError<Model::Error, Rr::Error, Qe::Error>,
+    >;
 
     fn into_future(self) -> Self::IntoFuture {
         Box::pin(self.run())
@@ -358,13 +627,15 @@ impl<
         F: IntoEmbeddingIndexedTableSearchFilter<Conn, Doc, M>,
         Chkr: Chunker,
         M,
-    > DocumentTableSearchBuilder<'a, Conn, Doc, Model, Chkr, E, F, M>
+        Rr,
+        Qe,
+    > DocumentTableSearchBuilder<'a, Conn, Doc, Model, Chkr, E, F, M, Rr, Qe>
 {
     /// Set a filter to apply to the results. Only vectors that pass the filter will be returned.
     pub fn with_filter<Marker, F2>(
         self,
         filter: F2,
-    ) -> DocumentTableSearchBuilder<'a, Conn, Doc, Model, Chkr, E, F2, Marker>
+    ) -> DocumentTableSearchBuilder<'a, Conn, Doc, Model, Chkr, E, F2, Marker, Rr, Qe>
     where
         F2: IntoEmbeddingIndexedTableSearchFilter<Conn, Doc, Marker> + Send + Sync + 'static,
     {
@@ -373,6 +644,171 @@ impl<
             embedding: self.embedding,
             results: self.results,
             filter: Some(filter),
+            max_sim: self.max_sim,
+            reranker: self.reranker,
+            expander: self.expander,
+            phantom: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<
+        'a,
+        Conn: Connection,
+        Doc: DeserializeOwned,
+        Model: Embedder,
+        E: IntoEmbedding + ToString,
+        F: IntoEmbeddingIndexedTableSearchFilter<Conn, Doc, M>,
+        Chkr: Chunker,
+        M,
+        Qe,
+    > DocumentTableSearchBuilder<'a, Conn, Doc, Model, Chkr, E, F, M, (), Qe>
+{
+    /// Rerank the results of the search with a [`Reranker`] like a cross-encoder.
+    ///
+    /// The search fetches more candidates than the number of results from the vector search, then the reranker scores each candidate against the query and the most relevant candidates are returned. Rerankers are slower than a vector search, but they are often much more accurate.
+    ///
+    /// # Example
+    /// ```rust, no_run
+    /// use kalosm::language::*;
+    /// use surrealdb::{engine::local::SurrealKv, Surreal};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let db = Surreal::new::<SurrealKv>("./db/temp.db").await.unwrap();
+    ///     db.use_ns("rag").use_db("rag").await.unwrap();
+    ///     let document_table = db
+    ///         .document_table_builder("documents")
+    ///         .at("./db/embeddings.db")
+    ///         .build::<Document>()
+    ///         .await
+    ///         .unwrap();
+    ///     let reranker = TeiReranker::builder()
+    ///         .with_client(TeiClient::new().with_base_url("http://localhost:8080"))
+    ///         .build();
+    ///
+    ///     let results = document_table
+    ///         .search("What is the capital of France?")
+    ///         .with_reranker(&reranker)
+    ///         .with_candidates(50)
+    ///         .with_results(5)
+    ///         .await
+    ///         .unwrap();
+    ///     for result in results {
+    ///         println!("{}", result.text());
+    ///     }
+    /// }
+    /// ```
+    pub fn with_reranker<R: Reranker>(
+        self,
+        reranker: &'a R,
+    ) -> DocumentTableSearchBuilder<'a, Conn, Doc, Model, Chkr, E, F, M, SearchReranking<'a, R>, Qe>
+    {
+        let query = self.embedding.to_string();
+        DocumentTableSearchBuilder {
+            table: self.table,
+            embedding: self.embedding,
+            results: self.results,
+            filter: self.filter,
+            max_sim: self.max_sim,
+            reranker: SearchReranking {
+                reranker,
+                query,
+                candidates: 50,
+            },
+            expander: self.expander,
+            phantom: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<
+        Conn: Connection,
+        Doc: DeserializeOwned,
+        Model: Embedder,
+        E: IntoEmbedding,
+        F: IntoEmbeddingIndexedTableSearchFilter<Conn, Doc, M>,
+        Chkr: Chunker,
+        M,
+        R,
+        Qe,
+    > DocumentTableSearchBuilder<'_, Conn, Doc, Model, Chkr, E, F, M, SearchReranking<'_, R>, Qe>
+{
+    /// Set the number of candidates to fetch from the vector search before reranking. If this is less than the number of results, the number of results is used instead. Defaults to 50.
+    pub fn with_candidates(mut self, candidates: usize) -> Self {
+        self.reranker.candidates = candidates;
+        self
+    }
+}
+
+impl<
+        'a,
+        Conn: Connection,
+        Doc: DeserializeOwned,
+        Model: Embedder,
+        E: IntoEmbedding + ToString,
+        F: IntoEmbeddingIndexedTableSearchFilter<Conn, Doc, M>,
+        Chkr: Chunker,
+        M,
+        Rr,
+    > DocumentTableSearchBuilder<'a, Conn, Doc, Model, Chkr, E, F, M, Rr>
+{
+    /// Expand the query with a [`QueryExpansion`] before the vector search. The table is searched for each expanded query and the results are merged with reciprocal rank fusion, so the distance of each result is the negated fusion score.
+    ///
+    /// # Example
+    /// ```rust, no_run
+    /// use kalosm::language::*;
+    /// use surrealdb::{engine::local::SurrealKv, Surreal};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let db = Surreal::new::<SurrealKv>("./db/temp.db").await.unwrap();
+    ///     db.use_ns("rag").use_db("rag").await.unwrap();
+    ///     let document_table = db
+    ///         .document_table_builder("documents")
+    ///         .at("./db/embeddings.db")
+    ///         .build::<Document>()
+    ///         .await
+    ///         .unwrap();
+    ///     let expansion = QueryExpansion::new(Llama::new().await.unwrap())
+    ///         .with_rewrite()
+    ///         .with_hypothetical_document();
+    ///
+    ///     let results = document_table
+    ///         .search("is floneum safe")
+    ///         .with_query_expansion(&expansion)
+    ///         .with_results(5)
+    ///         .await
+    ///         .unwrap();
+    ///     for result in results {
+    ///         println!("{}", result.text());
+    ///     }
+    /// }
+    /// ```
+    pub fn with_query_expansion<QM>(
+        self,
+        expansion: &'a QueryExpansion<QM>,
+    ) -> DocumentTableSearchBuilder<
+        'a,
+        Conn,
+        Doc,
+        Model,
+        Chkr,
+        E,
+        F,
+        M,
+        Rr,
+        SearchQueryExpansion<'a, QM>,
+    > {
+        let query = self.embedding.to_string();
+        DocumentTableSearchBuilder {
+            table: self.table,
+            embedding: self.embedding,
+            results: self.results,
+            filter: self.filter,
+            max_sim: self.max_sim,
+            reranker: self.reranker,
+            expander: SearchQueryExpansion { expansion, query },
             phantom: std::marker::PhantomData,
         }
     }
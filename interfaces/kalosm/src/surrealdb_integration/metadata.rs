@@ -0,0 +1,300 @@
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+/// A value in the [`Metadata`] of a record in an [`EmbeddingIndexedTable`](crate::EmbeddingIndexedTable).
+///
+/// Dates can be stored as unix timestamps or as RFC 3339 strings in UTC. Both compare in chronological order.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum MetadataValue {
+    /// A boolean value.
+    Bool(bool),
+    /// A number.
+    Number(f64),
+    /// A string.
+    String(String),
+    /// A list of values, like a set of tags.
+    List(Vec<MetadataValue>),
+}
+
+impl MetadataValue {
+    /// Compare two values of the same type. Values of different types are not comparable.
+    fn compare(&self, other: &Self) -> Option<Ordering> {
+        match (self, other) {
+            (Self::Bool(a), Self::Bool(b)) => a.partial_cmp(b),
+            (Self::Number(a), Self::Number(b)) => a.partial_cmp(b),
+            (Self::String(a), Self::String(b)) => a.partial_cmp(b),
+            _ => None,
+        }
+    }
+}
+
+impl From<bool> for MetadataValue {
+    fn from(value: bool) -> Self {
+        Self::Bool(value)
+    }
+}
+
+macro_rules! impl_from_number {
+    ($($ty:ty),*) => {
+        $(
+            impl From<$ty> for MetadataValue {
+                fn from(value: $ty) -> Self {
+                    Self::Number(value as f64)
+                }
+            }
+        )*
+    };
+}
+
+impl_from_number!(f32, f64, i32, i64, u32, u64, usize);
+
+impl From<String> for MetadataValue {
+    fn from(value: String) -> Self {
+        Self::String(value)
+    }
+}
+
+impl From<&str> for MetadataValue {
+    fn from(value: &str) -> Self {
+        Self::String(value.to_string())
+    }
+}
+
+impl<T: Into<MetadataValue>> From<Vec<T>> for MetadataValue {
+    fn from(value: Vec<T>) -> Self {
+        Self::List(value.into_iter().map(Into::into).collect())
+    }
+}
+
+/// Key/value metadata attached to every chunk of a record in an [`EmbeddingIndexedTable`](crate::EmbeddingIndexedTable). Searches can be filtered by the metadata with a [`MetadataFilter`].
+///
+/// # Example
+/// ```rust
+/// use kalosm::*;
+///
+/// let metadata = Metadata::new()
+///     .with("source", "docs")
+///     .with("published", 1717200000)
+///     .with("tags", vec!["rust", "search"]);
+/// assert_eq!(metadata.get("source"), Some(&MetadataValue::from("docs")));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Metadata(BTreeMap<String, MetadataValue>);
+
+impl Metadata {
+    /// Create a new empty set of metadata.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a key/value pair to the metadata.
+    pub fn with(mut self, key: impl Into<String>, value: impl Into<MetadataValue>) -> Self {
+        self.insert(key, value);
+        self
+    }
+
+    /// Insert a key/value pair into the metadata. Returns the old value if the key was already set.
+    pub fn insert(
+        &mut self,
+        key: impl Into<String>,
+        value: impl Into<MetadataValue>,
+    ) -> Option<MetadataValue> {
+        self.0.insert(key.into(), value.into())
+    }
+
+    /// Get the value for a key.
+    pub fn get(&self, key: &str) -> Option<&MetadataValue> {
+        self.0.get(key)
+    }
+
+    /// Iterate over the key/value pairs in the metadata.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &MetadataValue)> {
+        self.0.iter().map(|(key, value)| (key.as_str(), value))
+    }
+}
+
+impl<K: Into<String>, V: Into<MetadataValue>> FromIterator<(K, V)> for Metadata {
+    fn from_iter<T: IntoIterator<Item = (K, V)>>(iter: T) -> Self {
+        Self(
+            iter.into_iter()
+                .map(|(key, value)| (key.into(), value.into()))
+                .collect(),
+        )
+    }
+}
+
+/// A predicate on the [`Metadata`] of a chunk. Pass this to `with_filter` on a search to only return chunks with matching metadata.
+///
+/// # Example
+/// ```rust
+/// use kalosm::*;
+///
+/// // Only search documents from the docs source that were published in 2024 and tagged with rust
+/// let filter = MetadataFilter::eq("source", "docs")
+///     .and(MetadataFilter::between("published", 1704067200, 1735689600))
+///     .and(MetadataFilter::contains("tags", "rust"));
+///
+/// let metadata = Metadata::new()
+///     .with("source", "docs")
+///     .with("published", 1717200000)
+///     .with("tags", vec!["rust", "search"]);
+/// assert!(filter.matches(&metadata));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub enum MetadataFilter {
+    /// The value for the key is equal to the value.
+    Eq(String, MetadataValue),
+    /// The value for the key is greater than the value.
+    Gt(String, MetadataValue),
+    /// The value for the key is greater than or equal to the value.
+    Gte(String, MetadataValue),
+    /// The value for the key is less than the value.
+    Lt(String, MetadataValue),
+    /// The value for the key is less than or equal to the value.
+    Lte(String, MetadataValue),
+    /// The value for the key is a list that contains the value, or is equal to the value.
+    Contains(String, MetadataValue),
+    /// The key is set.
+    Exists(String),
+    /// Every filter matches.
+    And(Vec<MetadataFilter>),
+    /// Any filter matches.
+    Or(Vec<MetadataFilter>),
+    /// The filter does not match.
+    Not(Box<MetadataFilter>),
+}
+
+impl MetadataFilter {
+    /// Match chunks where the value for the key is equal to the value.
+    pub fn eq(key: impl Into<String>, value: impl Into<MetadataValue>) -> Self {
+        Self::Eq(key.into(), value.into())
+    }
+
+    /// Match chunks where the value for the key is greater than the value.
+    pub fn gt(key: impl Into<String>, value: impl Into<MetadataValue>) -> Self {
+        Self::Gt(key.into(), value.into())
+    }
+
+    /// Match chunks where the value for the key is greater than or equal to the value.
+    pub fn gte(key: impl Into<String>, value: impl Into<MetadataValue>) -> Self {
+        Self::Gte(key.into(), value.into())
+    }
+
+    /// Match chunks where the value for the key is less than the value.
+    pub fn lt(key: impl Into<String>, value: impl Into<MetadataValue>) -> Self {
+        Self::Lt(key.into(), value.into())
+    }
+
+    /// Match chunks where the value for the key is less than or equal to the value.
+    pub fn lte(key: impl Into<String>, value: impl Into<MetadataValue>) -> Self {
+        Self::Lte(key.into(), value.into())
+    }
+
+    /// Match chunks where the value for the key is in the range `start..end`.
+    pub fn between(
+        key: impl Into<String>,
+        start: impl Into<MetadataValue>,
+        end: impl Into<MetadataValue>,
+    ) -> Self {
+        let key = key.into();
+        Self::And(vec![Self::gte(key.clone(), start), Self::lt(key, end)])
+    }
+
+    /// Match chunks where the value for the key is a list that contains the value.
+    pub fn contains(key: impl Into<String>, value: impl Into<MetadataValue>) -> Self {
+        Self::Contains(key.into(), value.into())
+    }
+
+    /// Match chunks where the key is set.
+    pub fn exists(key: impl Into<String>) -> Self {
+        Self::Exists(key.into())
+    }
+
+    /// Match chunks that match both this filter and the other filter.
+    pub fn and(self, other: Self) -> Self {
+        match self {
+            Self::And(mut filters) => {
+                filters.push(other);
+                Self::And(filters)
+            }
+            _ => Self::And(vec![self, other]),
+        }
+    }
+
+    /// Match chunks that match either this filter or the other filter.
+    pub fn or(self, other: Self) -> Self {
+        match self {
+            Self::Or(mut filters) => {
+                filters.push(other);
+                Self::Or(filters)
+            }
+            _ => Self::Or(vec![self, other]),
+        }
+    }
+
+    /// Check if the metadata of a chunk matches the filter.
+    pub fn matches(&self, metadata: &Metadata) -> bool {
+        let compare = |key: &str, value: &MetadataValue| {
+            metadata
+                .get(key)
+                .and_then(|existing| existing.compare(value))
+        };
+        match self {
+            Self::Eq(key, value) => metadata.get(key) == Some(value),
+            Self::Gt(key, value) => compare(key, value) == Some(Ordering::Greater),
+            Self::Gte(key, value) => {
+                matches!(
+                    compare(key, value),
+                    Some(Ordering::Greater | Ordering::Equal)
+                )
+            }
+            Self::Lt(key, value) => compare(key, value) == Some(Ordering::Less),
+            Self::Lte(key, value) => {
+                matches!(compare(key, value), Some(Ordering::Less | Ordering::Equal))
+            }
+            Self::Contains(key, value) => match metadata.get(key) {
+                Some(MetadataValue::List(items)) => items.contains(value),
+                Some(existing) => existing == value,
+                None => false,
+            },
+            Self::Exists(key) => metadata.get(key).is_some(),
+            Self::And(filters) => filters.iter().all(|filter| filter.matches(metadata)),
+            Self::Or(filters) => filters.iter().any(|filter| filter.matches(metadata)),
+            Self::Not(filter) => !filter.matches(metadata),
+        }
+    }
+}
+
+impl std::ops::Not for MetadataFilter {
+    type Output = Self;
+
+    fn not(self) -> Self::Output {
+        Self::Not(Box::new(self))
+    }
+}
+
+#[test]
+fn test_metadata_filter() {
+    let metadata = Metadata::new()
+        .with("source", "docs")
+        .with("published", "2024-06-01T00:00:00Z")
+        .with("tags", vec!["rust", "search"]);
+
+    assert!(MetadataFilter::eq("source", "docs").matches(&metadata));
+    assert!(!MetadataFilter::eq("source", "blog").matches(&metadata));
+    assert!(
+        MetadataFilter::between("published", "2024-01-01T00:00:00Z", "2025-01-01T00:00:00Z")
+            .matches(&metadata)
+    );
+    assert!(!MetadataFilter::gt("published", "2024-07-01T00:00:00Z").matches(&metadata));
+    // Values of different types never compare
+    assert!(!MetadataFilter::gt("published", 0).matches(&metadata));
+    assert!(MetadataFilter::contains("tags", "rust").matches(&metadata));
+    assert!(!MetadataFilter::contains("tags", "python").matches(&metadata));
+    assert!((!MetadataFilter::exists("tenant")).matches(&metadata));
+    assert!(MetadataFilter::eq("source", "blog")
+        .or(MetadataFilter::contains("tags", "search"))
+        .matches(&metadata));
+}
@@ -6,8 +6,20 @@ use std::ops::Range;
 use std::pin::Pin;
 use surrealdb::{Connection, RecordId, RecordIdKey, Surreal};
 
+#[cfg(feature = "language")]
+pub(crate) mod chat_store;
+#[cfg(feature = "language")]
+pub(crate) mod citation;
+#[cfg(feature = "language")]
+pub(crate) mod document_sync;
 #[cfg(feature = "language")]
 pub(crate) mod document_table;
+#[cfg(feature = "language")]
+pub(crate) mod packing;
+#[cfg(feature = "language")]
+pub(crate) mod query_expansion;
+mod metadata;
+pub use metadata::*;
 
 /// An error that can occur when adding or searching for an embedding to the embedding indexed table.
 #[derive(Debug, thiserror::Error)]
@@ -48,6 +60,15 @@ impl From<VectorDbError> for EmbeddedIndexedTableError {
 pub struct DocumentLink {
     document_id: RecordIdKey,
     byte_range: std::ops::Range<usize>,
+    #[serde(default)]
+    metadata: Metadata,
+}
+
+/// The metadata of a [`DocumentLink`] with the embedding id it is stored under.
+#[derive(Deserialize)]
+struct LinkMetadata {
+    embedding_id: i64,
+    metadata: Option<Metadata>,
 }
 
 /// An object with associated embedding ids.
@@ -78,6 +99,11 @@ impl<C: Connection, R> EmbeddingIndexedTable<C, R> {
         format!("{}-links", &self.table)
     }
 
+    /// Get the name of the table that tracks the content of documents synced with `DocumentTable::sync`.
+    pub fn table_sources(&self) -> String {
+        format!("{}-sources", &self.table)
+    }
+
     /// Get the raw vector database.
     pub fn vector_db(&self) -> &VectorDB {
         &self.vector_db
@@ -94,6 +120,8 @@ impl<C: Connection, R> EmbeddingIndexedTable<C, R> {
         R: DeserializeOwned,
     {
         let _: Vec<DocumentLink> = self.db.delete(self.table_links()).await?;
+        #[cfg(feature = "language")]
+        let _: Vec<document_sync::DocumentSource> = self.db.delete(self.table_sources()).await?;
         let embeddings: Vec<ObjectWithEmbeddingIds<R>> = self.db.delete(&self.table).await?;
 
         let mut documents = Vec::with_capacity(embeddings.len());
@@ -124,6 +152,20 @@ impl<C: Connection, R> EmbeddingIndexedTable<C, R> {
         chunks: impl IntoIterator<Item = Chunk>,
         value: R,
     ) -> Result<RecordIdKey, EmbeddedIndexedTableError>
+    where
+        R: Serialize + DeserializeOwned + 'static,
+    {
+        self.insert_with_metadata(chunks, value, Metadata::new())
+            .await
+    }
+
+    /// Insert a new record into the table with the given embedding and attach the metadata to each chunk. Searches can be filtered by the metadata with a [`MetadataFilter`].
+    pub async fn insert_with_metadata(
+        &self,
+        chunks: impl IntoIterator<Item = Chunk>,
+        value: R,
+        metadata: Metadata,
+    ) -> Result<RecordIdKey, EmbeddedIndexedTableError>
     where
         R: Serialize + DeserializeOwned + 'static,
     {
@@ -144,6 +186,7 @@ impl<C: Connection, R> EmbeddingIndexedTable<C, R> {
                     .content(DocumentLink {
                         document_id: id.clone(),
                         byte_range,
+                        metadata: metadata.clone(),
                     })
                     .await?;
             }
@@ -259,6 +302,23 @@ impl<C: Connection, R> EmbeddingIndexedTable<C, R> {
             phantom: std::marker::PhantomData,
         }
     }
+
+    /// Search for records with MaxSim late interaction. Each chunk is scored by the sum of the best similarity of each query vector with any of the vectors stored for that chunk.
+    ///
+    /// This works best with tables where each chunk has several vectors, like tables built with a [`MultiVectorChunker`]. The query can be a single vector or one vector for each part of the query.
+    pub fn search_max_sim<'a>(
+        &'a self,
+        query: &'a [Embedding],
+    ) -> EmbeddingIndexedTableMaxSimSearchBuilder<'a, C, R> {
+        EmbeddingIndexedTableMaxSimSearchBuilder {
+            table: self,
+            query,
+            results: None,
+            candidates: 50,
+            filter: None,
+            phantom: std::marker::PhantomData,
+        }
+    }
 }
 
 /// A trait for anything that can be used to filter the results of an embedded table search.
@@ -279,6 +339,29 @@ impl<C: Connection, R: Send + Sync> IntoEmbeddingIndexedTableSearchFilter<C, R,
     }
 }
 
+impl<C: Connection, R: Send + Sync> IntoEmbeddingIndexedTableSearchFilter<C, R, ()>
+    for MetadataFilter
+{
+    async fn into_embedding_indexed_table_search_filter(
+        self,
+        table: &EmbeddingIndexedTable<C, R>,
+    ) -> Result<Candidates, EmbeddedIndexedTableError> {
+        let links: Vec<LinkMetadata> = table
+            .db
+            .query("SELECT record::id(id) AS embedding_id, metadata FROM type::table($table)")
+            .bind(("table", table.table_links()))
+            .await?
+            .take(0)?;
+        let mut candidates = Candidates::new();
+        for link in links {
+            if self.matches(&link.metadata.unwrap_or_default()) {
+                candidates.insert(link.embedding_id as u32);
+            }
+        }
+        Ok(candidates)
+    }
+}
+
 /// A marker type that allows kalosm to specialize the [`IntoEmbeddingIndexedTableSearchFilter`] trait for iterators.
 pub struct IteratorMarker;
 
@@ -404,6 +487,148 @@ impl<'a, C: Connection, R: DeserializeOwned> EmbeddingIndexedTableSearchBuilder<
     }
 }
 
+/// A builder for searching an [`EmbeddingIndexedTable`] with MaxSim late interaction. This is created with [`EmbeddingIndexedTable::search_max_sim`].
+pub struct EmbeddingIndexedTableMaxSimSearchBuilder<'a, C: Connection, R, F = Candidates, M = ()> {
+    table: &'a EmbeddingIndexedTable<C, R>,
+    query: &'a [Embedding],
+    results: Option<usize>,
+    candidates: usize,
+    filter: Option<F>,
+    phantom: std::marker::PhantomData<M>,
+}
+
+impl<C: Connection, R: DeserializeOwned, F: IntoEmbeddingIndexedTableSearchFilter<C, R, M>, M>
+    EmbeddingIndexedTableMaxSimSearchBuilder<'_, C, R, F, M>
+{
+    /// Set the number of results to return. Defaults to 10.
+    pub fn with_results(mut self, results: usize) -> Self {
+        self.results = Some(results);
+        self
+    }
+
+    /// Set the number of nearest vectors to fetch for each query vector. The chunks those vectors belong to are scored with MaxSim. If this is less than the number of results, the number of results is used instead. Defaults to 50.
+    pub fn with_candidates(mut self, candidates: usize) -> Self {
+        self.candidates = candidates;
+        self
+    }
+
+    /// Run the search and return the results. Each chunk is returned at most once and the distance of each result is the negated MaxSim score.
+    pub async fn run(
+        self,
+    ) -> Result<Vec<EmbeddingIndexedTableSearchResult<R>>, EmbeddedIndexedTableError> {
+        let results = self.results.unwrap_or(10);
+        let filter = match self.filter {
+            Some(filter) => Some(
+                filter
+                    .into_embedding_indexed_table_search_filter(self.table)
+                    .await?,
+            ),
+            None => None,
+        };
+
+        // Find the chunks with any vector close to a query vector
+        let mut seen = Candidates::new();
+        let mut records = Vec::new();
+        for query in self.query {
+            let mut search = self
+                .table
+                .vector_db
+                .search(query)
+                .with_results(self.candidates.max(results));
+            if let Some(filter) = &filter {
+                search = search.with_filter(filter.clone());
+            }
+            for id in search.run()? {
+                if seen.contains(id.value.0) {
+                    continue;
+                }
+                let link = self
+                    .table
+                    .db
+                    .select::<Option<DocumentLink>>(RecordId::from_table_key(
+                        self.table.table_links(),
+                        id.value.0 as i64,
+                    ))
+                    .await?
+                    .ok_or(EmbeddedIndexedTableError::RecordNotFound)?;
+                let record = self
+                    .table
+                    .db
+                    .select::<Option<ObjectWithEmbeddingIds<R>>>(RecordId::from_table_key(
+                        self.table.table.clone(),
+                        link.document_id.clone(),
+                    ))
+                    .await?
+                    .ok_or(EmbeddedIndexedTableError::RecordNotFound)?;
+                let embedding_ids = record
+                    .chunks
+                    .iter()
+                    .find(|(byte_range, ids)| {
+                        *byte_range == link.byte_range && ids.contains(&id.value)
+                    })
+                    .map(|(_, ids)| ids.as_slice())
+                    .unwrap_or(std::slice::from_ref(&id.value));
+
+                // Score the whole chunk with every vector stored for it
+                let mut embeddings = Vec::with_capacity(embedding_ids.len());
+                for embedding_id in embedding_ids {
+                    seen.insert(embedding_id.0);
+                    embeddings.push(self.table.vector_db.get_embedding(*embedding_id)?);
+                }
+                let score = max_sim(self.query, &embeddings);
+
+                records.push(EmbeddingIndexedTableSearchResult {
+                    distance: -score,
+                    id: id.value,
+                    record_id: link.document_id,
+                    byte_range: link.byte_range,
+                    record: record.object,
+                });
+            }
+        }
+
+        records.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+        records.truncate(results);
+        Ok(records)
+    }
+}
+
+impl<
+        'a,
+        C: Connection + 'a,
+        R: DeserializeOwned + Send + Sync + 'a,
+        F: IntoEmbeddingIndexedTableSearchFilter<C, R, M> + Send + 'a,
+        M: Send + 'a,
+    > IntoFuture for EmbeddingIndexedTableMaxSimSearchBuilder<'a, C, R, F, M>
+{
+    type IntoFuture = Pin<Box<dyn Future<Output = Self::Output> + Send + 'a>>;
+    type Output = Result<Vec<EmbeddingIndexedTableSearchResult<R>>, EmbeddedIndexedTableError>;
+
+    fn into_future(self) -> Self::IntoFuture {
+        Box::pin(self.run())
+    }
+}
+
+impl<'a, C: Connection, R: DeserializeOwned> EmbeddingIndexedTableMaxSimSearchBuilder<'a, C, R> {
+    /// Set a filter to apply to the results. Only vectors that pass the filter will be considered.
+    pub fn with_filter<Marker, F>(
+        self,
+        filter: F,
+    ) -> EmbeddingIndexedTableMaxSimSearchBuilder<'a, C, R, F, Marker>
+    where
+        F: IntoEmbeddingIndexedTableSearchFilter<C, R, Marker>,
+    {
+        EmbeddingIndexedTableMaxSimSearchBuilder {
+            table: self.table,
+            query: self.query,
+            results: self.results,
+            candidates: self.candidates,
+            filter: Some(filter),
+            phantom: std::marker::PhantomData,
+        }
+    }
+}
+
 /// The result of a search in an embedding indexed table.
 #[derive(Debug, Clone)]
 pub struct EmbeddingIndexedTableSearchResult<R> {
@@ -0,0 +1,219 @@
+use std::ops::Range;
+use std::sync::Arc;
+
+use kalosm_language::prelude::*;
+use surrealdb::RecordIdKey;
+use tokenizers::Tokenizer;
+
+use super::citation::{Citation, CitedSources};
+use super::EmbeddingIndexedTableSearchResult;
+
+/// The order of the chunks in context packed by a [`ContextPacker`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ContextOrder {
+    /// The most relevant chunk comes first.
+    #[default]
+    Relevance,
+    /// Chunks from the most recently updated (or created) documents come first. Chunks from documents without a date come last.
+    Recency,
+}
+
+/// Packs search results into a token budget for the context window of a model.
+///
+/// The packer merges overlapping chunks from the same document, then adds chunks from the most to the least relevant as long as they fit in the budget. Chunk sizes are counted with the tokenizer of the model that reads the context, so the context never overflows the window.
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm::language::*;
+/// use surrealdb::{engine::local::SurrealKv, Surreal};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let db = Surreal::new::<SurrealKv>("./db/temp.db").await.unwrap();
+///     db.use_ns("rag").use_db("rag").await.unwrap();
+///     let document_table = db
+///         .document_table_builder("documents")
+///         .at("./db/embeddings.db")
+///         .build::<Document>()
+///         .await
+///         .unwrap();
+///     let model = Llama::new_chat().await.unwrap();
+///     let mut chat = model.chat();
+///
+///     let question = prompt_input("Question: ").unwrap();
+///     let results = document_table.search(&question).with_results(50).await.unwrap();
+///     // Keep the context under 2048 tokens
+///     let sources = ContextPacker::new(model.tokenizer().clone(), 2048)
+///         .with_order(ContextOrder::Recency)
+///         .pack(&results)
+///         .unwrap();
+///     let answer = chat(&sources.prompt(&question)).await.unwrap();
+///     println!("{answer}");
+/// }
+/// ```
+#[derive(Clone)]
+pub struct ContextPacker {
+    tokenizer: Arc<Tokenizer>,
+    budget: usize,
+    order: ContextOrder,
+}
+
+impl ContextPacker {
+    /// Create a new packer that fits context into `budget` tokens of the tokenizer.
+    pub fn new(tokenizer: impl Into<Arc<Tokenizer>>, budget: usize) -> Self {
+        Self {
+            tokenizer: tokenizer.into(),
+            budget,
+            order: ContextOrder::default(),
+        }
+    }
+
+    /// Set the order of the chunks in the packed context. (default: [`ContextOrder::Relevance`])
+    pub fn with_order(mut self, order: ContextOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// Count the number of tokens in a string.
+    pub fn count_tokens(&self, text: &str) -> Result<usize, tokenizers::Error> {
+        Ok(self.tokenizer.encode(text, false)?.len())
+    }
+
+    /// Pack the results of a search into numbered sources that fit in the token budget.
+    pub fn pack<'a, R: AsRef<Document> + 'a>(
+        &self,
+        results: impl IntoIterator<Item = &'a EmbeddingIndexedTableSearchResult<R>>,
+    ) -> Result<CitedSources, tokenizers::Error> {
+        let mut spans = merge_overlapping(results);
+        spans.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+
+        // Add the most relevant chunks first, skipping chunks that don't fit in the remaining budget
+        let mut remaining = self.budget;
+        let mut packed = Vec::new();
+        for span in spans {
+            let citation = Citation::new(
+                0,
+                span.record_id,
+                span.document,
+                span.byte_range,
+                span.distance,
+            );
+            let tokens = self.count_tokens(&citation.format())?;
+            if tokens <= remaining {
+                remaining -= tokens;
+                packed.push((span.document, citation));
+            }
+        }
+
+        if self.order == ContextOrder::Recency {
+            // The sort is stable, so chunks from the same date stay in order of relevance
+            packed.sort_by_key(|(document, _)| {
+                std::cmp::Reverse(document.updated_at().or(document.created_at()))
+            });
+        }
+
+        Ok(CitedSources::from_citations(
+            packed
+                .into_iter()
+                .enumerate()
+                .map(|(i, (_, citation))| Citation {
+                    index: i + 1,
+                    ..citation
+                })
+                .collect(),
+        ))
+    }
+}
+
+/// A span of a document covered by one or more search results.
+struct Span<'a> {
+    record_id: RecordIdKey,
+    document: &'a Document,
+    byte_range: Range<usize>,
+    distance: f32,
+}
+
+/// Merge results from the same record with overlapping byte ranges into a single span with the best distance.
+fn merge_overlapping<'a, R: AsRef<Document> + 'a>(
+    results: impl IntoIterator<Item = &'a EmbeddingIndexedTableSearchResult<R>>,
+) -> Vec<Span<'a>> {
+    let mut spans: Vec<Span<'a>> = Vec::new();
+    for result in results {
+        let mut span = Span {
+            record_id: result.record_id.clone(),
+            document: result.record.as_ref(),
+            byte_range: result.byte_range.clone(),
+            distance: result.distance,
+        };
+        // Merging can make a span overlap spans it didn't overlap before, so keep merging until nothing overlaps
+        while let Some(i) = spans.iter().position(|other| {
+            other.record_id == span.record_id
+                && other.byte_range.start < span.byte_range.end
+                && span.byte_range.start < other.byte_range.end
+        }) {
+            let other = spans.swap_remove(i);
+            span.byte_range = other.byte_range.start.min(span.byte_range.start)
+                ..other.byte_range.end.max(span.byte_range.end);
+            span.distance = other.distance.min(span.distance);
+        }
+        spans.push(span);
+    }
+    spans
+}
+
+#[test]
+fn test_pack_context() {
+    use tokenizers::models::wordlevel::WordLevel;
+    use tokenizers::pre_tokenizers::whitespace::Whitespace;
+
+    let model = WordLevel::builder()
+        .vocab([("[UNK]".to_string(), 0)].into_iter().collect())
+        .unk_token("[UNK]".to_string())
+        .build()
+        .unwrap();
+    let mut tokenizer = Tokenizer::new(model);
+    tokenizer.with_pre_tokenizer(Some(Whitespace {}));
+
+    let mut old = Document::from_parts("Old", "one two three four five six seven eight");
+    old.set_created_at("2020-01-01T00:00:00Z".parse().unwrap());
+    let mut new = Document::from_parts("New", "alpha beta gamma delta epsilon zeta eta theta");
+    new.set_created_at("2024-01-01T00:00:00Z".parse().unwrap());
+    let result = |record: &Document, id: i64, byte_range: Range<usize>, distance: f32| {
+        EmbeddingIndexedTableSearchResult {
+            distance,
+            id: EmbeddingId(0),
+            record_id: RecordIdKey::from(id),
+            byte_range,
+            record: record.clone(),
+        }
+    };
+    let results = [
+        // "one two three" and "three four" overlap and are merged
+        result(&old, 1, 0..13, 0.1),
+        result(&old, 1, 8..18, 0.3),
+        result(&new, 2, 0..10, 0.2),
+        // This chunk doesn't fit in the budget once the others are packed
+        result(&new, 2, 11..45, 0.4),
+    ];
+
+    // Each source is formatted as "[n] Title" and the text of the chunk
+    let packer = ContextPacker::new(tokenizer, 14);
+    let sources = packer.pack(&results).unwrap();
+    let texts: Vec<_> = sources
+        .citations()
+        .iter()
+        .map(|citation| (citation.index, citation.text.as_str()))
+        .collect();
+    assert_eq!(texts, [(1, "one two three four"), (2, "alpha beta")]);
+
+    let sources = packer
+        .with_order(ContextOrder::Recency)
+        .pack(&results)
+        .unwrap();
+    let texts: Vec<_> = sources
+        .citations()
+        .iter()
+        .map(|citation| (citation.index, citation.text.as_str()))
+        .collect();
+    assert_eq!(texts, [(1, "alpha beta"), (2, "one two three four")]);
+}
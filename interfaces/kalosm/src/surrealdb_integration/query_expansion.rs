@@ -0,0 +1,317 @@
+use std::convert::Infallible;
+use std::future::Future;
+
+use kalosm_language::prelude::*;
+
+use super::EmbeddingIndexedTableSearchResult;
+
+/// A query to search for. This is created by a [`QueryExpansion`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum SearchQuery {
+    /// A search query. This is embedded as a query.
+    Query(String),
+    /// A hypothetical document that answers the query. This is embedded as a document, so it lands close to real documents that answer the query.
+    Document(String),
+}
+
+impl SearchQuery {
+    /// Get the text of the query.
+    pub fn text(&self) -> &str {
+        match self {
+            Self::Query(text) | Self::Document(text) => text,
+        }
+    }
+
+    /// Embed the query with the embedding model.
+    pub async fn embed<E: Embedder>(&self, embedder: &E) -> Result<Embedding, E::Error> {
+        match self {
+            Self::Query(text) => embedder.embed_query(text).await,
+            Self::Document(text) => embedder.embed(text).await,
+        }
+    }
+}
+
+/// Transforms the query of a search with a language model before the vector search runs.
+///
+/// Questions are often phrased very differently from the documents that answer them. A query expansion can rewrite the query into a clearer search query, generate alternative phrasings of the query (multi-query expansion), and write a hypothetical document that answers the query (HyDE). Each query is searched separately and the results are merged with reciprocal rank fusion.
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm::language::*;
+/// use surrealdb::{engine::local::SurrealKv, Surreal};
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let db = Surreal::new::<SurrealKv>("./db/temp.db").await.unwrap();
+///     db.use_ns("rag").use_db("rag").await.unwrap();
+///     let document_table = db
+///         .document_table_builder("documents")
+///         .at("./db/embeddings.db")
+///         .build::<Document>()
+///         .await
+///         .unwrap();
+///     let expansion = QueryExpansion::new(Llama::new().await.unwrap())
+///         .with_alternatives(3)
+///         .with_hypothetical_document();
+///
+///     let results = document_table
+///         .search("how do I keep my plugins from reading files?")
+///         .with_query_expansion(&expansion)
+///         .with_results(5)
+///         .await
+///         .unwrap();
+///     for result in results {
+///         println!("{}", result.text());
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct QueryExpansion<M> {
+    model: M,
+    rewrite: bool,
+    alternatives: usize,
+    hypothetical_document: bool,
+    rrf_k: f32,
+}
+
+impl<M> QueryExpansion<M> {
+    /// Create a new query expansion that generates text with the model. By default the query is searched as is.
+    pub fn new(model: M) -> Self {
+        Self {
+            model,
+            rewrite: false,
+            alternatives: 0,
+            hypothetical_document: false,
+            rrf_k: 60.0,
+        }
+    }
+
+    /// Rewrite the query into a specific, self-contained search query before searching. The rewritten query replaces the original query.
+    pub fn with_rewrite(mut self) -> Self {
+        self.rewrite = true;
+        self
+    }
+
+    /// Generate alternative phrasings of the query and search for each of them. (default: 0)
+    pub fn with_alternatives(mut self, alternatives: usize) -> Self {
+        self.alternatives = alternatives;
+        self
+    }
+
+    /// Write a hypothetical document that answers the query and search for documents close to it (HyDE).
+    pub fn with_hypothetical_document(mut self) -> Self {
+        self.hypothetical_document = true;
+        self
+    }
+
+    /// Set the constant used to merge the results of each query with reciprocal rank fusion. Larger values give lower ranked results more weight. (default: 60)
+    pub fn with_rrf_k(mut self, k: f32) -> Self {
+        self.rrf_k = k;
+        self
+    }
+
+    /// Get the constant used to merge the results of each query with reciprocal rank fusion.
+    pub fn rrf_k(&self) -> f32 {
+        self.rrf_k
+    }
+}
+
+impl<M> QueryExpansion<M>
+where
+    M: TextCompletionModel + Clone + Send + Sync + Unpin + 'static,
+    M::Session: Clone + Send + Sync + Unpin + 'static,
+{
+    /// Expand the query into the queries to search for.
+    pub async fn expand(&self, query: &str) -> Result<Vec<SearchQuery>, M::Error> {
+        let query = if self.rewrite {
+            let rewritten = self
+                .generate(
+                    format!("Rewrite the search query below so it is specific and self-contained. Respond with only the rewritten query.\n\nQuery: {query}\nRewritten query:"),
+                    "\n",
+                    64,
+                )
+                .await?;
+            if rewritten.is_empty() {
+                query.to_string()
+            } else {
+                rewritten
+            }
+        } else {
+            query.to_string()
+        };
+
+        let mut queries = vec![SearchQuery::Query(query.clone())];
+        if self.alternatives > 0 {
+            let alternatives = self
+                .generate(
+                    format!("Write {} different search queries that would find documents that answer the question below. Write one query on each line.\n\nQuestion: {query}\nQueries:\n", self.alternatives),
+                    "\n\n",
+                    64 * self.alternatives as u32,
+                )
+                .await?;
+            queries.extend(
+                alternatives
+                    .lines()
+                    .map(strip_list_marker)
+                    .filter(|line| !line.is_empty() && *line != query)
+                    .take(self.alternatives)
+                    .map(|line| SearchQuery::Query(line.to_string())),
+            );
+        }
+        if self.hypothetical_document {
+            let document = self
+                .generate(
+                    format!("Write a short passage that answers the question below.\n\nQuestion: {query}\nPassage:"),
+                    "\n\n",
+                    256,
+                )
+                .await?;
+            if !document.is_empty() {
+                queries.push(SearchQuery::Document(document));
+            }
+        }
+        Ok(queries)
+    }
+
+    async fn generate(
+        &self,
+        prompt: String,
+        stop_on: &str,
+        max_length: u32,
+    ) -> Result<String, M::Error> {
+        let text = self
+            .model
+            .complete(prompt)
+            .with_sampler(
+                GenerationParameters::default()
+                    .with_max_length(max_length)
+                    .with_stop_on(stop_on.to_string()),
+            )
+            .await?;
+        Ok(text.trim_end_matches(stop_on).trim().to_string())
+    }
+}
+
+/// Remove a list marker like `1.`, `2)` or `-` from the start of a generated line.
+fn strip_list_marker(line: &str) -> &str {
+    let line = line.trim();
+    let without_number = line.trim_start_matches(|c: char| c.is_ascii_digit());
+    let stripped = if without_number.len() < line.len() {
+        without_number.strip_prefix(['.', ')'])
+    } else {
+        line.strip_prefix(['-', '*'])
+    };
+    stripped.map_or(line, str::trim)
+}
+
+/// A stage that expands the query of a [`DocumentTableSearchBuilder`](crate::language::DocumentTableSearchBuilder) into several queries before the vector search.
+///
+/// This is implemented for `()`, which only searches for the original query, and [`SearchQueryExpansion`], which is created with [`DocumentTableSearchBuilder::with_query_expansion`](crate::language::DocumentTableSearchBuilder::with_query_expansion).
+pub trait SearchQueryExpander: Send + Sync {
+    /// The error type that can occur when expanding the query.
+    type Error: Send + Sync + 'static;
+
+    /// Get the queries to search for. If this is empty, only the original query is searched.
+    fn expand(&self) -> impl Future<Output = Result<Vec<SearchQuery>, Self::Error>> + Send;
+
+    /// Get the constant used to merge the results of each query with reciprocal rank fusion.
+    fn rrf_k(&self) -> f32 {
+        60.0
+    }
+}
+
+impl SearchQueryExpander for () {
+    type Error = Infallible;
+
+    async fn expand(&self) -> Result<Vec<SearchQuery>, Self::Error> {
+        Ok(Vec::new())
+    }
+}
+
+/// Expands the query of a search with a [`QueryExpansion`]. This is created with [`DocumentTableSearchBuilder::with_query_expansion`](crate::language::DocumentTableSearchBuilder::with_query_expansion).
+pub struct SearchQueryExpansion<'a, M> {
+    pub(crate) expansion: &'a QueryExpansion<M>,
+    pub(crate) query: String,
+}
+
+impl<M> SearchQueryExpander for SearchQueryExpansion<'_, M>
+where
+    M: TextCompletionModel + Clone + Send + Sync + Unpin + 'static,
+    M::Session: Clone + Send + Sync + Unpin + 'static,
+    M::Error: Send + Sync + 'static,
+{
+    type Error = M::Error;
+
+    async fn expand(&self) -> Result<Vec<SearchQuery>, Self::Error> {
+        self.expansion.expand(&self.query).await
+    }
+
+    fn rrf_k(&self) -> f32 {
+        self.expansion.rrf_k()
+    }
+}
+
+/// Merge ranked lists of search results with reciprocal rank fusion.
+///
+/// Each chunk is scored with the sum of `1 / (k + rank)` over every list it appears in, where the best result in a list has rank 1. Chunks are identified by their record and byte range. The merged results are sorted from the best to the worst score, and the distance of each result is the negated score.
+pub fn reciprocal_rank_fusion<Doc>(
+    lists: impl IntoIterator<Item = Vec<EmbeddingIndexedTableSearchResult<Doc>>>,
+    k: f32,
+) -> Vec<EmbeddingIndexedTableSearchResult<Doc>> {
+    let mut fused: Vec<(f32, EmbeddingIndexedTableSearchResult<Doc>)> = Vec::new();
+    for list in lists {
+        for (rank, result) in list.into_iter().enumerate() {
+            let score = 1.0 / (k + rank as f32 + 1.0);
+            match fused.iter_mut().find(|(_, existing)| {
+                existing.record_id == result.record_id && existing.byte_range == result.byte_range
+            }) {
+                Some((existing, _)) => *existing += score,
+                None => fused.push((score, result)),
+            }
+        }
+    }
+    fused.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+    fused
+        .into_iter()
+        .map(|(score, result)| EmbeddingIndexedTableSearchResult {
+            distance: -score,
+            ..result
+        })
+        .collect()
+}
+
+#[test]
+fn test_reciprocal_rank_fusion() {
+    use surrealdb::RecordIdKey;
+
+    let result =
+        |record: i64, byte_range: std::ops::Range<usize>| EmbeddingIndexedTableSearchResult {
+            distance: 0.0,
+            id: EmbeddingId(0),
+            record_id: RecordIdKey::from(record),
+            byte_range,
+            record: (),
+        };
+    let original = vec![result(1, 0..10), result(2, 0..10), result(3, 0..10)];
+    let alternative = vec![result(2, 0..10), result(3, 0..10), result(1, 10..20)];
+    let hypothetical = vec![result(3, 0..10), result(2, 0..10)];
+
+    let fused = reciprocal_rank_fusion([original, alternative, hypothetical], 60.0);
+    let ranked: Vec<_> = fused
+        .iter()
+        .map(|result| (result.record_id.clone(), result.byte_range.clone()))
+        .collect();
+    // Chunks that rank well in every list beat a chunk that is only first in one list
+    assert_eq!(
+        ranked,
+        [
+            (RecordIdKey::from(2i64), 0..10),
+            (RecordIdKey::from(3i64), 0..10),
+            (RecordIdKey::from(1i64), 0..10),
+            (RecordIdKey::from(1i64), 10..20),
+        ]
+    );
+    assert!(fused
+        .windows(2)
+        .all(|pair| pair[0].distance <= pair[1].distance));
+}
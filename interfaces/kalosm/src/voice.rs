@@ -0,0 +1,158 @@
+use futures_util::future::{select, Either};
+use futures_util::{Stream, StreamExt};
+use kalosm_language::kalosm_language_model::{RealtimeConversation, RealtimeEvent, RealtimeInput};
+use kalosm_sound::rodio::buffer::SamplesBuffer;
+use kalosm_sound::rodio::source::UniformSourceIterator;
+use kalosm_sound::rodio::{OutputStream, Sink};
+use kalosm_sound::SpeechDetectorOutput;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+
+/// A voice conversation that streams audio from the microphone to a [`RealtimeConversation`] and plays the audio the model responds with.
+///
+/// If the voice activity detector hears the user while the model is talking, the audio from the model is stopped and the response is interrupted (barge-in). The microphone can pick up the audio the model is playing, so use headphones or disable barge-in with [`VoiceConversation::with_barge_in`] if the model interrupts itself.
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm::language::*;
+/// use kalosm::sound::*;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let conversation = OpenAICompatibleRealtimeModel::builder()
+///         .with_gpt_4o_realtime()
+///         .with_instructions("You are a friendly assistant. Keep your answers short.")
+///         .build()
+///         .connect()
+///         .await
+///         .unwrap();
+///     let mic = MicInput::default()
+///         .stream()
+///         .voice_activity_stream()
+///         .detect_speech();
+///     VoiceConversation::new(conversation)
+///         .run(mic, |event| {
+///             if let RealtimeEvent::InputTranscript(text) = event {
+///                 println!("You: {text}");
+///             }
+///         })
+///         .await
+///         .unwrap();
+/// }
+/// ```
+pub struct VoiceConversation<C> {
+    conversation: C,
+    barge_in: bool,
+}
+
+impl<C: RealtimeConversation> VoiceConversation<C> {
+    /// Create a new voice conversation with a realtime conversation
+    pub fn new(conversation: C) -> Self {
+        Self {
+            conversation,
+            barge_in: true,
+        }
+    }
+
+    /// Set if the response of the model is interrupted when the voice activity detector hears the user (defaults to true)
+    pub fn with_barge_in(mut self, barge_in: bool) -> Self {
+        self.barge_in = barge_in;
+        self
+    }
+
+    /// Get the realtime conversation
+    pub fn conversation(&self) -> &C {
+        &self.conversation
+    }
+
+    /// Run the conversation until the audio input ends or the model closes the conversation. Every event from the model is passed to `on_event`
+    ///
+    /// The input can be a [`SpeechDetectorOutput`] stream, or any other voice activity stream where chunks with a probability above 0.5 interrupt the model.
+    pub async fn run(
+        &self,
+        mut input: impl Stream<Item = impl Into<SpeechDetectorOutput>> + Unpin,
+        mut on_event: impl FnMut(&RealtimeEvent),
+    ) -> Result<(), C::Error> {
+        let sample_rate = self.conversation.sample_rate();
+        let playback = spawn_playback(sample_rate);
+        // If audio from the model may still be playing
+        let speaking = AtomicBool::new(false);
+
+        let send = async {
+            while let Some(chunk) = input.next().await {
+                let chunk: SpeechDetectorOutput = chunk.into();
+                if self.barge_in && chunk.is_speech && speaking.swap(false, Ordering::SeqCst) {
+                    let _ = playback.send(Playback::Stop);
+                    self.conversation.send(RealtimeInput::Interrupt).await?;
+                }
+                let samples =
+                    UniformSourceIterator::<_, f32>::new(chunk.samples, 1, sample_rate).collect();
+                self.conversation
+                    .send(RealtimeInput::Audio(samples))
+                    .await?;
+            }
+            Ok::<_, C::Error>(())
+        };
+        let receive = async {
+            while let Some(event) = self.conversation.next_event().await {
+                let event = event?;
+                match &event {
+                    RealtimeEvent::AudioDelta(samples) => {
+                        speaking.store(true, Ordering::SeqCst);
+                        let _ = playback.send(Playback::Play(samples.clone()));
+                    }
+                    // The model heard the user start talking before the local voice activity detector
+                    RealtimeEvent::SpeechStarted => {
+                        speaking.store(false, Ordering::SeqCst);
+                        let _ = playback.send(Playback::Stop);
+                    }
+                    _ => {}
+                }
+                on_event(&event);
+            }
+            Ok::<_, C::Error>(())
+        };
+
+        match select(std::pin::pin!(send), std::pin::pin!(receive)).await {
+            Either::Left((result, _)) | Either::Right((result, _)) => result,
+        }
+    }
+}
+
+enum Playback {
+    Play(Vec<f32>),
+    Stop,
+}
+
+/// Play audio on the default output device. The output stream can't be sent between threads, so it lives on its own thread
+fn spawn_playback(sample_rate: u32) -> mpsc::Sender<Playback> {
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        let sink = OutputStream::try_default()
+            .map_err(|err| err.to_string())
+            .and_then(|(stream, handle)| {
+                Sink::try_new(&handle)
+                    .map(|sink| (stream, sink))
+                    .map_err(|err| err.to_string())
+            });
+        let (_stream, sink) = match sink {
+            Ok(sink) => sink,
+            Err(err) => {
+                tracing::error!(
+                    "Failed to open the audio output for the voice conversation: {err}"
+                );
+                return;
+            }
+        };
+        for command in receiver {
+            match command {
+                Playback::Play(samples) => sink.append(SamplesBuffer::new(1, sample_rate, samples)),
+                Playback::Stop => {
+                    sink.clear();
+                    sink.play();
+                }
+            }
+        }
+    });
+    sender
+}
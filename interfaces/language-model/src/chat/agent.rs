@@ -0,0 +1,650 @@
+use std::fmt::Debug;
+use std::future::{Future, IntoFuture};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use futures_channel::mpsc::{UnboundedReceiver, UnboundedSender};
+use futures_util::{FutureExt, Stream, StreamExt};
+
+use crate::GenerationParameters;
+
+use super::{ChatMessage, ChatModel, MessageType};
+
+/// The error a [`Tool`] can return. The error is shown to the agent as the observation of the action.
+pub type ToolError = Box<dyn std::error::Error + Send + Sync>;
+
+/// A tool an [`Agent`] can call while it works on a question.
+///
+/// The name and description are shown to the model, so the description should explain what the tool does and what input it expects. You can create a tool from an async closure with [`FnTool`].
+pub trait Tool: Send + Sync {
+    /// The name the model uses to call the tool.
+    fn name(&self) -> &str;
+
+    /// A description of what the tool does and the input it expects.
+    fn description(&self) -> &str;
+
+    /// Call the tool with the input the model wrote and return the observation.
+    fn call(
+        &self,
+        input: String,
+    ) -> Pin<Box<dyn Future<Output = Result<String, ToolError>> + Send + '_>>;
+}
+
+/// A [`Tool`] that calls an async closure.
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm::language::*;
+///
+/// let shout = FnTool::new(
+///     "shout",
+///     "Converts the input text to uppercase.",
+///     |input: String| async move { Ok(input.to_uppercase()) },
+/// );
+/// ```
+pub struct FnTool<F> {
+    name: String,
+    description: String,
+    call: F,
+}
+
+impl<F> FnTool<F> {
+    /// Create a new tool with a name, a description and the closure that runs the tool.
+    pub fn new(name: impl ToString, description: impl ToString, call: F) -> Self {
+        Self {
+            name: name.to_string(),
+            description: description.to_string(),
+            call,
+        }
+    }
+}
+
+impl<F, Fut> Tool for FnTool<F>
+where
+    F: Fn(String) -> Fut + Send + Sync,
+    Fut: Future<Output = Result<String, ToolError>> + Send + 'static,
+{
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn call(
+        &self,
+        input: String,
+    ) -> Pin<Box<dyn Future<Output = Result<String, ToolError>> + Send + '_>> {
+        Box::pin((self.call)(input))
+    }
+}
+
+/// An agent that answers questions by reasoning about the question and calling tools in a loop.
+///
+/// Each step, the model writes a thought and either an action with the tool to call and its input, or the final answer. The result of each action is added to the scratchpad of the agent as an observation, and the scratchpad is shown to the model in the next step. The loop stops when the model writes a final answer, or when the agent runs out of steps or tokens.
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm::language::*;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let model = Llama::new_chat().await.unwrap();
+///     let agent = Agent::new(model, "You are a helpful assistant.")
+///         .with_tool(FnTool::new(
+///             "word_count",
+///             "Counts the words in the input text.",
+///             |input: String| async move { Ok(input.split_whitespace().count().to_string()) },
+///         ))
+///         .with_max_steps(5);
+///
+///     let mut response = agent.run("How many words are in the sentence 'the quick brown fox'?");
+///     // Stream the thoughts and actions of the agent as it works
+///     while let Some(event) = response.next().await {
+///         println!("{event:?}");
+///     }
+///     // And then get the final answer
+///     let answer = response.await.unwrap();
+///     println!("{answer}");
+/// }
+/// ```
+pub struct Agent<M> {
+    model: M,
+    description: String,
+    tools: Vec<Arc<dyn Tool>>,
+    sampler: GenerationParameters,
+    max_steps: usize,
+    max_retries: usize,
+    token_budget: Option<u32>,
+    scratchpad_steps: Option<usize>,
+    max_observation_length: Option<usize>,
+}
+
+impl<M: Clone> Clone for Agent<M> {
+    fn clone(&self) -> Self {
+        Self {
+            model: self.model.clone(),
+            description: self.description.clone(),
+            tools: self.tools.clone(),
+            sampler: self.sampler.clone(),
+            max_steps: self.max_steps,
+            max_retries: self.max_retries,
+            token_budget: self.token_budget,
+            scratchpad_steps: self.scratchpad_steps,
+            max_observation_length: self.max_observation_length,
+        }
+    }
+}
+
+impl<M: Debug> Debug for Agent<M> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Agent")
+            .field("model", &self.model)
+            .field("description", &self.description)
+            .field(
+                "tools",
+                &self
+                    .tools
+                    .iter()
+                    .map(|tool| tool.name())
+                    .collect::<Vec<_>>(),
+            )
+            .field("sampler", &self.sampler)
+            .field("max_steps", &self.max_steps)
+            .field("max_retries", &self.max_retries)
+            .field("token_budget", &self.token_budget)
+            .field("scratchpad_steps", &self.scratchpad_steps)
+            .field("max_observation_length", &self.max_observation_length)
+            .finish()
+    }
+}
+
+impl<M> Agent<M> {
+    /// Create a new agent with no tools. The description is added to the start of the system prompt of the agent.
+    pub fn new(model: M, description: impl ToString) -> Self {
+        Self {
+            model,
+            description: description.to_string(),
+            tools: Vec::new(),
+            sampler: GenerationParameters::default().with_max_length(512),
+            max_steps: 10,
+            max_retries: 2,
+            token_budget: None,
+            scratchpad_steps: None,
+            max_observation_length: None,
+        }
+    }
+
+    /// Add a tool the agent can call.
+    pub fn with_tool(mut self, tool: impl Tool + 'static) -> Self {
+        self.tools.push(Arc::new(tool));
+        self
+    }
+
+    /// Set the sampler used to generate each step. The max length of the sampler limits the tokens of each step. The stop sequence is always set to the start of an observation. (default: [`GenerationParameters::default`] with a max length of 512)
+    pub fn with_sampler(mut self, sampler: GenerationParameters) -> Self {
+        self.sampler = sampler;
+        self
+    }
+
+    /// Set the maximum number of actions the agent can take before it must write a final answer. (default: 10)
+    pub fn with_max_steps(mut self, max_steps: usize) -> Self {
+        self.max_steps = max_steps;
+        self
+    }
+
+    /// Set how many times in a row the agent can retry after writing a step that can't be parsed or calls a tool that doesn't exist. (default: 2)
+    pub fn with_max_retries(mut self, max_retries: usize) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Set the maximum number of tokens the agent can generate over every step of a run. (default: unlimited)
+    pub fn with_token_budget(mut self, tokens: u32) -> Self {
+        self.token_budget = Some(tokens);
+        self
+    }
+
+    /// Only show the most recent steps in the scratchpad. Older steps are replaced with a note that they were omitted. (default: every step)
+    pub fn with_scratchpad_steps(mut self, steps: usize) -> Self {
+        self.scratchpad_steps = Some(steps);
+        self
+    }
+
+    /// Truncate observations to at most this many characters before they are added to the scratchpad. (default: unlimited)
+    pub fn with_max_observation_length(mut self, characters: usize) -> Self {
+        self.max_observation_length = Some(characters);
+        self
+    }
+
+    fn system_prompt(&self) -> String {
+        let mut prompt = self.description.clone();
+        prompt.push_str(
+            "\n\nYou answer questions step by step. You have access to the following tools:\n",
+        );
+        for tool in &self.tools {
+            prompt.push_str(&format!("- {}: {}\n", tool.name(), tool.description()));
+        }
+        let tool_names = self
+            .tools
+            .iter()
+            .map(|tool| tool.name())
+            .collect::<Vec<_>>()
+            .join(", ");
+        prompt.push_str(&format!(
+            "\nRespond with only the next step. To use a tool, use this format:\n\
+            Thought: what you need to do next\n\
+            Action: the tool to use, one of [{tool_names}]\n\
+            Action Input: the input to the tool\n\n\
+            The result of the action will be added to the scratchpad as an Observation. When you know the answer, use this format:\n\
+            Thought: why you know the answer\n\
+            Final Answer: the answer to the question"
+        ));
+        prompt
+    }
+
+    fn user_message(
+        &self,
+        question: &str,
+        scratchpad: &[ScratchpadStep],
+        invalid: Option<&MalformedStep>,
+    ) -> String {
+        let mut message = format!("Question: {question}\n");
+        let shown = self
+            .scratchpad_steps
+            .map_or(scratchpad.len(), |steps| steps.min(scratchpad.len()));
+        let omitted = scratchpad.len() - shown;
+        if omitted > 0 {
+            message.push_str(&format!("\n({omitted} earlier steps omitted)\n"));
+        }
+        for step in &scratchpad[omitted..] {
+            message.push_str(&format!(
+                "\nThought: {}\nAction: {}\nAction Input: {}\n{OBSERVATION} {}\n",
+                step.thought, step.tool, step.input, step.observation
+            ));
+        }
+        if let Some(error) = invalid {
+            message.push_str(&format!("\nYour last response was invalid because {error}. Respond with a thought followed by either an action and action input or a final answer.\n"));
+        }
+        message
+    }
+
+    fn truncate_observation(&self, mut observation: String) -> String {
+        if let Some(max) = self.max_observation_length {
+            if let Some((index, _)) = observation.char_indices().nth(max) {
+                observation.truncate(index);
+                observation.push_str("... (truncated)");
+            }
+        }
+        observation
+    }
+}
+
+impl<M> Agent<M>
+where
+    M: ChatModel + Clone + Send + Sync + 'static,
+    M::ChatSession: Send + Sync + 'static,
+{
+    /// Run the agent on a question.
+    ///
+    /// The response is a stream of the [`AgentEvent`]s of the run. Awaiting the response returns the final answer.
+    pub fn run(&self, question: impl ToString) -> AgentResponse<M::Error> {
+        let agent = self.clone();
+        let question = question.to_string();
+        let (events, receiver) = futures_channel::mpsc::unbounded();
+        let task = async move { agent.run_loop(&question, events).await };
+        AgentResponse {
+            events: receiver,
+            task: Some(Box::pin(task)),
+            result: None,
+        }
+    }
+
+    async fn run_loop(
+        &self,
+        question: &str,
+        events: UnboundedSender<AgentEvent>,
+    ) -> Result<String, AgentError<M::Error>> {
+        let emit = |event: AgentEvent| _ = events.unbounded_send(event);
+        let system_prompt = self.system_prompt();
+        let mut scratchpad = Vec::new();
+        let mut invalid = None;
+        let mut retries = 0;
+        let mut tokens_used = 0;
+
+        loop {
+            let max_length = match self.token_budget {
+                Some(budget) if tokens_used >= budget => {
+                    return Err(AgentError::TokenBudgetExceeded(budget))
+                }
+                Some(budget) => self.sampler.max_length.min(budget - tokens_used),
+                None => self.sampler.max_length,
+            };
+            let sampler = self
+                .sampler
+                .clone()
+                .with_max_length(max_length)
+                .with_stop_on(OBSERVATION.to_string());
+
+            let messages = [
+                ChatMessage::new(MessageType::SystemPrompt, &system_prompt),
+                ChatMessage::new(
+                    MessageType::UserMessage,
+                    self.user_message(question, &scratchpad, invalid.as_ref()),
+                ),
+            ];
+            let mut session = self.model.new_chat_session().map_err(AgentError::Model)?;
+            let generated = Arc::new(Mutex::new((String::new(), 0)));
+            let on_token = {
+                let generated = generated.clone();
+                move |token: String| {
+                    let mut generated = generated.lock().unwrap();
+                    generated.0.push_str(&token);
+                    generated.1 += 1;
+                    Ok(())
+                }
+            };
+            self.model
+                .add_messages_with_callback(&mut session, &messages, sampler, on_token)
+                .await
+                .map_err(AgentError::Model)?;
+            let (response, tokens) = std::mem::take(&mut *generated.lock().unwrap());
+            tokens_used += tokens;
+
+            let step = parse_step(&response).and_then(|step| match step {
+                ParsedStep::Action { tool, .. }
+                    if !self.tools.iter().any(|existing| existing.name() == tool) =>
+                {
+                    Err(MalformedStep::UnknownTool(tool))
+                }
+                step => Ok(step),
+            });
+            let step = match step {
+                Ok(step) => step,
+                Err(error) => {
+                    emit(AgentEvent::MalformedResponse {
+                        response,
+                        error: error.clone(),
+                    });
+                    if retries >= self.max_retries {
+                        return Err(AgentError::MalformedResponse(error));
+                    }
+                    retries += 1;
+                    invalid = Some(error);
+                    continue;
+                }
+            };
+            retries = 0;
+            invalid = None;
+
+            match step {
+                ParsedStep::FinalAnswer { thought, answer } => {
+                    if !thought.is_empty() {
+                        emit(AgentEvent::Thought(thought));
+                    }
+                    emit(AgentEvent::FinalAnswer(answer.clone()));
+                    return Ok(answer);
+                }
+                ParsedStep::Action {
+                    thought,
+                    tool,
+                    input,
+                } => {
+                    if scratchpad.len() >= self.max_steps {
+                        return Err(AgentError::StepLimitExceeded(self.max_steps));
+                    }
+                    if !thought.is_empty() {
+                        emit(AgentEvent::Thought(thought.clone()));
+                    }
+                    emit(AgentEvent::Action {
+                        tool: tool.clone(),
+                        input: input.clone(),
+                    });
+                    let called = self
+                        .tools
+                        .iter()
+                        .find(|existing| existing.name() == tool)
+                        .expect("unknown tools are rejected while parsing the step");
+                    let observation = match called.call(input.clone()).await {
+                        Ok(output) => output,
+                        Err(error) => format!("Error: {error}"),
+                    };
+                    let observation = self.truncate_observation(observation);
+                    emit(AgentEvent::Observation {
+                        tool: tool.clone(),
+                        output: observation.clone(),
+                    });
+                    scratchpad.push(ScratchpadStep {
+                        thought,
+                        tool,
+                        input,
+                        observation,
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// The response of an [`Agent`] run. This is created with [`Agent::run`].
+///
+/// The response is a stream of the [`AgentEvent`]s of the run. You can await the response to get the final answer, either directly or after the stream of events ends.
+pub struct AgentResponse<E> {
+    events: UnboundedReceiver<AgentEvent>,
+    task: Option<AgentTask<E>>,
+    result: Option<Result<String, AgentError<E>>>,
+}
+
+impl<E: Unpin> Stream for AgentResponse<E> {
+    type Item = AgentEvent;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let myself = Pin::get_mut(self);
+        if let Poll::Ready(Some(event)) = myself.events.poll_next_unpin(cx) {
+            return Poll::Ready(Some(event));
+        }
+        if let Some(task) = &mut myself.task {
+            match task.poll_unpin(cx) {
+                Poll::Ready(result) => {
+                    // Dropping the task closes the channel so the remaining events can be drained
+                    myself.task = None;
+                    myself.result = Some(result);
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        myself.events.poll_next_unpin(cx)
+    }
+}
+
+impl<E: Send + 'static> IntoFuture for AgentResponse<E> {
+    type Output = Result<String, AgentError<E>>;
+    type IntoFuture = Pin<Box<dyn Future<Output = Self::Output> + Send>>;
+
+    fn into_future(mut self) -> Self::IntoFuture {
+        Box::pin(async move {
+            match self.result.take() {
+                Some(result) => result,
+                None => {
+                    self.task
+                        .take()
+                        .expect("AgentResponse cannot be awaited twice")
+                        .await
+                }
+            }
+        })
+    }
+}
+
+type AgentTask<E> = Pin<Box<dyn Future<Output = Result<String, AgentError<E>>> + Send>>;
+
+/// An event that happened while an [`Agent`] was running.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AgentEvent {
+    /// The model reasoned about what to do next.
+    Thought(String),
+    /// The model decided to call a tool.
+    Action {
+        /// The name of the tool.
+        tool: String,
+        /// The input the model passed to the tool.
+        input: String,
+    },
+    /// A tool returned a result. If the tool failed, the output is the error.
+    Observation {
+        /// The name of the tool.
+        tool: String,
+        /// The output of the tool.
+        output: String,
+    },
+    /// The model wrote a step that could not be used. The agent will retry the step if it has retries left.
+    MalformedResponse {
+        /// The text the model generated.
+        response: String,
+        /// Why the step could not be used.
+        error: MalformedStep,
+    },
+    /// The model answered the question.
+    FinalAnswer(String),
+}
+
+/// The reason a step written by the model could not be used.
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum MalformedStep {
+    /// The step has neither an action nor a final answer.
+    #[error("the response has neither an `Action:` nor a `Final Answer:`")]
+    MissingAction,
+    /// The step has an action without an action input.
+    #[error("the action is missing an `Action Input:`")]
+    MissingActionInput,
+    /// The step calls a tool that doesn't exist.
+    #[error("there is no tool named `{0}`")]
+    UnknownTool(String),
+}
+
+/// An error that can occur when running an [`Agent`].
+#[derive(Debug, thiserror::Error)]
+pub enum AgentError<E> {
+    /// An error from the model.
+    #[error("Failed to generate a step: {0}")]
+    Model(E),
+    /// The agent took the maximum number of steps without reaching a final answer.
+    #[error("The agent did not reach a final answer within {0} steps")]
+    StepLimitExceeded(usize),
+    /// The agent generated the maximum number of tokens without reaching a final answer.
+    #[error("The agent did not reach a final answer within its budget of {0} tokens")]
+    TokenBudgetExceeded(u32),
+    /// The model wrote a malformed step and ran out of retries.
+    #[error("The agent wrote a malformed step and ran out of retries: {0}")]
+    MalformedResponse(MalformedStep),
+}
+
+/// The text that starts an observation in the scratchpad. Generation stops here so the model doesn't make up the result of the action.
+const OBSERVATION: &str = "Observation:";
+
+struct ScratchpadStep {
+    thought: String,
+    tool: String,
+    input: String,
+    observation: String,
+}
+
+#[derive(Debug, PartialEq)]
+enum ParsedStep {
+    Action {
+        thought: String,
+        tool: String,
+        input: String,
+    },
+    FinalAnswer {
+        thought: String,
+        answer: String,
+    },
+}
+
+/// Parse a step written by the model. Whichever of the action and the final answer comes first is used.
+fn parse_step(response: &str) -> Result<ParsedStep, MalformedStep> {
+    const ACTION: &str = "Action:";
+    const ACTION_INPUT: &str = "Action Input:";
+    const FINAL_ANSWER: &str = "Final Answer:";
+
+    let response = response.trim();
+    let response = response.strip_suffix(OBSERVATION).unwrap_or(response);
+    let thought = |text: &str| {
+        let text = text.trim();
+        text.strip_prefix("Thought:")
+            .unwrap_or(text)
+            .trim()
+            .to_string()
+    };
+
+    // The model sometimes answers right after the action. The answer can't depend on the observation yet, so it is ignored
+    let input_text = |text: &str| {
+        let end = text.find(FINAL_ANSWER).unwrap_or(text.len());
+        text[..end].trim().to_string()
+    };
+
+    let action = response.find(ACTION);
+    let final_answer = response.find(FINAL_ANSWER);
+    match (action, final_answer) {
+        (_, Some(answer)) if action.is_none_or(|action| answer < action) => {
+            Ok(ParsedStep::FinalAnswer {
+                thought: thought(&response[..answer]),
+                answer: response[answer + FINAL_ANSWER.len()..].trim().to_string(),
+            })
+        }
+        (Some(action), _) => {
+            let rest = &response[action + ACTION.len()..];
+            let input = rest
+                .find(ACTION_INPUT)
+                .ok_or(MalformedStep::MissingActionInput)?;
+            let tool = rest[..input].trim().trim_matches('`').trim();
+            if tool.is_empty() {
+                return Err(MalformedStep::MissingAction);
+            }
+            Ok(ParsedStep::Action {
+                thought: thought(&response[..action]),
+                tool: tool.to_string(),
+                input: input_text(&rest[input + ACTION_INPUT.len()..]),
+            })
+        }
+        (None, _) => Err(MalformedStep::MissingAction),
+    }
+}
+
+#[test]
+fn test_parse_step() {
+    assert_eq!(
+        parse_step("Thought: I should count the words.\nAction: word_count\nAction Input: the quick brown fox\nObservation:"),
+        Ok(ParsedStep::Action {
+            thought: "I should count the words.".to_string(),
+            tool: "word_count".to_string(),
+            input: "the quick brown fox".to_string(),
+        })
+    );
+    assert_eq!(
+        parse_step("Thought: The tool said 4.\nFinal Answer: 4"),
+        Ok(ParsedStep::FinalAnswer {
+            thought: "The tool said 4.".to_string(),
+            answer: "4".to_string(),
+        })
+    );
+    assert_eq!(
+        parse_step("Action: `search`\nAction Input: rust\nFinal Answer: maybe"),
+        Ok(ParsedStep::Action {
+            thought: String::new(),
+            tool: "search".to_string(),
+            input: "rust".to_string(),
+        })
+    );
+    assert_eq!(
+        parse_step("Thought: I should search.\nAction: search"),
+        Err(MalformedStep::MissingActionInput)
+    );
+    assert_eq!(
+        parse_step("I don't know what to do."),
+        Err(MalformedStep::MissingAction)
+    );
+}
@@ -1,3 +1,4 @@
+use crate::BoxedFuture;
 use crate::GenerationParameters;
 use crate::ModelConstraints;
 use crate::NoConstraints;
@@ -187,6 +188,7 @@ impl<M: CreateChatSession> Chat<M> {
             task: OnceLock::new(),
             queued_tokens: None,
             result: None,
+            prepared: None,
         }
     }
 
@@ -222,9 +224,15 @@ impl<M: CreateChatSession> Chat<M> {
             task: OnceLock::new(),
             queued_tokens: None,
             result: None,
+            prepared: None,
         }
     }
 
+    /// Get the model the chat uses.
+    pub(crate) fn model(&self) -> &M {
+        &self.model
+    }
+
     fn session_clone(&mut self) -> Result<Arc<AsyncMutex<M::ChatSession>>, M::Error> {
         let session = self.session.get_or_init(|| {
             self.model
@@ -434,6 +442,7 @@ pub struct ChatResponseBuilder<
     #[allow(clippy::type_complexity)]
     result: Option<Receiver<Result<Box<dyn Any + Send>, M::Error>>>,
     queued_tokens: Option<UnboundedReceiver<String>>,
+    prepared: Option<BoxedFuture<'static, Vec<ChatMessage>>>,
 }
 
 impl<'a, M: CreateChatSession, Constraints, Sampler>
@@ -472,6 +481,7 @@ impl<'a, M: CreateChatSession, Constraints, Sampler>
             queued_tokens: None,
             result: None,
             task: OnceLock::new(),
+            prepared: self.prepared,
         }
     }
 
@@ -552,8 +562,18 @@ impl<'a, M: CreateChatSession, Constraints, Sampler>
             queued_tokens: None,
             result: None,
             task: OnceLock::new(),
+            prepared: self.prepared,
         }
     }
+
+    /// Replace the last queued message with the messages the future resolves to when the response starts generating.
+    pub(crate) fn with_prepared_messages(
+        mut self,
+        prepared: impl Future<Output = Vec<ChatMessage>> + Send + 'static,
+    ) -> Self {
+        self.prepared = Some(Box::pin(prepared));
+        self
+    }
 }
 
 impl<M, Sampler> ChatResponseBuilder<'_, M, NoConstraints, Sampler>
@@ -564,7 +584,8 @@ where
 {
     fn ensure_unstructured_task_started(&mut self) {
         if self.task.get().is_none() {
-            let messages = std::mem::take(&mut self.chat_session.queued_messages);
+            let mut messages = std::mem::take(&mut self.chat_session.queued_messages);
+            let prepared = self.prepared.take();
             let sampler = self
                 .sampler
                 .take()
@@ -585,6 +606,10 @@ where
             let session = self.chat_session.session_clone();
             let model = self.chat_session.model.clone();
             let future = async move {
+                if let Some(prepared) = prepared {
+                    messages.pop();
+                    messages.extend(prepared.await);
+                }
                 let session = session?;
                 let mut session = session.lock().await;
                 model
@@ -669,7 +694,8 @@ where
 {
     fn ensure_structured_task_started(&mut self) {
         if self.task.get().is_none() {
-            let messages = std::mem::take(&mut self.chat_session.queued_messages);
+            let mut messages = std::mem::take(&mut self.chat_session.queued_messages);
+            let prepared = self.prepared.take();
             let sampler = self
                 .sampler
                 .take()
@@ -689,6 +715,10 @@ where
             let session = self.chat_session.session_clone();
             let model = self.chat_session.model.clone();
             let future = async move {
+                if let Some(prepared) = prepared {
+                    messages.pop();
+                    messages.extend(prepared.await);
+                }
                 let session = session?;
                 let mut session = session.lock().await;
                 model
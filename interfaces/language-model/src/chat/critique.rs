@@ -0,0 +1,244 @@
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use super::{Chat, ChatModel};
+
+/// The check every draft in a critique loop must pass. A critique can check drafts against principles with a critique pass of the model, with validation closures, or both.
+///
+/// A critique loop is started with [`Chat::add_message_with_critique`] or [`Task::run_with_critique`](super::Task::run_with_critique). The model writes a draft, the draft is checked, and if the check fails, the model revises the draft with the feedback until a draft passes or the loop runs out of revisions.
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm::language::*;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let model = Llama::new_chat().await.unwrap();
+///     let mut chat = model.chat();
+///     let critique = Critique::new()
+///         .with_principle("The response is written for a five year old")
+///         .with_validator(|draft| {
+///             if draft.split_whitespace().count() <= 50 {
+///                 Ok(())
+///             } else {
+///                 Err("The response is longer than 50 words".to_string())
+///             }
+///         })
+///         .with_max_revisions(2);
+///     let response = chat
+///         .add_message_with_critique("Why is the sky blue?", &critique)
+///         .await
+///         .unwrap();
+///     for draft in &response.drafts {
+///         println!("{}\nFeedback: {:?}\n", draft.text, draft.feedback);
+///     }
+///     println!("Final response: {}", response.text());
+/// }
+/// ```
+#[derive(Clone)]
+pub struct Critique {
+    principles: Vec<String>,
+    validators: Vec<Arc<dyn Fn(&str) -> Result<(), String> + Send + Sync>>,
+    max_revisions: usize,
+}
+
+impl Debug for Critique {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Critique")
+            .field("principles", &self.principles)
+            .field("validators", &self.validators.len())
+            .field("max_revisions", &self.max_revisions)
+            .finish()
+    }
+}
+
+impl Default for Critique {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Critique {
+    /// Create a new critique with no principles or validators. Every draft passes until a principle or validator is added.
+    pub fn new() -> Self {
+        Self {
+            principles: Vec::new(),
+            validators: Vec::new(),
+            max_revisions: 3,
+        }
+    }
+
+    /// Add a principle the draft must follow. Drafts are checked against every principle in one critique pass of the model.
+    pub fn with_principle(mut self, principle: impl ToString) -> Self {
+        self.principles.push(principle.to_string());
+        self
+    }
+
+    /// Add multiple principles the draft must follow.
+    pub fn with_principles(mut self, principles: impl IntoIterator<Item = impl ToString>) -> Self {
+        self.principles.extend(
+            principles
+                .into_iter()
+                .map(|principle| principle.to_string()),
+        );
+        self
+    }
+
+    /// Add a closure that validates the draft. The closure returns feedback for the model if the draft is invalid. Validators run before the critique pass, and the critique pass is skipped if any validator fails.
+    pub fn with_validator(
+        mut self,
+        validator: impl Fn(&str) -> Result<(), String> + Send + Sync + 'static,
+    ) -> Self {
+        self.validators.push(Arc::new(validator));
+        self
+    }
+
+    /// Set the maximum number of times the model can revise the first draft. (default: 3)
+    pub fn with_max_revisions(mut self, max_revisions: usize) -> Self {
+        self.max_revisions = max_revisions;
+        self
+    }
+
+    /// Revise the draft the chat generated for the request until it passes or the loop runs out of revisions.
+    pub(crate) async fn revise<M>(
+        &self,
+        chat: &mut Chat<M>,
+        request: &str,
+        mut draft: String,
+    ) -> Result<CritiquedResponse, M::Error>
+    where
+        M: ChatModel + Send + Sync + Clone + Unpin + 'static,
+        M::ChatSession: Clone + Send + Sync + Unpin + 'static,
+    {
+        let mut drafts = Vec::new();
+        loop {
+            let feedback = self.check(chat.model(), request, &draft).await?;
+            let revise = feedback.is_some() && drafts.len() < self.max_revisions;
+            drafts.push(Draft {
+                text: draft,
+                feedback: feedback.clone(),
+            });
+            let Some(feedback) = feedback.filter(|_| revise) else {
+                return Ok(CritiquedResponse { drafts });
+            };
+            draft = chat
+                .add_message(format!(
+                    "Your response did not pass review:\n{feedback}\n\nRewrite your response to fix these issues. Respond with only the revised response."
+                ))
+                .await?;
+        }
+    }
+
+    /// Check the draft and return the feedback for the model if it fails.
+    async fn check<M>(
+        &self,
+        model: &M,
+        request: &str,
+        draft: &str,
+    ) -> Result<Option<String>, M::Error>
+    where
+        M: ChatModel + Send + Sync + Clone + Unpin + 'static,
+        M::ChatSession: Clone + Send + Sync + Unpin + 'static,
+    {
+        let failures = self
+            .validators
+            .iter()
+            .filter_map(|validator| validator(draft).err())
+            .collect::<Vec<_>>();
+        if !failures.is_empty() {
+            return Ok(Some(failures.join("\n")));
+        }
+        if self.principles.is_empty() {
+            return Ok(None);
+        }
+
+        // The critic runs in a separate session so the critique pass doesn't end up in the history of the chat
+        let mut critic = Chat::new(model.clone()).with_system_prompt(
+            "You are a careful reviewer. You check if responses follow a list of principles.",
+        );
+        let critique = critic
+            .add_message(self.critic_message(request, draft))
+            .await?;
+        Ok(critic_feedback(&critique))
+    }
+
+    fn critic_message(&self, request: &str, draft: &str) -> String {
+        let mut message =
+            String::from("Review the response to the request below.\n\nPrinciples:\n");
+        for principle in &self.principles {
+            message.push_str(&format!("- {principle}\n"));
+        }
+        message.push_str(&format!(
+            "\nRequest:\n{request}\n\nResponse:\n{draft}\n\n\
+            If the response follows every principle, reply with only PASS. Otherwise, list each principle the response breaks and how to fix it."
+        ));
+        message
+    }
+}
+
+/// Parse the reply of the critic into feedback for the model, or `None` if the draft passed.
+fn critic_feedback(critique: &str) -> Option<String> {
+    let critique = critique.trim();
+    (!critique.starts_with("PASS")).then(|| critique.to_string())
+}
+
+/// A draft written in a critique loop.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Draft {
+    /// The text of the draft.
+    pub text: String,
+    /// The feedback from the critique, or `None` if the draft passed.
+    pub feedback: Option<String>,
+}
+
+/// The response of a critique loop with every draft the model wrote. Created with [`Chat::add_message_with_critique`] or [`Task::run_with_critique`](super::Task::run_with_critique).
+#[derive(Debug, Clone, PartialEq)]
+pub struct CritiquedResponse {
+    /// Every draft in the order the model wrote them. The last draft is the final response.
+    pub drafts: Vec<Draft>,
+}
+
+impl CritiquedResponse {
+    /// The text of the final draft.
+    pub fn text(&self) -> &str {
+        self.drafts
+            .last()
+            .map(|draft| draft.text.as_str())
+            .unwrap_or_default()
+    }
+
+    /// Check if the final draft passed the critique. This is false if the loop ran out of revisions.
+    pub fn passed(&self) -> bool {
+        self.drafts
+            .last()
+            .is_some_and(|draft| draft.feedback.is_none())
+    }
+}
+
+impl<M> Chat<M>
+where
+    M: ChatModel + Send + Sync + Clone + Unpin + 'static,
+    M::ChatSession: Clone + Send + Sync + Unpin + 'static,
+{
+    /// Add a message to the chat and revise the response until it passes the critique. The feedback and every revision are added to the history of the chat.
+    ///
+    /// See [`Critique`] for an example.
+    pub async fn add_message_with_critique(
+        &mut self,
+        message: impl ToString,
+        critique: &Critique,
+    ) -> Result<CritiquedResponse, M::Error> {
+        let message = message.to_string();
+        let draft = self.add_message(message.clone()).await?;
+        critique.revise(self, &message, draft).await
+    }
+}
+
+#[test]
+fn test_critic_feedback() {
+    assert_eq!(critic_feedback("  PASS\n"), None);
+    assert_eq!(
+        critic_feedback("The response is too long.\n"),
+        Some("The response is too long.".to_string())
+    );
+}
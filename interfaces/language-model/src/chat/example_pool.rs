@@ -0,0 +1,184 @@
+use std::fmt::Debug;
+
+use crate::{DynEmbedder, Embedder, EmbedderExt, Embedding};
+
+/// A pool of input/output examples for a [`Task`](super::Task). Each time the task runs, the examples with inputs that are most similar to the message are added to the prompt.
+///
+/// Examples help the model mimic the format and style of the output, but every example makes the prompt longer. With a pool, you can keep many examples around and only show the model the few that are relevant to each message.
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm::language::*;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let model = Llama::new_chat().await.unwrap();
+///     let examples = ExamplePool::new(
+///         Bert::new().await.unwrap(),
+///         [
+///             ("What is 1 + 2?", "Step 1: 1 + 2 = 3\nOutput: 3"),
+///             ("What is (4 + 8) / 3?", "Step 1: 4 + 8 = 12\nStep 2: 12 / 3 = 4\nOutput: 4"),
+///             ("Spell cat backwards", "Step 1: c-a-t reversed is t-a-c\nOutput: tac"),
+///         ],
+///     )
+///     .await
+///     .unwrap()
+///     .with_k(2);
+///     let task = model
+///         .task("You are an assistant who solves problems step by step.")
+///         .with_example_pool(examples);
+///     let mut stream = task("What is 3 + 4?");
+///     stream.to_std_out().await.unwrap();
+/// }
+/// ```
+pub struct ExamplePool {
+    embedder: DynEmbedder,
+    examples: Vec<PoolExample>,
+    k: usize,
+}
+
+struct PoolExample {
+    input: String,
+    output: String,
+    embedding: Embedding,
+}
+
+impl Debug for ExamplePool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ExamplePool")
+            .field(
+                "examples",
+                &self
+                    .examples
+                    .iter()
+                    .map(|example| (&example.input, &example.output))
+                    .collect::<Vec<_>>(),
+            )
+            .field("k", &self.k)
+            .finish()
+    }
+}
+
+impl ExamplePool {
+    /// Create a new example pool from pairs of inputs and outputs. The inputs are embedded with the embedding model.
+    pub async fn new<E>(
+        embedder: E,
+        examples: impl IntoIterator<Item = (impl ToString, impl ToString)>,
+    ) -> Result<Self, E::Error>
+    where
+        E: Embedder,
+        E::Error: std::error::Error,
+    {
+        let (inputs, outputs): (Vec<_>, Vec<_>) = examples
+            .into_iter()
+            .map(|(input, output)| (input.to_string(), output.to_string()))
+            .unzip();
+        let embeddings = embedder.embed_batch(&inputs).await?;
+        let examples = inputs
+            .into_iter()
+            .zip(outputs)
+            .zip(embeddings)
+            .map(|((input, output), embedding)| PoolExample {
+                input,
+                output,
+                embedding,
+            })
+            .collect();
+        Ok(Self {
+            embedder: embedder.into_any_embedder(),
+            examples,
+            k: 3,
+        })
+    }
+
+    /// Set the number of examples that are added to the prompt each time the task runs. (default: 3)
+    pub fn with_k(mut self, k: usize) -> Self {
+        self.k = k;
+        self
+    }
+
+    /// Add an example to the pool.
+    pub async fn add_example(
+        &mut self,
+        input: impl ToString,
+        output: impl ToString,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let input = input.to_string();
+        let embedding = self.embedder.embed(&input).await?;
+        self.examples.push(PoolExample {
+            input,
+            output: output.to_string(),
+            embedding,
+        });
+        Ok(())
+    }
+
+    /// Get the number of examples in the pool.
+    pub fn len(&self) -> usize {
+        self.examples.len()
+    }
+
+    /// Check if the pool has no examples.
+    pub fn is_empty(&self) -> bool {
+        self.examples.is_empty()
+    }
+
+    /// Select the examples with inputs that are most similar to the message. The examples are sorted from the least to the most similar so the most relevant example ends up closest to the message in the prompt.
+    pub async fn select(
+        &self,
+        message: &str,
+    ) -> Result<Vec<(&str, &str)>, Box<dyn std::error::Error + Send + Sync>> {
+        if self.k == 0 || self.examples.is_empty() {
+            return Ok(Vec::new());
+        }
+        let embedding = self.embedder.embed(message).await?;
+        Ok(self.select_similar(&embedding))
+    }
+
+    fn select_similar(&self, embedding: &Embedding) -> Vec<(&str, &str)> {
+        let mut scored: Vec<_> = self
+            .examples
+            .iter()
+            .map(|example| (example.embedding.cosine_similarity(embedding), example))
+            .collect();
+        scored.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+        scored.truncate(self.k);
+        scored
+            .into_iter()
+            .rev()
+            .map(|(_, example)| (example.input.as_str(), example.output.as_str()))
+            .collect()
+    }
+}
+
+#[test]
+fn test_select_similar_examples() {
+    let example = |input: &str, vector: [f32; 2]| PoolExample {
+        input: input.to_string(),
+        output: input.to_uppercase(),
+        embedding: Embedding::new(vector.to_vec().into()),
+    };
+    struct NoEmbedder;
+    impl Embedder for NoEmbedder {
+        type Error = std::io::Error;
+
+        async fn embed_for(&self, _: crate::EmbeddingInput) -> Result<Embedding, Self::Error> {
+            unimplemented!()
+        }
+    }
+    let pool = ExamplePool {
+        embedder: NoEmbedder.into_any_embedder(),
+        examples: vec![
+            example("math", [1.0, 0.0]),
+            example("spelling", [0.0, 1.0]),
+            example("word problem", [0.8, 0.6]),
+        ],
+        k: 2,
+    };
+
+    let selected = pool.select_similar(&Embedding::new(vec![1.0, 0.1].into()));
+    assert_eq!(
+        selected,
+        [("word problem", "WORD PROBLEM"), ("math", "MATH")]
+    );
+}
@@ -1,5 +1,6 @@
 use std::error::Error;
 
+use super::Agent;
 use super::BoxedChatModel;
 use super::BoxedStructuredChatModel;
 use super::Chat;
@@ -30,6 +31,14 @@ pub trait ChatModelExt: CreateChatSession {
         Task::new(self.clone(), description)
     }
 
+    /// Create a new agent with the model. See [`Agent`] for more details.
+    fn agent(&self, description: impl ToString) -> Agent<Self>
+    where
+        Self: Clone,
+    {
+        Agent::new(self.clone(), description)
+    }
+
     /// Erase the type of the chat model. This can be used to make multiple implementations of
     /// [`ChatModel`] compatible with the same type.
     ///
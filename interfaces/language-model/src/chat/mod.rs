@@ -8,6 +8,12 @@ mod ext;
 pub use ext::*;
 mod task;
 pub use task::*;
+mod example_pool;
+pub use example_pool::*;
+mod agent;
+pub use agent::*;
+mod critique;
+pub use critique::*;
 mod chat_builder;
 pub use chat_builder::*;
 mod boxed;
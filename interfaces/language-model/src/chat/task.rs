@@ -1,14 +1,21 @@
+use std::future::Future;
 use std::mem::MaybeUninit;
 use std::ops::Deref;
+use std::sync::Arc;
 
 use crate::ModelConstraints;
 use crate::NoConstraints;
+use crate::PromptCompressor;
 
 use super::Chat;
 use super::ChatMessage;
+use super::ChatModel;
 use super::ChatResponseBuilder;
 use super::CreateChatSession;
 use super::CreateDefaultChatConstraintsForType;
+use super::Critique;
+use super::CritiquedResponse;
+use super::ExamplePool;
 use super::MessageType;
 
 /// A task session lets you efficiently run a task with a model. The task session will reuse the model's cache to avoid re-feeding the task prompt repeatedly.
@@ -41,6 +48,8 @@ use super::MessageType;
 pub struct Task<M: CreateChatSession, Constraints = NoConstraints> {
     chat: Chat<M>,
     constraints: Constraints,
+    example_pool: Option<Arc<ExamplePool>>,
+    compressor: Option<Arc<PromptCompressor>>,
 }
 
 impl<M: CreateChatSession, Constraints: Clone> Clone for Task<M, Constraints> {
@@ -48,6 +57,8 @@ impl<M: CreateChatSession, Constraints: Clone> Clone for Task<M, Constraints> {
         Self {
             chat: self.chat.clone(),
             constraints: self.constraints.clone(),
+            example_pool: self.example_pool.clone(),
+            compressor: self.compressor.clone(),
         }
     }
 }
@@ -59,6 +70,8 @@ impl<M: CreateChatSession> Task<M> {
         Self {
             chat,
             constraints: NoConstraints,
+            example_pool: None,
+            compressor: None,
         }
     }
 }
@@ -116,6 +129,68 @@ impl<M: CreateChatSession, Constraints> Task<M, Constraints> {
         self
     }
 
+    /// Add a pool of examples to the task. Unlike [`Task::with_example`], the examples are not added to every run of the task. Each time the task runs, the examples in the pool with inputs that are most similar to the message are added to the prompt right before the message.
+    ///
+    /// If the message fails to embed, the task runs without examples from the pool.
+    ///
+    /// # Example
+    /// ```rust, no_run
+    /// use kalosm::language::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let model = Llama::new_chat().await.unwrap();
+    ///     let examples = ExamplePool::new(
+    ///         Bert::new().await.unwrap(),
+    ///         [
+    ///             ("What is 1 + 2?", "Step 1: 1 + 2 = 3\nOutput: 3"),
+    ///             ("Spell cat backwards", "Step 1: c-a-t reversed is t-a-c\nOutput: tac"),
+    ///         ],
+    ///     )
+    ///     .await
+    ///     .unwrap()
+    ///     .with_k(1);
+    ///     let task = model
+    ///         .task("You are an assistant who solves problems step by step.")
+    ///         .with_example_pool(examples);
+    ///     let mut stream = task("What is 2 + 2?");
+    ///     stream.to_std_out().await.unwrap();
+    /// }
+    /// ```
+    pub fn with_example_pool(mut self, examples: ExamplePool) -> Self {
+        self.example_pool = Some(Arc::new(examples));
+        self
+    }
+
+    /// Compress each message with the compressor before it is sent to the model. This is useful for tasks that run on long contexts like retrieved documents. The system prompt and examples are not compressed.
+    ///
+    /// If the message fails to compress, the task runs with the original message.
+    ///
+    /// # Example
+    /// ```rust, no_run
+    /// use kalosm::language::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let model = Llama::new_chat().await.unwrap();
+    ///     let small_model = Llama::builder()
+    ///         .with_source(LlamaSource::qwen_2_5_0_5b_instruct())
+    ///         .build()
+    ///         .await
+    ///         .unwrap();
+    ///     let task = model
+    ///         .task("You summarize documents in one paragraph.")
+    ///         .with_compressor(PromptCompressor::new(small_model).with_rate(0.4));
+    ///     let document = std::fs::read_to_string("./report.txt").unwrap();
+    ///     let mut stream = task(&document);
+    ///     stream.to_std_out().await.unwrap();
+    /// }
+    /// ```
+    pub fn with_compressor(mut self, compressor: PromptCompressor) -> Self {
+        self.compressor = Some(Arc::new(compressor));
+        self
+    }
+
     /// Set the constraints for the task. The constraints force the format of all outputs of the task to fit
     /// the constraints. This can be used to make the model return a specific type. This method does the same thing
     /// as [`ChatResponseBuilder::with_constraints`] except it is called once on the task instead of any time you
@@ -145,6 +220,8 @@ impl<M: CreateChatSession, Constraints> Task<M, Constraints> {
         Task {
             chat: self.chat,
             constraints,
+            example_pool: self.example_pool,
+            compressor: self.compressor,
         }
     }
 
@@ -193,10 +270,101 @@ impl<M: CreateChatSession, Constraints: Clone> Task<M, Constraints> {
     /// }
     /// ```
     pub fn run(&self, message: impl ToString) -> ChatResponseBuilder<'static, M, Constraints> {
-        self.chat
+        let message = message.to_string();
+        let prepared = self.prepare(&message);
+        let builder = self
+            .chat
             .clone()
             .into_add_message(message)
-            .with_constraints(self.constraints.clone())
+            .with_constraints(self.constraints.clone());
+        match prepared {
+            Some(prepared) => builder.with_prepared_messages(prepared),
+            None => builder,
+        }
+    }
+
+    /// Create a future that resolves to the examples from the pool and the (compressed) message if the task has an example pool or compressor.
+    fn prepare(
+        &self,
+        message: &str,
+    ) -> Option<impl Future<Output = Vec<ChatMessage>> + Send + 'static> {
+        let example_pool = self.example_pool.clone();
+        let compressor = self.compressor.clone();
+        (example_pool.is_some() || compressor.is_some()).then(|| {
+            let message = message.to_string();
+            async move {
+                let mut messages = Vec::new();
+                if let Some(pool) = example_pool {
+                    match pool.select(&message).await {
+                        Ok(examples) => {
+                            messages.extend(examples.into_iter().flat_map(|(input, output)| {
+                                [
+                                    ChatMessage::new(MessageType::UserMessage, input),
+                                    ChatMessage::new(MessageType::ModelAnswer, output),
+                                ]
+                            }))
+                        }
+                        Err(err) => {
+                            tracing::error!("Failed to select examples for the task: {err}");
+                        }
+                    }
+                }
+                let message = match compressor {
+                    Some(compressor) => match compressor.compress(&message).await {
+                        Ok(compressed) => compressed,
+                        Err(err) => {
+                            tracing::error!("Failed to compress the message for the task: {err}");
+                            message
+                        }
+                    },
+                    None => message,
+                };
+                messages.push(ChatMessage::new(MessageType::UserMessage, message));
+                messages
+            }
+        })
+    }
+}
+
+impl<M> Task<M>
+where
+    M: ChatModel + Send + Sync + Clone + Unpin + 'static,
+    M::ChatSession: Clone + Send + Sync + Unpin + 'static,
+{
+    /// Run the task with a message and revise the response until it passes the critique. Like [`Task::run`], the revisions don't change the task for future runs.
+    ///
+    /// # Example
+    /// ```rust, no_run
+    /// use kalosm::language::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let model = Llama::new_chat().await.unwrap();
+    ///     let task = model.task("You write product descriptions for an online store.");
+    ///     let critique = Critique::new()
+    ///         .with_principle("The description doesn't make claims about the product that are not in the request")
+    ///         .with_principle("The description is friendly and concise");
+    ///     let response = task
+    ///         .run_with_critique("A blue ceramic mug that holds 350ml", &critique)
+    ///         .await
+    ///         .unwrap();
+    ///     println!("{} ({} drafts)", response.text(), response.drafts.len());
+    /// }
+    /// ```
+    pub async fn run_with_critique(
+        &self,
+        message: impl ToString,
+        critique: &Critique,
+    ) -> Result<CritiquedResponse, M::Error> {
+        let message = message.to_string();
+        let mut chat = self.chat.clone();
+        let prepared = self.prepare(&message);
+        let builder = chat.add_message(message.clone());
+        let draft = match prepared {
+            Some(prepared) => builder.with_prepared_messages(prepared).await?,
+            None => builder.await?,
+        };
+        critique.revise(&mut chat, &message, draft).await
     }
 }
 
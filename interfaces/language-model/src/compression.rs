@@ -0,0 +1,200 @@
+use std::fmt::Debug;
+use std::future::Future;
+use std::ops::Range;
+
+use crate::BoxedFuture;
+
+/// The log probability of one token in a text scored by a [`TokenScorer`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct TokenScore {
+    /// The byte range of the token in the text.
+    pub byte_range: Range<usize>,
+    /// The natural log of the probability the model assigned to the token given the text before it. This is `None` if the model didn't see any text before the token, like the first token in the text.
+    pub log_prob: Option<f32>,
+}
+
+impl TokenScore {
+    /// The surprisal of the token in nats. Tokens with a higher surprisal carry more information that the model could not predict from the text before them.
+    pub fn surprisal(&self) -> f32 {
+        self.log_prob
+            .map(|log_prob| -log_prob)
+            .unwrap_or(f32::INFINITY)
+    }
+}
+
+/// A model that can score how likely each token in a text is. Scoring is used by the [`PromptCompressor`] to find the tokens the model can predict without seeing them.
+pub trait TokenScorer: Send + Sync + 'static {
+    /// The error type returned when scoring the tokens fails.
+    type Error: Send + Sync + 'static;
+
+    /// Score every token in the text. The scores are returned in the order the tokens appear in the text.
+    fn score_tokens(
+        &self,
+        text: &str,
+    ) -> impl Future<Output = Result<Vec<TokenScore>, Self::Error>> + Send;
+}
+
+#[allow(clippy::type_complexity)]
+trait BoxedTokenScorer: Send + Sync {
+    fn score_tokens_boxed<'a>(
+        &'a self,
+        text: &'a str,
+    ) -> BoxedFuture<'a, Result<Vec<TokenScore>, Box<dyn std::error::Error + Send + Sync>>>;
+}
+
+struct AnyTokenScorer<S>(S);
+
+impl<S: TokenScorer> BoxedTokenScorer for AnyTokenScorer<S>
+where
+    S::Error: std::error::Error,
+{
+    fn score_tokens_boxed<'a>(
+        &'a self,
+        text: &'a str,
+    ) -> BoxedFuture<'a, Result<Vec<TokenScore>, Box<dyn std::error::Error + Send + Sync>>> {
+        let future = self.0.score_tokens(text);
+        Box::pin(async move { future.await.map_err(|e| e.into()) })
+    }
+}
+
+/// How much of the text a [`PromptCompressor`] keeps.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CompressionTarget {
+    /// Keep at most this many tokens of the scoring model.
+    Tokens(usize),
+    /// Keep this fraction of the tokens.
+    Rate(f32),
+}
+
+/// A prompt compressor that drops the tokens a small model can predict from long contexts.
+///
+/// The compressor scores every token in the text with a [`TokenScorer`], like a small local model. Tokens the model assigns a high probability to carry little information, so they are dropped first until the text fits the target. The kept tokens stay in their original order. This is the same idea as [LLMLingua](https://arxiv.org/abs/2310.05736) without the budget controller.
+///
+/// A compressor can be added to a [`Task`](crate::Task) with [`Task::with_compressor`](crate::Task::with_compressor) to compress every message before it is sent to the model.
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm::language::*;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let small_model = Llama::builder()
+///         .with_source(LlamaSource::qwen_2_5_0_5b_instruct())
+///         .build()
+///         .await
+///         .unwrap();
+///     let compressor = PromptCompressor::new(small_model).with_token_budget(512);
+///     let context = std::fs::read_to_string("./report.txt").unwrap();
+///     let compressed = compressor.compress(&context).await.unwrap();
+///     println!("{compressed}");
+/// }
+/// ```
+pub struct PromptCompressor {
+    scorer: Box<dyn BoxedTokenScorer>,
+    target: CompressionTarget,
+}
+
+impl Debug for PromptCompressor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PromptCompressor")
+            .field("target", &self.target)
+            .finish()
+    }
+}
+
+impl PromptCompressor {
+    /// Create a new compressor that scores tokens with the model. By default, the compressor keeps half of the tokens.
+    pub fn new<S>(scorer: S) -> Self
+    where
+        S: TokenScorer,
+        S::Error: std::error::Error,
+    {
+        Self {
+            scorer: Box::new(AnyTokenScorer(scorer)),
+            target: CompressionTarget::Rate(0.5),
+        }
+    }
+
+    /// Keep at most `budget` tokens of the scoring model. Text that already fits in the budget is not changed.
+    pub fn with_token_budget(mut self, budget: usize) -> Self {
+        self.target = CompressionTarget::Tokens(budget);
+        self
+    }
+
+    /// Keep a fraction of the tokens between 0 and 1. (default: 0.5)
+    pub fn with_rate(mut self, rate: f32) -> Self {
+        self.target = CompressionTarget::Rate(rate.clamp(0.0, 1.0));
+        self
+    }
+
+    /// Compress the text to fit the target.
+    pub async fn compress(
+        &self,
+        text: &str,
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        if text.is_empty() {
+            return Ok(String::new());
+        }
+        let scores = self.scorer.score_tokens_boxed(text).await?;
+        let keep = match self.target {
+            CompressionTarget::Tokens(budget) => budget,
+            CompressionTarget::Rate(rate) => (scores.len() as f32 * rate).ceil() as usize,
+        };
+        Ok(compress_scored(text, &scores, keep))
+    }
+}
+
+/// Keep the `keep` most surprising tokens of the text in their original order.
+fn compress_scored(text: &str, scores: &[TokenScore], keep: usize) -> String {
+    if keep >= scores.len() {
+        return text.to_string();
+    }
+
+    let mut ranked: Vec<_> = (0..scores.len()).collect();
+    ranked.sort_by(|&a, &b| scores[b].surprisal().total_cmp(&scores[a].surprisal()));
+    let mut kept = vec![false; scores.len()];
+    for index in ranked.into_iter().take(keep) {
+        kept[index] = true;
+    }
+
+    // Each token owns the text up to the start of the next token so the whitespace between kept tokens is not lost
+    let mut compressed = String::new();
+    for (index, score) in scores.iter().enumerate() {
+        if !kept[index] {
+            continue;
+        }
+        let start = if index == 0 {
+            0
+        } else {
+            score.byte_range.start
+        };
+        let end = scores
+            .get(index + 1)
+            .map(|next| next.byte_range.start)
+            .unwrap_or(text.len());
+        if let Some(token) = text.get(start..end) {
+            compressed.push_str(token);
+        }
+    }
+    compressed
+}
+
+#[test]
+fn test_compress_scored() {
+    let text = "The cat sat on the mat";
+    let score = |start: usize, end: usize, log_prob: Option<f32>| TokenScore {
+        byte_range: start..end,
+        log_prob,
+    };
+    let scores = [
+        score(0, 3, None),
+        score(4, 7, Some(-5.0)),
+        score(8, 11, Some(-3.0)),
+        score(12, 14, Some(-0.1)),
+        score(15, 18, Some(-0.2)),
+        score(19, 22, Some(-4.0)),
+    ];
+    assert_eq!(compress_scored(text, &scores, 6), text);
+    assert_eq!(compress_scored(text, &scores, 4), "The cat sat mat");
+    assert_eq!(compress_scored(text, &scores, 1), "The ");
+}
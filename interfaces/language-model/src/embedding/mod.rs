@@ -1,4 +1,5 @@
-use std::ops::{Add, Div, Mul, Sub};
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Div, Mul, Neg, Sub, SubAssign};
 
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
@@ -30,6 +31,93 @@ impl Embedding {
         let sum_j2 = self.embedding.iter().map(|a| a * a).sum::<f32>();
         sum_ij / (sum_i2 * sum_j2).sqrt()
     }
+
+    /// Compute the dot product between this embedding and another embedding.
+    pub fn dot(&self, other: &Self) -> f32 {
+        self.embedding
+            .iter()
+            .zip(other.embedding.iter())
+            .map(|(a, b)| a * b)
+            .sum()
+    }
+
+    /// Get the euclidean length of the embedding.
+    pub fn norm(&self) -> f32 {
+        self.embedding.iter().map(|a| a * a).sum::<f32>().sqrt()
+    }
+
+    /// Scale the embedding to unit length. An embedding with a length of zero is returned unchanged.
+    pub fn normalized(self) -> Self {
+        let norm = self.norm();
+        if norm == 0. {
+            return self;
+        }
+        self / norm
+    }
+
+    /// Compute the average of a set of embeddings weighted by the weight paired with each embedding. The result is
+    /// renormalized to unit length so it can be compared to the (normalized) embeddings most models produce.
+    ///
+    /// Returns `None` if there are no embeddings or the weights sum to zero.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use kalosm_language_model::Embedding;
+    /// let liked = Embedding::from([1.0, 0.0]);
+    /// let loved = Embedding::from([0.0, 1.0]);
+    /// let query = Embedding::weighted_average([(&liked, 1.0), (&loved, 3.0)]).unwrap();
+    /// assert!(query.vector()[1] > query.vector()[0]);
+    /// ```
+    pub fn weighted_average<'a>(
+        embeddings: impl IntoIterator<Item = (&'a Embedding, f32)>,
+    ) -> Option<Self> {
+        let mut total_weight = 0.;
+        let mut sum: Option<Embedding> = None;
+        for (embedding, weight) in embeddings {
+            total_weight += weight;
+            match &mut sum {
+                Some(sum) => sum.add_scaled(embedding, weight),
+                None => sum = Some(embedding.clone() * weight),
+            }
+        }
+        if total_weight == 0. {
+            return None;
+        }
+        sum.map(|sum| (sum / total_weight).normalized())
+    }
+
+    /// Compute the centroid (mean) of a set of embeddings. Returns `None` if there are no embeddings.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use kalosm_language_model::Embedding;
+    /// let examples = [Embedding::from([1.0, 0.0]), Embedding::from([0.0, 1.0])];
+    /// let centroid = Embedding::centroid(&examples).unwrap();
+    /// assert_eq!(centroid.vector(), &[0.5, 0.5]);
+    /// ```
+    pub fn centroid<'a>(embeddings: impl IntoIterator<Item = &'a Embedding>) -> Option<Self> {
+        let mut count = 0;
+        let mut sum: Option<Embedding> = None;
+        for embedding in embeddings {
+            count += 1;
+            match &mut sum {
+                Some(sum) => *sum += embedding,
+                None => sum = Some(embedding.clone()),
+            }
+        }
+        sum.map(|sum| sum / count as f32)
+    }
+
+    fn add_scaled(&mut self, other: &Self, scale: f32) {
+        debug_assert_eq!(
+            self.embedding.len(),
+            other.embedding.len(),
+            "embedding size mismatch"
+        );
+        for (a, b) in self.embedding.iter_mut().zip(other.embedding.iter()) {
+            *a += b * scale;
+        }
+    }
 }
 
 impl Add for Embedding {
@@ -60,6 +148,73 @@ impl Sub for Embedding {
     }
 }
 
+impl Add<&Embedding> for &Embedding {
+    type Output = Embedding;
+
+    fn add(self, other: &Embedding) -> Self::Output {
+        self.clone() + other
+    }
+}
+
+impl Add<&Embedding> for Embedding {
+    type Output = Self;
+
+    fn add(mut self, other: &Embedding) -> Self::Output {
+        self += other;
+        self
+    }
+}
+
+impl AddAssign<&Embedding> for Embedding {
+    fn add_assign(&mut self, other: &Embedding) {
+        self.add_scaled(other, 1.);
+    }
+}
+
+impl Sub<&Embedding> for &Embedding {
+    type Output = Embedding;
+
+    fn sub(self, other: &Embedding) -> Self::Output {
+        self.clone() - other
+    }
+}
+
+impl Sub<&Embedding> for Embedding {
+    type Output = Self;
+
+    fn sub(mut self, other: &Embedding) -> Self::Output {
+        self -= other;
+        self
+    }
+}
+
+impl SubAssign<&Embedding> for Embedding {
+    fn sub_assign(&mut self, other: &Embedding) {
+        self.add_scaled(other, -1.);
+    }
+}
+
+impl Neg for Embedding {
+    type Output = Self;
+
+    fn neg(self) -> Self::Output {
+        self * -1.
+    }
+}
+
+impl<'a> Sum<&'a Embedding> for Option<Embedding> {
+    fn sum<I: Iterator<Item = &'a Embedding>>(iter: I) -> Self {
+        let mut sum: Option<Embedding> = None;
+        for embedding in iter {
+            match &mut sum {
+                Some(sum) => *sum += embedding,
+                None => sum = Some(embedding.clone()),
+            }
+        }
+        sum
+    }
+}
+
 impl Mul<f32> for Embedding {
     type Output = Self;
 
@@ -110,6 +265,23 @@ impl<'de> Deserialize<'de> for Embedding {
     }
 }
 
+#[test]
+fn embedding_arithmetic() {
+    let king = Embedding::from([1.0, 1.0, 0.0]);
+    let man = Embedding::from([1.0, 0.0, 0.0]);
+    let woman = Embedding::from([0.0, 0.0, 1.0]);
+    let queen = &king - &man + &woman;
+    assert_eq!(queen.vector(), &[0.0, 1.0, 1.0]);
+
+    let total: Option<Embedding> = [&king, &man].into_iter().sum();
+    assert_eq!(total.unwrap().vector(), &[2.0, 1.0, 0.0]);
+
+    let average = Embedding::weighted_average([(&man, 1.0), (&woman, 1.0)]).unwrap();
+    assert!((average.norm() - 1.0).abs() < 1e-6);
+    assert!(Embedding::weighted_average([(&man, 0.0)]).is_none());
+    assert!(Embedding::centroid([]).is_none());
+}
+
 #[cfg(feature = "cache")]
 #[test]
 fn embedding_serialization() {
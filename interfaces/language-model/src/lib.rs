@@ -33,12 +33,26 @@ pub use openai::*;
 mod claude;
 #[cfg(feature = "anthropic")]
 pub use claude::*;
+#[cfg(feature = "tei")]
+mod tei;
+#[cfg(feature = "tei")]
+pub use tei::*;
+#[cfg(feature = "recorder")]
+mod recorder;
+#[cfg(feature = "recorder")]
+pub use recorder::*;
 
 mod embedding;
 pub use embedding::*;
+mod rerank;
+pub use rerank::*;
 mod model;
 pub use model::*;
 mod builder;
 pub use builder::*;
 mod chat;
 pub use chat::*;
+mod compression;
+pub use compression::*;
+mod realtime;
+pub use realtime::*;
@@ -8,6 +8,11 @@ pub use embedding::*;
 mod chat;
 pub use chat::*;
 
+#[cfg(feature = "openai-realtime")]
+mod realtime;
+#[cfg(feature = "openai-realtime")]
+pub use realtime::*;
+
 /// A client for making requests to an OpenAI compatible API.
 #[derive(Debug, Clone)]
 pub struct OpenAICompatibleClient {
@@ -0,0 +1,348 @@
+use super::{NoOpenAIAPIKeyError, OpenAICompatibleClient};
+use crate::{RealtimeConversation, RealtimeEvent, RealtimeInput};
+use async_lock::Mutex;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use futures_util::stream::{SplitSink, SplitStream};
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicBool, Ordering};
+use thiserror::Error;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::header::InvalidHeaderValue;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+type Socket = WebSocketStream<MaybeTlsStream<tokio::net::TcpStream>>;
+
+/// The sample rate of the pcm16 audio the OpenAI realtime API sends and receives
+const SAMPLE_RATE: u32 = 24_000;
+
+/// A speech to speech model that uses OpenAI's realtime websocket API.
+#[derive(Debug, Clone)]
+pub struct OpenAICompatibleRealtimeModel {
+    model: String,
+    client: OpenAICompatibleClient,
+    instructions: Option<String>,
+    voice: Option<String>,
+    server_turn_detection: bool,
+}
+
+impl OpenAICompatibleRealtimeModel {
+    /// Create a new builder for the OpenAI compatible realtime model.
+    pub fn builder() -> OpenAICompatibleRealtimeModelBuilder<false> {
+        OpenAICompatibleRealtimeModelBuilder::new()
+    }
+
+    /// Open a new conversation with the model.
+    pub async fn connect(
+        &self,
+    ) -> Result<OpenAICompatibleRealtimeConversation, OpenAICompatibleRealtimeError> {
+        let api_key = self.client.resolve_api_key()?;
+        let url = format!(
+            "{}/realtime?model={}",
+            websocket_url(self.client.base_url()),
+            self.model
+        );
+        let mut request = url.into_client_request()?;
+        let headers = request.headers_mut();
+        headers.insert("Authorization", format!("Bearer {api_key}").parse()?);
+        headers.insert("OpenAI-Beta", "realtime=v1".parse()?);
+        let (socket, _) = tokio_tungstenite::connect_async(request).await?;
+        let (sink, stream) = socket.split();
+        let conversation = OpenAICompatibleRealtimeConversation {
+            sink: Mutex::new(sink),
+            stream: Mutex::new(stream),
+            responding: AtomicBool::new(false),
+        };
+
+        let mut session = json!({
+            "modalities": ["text", "audio"],
+            "input_audio_format": "pcm16",
+            "output_audio_format": "pcm16",
+            "input_audio_transcription": { "model": "whisper-1" },
+            "turn_detection": if self.server_turn_detection {
+                json!({ "type": "server_vad" })
+            } else {
+                Value::Null
+            },
+        });
+        if let Some(instructions) = &self.instructions {
+            session["instructions"] = json!(instructions);
+        }
+        if let Some(voice) = &self.voice {
+            session["voice"] = json!(voice);
+        }
+        conversation
+            .send_json(json!({ "type": "session.update", "session": session }))
+            .await?;
+
+        Ok(conversation)
+    }
+}
+
+/// A builder for an openai compatible realtime model.
+#[derive(Debug)]
+pub struct OpenAICompatibleRealtimeModelBuilder<const WITH_NAME: bool> {
+    model: Option<String>,
+    client: OpenAICompatibleClient,
+    instructions: Option<String>,
+    voice: Option<String>,
+    server_turn_detection: bool,
+}
+
+impl Default for OpenAICompatibleRealtimeModelBuilder<false> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OpenAICompatibleRealtimeModelBuilder<false> {
+    /// Creates a new builder
+    pub fn new() -> Self {
+        Self {
+            model: None,
+            client: Default::default(),
+            instructions: None,
+            voice: None,
+            server_turn_detection: true,
+        }
+    }
+}
+
+impl<const WITH_NAME: bool> OpenAICompatibleRealtimeModelBuilder<WITH_NAME> {
+    /// Set the name of the model to use.
+    pub fn with_model(self, model: impl ToString) -> OpenAICompatibleRealtimeModelBuilder<true> {
+        OpenAICompatibleRealtimeModelBuilder {
+            model: Some(model.to_string()),
+            client: self.client,
+            instructions: self.instructions,
+            voice: self.voice,
+            server_turn_detection: self.server_turn_detection,
+        }
+    }
+
+    /// Set the model to the latest version of gpt 4o realtime
+    pub fn with_gpt_4o_realtime(self) -> OpenAICompatibleRealtimeModelBuilder<true> {
+        self.with_model("gpt-4o-realtime-preview")
+    }
+
+    /// Set the model to the latest version of gpt 4o mini realtime
+    pub fn with_gpt_4o_mini_realtime(self) -> OpenAICompatibleRealtimeModelBuilder<true> {
+        self.with_model("gpt-4o-mini-realtime-preview")
+    }
+
+    /// Set the client used to make requests to the OpenAI API.
+    pub fn with_client(mut self, client: OpenAICompatibleClient) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Set the instructions the model follows for every response in the conversation.
+    pub fn with_instructions(mut self, instructions: impl ToString) -> Self {
+        self.instructions = Some(instructions.to_string());
+        self
+    }
+
+    /// Set the voice the model speaks with, like `alloy` or `verse`.
+    pub fn with_voice(mut self, voice: impl ToString) -> Self {
+        self.voice = Some(voice.to_string());
+        self
+    }
+
+    /// Set if the server detects when the user starts and stops speaking. (defaults to true)
+    ///
+    /// With turn detection on the server, the model responds when the user stops speaking and stops responding when the user talks over it. Without it, send [`RealtimeInput::CommitAudio`] at the end of each turn.
+    pub fn with_server_turn_detection(mut self, server_turn_detection: bool) -> Self {
+        self.server_turn_detection = server_turn_detection;
+        self
+    }
+}
+
+impl OpenAICompatibleRealtimeModelBuilder<true> {
+    /// Build the model.
+    pub fn build(self) -> OpenAICompatibleRealtimeModel {
+        OpenAICompatibleRealtimeModel {
+            model: self.model.unwrap(),
+            client: self.client,
+            instructions: self.instructions,
+            voice: self.voice,
+            server_turn_detection: self.server_turn_detection,
+        }
+    }
+}
+
+/// An error that can occur when running a [`OpenAICompatibleRealtimeConversation`].
+#[derive(Error, Debug)]
+pub enum OpenAICompatibleRealtimeError {
+    /// An error occurred while resolving the API key.
+    #[error("Error resolving API key: {0}")]
+    APIKeyError(#[from] NoOpenAIAPIKeyError),
+    /// The API key or model name can't be sent in a request header.
+    #[error("Invalid header: {0}")]
+    InvalidHeader(#[from] InvalidHeaderValue),
+    /// An error occurred in the websocket connection to the OpenAI API.
+    #[error("Websocket error: {0}")]
+    WebSocketError(#[from] tokio_tungstenite::tungstenite::Error),
+    /// Failed to deserialize an event from the OpenAI API.
+    #[error("Failed to deserialize OpenAI API event: {0}")]
+    DeserializeError(#[from] serde_json::Error),
+    /// The OpenAI API sent audio that is not valid base64.
+    #[error("Failed to decode audio from the OpenAI API: {0}")]
+    AudioDecodeError(#[from] base64::DecodeError),
+    /// The OpenAI API sent an error event.
+    #[error("OpenAI API error: {0}")]
+    APIError(String),
+}
+
+/// A live conversation with an [`OpenAICompatibleRealtimeModel`].
+pub struct OpenAICompatibleRealtimeConversation {
+    sink: Mutex<SplitSink<Socket, Message>>,
+    stream: Mutex<SplitStream<Socket>>,
+    /// If the model is generating a response that can be interrupted
+    responding: AtomicBool,
+}
+
+impl OpenAICompatibleRealtimeConversation {
+    async fn send_json(&self, event: Value) -> Result<(), OpenAICompatibleRealtimeError> {
+        self.sink
+            .lock()
+            .await
+            .send(Message::text(event.to_string()))
+            .await?;
+        Ok(())
+    }
+}
+
+impl RealtimeConversation for OpenAICompatibleRealtimeConversation {
+    type Error = OpenAICompatibleRealtimeError;
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    async fn send(&self, input: RealtimeInput) -> Result<(), Self::Error> {
+        match input {
+            RealtimeInput::Audio(samples) => {
+                self.send_json(json!({
+                    "type": "input_audio_buffer.append",
+                    "audio": encode_pcm16(&samples),
+                }))
+                .await
+            }
+            RealtimeInput::CommitAudio => {
+                self.send_json(json!({ "type": "input_audio_buffer.commit" }))
+                    .await?;
+                self.send_json(json!({ "type": "response.create" })).await
+            }
+            RealtimeInput::Text(text) => {
+                self.send_json(json!({
+                    "type": "conversation.item.create",
+                    "item": {
+                        "type": "message",
+                        "role": "user",
+                        "content": [{ "type": "input_text", "text": text }],
+                    },
+                }))
+                .await?;
+                self.send_json(json!({ "type": "response.create" })).await
+            }
+            RealtimeInput::Interrupt => {
+                // Cancelling without an active response is an error in the API
+                if self.responding.swap(false, Ordering::SeqCst) {
+                    self.send_json(json!({ "type": "response.cancel" })).await?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    async fn next_event(&self) -> Option<Result<RealtimeEvent, Self::Error>> {
+        let mut stream = self.stream.lock().await;
+        loop {
+            let text = match stream.next().await? {
+                Ok(Message::Text(text)) => text,
+                Ok(Message::Close(_)) => return None,
+                Ok(_) => continue,
+                Err(err) => return Some(Err(err.into())),
+            };
+            let event: Value = match serde_json::from_str(&text) {
+                Ok(event) => event,
+                Err(err) => return Some(Err(err.into())),
+            };
+            let field = |name: &str| event[name].as_str().unwrap_or_default().to_string();
+            let event = match event["type"].as_str().unwrap_or_default() {
+                "response.audio.delta" => STANDARD
+                    .decode(field("delta"))
+                    .map(|bytes| RealtimeEvent::AudioDelta(decode_pcm16(&bytes)))
+                    .map_err(Into::into),
+                "response.text.delta" => Ok(RealtimeEvent::TextDelta(field("delta"))),
+                "response.audio_transcript.delta" => {
+                    Ok(RealtimeEvent::TranscriptDelta(field("delta")))
+                }
+                "conversation.item.input_audio_transcription.completed" => {
+                    Ok(RealtimeEvent::InputTranscript(field("transcript")))
+                }
+                "input_audio_buffer.speech_started" => Ok(RealtimeEvent::SpeechStarted),
+                "input_audio_buffer.speech_stopped" => Ok(RealtimeEvent::SpeechStopped),
+                "response.created" => {
+                    self.responding.store(true, Ordering::SeqCst);
+                    continue;
+                }
+                "response.done" => {
+                    self.responding.store(false, Ordering::SeqCst);
+                    Ok(RealtimeEvent::ResponseDone)
+                }
+                "error" => Err(OpenAICompatibleRealtimeError::APIError(
+                    event["error"]["message"]
+                        .as_str()
+                        .unwrap_or_default()
+                        .to_string(),
+                )),
+                _ => continue,
+            };
+            return Some(event);
+        }
+    }
+}
+
+/// Convert the http base url of the client into the websocket url of the same server
+fn websocket_url(base_url: &str) -> String {
+    if let Some(rest) = base_url.strip_prefix("https://") {
+        format!("wss://{rest}")
+    } else if let Some(rest) = base_url.strip_prefix("http://") {
+        format!("ws://{rest}")
+    } else {
+        base_url.to_string()
+    }
+}
+
+/// Encode samples between -1 and 1 as base64 little endian pcm16 audio
+fn encode_pcm16(samples: &[f32]) -> String {
+    let bytes = samples
+        .iter()
+        .flat_map(|sample| ((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16).to_le_bytes())
+        .collect::<Vec<_>>();
+    STANDARD.encode(bytes)
+}
+
+/// Decode little endian pcm16 audio into samples between -1 and 1
+fn decode_pcm16(bytes: &[u8]) -> Vec<f32> {
+    bytes
+        .chunks_exact(2)
+        .map(|chunk| i16::from_le_bytes([chunk[0], chunk[1]]) as f32 / i16::MAX as f32)
+        .collect()
+}
+
+#[test]
+fn test_pcm16_round_trip() {
+    let samples = [0.0, 0.5, -0.5, 1.0, -1.0];
+    let encoded = encode_pcm16(&samples);
+    let decoded = decode_pcm16(&STANDARD.decode(encoded).unwrap());
+    for (sample, decoded) in samples.iter().zip(decoded) {
+        assert!((sample - decoded).abs() < 1e-4);
+    }
+    assert_eq!(
+        websocket_url("https://api.openai.com/v1"),
+        "wss://api.openai.com/v1"
+    );
+}
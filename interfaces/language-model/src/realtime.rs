@@ -0,0 +1,100 @@
+use std::future::Future;
+
+/// Input sent to a [`RealtimeConversation`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum RealtimeInput {
+    /// Mono audio samples between -1 and 1 at the [`RealtimeConversation::sample_rate`] of the conversation.
+    Audio(Vec<f32>),
+    /// Mark the end of the audio the user is speaking and ask the model to respond. This is only required if the model doesn't detect the end of turns itself.
+    CommitAudio,
+    /// A text message from the user. The model responds to the message right away.
+    Text(String),
+    /// Stop the response the model is currently generating, like when the user starts talking over the model.
+    Interrupt,
+}
+
+/// An event from a [`RealtimeConversation`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum RealtimeEvent {
+    /// The next chunk of audio the model is speaking. The audio is mono with samples between -1 and 1 at the [`RealtimeConversation::sample_rate`] of the conversation.
+    AudioDelta(Vec<f32>),
+    /// The next chunk of a text response.
+    TextDelta(String),
+    /// The next chunk of the transcript of the audio the model is speaking.
+    TranscriptDelta(String),
+    /// The transcript of the audio the user spoke.
+    InputTranscript(String),
+    /// The model detected the user started speaking. Any audio from the model that is still playing should be stopped.
+    SpeechStarted,
+    /// The model detected the user stopped speaking.
+    SpeechStopped,
+    /// The model finished or stopped the current response.
+    ResponseDone,
+}
+
+/// A live, bidirectional conversation with a model that streams audio and text in both directions.
+///
+/// Input and events can be sent and received at the same time from different tasks because both methods take `&self`.
+pub trait RealtimeConversation: Send + Sync + 'static {
+    /// The error type that can occur when sending input or receiving events.
+    type Error: Send + Sync + 'static;
+
+    /// The sample rate of audio sent to and received from the model.
+    fn sample_rate(&self) -> u32;
+
+    /// Send input to the model.
+    fn send(&self, input: RealtimeInput) -> impl Future<Output = Result<(), Self::Error>> + Send;
+
+    /// Wait for the next event from the model. Returns `None` once the conversation is closed.
+    fn next_event(&self)
+        -> impl Future<Output = Option<Result<RealtimeEvent, Self::Error>>> + Send;
+}
+
+/// An extension trait for [`RealtimeConversation`] with helper methods.
+pub trait RealtimeConversationExt: RealtimeConversation {
+    /// Send mono audio samples at the [`RealtimeConversation::sample_rate`] of the conversation.
+    fn send_audio(
+        &self,
+        samples: impl Into<Vec<f32>>,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send {
+        self.send(RealtimeInput::Audio(samples.into()))
+    }
+
+    /// Send a text message and ask the model to respond.
+    ///
+    /// # Example
+    /// ```rust, no_run
+    /// use kalosm::language::*;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let conversation = OpenAICompatibleRealtimeModel::builder()
+    ///     .with_gpt_4o_realtime()
+    ///     .build()
+    ///     .connect()
+    ///     .await?;
+    /// conversation.send_text("Tell me a joke").await?;
+    /// while let Some(event) = conversation.next_event().await {
+    ///     match event? {
+    ///         RealtimeEvent::TranscriptDelta(text) => print!("{text}"),
+    ///         RealtimeEvent::ResponseDone => break,
+    ///         _ => {}
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn send_text(
+        &self,
+        text: impl ToString,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send {
+        self.send(RealtimeInput::Text(text.to_string()))
+    }
+
+    /// Stop the response the model is currently generating.
+    fn interrupt(&self) -> impl Future<Output = Result<(), Self::Error>> + Send {
+        self.send(RealtimeInput::Interrupt)
+    }
+}
+
+impl<C: RealtimeConversation> RealtimeConversationExt for C {}
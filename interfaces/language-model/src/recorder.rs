@@ -0,0 +1,454 @@
+use std::any::Any;
+use std::future::Future;
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ChatMessage, ChatModel, ChatSession, CreateChatSession, CreateDefaultChatConstraintsForType,
+    CreateDefaultCompletionConstraintsForType, CreateTextCompletionSession, GenerationParameters,
+    ModelConstraints, StructuredChatModel, StructuredTextCompletionModel, TextCompletionModel,
+};
+
+/// A request and completion recorded by a [`Recorder`]. Each record is written as one line of JSON.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Record {
+    /// The name of the model set with [`RecordedModel::with_model_name`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// The time the request was made in milliseconds since the unix epoch
+    pub timestamp_ms: u64,
+    /// The prompt of a text completion request
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt: Option<String>,
+    /// The full history of a chat request, including the new messages
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub messages: Option<Vec<ChatMessage>>,
+    /// The parameters of the request if the sampler was [`GenerationParameters`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parameters: Option<RecordedParameters>,
+    /// If the completion was generated with constraints
+    pub structured: bool,
+    /// The text the model generated
+    pub completion: String,
+}
+
+/// The [`GenerationParameters`] used for a [`Record`].
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RecordedParameters {
+    /// The temperature used for sampling
+    pub temperature: f32,
+    /// The tau value used for mirostat sampling
+    pub tau: f32,
+    /// The eta value used for mirostat sampling
+    pub eta: f32,
+    /// The mu value used for mirostat sampling
+    pub mu: f32,
+    /// The penalty for repeating tokens
+    pub repetition_penalty: f32,
+    /// The number of tokens the repetition penalty applies to
+    pub repetition_penalty_range: u32,
+    /// The maximum number of tokens to generate
+    pub max_length: u32,
+    /// The string generation stops on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop_on: Option<String>,
+    /// The seed used for sampling
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+}
+
+impl From<&GenerationParameters> for RecordedParameters {
+    fn from(parameters: &GenerationParameters) -> Self {
+        Self {
+            temperature: parameters.temperature(),
+            tau: parameters.tau(),
+            eta: parameters.eta(),
+            mu: parameters.mu(),
+            repetition_penalty: parameters.repetition_penalty(),
+            repetition_penalty_range: parameters.repetition_penalty_range(),
+            max_length: parameters.max_length(),
+            stop_on: parameters.stop_on().map(ToString::to_string),
+            seed: parameters.seed(),
+        }
+    }
+}
+
+type Redaction = Arc<dyn Fn(&mut Record) + Send + Sync>;
+
+/// A recorder that writes every request and completion that runs through a [`RecordedModel`] to JSONL. The records can be used to build fine-tuning datasets or replay suites from real traffic.
+///
+/// Cloning the recorder is cheap. Clones write to the same destination, so one recorder can be shared between several models.
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm::language::*;
+///
+/// #[tokio::main]
+/// async fn main() {
+///     let recorder = Recorder::to_file("requests.jsonl")
+///         .unwrap()
+///         // Hide anything that looks like an email address before the record is written
+///         .with_redaction(|record| {
+///             record.completion = record
+///                 .completion
+///                 .split(' ')
+///                 .map(|word| if word.contains('@') { "[email]" } else { word })
+///                 .collect::<Vec<_>>()
+///                 .join(" ");
+///         });
+///     let llm = RecordedModel::new(Llama::new_chat().await.unwrap(), recorder)
+///         .with_model_name("llama");
+///     let mut chat = llm.chat();
+///     chat("Hello, world!").to_std_out().await.unwrap();
+/// }
+/// ```
+#[derive(Clone)]
+pub struct Recorder {
+    writer: Arc<Mutex<Box<dyn Write + Send>>>,
+    redactions: Vec<Redaction>,
+}
+
+impl Recorder {
+    /// Create a recorder that writes records to the given writer.
+    pub fn new(writer: impl Write + Send + 'static) -> Self {
+        Self {
+            writer: Arc::new(Mutex::new(Box::new(writer))),
+            redactions: Vec::new(),
+        }
+    }
+
+    /// Create a recorder that appends records to a file. The file is created if it doesn't exist.
+    pub fn to_file(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        Ok(Self::new(file))
+    }
+
+    /// Add a hook that can modify each record before it is written. Hooks run in the order they were added.
+    pub fn with_redaction(
+        mut self,
+        redaction: impl Fn(&mut Record) + Send + Sync + 'static,
+    ) -> Self {
+        self.redactions.push(Arc::new(redaction));
+        self
+    }
+
+    fn write(&self, mut record: Record) {
+        for redaction in &self.redactions {
+            redaction(&mut record);
+        }
+        let mut line = match serde_json::to_vec(&record) {
+            Ok(line) => line,
+            Err(err) => {
+                tracing::error!("Failed to serialize record: {err}");
+                return;
+            }
+        };
+        line.push(b'\n');
+        let mut writer = self.writer.lock().unwrap();
+        if let Err(err) = writer.write_all(&line).and_then(|_| writer.flush()) {
+            tracing::error!("Failed to write record: {err}");
+        }
+    }
+}
+
+/// A record that is written once the generation finishes successfully.
+struct PendingRecord {
+    recorder: Recorder,
+    record: Record,
+    completion: Arc<Mutex<String>>,
+}
+
+impl PendingRecord {
+    fn on_token<E>(
+        &self,
+        mut on_token: impl FnMut(String) -> Result<(), E> + Send + Sync + 'static,
+    ) -> impl FnMut(String) -> Result<(), E> + Send + Sync + 'static {
+        let completion = self.completion.clone();
+        move |token| {
+            completion.lock().unwrap().push_str(&token);
+            on_token(token)
+        }
+    }
+
+    fn finish(mut self) {
+        self.record.completion = std::mem::take(&mut *self.completion.lock().unwrap());
+        self.recorder.write(self.record);
+    }
+}
+
+/// A model that records every request and completion with a [`Recorder`]. Failed generations are not recorded.
+///
+/// The wrapper works with any local or remote model. It implements the same text completion and chat traits as the model it wraps.
+#[derive(Clone)]
+pub struct RecordedModel<M> {
+    model: M,
+    recorder: Recorder,
+    name: Option<String>,
+}
+
+impl<M> RecordedModel<M> {
+    /// Wrap a model to record every request with the recorder.
+    pub fn new(model: M, recorder: Recorder) -> Self {
+        Self {
+            model,
+            recorder,
+            name: None,
+        }
+    }
+
+    /// Set the model name included in each record.
+    pub fn with_model_name(mut self, name: impl ToString) -> Self {
+        self.name = Some(name.to_string());
+        self
+    }
+
+    /// Get the model this wrapper records.
+    pub fn model(&self) -> &M {
+        &self.model
+    }
+
+    fn pending<S: 'static>(
+        &self,
+        prompt: Option<String>,
+        messages: Option<Vec<ChatMessage>>,
+        sampler: &S,
+        structured: bool,
+    ) -> PendingRecord {
+        let timestamp_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_millis() as u64)
+            .unwrap_or_default();
+        let parameters = (sampler as &dyn Any)
+            .downcast_ref::<GenerationParameters>()
+            .map(RecordedParameters::from);
+        PendingRecord {
+            recorder: self.recorder.clone(),
+            record: Record {
+                model: self.name.clone(),
+                timestamp_ms,
+                prompt,
+                messages,
+                parameters,
+                structured,
+                completion: String::new(),
+            },
+            completion: Default::default(),
+        }
+    }
+}
+
+impl<M: CreateTextCompletionSession> CreateTextCompletionSession for RecordedModel<M> {
+    type Error = M::Error;
+    type Session = M::Session;
+
+    fn new_session(&self) -> Result<Self::Session, Self::Error> {
+        self.model.new_session()
+    }
+}
+
+impl<S: 'static, M: TextCompletionModel<S>> TextCompletionModel<S> for RecordedModel<M> {
+    fn stream_text_with_callback<'a>(
+        &'a self,
+        session: &'a mut Self::Session,
+        text: &str,
+        sampler: S,
+        on_token: impl FnMut(String) -> Result<(), Self::Error> + Send + Sync + 'static,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send + 'a {
+        let pending = self.pending(Some(text.to_string()), None, &sampler, false);
+        let generation = self.model.stream_text_with_callback(
+            session,
+            text,
+            sampler,
+            pending.on_token(on_token),
+        );
+        async move {
+            let result = generation.await;
+            if result.is_ok() {
+                pending.finish();
+            }
+            result
+        }
+    }
+}
+
+impl<Constraints, S, M> StructuredTextCompletionModel<Constraints, S> for RecordedModel<M>
+where
+    Constraints: ModelConstraints,
+    S: 'static,
+    M: StructuredTextCompletionModel<Constraints, S>,
+{
+    fn stream_text_with_callback_and_parser<'a>(
+        &'a self,
+        session: &'a mut Self::Session,
+        text: &str,
+        sampler: S,
+        parser: Constraints,
+        on_token: impl FnMut(String) -> Result<(), Self::Error> + Send + Sync + 'static,
+    ) -> impl Future<Output = Result<Constraints::Output, Self::Error>> + Send + 'a {
+        let pending = self.pending(Some(text.to_string()), None, &sampler, true);
+        let generation = self.model.stream_text_with_callback_and_parser(
+            session,
+            text,
+            sampler,
+            parser,
+            pending.on_token(on_token),
+        );
+        async move {
+            let result = generation.await;
+            if result.is_ok() {
+                pending.finish();
+            }
+            result
+        }
+    }
+}
+
+impl<T, M> CreateDefaultCompletionConstraintsForType<T> for RecordedModel<M>
+where
+    M: CreateDefaultCompletionConstraintsForType<T>,
+{
+    type DefaultConstraints = M::DefaultConstraints;
+
+    fn create_default_constraints() -> Self::DefaultConstraints {
+        M::create_default_constraints()
+    }
+}
+
+impl<M: CreateChatSession> CreateChatSession for RecordedModel<M> {
+    type Error = M::Error;
+    type ChatSession = M::ChatSession;
+
+    fn new_chat_session(&self) -> Result<Self::ChatSession, Self::Error> {
+        self.model.new_chat_session()
+    }
+}
+
+fn chat_history(session: &impl ChatSession, messages: &[ChatMessage]) -> Vec<ChatMessage> {
+    let mut history = session.history();
+    history.extend_from_slice(messages);
+    history
+}
+
+impl<S: 'static, M: ChatModel<S>> ChatModel<S> for RecordedModel<M> {
+    fn add_messages_with_callback<'a>(
+        &'a self,
+        session: &'a mut Self::ChatSession,
+        messages: &[ChatMessage],
+        sampler: S,
+        on_token: impl FnMut(String) -> Result<(), Self::Error> + Send + Sync + 'static,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send + 'a {
+        let history = chat_history(session, messages);
+        let pending = self.pending(None, Some(history), &sampler, false);
+        let generation = self.model.add_messages_with_callback(
+            session,
+            messages,
+            sampler,
+            pending.on_token(on_token),
+        );
+        async move {
+            let result = generation.await;
+            if result.is_ok() {
+                pending.finish();
+            }
+            result
+        }
+    }
+}
+
+impl<Constraints, S, M> StructuredChatModel<Constraints, S> for RecordedModel<M>
+where
+    Constraints: ModelConstraints,
+    S: 'static,
+    M: StructuredChatModel<Constraints, S>,
+{
+    fn add_message_with_callback_and_constraints<'a>(
+        &'a self,
+        session: &'a mut Self::ChatSession,
+        messages: &[ChatMessage],
+        sampler: S,
+        constraints: Constraints,
+        on_token: impl FnMut(String) -> Result<(), Self::Error> + Send + Sync + 'static,
+    ) -> impl Future<Output = Result<Constraints::Output, Self::Error>> + Send + 'a {
+        let history = chat_history(session, messages);
+        let pending = self.pending(None, Some(history), &sampler, true);
+        let generation = self.model.add_message_with_callback_and_constraints(
+            session,
+            messages,
+            sampler,
+            constraints,
+            pending.on_token(on_token),
+        );
+        async move {
+            let result = generation.await;
+            if result.is_ok() {
+                pending.finish();
+            }
+            result
+        }
+    }
+}
+
+impl<T, M> CreateDefaultChatConstraintsForType<T> for RecordedModel<M>
+where
+    M: CreateDefaultChatConstraintsForType<T>,
+{
+    type DefaultConstraints = M::DefaultConstraints;
+
+    fn create_default_constraints() -> Self::DefaultConstraints {
+        M::create_default_constraints()
+    }
+}
+
+#[test]
+fn test_record_redaction() {
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let buffer = SharedBuffer::default();
+    let recorder = Recorder::new(buffer.clone()).with_redaction(|record| {
+        record.completion = record.completion.replace("secret", "[redacted]")
+    });
+    let model = RecordedModel::new((), recorder).with_model_name("echo");
+    for prompt in ["my secret prompt", "another prompt"] {
+        let pending = model.pending(
+            Some(prompt.to_string()),
+            None,
+            &GenerationParameters::new().with_seed(1),
+            false,
+        );
+        let mut on_token = pending.on_token(|_| Ok::<_, ()>(()));
+        for word in prompt.split_inclusive(' ') {
+            on_token(word.to_string()).unwrap();
+        }
+        pending.finish();
+    }
+
+    let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+    let records = output
+        .lines()
+        .map(|line| serde_json::from_str::<Record>(line).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0].model.as_deref(), Some("echo"));
+    assert_eq!(records[0].prompt.as_deref(), Some("my secret prompt"));
+    assert_eq!(records[0].completion, "my [redacted] prompt");
+    assert_eq!(records[0].parameters.as_ref().unwrap().seed, Some(1));
+    assert_eq!(records[1].completion, "another prompt");
+}
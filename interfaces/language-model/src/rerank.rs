@@ -0,0 +1,75 @@
+use std::future::Future;
+
+/// The relevance score a [`Reranker`] assigned to one of the documents it was given.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RerankedDocument {
+    /// The index of the document in the list of documents passed to the reranker.
+    pub index: usize,
+    /// The relevance of the document to the query. Higher scores are more relevant.
+    pub score: f32,
+}
+
+/// A model that can score how relevant a set of documents is to a query. Rerankers (typically cross-encoders) are
+/// slower but more accurate than comparing embeddings, so they are usually run on the top results of a vector search.
+pub trait Reranker: Send + Sync + 'static {
+    /// The error type that can occur when reranking documents.
+    type Error: Send + Sync + 'static;
+
+    /// Score each document against the query. Returns the documents sorted from the most to the least relevant.
+    fn rerank_vec(
+        &self,
+        query: String,
+        documents: Vec<String>,
+    ) -> impl Future<Output = Result<Vec<RerankedDocument>, Self::Error>> + Send;
+}
+
+/// An extension trait for [`Reranker`] with helper methods.
+pub trait RerankerExt: Reranker {
+    /// Score each document against the query. Returns the documents sorted from the most to the least relevant.
+    ///
+    /// # Example
+    /// ```rust, no_run
+    /// use kalosm::language::*;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let reranker = TeiReranker::builder()
+    ///     .with_client(TeiClient::new().with_base_url("http://localhost:8080"))
+    ///     .build();
+    /// let documents = ["Paris is the capital of France", "Cats are great pets"];
+    /// let ranked = reranker
+    ///     .rerank("What is the capital of France?", documents)
+    ///     .await?;
+    /// println!("Most relevant: {}", documents[ranked[0].index]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn rerank(
+        &self,
+        query: impl ToString,
+        documents: impl IntoIterator<Item = impl ToString>,
+    ) -> impl Future<Output = Result<Vec<RerankedDocument>, Self::Error>> + Send {
+        let documents = documents
+            .into_iter()
+            .map(|document| document.to_string())
+            .collect::<Vec<_>>();
+        self.rerank_vec(query.to_string(), documents)
+    }
+
+    /// Score each document against the query and return the `k` most relevant documents.
+    fn rerank_top_k(
+        &self,
+        query: impl ToString,
+        documents: impl IntoIterator<Item = impl ToString>,
+        k: usize,
+    ) -> impl Future<Output = Result<Vec<RerankedDocument>, Self::Error>> + Send {
+        let future = self.rerank(query, documents);
+        async move {
+            let mut ranked = future.await?;
+            ranked.truncate(k);
+            Ok(ranked)
+        }
+    }
+}
+
+impl<R: Reranker> RerankerExt for R {}
@@ -0,0 +1,224 @@
+use super::{TeiClient, TeiError, TruncationDirection};
+use crate::{Embedder, Embedding, EmbeddingInput, EmbeddingVariant, ModelBuilder};
+use kalosm_model_types::ModelLoadingProgress;
+
+/// An embedder that uses a remote [Text Embeddings Inference](https://github.com/huggingface/text-embeddings-inference) server.
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm::language::*;
+///
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let model = TeiEmbeddingModel::builder()
+///     .with_client(TeiClient::new().with_base_url("http://localhost:8080"))
+///     .with_truncate(true)
+///     .build();
+/// let embedding = model.embed("Hello, world!").await?;
+/// println!("{:?}", embedding);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct TeiEmbeddingModel {
+    client: TeiClient,
+    truncate: bool,
+    truncation_direction: TruncationDirection,
+    normalize: bool,
+    query_prompt_name: Option<String>,
+    document_prompt_name: Option<String>,
+    max_batch_size: usize,
+}
+
+impl TeiEmbeddingModel {
+    /// Create a new builder for [`TeiEmbeddingModel`]
+    pub fn builder() -> TeiEmbeddingModelBuilder {
+        TeiEmbeddingModelBuilder::new()
+    }
+
+    /// Embed a batch of strings with the same prompt name.
+    async fn embed_batch(
+        &self,
+        inputs: Vec<String>,
+        prompt_name: Option<&str>,
+    ) -> Result<Vec<Embedding>, TeiError> {
+        let mut embeddings = Vec::with_capacity(inputs.len());
+        for chunk in inputs.chunks(self.max_batch_size.max(1)) {
+            let mut body = serde_json::json!({
+                "inputs": chunk,
+                "truncate": self.truncate,
+                "truncation_direction": self.truncation_direction.as_str(),
+                "normalize": self.normalize,
+            });
+            if let Some(prompt_name) = prompt_name {
+                body["prompt_name"] = prompt_name.into();
+            }
+            let response: Vec<Vec<f32>> = self.client.post("embed", &body).await?;
+            if response.len() != chunk.len() {
+                return Err(TeiError::InvalidResponse);
+            }
+            embeddings.extend(response.into_iter().map(Embedding::from));
+        }
+
+        Ok(embeddings)
+    }
+
+    fn prompt_name(&self, variant: EmbeddingVariant) -> Option<&str> {
+        match variant {
+            EmbeddingVariant::Query => self.query_prompt_name.as_deref(),
+            EmbeddingVariant::Document => self.document_prompt_name.as_deref(),
+        }
+    }
+}
+
+/// A builder for a [`TeiEmbeddingModel`].
+#[derive(Debug)]
+pub struct TeiEmbeddingModelBuilder {
+    client: TeiClient,
+    truncate: bool,
+    truncation_direction: TruncationDirection,
+    normalize: bool,
+    query_prompt_name: Option<String>,
+    document_prompt_name: Option<String>,
+    max_batch_size: usize,
+}
+
+impl Default for TeiEmbeddingModelBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TeiEmbeddingModelBuilder {
+    /// Creates a new builder
+    pub fn new() -> Self {
+        Self {
+            client: Default::default(),
+            truncate: false,
+            truncation_direction: TruncationDirection::Right,
+            normalize: true,
+            query_prompt_name: None,
+            document_prompt_name: None,
+            max_batch_size: 32,
+        }
+    }
+
+    /// Set the client used to make requests to the server.
+    pub fn with_client(mut self, client: TeiClient) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Truncate inputs that are longer than the maximum input length of the model instead of returning an error. (defaults to false)
+    pub fn with_truncate(mut self, truncate: bool) -> Self {
+        self.truncate = truncate;
+        self
+    }
+
+    /// Set the direction inputs are truncated from if truncation is enabled. (defaults to [`TruncationDirection::Right`])
+    pub fn with_truncation_direction(mut self, direction: TruncationDirection) -> Self {
+        self.truncation_direction = direction;
+        self
+    }
+
+    /// Normalize the embeddings the server returns to unit length. (defaults to true)
+    pub fn with_normalize(mut self, normalize: bool) -> Self {
+        self.normalize = normalize;
+        self
+    }
+
+    /// Set the name of the prompt the server should prepend to [`EmbeddingVariant::Query`] inputs. The prompt must be configured in the server's sentence transformers config.
+    pub fn with_query_prompt_name(mut self, prompt_name: impl ToString) -> Self {
+        self.query_prompt_name = Some(prompt_name.to_string());
+        self
+    }
+
+    /// Set the name of the prompt the server should prepend to [`EmbeddingVariant::Document`] inputs. The prompt must be configured in the server's sentence transformers config.
+    pub fn with_document_prompt_name(mut self, prompt_name: impl ToString) -> Self {
+        self.document_prompt_name = Some(prompt_name.to_string());
+        self
+    }
+
+    /// Set the maximum number of inputs sent to the server in a single request. Larger batches are split into multiple requests. (defaults to 32)
+    ///
+    /// This should be at most the `--max-client-batch-size` the server was started with.
+    pub fn with_max_batch_size(mut self, max_batch_size: usize) -> Self {
+        self.max_batch_size = max_batch_size;
+        self
+    }
+
+    /// Build the model.
+    pub fn build(self) -> TeiEmbeddingModel {
+        TeiEmbeddingModel {
+            client: self.client,
+            truncate: self.truncate,
+            truncation_direction: self.truncation_direction,
+            normalize: self.normalize,
+            query_prompt_name: self.query_prompt_name,
+            document_prompt_name: self.document_prompt_name,
+            max_batch_size: self.max_batch_size,
+        }
+    }
+}
+
+impl ModelBuilder for TeiEmbeddingModelBuilder {
+    type Model = TeiEmbeddingModel;
+    type Error = std::convert::Infallible;
+
+    async fn start_with_loading_handler(
+        self,
+        _: impl FnMut(ModelLoadingProgress) + Send + Sync + 'static,
+    ) -> Result<Self::Model, Self::Error> {
+        Ok(self.build())
+    }
+
+    fn requires_download(&self) -> bool {
+        false
+    }
+}
+
+impl Embedder for TeiEmbeddingModel {
+    type Error = TeiError;
+
+    async fn embed_for(&self, input: EmbeddingInput) -> Result<Embedding, Self::Error> {
+        let prompt_name = self.prompt_name(input.variant);
+        let mut embeddings = self.embed_batch(vec![input.text], prompt_name).await?;
+        embeddings.pop().ok_or(TeiError::InvalidResponse)
+    }
+
+    async fn embed_vec_for(
+        &self,
+        inputs: Vec<EmbeddingInput>,
+    ) -> Result<Vec<Embedding>, Self::Error> {
+        // Queries and documents may use different prompts, so they are sent in separate requests
+        let (queries, documents): (Vec<_>, Vec<_>) = inputs
+            .into_iter()
+            .enumerate()
+            .partition(|(_, input)| input.variant == EmbeddingVariant::Query);
+
+        let mut embeddings = vec![Embedding::from([]); queries.len() + documents.len()];
+        for (variant, group) in [
+            (EmbeddingVariant::Query, queries),
+            (EmbeddingVariant::Document, documents),
+        ] {
+            if group.is_empty() {
+                continue;
+            }
+            let (indices, texts): (Vec<_>, Vec<_>) = group
+                .into_iter()
+                .map(|(index, input)| (index, input.text))
+                .unzip();
+            let group_embeddings = self.embed_batch(texts, self.prompt_name(variant)).await?;
+            for (index, embedding) in indices.into_iter().zip(group_embeddings) {
+                embeddings[index] = embedding;
+            }
+        }
+
+        Ok(embeddings)
+    }
+
+    async fn embed_vec(&self, inputs: Vec<String>) -> Result<Vec<Embedding>, Self::Error> {
+        let prompt_name = self.prompt_name(EmbeddingVariant::Document);
+        self.embed_batch(inputs, prompt_name).await
+    }
+}
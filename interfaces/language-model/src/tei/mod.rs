@@ -0,0 +1,137 @@
+use thiserror::Error;
+
+mod embedding;
+pub use embedding::*;
+
+mod rerank;
+pub use rerank::*;
+
+/// A client for making requests to a [Text Embeddings Inference](https://github.com/huggingface/text-embeddings-inference) compatible server.
+#[derive(Debug, Clone)]
+pub struct TeiClient {
+    reqwest_client: reqwest::Client,
+    base_url: String,
+    api_key: Option<String>,
+}
+
+impl Default for TeiClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TeiClient {
+    /// Create a new client.
+    pub fn new() -> Self {
+        Self {
+            reqwest_client: reqwest::Client::new(),
+            base_url: "http://localhost:8080/".to_string(),
+            api_key: None,
+        }
+    }
+
+    /// Sets the API key that is sent as a bearer token with every request. (defaults to the environment variable `TEI_API_KEY` if it is set)
+    ///
+    /// Local servers don't need an API key, but Hugging Face inference endpoints and servers started with `--api-key` do.
+    pub fn with_api_key(mut self, api_key: impl ToString) -> Self {
+        self.api_key = Some(api_key.to_string());
+        self
+    }
+
+    /// Set the base URL of the server. (defaults to `http://localhost:8080/`)
+    pub fn with_base_url(mut self, base_url: impl ToString) -> Self {
+        self.base_url = base_url.to_string();
+        self
+    }
+
+    /// Set the reqwest client for the builder.
+    pub fn with_reqwest_client(mut self, client: reqwest::Client) -> Self {
+        self.reqwest_client = client;
+        self
+    }
+
+    /// Get the base URL for the server.
+    pub(crate) fn base_url(&self) -> &str {
+        self.base_url.trim_end_matches('/')
+    }
+
+    /// Send a json request to the given route and deserialize the response.
+    pub(crate) async fn post<T: serde::de::DeserializeOwned>(
+        &self,
+        route: &str,
+        body: &serde_json::Value,
+    ) -> Result<T, TeiError> {
+        let mut request = self
+            .reqwest_client
+            .post(format!("{}/{route}", self.base_url()))
+            .json(body);
+        let api_key = self
+            .api_key
+            .clone()
+            .or_else(|| std::env::var("TEI_API_KEY").ok());
+        if let Some(api_key) = api_key {
+            request = request.bearer_auth(api_key);
+        }
+        let response = request.send().await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let message = response
+                .json::<TeiErrorResponse>()
+                .await
+                .map(|error| error.error)
+                .unwrap_or_else(|_| status.to_string());
+            return Err(TeiError::Server {
+                status: status.as_u16(),
+                message,
+            });
+        }
+
+        Ok(response.json().await?)
+    }
+}
+
+/// The direction text is truncated from when it is longer than the maximum input length of the model.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TruncationDirection {
+    /// Remove tokens from the start of the text.
+    Left,
+    /// Remove tokens from the end of the text.
+    #[default]
+    Right,
+}
+
+impl TruncationDirection {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            Self::Left => "Left",
+            Self::Right => "Right",
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct TeiErrorResponse {
+    error: String,
+}
+
+/// An error that can occur when making a request to a Text Embeddings Inference server.
+#[derive(Error, Debug)]
+pub enum TeiError {
+    /// An error occurred while making a request to the server.
+    #[error("Error making request: {0}")]
+    ReqwestError(#[from] reqwest::Error),
+    /// The server returned an error.
+    #[error("The server returned an error ({status}): {message}")]
+    Server {
+        /// The http status code of the response.
+        status: u16,
+        /// The error message the server returned.
+        message: String,
+    },
+    /// The response from the server was not in the format kalosm expected.
+    #[error(
+        "Invalid response from the server. The response did not contain a result for every input"
+    )]
+    InvalidResponse,
+}
@@ -0,0 +1,152 @@
+use super::{TeiClient, TeiError, TruncationDirection};
+use crate::{ModelBuilder, RerankedDocument, Reranker};
+use kalosm_model_types::ModelLoadingProgress;
+use serde::Deserialize;
+
+/// A reranker that uses the `/rerank` route of a remote [Text Embeddings Inference](https://github.com/huggingface/text-embeddings-inference) server running a cross-encoder model.
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm::language::*;
+///
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let reranker = TeiReranker::builder()
+///     .with_client(TeiClient::new().with_base_url("http://localhost:8080"))
+///     .build();
+/// let ranked = reranker
+///     .rerank(
+///         "What is deep learning?",
+///         ["Deep learning is a subset of machine learning", "Cheese is made from milk"],
+///     )
+///     .await?;
+/// println!("{:?}", ranked);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct TeiReranker {
+    client: TeiClient,
+    truncate: bool,
+    truncation_direction: TruncationDirection,
+    raw_scores: bool,
+}
+
+impl TeiReranker {
+    /// Create a new builder for [`TeiReranker`]
+    pub fn builder() -> TeiRerankerBuilder {
+        TeiRerankerBuilder::new()
+    }
+}
+
+/// A builder for a [`TeiReranker`].
+#[derive(Debug, Default)]
+pub struct TeiRerankerBuilder {
+    client: TeiClient,
+    truncate: bool,
+    truncation_direction: TruncationDirection,
+    raw_scores: bool,
+}
+
+impl TeiRerankerBuilder {
+    /// Creates a new builder
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the client used to make requests to the server.
+    pub fn with_client(mut self, client: TeiClient) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Truncate query and document pairs that are longer than the maximum input length of the model instead of returning an error. (defaults to false)
+    pub fn with_truncate(mut self, truncate: bool) -> Self {
+        self.truncate = truncate;
+        self
+    }
+
+    /// Set the direction inputs are truncated from if truncation is enabled. (defaults to [`TruncationDirection::Right`])
+    pub fn with_truncation_direction(mut self, direction: TruncationDirection) -> Self {
+        self.truncation_direction = direction;
+        self
+    }
+
+    /// Return the raw logits of the model instead of scores passed through a sigmoid. (defaults to false)
+    pub fn with_raw_scores(mut self, raw_scores: bool) -> Self {
+        self.raw_scores = raw_scores;
+        self
+    }
+
+    /// Build the reranker.
+    pub fn build(self) -> TeiReranker {
+        TeiReranker {
+            client: self.client,
+            truncate: self.truncate,
+            truncation_direction: self.truncation_direction,
+            raw_scores: self.raw_scores,
+        }
+    }
+}
+
+impl ModelBuilder for TeiRerankerBuilder {
+    type Model = TeiReranker;
+    type Error = std::convert::Infallible;
+
+    async fn start_with_loading_handler(
+        self,
+        _: impl FnMut(ModelLoadingProgress) + Send + Sync + 'static,
+    ) -> Result<Self::Model, Self::Error> {
+        Ok(self.build())
+    }
+
+    fn requires_download(&self) -> bool {
+        false
+    }
+}
+
+#[derive(Deserialize)]
+struct RerankResponse {
+    index: usize,
+    score: f32,
+}
+
+impl Reranker for TeiReranker {
+    type Error = TeiError;
+
+    async fn rerank_vec(
+        &self,
+        query: String,
+        documents: Vec<String>,
+    ) -> Result<Vec<RerankedDocument>, Self::Error> {
+        if documents.is_empty() {
+            return Ok(Vec::new());
+        }
+        let document_count = documents.len();
+        let body = serde_json::json!({
+            "query": query,
+            "texts": documents,
+            "truncate": self.truncate,
+            "truncation_direction": self.truncation_direction.as_str(),
+            "raw_scores": self.raw_scores,
+            "return_text": false,
+        });
+        let response: Vec<RerankResponse> = self.client.post("rerank", &body).await?;
+        if response.len() != document_count
+            || response.iter().any(|result| result.index >= document_count)
+        {
+            return Err(TeiError::InvalidResponse);
+        }
+
+        let mut ranked = response
+            .into_iter()
+            .map(|result| RerankedDocument {
+                index: result.index,
+                score: result.score,
+            })
+            .collect::<Vec<_>>();
+        ranked.sort_by(|a, b| b.score.total_cmp(&a.score));
+
+        Ok(ranked)
+    }
+}
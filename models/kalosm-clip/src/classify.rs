@@ -0,0 +1,107 @@
+use candle_core::{IndexOp, Tensor};
+use candle_nn::ops::softmax_last_dim;
+use image::DynamicImage;
+use kalosm_common::maybe_autoreleasepool;
+
+use crate::{image_to_tensor, Clip, ClipError};
+
+/// The template used to turn labels into captions in [`Clip::classify`]
+pub const DEFAULT_CLASSIFICATION_TEMPLATE: &str = "a photo of a {}";
+
+impl Clip {
+    /// Classify an image into one of the labels without any training. Each label is inserted into the template "a photo of a {}" and compared to the image.
+    ///
+    /// Returns each label with the probability that it describes the image, sorted from the most to the least likely label.
+    ///
+    /// # Example
+    /// ```rust, no_run
+    /// use kalosm_clip::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> anyhow::Result<()> {
+    ///     let clip = Clip::new().await?;
+    ///     let scores = clip
+    ///         .classify(image::open("cat.jpg")?, ["cat", "dog", "car"])
+    ///         .await?;
+    ///     for (label, score) in scores {
+    ///         println!("{label}: {score}");
+    ///     }
+    ///
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn classify(
+        &self,
+        image: DynamicImage,
+        labels: impl IntoIterator<Item = impl ToString>,
+    ) -> Result<Vec<(String, f32)>, ClipError> {
+        self.classify_with_template(image, labels, DEFAULT_CLASSIFICATION_TEMPLATE)
+            .await
+    }
+
+    /// Classify an image into one of the labels with a custom template. Every `{}` in the template is replaced with the label. If the template doesn't contain `{}`, the label is appended to the template.
+    ///
+    /// Returns each label with the probability that it describes the image, sorted from the most to the least likely label.
+    pub async fn classify_with_template(
+        &self,
+        image: DynamicImage,
+        labels: impl IntoIterator<Item = impl ToString>,
+        template: &str,
+    ) -> Result<Vec<(String, f32)>, ClipError> {
+        let labels: Vec<String> = labels.into_iter().map(|label| label.to_string()).collect();
+        if labels.is_empty() {
+            return Ok(Vec::new());
+        }
+        let captions: Vec<String> = labels
+            .iter()
+            .map(|label| fill_template(template, label))
+            .collect();
+        let self_clone = self.clone();
+        let scores = tokio::task::spawn_blocking(move || {
+            let captions = captions.iter().map(|s| s.as_str()).collect::<Vec<_>>();
+            self_clone.classify_blocking(&image, &captions)
+        })
+        .await??;
+
+        let mut scores: Vec<_> = labels.into_iter().zip(scores).collect();
+        scores.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+        Ok(scores)
+    }
+
+    /// Find the probability of each caption describing the image on the current thread
+    fn classify_blocking(
+        &self,
+        image: &DynamicImage,
+        captions: &[&str],
+    ) -> Result<Vec<f32>, ClipError> {
+        let image = image_to_tensor(image, self.image_size, &self.device)?.unsqueeze(0)?;
+        let tokens = self.tokenize(captions)?;
+        // The model scales the similarities with the temperature it learned during training
+        let (_, logits_per_image) = maybe_autoreleasepool(|| self.model.forward(&image, &tokens))?;
+        let probabilities: Tensor = softmax_last_dim(&logits_per_image)?;
+        Ok(probabilities.i(0)?.to_vec1()?)
+    }
+}
+
+/// Insert a label into a classification template
+fn fill_template(template: &str, label: &str) -> String {
+    if template.contains("{}") {
+        template.replace("{}", label)
+    } else {
+        format!("{template} {label}")
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn fills_templates() {
+    assert_eq!(
+        fill_template(DEFAULT_CLASSIFICATION_TEMPLATE, "cat"),
+        "a photo of a cat"
+    );
+    assert_eq!(
+        fill_template("{} or not {}", "hot dog"),
+        "hot dog or not hot dog"
+    );
+    assert_eq!(fill_template("a drawing of", "tree"), "a drawing of tree");
+}
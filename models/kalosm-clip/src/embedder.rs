@@ -0,0 +1,143 @@
+use std::future::Future;
+use std::sync::Arc;
+
+use image::DynamicImage;
+pub use kalosm_language_model::{Embedder, EmbedderExt, Embedding, EmbeddingInput};
+
+use crate::{Clip, ClipError};
+
+/// A model that can be used to embed images. Models that implement both [`ImageEmbedder`] and [`Embedder`] embed images and text into the same vector space, so the embeddings can be compared and stored in the same vector database.
+///
+/// # Example
+///
+/// ```rust, no_run
+/// use kalosm_clip::*;
+///
+/// #[tokio::main]
+/// async fn main() -> anyhow::Result<()> {
+///     // Clip implements ImageEmbedder
+///     let clip = Clip::new().await?;
+///     let embedding = clip.embed_image(image::open("cat.jpg")?).await?;
+///     println!("embedding {:?}", embedding);
+///
+///     Ok(())
+/// }
+/// ```
+pub trait ImageEmbedder: Send + Sync + 'static {
+    /// The error type that can occur when embedding an image.
+    type Error: Send + Sync + 'static;
+
+    /// Embed an image into a vector space.
+    fn embed_image(
+        &self,
+        image: DynamicImage,
+    ) -> impl Future<Output = Result<Embedding, Self::Error>> + Send;
+
+    /// Embed a [`Vec<DynamicImage>`] into a vector space. Returns a list of embeddings in the same order as the inputs.
+    fn embed_image_vec(
+        &self,
+        images: Vec<DynamicImage>,
+    ) -> impl Future<Output = Result<Vec<Embedding>, Self::Error>> + Send {
+        async move {
+            let mut embeddings = Vec::with_capacity(images.len());
+            for image in images {
+                embeddings.push(self.embed_image(image).await?);
+            }
+            Ok(embeddings)
+        }
+    }
+}
+
+impl<E: ImageEmbedder> ImageEmbedder for Arc<E> {
+    type Error = E::Error;
+
+    fn embed_image(
+        &self,
+        image: DynamicImage,
+    ) -> impl Future<Output = Result<Embedding, Self::Error>> + Send {
+        E::embed_image(self, image)
+    }
+
+    fn embed_image_vec(
+        &self,
+        images: Vec<DynamicImage>,
+    ) -> impl Future<Output = Result<Vec<Embedding>, Self::Error>> + Send {
+        E::embed_image_vec(self, images)
+    }
+}
+
+/// An extension trait for [`ImageEmbedder`] with helper methods for iterators.
+///
+/// This trait is automatically implemented for any item that implements [`ImageEmbedder`].
+pub trait ImageEmbedderExt: ImageEmbedder {
+    /// Embed a batch of images into a vector space. Returns a list of embeddings in the same order as the inputs.
+    fn embed_image_batch(
+        &self,
+        images: impl IntoIterator<Item = impl Into<DynamicImage>>,
+    ) -> impl Future<Output = Result<Vec<Embedding>, Self::Error>> + Send {
+        let images = images.into_iter().map(Into::into).collect::<Vec<_>>();
+        self.embed_image_vec(images)
+    }
+}
+
+impl<E: ImageEmbedder> ImageEmbedderExt for E {}
+
+impl ImageEmbedder for Clip {
+    type Error = ClipError;
+
+    async fn embed_image(&self, image: DynamicImage) -> Result<Embedding, Self::Error> {
+        let mut embeddings = self.embed_image_vec(vec![image]).await?;
+        Ok(embeddings.pop().unwrap())
+    }
+
+    async fn embed_image_vec(
+        &self,
+        images: Vec<DynamicImage>,
+    ) -> Result<Vec<Embedding>, Self::Error> {
+        let self_clone = self.clone();
+        tokio::task::spawn_blocking(move || self_clone.embed_images_blocking(&images)).await?
+    }
+}
+
+impl Embedder for Clip {
+    type Error = ClipError;
+
+    fn embed_for(
+        &self,
+        input: EmbeddingInput,
+    ) -> impl Future<Output = Result<Embedding, Self::Error>> + Send {
+        // CLIP embeds queries and documents the same way
+        self.embed_string(input.text)
+    }
+
+    fn embed_vec_for(
+        &self,
+        inputs: Vec<EmbeddingInput>,
+    ) -> impl Future<Output = Result<Vec<Embedding>, Self::Error>> + Send {
+        self.embed_vec(inputs.into_iter().map(|input| input.text).collect())
+    }
+
+    async fn embed_string(&self, input: String) -> Result<Embedding, Self::Error> {
+        let mut embeddings = self.embed_vec(vec![input]).await?;
+        Ok(embeddings.pop().unwrap())
+    }
+
+    async fn embed_vec(&self, inputs: Vec<String>) -> Result<Vec<Embedding>, Self::Error> {
+        let self_clone = self.clone();
+        tokio::task::spawn_blocking(move || {
+            let inputs_borrowed = inputs.iter().map(|s| s.as_str()).collect::<Vec<_>>();
+            self_clone.embed_texts_blocking(&inputs_borrowed)
+        })
+        .await?
+    }
+}
+
+impl Clip {
+    /// Embed some text into the same vector space as [`ImageEmbedder::embed_image`]
+    pub fn embed_text(
+        &self,
+        text: impl ToString,
+    ) -> impl Future<Output = Result<Embedding, ClipError>> + Send + '_ {
+        self.embed_string(text.to_string())
+    }
+}
@@ -0,0 +1,343 @@
+//! # Kalosm CLIP
+//!
+//! A rust wrapper for [CLIP](https://openai.com/research/clip) image and text embeddings implemented in [Candle](https://github.com/huggingface/candle)
+//!
+//! CLIP embeds images and text into the same vector space, so you can search images with text, or find the text that best describes an image. The embeddings are normalized and can be stored in the same vector database as text embeddings.
+//!
+//! ## Usage
+//!
+//! ```rust, no_run
+//! use kalosm_clip::*;
+//!
+//! #[tokio::main]
+//! async fn main() -> anyhow::Result<()> {
+//!     let clip = Clip::new().await?;
+//!     let image = clip
+//!         .embed_image(image::open("mountain.jpg")?)
+//!         .await?;
+//!     let captions = clip
+//!         .embed_batch(["a photo of a mountain", "a photo of a cat"])
+//!         .await?;
+//!     for (caption, embedding) in ["a mountain", "a cat"].iter().zip(captions) {
+//!         println!("{caption}: {}", image.cosine_similarity(&embedding));
+//!     }
+//!
+//!     Ok(())
+//! }
+//! ```
+
+#![warn(missing_docs)]
+
+#[cfg(feature = "mkl")]
+extern crate intel_mkl_src;
+
+#[cfg(feature = "accelerate")]
+extern crate accelerate_src;
+
+use std::sync::Arc;
+
+use candle_core::{DType, Device, Tensor};
+use candle_nn::VarBuilder;
+use candle_transformers::models::clip::{div_l2_norm, ClipModel};
+use image::DynamicImage;
+use kalosm_common::*;
+use kalosm_model_types::ModelLoadingProgress;
+use tokenizers::Tokenizer;
+
+mod classify;
+mod embedder;
+mod source;
+
+pub use crate::classify::*;
+pub use crate::embedder::*;
+pub use crate::source::*;
+
+/// The mean of each channel of the images CLIP was trained on
+const IMAGE_MEAN: [f32; 3] = [0.481_454_66, 0.457_827_5, 0.408_210_73];
+/// The standard deviation of each channel of the images CLIP was trained on
+const IMAGE_STD: [f32; 3] = [0.268_629_54, 0.261_302_6, 0.275_777_1];
+/// The token that marks the end of the text. CLIP uses the embedding of this token as the embedding of the text
+const END_OF_TEXT: &str = "<|endoftext|>";
+
+/// A builder for a [`Clip`] model
+#[derive(Default)]
+pub struct ClipBuilder {
+    source: ClipSource,
+    cache: Cache,
+}
+
+impl ClipBuilder {
+    /// Set the source of the model
+    pub fn with_source(mut self, source: ClipSource) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// Set the cache location to use for the model (defaults DATA_DIR/kalosm/cache)
+    pub fn with_cache(mut self, cache: Cache) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    /// Build the model
+    pub async fn build(self) -> Result<Clip, ClipLoadingError> {
+        self.build_with_loading_handler(ModelLoadingProgress::multi_bar_loading_indicator())
+            .await
+    }
+
+    /// Build the model with a loading handler
+    pub async fn build_with_loading_handler(
+        self,
+        loading_handler: impl FnMut(ModelLoadingProgress) + Send + 'static,
+    ) -> Result<Clip, ClipLoadingError> {
+        Clip::from_builder(self, loading_handler).await
+    }
+}
+
+/// An error that can occur when loading a [`Clip`] model.
+#[derive(Debug, thiserror::Error)]
+pub enum ClipLoadingError {
+    /// An error that can occur when trying to load a [`Clip`] model from huggingface or a local file.
+    #[error("Failed to load model from huggingface or local file: {0}")]
+    DownloadingError(#[from] CacheError),
+    /// An error that can occur when trying to load a [`Clip`] model into a device.
+    #[error("Failed to load model into device: {0}")]
+    LoadModel(#[from] candle_core::Error),
+    /// An error that can occur when trying to load the tokenizer.
+    #[error("Failed to load tokenizer: {0}")]
+    LoadTokenizer(tokenizers::Error),
+    /// The tokenizer doesn't contain the end of text token
+    #[error("The tokenizer doesn't contain the end of text token")]
+    MissingEndOfTextToken,
+}
+
+/// An error that can occur when running a [`Clip`] model.
+#[derive(Debug, thiserror::Error)]
+pub enum ClipError {
+    /// An error that can occur when trying to run a [`Clip`] model.
+    #[error("Failed to run model: {0}")]
+    Candle(#[from] candle_core::Error),
+    /// An error that can occur when tokenizing text.
+    #[error("Failed to tokenize: {0}")]
+    TokenizerError(tokenizers::Error),
+    /// Failed to join the thread that is running the model
+    #[error("Failed to join thread: {0}")]
+    Join(#[from] tokio::task::JoinError),
+}
+
+/// A CLIP model that embeds images and text into a shared vector space. Images are embedded with [`ImageEmbedder`] and text is embedded with [`Embedder`].
+///
+/// # Example
+/// ```rust, no_run
+/// use kalosm_clip::*;
+///
+/// #[tokio::main]
+/// async fn main() -> anyhow::Result<()> {
+///     let clip = Clip::new().await?;
+///     let images = clip
+///         .embed_image_batch([
+///             image::open("cat.jpg")?,
+///             image::open("dog.jpg")?,
+///         ])
+///         .await?;
+///     let query = clip.embed_text("a sleeping cat").await?;
+///     for (i, image) in images.iter().enumerate() {
+///         println!("image {i}: {}", query.cosine_similarity(image));
+///     }
+///
+///     Ok(())
+/// }
+/// ```
+#[derive(Clone)]
+pub struct Clip {
+    model: Arc<ClipModel>,
+    tokenizer: Arc<Tokenizer>,
+    device: Device,
+    image_size: usize,
+    max_tokens: usize,
+    end_of_text: u32,
+}
+
+impl Clip {
+    /// Create a new [`ClipBuilder`]
+    pub fn builder() -> ClipBuilder {
+        ClipBuilder::default()
+    }
+
+    /// Create a new default CLIP model
+    pub async fn new() -> Result<Self, ClipLoadingError> {
+        Self::builder().build().await
+    }
+
+    async fn from_builder(
+        builder: ClipBuilder,
+        progress_handler: impl FnMut(ModelLoadingProgress) + Send + 'static,
+    ) -> Result<Self, ClipLoadingError> {
+        let ClipBuilder { source, cache } = builder;
+        let ClipSource {
+            config,
+            tokenizer,
+            model,
+        } = source;
+        cache.ensure_available([&tokenizer, &model])?;
+
+        // The tokenizer and weights are reported as a single download with a total size that is known before either file starts
+        let size = cache.download_size([&tokenizer, &model]).await;
+        let mut progress_handler = ModelLoadingProgress::aggregate_with_size(
+            format!("Model ({})", model),
+            size,
+            progress_handler,
+        );
+
+        let tokenizer_source = format!("Tokenizer ({})", tokenizer);
+        let mut create_progress = ModelLoadingProgress::downloading_progress(tokenizer_source);
+        let tokenizer_file = cache
+            .get(&tokenizer, |progress| {
+                progress_handler(create_progress(progress))
+            })
+            .await?;
+        let model_source = format!("Model ({})", model);
+        let mut create_progress = ModelLoadingProgress::downloading_progress(model_source);
+        let weights_file = cache
+            .get(&model, |progress| {
+                progress_handler(create_progress(progress))
+            })
+            .await?;
+
+        let tokenizer =
+            Tokenizer::from_file(tokenizer_file).map_err(ClipLoadingError::LoadTokenizer)?;
+        let end_of_text = tokenizer
+            .token_to_id(END_OF_TEXT)
+            .ok_or(ClipLoadingError::MissingEndOfTextToken)?;
+
+        let device = accelerated_device_if_available()?;
+        let vb =
+            unsafe { VarBuilder::from_mmaped_safetensors(&[weights_file], DType::F32, &device)? };
+        let model = ClipModel::new(vb, &config)?;
+
+        Ok(Self {
+            model: Arc::new(model),
+            tokenizer: Arc::new(tokenizer),
+            device,
+            image_size: config.image_size,
+            max_tokens: config.text_config.max_position_embeddings,
+            end_of_text,
+        })
+    }
+
+    /// Embed a batch of images on the current thread
+    pub(crate) fn embed_images_blocking(
+        &self,
+        images: &[DynamicImage],
+    ) -> Result<Vec<Embedding>, ClipError> {
+        if images.is_empty() {
+            return Ok(Vec::new());
+        }
+        let images = images
+            .iter()
+            .map(|image| image_to_tensor(image, self.image_size, &self.device))
+            .collect::<candle_core::Result<Vec<_>>>()?;
+        let images = Tensor::stack(&images, 0)?;
+        let embeddings = maybe_autoreleasepool(|| self.model.get_image_features(&images))?;
+        to_embeddings(&embeddings)
+    }
+
+    /// Embed a batch of text on the current thread
+    pub(crate) fn embed_texts_blocking(&self, texts: &[&str]) -> Result<Vec<Embedding>, ClipError> {
+        if texts.is_empty() {
+            return Ok(Vec::new());
+        }
+        let tokens = self.tokenize(texts)?;
+        let embeddings = maybe_autoreleasepool(|| self.model.get_text_features(&tokens))?;
+        to_embeddings(&embeddings)
+    }
+
+    /// Tokenize a batch of text into a tensor of token ids padded to the same length
+    fn tokenize(&self, texts: &[&str]) -> Result<Tensor, ClipError> {
+        let encodings = self
+            .tokenizer
+            .encode_batch(texts.to_vec(), true)
+            .map_err(ClipError::TokenizerError)?;
+        let mut tokens: Vec<Vec<u32>> = encodings
+            .iter()
+            .map(|encoding| {
+                let mut ids = encoding.get_ids().to_vec();
+                // Keep the end of text token if the text is too long because the text embedding is read from it
+                if ids.len() > self.max_tokens {
+                    ids.truncate(self.max_tokens - 1);
+                    ids.push(self.end_of_text);
+                }
+                ids
+            })
+            .collect();
+        // Pad with the end of text token. The model uses the first end of text token in each sequence
+        let longest = tokens.iter().map(Vec::len).max().unwrap_or_default();
+        for ids in &mut tokens {
+            ids.resize(longest, self.end_of_text);
+        }
+        let tokens = tokens
+            .iter()
+            .map(|ids| Tensor::new(ids.as_slice(), &self.device))
+            .collect::<candle_core::Result<Vec<_>>>()?;
+        Ok(Tensor::stack(&tokens, 0)?)
+    }
+}
+
+/// Resize and crop an image to the input size of the model and normalize the pixels
+fn image_to_tensor(
+    image: &DynamicImage,
+    size: usize,
+    device: &Device,
+) -> candle_core::Result<Tensor> {
+    let image = image
+        .resize_to_fill(
+            size as u32,
+            size as u32,
+            image::imageops::FilterType::CatmullRom,
+        )
+        .to_rgb8();
+    let data = image.into_raw();
+    let image = Tensor::from_vec(data, (size, size, 3), device)?
+        .permute((2, 0, 1))?
+        .to_dtype(DType::F32)?
+        .affine(1. / 255., 0.)?;
+    let mean = Tensor::new(&IMAGE_MEAN, device)?.reshape((3, 1, 1))?;
+    let std = Tensor::new(&IMAGE_STD, device)?.reshape((3, 1, 1))?;
+    image.broadcast_sub(&mean)?.broadcast_div(&std)
+}
+
+/// Normalize a batch of features and split them into embeddings
+fn to_embeddings(features: &Tensor) -> Result<Vec<Embedding>, ClipError> {
+    Ok(div_l2_norm(features)?
+        .to_vec2::<f32>()?
+        .into_iter()
+        .map(Embedding::from)
+        .collect())
+}
+
+#[cfg(test)]
+#[test]
+fn normalizes_images() {
+    let image = DynamicImage::ImageRgb8(image::RgbImage::from_pixel(
+        400,
+        100,
+        image::Rgb([255, 0, 255]),
+    ));
+    let tensor = image_to_tensor(&image, 224, &Device::Cpu).unwrap();
+    assert_eq!(tensor.dims(), &[3, 224, 224]);
+    let pixel: Vec<f32> = tensor
+        .flatten_from(1)
+        .unwrap()
+        .max(1)
+        .unwrap()
+        .to_vec1()
+        .unwrap();
+    let expected = [
+        (1. - IMAGE_MEAN[0]) / IMAGE_STD[0],
+        -IMAGE_MEAN[1] / IMAGE_STD[1],
+        (1. - IMAGE_MEAN[2]) / IMAGE_STD[2],
+    ];
+    for (pixel, expected) in pixel.iter().zip(expected) {
+        assert!((pixel - expected).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,99 @@
+use candle_transformers::models::clip::{
+    text_model::{Activation, ClipTextConfig},
+    vision_model::ClipVisionConfig,
+    ClipConfig,
+};
+use kalosm_model_types::FileSource;
+
+/// The source of a [`crate::Clip`] model
+pub struct ClipSource {
+    pub(crate) config: ClipConfig,
+    pub(crate) tokenizer: FileSource,
+    pub(crate) model: FileSource,
+}
+
+impl ClipSource {
+    /// Create a new [`ClipSource`] with the default model
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the model to use. The model must have the same architecture as the preset this source was created from
+    pub fn with_model(mut self, model: FileSource) -> Self {
+        self.model = model;
+        self
+    }
+
+    /// Set the tokenizer to use
+    pub fn with_tokenizer(mut self, tokenizer: FileSource) -> Self {
+        self.tokenizer = tokenizer;
+        self
+    }
+
+    /// Create a new [`ClipSource`] with the [clip-vit-base-patch32](https://huggingface.co/openai/clip-vit-base-patch32) model
+    pub fn clip_vit_base_patch32() -> Self {
+        Self {
+            config: ClipConfig::vit_base_patch32(),
+            tokenizer: FileSource::huggingface(
+                "openai/clip-vit-base-patch32".to_string(),
+                "refs/pr/15".to_string(),
+                "tokenizer.json".to_string(),
+            ),
+            model: FileSource::huggingface(
+                "openai/clip-vit-base-patch32".to_string(),
+                "refs/pr/15".to_string(),
+                "model.safetensors".to_string(),
+            ),
+        }
+    }
+
+    /// Create a new [`ClipSource`] with the [clip-vit-large-patch14](https://huggingface.co/openai/clip-vit-large-patch14) model
+    ///
+    /// This model is much larger than [`Self::clip_vit_base_patch32`], but produces more accurate embeddings.
+    pub fn clip_vit_large_patch14() -> Self {
+        Self {
+            config: ClipConfig {
+                text_config: ClipTextConfig {
+                    vocab_size: 49408,
+                    embed_dim: 768,
+                    activation: Activation::QuickGelu,
+                    intermediate_size: 3072,
+                    max_position_embeddings: 77,
+                    pad_with: None,
+                    num_hidden_layers: 12,
+                    num_attention_heads: 12,
+                    projection_dim: 768,
+                },
+                vision_config: ClipVisionConfig {
+                    embed_dim: 1024,
+                    activation: Activation::QuickGelu,
+                    intermediate_size: 4096,
+                    num_hidden_layers: 24,
+                    num_attention_heads: 16,
+                    projection_dim: 768,
+                    num_channels: 3,
+                    image_size: 224,
+                    patch_size: 14,
+                },
+                logit_scale_init_value: 2.6592,
+                image_size: 224,
+            },
+            tokenizer: FileSource::huggingface(
+                "openai/clip-vit-large-patch14".to_string(),
+                "main".to_string(),
+                "tokenizer.json".to_string(),
+            ),
+            model: FileSource::huggingface(
+                "openai/clip-vit-large-patch14".to_string(),
+                "main".to_string(),
+                "model.safetensors".to_string(),
+            ),
+        }
+    }
+}
+
+impl Default for ClipSource {
+    fn default() -> Self {
+        Self::clip_vit_base_patch32()
+    }
+}
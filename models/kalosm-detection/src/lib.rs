@@ -0,0 +1,465 @@
+//! # Kalosm Detection
+//!
+//! A rust wrapper for [YOLOv8](https://docs.ultralytics.com/models/yolov8/) object detection implemented in [Candle](https://github.com/huggingface/candle)
+//!
+//! ## Usage
+//!
+//! ```rust, no_run
+//! # #[tokio::main]
+//! # async fn main() {
+//! use kalosm_detection::*;
+//!
+//! let model = ObjectDetector::builder().build().await.unwrap();
+//! let image = image::open("street.jpg").unwrap();
+//! let objects = model
+//!     .detect(ObjectDetectorInferenceSettings::new(image))
+//!     .unwrap();
+//!
+//! for object in objects {
+//!     println!(
+//!         "{} ({:.0}%) at {:?}",
+//!         object.label(),
+//!         object.confidence() * 100.,
+//!         object.bounding_box()
+//!     );
+//! }
+//! # }
+//! ```
+
+#![warn(missing_docs)]
+#[cfg(feature = "mkl")]
+extern crate intel_mkl_src;
+
+#[cfg(feature = "accelerate")]
+extern crate accelerate_src;
+
+mod coco;
+pub use coco::*;
+mod model;
+
+use candle_core::{DType, Device, IndexOp, Module, Tensor};
+use candle_nn::VarBuilder;
+use candle_transformers::object_detection::{non_maximum_suppression, Bbox};
+use image::{DynamicImage, GenericImage, GenericImageView, ImageBuffer, Rgba};
+use kalosm_common::*;
+use kalosm_model_types::{FileSource, ModelLoadingProgress};
+use model::{Multiples, YoloV8};
+
+/// The length of the longest side of the image the model sees
+const IMAGE_SIZE: usize = 640;
+
+/// A builder for [`ObjectDetector`].
+#[derive(Default)]
+pub struct ObjectDetectorBuilder {
+    source: ObjectDetectorSource,
+    cache: Cache,
+}
+
+impl ObjectDetectorBuilder {
+    /// Sets the source of the model.
+    pub fn with_source(mut self, source: ObjectDetectorSource) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// Set the cache location to use for the model (defaults DATA_DIR/kalosm/cache)
+    pub fn with_cache(mut self, cache: Cache) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    /// Builds the [`ObjectDetector`] model.
+    pub async fn build(self) -> Result<ObjectDetector, LoadObjectDetectorError> {
+        ObjectDetector::new(self, |_| {}).await
+    }
+
+    /// Builds the [`ObjectDetector`] model.
+    pub async fn build_with_loading_handler(
+        self,
+        handler: impl FnMut(ModelLoadingProgress) + Send + Sync + 'static,
+    ) -> Result<ObjectDetector, LoadObjectDetectorError> {
+        ObjectDetector::new(self, handler).await
+    }
+}
+
+/// The source of the model.
+pub struct ObjectDetectorSource {
+    model: FileSource,
+    multiples: Multiples,
+    labels: Vec<String>,
+}
+
+impl ObjectDetectorSource {
+    fn yolo_v8(size: &str, multiples: Multiples) -> Self {
+        Self {
+            model: FileSource::huggingface(
+                "lmz/candle-yolo-v8",
+                "main",
+                format!("yolov8{size}.safetensors"),
+            ),
+            multiples,
+            labels: COCO_CLASSES.iter().map(|label| label.to_string()).collect(),
+        }
+    }
+
+    /// Set the model weights. The model must have the same architecture as the preset this source was created from.
+    pub fn with_model(mut self, model: FileSource) -> Self {
+        self.model = model;
+        self
+    }
+
+    /// Set the labels of the classes the model predicts. Defaults to the [`COCO_CLASSES`]. Use this for models fine-tuned on a different set of classes.
+    pub fn with_labels(mut self, labels: impl IntoIterator<Item = impl ToString>) -> Self {
+        self.labels = labels.into_iter().map(|label| label.to_string()).collect();
+        self
+    }
+
+    /// Create the nano YOLOv8 model source. This is the smallest and fastest model.
+    pub fn yolo_v8_nano() -> Self {
+        Self::yolo_v8("n", Multiples::n())
+    }
+
+    /// Create the small YOLOv8 model source.
+    pub fn yolo_v8_small() -> Self {
+        Self::yolo_v8("s", Multiples::s())
+    }
+
+    /// Create the medium YOLOv8 model source.
+    pub fn yolo_v8_medium() -> Self {
+        Self::yolo_v8("m", Multiples::m())
+    }
+
+    /// Create the large YOLOv8 model source.
+    pub fn yolo_v8_large() -> Self {
+        Self::yolo_v8("l", Multiples::l())
+    }
+
+    /// Create the extra large YOLOv8 model source. This is the largest and most accurate model.
+    pub fn yolo_v8_extra_large() -> Self {
+        Self::yolo_v8("x", Multiples::x())
+    }
+}
+
+impl Default for ObjectDetectorSource {
+    fn default() -> Self {
+        Self::yolo_v8_nano()
+    }
+}
+
+/// Settings for running inference on [`ObjectDetector`].
+pub struct ObjectDetectorInferenceSettings {
+    image: ImageBuffer<image::Rgba<u8>, Vec<u8>>,
+    confidence_threshold: f32,
+    iou_threshold: f32,
+}
+
+impl ObjectDetectorInferenceSettings {
+    /// Creates a new [`ObjectDetectorInferenceSettings`] from an image.
+    pub fn new<I: GenericImageView<Pixel = Rgba<u8>>>(input: I) -> Self {
+        let mut image = ImageBuffer::new(input.width(), input.height());
+        image.copy_from(&input, 0, 0).unwrap();
+        Self {
+            image,
+            confidence_threshold: 0.25,
+            iou_threshold: 0.45,
+        }
+    }
+
+    /// Set the minimum confidence of the detected objects (between 0 and 1). Defaults to 0.25.
+    pub fn with_confidence_threshold(mut self, confidence_threshold: f32) -> Self {
+        self.confidence_threshold = confidence_threshold;
+        self
+    }
+
+    /// Set the maximum overlap (intersection over union) between two objects of the same class before the less confident object is removed. Defaults to 0.45.
+    pub fn with_iou_threshold(mut self, iou_threshold: f32) -> Self {
+        self.iou_threshold = iou_threshold;
+        self
+    }
+}
+
+/// An error that can occur when loading an [`ObjectDetector`] model.
+#[derive(Debug, thiserror::Error)]
+pub enum LoadObjectDetectorError {
+    /// An error that can occur when trying to load an [`ObjectDetector`] model into a device.
+    #[error("Failed to load model into device: {0}")]
+    LoadModel(#[from] candle_core::Error),
+    /// An error that can occur when downloading an [`ObjectDetector`] model from the cache.
+    #[error("Failed to download model: {0}")]
+    DownloadModel(#[from] CacheError),
+}
+
+/// An error that can occur when running an [`ObjectDetector`] model.
+#[derive(Debug, thiserror::Error)]
+pub enum ObjectDetectorInferenceError {
+    /// An error that can occur when trying to run an [`ObjectDetector`] model.
+    #[error("Failed to run model: {0}")]
+    RunModel(#[from] candle_core::Error),
+}
+
+/// The position of an object in an image. Coordinates are in pixels from the top left of the image.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ObjectBoundingBox {
+    /// The left edge of the box
+    pub x: f32,
+    /// The top edge of the box
+    pub y: f32,
+    /// The width of the box
+    pub width: f32,
+    /// The height of the box
+    pub height: f32,
+}
+
+impl ObjectBoundingBox {
+    /// Get the center of the box
+    pub fn center(&self) -> (f32, f32) {
+        (self.x + self.width / 2., self.y + self.height / 2.)
+    }
+}
+
+/// An object found by [`ObjectDetector::detect`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DetectedObject {
+    label: String,
+    class: usize,
+    confidence: f32,
+    bounding_box: ObjectBoundingBox,
+}
+
+impl DetectedObject {
+    /// Get the label of the object
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    /// Get the index of the class of the object in the labels of the model
+    pub fn class(&self) -> usize {
+        self.class
+    }
+
+    /// Get the confidence of the model in the object (between 0 and 1)
+    pub fn confidence(&self) -> f32 {
+        self.confidence
+    }
+
+    /// Get the position of the object in the image
+    pub fn bounding_box(&self) -> ObjectBoundingBox {
+        self.bounding_box
+    }
+
+    /// Crop the object out of the image it was detected in. This can be used to run other models like OCR or segmentation on just the object.
+    pub fn crop(&self, image: &DynamicImage) -> DynamicImage {
+        let ObjectBoundingBox {
+            x,
+            y,
+            width,
+            height,
+        } = self.bounding_box;
+        image.crop_imm(
+            x.round() as u32,
+            y.round() as u32,
+            width.round() as u32,
+            height.round() as u32,
+        )
+    }
+}
+
+/// A [YOLOv8](https://docs.ultralytics.com/models/yolov8/) object detection model.
+pub struct ObjectDetector {
+    device: Device,
+    model: YoloV8,
+    labels: Vec<String>,
+}
+
+impl ObjectDetector {
+    /// Creates a new [`ObjectDetectorBuilder`].
+    pub fn builder() -> ObjectDetectorBuilder {
+        ObjectDetectorBuilder::default()
+    }
+
+    async fn new(
+        settings: ObjectDetectorBuilder,
+        mut handler: impl FnMut(ModelLoadingProgress) + Send + Sync + 'static,
+    ) -> Result<Self, LoadObjectDetectorError> {
+        let ObjectDetectorBuilder { source, cache } = settings;
+        let ObjectDetectorSource {
+            model,
+            multiples,
+            labels,
+        } = source;
+        cache.ensure_available([&model])?;
+
+        let display_source = format!("Model ({})", model);
+        let mut create_progress = ModelLoadingProgress::downloading_progress(display_source);
+        let filename = cache
+            .get(&model, |progress| handler(create_progress(progress)))
+            .await?;
+
+        let device = accelerated_device_if_available()?;
+        let vb = unsafe { VarBuilder::from_mmaped_safetensors(&[filename], DType::F32, &device)? };
+        let model = YoloV8::load(vb, multiples, labels.len())?;
+
+        Ok(Self {
+            device,
+            model,
+            labels,
+        })
+    }
+
+    /// Find the objects in an image. Returns the objects sorted from most to least confident.
+    ///
+    /// # Example
+    /// ```rust, no_run
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// use kalosm_detection::*;
+    ///
+    /// let model = ObjectDetector::builder().build().await.unwrap();
+    /// let image = image::open("street.jpg").unwrap();
+    /// let objects = model
+    ///     .detect(ObjectDetectorInferenceSettings::new(image.clone()).with_confidence_threshold(0.5))
+    ///     .unwrap();
+    ///
+    /// // Save each person in the image to a separate file
+    /// for (i, object) in objects.iter().filter(|object| object.label() == "person").enumerate() {
+    ///     object.crop(&image).save(format!("person-{i}.png")).unwrap();
+    /// }
+    /// # }
+    /// ```
+    pub fn detect(
+        &self,
+        settings: ObjectDetectorInferenceSettings,
+    ) -> Result<Vec<DetectedObject>, ObjectDetectorInferenceError> {
+        let ObjectDetectorInferenceSettings {
+            image,
+            confidence_threshold,
+            iou_threshold,
+        } = settings;
+
+        let image = DynamicImage::ImageRgba8(image);
+        let (image_width, image_height) = (image.width() as f32, image.height() as f32);
+        let image_tensor = self.image_to_tensor(&image)?;
+        let (_, _, height, width) = image_tensor.dims4()?;
+
+        let predictions = maybe_autoreleasepool(|| self.model.forward(&image_tensor))?.i(0)?;
+        let boxes = decode_predictions(&predictions, confidence_threshold, iou_threshold)?;
+
+        // Scale the boxes from the size of the resized image back to the original image
+        let x_scale = image_width / width as f32;
+        let y_scale = image_height / height as f32;
+        Ok(boxes
+            .into_iter()
+            .map(|bbox| {
+                let left = (bbox.xmin * x_scale).clamp(0., image_width);
+                let top = (bbox.ymin * y_scale).clamp(0., image_height);
+                let right = (bbox.xmax * x_scale).clamp(0., image_width);
+                let bottom = (bbox.ymax * y_scale).clamp(0., image_height);
+                DetectedObject {
+                    label: self.labels.get(bbox.data).cloned().unwrap_or_default(),
+                    class: bbox.data,
+                    confidence: bbox.confidence,
+                    bounding_box: ObjectBoundingBox {
+                        x: left,
+                        y: top,
+                        width: right - left,
+                        height: bottom - top,
+                    },
+                }
+            })
+            .collect())
+    }
+
+    /// Resize the image so the longest side is 640 pixels and both sides are a multiple of 32
+    fn image_to_tensor(&self, image: &DynamicImage) -> candle_core::Result<Tensor> {
+        let (width, height) = {
+            let w = image.width() as usize;
+            let h = image.height() as usize;
+            if w < h {
+                let w = w * IMAGE_SIZE / h;
+                ((w / 32 * 32).max(32), IMAGE_SIZE)
+            } else {
+                let h = h * IMAGE_SIZE / w;
+                (IMAGE_SIZE, (h / 32 * 32).max(32))
+            }
+        };
+        let image = image.resize_exact(
+            width as u32,
+            height as u32,
+            image::imageops::FilterType::CatmullRom,
+        );
+        let data = image.to_rgb8().into_raw();
+        let image = Tensor::from_vec(data, (height, width, 3), &self.device)?.permute((2, 0, 1))?;
+        image.unsqueeze(0)?.to_dtype(DType::F32)? * (1. / 255.)
+    }
+}
+
+/// Turn the raw predictions of the model with the shape `(4 + classes, anchors)` into boxes sorted by confidence with the class index as the data
+fn decode_predictions(
+    predictions: &Tensor,
+    confidence_threshold: f32,
+    iou_threshold: f32,
+) -> candle_core::Result<Vec<Bbox<usize>>> {
+    let (outputs, anchors) = predictions.dims2()?;
+    let classes = outputs - 4;
+    let predictions = predictions.t()?.to_vec2::<f32>()?;
+
+    let mut boxes_per_class: Vec<Vec<Bbox<usize>>> = (0..classes).map(|_| Vec::new()).collect();
+    for prediction in predictions.iter().take(anchors) {
+        let Some((class, confidence)) = prediction[4..]
+            .iter()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        else {
+            continue;
+        };
+        if *confidence < confidence_threshold {
+            continue;
+        }
+        let (center_x, center_y, width, height) =
+            (prediction[0], prediction[1], prediction[2], prediction[3]);
+        boxes_per_class[class].push(Bbox {
+            xmin: center_x - width / 2.,
+            ymin: center_y - height / 2.,
+            xmax: center_x + width / 2.,
+            ymax: center_y + height / 2.,
+            confidence: *confidence,
+            data: class,
+        });
+    }
+
+    non_maximum_suppression(&mut boxes_per_class, iou_threshold);
+
+    let mut boxes: Vec<_> = boxes_per_class.into_iter().flatten().collect();
+    boxes.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+    Ok(boxes)
+}
+
+#[cfg(test)]
+#[test]
+fn decodes_and_suppresses_overlapping_boxes() {
+    // Each column is one anchor: center x, center y, width, height, then the probability of each class
+    #[rustfmt::skip]
+    let predictions = Tensor::new(
+        &[
+            [50f32, 52., 200., 300.],
+            [50., 50., 200., 300.],
+            [20., 20., 40., 40.],
+            [20., 20., 40., 40.],
+            [0.9, 0.8, 0.1, 0.1],
+            [0.1, 0.1, 0.7, 0.2],
+        ],
+        &Device::Cpu,
+    )
+    .unwrap();
+    let boxes = decode_predictions(&predictions, 0.25, 0.45).unwrap();
+    // The second box overlaps the first box of the same class and the last box isn't confident enough
+    assert_eq!(boxes.len(), 2);
+    assert_eq!(boxes[0].data, 0);
+    assert_eq!(boxes[0].confidence, 0.9);
+    assert_eq!(
+        (boxes[0].xmin, boxes[0].ymin, boxes[0].xmax, boxes[0].ymax),
+        (40., 40., 60., 60.)
+    );
+    assert_eq!(boxes[1].data, 1);
+    assert_eq!(boxes[1].confidence, 0.7);
+}
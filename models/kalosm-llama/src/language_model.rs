@@ -1,7 +1,8 @@
+use kalosm_common::metrics::Timer;
 use kalosm_language_model::{
     CreateDefaultChatConstraintsForType, CreateDefaultCompletionConstraintsForType,
     CreateTextCompletionSession, GenerationParameters, ModelBuilder, StructuredTextCompletionModel,
-    TextCompletionModel,
+    TextCompletionModel, TokenScore, TokenScorer,
 };
 use kalosm_model_types::ModelLoadingProgress;
 use kalosm_sample::{ArcParser, CreateParserState, Parse, Parser, ParserExt};
@@ -14,8 +15,8 @@ use crate::structured::generate_structured;
 pub use crate::Llama;
 use crate::LlamaBuilder;
 use crate::{
-    InferenceSettings, LlamaSession, LlamaSourceError, StructuredGenerationTask, Task,
-    UnstructuredGenerationTask,
+    InferenceSettings, LlamaSession, LlamaSourceError, ScoreTokensTask, StructuredGenerationTask,
+    Task, UnstructuredGenerationTask,
 };
 
 impl ModelBuilder for LlamaBuilder {
@@ -74,6 +75,7 @@ impl<S: Sampler + 'static> TextCompletionModel<S> for Llama {
             let on_token = Box::new(on_token);
             self.task_sender
                 .send(Task::UnstructuredGeneration(UnstructuredGenerationTask {
+                    queued: Timer::start(),
                     settings: InferenceSettings::new(
                         text,
                         session.clone(),
@@ -94,6 +96,22 @@ impl<S: Sampler + 'static> TextCompletionModel<S> for Llama {
     }
 }
 
+impl TokenScorer for Llama {
+    type Error = LlamaModelError;
+
+    async fn score_tokens(&self, text: &str) -> Result<Vec<TokenScore>, Self::Error> {
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        self.task_sender
+            .send(Task::ScoreTokens(ScoreTokensTask {
+                text: text.to_string(),
+                finished: tx,
+            }))
+            .map_err(|_| LlamaModelError::ModelStopped)?;
+
+        rx.await.map_err(|_| LlamaModelError::ModelStopped)?
+    }
+}
+
 impl<T: Parse + 'static> CreateDefaultChatConstraintsForType<T> for Llama {
     type DefaultConstraints = ArcParser<T>;
 
@@ -136,7 +154,8 @@ where
             let on_token = Box::new(on_token);
             self.task_sender
                 .send(Task::StructuredGeneration(StructuredGenerationTask {
-                    runner: Box::new(move |model| {
+                    queued: Timer::start(),
+                    runner: Box::new(move |model, mut metrics| {
                         let parser_state = parser.create_parser_state();
                         let result = generate_structured(
                             text,
@@ -148,6 +167,7 @@ where
                             on_token,
                             Some(64),
                             seed,
+                            &mut metrics,
                         );
                         _ = tx.send(result);
                     }),
@@ -48,8 +48,9 @@ use crate::model::LlamaModel;
 pub use crate::raw::cache::*;
 pub use crate::session::LlamaSession;
 use candle_core::Device;
+use kalosm_common::metrics::{GenerationMetrics, LoadMetrics, Timer};
 pub use kalosm_common::*;
-use kalosm_language_model::{TextCompletionBuilder, TextCompletionModelExt};
+use kalosm_language_model::{TextCompletionBuilder, TextCompletionModelExt, TokenScore};
 use kalosm_model_types::ModelLoadingProgress;
 use kalosm_sample::{LiteralParser, StopOn};
 use model::LlamaModelError;
@@ -59,6 +60,7 @@ use std::mem::MaybeUninit;
 use std::ops::Deref;
 use std::sync::Arc;
 use tokenizers::Tokenizer;
+use tracing::Instrument;
 
 /// A prelude of commonly used items in kalosm-llama.
 pub mod prelude {
@@ -70,14 +72,22 @@ pub mod prelude {
 enum Task {
     UnstructuredGeneration(UnstructuredGenerationTask),
     StructuredGeneration(StructuredGenerationTask),
+    ScoreTokens(ScoreTokensTask),
 }
 
 struct StructuredGenerationTask {
-    runner: Box<dyn FnOnce(&mut LlamaModel) + Send>,
+    runner: Box<dyn FnOnce(&mut LlamaModel, GenerationMetrics) + Send>,
+    queued: Timer,
+}
+
+struct ScoreTokensTask {
+    text: String,
+    finished: tokio::sync::oneshot::Sender<Result<Vec<TokenScore>, LlamaModelError>>,
 }
 
 struct UnstructuredGenerationTask {
     settings: InferenceSettings,
+    queued: Timer,
     on_token: Box<dyn FnMut(String) -> Result<(), LlamaModelError> + Send + Sync>,
     finished: tokio::sync::oneshot::Sender<Result<(), LlamaModelError>>,
 }
@@ -137,17 +147,23 @@ impl Llama {
                     match task {
                         Task::UnstructuredGeneration(UnstructuredGenerationTask {
                             settings,
+                            queued,
                             on_token,
                             finished,
                         }) => {
-                            let result = model._infer(settings, on_token, &finished);
+                            let metrics = GenerationMetrics::start("llama", false, queued);
+                            let result = model._infer(settings, on_token, &finished, metrics);
                             if let Err(err) = &result {
                                 tracing::error!("Error running model: {err}");
                             }
                             _ = finished.send(result);
                         }
-                        Task::StructuredGeneration(StructuredGenerationTask { runner }) => {
-                            runner(&mut model);
+                        Task::StructuredGeneration(StructuredGenerationTask { runner, queued }) => {
+                            let metrics = GenerationMetrics::start("llama", true, queued);
+                            runner(&mut model, metrics);
+                        }
+                        Task::ScoreTokens(ScoreTokensTask { text, finished }) => {
+                            _ = finished.send(model.score_tokens(&text));
                         }
                     }
                 }
@@ -220,6 +236,8 @@ pub struct LlamaBuilder {
     source: source::LlamaSource,
     device: Option<Device>,
     flash_attn: bool,
+    weight_loading: WeightLoadingOptions,
+    constraint_debugging: bool,
 }
 
 impl LlamaBuilder {
@@ -241,6 +259,20 @@ impl LlamaBuilder {
         self
     }
 
+    /// Set how the weights of the model are read from disk. (Defaults to memory mapping the weights)
+    pub fn with_weight_loading(mut self, weight_loading: WeightLoadingOptions) -> Self {
+        self.weight_loading = weight_loading;
+        self
+    }
+
+    /// Set whether to record why tokens are masked during structured generation. (Defaults to false)
+    ///
+    /// When enabled, a [`kalosm_sample::ConstraintReport`] with the allowed and masked tokens is logged at the debug level for each token and structured generation fails with `LlamaModelError::ConstraintFailed` instead of `LlamaModelError::NoValidTokens` if the constraints can't be satisfied. Recording the reports is slower, so this should only be enabled while debugging constraints.
+    pub fn with_constraint_debugging(mut self, constraint_debugging: bool) -> Self {
+        self.constraint_debugging = constraint_debugging;
+        self
+    }
+
     /// Get the device or the default device if not set.
     pub(crate) fn get_device(&self) -> Result<Device, LlamaSourceError> {
         match self.device.clone() {
@@ -251,6 +283,8 @@ impl LlamaBuilder {
 
     /// Build the model with a handler for progress as the download and loading progresses.
     ///
+    /// The model and tokenizer downloads are reported as a single download. The total size includes every file that needs to be downloaded from the first progress update.
+    ///
     /// ```rust, no_run
     /// use kalosm::language::*;
     /// # #[tokio::main]
@@ -276,7 +310,11 @@ impl LlamaBuilder {
         self,
         handler: impl FnMut(ModelLoadingProgress) + Send + Sync + 'static,
     ) -> Result<Llama, LlamaSourceError> {
-        let model = LlamaModel::from_builder(self, handler).await?;
+        let metrics = LoadMetrics::start("llama");
+        let model = LlamaModel::from_builder(self, handler)
+            .instrument(metrics.span())
+            .await?;
+        metrics.finish();
 
         Ok(Llama::from_build(model))
     }
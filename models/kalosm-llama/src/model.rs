@@ -3,7 +3,9 @@ use crate::raw::cache::LlamaCache;
 use crate::raw::Model;
 use crate::token_stream::TokenOutputStream;
 use crate::token_stream::TokenOutputStreamError;
+use kalosm_common::metrics::{GenerationMetrics, Timer};
 use kalosm_common::*;
+use kalosm_language_model::TokenScore;
 use kalosm_model_types::ModelLoadingProgress;
 use llm_samplers::types::Logits;
 use std::collections::HashMap;
@@ -11,7 +13,7 @@ use std::sync::Arc;
 
 use candle_core::{
     quantized::{ggml_file, gguf_file},
-    DType, Device,
+    DType, Device, Tensor, D,
 };
 use tokenizers::Tokenizer;
 
@@ -44,6 +46,10 @@ pub enum LlamaModelError {
     #[error("No valid tokens were sampled")]
     NoValidTokens,
 
+    /// No valid tokens were sampled during structured generation with constraint debugging enabled. The report explains why each token was masked
+    #[error("No valid tokens were sampled\n{0}")]
+    ConstraintFailed(Box<kalosm_sample::ConstraintReport>),
+
     /// The model has already stopped.
     #[error("Model stopped")]
     ModelStopped,
@@ -57,11 +63,15 @@ pub enum LlamaModelError {
     ChatTemplateError(#[from] minijinja::Error),
 }
 
+/// The number of tokens scored at once in [`LlamaModel::score_tokens`]. The logits for every token in the window are kept in memory at the same time.
+const SCORE_WINDOW: usize = 512;
+
 /// The inner, synchronous Llama model.
 pub(crate) struct LlamaModel {
     pub(crate) model: Model,
     pub(crate) device: Device,
     pub(crate) tokenizer: Arc<Tokenizer>,
+    pub(crate) constraint_debugging: bool,
 }
 
 impl LlamaModel {
@@ -84,32 +94,85 @@ impl LlamaModel {
         Ok(())
     }
 
+    /// Score every token in the text. The text is scored in windows of at most [`SCORE_WINDOW`] tokens, so the first token of each window has no log probability.
+    pub(crate) fn score_tokens(&self, text: &str) -> Result<Vec<TokenScore>, LlamaModelError> {
+        let encoding = self
+            .tokenizer
+            .encode(text, false)
+            .map_err(LlamaModelError::Tokenizer)?;
+        let tokens = encoding.get_ids();
+        let offsets = encoding.get_offsets();
+        let window = SCORE_WINDOW.min(self.model.config.context_length).max(1);
+
+        let mut log_probs = Vec::with_capacity(tokens.len());
+        for chunk in tokens.chunks(window) {
+            log_probs.push(None);
+            if chunk.len() < 2 {
+                continue;
+            }
+            let logits = self
+                .model
+                .forward_all(chunk, &self.device)?
+                .squeeze(0)?
+                .to_dtype(DType::F32)?;
+            // Only the logits before the last token predict a token in the chunk
+            let logits = logits.narrow(0, 0, chunk.len() - 1)?;
+            let log_softmax = candle_nn::ops::log_softmax(&logits, D::Minus1)?;
+            let next_tokens = Tensor::new(&chunk[1..], &self.device)?.unsqueeze(1)?;
+            let chunk_log_probs = log_softmax
+                .gather(&next_tokens, 1)?
+                .squeeze(1)?
+                .to_vec1::<f32>()?;
+            log_probs.extend(chunk_log_probs.into_iter().map(Some));
+        }
+
+        Ok(offsets
+            .iter()
+            .zip(log_probs)
+            .map(|(&(start, end), log_prob)| TokenScore {
+                byte_range: start..end,
+                log_prob,
+            })
+            .collect())
+    }
+
     /// Create a new sync Llama model from a builder.
     pub(crate) async fn from_builder(
         builder: crate::LlamaBuilder,
-        mut handler: impl FnMut(ModelLoadingProgress) + Send + Sync + 'static,
+        handler: impl FnMut(ModelLoadingProgress) + Send + Sync + 'static,
     ) -> Result<Self, LlamaSourceError> {
         let device = builder.get_device()?;
+        let constraint_debugging = builder.constraint_debugging;
+        let sources = || std::iter::once(&builder.source.model).chain(&builder.source.tokenizer);
+        builder.source.cache.ensure_available(sources())?;
+
+        // The model and tokenizer are reported as a single download with a total size that is known before either file starts
+        let size = builder.source.cache.download_size(sources()).await;
+        let mut handler = ModelLoadingProgress::aggregate_with_size(
+            format!("Model ({})", builder.source.model),
+            size,
+            handler,
+        );
 
         // Download the model and tokenizer. These are relatively cheep operations that can be run in the async runtime
-        let tokenizer_path = match &builder.source.tokenizer {
+        let tokenizer_file = match &builder.source.tokenizer {
             Some(tokenizer) => {
                 let tokenizer_source = format!("Tokenizer ({})", tokenizer);
                 let mut create_progress =
                     ModelLoadingProgress::downloading_progress(tokenizer_source);
-                let tokenizer_path = builder
+                let tokenizer_file = builder
                     .source
                     .cache
-                    .get(tokenizer, |progress| handler(create_progress(progress)))
+                    .load(tokenizer, |progress| handler(create_progress(progress)))
                     .await?;
-                Some(tokenizer_path)
+                Some(tokenizer_file)
             }
             None => None,
         };
 
         let source = format!("Model ({})", builder.source.model);
         let mut create_progress = ModelLoadingProgress::downloading_progress(source);
-        let filename = builder
+        let model_file = builder
             .source
             .model(|progress| handler(create_progress(progress)))
             .await?;
@@ -118,19 +181,32 @@ impl LlamaModel {
         let (model, tokenizer) = tokio::task::spawn_blocking({
             let device = device.clone();
             move || {
-                let tokenizer = match tokenizer_path {
-                    Some(tokenizer_path) => {
-                        let tokenizer = Tokenizer::from_file(tokenizer_path)
-                            .map_err(LlamaSourceError::Tokenizer)?;
-                        Some(tokenizer)
-                    }
+                let tokenizer = match tokenizer_file {
+                    Some(CachedFile::Path(tokenizer_path)) => Some(
+                        Tokenizer::from_file(tokenizer_path)
+                            .map_err(LlamaSourceError::Tokenizer)?,
+                    ),
+                    Some(CachedFile::InMemory(tokenizer_file)) => Some(
+                        Tokenizer::from_bytes(tokenizer_file.as_bytes())
+                            .map_err(LlamaSourceError::Tokenizer)?,
+                    ),
                     None => None,
                 };
 
-                let mut file = std::fs::File::open(&filename)
-                    .expect("The path returned by LlamaSource::model should be valid");
+                let mut file = std::io::Cursor::new(
+                    model_file
+                        .load_weights(builder.weight_loading)
+                        .map_err(CacheError::from)?,
+                );
                 let override_stop_token_string = builder.source.override_stop_token_string;
-                match filename.extension().and_then(|v| v.to_str()) {
+                // In memory files don't have an extension, so gguf files are detected by their magic bytes instead
+                let extension = match &model_file {
+                    CachedFile::Path(path) => path.extension().and_then(|v| v.to_str()),
+                    CachedFile::InMemory(bytes) => {
+                        bytes.as_bytes().starts_with(b"GGUF").then_some("gguf")
+                    }
+                };
+                match extension {
                     Some("gguf") => {
                         let model = gguf_file::Content::read(&mut file)?;
                         let tokenizer = match tokenizer {
@@ -299,6 +375,7 @@ impl LlamaModel {
             model,
             tokenizer: Arc::new(tokenizer),
             device,
+            constraint_debugging,
         })
     }
 
@@ -307,6 +384,7 @@ impl LlamaModel {
         settings: InferenceSettings,
         mut on_token: Box<dyn FnMut(String) -> Result<(), LlamaModelError> + Send + Sync>,
         finished: &tokio::sync::oneshot::Sender<Result<(), LlamaModelError>>,
+        mut metrics: GenerationMetrics,
     ) -> Result<(), LlamaModelError> {
         let InferenceSettings {
             prompt,
@@ -342,6 +420,7 @@ impl LlamaModel {
             Some(&mut session),
             &mut logit_probs,
         )?;
+        metrics.prefill_finished(tokens.len());
         let mut logits = Logits::try_from_iter_top_k(logit_probs, 512)
             .expect("model output should be valid logits");
         // This stores a buffer of text that has been generated to check against the stop_on string. It should never be longer than the stop_on string.
@@ -353,9 +432,11 @@ impl LlamaModel {
         let mut logit_probs = Vec::new();
 
         'generate: while !finished.is_closed() && tokens_generated < max_tokens {
+            let sampling = Timer::start();
             let new_token = text_stream
                 .sample_token(&mut sampler, logits, stop_on.as_deref(), seed)
                 .map_err(LlamaModelError::TokenOutputStreamError)?;
+            metrics.sampler_finished(sampling);
             if new_token == stop_token {
                 tracing::trace!("Stopping on stop token");
                 break;
@@ -365,6 +446,7 @@ impl LlamaModel {
                 .map_err(LlamaModelError::TokenOutputStreamError)?
             {
                 tokens_generated += 1;
+                metrics.token_generated();
                 if let Some(stop_on) = stop_on_lowercase {
                     let lowercase = new_text.to_lowercase();
 
@@ -35,7 +35,7 @@ pub struct LlamaConfig {
     rope_freq_weight: Option<Tensor>,
     rope_theta: f32,
     pub(crate) context_length: usize,
-    head_dimension: usize,
+    pub(crate) head_dimension: usize,
     n_head: usize,
     pub(crate) n_layer: usize,
     pub(crate) start_token_string: String,
@@ -346,6 +346,31 @@ impl Model {
     }
 
     pub fn forward(
+        &self,
+        tokens: &[u32],
+        device: &Device,
+        cache: Option<&mut LlamaCache>,
+    ) -> Result<Tensor> {
+        let x = self.hidden_states(tokens, device, cache)?;
+        let seq_len = x.dim(1)?;
+        let x = x.i((.., seq_len - 1, ..))?;
+        self.output.forward(&x)
+    }
+
+    /// Run the model on the tokens without a cache and return the logits for every position. The tokens must fit in the context length of the model.
+    pub fn forward_all(&self, tokens: &[u32], device: &Device) -> Result<Tensor> {
+        if tokens.len() > self.config.context_length {
+            candle_core::bail!(
+                "Cannot run model on {} tokens with a context length of {}",
+                tokens.len(),
+                self.config.context_length
+            );
+        }
+        let x = self.hidden_states(tokens, device, None)?;
+        self.output.forward(&x)
+    }
+
+    fn hidden_states(
         &self,
         tokens: &[u32],
         device: &Device,
@@ -401,8 +426,6 @@ impl Model {
 
             layer_in = (&layer.feed_forward_variant.forward(&x)? + residual)?;
         }
-        let x = self.norm.forward(&layer_in)?;
-        let x = x.i((.., seq_len - 1, ..))?;
-        self.output.forward(&x)
+        self.norm.forward(&layer_in)
     }
 }
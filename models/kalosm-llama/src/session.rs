@@ -5,6 +5,8 @@ use kalosm_language_model::TextCompletionSession;
 use std::collections::HashMap;
 use std::sync::{Arc, RwLock};
 
+mod llama_cpp;
+
 /// An error that can occur when saving or loading a [`LlamaSession`].
 #[derive(Debug, thiserror::Error)]
 pub enum LlamaSessionLoadingError {
@@ -14,6 +16,9 @@ pub enum LlamaSessionLoadingError {
     /// An error from candle while loading or saving a [`LlamaSession`].
     #[error("Candle error: {0:?}")]
     Candle(#[from] candle_core::Error),
+    /// The session file from llama.cpp is invalid or uses a layout kalosm doesn't support.
+    #[error("Invalid llama.cpp session: {0}")]
+    InvalidLlamaCpp(String),
     /// The chat messages deserialized from the session are invalid.
     #[error("Chat messages deserialized from the session are invalid")]
     InvalidChatMessages,
@@ -0,0 +1,379 @@
+//! Conversion between [`LlamaSession`] and the session files llama.cpp saves with `llama_state_save_file` (or `--prompt-cache` in the cli).
+//!
+//! Only version 9 of the session format is supported. Each session must contain a single sequence and the key value cache must be stored as `f32`, `f16` or `bf16`.
+
+use std::collections::HashMap;
+
+use candle_core::{Device, Tensor};
+
+use super::{LlamaSession, LlamaSessionLoadingError};
+use crate::{accelerated_device_if_available, Llama};
+
+/// The magic number at the start of every llama.cpp session file ("ggsn")
+const SESSION_MAGIC: u32 = 0x6767736e;
+/// The version of the llama.cpp session format this module reads and writes
+const SESSION_VERSION: u32 = 9;
+
+const GGML_TYPE_F32: i32 = 0;
+const GGML_TYPE_F16: i32 = 1;
+const GGML_TYPE_BF16: i32 = 30;
+
+impl LlamaSession {
+    /// Export the session as a llama.cpp session file. The file can be loaded with `llama_state_load_file` or `--prompt-cache` in the llama.cpp cli with the same gguf model.
+    ///
+    /// The key value cache is written as `f16` with the values transposed, which matches the default cache of llama.cpp when flash attention is disabled.
+    ///
+    /// # Example
+    /// ```rust, no_run
+    /// use kalosm_llama::prelude::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let model = Llama::new().await.unwrap();
+    ///     let mut session = model.new_session().unwrap();
+    ///     model
+    ///         .stream_text_with_callback(&mut session, "The capital of France is", GenerationParameters::new().with_max_length(1), |_| Ok(()))
+    ///         .await
+    ///         .unwrap();
+    ///     std::fs::write("prompt.bin", session.to_llama_cpp_session().unwrap()).unwrap();
+    /// }
+    /// ```
+    pub fn to_llama_cpp_session(&self) -> Result<Vec<u8>, LlamaSessionLoadingError> {
+        let cache = self.cache.read().unwrap();
+        let mut cell_count = 0;
+        let mut layers = Vec::with_capacity(cache.blocks.len());
+        for block in &cache.blocks {
+            let (Some(keys), Some(values)) = (block.cache().k()?, block.cache().v()?) else {
+                // The session has not seen any tokens yet
+                layers.clear();
+                cell_count = 0;
+                break;
+            };
+            cell_count = keys.dim(2)?;
+            layers.push(LayerState {
+                keys: cell_major(&keys)?,
+                values: cell_major(&values)?,
+            });
+        }
+        let state = SessionState {
+            tokens: cache.tokens.clone(),
+            cell_count,
+            layers,
+        };
+        Ok(state.write())
+    }
+}
+
+impl Llama {
+    /// Import a session file saved by llama.cpp with `llama_state_save_file` or `--prompt-cache`. The session must be saved with the same gguf model as this model.
+    ///
+    /// # Example
+    /// ```rust, no_run
+    /// use kalosm_llama::prelude::*;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let model = Llama::new().await.unwrap();
+    ///     let bytes = std::fs::read("prompt.bin").unwrap();
+    ///     let mut session = model.session_from_llama_cpp(&bytes).unwrap();
+    ///     model
+    ///         .stream_text_with_callback(&mut session, " Paris", GenerationParameters::new(), |token| {
+    ///             print!("{token}");
+    ///             Ok(())
+    ///         })
+    ///         .await
+    ///         .unwrap();
+    /// }
+    /// ```
+    pub fn session_from_llama_cpp(
+        &self,
+        bytes: &[u8],
+    ) -> Result<LlamaSession, LlamaSessionLoadingError> {
+        let state = SessionState::read(bytes).map_err(LlamaSessionLoadingError::InvalidLlamaCpp)?;
+        let config = &self.config;
+        if state.layers.len() != config.n_layer {
+            return Err(LlamaSessionLoadingError::InvalidLlamaCpp(format!(
+                "the session has {} layers but the model has {}",
+                state.layers.len(),
+                config.n_layer
+            )));
+        }
+        if state.cell_count > config.context_length {
+            return Err(LlamaSessionLoadingError::InvalidLlamaCpp(format!(
+                "the session has {} tokens but the context length of the model is {}",
+                state.cell_count, config.context_length
+            )));
+        }
+
+        let device = accelerated_device_if_available()?;
+        let mut map = HashMap::new();
+        for (i, layer) in state.layers.into_iter().enumerate() {
+            let keys = head_major(layer.keys, state.cell_count, config.head_dimension, &device)?;
+            let values = head_major(
+                layer.values,
+                state.cell_count,
+                config.head_dimension,
+                &device,
+            )?;
+            map.insert(format!("llama.cache.blocks.{}.key", i), keys);
+            map.insert(format!("llama.cache.blocks.{}.value", i), values);
+        }
+        if !state.tokens.is_empty() {
+            // Tensor from iter panics or segfaults if the iterator is empty
+            map.insert(
+                "llama.cache.tokens".to_string(),
+                Tensor::from_iter(state.tokens, &device)?,
+            );
+        }
+        map.insert(
+            "llama.cache.max_seq_len".to_string(),
+            Tensor::new(config.context_length as u32, &device)?,
+        );
+        Ok(LlamaSession::from_tensor_map(map)?)
+    }
+}
+
+/// Convert a `[1, heads, cells, head_dim]` cache tensor into the `[cells, heads * head_dim]` layout llama.cpp uses
+fn cell_major(tensor: &Tensor) -> candle_core::Result<Vec<f32>> {
+    tensor
+        .squeeze(0)?
+        .transpose(0, 1)?
+        .flatten_all()?
+        .to_dtype(candle_core::DType::F32)?
+        .to_vec1()
+}
+
+/// Convert a `[cells, heads * head_dim]` cache from llama.cpp into the `[1, heads, cells, head_dim]` layout kalosm uses
+fn head_major(
+    data: Vec<f32>,
+    cell_count: usize,
+    head_dim: usize,
+    device: &Device,
+) -> Result<Tensor, LlamaSessionLoadingError> {
+    let embedding = data.len() / cell_count.max(1);
+    if !embedding.is_multiple_of(head_dim) {
+        return Err(LlamaSessionLoadingError::InvalidLlamaCpp(format!(
+            "the cache rows have {embedding} elements which is not a multiple of the head dimension {head_dim}"
+        )));
+    }
+    Ok(
+        Tensor::from_vec(data, (cell_count, embedding / head_dim, head_dim), device)?
+            .transpose(0, 1)?
+            .unsqueeze(0)?
+            .contiguous()?,
+    )
+}
+
+/// The key value cache of one layer with one row per cell
+#[derive(Debug, PartialEq)]
+struct LayerState {
+    keys: Vec<f32>,
+    values: Vec<f32>,
+}
+
+/// The parts of a llama.cpp session file kalosm uses
+#[derive(Debug, PartialEq)]
+struct SessionState {
+    tokens: Vec<u32>,
+    cell_count: usize,
+    layers: Vec<LayerState>,
+}
+
+impl SessionState {
+    fn write(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_u32(&mut out, SESSION_MAGIC);
+        write_u32(&mut out, SESSION_VERSION);
+        write_u32(&mut out, self.tokens.len() as u32);
+        for token in &self.tokens {
+            write_u32(&mut out, *token);
+        }
+        // kalosm doesn't keep the outputs of the last batch, so the output ids, logits and embeddings are empty
+        write_u32(&mut out, 0);
+        out.extend_from_slice(&0u64.to_le_bytes());
+        out.extend_from_slice(&0u64.to_le_bytes());
+
+        write_u32(&mut out, self.cell_count as u32);
+        for position in 0..self.cell_count {
+            out.extend_from_slice(&(position as i32).to_le_bytes());
+            // Every cell belongs to sequence 0
+            write_u32(&mut out, 1);
+            out.extend_from_slice(&0i32.to_le_bytes());
+        }
+
+        // The values are transposed
+        write_u32(&mut out, 1);
+        write_u32(&mut out, self.layers.len() as u32);
+        for layer in &self.layers {
+            let row = layer.keys.len() / self.cell_count.max(1);
+            out.extend_from_slice(&GGML_TYPE_F16.to_le_bytes());
+            out.extend_from_slice(&(row as u64 * 2).to_le_bytes());
+            write_f16(&mut out, layer.keys.iter().copied());
+        }
+        for layer in &self.layers {
+            let row = layer.values.len() / self.cell_count.max(1);
+            out.extend_from_slice(&GGML_TYPE_F16.to_le_bytes());
+            write_u32(&mut out, 2);
+            write_u32(&mut out, row as u32);
+            let transposed = (0..row).flat_map(|element| {
+                (0..self.cell_count).map(move |cell| layer.values[cell * row + element])
+            });
+            write_f16(&mut out, transposed);
+        }
+        out
+    }
+
+    fn read(bytes: &[u8]) -> Result<Self, String> {
+        let mut reader = Reader { bytes };
+        if reader.u32()? != SESSION_MAGIC {
+            return Err("the file is not a llama.cpp session file".to_string());
+        }
+        let version = reader.u32()?;
+        if version != SESSION_VERSION {
+            return Err(format!(
+                "version {version} of the session format is not supported, only version {SESSION_VERSION} is"
+            ));
+        }
+        let token_count = reader.u32()? as usize;
+        let tokens = (0..token_count)
+            .map(|_| reader.u32())
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let output_count = reader.u32()? as usize;
+        reader.take(output_count * 4)?;
+        let logits_size = reader.u64()? as usize;
+        reader.take(logits_size * 4)?;
+        let embeddings_size = reader.u64()? as usize;
+        reader.take(embeddings_size * 4)?;
+
+        let cell_count = reader.u32()? as usize;
+        for position in 0..cell_count {
+            if reader.i32()? != position as i32 {
+                return Err(
+                    "only sessions with cells in order from position 0 are supported".to_string(),
+                );
+            }
+            let sequence_count = reader.u32()?;
+            for _ in 0..sequence_count {
+                if reader.i32()? != 0 {
+                    return Err("only sessions with a single sequence are supported".to_string());
+                }
+            }
+        }
+
+        let values_transposed = reader.u32()? != 0;
+        let layer_count = reader.u32()? as usize;
+        let mut keys = Vec::with_capacity(layer_count);
+        for _ in 0..layer_count {
+            let ty = reader.i32()?;
+            let row_size = reader.u64()? as usize;
+            keys.push(reader.floats(ty, cell_count * row_size)?);
+        }
+        let mut layers = Vec::with_capacity(layer_count);
+        for keys in keys {
+            let values = if values_transposed {
+                let ty = reader.i32()?;
+                let element_size = reader.u32()? as usize;
+                let row = reader.u32()? as usize;
+                let transposed = reader.floats(ty, row * cell_count * element_size)?;
+                (0..cell_count)
+                    .flat_map(|cell| (0..row).map(move |element| element * cell_count + cell))
+                    .map(|index| transposed[index])
+                    .collect()
+            } else {
+                let ty = reader.i32()?;
+                let row_size = reader.u64()? as usize;
+                reader.floats(ty, cell_count * row_size)?
+            };
+            layers.push(LayerState { keys, values });
+        }
+
+        Ok(Self {
+            tokens,
+            cell_count,
+            layers,
+        })
+    }
+}
+
+fn write_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+fn write_f16(out: &mut Vec<u8>, values: impl Iterator<Item = f32>) {
+    for value in values {
+        out.extend_from_slice(&half::f16::from_f32(value).to_bits().to_le_bytes());
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if self.bytes.len() < len {
+            return Err("the session file ended early".to_string());
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn i32(&mut self) -> Result<i32, String> {
+        Ok(i32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    /// Read `len` bytes of a ggml tensor as floats
+    fn floats(&mut self, ty: i32, len: usize) -> Result<Vec<f32>, String> {
+        let bytes = self.take(len)?;
+        match ty {
+            GGML_TYPE_F32 => Ok(bytes
+                .chunks_exact(4)
+                .map(|chunk| f32::from_le_bytes(chunk.try_into().unwrap()))
+                .collect()),
+            GGML_TYPE_F16 => Ok(bytes
+                .chunks_exact(2)
+                .map(|chunk| half::f16::from_le_bytes(chunk.try_into().unwrap()).to_f32())
+                .collect()),
+            GGML_TYPE_BF16 => Ok(bytes
+                .chunks_exact(2)
+                .map(|chunk| half::bf16::from_le_bytes(chunk.try_into().unwrap()).to_f32())
+                .collect()),
+            _ => Err(format!(
+                "the cache is stored as ggml type {ty}, only f32, f16 and bf16 caches are supported"
+            )),
+        }
+    }
+}
+
+#[test]
+fn test_llama_cpp_session_round_trip() {
+    // Two cells with rows of three elements in two layers
+    let state = SessionState {
+        tokens: vec![1, 15043],
+        cell_count: 2,
+        layers: vec![
+            LayerState {
+                keys: vec![0.0, 0.5, 1.0, 1.5, 2.0, 2.5],
+                values: vec![-1.0, -2.0, -3.0, 4.0, 5.0, 6.0],
+            },
+            LayerState {
+                keys: vec![0.25, 0.75, 1.25, 1.75, 2.25, 2.75],
+                values: vec![10.0, 20.0, 30.0, 40.0, 50.0, 60.0],
+            },
+        ],
+    };
+    let bytes = state.write();
+    assert_eq!(&bytes[..4], b"nsgg");
+    assert_eq!(SessionState::read(&bytes).unwrap(), state);
+    assert!(SessionState::read(&bytes[..bytes.len() - 1]).is_err());
+}
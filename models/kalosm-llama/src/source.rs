@@ -1,6 +1,4 @@
-use std::path::PathBuf;
-
-use kalosm_common::CacheError;
+use kalosm_common::{CacheError, CachedFile};
 use kalosm_model_types::{FileLoadingProgress, FileSource};
 
 fn llama_tokenizer() -> FileSource {
@@ -124,9 +122,9 @@ impl LlamaSource {
     pub(crate) async fn model(
         &self,
         progress: impl FnMut(FileLoadingProgress),
-    ) -> Result<PathBuf, LlamaSourceError> {
-        let path = self.cache.get(&self.model, progress).await?;
-        Ok(path)
+    ) -> Result<CachedFile, LlamaSourceError> {
+        let file = self.cache.load(&self.model, progress).await?;
+        Ok(file)
     }
 
     /// A preset for Mistral7b
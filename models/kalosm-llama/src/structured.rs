@@ -1,11 +1,14 @@
+use kalosm_common::metrics::{GenerationMetrics, Timer};
 use kalosm_sample::CreateParserState;
-use kalosm_sample::{LiteralParser, ParseStatus, Parser, ParserExt};
+use kalosm_sample::{ConstraintReport, LiteralParser, ParseStatus, Parser, ParserExt};
 use llm_samplers::prelude::{Logit, Logits};
 use llm_samplers::types::{HasSamplerResources, Sampler, SamplerError};
 use rand::SeedableRng;
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
 use std::{
+    cell::RefCell,
     fmt::{Debug, Display, Formatter},
+    rc::Rc,
     sync::{Arc, Mutex},
 };
 use tokenizers::tokenizer::Tokenizer;
@@ -25,13 +28,24 @@ pub(crate) fn generate_structured<P: Parser>(
     mut on_token: impl FnMut(String) -> Result<(), LlamaModelError>,
     top_k: Option<usize>,
     seed: Option<u64>,
+    metrics: &mut GenerationMetrics,
 ) -> Result<P::Output, LlamaModelError> {
     let eos_token = llm.model.config.stop_token_string.clone();
-    let mut on_token = move |tok: String| {
-        if tok == eos_token {
-            return Ok(());
+    // The text that was generated so far. This is only tracked if constraint debugging is enabled
+    let generated = llm
+        .constraint_debugging
+        .then(|| Rc::new(RefCell::new(String::new())));
+    let mut on_token = {
+        let generated = generated.clone();
+        move |tok: String| {
+            if tok == eos_token {
+                return Ok(());
+            }
+            if let Some(generated) = &generated {
+                generated.borrow_mut().push_str(&tok);
+            }
+            on_token(tok)
         }
-        on_token(tok)
     };
     let mut session = session
         .cache
@@ -113,6 +127,8 @@ pub(crate) fn generate_structured<P: Parser>(
             Some(&mut *session),
             &mut logit_probs,
         )?;
+        metrics.prefill_finished(unprocessed_token_count);
+        let sampling = Timer::start();
         let resources = &mut SamplerResources {
             previous_tokens: tokens,
             rng: &mut rng,
@@ -133,6 +149,9 @@ pub(crate) fn generate_structured<P: Parser>(
         }
 
         let mut valid_tokens = false;
+        let mut report = generated
+            .as_ref()
+            .map(|generated| ConstraintReport::new(&parser, &parser_state, generated.borrow()));
 
         // If we don't have a top k, then we can just cache the entire detokenization
         if top_k.is_none() {
@@ -182,9 +201,20 @@ pub(crate) fn generate_structured<P: Parser>(
                 token_id, logit, ..
             } = logits_indexed[i];
             let Some(text) = token_cache.get(token_id as usize) else {
+                if let Some(report) = &mut report {
+                    report.mask(
+                        token_id,
+                        "",
+                        "The token can't be decoded after the current text",
+                    );
+                }
                 continue;
             };
-            if let Ok(result) = parser.parse(&parser_state, text.as_bytes()) {
+            let result = match &mut report {
+                Some(report) => report.check(&parser, &parser_state, token_id, text),
+                None => parser.parse(&parser_state, text.as_bytes()),
+            };
+            if let Ok(result) = result {
                 let parsed_bytes = match result {
                     ParseStatus::Finished { remaining, .. } => text.len() - remaining.len(),
                     ParseStatus::Incomplete { .. } => text.len(),
@@ -206,14 +236,23 @@ pub(crate) fn generate_structured<P: Parser>(
             }
         }
 
+        if let Some(report) = &report {
+            tracing::debug!("{report}");
+        }
+
         // If there are no valid tokens, return an error
         if !valid_tokens {
-            return Err(LlamaModelError::NoValidTokens);
+            return Err(match report {
+                Some(report) => LlamaModelError::ConstraintFailed(Box::new(report)),
+                None => LlamaModelError::NoValidTokens,
+            });
         }
         let token_id = sampler
             .sample_token(resources, &mut logits)
             .map_err(|err| LlamaModelError::SamplerError(err.into()))?
             .ok_or(LlamaModelError::NoValidTokens)?;
+        metrics.sampler_finished(sampling);
+        metrics.token_generated();
 
         unprocessed_token_count = 1;
         let (result, parsed_bytes) = state_map
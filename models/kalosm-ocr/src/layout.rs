@@ -0,0 +1,334 @@
+//! Finds lines and words of text in an image so each line can be recognized separately
+
+use image::{DynamicImage, GenericImageView, GrayImage, Luma, Rgba, RgbaImage};
+
+use crate::BoundingBox;
+
+/// The largest skew that is corrected, in degrees
+const MAX_SKEW: f32 = 10.;
+/// The step between the skew angles that are tried, in degrees
+const SKEW_STEP: f32 = 0.5;
+/// The padding added around each line before it is recognized, in pixels
+const LINE_PADDING: u32 = 4;
+
+/// An axis aligned rectangle in the deskewed image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Rect {
+    pub(crate) x: u32,
+    pub(crate) y: u32,
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+}
+
+impl Rect {
+    /// Split the rectangle horizontally into pieces with widths proportional to the weights
+    pub(crate) fn split_horizontally(&self, weights: &[usize]) -> Vec<Rect> {
+        let total = weights.iter().sum::<usize>().max(1) as f32;
+        let mut start = 0.;
+        weights
+            .iter()
+            .map(|weight| {
+                let end = start + *weight as f32 / total;
+                let x = self.x + (start * self.width as f32) as u32;
+                let right = self.x + (end * self.width as f32) as u32;
+                start = end;
+                Rect {
+                    x,
+                    y: self.y,
+                    width: right.saturating_sub(x).max(1),
+                    height: self.height,
+                }
+            })
+            .collect()
+    }
+}
+
+/// A line of text found in the image
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct LineLayout {
+    pub(crate) rect: Rect,
+    pub(crate) words: Vec<Rect>,
+}
+
+/// The lines of text in an image
+pub(crate) struct Layout {
+    /// The skew of the text in degrees clockwise
+    skew: f32,
+    deskewed: RgbaImage,
+    pub(crate) lines: Vec<LineLayout>,
+}
+
+impl Layout {
+    /// Find the lines and words of text in an image
+    pub(crate) fn analyze(image: &RgbaImage) -> Self {
+        let gray = DynamicImage::ImageRgba8(image.clone()).to_luma8();
+        let ink = ink_mask(&gray);
+        let skew = estimate_skew(&ink);
+        let (ink, deskewed) = if skew == 0. {
+            (ink, image.clone())
+        } else {
+            (
+                rotate(&ink, skew, Luma([0])),
+                rotate(image, skew, Rgba([255, 255, 255, 255])),
+            )
+        };
+        let mut lines = find_lines(&ink);
+        // If no text is found, recognize the whole image as one line
+        if lines.is_empty() {
+            let rect = Rect {
+                x: 0,
+                y: 0,
+                width: image.width(),
+                height: image.height(),
+            };
+            lines.push(LineLayout {
+                rect,
+                words: vec![rect],
+            });
+        }
+        Self {
+            skew,
+            deskewed,
+            lines,
+        }
+    }
+
+    /// Crop a line out of the deskewed image
+    pub(crate) fn crop(&self, rect: Rect) -> DynamicImage {
+        let x = rect.x.saturating_sub(LINE_PADDING);
+        let y = rect.y.saturating_sub(LINE_PADDING);
+        let width = (rect.x + rect.width + LINE_PADDING).min(self.deskewed.width()) - x;
+        let height = (rect.y + rect.height + LINE_PADDING).min(self.deskewed.height()) - y;
+        DynamicImage::ImageRgba8(self.deskewed.view(x, y, width, height).to_image())
+    }
+
+    /// Get the bounding box of a rectangle in the deskewed image in the coordinates of the original image
+    pub(crate) fn bounding_box(&self, rect: Rect) -> BoundingBox {
+        let center_x = self.deskewed.width() as f32 / 2.;
+        let center_y = self.deskewed.height() as f32 / 2.;
+        let x = rect.x as f32 + rect.width as f32 / 2. - center_x;
+        let y = rect.y as f32 + rect.height as f32 / 2. - center_y;
+        let (sin, cos) = self.skew.to_radians().sin_cos();
+        let rotated_x = x * cos - y * sin + center_x;
+        let rotated_y = x * sin + y * cos + center_y;
+        BoundingBox {
+            x: rotated_x - rect.width as f32 / 2.,
+            y: rotated_y - rect.height as f32 / 2.,
+            width: rect.width as f32,
+            height: rect.height as f32,
+            rotation: self.skew,
+        }
+    }
+}
+
+/// Find the pixels that are part of the text with Otsu's threshold. Text is assumed to be the minority of the image, so light text on a dark background is also handled
+fn ink_mask(gray: &GrayImage) -> GrayImage {
+    let mut histogram = [0usize; 256];
+    for pixel in gray.pixels() {
+        histogram[pixel.0[0] as usize] += 1;
+    }
+    let total = gray.pixels().len().max(1) as f64;
+    let sum: f64 = histogram
+        .iter()
+        .enumerate()
+        .map(|(value, count)| value as f64 * *count as f64)
+        .sum();
+    let mut threshold = 0;
+    let mut best_variance = 0.;
+    let mut background_weight = 0.;
+    let mut background_sum = 0.;
+    for (value, count) in histogram.iter().enumerate() {
+        background_weight += *count as f64;
+        background_sum += value as f64 * *count as f64;
+        let foreground_weight = total - background_weight;
+        if background_weight == 0. || foreground_weight == 0. {
+            continue;
+        }
+        let background_mean = background_sum / background_weight;
+        let foreground_mean = (sum - background_sum) / foreground_weight;
+        let variance =
+            background_weight * foreground_weight * (background_mean - foreground_mean).powi(2);
+        if variance > best_variance {
+            best_variance = variance;
+            threshold = value;
+        }
+    }
+    let dark_pixels: usize = histogram[..=threshold].iter().sum();
+    let dark_text = dark_pixels as f64 <= total / 2.;
+    GrayImage::from_fn(gray.width(), gray.height(), |x, y| {
+        let dark = gray.get_pixel(x, y).0[0] as usize <= threshold;
+        Luma([if dark == dark_text { 255 } else { 0 }])
+    })
+}
+
+/// Find the angle of the lines of text by finding the angle where the rows of ink are the most concentrated
+fn estimate_skew(ink: &GrayImage) -> f32 {
+    let points: Vec<(f32, f32)> = ink
+        .enumerate_pixels()
+        .filter(|(_, _, pixel)| pixel.0[0] > 0)
+        .map(|(x, y, _)| (x as f32, y as f32))
+        .collect();
+    if points.is_empty() {
+        return 0.;
+    }
+    let rows = (ink.width() + ink.height()) as usize * 2;
+    let offset = ink.width() as f32;
+    let score = |angle: f32| {
+        let (sin, cos) = angle.to_radians().sin_cos();
+        let mut histogram = vec![0u64; rows];
+        for (x, y) in &points {
+            let row = (y * cos - x * sin + offset) as usize;
+            histogram[row.min(rows - 1)] += 1;
+        }
+        histogram.iter().map(|count| count * count).sum::<u64>()
+    };
+    let steps = (MAX_SKEW / SKEW_STEP) as i32;
+    let mut best_angle = 0.;
+    let mut best_score = score(0.);
+    for step in (-steps..=steps).filter(|step| *step != 0) {
+        let angle = step as f32 * SKEW_STEP;
+        let score = score(angle);
+        if score > best_score {
+            best_score = score;
+            best_angle = angle;
+        }
+    }
+    best_angle
+}
+
+/// Rotate an image counterclockwise by the angle in degrees around its center, so text skewed by the angle becomes level
+fn rotate<P: image::Pixel>(
+    image: &image::ImageBuffer<P, Vec<P::Subpixel>>,
+    angle: f32,
+    fill: P,
+) -> image::ImageBuffer<P, Vec<P::Subpixel>> {
+    let (sin, cos) = angle.to_radians().sin_cos();
+    let center_x = image.width() as f32 / 2.;
+    let center_y = image.height() as f32 / 2.;
+    image::ImageBuffer::from_fn(image.width(), image.height(), |x, y| {
+        let x = x as f32 + 0.5 - center_x;
+        let y = y as f32 + 0.5 - center_y;
+        let source_x = (x * cos - y * sin + center_x).floor();
+        let source_y = (x * sin + y * cos + center_y).floor();
+        if source_x < 0.
+            || source_y < 0.
+            || source_x >= image.width() as f32
+            || source_y >= image.height() as f32
+        {
+            fill
+        } else {
+            *image.get_pixel(source_x as u32, source_y as u32)
+        }
+    })
+}
+
+/// Find runs of indices where the profile is above zero, merging runs separated by gaps smaller than `min_gap`
+fn runs(profile: &[u32], min_gap: usize) -> Vec<(usize, usize)> {
+    let mut runs: Vec<(usize, usize)> = Vec::new();
+    for (index, count) in profile.iter().enumerate() {
+        if *count == 0 {
+            continue;
+        }
+        match runs.last_mut() {
+            Some((_, end)) if index - *end <= min_gap => *end = index + 1,
+            _ => runs.push((index, index + 1)),
+        }
+    }
+    runs
+}
+
+/// Find the lines of text in the mask, and the words in each line
+fn find_lines(ink: &GrayImage) -> Vec<LineLayout> {
+    let mut row_profile = vec![0; ink.height() as usize];
+    for (_, y, pixel) in ink.enumerate_pixels() {
+        if pixel.0[0] > 0 {
+            row_profile[y as usize] += 1;
+        }
+    }
+    runs(&row_profile, 1)
+        .into_iter()
+        // Ignore specks of noise that are too short to be text
+        .filter(|(start, end)| end - start >= 3)
+        .filter_map(|(top, bottom)| {
+            let height = bottom - top;
+            let mut column_profile = vec![0; ink.width() as usize];
+            for y in top..bottom {
+                for (x, count) in column_profile.iter_mut().enumerate() {
+                    if ink.get_pixel(x as u32, y as u32).0[0] > 0 {
+                        *count += 1;
+                    }
+                }
+            }
+            // Gaps between letters are smaller than gaps between words
+            let word_gap = (height * 2 / 5).max(3);
+            let words: Vec<Rect> = runs(&column_profile, word_gap)
+                .into_iter()
+                .map(|(left, right)| Rect {
+                    x: left as u32,
+                    y: top as u32,
+                    width: (right - left) as u32,
+                    height: height as u32,
+                })
+                .collect();
+            let first = words.first()?;
+            let last = words.last()?;
+            Some(LineLayout {
+                rect: Rect {
+                    x: first.x,
+                    y: top as u32,
+                    width: last.x + last.width - first.x,
+                    height: height as u32,
+                },
+                words,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+#[test]
+fn finds_skewed_lines_and_words() {
+    // Two lines of "words" drawn as black boxes
+    let mut image = RgbaImage::from_pixel(400, 200, Rgba([255, 255, 255, 255]));
+    let words = [
+        (40, 40, 60),
+        (120, 40, 90),
+        (230, 40, 50),
+        (40, 120, 100),
+        (160, 120, 70),
+    ];
+    for (x, y, width) in words {
+        for dx in 0..width {
+            for dy in 0..20 {
+                image.put_pixel(x + dx, y + dy, Rgba([0, 0, 0, 255]));
+            }
+        }
+    }
+
+    let layout = Layout::analyze(&image);
+    assert_eq!(layout.skew, 0.);
+    assert_eq!(layout.lines.len(), 2);
+    assert_eq!(layout.lines[0].words.len(), 3);
+    assert_eq!(layout.lines[1].words.len(), 2);
+    assert_eq!(
+        layout.lines[1].rect,
+        Rect {
+            x: 40,
+            y: 120,
+            width: 190,
+            height: 20
+        }
+    );
+
+    // Skew the page clockwise and check the lines are still found
+    let skewed = rotate(&image, -4., Rgba([255, 255, 255, 255]));
+    let layout = Layout::analyze(&skewed);
+    assert!((layout.skew - 4.).abs() <= SKEW_STEP);
+    assert_eq!(layout.lines.len(), 2);
+    assert_eq!(layout.lines[0].words.len(), 3);
+    let bounding_box = layout.bounding_box(layout.lines[1].rect);
+    assert!((bounding_box.rotation - 4.).abs() <= SKEW_STEP);
+    // The center of the line rotated around the center of the image
+    assert!((bounding_box.x + bounding_box.width / 2. - 133.1).abs() < 3.);
+    assert!((bounding_box.y + bounding_box.height / 2. - 125.4).abs() < 3.);
+}
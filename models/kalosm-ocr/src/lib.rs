@@ -27,13 +27,17 @@ extern crate intel_mkl_src;
 extern crate accelerate_src;
 
 mod image_processor;
+mod layout;
+mod script;
+pub use script::*;
+mod structured;
+pub use structured::*;
 
 use candle_core::DType;
 use candle_core::{Device, Tensor};
 use candle_nn::VarBuilder;
 use candle_transformers::models::trocr;
 use candle_transformers::models::vit;
-use hf_hub::api::sync::Api;
 use image::{GenericImage, GenericImageView, ImageBuffer, Rgba};
 use kalosm_common::*;
 use kalosm_model_types::{FileSource, ModelLoadingProgress};
@@ -43,6 +47,7 @@ use tokenizers::Tokenizer;
 #[derive(Default)]
 pub struct OcrBuilder {
     source: OcrSource,
+    cache: Cache,
 }
 
 impl OcrBuilder {
@@ -52,6 +57,12 @@ impl OcrBuilder {
         self
     }
 
+    /// Set the cache location to use for the model (defaults DATA_DIR/kalosm/cache)
+    pub fn with_cache(mut self, cache: Cache) -> Self {
+        self.cache = cache;
+        self
+    }
+
     /// Builds the [`Ocr`] model.
     pub async fn build(self) -> Result<Ocr, LoadOcrError> {
         Ocr::new(self, |_| {}).await
@@ -70,12 +81,35 @@ impl OcrBuilder {
 pub struct OcrSource {
     model: FileSource,
     config: FileSource,
+    tokenizer: FileSource,
 }
 
 impl OcrSource {
-    /// Creates a new [`OcrSource`].
+    /// Creates a new [`OcrSource`]. The tokenizer defaults to the tokenizer of the English TrOCR models. Use [`OcrSource::with_tokenizer`] for models fine-tuned with a different tokenizer.
     pub fn new(model: FileSource, config: FileSource) -> Self {
-        Self { model, config }
+        Self {
+            model,
+            config,
+            tokenizer: FileSource::huggingface(
+                "ToluClassics/candle-trocr-tokenizer",
+                "main",
+                "tokenizer.json",
+            ),
+        }
+    }
+
+    /// Set the `tokenizer.json` file for the model.
+    pub fn with_tokenizer(mut self, tokenizer: FileSource) -> Self {
+        self.tokenizer = tokenizer;
+        self
+    }
+
+    fn huggingface(model_id: &str) -> Self {
+        Self::new(
+            FileSource::huggingface(model_id, "main", "model.safetensors"),
+            FileSource::huggingface(model_id, "main", "config.json"),
+        )
+        .with_tokenizer(FileSource::huggingface(model_id, "main", "tokenizer.json"))
     }
 
     /// Create the base model source.
@@ -142,14 +176,29 @@ impl OcrSource {
         )
     }
 
+    /// Create a base printed Spanish model source.
+    pub fn base_spanish_printed() -> Self {
+        Self::huggingface("qantev/trocr-base-spanish")
+    }
+
+    /// Create a large printed Spanish model source.
+    pub fn large_spanish_printed() -> Self {
+        Self::huggingface("qantev/trocr-large-spanish")
+    }
+
+    /// Create a base handwritten Swedish model source. The model is trained on historical documents from the 17th to 19th century.
+    pub fn base_swedish_handwritten() -> Self {
+        Self::huggingface("Riksarkivet/trocr-base-handwritten-hist-swe-2")
+    }
+
     async fn varbuilder(
         &self,
+        cache: &Cache,
         device: &Device,
         mut handler: impl FnMut(ModelLoadingProgress) + Send + Sync,
     ) -> Result<VarBuilder, LoadOcrError> {
         let source = format!("Model ({})", self.model);
         let mut create_progress = ModelLoadingProgress::downloading_progress(source);
-        let cache = Cache::default();
         let filename = cache
             .get(&self.model, |progress| handler(create_progress(progress)))
             .await?;
@@ -158,18 +207,21 @@ impl OcrSource {
 
     async fn config(
         &self,
+        cache: &Cache,
         mut handler: impl FnMut(ModelLoadingProgress) + Send + Sync,
     ) -> Result<(vit::Config, trocr::TrOCRConfig), LoadOcrError> {
         #[derive(Debug, Clone, serde::Deserialize)]
         struct Config {
             encoder: vit::Config,
             decoder: trocr::TrOCRConfig,
+            // Fine-tuned models often set the special tokens on the whole model instead of the decoder
+            decoder_start_token_id: Option<u32>,
+            eos_token_id: Option<u32>,
         }
 
         let (encoder_config, decoder_config) = {
             let source = format!("Config ({})", self.model);
             let mut create_progress = ModelLoadingProgress::downloading_progress(source);
-            let cache = Cache::default();
             let config_filename = cache
                 .get(&self.config, |progress| handler(create_progress(progress)))
                 .await?;
@@ -178,7 +230,14 @@ impl OcrSource {
                     .expect("FileSource::download should return a valid path"),
             )
             .map_err(LoadOcrError::LoadConfig)?;
-            (config.encoder, config.decoder)
+            let mut decoder = config.decoder;
+            if let Some(decoder_start_token_id) = config.decoder_start_token_id {
+                decoder.decoder_start_token_id = decoder_start_token_id;
+            }
+            if let Some(eos_token_id) = config.eos_token_id {
+                decoder.eos_token_id = eos_token_id;
+            }
+            (config.encoder, decoder)
         };
 
         Ok((encoder_config, decoder_config))
@@ -250,23 +309,37 @@ impl Ocr {
 
     async fn new(
         settings: OcrBuilder,
-        mut handler: impl FnMut(ModelLoadingProgress) + Send + Sync + 'static,
+        handler: impl FnMut(ModelLoadingProgress) + Send + Sync + 'static,
     ) -> Result<Self, LoadOcrError> {
-        let OcrBuilder { source } = settings;
+        let OcrBuilder { source, cache } = settings;
+        let tokenizer_source = &source.tokenizer;
+        cache.ensure_available([tokenizer_source, &source.model, &source.config])?;
+
+        // The tokenizer, weights and config are reported as a single download with a total size that is known before any of the files start
+        let size = cache
+            .download_size([tokenizer_source, &source.model, &source.config])
+            .await;
+        let mut handler = ModelLoadingProgress::aggregate_with_size(
+            format!("Model ({})", source.model),
+            size,
+            handler,
+        );
         let tokenizer_dec = {
-            let tokenizer = Api::new()
-                .map_err(CacheError::HuggingFaceApi)?
-                .model(String::from("ToluClassics/candle-trocr-tokenizer"))
-                .get("tokenizer.json")
-                .map_err(CacheError::HuggingFaceApi)?;
+            let display_source = format!("Tokenizer ({})", tokenizer_source);
+            let mut create_progress = ModelLoadingProgress::downloading_progress(display_source);
+            let tokenizer = cache
+                .get(tokenizer_source, |progress| {
+                    handler(create_progress(progress))
+                })
+                .await?;
 
             Tokenizer::from_file(&tokenizer).map_err(LoadOcrError::LoadTokenizer)?
         };
         let device = accelerated_device_if_available()?;
 
-        let vb = source.varbuilder(&device, &mut handler).await?;
+        let vb = source.varbuilder(&cache, &device, &mut handler).await?;
 
-        let (encoder_config, decoder_config) = source.config(&mut handler).await?;
+        let (encoder_config, decoder_config) = source.config(&cache, &mut handler).await?;
 
         let model = trocr::TrOCRModel::new(&encoder_config, &decoder_config, vb)?;
 
@@ -307,15 +380,22 @@ impl Ocr {
 
         let image = image::DynamicImage::ImageRgba8(image);
 
+        Ok(self.decode(image)?.text)
+    }
+
+    /// Decode the text in one line of text
+    fn decode(
+        &mut self,
+        image: image::DynamicImage,
+    ) -> Result<structured::DecodedText, OcrInferenceError> {
         let image = vec![image];
         let image = self.processor.preprocess(image, &self.device)?;
 
         let encoder_xs = self.decoder.encoder().forward(&image)?;
 
-        let mut logits_processor =
-            candle_transformers::generation::LogitsProcessor::new(1337, None, None);
-
         let mut token_ids: Vec<u32> = vec![self.decoder_config.decoder_start_token_id];
+        let mut tokens = Vec::new();
+        let mut decoded_len = 0;
         for index in 0..1000 {
             let context_size = if index >= 1 { 1 } else { token_ids.len() };
             let start_pos = token_ids.len().saturating_sub(context_size);
@@ -325,19 +405,34 @@ impl Ocr {
 
             let logits = logits.squeeze(0)?;
             let logits = logits.get(logits.dim(0)? - 1)?;
-            let token = logits_processor.sample(&logits)?;
+            let probabilities = candle_nn::ops::softmax_last_dim(&logits)?.to_vec1::<f32>()?;
+            let (token, probability) = probabilities
+                .iter()
+                .enumerate()
+                .max_by(|(_, a), (_, b)| a.total_cmp(b))
+                .map(|(token, probability)| (token as u32, *probability))
+                .unwrap_or((self.decoder_config.eos_token_id, 1.));
             token_ids.push(token);
 
             if token == self.decoder_config.eos_token_id {
                 break;
             }
+
+            // Track which part of the text each token decoded to
+            let len = self
+                .tokenizer_dec
+                .decode(&token_ids, true)
+                .map_err(OcrInferenceError::Decode)?
+                .len();
+            tokens.push((decoded_len..len.max(decoded_len), probability));
+            decoded_len = len.max(decoded_len);
         }
 
-        let decoded = self
+        let text = self
             .tokenizer_dec
             .decode(&token_ids, true)
             .map_err(OcrInferenceError::Decode)?;
 
-        Ok(decoded)
+        Ok(structured::DecodedText { text, tokens })
     }
 }
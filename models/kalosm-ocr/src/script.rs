@@ -0,0 +1,84 @@
+/// A writing system detected in recognized text
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OcrScript {
+    /// The Latin alphabet used by English, Spanish, German and many other languages
+    Latin,
+    /// The Greek alphabet
+    Greek,
+    /// The Cyrillic alphabet used by Russian, Ukrainian and other languages
+    Cyrillic,
+    /// The Arabic script
+    Arabic,
+    /// The Hebrew alphabet
+    Hebrew,
+    /// The Devanagari script used by Hindi and other languages
+    Devanagari,
+    /// The Thai script
+    Thai,
+    /// Chinese characters
+    Han,
+    /// Japanese text written with hiragana or katakana, and possibly Chinese characters
+    Japanese,
+    /// The Korean Hangul alphabet
+    Hangul,
+}
+
+impl OcrScript {
+    /// Get the script of a single character. Returns `None` for characters shared between scripts, like digits, punctuation and whitespace
+    pub fn of_char(c: char) -> Option<Self> {
+        match c as u32 {
+            0x41..=0x5A | 0x61..=0x7A | 0xC0..=0x24F | 0x1E00..=0x1EFF => Some(Self::Latin),
+            0x370..=0x3FF | 0x1F00..=0x1FFF => Some(Self::Greek),
+            0x400..=0x52F => Some(Self::Cyrillic),
+            0x590..=0x5FF => Some(Self::Hebrew),
+            0x600..=0x6FF | 0x750..=0x77F | 0xFB50..=0xFDFF | 0xFE70..=0xFEFF => Some(Self::Arabic),
+            0x900..=0x97F => Some(Self::Devanagari),
+            0xE00..=0xE7F => Some(Self::Thai),
+            0x3040..=0x30FF | 0x31F0..=0x31FF | 0xFF66..=0xFF9F => Some(Self::Japanese),
+            0x1100..=0x11FF | 0x3130..=0x318F | 0xAC00..=0xD7AF => Some(Self::Hangul),
+            0x3400..=0x4DBF | 0x4E00..=0x9FFF | 0xF900..=0xFAFF | 0x20000..=0x2FA1F => {
+                Some(Self::Han)
+            }
+            _ => None,
+        }
+    }
+
+    /// Detect the script most of the text is written in. Returns `None` if the text doesn't contain any letters
+    pub fn detect(text: &str) -> Option<Self> {
+        // Japanese text mixes kana with Chinese characters
+        let has_kana = text
+            .chars()
+            .any(|c| Self::of_char(c) == Some(Self::Japanese));
+        let mut counts: Vec<(Self, usize)> = Vec::new();
+        for script in text.chars().filter_map(Self::of_char) {
+            let script = match script {
+                Self::Han if has_kana => Self::Japanese,
+                script => script,
+            };
+            match counts.iter_mut().find(|(other, _)| *other == script) {
+                Some((_, count)) => *count += 1,
+                None => counts.push((script, 1)),
+            }
+        }
+        counts
+            .into_iter()
+            .max_by_key(|(_, count)| *count)
+            .map(|(script, _)| script)
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn detects_scripts() {
+    assert_eq!(
+        OcrScript::detect("Hola, ¿cómo estás?"),
+        Some(OcrScript::Latin)
+    );
+    assert_eq!(OcrScript::detect("Привет, мир"), Some(OcrScript::Cyrillic));
+    assert_eq!(
+        OcrScript::detect("今日は晴れです"),
+        Some(OcrScript::Japanese)
+    );
+    assert_eq!(OcrScript::detect("你好世界"), Some(OcrScript::Han));
+    assert_eq!(OcrScript::detect("123 - 456"), None);
+}
@@ -0,0 +1,248 @@
+use std::ops::Range;
+
+use crate::layout::Layout;
+use crate::{Ocr, OcrInferenceError, OcrInferenceSettings, OcrScript};
+
+/// The position of some text in an image. Coordinates are in pixels from the top left of the image.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundingBox {
+    /// The left edge of the box before it is rotated
+    pub x: f32,
+    /// The top edge of the box before it is rotated
+    pub y: f32,
+    /// The width of the box
+    pub width: f32,
+    /// The height of the box
+    pub height: f32,
+    /// The rotation of the box around its center in degrees clockwise. This is the skew of the text in the image
+    pub rotation: f32,
+}
+
+impl BoundingBox {
+    /// Get the center of the box
+    pub fn center(&self) -> (f32, f32) {
+        (self.x + self.width / 2., self.y + self.height / 2.)
+    }
+
+    /// Get the four corners of the box after it is rotated, starting from the top left and going clockwise
+    pub fn corners(&self) -> [(f32, f32); 4] {
+        let (center_x, center_y) = self.center();
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+        let half_width = self.width / 2.;
+        let half_height = self.height / 2.;
+        [
+            (-half_width, -half_height),
+            (half_width, -half_height),
+            (half_width, half_height),
+            (-half_width, half_height),
+        ]
+        .map(|(x, y)| (center_x + x * cos - y * sin, center_y + x * sin + y * cos))
+    }
+}
+
+/// A word recognized by [`Ocr::recognize_structured`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct OcrWord {
+    text: String,
+    bounding_box: BoundingBox,
+    confidence: f32,
+}
+
+impl OcrWord {
+    /// Get the text of the word
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Get the position of the word in the image
+    pub fn bounding_box(&self) -> BoundingBox {
+        self.bounding_box
+    }
+
+    /// Get the confidence of the model in the word (between 0 and 1)
+    pub fn confidence(&self) -> f32 {
+        self.confidence
+    }
+}
+
+/// A line of text recognized by [`Ocr::recognize_structured`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct OcrLine {
+    text: String,
+    bounding_box: BoundingBox,
+    confidence: f32,
+    words: Vec<OcrWord>,
+}
+
+impl OcrLine {
+    /// Get the text of the line
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Get the position of the line in the image
+    pub fn bounding_box(&self) -> BoundingBox {
+        self.bounding_box
+    }
+
+    /// Get the confidence of the model in the line (between 0 and 1)
+    pub fn confidence(&self) -> f32 {
+        self.confidence
+    }
+
+    /// Get the words in the line
+    pub fn words(&self) -> &[OcrWord] {
+        &self.words
+    }
+
+    /// Detect the script the line is written in
+    pub fn script(&self) -> Option<OcrScript> {
+        OcrScript::detect(&self.text)
+    }
+}
+
+/// The text recognized by [`Ocr::recognize_structured`], split into lines and words with their positions and confidence
+#[derive(Debug, Clone, PartialEq)]
+pub struct OcrResult {
+    lines: Vec<OcrLine>,
+}
+
+impl OcrResult {
+    /// Get the lines of text from top to bottom
+    pub fn lines(&self) -> &[OcrLine] {
+        &self.lines
+    }
+
+    /// Get all of the words in the image in reading order
+    pub fn words(&self) -> impl Iterator<Item = &OcrWord> {
+        self.lines.iter().flat_map(|line| &line.words)
+    }
+
+    /// Get the text of the image with one line of text per line
+    pub fn text(&self) -> String {
+        self.lines
+            .iter()
+            .map(|line| line.text.as_str())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Detect the script most of the text in the image is written in. This can be used to pick a model trained on that script with [`crate::OcrSource`]
+    pub fn script(&self) -> Option<OcrScript> {
+        OcrScript::detect(&self.text())
+    }
+}
+
+impl std::fmt::Display for OcrResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.text())
+    }
+}
+
+/// A line of text decoded by the model
+pub(crate) struct DecodedText {
+    pub(crate) text: String,
+    /// The range of the text each token decoded to and the probability of the token
+    pub(crate) tokens: Vec<(Range<usize>, f32)>,
+}
+
+impl DecodedText {
+    /// The geometric mean of the probabilities of the tokens that overlap the range
+    fn confidence(&self, range: Range<usize>) -> f32 {
+        let log_probabilities: Vec<f32> = self
+            .tokens
+            .iter()
+            .filter(|(token, _)| token.start < range.end && range.start < token.end)
+            .map(|(_, probability)| probability.ln())
+            .collect();
+        if log_probabilities.is_empty() {
+            return 0.;
+        }
+        (log_probabilities.iter().sum::<f32>() / log_probabilities.len() as f32).exp()
+    }
+}
+
+impl Ocr {
+    /// Recognize text from an image. Returns the lines and words in the image with their bounding boxes and confidence.
+    ///
+    /// Lines of text are found in the image, straightened if the image is skewed, and recognized one at a time.
+    ///
+    /// # Example
+    /// ```rust, no_run
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// use kalosm_ocr::*;
+    ///
+    /// let mut model = Ocr::builder()
+    ///     .with_source(OcrSource::base_printed())
+    ///     .build()
+    ///     .await
+    ///     .unwrap();
+    /// let image = image::open("examples/printed.png").unwrap();
+    /// let result = model
+    ///     .recognize_structured(OcrInferenceSettings::new(image))
+    ///     .unwrap();
+    ///
+    /// for word in result.words() {
+    ///     println!(
+    ///         "{} at {:?} ({:.0}%)",
+    ///         word.text(),
+    ///         word.bounding_box().corners(),
+    ///         word.confidence() * 100.
+    ///     );
+    /// }
+    /// # }
+    /// ```
+    pub fn recognize_structured(
+        &mut self,
+        settings: OcrInferenceSettings,
+    ) -> Result<OcrResult, OcrInferenceError> {
+        let OcrInferenceSettings { image } = settings;
+        let layout = Layout::analyze(&image);
+
+        let mut lines = Vec::with_capacity(layout.lines.len());
+        for line in &layout.lines {
+            let decoded = self.decode(layout.crop(line.rect))?;
+            let text = decoded.text.trim();
+            if text.is_empty() {
+                continue;
+            }
+
+            let word_ranges: Vec<Range<usize>> = text
+                .split_whitespace()
+                .map(|word| {
+                    let start = word.as_ptr() as usize - decoded.text.as_ptr() as usize;
+                    start..start + word.len()
+                })
+                .collect();
+            // Use the gaps in the image for the word boxes if they match the recognized words. Otherwise split the line based on the length of each word
+            let word_rects = if line.words.len() == word_ranges.len() {
+                line.words.clone()
+            } else {
+                let lengths: Vec<_> = word_ranges
+                    .iter()
+                    .map(|range| decoded.text[range.clone()].chars().count())
+                    .collect();
+                line.rect.split_horizontally(&lengths)
+            };
+            let words = word_ranges
+                .into_iter()
+                .zip(word_rects)
+                .map(|(range, rect)| OcrWord {
+                    text: decoded.text[range.clone()].to_string(),
+                    bounding_box: layout.bounding_box(rect),
+                    confidence: decoded.confidence(range),
+                })
+                .collect();
+
+            lines.push(OcrLine {
+                text: text.to_string(),
+                bounding_box: layout.bounding_box(line.rect),
+                confidence: decoded.confidence(0..decoded.text.len()),
+                words,
+            });
+        }
+
+        Ok(OcrResult { lines })
+    }
+}
@@ -109,13 +109,12 @@ impl Embedder for Bert {
 
     async fn embed_string(&self, input: String) -> Result<Embedding, Self::Error> {
         let self_clone = self.clone();
-        tokio::task::spawn_blocking(move || self_clone.embed_with_pooling(&input, Pooling::CLS))
-            .await?
+        crate::spawn_blocking(move || self_clone.embed_with_pooling(&input, Pooling::CLS)).await?
     }
 
     async fn embed_vec(&self, inputs: Vec<String>) -> Result<Vec<Embedding>, Self::Error> {
         let self_clone = self.clone();
-        tokio::task::spawn_blocking(move || {
+        crate::spawn_blocking(move || {
             let inputs_borrowed = inputs.iter().map(|s| s.as_str()).collect::<Vec<_>>();
             self_clone.embed_batch_with_pooling(inputs_borrowed, Pooling::CLS)
         })
@@ -143,10 +142,8 @@ impl Deref for Bert {
             let input = text.to_string();
 
             Box::pin(async move {
-                tokio::task::spawn_blocking(move || {
-                    self_clone.embed_with_pooling(&input, Pooling::CLS)
-                })
-                .await?
+                crate::spawn_blocking(move || self_clone.embed_with_pooling(&input, Pooling::CLS))
+                    .await?
             })
                 as Pin<Box<dyn Future<Output = Result<Embedding, BertError>> + Send + 'static>>
         };
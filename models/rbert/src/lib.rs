@@ -39,6 +39,17 @@
 //!     Ok(())
 //! }
 //! ```
+//!
+//! ## Browser
+//!
+//! rbert builds for `wasm32-unknown-unknown`. The tokenizer uses [getrandom](https://docs.rs/getrandom) 0.3, which needs the `wasm_js` backend to be selected when building:
+//!
+//! ```sh
+//! RUSTFLAGS='--cfg getrandom_backend="wasm_js"' cargo build --target wasm32-unknown-unknown -p rbert
+//! ```
+//!
+//! In the browser, model files are stored in the origin private file system instead of on disk and the model runs on the CPU.
+//! [`Bert::new`] and the other builders work the same way, but [`tokio::main`](https://docs.rs/tokio/latest/tokio/attr.main.html) is replaced by an executor like [`wasm_bindgen_futures::spawn_local`](https://docs.rs/wasm-bindgen-futures/latest/wasm_bindgen_futures/fn.spawn_local.html).
 
 #![warn(missing_docs)]
 
@@ -56,19 +67,24 @@ use std::sync::{Arc, RwLock};
 use tokenizers::{Encoding, PaddingParams, Tokenizer};
 
 mod language_model;
+mod ner;
 mod raw;
 mod source;
+mod zero_shot;
 
 pub use crate::language_model::*;
+pub use crate::ner::*;
 use crate::raw::DTYPE;
 pub use crate::raw::{BertModel, Config};
 pub use crate::source::*;
+pub use crate::zero_shot::*;
 
 /// A builder for a [`Bert`] model
 #[derive(Default)]
 pub struct BertBuilder {
     source: BertSource,
     cache: kalosm_common::Cache,
+    weight_loading: WeightLoadingOptions,
 }
 
 impl BertBuilder {
@@ -91,6 +107,13 @@ impl BertBuilder {
         self
     }
 
+    /// Set how the weights of the model are read from disk (defaults to memory mapping the weights)
+    pub fn with_weight_loading(mut self, weight_loading: WeightLoadingOptions) -> Self {
+        self.weight_loading = weight_loading;
+
+        self
+    }
+
     /// Build the model with a loading handler
     ///
     /// ```rust, no_run
@@ -140,6 +163,27 @@ pub enum BertLoadingError {
     /// A config was not found
     #[error("Config not found")]
     ConfigNotFound,
+    /// The config of a classification model doesn't contain a label that is required
+    #[error("The model config doesn't contain the label {0}")]
+    MissingLabel(String),
+    /// The model architecture isn't supported. Zero-shot classification only supports BERT and RoBERTa cross-encoders
+    #[error("Unsupported model type {0}. Only BERT and RoBERTa models are supported")]
+    UnsupportedModelType(String),
+}
+
+/// Read the config of a model from a file loaded from the cache
+pub(crate) fn load_config(file: &CachedFile) -> Result<Config, BertLoadingError> {
+    let config = file.bytes().map_err(|_| BertLoadingError::ConfigNotFound)?;
+    serde_json::from_slice(&config).map_err(BertLoadingError::LoadConfig)
+}
+
+/// Read a tokenizer from a file loaded from the cache
+pub(crate) fn load_tokenizer(file: &CachedFile) -> Result<Tokenizer, BertLoadingError> {
+    match file {
+        CachedFile::Path(path) => Tokenizer::from_file(path),
+        CachedFile::InMemory(file) => Tokenizer::from_bytes(file.as_bytes()),
+    }
+    .map_err(BertLoadingError::LoadTokenizer)
 }
 
 /// An error that can occur when running a Bert model.
@@ -156,6 +200,20 @@ pub enum BertError {
     Join(#[from] tokio::task::JoinError),
 }
 
+/// Run the model on a blocking thread so it doesn't block the async runtime
+#[cfg(not(target_arch = "wasm32"))]
+async fn spawn_blocking<T: Send + 'static>(
+    f: impl FnOnce() -> T + Send + 'static,
+) -> Result<T, BertError> {
+    Ok(tokio::task::spawn_blocking(f).await?)
+}
+
+/// The browser doesn't have blocking threads, so the model runs on the current task
+#[cfg(target_arch = "wasm32")]
+async fn spawn_blocking<T>(f: impl FnOnce() -> T) -> Result<T, BertError> {
+    Ok(f())
+}
+
 /// The pooling strategy to use when embedding text.
 #[derive(Debug, Clone, Copy)]
 pub enum Pooling {
@@ -231,48 +289,60 @@ impl Bert {
 
     async fn from_builder(
         builder: BertBuilder,
-        mut progress_handler: impl FnMut(ModelLoadingProgress) + Send + 'static,
+        progress_handler: impl FnMut(ModelLoadingProgress) + Send + 'static,
     ) -> Result<Self, BertLoadingError> {
-        let BertBuilder { source, cache } = builder;
+        let BertBuilder {
+            source,
+            cache,
+            weight_loading,
+        } = builder;
         let BertSource {
             config,
             tokenizer,
             model,
             search_embedding_prefix,
         } = source;
+        cache.ensure_available([&config, &tokenizer, &model])?;
+
+        // The config, tokenizer and weights are reported as a single download with a total size that is known before any of the files start
+        let size = cache.download_size([&config, &tokenizer, &model]).await;
+        let mut progress_handler = ModelLoadingProgress::aggregate_with_size(
+            format!("Model ({})", model),
+            size,
+            progress_handler,
+        );
 
         let source = format!("Config ({})", config);
         let mut create_progress = ModelLoadingProgress::downloading_progress(source);
-        let config_filename = cache
-            .get(&config, |progress| {
+        let config_file = cache
+            .load(&config, |progress| {
                 progress_handler(create_progress(progress))
             })
             .await?;
         let tokenizer_source = format!("Tokenizer ({})", tokenizer);
         let mut create_progress = ModelLoadingProgress::downloading_progress(tokenizer_source);
-        let tokenizer_filename = cache
-            .get(&tokenizer, |progress| {
+        let tokenizer_file = cache
+            .load(&tokenizer, |progress| {
                 progress_handler(create_progress(progress))
             })
             .await?;
         let model_source = format!("Model ({})", model);
         let mut create_progress = ModelLoadingProgress::downloading_progress(model_source);
-        let weights_filename = cache
-            .get(&model, |progress| {
+        let weights_file = cache
+            .load(&model, |progress| {
                 progress_handler(create_progress(progress))
             })
             .await?;
 
-        let config = std::fs::read_to_string(config_filename)
-            .map_err(|_| BertLoadingError::ConfigNotFound)?;
-        let config: Config = serde_json::from_str(&config).map_err(BertLoadingError::LoadConfig)?;
+        let config = load_config(&config_file)?;
 
         let device = accelerated_device_if_available()?;
-        let vb =
-            unsafe { VarBuilder::from_mmaped_safetensors(&[&weights_filename], DTYPE, &device)? };
+        let weights = weights_file
+            .load_weights(weight_loading)
+            .map_err(CacheError::from)?;
+        let vb = VarBuilder::from_slice_safetensors(&weights, DTYPE, &device)?;
         let model = BertModel::load(vb, &config)?;
-        let mut tokenizer =
-            Tokenizer::from_file(&tokenizer_filename).map_err(BertLoadingError::LoadTokenizer)?;
+        let mut tokenizer = load_tokenizer(&tokenizer_file)?;
         tokenizer.with_padding(None);
 
         Ok(Bert {
@@ -0,0 +1,384 @@
+use std::ops::Range;
+use std::sync::Arc;
+
+use candle_core::{Tensor, D};
+use candle_nn::{ops, VarBuilder};
+use kalosm_common::*;
+use kalosm_model_types::{FileSource, ModelLoadingProgress};
+use tokenizers::{Tokenizer, TruncationParams};
+
+use crate::raw::{TokenClassificationHead, DTYPE};
+use crate::{load_config, load_tokenizer, BertError, BertLoadingError, BertModel};
+
+/// The source of an [`EntityRecognizer`] model. The model must be a token classification model with labels in the
+/// `B-TYPE`, `I-TYPE` and `O` format.
+pub struct EntityRecognizerSource {
+    pub(crate) config: FileSource,
+    pub(crate) tokenizer: FileSource,
+    pub(crate) model: FileSource,
+}
+
+impl EntityRecognizerSource {
+    /// Create a new [`EntityRecognizerSource`] with the default model
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the model to use
+    pub fn with_model(mut self, model: FileSource) -> Self {
+        self.model = model;
+        self
+    }
+
+    /// Set the tokenizer to use
+    pub fn with_tokenizer(mut self, tokenizer: FileSource) -> Self {
+        self.tokenizer = tokenizer;
+        self
+    }
+
+    /// Set the config to use
+    pub fn with_config(mut self, config: FileSource) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Create a new [`EntityRecognizerSource`] with the [bert-base-NER](https://huggingface.co/dslim/bert-base-NER) model. The model recognizes people (`PER`), organizations (`ORG`), locations (`LOC`) and miscellaneous entities (`MISC`).
+    pub fn bert_base_ner() -> Self {
+        Self {
+            config: FileSource::huggingface(
+                "dslim/bert-base-NER".to_string(),
+                "main".to_string(),
+                "config.json".to_string(),
+            ),
+            // The model is fine-tuned from bert-base-cased and uses the same vocabulary
+            tokenizer: FileSource::huggingface(
+                "google-bert/bert-base-cased".to_string(),
+                "main".to_string(),
+                "tokenizer.json".to_string(),
+            ),
+            model: FileSource::huggingface(
+                "dslim/bert-base-NER".to_string(),
+                "main".to_string(),
+                "model.safetensors".to_string(),
+            ),
+        }
+    }
+}
+
+impl Default for EntityRecognizerSource {
+    fn default() -> Self {
+        Self::bert_base_ner()
+    }
+}
+
+/// A builder for an [`EntityRecognizer`]
+#[derive(Default)]
+pub struct EntityRecognizerBuilder {
+    source: EntityRecognizerSource,
+    cache: kalosm_common::Cache,
+    weight_loading: WeightLoadingOptions,
+}
+
+impl EntityRecognizerBuilder {
+    /// Set the source of the model
+    pub fn with_source(mut self, source: EntityRecognizerSource) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// Set the cache location to use for the model (defaults DATA_DIR/kalosm/cache)
+    pub fn with_cache(mut self, cache: kalosm_common::Cache) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    /// Set how the weights of the model are read from disk (defaults to memory mapping the weights)
+    pub fn with_weight_loading(mut self, weight_loading: WeightLoadingOptions) -> Self {
+        self.weight_loading = weight_loading;
+        self
+    }
+
+    /// Build the model
+    pub async fn build(self) -> Result<EntityRecognizer, BertLoadingError> {
+        self.build_with_loading_handler(ModelLoadingProgress::multi_bar_loading_indicator())
+            .await
+    }
+
+    /// Build the model with a loading handler
+    pub async fn build_with_loading_handler(
+        self,
+        progress_handler: impl FnMut(ModelLoadingProgress) + Send + 'static,
+    ) -> Result<EntityRecognizer, BertLoadingError> {
+        let EntityRecognizerBuilder {
+            source,
+            cache,
+            weight_loading,
+        } = self;
+        let EntityRecognizerSource {
+            config,
+            tokenizer,
+            model,
+        } = source;
+        cache.ensure_available([&config, &tokenizer, &model])?;
+
+        // The config, tokenizer and weights are reported as a single download with a total size that is known before any of the files start
+        let size = cache.download_size([&config, &tokenizer, &model]).await;
+        let mut progress_handler = ModelLoadingProgress::aggregate_with_size(
+            format!("Model ({})", model),
+            size,
+            progress_handler,
+        );
+
+        let source = format!("Config ({})", config);
+        let mut create_progress = ModelLoadingProgress::downloading_progress(source);
+        let config_file = cache
+            .load(&config, |progress| {
+                progress_handler(create_progress(progress))
+            })
+            .await?;
+        let tokenizer_source = format!("Tokenizer ({})", tokenizer);
+        let mut create_progress = ModelLoadingProgress::downloading_progress(tokenizer_source);
+        let tokenizer_file = cache
+            .load(&tokenizer, |progress| {
+                progress_handler(create_progress(progress))
+            })
+            .await?;
+        let model_source = format!("Model ({})", model);
+        let mut create_progress = ModelLoadingProgress::downloading_progress(model_source);
+        let weights_file = cache
+            .load(&model, |progress| {
+                progress_handler(create_progress(progress))
+            })
+            .await?;
+
+        let config = load_config(&config_file)?;
+        let labels = config.labels();
+        if !labels.iter().any(|label| label == "O") {
+            return Err(BertLoadingError::MissingLabel("O".to_string()));
+        }
+
+        let device = accelerated_device_if_available()?;
+        let weights = weights_file
+            .load_weights(weight_loading)
+            .map_err(CacheError::from)?;
+        let vb = VarBuilder::from_slice_safetensors(&weights, DTYPE, &device)?;
+        let model = BertModel::load(vb.clone(), &config)?;
+        let head = TokenClassificationHead::load(vb, &config, labels.len())?;
+        let mut tokenizer = load_tokenizer(&tokenizer_file)?;
+        tokenizer.with_padding(None);
+        // Long texts are split into windows the model can process at once
+        tokenizer
+            .with_truncation(Some(TruncationParams {
+                max_length: model.max_seq_len(),
+                ..Default::default()
+            }))
+            .map_err(BertLoadingError::LoadTokenizer)?;
+
+        Ok(EntityRecognizer {
+            model: Arc::new(model),
+            head: Arc::new(head),
+            tokenizer: Arc::new(tokenizer),
+            labels: labels.into(),
+        })
+    }
+}
+
+/// A named entity found by an [`EntityRecognizer`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entity {
+    /// The type of the entity without the `B-` or `I-` prefix, like `PER` for a person
+    pub label: String,
+    /// The text of the entity
+    pub text: String,
+    /// The byte range of the entity in the text
+    pub byte_range: Range<usize>,
+    /// The mean probability of the label for the tokens in the entity
+    pub score: f32,
+}
+
+/// A named entity recognition (NER) model built on a bert token classification model. The model labels each token in
+/// the text and neighboring tokens with the same label are grouped into entities.
+///
+/// # Example
+/// ```rust, no_run
+/// use rbert::*;
+///
+/// #[tokio::main]
+/// async fn main() -> anyhow::Result<()> {
+///     let recognizer = EntityRecognizer::new().await?;
+///     let entities = recognizer
+///         .recognize("Ada Lovelace worked with Charles Babbage in London")
+///         .await?;
+///     for entity in entities {
+///         println!("{}: {}", entity.label, entity.text);
+///     }
+///     Ok(())
+/// }
+/// ```
+#[derive(Clone)]
+pub struct EntityRecognizer {
+    model: Arc<BertModel>,
+    head: Arc<TokenClassificationHead>,
+    tokenizer: Arc<Tokenizer>,
+    labels: Arc<[String]>,
+}
+
+impl EntityRecognizer {
+    /// Create a new [`EntityRecognizerBuilder`]
+    pub fn builder() -> EntityRecognizerBuilder {
+        EntityRecognizerBuilder::default()
+    }
+
+    /// Create a new default entity recognizer
+    pub async fn new() -> Result<Self, BertLoadingError> {
+        Self::builder().build().await
+    }
+
+    /// Find the named entities in the text, in the order they appear
+    pub async fn recognize(&self, text: impl ToString) -> Result<Vec<Entity>, BertError> {
+        let text = text.to_string();
+        let self_clone = self.clone();
+        crate::spawn_blocking(move || self_clone.recognize_sync(&text)).await?
+    }
+
+    fn recognize_sync(&self, text: &str) -> Result<Vec<Entity>, BertError> {
+        let encoding = self
+            .tokenizer
+            .encode(text, true)
+            .map_err(BertError::TokenizerError)?;
+        let device = &self.model.device;
+
+        let mut tokens = Vec::new();
+        for encoding in std::iter::once(&encoding).chain(encoding.get_overflowing()) {
+            let token_ids = Tensor::new(encoding.get_ids(), device)?.unsqueeze(0)?;
+            let token_type_ids = Tensor::new(encoding.get_type_ids(), device)?.unsqueeze(0)?;
+            let logits = maybe_autoreleasepool(|| {
+                let sequence_output =
+                    self.model
+                        .forward(&token_ids, &token_type_ids, None, false)?;
+                self.head.forward(&sequence_output)
+            })?;
+            let probabilities = ops::softmax(&logits.squeeze(0)?, D::Minus1)?;
+            let labels = probabilities.argmax(D::Minus1)?.to_vec1::<u32>()?;
+            let scores = probabilities.max(D::Minus1)?.to_vec1::<f32>()?;
+
+            for (((word, offsets), label), score) in encoding
+                .get_word_ids()
+                .iter()
+                .zip(encoding.get_offsets())
+                .zip(labels)
+                .zip(scores)
+            {
+                // Special tokens are not part of any word
+                if word.is_none() {
+                    continue;
+                }
+                tokens.push(TokenLabel {
+                    word: *word,
+                    byte_range: offsets.0..offsets.1,
+                    label: &self.labels[label as usize],
+                    score,
+                });
+            }
+        }
+
+        Ok(group_entities(text, &tokens))
+    }
+}
+
+/// The label predicted for one token
+struct TokenLabel<'a> {
+    word: Option<u32>,
+    byte_range: Range<usize>,
+    label: &'a str,
+    score: f32,
+}
+
+/// Group neighboring tokens with the same entity type into entities. Tokens that continue a word are always grouped with the start of the word.
+fn group_entities(text: &str, tokens: &[TokenLabel]) -> Vec<Entity> {
+    let mut entities = Vec::new();
+    let mut current: Option<(Entity, Vec<f32>)> = None;
+    let mut finish = |current: Option<(Entity, Vec<f32>)>| {
+        if let Some((mut entity, scores)) = current {
+            entity.score = scores.iter().sum::<f32>() / scores.len() as f32;
+            entity.text = text
+                .get(entity.byte_range.clone())
+                .unwrap_or_default()
+                .to_string();
+            entities.push(entity);
+        }
+    };
+
+    let mut previous_word = None;
+    for token in tokens {
+        let continues_word = token.word.is_some() && token.word == previous_word;
+        previous_word = token.word;
+        if continues_word {
+            if let Some((entity, scores)) = &mut current {
+                entity.byte_range.end = token.byte_range.end;
+                scores.push(token.score);
+            }
+            continue;
+        }
+
+        if token.label == "O" {
+            finish(current.take());
+            continue;
+        }
+        let (prefix, label) = token.label.split_once('-').unwrap_or(("B", token.label));
+        match &mut current {
+            Some((entity, scores)) if prefix == "I" && entity.label == label => {
+                entity.byte_range.end = token.byte_range.end;
+                scores.push(token.score);
+            }
+            _ => {
+                finish(current.take());
+                current = Some((
+                    Entity {
+                        label: label.to_string(),
+                        text: String::new(),
+                        byte_range: token.byte_range.clone(),
+                        score: 0.0,
+                    },
+                    vec![token.score],
+                ));
+            }
+        }
+    }
+    finish(current);
+
+    entities
+}
+
+#[test]
+fn test_group_entities() {
+    let text = "Ada Lovelace met Babbage in London";
+    let token = |word: u32, byte_range: Range<usize>, label: &'static str| TokenLabel {
+        word: Some(word),
+        byte_range,
+        label,
+        score: 1.0,
+    };
+    let tokens = [
+        token(0, 0..3, "B-PER"),
+        token(1, 4..8, "I-PER"),
+        token(1, 8..12, "O"),
+        token(2, 13..16, "O"),
+        token(3, 17..20, "B-PER"),
+        token(3, 20..24, "I-MISC"),
+        token(4, 25..27, "O"),
+        token(5, 28..34, "B-LOC"),
+    ];
+    let entities = group_entities(text, &tokens)
+        .into_iter()
+        .map(|entity| (entity.label, entity.text))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        entities,
+        [
+            ("PER".to_string(), "Ada Lovelace".to_string()),
+            ("PER".to_string(), "Babbage".to_string()),
+            ("LOC".to_string(), "London".to_string()),
+        ]
+    );
+}
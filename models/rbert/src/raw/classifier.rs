@@ -0,0 +1,73 @@
+use candle_core::{IndexOp, Result, Tensor};
+use candle_nn::{Module, VarBuilder};
+use candle_transformers::models::with_tracing::{linear, Linear};
+
+/// A sequence classification head that sits on top of the CLS token of a [`super::BertModel`].
+// https://github.com/huggingface/transformers/blob/6eedfa6dd15dc1e22a55ae036f681914e5a0d9a1/src/transformers/models/bert/modeling_bert.py#L1517
+// https://github.com/huggingface/transformers/blob/6eedfa6dd15dc1e22a55ae036f681914e5a0d9a1/src/transformers/models/roberta/modeling_roberta.py#L1413
+pub(crate) enum SequenceClassificationHead {
+    /// Bert models run the CLS token through the pooler before the classifier
+    Bert { pooler: Linear, classifier: Linear },
+    /// Roberta models have a dense layer and an output projection in the classifier
+    Roberta { dense: Linear, out_proj: Linear },
+}
+
+impl SequenceClassificationHead {
+    pub(crate) fn load(vb: VarBuilder, config: &super::Config, num_labels: usize) -> Result<Self> {
+        let hidden_size = config.hidden_size;
+        let roberta = (|| {
+            Ok::<_, candle_core::Error>(Self::Roberta {
+                dense: linear(hidden_size, hidden_size, vb.pp("classifier.dense"))?,
+                out_proj: linear(hidden_size, num_labels, vb.pp("classifier.out_proj"))?,
+            })
+        })();
+        if let Ok(head) = roberta {
+            return Ok(head);
+        }
+
+        let pooler = match &config.model_type {
+            Some(model_type) => linear(
+                hidden_size,
+                hidden_size,
+                vb.pp(format!("{model_type}.pooler.dense")),
+            )
+            .or_else(|_| linear(hidden_size, hidden_size, vb.pp("pooler.dense")))?,
+            None => linear(hidden_size, hidden_size, vb.pp("pooler.dense"))?,
+        };
+        let classifier = linear(hidden_size, num_labels, vb.pp("classifier"))?;
+        Ok(Self::Bert { pooler, classifier })
+    }
+
+    /// Get the logits for each label from the output of the bert model
+    pub(crate) fn forward(&self, sequence_output: &Tensor) -> Result<Tensor> {
+        let cls = sequence_output.i((.., 0, ..))?;
+        match self {
+            Self::Bert { pooler, classifier } => {
+                let pooled = pooler.forward(&cls)?.tanh()?;
+                classifier.forward(&pooled)
+            }
+            Self::Roberta { dense, out_proj } => {
+                let hidden = dense.forward(&cls)?.tanh()?;
+                out_proj.forward(&hidden)
+            }
+        }
+    }
+}
+
+/// A token classification head that classifies every token in the output of a [`super::BertModel`].
+// https://github.com/huggingface/transformers/blob/6eedfa6dd15dc1e22a55ae036f681914e5a0d9a1/src/transformers/models/bert/modeling_bert.py#L1735
+pub(crate) struct TokenClassificationHead {
+    classifier: Linear,
+}
+
+impl TokenClassificationHead {
+    pub(crate) fn load(vb: VarBuilder, config: &super::Config, num_labels: usize) -> Result<Self> {
+        let classifier = linear(config.hidden_size, num_labels, vb.pp("classifier"))?;
+        Ok(Self { classifier })
+    }
+
+    /// Get the logits for each label for every token from the output of the bert model
+    pub(crate) fn forward(&self, sequence_output: &Tensor) -> Result<Tensor> {
+        self.classifier.forward(sequence_output)
+    }
+}
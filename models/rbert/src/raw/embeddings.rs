@@ -11,6 +11,7 @@ use candle_transformers::models::with_tracing::{layer_norm, LayerNorm};
 pub(crate) struct BertEmbeddings {
     word_embeddings: Embedding,
     position_embeddings: Option<Embedding>,
+    position_offset: usize,
     token_type_embeddings: Embedding,
     layer_norm: LayerNorm,
     dropout: Dropout,
@@ -42,6 +43,7 @@ impl BertEmbeddings {
         Ok(Self {
             word_embeddings,
             position_embeddings: Some(position_embeddings),
+            position_offset: config.position_offset(),
             token_type_embeddings,
             layer_norm,
             dropout: Dropout::new(config.hidden_dropout_prob),
@@ -61,7 +63,11 @@ impl BertEmbeddings {
         let token_type_embeddings = self.token_type_embeddings.forward(token_type_ids)?;
         let mut embeddings = (&input_embeddings + token_type_embeddings)?;
         if let Some(position_embeddings) = &self.position_embeddings {
-            let position_ids = Tensor::arange(0, seq_len as u32, input_ids.device())?;
+            let position_ids = Tensor::arange(
+                self.position_offset as u32,
+                (self.position_offset + seq_len) as u32,
+                input_ids.device(),
+            )?;
             embeddings = embeddings.broadcast_add(&position_embeddings.forward(&position_ids)?)?
         }
         let embeddings = self.layer_norm.forward(&embeddings)?;
@@ -76,7 +82,7 @@ impl BertEmbeddings {
     pub(crate) fn max_seq_len(&self) -> usize {
         self.position_embeddings
             .as_ref()
-            .map(|p| p.embeddings().dims()[0])
+            .map(|p| p.embeddings().dims()[0].saturating_sub(self.position_offset))
             .unwrap_or(0)
     }
 }
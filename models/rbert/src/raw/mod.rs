@@ -16,6 +16,10 @@ mod self_output;
 use self_output::*;
 mod intermediate_layer;
 use intermediate_layer::*;
+mod classifier;
+pub(crate) use classifier::*;
+
+use std::collections::HashMap;
 
 use candle_core::{DType, Device, Result, Tensor};
 use candle_nn::VarBuilder;
@@ -82,6 +86,43 @@ pub struct Config {
     use_cache: bool,
     classifier_dropout: Option<f64>,
     model_type: Option<String>,
+    /// The labels of a classification head, if the model has one
+    #[serde(default)]
+    id2label: HashMap<String, String>,
+    /// Whether Roberta style models start the position ids after the padding token id
+    #[serde(skip)]
+    roberta_position_ids: bool,
+}
+
+impl Config {
+    /// Get the labels the classification head of the model predicts, in order of the output index.
+    pub(crate) fn labels(&self) -> Vec<String> {
+        let mut labels = self
+            .id2label
+            .iter()
+            .filter_map(|(id, label)| Some((id.parse::<usize>().ok()?, label.clone())))
+            .collect::<Vec<_>>();
+        labels.sort_by_key(|(id, _)| *id);
+        labels.into_iter().map(|(_, label)| label).collect()
+    }
+
+    /// Offset the position ids of Roberta style models by the padding token id like the original implementation.
+    /// This is only enabled for the NLI models used for zero-shot classification, the embedding models keep
+    /// their existing position ids.
+    pub(crate) fn with_roberta_position_ids(mut self) -> Self {
+        self.roberta_position_ids = true;
+        self
+    }
+
+    /// The first position id of the model
+    fn position_offset(&self) -> usize {
+        match self.model_type.as_deref() {
+            Some("roberta") | Some("xlm-roberta") if self.roberta_position_ids => {
+                self.pad_token_id + 1
+            }
+            _ => 0,
+        }
+    }
 }
 
 /// A raw synchronous Bert model. You should generally use the [`super::Bert`] instead.
@@ -153,3 +194,35 @@ impl BertModel {
         self.embeddings.embedding_dim()
     }
 }
+
+#[test]
+fn test_roberta_position_ids_are_opt_in() -> Result<()> {
+    let config: Config = serde_json::from_str(
+        r#"{
+            "vocab_size": 8,
+            "hidden_size": 4,
+            "num_hidden_layers": 1,
+            "num_attention_heads": 1,
+            "intermediate_size": 4,
+            "hidden_act": "gelu",
+            "hidden_dropout_prob": 0.1,
+            "max_position_embeddings": 514,
+            "type_vocab_size": 1,
+            "initializer_range": 0.02,
+            "layer_norm_eps": 1e-5,
+            "pad_token_id": 1,
+            "model_type": "roberta"
+        }"#,
+    )
+    .unwrap();
+    let vb = VarBuilder::zeros(DTYPE, &Device::Cpu);
+
+    // Embedding models keep starting the position ids at 0
+    let model = BertModel::load(vb.clone(), &config)?;
+    assert_eq!(model.max_seq_len(), 514);
+
+    // NLI models start the position ids after the padding token like the original implementation
+    let model = BertModel::load(vb, &config.with_roberta_position_ids())?;
+    assert_eq!(model.max_seq_len(), 512);
+    Ok(())
+}
@@ -0,0 +1,497 @@
+use std::sync::{Arc, RwLock};
+
+use candle_core::{Tensor, D};
+use candle_nn::{ops, VarBuilder};
+use kalosm_common::*;
+use kalosm_model_types::{FileSource, ModelLoadingProgress};
+use tokenizers::{EncodeInput, PaddingParams, Tokenizer, TruncationParams, TruncationStrategy};
+
+use crate::raw::{SequenceClassificationHead, DTYPE};
+use crate::{load_config, load_tokenizer, BertError, BertLoadingError, BertModel};
+
+const DEFAULT_HYPOTHESIS_TEMPLATE: &str = "This example is {}.";
+
+/// The source of a [`ZeroShotClassifier`] model. The model must be a natural language inference (NLI) model with
+/// `entailment` and `contradiction` labels.
+///
+/// Only BERT and RoBERTa style cross-encoders (like the [`cross-encoder`](https://huggingface.co/cross-encoder) NLI
+/// models) are supported. Other NLI architectures like BART or DeBERTa fail to load with
+/// [`BertLoadingError::UnsupportedModelType`].
+pub struct ZeroShotClassifierSource {
+    pub(crate) config: FileSource,
+    pub(crate) tokenizer: FileSource,
+    pub(crate) model: FileSource,
+}
+
+impl ZeroShotClassifierSource {
+    /// Create a new [`ZeroShotClassifierSource`] with the default model
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the model to use
+    pub fn with_model(mut self, model: FileSource) -> Self {
+        self.model = model;
+        self
+    }
+
+    /// Set the tokenizer to use
+    pub fn with_tokenizer(mut self, tokenizer: FileSource) -> Self {
+        self.tokenizer = tokenizer;
+        self
+    }
+
+    /// Set the config to use
+    pub fn with_config(mut self, config: FileSource) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Create a new [`ZeroShotClassifierSource`] with the [nli-MiniLM2-L6-H768](https://huggingface.co/cross-encoder/nli-MiniLM2-L6-H768) model
+    pub fn nli_mini_lm2_l6_h768() -> Self {
+        Self {
+            config: FileSource::huggingface(
+                "cross-encoder/nli-MiniLM2-L6-H768".to_string(),
+                "main".to_string(),
+                "config.json".to_string(),
+            ),
+            tokenizer: FileSource::huggingface(
+                "cross-encoder/nli-MiniLM2-L6-H768".to_string(),
+                "main".to_string(),
+                "tokenizer.json".to_string(),
+            ),
+            model: FileSource::huggingface(
+                "cross-encoder/nli-MiniLM2-L6-H768".to_string(),
+                "main".to_string(),
+                "model.safetensors".to_string(),
+            ),
+        }
+    }
+
+    /// Create a new [`ZeroShotClassifierSource`] with the [nli-distilroberta-base](https://huggingface.co/cross-encoder/nli-distilroberta-base) model
+    pub fn nli_distilroberta_base() -> Self {
+        Self {
+            config: FileSource::huggingface(
+                "cross-encoder/nli-distilroberta-base".to_string(),
+                "main".to_string(),
+                "config.json".to_string(),
+            ),
+            tokenizer: FileSource::huggingface(
+                "cross-encoder/nli-distilroberta-base".to_string(),
+                "main".to_string(),
+                "tokenizer.json".to_string(),
+            ),
+            model: FileSource::huggingface(
+                "cross-encoder/nli-distilroberta-base".to_string(),
+                "main".to_string(),
+                "model.safetensors".to_string(),
+            ),
+        }
+    }
+}
+
+impl Default for ZeroShotClassifierSource {
+    fn default() -> Self {
+        Self::nli_mini_lm2_l6_h768()
+    }
+}
+
+/// A builder for a [`ZeroShotClassifier`]
+pub struct ZeroShotClassifierBuilder {
+    source: ZeroShotClassifierSource,
+    hypothesis_template: String,
+    cache: kalosm_common::Cache,
+    weight_loading: WeightLoadingOptions,
+}
+
+impl Default for ZeroShotClassifierBuilder {
+    fn default() -> Self {
+        Self {
+            source: ZeroShotClassifierSource::default(),
+            hypothesis_template: DEFAULT_HYPOTHESIS_TEMPLATE.to_string(),
+            cache: kalosm_common::Cache::default(),
+            weight_loading: WeightLoadingOptions::default(),
+        }
+    }
+}
+
+impl ZeroShotClassifierBuilder {
+    /// Set the source of the model
+    pub fn with_source(mut self, source: ZeroShotClassifierSource) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// Set the template used to turn each candidate label into a hypothesis. `{}` is replaced with the label.
+    /// (defaults to `"This example is {}."`)
+    pub fn with_hypothesis_template(mut self, template: impl ToString) -> Self {
+        self.hypothesis_template = template.to_string();
+        self
+    }
+
+    /// Set the cache location to use for the model (defaults DATA_DIR/kalosm/cache)
+    pub fn with_cache(mut self, cache: kalosm_common::Cache) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    /// Set how the weights of the model are read from disk (defaults to memory mapping the weights)
+    pub fn with_weight_loading(mut self, weight_loading: WeightLoadingOptions) -> Self {
+        self.weight_loading = weight_loading;
+        self
+    }
+
+    /// Build the model
+    pub async fn build(self) -> Result<ZeroShotClassifier, BertLoadingError> {
+        self.build_with_loading_handler(ModelLoadingProgress::multi_bar_loading_indicator())
+            .await
+    }
+
+    /// Build the model with a loading handler
+    pub async fn build_with_loading_handler(
+        self,
+        progress_handler: impl FnMut(ModelLoadingProgress) + Send + 'static,
+    ) -> Result<ZeroShotClassifier, BertLoadingError> {
+        let ZeroShotClassifierBuilder {
+            source,
+            hypothesis_template,
+            cache,
+            weight_loading,
+        } = self;
+        let ZeroShotClassifierSource {
+            config,
+            tokenizer,
+            model,
+        } = source;
+        cache.ensure_available([&config, &tokenizer, &model])?;
+
+        // The config, tokenizer and weights are reported as a single download with a total size that is known before any of the files start
+        let size = cache.download_size([&config, &tokenizer, &model]).await;
+        let mut progress_handler = ModelLoadingProgress::aggregate_with_size(
+            format!("Model ({})", model),
+            size,
+            progress_handler,
+        );
+
+        let source = format!("Config ({})", config);
+        let mut create_progress = ModelLoadingProgress::downloading_progress(source);
+        let config_file = cache
+            .load(&config, |progress| {
+                progress_handler(create_progress(progress))
+            })
+            .await?;
+        let tokenizer_source = format!("Tokenizer ({})", tokenizer);
+        let mut create_progress = ModelLoadingProgress::downloading_progress(tokenizer_source);
+        let tokenizer_file = cache
+            .load(&tokenizer, |progress| {
+                progress_handler(create_progress(progress))
+            })
+            .await?;
+        let model_source = format!("Model ({})", model);
+        let mut create_progress = ModelLoadingProgress::downloading_progress(model_source);
+        let weights_file = cache
+            .load(&model, |progress| {
+                progress_handler(create_progress(progress))
+            })
+            .await?;
+
+        let config_bytes = config_file
+            .bytes()
+            .map_err(|_| BertLoadingError::ConfigNotFound)?;
+        check_model_type(&config_bytes)?;
+        let config = load_config(&config_file)?.with_roberta_position_ids();
+        let labels = config.labels();
+        let find_label = |name: &str| {
+            labels
+                .iter()
+                .position(|label| label.eq_ignore_ascii_case(name))
+                .ok_or_else(|| BertLoadingError::MissingLabel(name.to_string()))
+        };
+        let entailment = find_label("entailment")?;
+        let contradiction = find_label("contradiction")?;
+
+        let device = accelerated_device_if_available()?;
+        let weights = weights_file
+            .load_weights(weight_loading)
+            .map_err(CacheError::from)?;
+        let vb = VarBuilder::from_slice_safetensors(&weights, DTYPE, &device)?;
+        let model = BertModel::load(vb.clone(), &config)?;
+        let head = SequenceClassificationHead::load(vb, &config, labels.len())?;
+        let mut tokenizer = load_tokenizer(&tokenizer_file)?;
+        tokenizer.with_padding(None);
+        tokenizer
+            .with_truncation(Some(nli_truncation(model.max_seq_len())))
+            .map_err(BertLoadingError::LoadTokenizer)?;
+
+        Ok(ZeroShotClassifier {
+            model: Arc::new(model),
+            head: Arc::new(head),
+            tokenizer: Arc::new(RwLock::new(tokenizer)),
+            hypothesis_template: Arc::new(hypothesis_template),
+            entailment,
+            contradiction,
+        })
+    }
+}
+
+/// The model types of the encoder and classification heads the classifier can load. Configs without a model type are
+/// treated as BERT.
+const SUPPORTED_MODEL_TYPES: [&str; 3] = ["bert", "roberta", "xlm-roberta"];
+
+/// Check that the config is for a model the classifier can load before the rest of the config is read, so an
+/// unsupported model fails with a clear error instead of a missing field or weight.
+fn check_model_type(config: &[u8]) -> Result<(), BertLoadingError> {
+    #[derive(serde::Deserialize)]
+    struct ModelType {
+        model_type: Option<String>,
+    }
+
+    let ModelType { model_type } =
+        serde_json::from_slice(config).map_err(BertLoadingError::LoadConfig)?;
+    match model_type {
+        Some(model_type) if !SUPPORTED_MODEL_TYPES.contains(&model_type.as_str()) => {
+            Err(BertLoadingError::UnsupportedModelType(model_type))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// A zero-shot text classifier built on a natural language inference (NLI) model. Each candidate label is turned into a
+/// hypothesis (like "This example is sports.") and the model scores how strongly the text entails the hypothesis.
+///
+/// # Example
+/// ```rust, no_run
+/// use rbert::*;
+///
+/// #[tokio::main]
+/// async fn main() -> anyhow::Result<()> {
+///     let classifier = ZeroShotClassifier::new().await?;
+///     let result = classifier
+///         .classify(
+///             "The team scored in the last minute to win the cup",
+///             &["sports", "politics", "cooking"],
+///         )
+///         .await?;
+///     println!("{:?}", result.top());
+///     Ok(())
+/// }
+/// ```
+#[derive(Clone)]
+pub struct ZeroShotClassifier {
+    model: Arc<BertModel>,
+    head: Arc<SequenceClassificationHead>,
+    tokenizer: Arc<RwLock<Tokenizer>>,
+    hypothesis_template: Arc<String>,
+    entailment: usize,
+    contradiction: usize,
+}
+
+impl ZeroShotClassifier {
+    /// Create a new [`ZeroShotClassifierBuilder`]
+    pub fn builder() -> ZeroShotClassifierBuilder {
+        ZeroShotClassifierBuilder::default()
+    }
+
+    /// Create a new default zero-shot classifier
+    pub async fn new() -> Result<Self, BertLoadingError> {
+        Self::builder().build().await
+    }
+
+    /// Classify the text into exactly one of the candidate labels. The scores of all labels sum to one.
+    pub async fn classify(
+        &self,
+        text: impl ToString,
+        candidate_labels: &[&str],
+    ) -> Result<ZeroShotClassification, BertError> {
+        let text = text.to_string();
+        let labels = candidate_labels
+            .iter()
+            .map(|label| label.to_string())
+            .collect::<Vec<_>>();
+        let self_clone = self.clone();
+        crate::spawn_blocking(move || self_clone.classify_sync(&text, labels, false)).await?
+    }
+
+    /// Score each of the candidate labels independently. Each score is the probability that the label applies to the text,
+    /// so any number of labels can have a high score.
+    pub async fn classify_multi_label(
+        &self,
+        text: impl ToString,
+        candidate_labels: &[&str],
+    ) -> Result<ZeroShotClassification, BertError> {
+        let text = text.to_string();
+        let labels = candidate_labels
+            .iter()
+            .map(|label| label.to_string())
+            .collect::<Vec<_>>();
+        let self_clone = self.clone();
+        crate::spawn_blocking(move || self_clone.classify_sync(&text, labels, true)).await?
+    }
+
+    fn classify_sync(
+        &self,
+        text: &str,
+        labels: Vec<String>,
+        multi_label: bool,
+    ) -> Result<ZeroShotClassification, BertError> {
+        if labels.is_empty() {
+            return Ok(ZeroShotClassification { scores: Vec::new() });
+        }
+        let logits = maybe_autoreleasepool(|| self.nli_logits(text, &labels))?;
+        let entailment = logits.narrow(D::Minus1, self.entailment, 1)?;
+        let scores = if multi_label {
+            // Compare entailment against contradiction for each label independently
+            let contradiction = logits.narrow(D::Minus1, self.contradiction, 1)?;
+            let pair = Tensor::cat(&[&contradiction, &entailment], D::Minus1)?;
+            ops::softmax(&pair, D::Minus1)?
+                .narrow(D::Minus1, 1, 1)?
+                .flatten_all()?
+        } else {
+            // Compare the entailment of each label against every other label
+            ops::softmax(&entailment.flatten_all()?, D::Minus1)?
+        };
+        let scores = scores.to_vec1::<f32>()?;
+
+        let mut scores = labels.into_iter().zip(scores).collect::<Vec<_>>();
+        scores.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+        Ok(ZeroShotClassification { scores })
+    }
+
+    fn nli_logits(&self, premise: &str, labels: &[String]) -> Result<Tensor, BertError> {
+        let device = &self.model.device;
+        let inputs = labels
+            .iter()
+            .map(|label| {
+                let hypothesis = self.hypothesis_template.replace("{}", label);
+                EncodeInput::Dual(premise.into(), hypothesis.into())
+            })
+            .collect::<Vec<_>>();
+        let mut encodings = {
+            let tokenizer = self.tokenizer.read().unwrap();
+            tokenizer.encode_batch(inputs, true)
+        }
+        .map_err(BertError::TokenizerError)?;
+        let pp = PaddingParams {
+            strategy: tokenizers::PaddingStrategy::BatchLongest,
+            ..Default::default()
+        };
+        tokenizers::pad_encodings(&mut encodings, &pp).map_err(BertError::TokenizerError)?;
+
+        let stack = |get: &dyn Fn(&tokenizers::Encoding) -> &[u32]| {
+            let rows = encodings
+                .iter()
+                .map(|encoding| Tensor::new(get(encoding), device))
+                .collect::<candle_core::Result<Vec<_>>>()?;
+            Tensor::stack(&rows, 0)
+        };
+        let token_ids = stack(&|encoding| encoding.get_ids())?;
+        let token_type_ids = stack(&|encoding| encoding.get_type_ids())?;
+        let attention_mask = stack(&|encoding| encoding.get_attention_mask())?;
+
+        let sequence_output =
+            self.model
+                .forward(&token_ids, &token_type_ids, Some(&attention_mask), false)?;
+        Ok(self.head.forward(&sequence_output)?)
+    }
+}
+
+/// Long premises are truncated to fit in the model. The hypothesis is never truncated because it contains the label.
+fn nli_truncation(max_length: usize) -> TruncationParams {
+    TruncationParams {
+        max_length,
+        strategy: TruncationStrategy::OnlyFirst,
+        ..Default::default()
+    }
+}
+
+/// The result of a [`ZeroShotClassifier`]
+#[derive(Debug, Clone)]
+pub struct ZeroShotClassification {
+    scores: Vec<(String, f32)>,
+}
+
+impl ZeroShotClassification {
+    /// Get the score of each candidate label, sorted from the most to the least likely label
+    pub fn scores(&self) -> &[(String, f32)] {
+        &self.scores
+    }
+
+    /// Get the most likely label
+    pub fn top(&self) -> Option<&str> {
+        self.scores.first().map(|(label, _)| label.as_str())
+    }
+
+    /// Get the score of a specific label
+    pub fn score(&self, label: &str) -> Option<f32> {
+        self.scores
+            .iter()
+            .find(|(candidate, _)| candidate == label)
+            .map(|(_, score)| *score)
+    }
+}
+
+#[test]
+fn test_nli_truncation_keeps_the_hypothesis() {
+    use tokenizers::models::wordlevel::WordLevel;
+    use tokenizers::pre_tokenizers::whitespace::Whitespace;
+    use tokenizers::processors::template::TemplateProcessing;
+
+    let words = [
+        "[CLS]", "[SEP]", "[UNK]", "a", "long", "premise", "this", "is", "sports",
+    ];
+    let vocab = words
+        .iter()
+        .enumerate()
+        .map(|(id, word)| (word.to_string(), id as u32))
+        .collect();
+    let model = WordLevel::builder()
+        .vocab(vocab)
+        .unk_token("[UNK]".to_string())
+        .build()
+        .unwrap();
+    let post_processor = TemplateProcessing::builder()
+        .try_single("[CLS] $A [SEP]")
+        .unwrap()
+        .try_pair("[CLS] $A [SEP] $B:1 [SEP]:1")
+        .unwrap()
+        .special_tokens(vec![("[CLS]", 0), ("[SEP]", 1)])
+        .build()
+        .unwrap();
+    let mut tokenizer = Tokenizer::new(model);
+    tokenizer
+        .with_pre_tokenizer(Some(Whitespace {}))
+        .with_post_processor(Some(post_processor))
+        .with_truncation(Some(nli_truncation(8)))
+        .unwrap();
+
+    let premise = "a long long long long long long premise";
+    let hypothesis = "this is sports";
+    let encoding = tokenizer
+        .encode(EncodeInput::Dual(premise.into(), hypothesis.into()), true)
+        .unwrap();
+    assert_eq!(
+        encoding.get_tokens(),
+        ["[CLS]", "a", "long", "[SEP]", "this", "is", "sports", "[SEP]"]
+    );
+}
+
+#[test]
+fn test_zero_shot_rejects_unsupported_model_types() {
+    for config in [
+        r#"{"model_type": "bert"}"#,
+        r#"{"model_type": "roberta"}"#,
+        r#"{"model_type": "xlm-roberta"}"#,
+        r#"{"hidden_size": 768}"#,
+    ] {
+        assert!(check_model_type(config.as_bytes()).is_ok(), "{config}");
+    }
+    for (config, model_type) in [
+        (r#"{"model_type": "bart", "d_model": 1024}"#, "bart"),
+        (r#"{"model_type": "deberta-v2"}"#, "deberta-v2"),
+    ] {
+        assert!(matches!(
+            check_model_type(config.as_bytes()),
+            Err(BertLoadingError::UnsupportedModelType(found)) if found == model_type
+        ));
+    }
+}
@@ -0,0 +1,61 @@
+use std::{
+    fs::File,
+    io::{BufReader, Cursor},
+    path::Path,
+};
+
+use rodio::Decoder;
+
+use crate::{TranscriptionTask, Whisper};
+
+/// An error that can occur when decoding an audio file
+#[derive(Debug, thiserror::Error)]
+pub enum AudioDecodingError {
+    /// The audio file could not be read
+    #[error("Failed to read audio file: {0}")]
+    Io(#[from] std::io::Error),
+    /// The audio file is in a format that is not supported or is corrupted
+    #[error("Failed to decode audio: {0}")]
+    Decode(#[from] rodio::decoder::DecoderError),
+}
+
+/// An audio file decoded with [`decode_audio_file`] or [`decode_audio`]. The file can be passed to [`Whisper::transcribe`] or any other function that accepts a [`rodio::Source`].
+pub type AudioFile<R> = Decoder<R>;
+
+/// Open and decode an audio file. Wav, flac, mp3, ogg vorbis and m4a/aac files are supported.
+///
+/// The audio can have any sample rate or number of channels. It is resampled and mixed down to the 16khz mono audio whisper needs when it is transcribed.
+pub fn decode_audio_file(
+    path: impl AsRef<Path>,
+) -> Result<AudioFile<BufReader<File>>, AudioDecodingError> {
+    let file = BufReader::new(File::open(path)?);
+    Ok(Decoder::new(file)?)
+}
+
+/// Decode an audio file that is already in memory. See [`decode_audio_file`] for the supported formats.
+pub fn decode_audio(
+    bytes: impl Into<Vec<u8>>,
+) -> Result<AudioFile<Cursor<Vec<u8>>>, AudioDecodingError> {
+    Ok(Decoder::new(Cursor::new(bytes.into()))?)
+}
+
+impl Whisper {
+    /// Decode and transcribe an audio file. See [`decode_audio_file`] for the supported formats.
+    ///
+    /// ```rust, no_run
+    /// use kalosm::sound::*;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), anyhow::Error> {
+    /// let model = Whisper::new().await?;
+    /// let mut text = model.transcribe_file("./interview.m4a")?;
+    /// text.to_std_out().await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn transcribe_file(
+        &self,
+        path: impl AsRef<Path>,
+    ) -> Result<TranscriptionTask, AudioDecodingError> {
+        Ok(self.transcribe(decode_audio_file(path)?))
+    }
+}
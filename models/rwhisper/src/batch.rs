@@ -0,0 +1,213 @@
+use std::{collections::VecDeque, task::Poll};
+
+use cpal::FromSample;
+use futures_channel::oneshot;
+use futures_util::{FutureExt, Stream, StreamExt};
+use rodio::Source;
+
+use crate::{normalize_audio, Segment, TranscriptionTask, Whisper};
+
+/// A segment from one of the files in a [`BatchTranscriptionTask`]
+#[derive(Debug, Clone)]
+pub struct BatchSegment {
+    file_index: usize,
+    progress: f32,
+    segment: Segment,
+}
+
+impl BatchSegment {
+    /// Get the index of the file this segment was transcribed from, in the order the files were passed to [`Whisper::transcribe_batch`]
+    pub fn file_index(&self) -> usize {
+        self.file_index
+    }
+
+    /// Get the progress of the whole batch, from 0 to 1
+    pub fn progress(&self) -> f32 {
+        self.progress
+    }
+
+    /// Get the transcribed segment
+    pub fn segment(&self) -> &Segment {
+        &self.segment
+    }
+}
+
+impl AsRef<str> for BatchSegment {
+    fn as_ref(&self) -> &str {
+        self.segment.as_ref()
+    }
+}
+
+type PrepareAudio = Box<dyn FnOnce() -> Vec<f32> + Send>;
+
+enum BatchFile {
+    /// The audio is being decoded and resampled on the rayon thread pool
+    Preparing(oneshot::Receiver<Vec<f32>>),
+    /// The audio is queued or running on the model
+    Transcribing(TranscriptionTask),
+}
+
+/// A transcription of many audio files with one [`Whisper`] model. Created with [`Whisper::transcribe_batch`].
+///
+/// Files are decoded in parallel and queued on the model as soon as they are ready, so the model never waits for the next file to load.
+pub struct BatchTranscriptionTask {
+    whisper: Whisper,
+    word_level_time_stamps: bool,
+    prompt: Option<String>,
+    max_parallel: usize,
+    pending: VecDeque<(usize, PrepareAudio)>,
+    active: Vec<(usize, BatchFile)>,
+    file_progress: Vec<f32>,
+}
+
+impl BatchTranscriptionTask {
+    /// Include word level timestamps in the transcription.
+    pub fn timestamped(mut self) -> Self {
+        self.word_level_time_stamps = true;
+        self
+    }
+
+    /// Condition the model on some text that comes before each file. See [`TranscriptionTask::with_prompt`] for more details.
+    pub fn with_prompt(mut self, prompt: impl Into<String>) -> Self {
+        self.prompt = Some(prompt.into());
+        self
+    }
+
+    /// Set the maximum number of files that are loaded or queued on the model at the same time. (defaults to the number of available threads)
+    pub fn with_max_parallel(mut self, max_parallel: usize) -> Self {
+        self.max_parallel = max_parallel.max(1);
+        self
+    }
+
+    fn start_pending(&mut self) {
+        while self.active.len() < self.max_parallel {
+            let Some((index, prepare)) = self.pending.pop_front() else {
+                break;
+            };
+            let (sender, receiver) = oneshot::channel();
+            rayon::spawn(move || {
+                _ = sender.send(prepare());
+            });
+            self.active.push((index, BatchFile::Preparing(receiver)));
+        }
+    }
+
+    fn progress(&self) -> f32 {
+        self.file_progress.iter().sum::<f32>() / self.file_progress.len().max(1) as f32
+    }
+}
+
+impl Whisper {
+    /// Transcribe many audio files with this model. The segments of every file are returned in one stream, and each segment includes the index of the file it came from.
+    ///
+    /// ```rust, no_run
+    /// use futures_util::StreamExt;
+    /// use kalosm::sound::*;
+    /// use std::{fs::File, io::BufReader};
+    ///
+    /// #[tokio::main]
+    /// async fn main() -> Result<(), anyhow::Error> {
+    ///     let model = Whisper::new().await?;
+    ///     let files = ["first.wav", "second.wav", "third.wav"];
+    ///     let audio = files
+    ///         .iter()
+    ///         .map(|path| rodio::Decoder::new(BufReader::new(File::open(path).unwrap())).unwrap());
+    ///     let mut segments = model.transcribe_batch(audio).with_max_parallel(2);
+    ///     while let Some(segment) = segments.next().await {
+    ///         let file = files[segment.file_index()];
+    ///         let progress = segment.progress() * 100.;
+    ///         println!("[{progress:.0}%] {file}: {}", segment.segment().text());
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn transcribe_batch<S>(&self, inputs: impl IntoIterator<Item = S>) -> BatchTranscriptionTask
+    where
+        S: Source + Send + 'static,
+        <S as Iterator>::Item: rodio::Sample,
+        f32: FromSample<<S as Iterator>::Item>,
+    {
+        let pending: VecDeque<_> = inputs
+            .into_iter()
+            .enumerate()
+            .map(|(index, input)| {
+                let prepare: PrepareAudio = Box::new(move || normalize_audio(input));
+                (index, prepare)
+            })
+            .collect();
+        BatchTranscriptionTask {
+            whisper: self.clone(),
+            word_level_time_stamps: false,
+            prompt: None,
+            max_parallel: std::thread::available_parallelism()
+                .map(|threads| threads.get())
+                .unwrap_or(1),
+            file_progress: vec![0.; pending.len()],
+            pending,
+            active: Vec::new(),
+        }
+    }
+}
+
+impl Stream for BatchTranscriptionTask {
+    type Item = BatchSegment;
+
+    fn poll_next(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        let myself = self.get_mut();
+        'outer: loop {
+            myself.start_pending();
+            if myself.active.is_empty() {
+                return Poll::Ready(None);
+            }
+
+            for i in 0..myself.active.len() {
+                let (file_index, file) = &mut myself.active[i];
+                let file_index = *file_index;
+                if let BatchFile::Preparing(receiver) = file {
+                    let Poll::Ready(audio) = receiver.poll_unpin(cx) else {
+                        continue;
+                    };
+                    let audio = audio.unwrap_or_default();
+                    // Files without any audio are skipped
+                    if audio.is_empty() {
+                        tracing::warn!("File {file_index} in the batch has no audio");
+                        myself.file_progress[file_index] = 1.;
+                        myself.active.remove(i);
+                        continue 'outer;
+                    }
+                    *file = BatchFile::Transcribing(TranscriptionTask {
+                        word_level_time_stamps: myself.word_level_time_stamps,
+                        prompt: myself.prompt.clone(),
+                        audio,
+                        sender: myself.whisper.inner.sender.clone(),
+                        receiver: Default::default(),
+                    });
+                }
+                let BatchFile::Transcribing(task) = file else {
+                    unreachable!()
+                };
+                match task.poll_next_unpin(cx) {
+                    Poll::Ready(Some(segment)) => {
+                        myself.file_progress[file_index] = segment.progress();
+                        return Poll::Ready(Some(BatchSegment {
+                            file_index,
+                            progress: myself.progress(),
+                            segment,
+                        }));
+                    }
+                    Poll::Ready(None) => {
+                        myself.file_progress[file_index] = 1.;
+                        myself.active.remove(i);
+                        continue 'outer;
+                    }
+                    Poll::Pending => {}
+                }
+            }
+
+            return Poll::Pending;
+        }
+    }
+}
@@ -35,7 +35,6 @@
 
 use cpal::FromSample;
 use futures_channel::mpsc::{UnboundedReceiver, UnboundedSender};
-use kalosm_common::Cache;
 use kalosm_language_model::ModelBuilder;
 pub use kalosm_model_types::{FileSource, ModelLoadingProgress};
 use model::{WhisperInner, WhisperLoadingError};
@@ -52,7 +51,13 @@ use candle_transformers::models::whisper::{self as m};
 
 use futures_util::{Stream, StreamExt};
 
+mod audio;
+pub use audio::*;
+mod batch;
+pub use batch::*;
 mod model;
+mod subtitles;
+pub use subtitles::*;
 mod source;
 pub use source::*;
 mod quantized;
@@ -120,6 +125,7 @@ pub struct Segment {
     elapsed_time: Duration,
     remaining_time: Duration,
     progress: f32,
+    language: Option<WhisperLanguage>,
     result: DecodingResult,
 }
 
@@ -172,6 +178,11 @@ impl Segment {
         self.progress
     }
 
+    /// Get the language of the segment. This is the language set with [`WhisperBuilder::with_language`], or the language detected from the start of the audio if no language was set.
+    pub fn language(&self) -> Option<WhisperLanguage> {
+        self.language
+    }
+
     /// Return the confidence of the transcription result (between 0 and 1)
     pub fn confidence(&self) -> f64 {
         self.result.avg_logprob.exp()
@@ -204,6 +215,7 @@ where
     fn transcribe(self, model: Whisper) -> ChunkedTranscriptionTask<S> {
         ChunkedTranscriptionTask {
             word_level_time_stamps: false,
+            prompt: None,
             stream: self,
             whisper: model,
             current_segment_task: None,
@@ -214,6 +226,7 @@ where
 /// A chunked audio transcription task which can be streamed from a [`Whisper`] model.
 pub struct ChunkedTranscriptionTask<S> {
     word_level_time_stamps: bool,
+    prompt: Option<String>,
     stream: S,
     whisper: Whisper,
     current_segment_task: Option<TranscriptionTask>,
@@ -225,6 +238,12 @@ impl<S> ChunkedTranscriptionTask<S> {
         self.word_level_time_stamps = true;
         self
     }
+
+    /// Condition the model on some text that comes before each chunk of audio. See [`TranscriptionTask::with_prompt`] for more details.
+    pub fn with_prompt(mut self, prompt: impl Into<String>) -> Self {
+        self.prompt = Some(prompt.into());
+        self
+    }
 }
 
 impl<S> Stream for ChunkedTranscriptionTask<S>
@@ -261,6 +280,9 @@ where
                     if myself.word_level_time_stamps {
                         task = task.timestamped();
                     }
+                    if let Some(prompt) = &myself.prompt {
+                        task = task.with_prompt(prompt.clone());
+                    }
                     myself.current_segment_task = Some(task);
                 }
                 std::task::Poll::Ready(None) => return std::task::Poll::Ready(None),
@@ -277,7 +299,6 @@ struct Task {
     without_timestamps: bool,
 }
 
-#[allow(dead_code)]
 #[derive(Clone, Copy, Debug)]
 enum TaskType {
     Transcribe,
@@ -307,6 +328,9 @@ pub struct WhisperBuilder {
     /// Language.
     language: Option<WhisperLanguage>,
 
+    /// The task to perform on the audio.
+    task: WhisperTask,
+
     /// The cache location to use for the model (defaults DATA_DIR/kalosm/cache)
     cache: kalosm_common::Cache,
 }
@@ -315,7 +339,8 @@ impl Default for WhisperBuilder {
     fn default() -> Self {
         Self {
             model: WhisperSource::default(),
-            language: Some(WhisperLanguage::English),
+            language: None,
+            task: WhisperTask::default(),
             cache: kalosm_common::Cache::default(),
         }
     }
@@ -334,7 +359,7 @@ impl ModelBuilder for WhisperBuilder {
 
     fn requires_download(&self) -> bool {
         let whisper = self.get_whisper_model_config();
-        let cache = Cache::default();
+        let cache = &self.cache;
         !cache.exists(&whisper.model)
             || !cache.exists(&whisper.tokenizer)
             || !cache.exists(&whisper.config)
@@ -489,13 +514,26 @@ impl WhisperBuilder {
     /// ```
     pub async fn build_with_loading_handler(
         self,
-        mut progress_handler: impl FnMut(ModelLoadingProgress) + Send + Sync + 'static,
+        progress_handler: impl FnMut(ModelLoadingProgress) + Send + Sync + 'static,
     ) -> Result<Whisper, WhisperLoadingError> {
         // Download section
         let whisper = self.get_whisper_model_config();
         let tokenizer_source = whisper.tokenizer;
         let model_source = whisper.model;
         let config_source = whisper.config;
+        self.cache
+            .ensure_available([&tokenizer_source, &model_source, &config_source])?;
+
+        // The tokenizer, weights and config are reported as a single download with a total size that is known before any of the files start
+        let size = self
+            .cache
+            .download_size([&tokenizer_source, &model_source, &config_source])
+            .await;
+        let mut progress_handler = ModelLoadingProgress::aggregate_with_size(
+            format!("Model ({})", model_source),
+            size,
+            progress_handler,
+        );
 
         let display_tokenizer_source = format!("Tokenizer ({})", tokenizer_source);
         let mut create_progress =
@@ -531,8 +569,8 @@ impl WhisperBuilder {
             while let Ok(message) = tx.recv() {
                 match message {
                     WhisperMessage::Kill => return,
-                    WhisperMessage::Transcribe(input, word_level_time_stamps, result) => {
-                        model.transcribe(input, word_level_time_stamps, result);
+                    WhisperMessage::Transcribe(input, word_level_time_stamps, prompt, result) => {
+                        model.transcribe(input, word_level_time_stamps, prompt, result);
                     }
                 }
             }
@@ -552,12 +590,34 @@ impl WhisperBuilder {
         self
     }
 
-    /// Set the language to be used.
+    /// Set the language of the audio, or `None` to detect the language from the start of each audio clip. (defaults to `None`)
+    ///
+    /// Models that only support English always transcribe English.
     pub fn with_language(mut self, language: Option<WhisperLanguage>) -> Self {
         self.language = language;
         self
     }
 
+    /// Set the task the model performs on the audio. (defaults to [`WhisperTask::Transcribe`])
+    ///
+    /// ```rust, no_run
+    /// use kalosm::sound::*;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), anyhow::Error> {
+    /// // Create a whisper model that translates any language into English text
+    /// let model = Whisper::builder()
+    ///     .with_source(WhisperSource::Medium)
+    ///     .with_task(WhisperTask::Translate)
+    ///     .build()
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_task(mut self, task: WhisperTask) -> Self {
+        self.task = task;
+        self
+    }
+
     /// Set the cache location to use for the model (defaults DATA_DIR/kalosm/cache)
     pub fn with_cache(mut self, cache: kalosm_common::Cache) -> Self {
         self.cache = cache;
@@ -566,9 +626,20 @@ impl WhisperBuilder {
     }
 }
 
+/// The task a whisper model performs on the audio
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum WhisperTask {
+    /// Transcribe the audio into text in the language that is spoken
+    #[default]
+    Transcribe,
+    /// Translate the audio into English text. This is only supported by multilingual models
+    Translate,
+}
+
 /// A language whisper can use
 #[allow(missing_docs)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum WhisperLanguage {
     English,
     Chinese,
@@ -937,6 +1008,7 @@ impl Whisper {
         let pcm_data: Vec<_> = normalize_audio(input);
         TranscriptionTask {
             word_level_time_stamps: false,
+            prompt: None,
             audio: pcm_data,
             sender: self.inner.sender.clone(),
             receiver: Default::default(),
@@ -947,6 +1019,7 @@ impl Whisper {
 /// A transcription task which can be streamed from a [`Whisper`] model.
 pub struct TranscriptionTask {
     word_level_time_stamps: bool,
+    prompt: Option<String>,
     audio: Vec<f32>,
     sender: std::sync::mpsc::Sender<WhisperMessage>,
     receiver: RwLock<Option<UnboundedReceiver<Segment>>>,
@@ -958,6 +1031,31 @@ impl TranscriptionTask {
         self.word_level_time_stamps = true;
         self
     }
+
+    /// Condition the model on some text that comes before the audio. The prompt can include names, jargon or the formatting you expect, and the model will be more likely to transcribe them correctly.
+    ///
+    /// ```rust, no_run
+    /// use futures_util::StreamExt;
+    /// use kalosm::sound::*;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), anyhow::Error> {
+    /// let model = Whisper::new().await?;
+    /// let audio = MicInput::default()
+    ///     .record_until(std::time::Instant::now() + std::time::Duration::from_secs(5))
+    ///     .await;
+    /// let mut text = model
+    ///     .transcribe(audio)
+    ///     .with_prompt("Kalosm, Floneum and Candle are Rust libraries.");
+    /// while let Some(segment) = text.next().await {
+    ///     print!("{}", segment.text());
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_prompt(mut self, prompt: impl Into<String>) -> Self {
+        self.prompt = Some(prompt.into());
+        self
+    }
 }
 
 impl Stream for TranscriptionTask {
@@ -976,6 +1074,7 @@ impl Stream for TranscriptionTask {
             _ = myself.sender.send(WhisperMessage::Transcribe(
                 pcm_data,
                 myself.word_level_time_stamps,
+                myself.prompt.take(),
                 sender,
             ));
 
@@ -988,7 +1087,7 @@ impl Stream for TranscriptionTask {
 
 enum WhisperMessage {
     Kill,
-    Transcribe(Vec<f32>, bool, UnboundedSender<Segment>),
+    Transcribe(Vec<f32>, bool, Option<String>, UnboundedSender<Segment>),
 }
 
 pub(crate) fn normalize_audio<S: Source>(input: S) -> Vec<f32>
@@ -996,8 +1095,18 @@ where
     <S as Iterator>::Item: rodio::Sample,
     f32: FromSample<<S as Iterator>::Item>,
 {
-    let resample = UniformSourceIterator::new(input, 1, m::SAMPLE_RATE as u32);
-    let pass_filter = resample.low_pass(3000).high_pass(200).convert_samples();
+    let channels = input.channels().max(1);
+    let resample: UniformSourceIterator<S, f32> =
+        UniformSourceIterator::new(input, channels, m::SAMPLE_RATE as u32);
+    // Average the channels together instead of only keeping the first channel
+    let mono: Vec<f32> = resample
+        .collect::<Vec<_>>()
+        .chunks(channels as usize)
+        .map(|frame| frame.iter().sum::<f32>() / frame.len() as f32)
+        .collect();
+    let pass_filter = rodio::buffer::SamplesBuffer::new(1, m::SAMPLE_RATE as u32, mono)
+        .low_pass(3000)
+        .high_pass(200);
 
     pass_filter.collect::<Vec<f32>>()
 }
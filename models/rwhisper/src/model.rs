@@ -17,6 +17,7 @@ use tokenizers::Tokenizer;
 use super::{DecodingResult, Segment};
 use crate::{
     quantized::TextDecoderCache, Task, TaskType, TokenChunk, WhisperBuilder, WhisperLanguage,
+    WhisperSource, WhisperTask,
 };
 
 enum ModelType {
@@ -77,6 +78,9 @@ pub enum WhisperLoadingError {
     /// Language not supported
     #[error("Language not supported: {0}")]
     UnsupportedLanguage(WhisperLanguage),
+    /// Translation is only supported by multilingual models
+    #[error("Translation is not supported by the English only model {0}; use a multilingual model instead")]
+    TranslationNotSupported(WhisperSource),
 }
 
 /// An error that can occur when running a [`Whisper`] model.
@@ -98,6 +102,7 @@ pub(crate) struct WhisperInner {
     device: Device,
     decoder: Decoder,
     config: Config,
+    task_type: TaskType,
 }
 
 impl WhisperInner {
@@ -125,6 +130,15 @@ impl WhisperInner {
             &mut mel_filters,
         );
         let attention_heads = settings.model.timestamp_attention_heads();
+        // English only models don't have a task token in their prompt
+        let task_type = match (settings.model.is_multilingual(), settings.task) {
+            (true, WhisperTask::Transcribe) => TaskType::Transcribe,
+            (true, WhisperTask::Translate) => TaskType::Translate,
+            (false, WhisperTask::Transcribe) => TaskType::Unset,
+            (false, WhisperTask::Translate) => {
+                return Err(WhisperLoadingError::TranslationNotSupported(settings.model))
+            }
+        };
 
         let model = ModelType::load(
             &weights_filename,
@@ -132,21 +146,26 @@ impl WhisperInner {
             config.clone(),
             settings.model.is_quantized(),
         )?;
-        let language_token = if settings.model.is_multilingual() {
-            let language = settings.language.unwrap_or(WhisperLanguage::English);
-            match token_id(&tokenizer, &format!("<|{language}|>")) {
-                Ok(token_id) => Some(token_id),
-                Err(_) => return Err(WhisperLoadingError::UnsupportedLanguage(language)),
+        let (language, language_token, language_tokens) = if settings.model.is_multilingual() {
+            match settings.language {
+                Some(language) => match token_id(&tokenizer, &format!("<|{language}|>")) {
+                    Ok(token_id) => (Some(language), Some(token_id), Vec::new()),
+                    Err(_) => return Err(WhisperLoadingError::UnsupportedLanguage(language)),
+                },
+                // Without a fixed language, the language is detected from the audio
+                None => (None, None, language_tokens(&tokenizer)),
             }
         } else {
-            None
+            (Some(WhisperLanguage::English), None, Vec::new())
         };
         let decoder = Decoder::new(
             model,
             tokenizer,
             0,
             &device,
+            language,
             language_token,
+            language_tokens,
             attention_heads,
         )?;
 
@@ -155,6 +174,7 @@ impl WhisperInner {
             device,
             decoder,
             config,
+            task_type,
         })
     }
 
@@ -162,8 +182,24 @@ impl WhisperInner {
         &mut self,
         pcm_data: Vec<f32>,
         word_level_time_stamps: bool,
+        prompt: Option<String>,
         result: UnboundedSender<Segment>,
     ) {
+        let prompt = match prompt {
+            Some(prompt) => match self
+                .decoder
+                .tokenizer
+                .encode(format!(" {}", prompt.trim()), false)
+            {
+                Ok(encoding) => encoding.get_ids().to_vec(),
+                Err(err) => {
+                    tracing::error!("Error tokenizing prompt: {err}");
+                    Vec::new()
+                }
+            },
+            None => Vec::new(),
+        };
+
         let mel = audio::pcm_to_mel(&self.config, &pcm_data, &self.mel_filters);
         let mel_len = mel.len();
         let mel = Tensor::from_vec(
@@ -176,8 +212,9 @@ impl WhisperInner {
         if let Err(err) = self.decoder.run(
             &mel,
             pcm_data.len(),
+            &prompt,
             Task {
-                task_type: TaskType::Unset,
+                task_type: self.task_type,
                 word_level_time_stamps,
                 without_timestamps: true,
             },
@@ -194,12 +231,17 @@ struct Decoder {
     tokenizer: Tokenizer,
     suppress_tokens: Tensor,
     sot_token: u32,
+    // The token that starts the text before the audio. Prompts are ignored if the tokenizer doesn't have this token
+    sot_prev_token: Option<u32>,
     transcribe_token: u32,
     translate_token: u32,
     eot_token: u32,
     no_speech_token: u32,
     no_timestamps_token: u32,
+    language: Option<WhisperLanguage>,
     language_token: Option<u32>,
+    // The tokens of every language the model can detect. This is empty if the language is fixed
+    language_tokens: Vec<(WhisperLanguage, u32)>,
     timestamp_token_range: RangeInclusive<u32>,
     attention_heads: Option<&'static [[usize; 2]]>,
 }
@@ -211,7 +253,9 @@ impl Decoder {
         tokenizer: Tokenizer,
         seed: u64,
         device: &Device,
+        language: Option<WhisperLanguage>,
         language_token: Option<u32>,
+        language_tokens: Vec<(WhisperLanguage, u32)>,
         attention_heads: Option<&'static [[usize; 2]]>,
     ) -> candle_core::Result<Self> {
         let no_timestamps_token = token_id(&tokenizer, m::NO_TIMESTAMPS_TOKEN)?;
@@ -228,6 +272,7 @@ impl Decoder {
             .collect();
         let suppress_tokens = Tensor::new(suppress_tokens.as_slice(), device)?;
         let sot_token = token_id(&tokenizer, m::SOT_TOKEN)?;
+        let sot_prev_token = token_id(&tokenizer, SOT_PREV_TOKEN).ok();
         let transcribe_token = token_id(&tokenizer, m::TRANSCRIBE_TOKEN)?;
         let translate_token = token_id(&tokenizer, m::TRANSLATE_TOKEN)?;
         let eot_token = token_id(&tokenizer, m::EOT_TOKEN)?;
@@ -250,11 +295,14 @@ impl Decoder {
             tokenizer,
             suppress_tokens,
             sot_token,
+            sot_prev_token,
             transcribe_token,
             translate_token,
             eot_token,
             no_speech_token,
+            language,
             language_token,
+            language_tokens,
             no_timestamps_token,
             timestamp_token_range,
             attention_heads,
@@ -289,19 +337,70 @@ impl Decoder {
         Ok(tensor)
     }
 
+    /// Get the language of the audio and the language token to decode with. If the language isn't fixed, it is detected from the audio features
+    fn detect_language(
+        &mut self,
+        audio_features: &Tensor,
+    ) -> candle_core::Result<(Option<WhisperLanguage>, Option<u32>)> {
+        if self.language_tokens.is_empty() {
+            return Ok((self.language, self.language_token));
+        }
+        // Find the most likely language token after the start of transcript token
+        let ys = match &mut self.model {
+            ModelType::Quantized(model) => model.decoder.forward(
+                &[self.sot_token],
+                audio_features,
+                &mut TextDecoderCache::new(),
+                None,
+            )?,
+            ModelType::Unquantized(model) => {
+                let tokens_t = Tensor::new(&[self.sot_token], audio_features.device())?;
+                model
+                    .decoder
+                    .forward(&tokens_t.unsqueeze(0)?, audio_features, true)?
+            }
+        };
+        let logits = match &self.model {
+            ModelType::Quantized(model) => model.decoder.final_linear(&ys.i((..1, ..1))?)?,
+            ModelType::Unquantized(model) => model.decoder.final_linear(&ys.i((..1, ..1))?)?,
+        }
+        .i(0)?
+        .i(0)?
+        .to_vec1::<f32>()?;
+        let (language, token) = *self
+            .language_tokens
+            .iter()
+            .max_by(|(_, a), (_, b)| logits[*a as usize].total_cmp(&logits[*b as usize]))
+            .unwrap();
+        tracing::trace!("detected language {language}");
+        Ok((Some(language), Some(token)))
+    }
+
+    #[allow(clippy::too_many_arguments)]
     fn decode(
         &mut self,
         audio_features: &Tensor,
         temperature: f64,
         task: Task,
+        language_token: Option<u32>,
+        prompt: &[u32],
         previous_tokens: &[u32],
         n_frames: usize,
     ) -> Result<DecodingResult, WhisperError> {
         let sample_len = self.model.config().max_target_positions / 2;
         let mut sum_logprob = 0f64;
         let mut no_speech_prob = f64::NAN;
-        let mut tokens = vec![self.sot_token];
-        if let Some(language_token) = self.language_token {
+        let mut tokens = Vec::new();
+        if let (Some(sot_prev_token), false) = (self.sot_prev_token, prompt.is_empty()) {
+            // Like openai's implementation, only the end of the prompt is kept so it uses at most half of the context
+            let max_prompt_len = self.model.config().max_target_positions / 2 - 1;
+            tokens.push(sot_prev_token);
+            tokens.extend(&prompt[prompt.len().saturating_sub(max_prompt_len)..]);
+        }
+        // The prompt is not part of the output
+        let prompt_len = tokens.len();
+        tokens.push(self.sot_token);
+        if let Some(language_token) = language_token {
             tokens.push(language_token);
         }
         match task.task_type {
@@ -456,7 +555,7 @@ impl Decoder {
         }
 
         let (text, chunks) = {
-            let mut remaining_tokens: Vec<_> = tokens
+            let mut remaining_tokens: Vec<_> = tokens[prompt_len..]
                 .iter()
                 .copied()
                 .filter(|t| !self.is_special(*t))
@@ -523,7 +622,7 @@ impl Decoder {
 
             (current_text, chunks)
         };
-        let avg_logprob = sum_logprob / tokens.len() as f64;
+        let avg_logprob = sum_logprob / (tokens.len() - prompt_len) as f64;
 
         let compression_ratio = {
             let mut e = ZlibEncoder::new(Vec::new(), Compression::default());
@@ -549,12 +648,21 @@ impl Decoder {
         &mut self,
         audio_features: &Tensor,
         task: Task,
+        language_token: Option<u32>,
+        prompt: &[u32],
         previous_tokens: &[u32],
         n_frames: usize,
     ) -> Result<DecodingResult, WhisperError> {
         for (i, &t) in m::TEMPERATURES.iter().enumerate() {
-            let dr: Result<DecodingResult, WhisperError> =
-                self.decode(audio_features, t, task, previous_tokens, n_frames);
+            let dr: Result<DecodingResult, WhisperError> = self.decode(
+                audio_features,
+                t,
+                task,
+                language_token,
+                prompt,
+                previous_tokens,
+                n_frames,
+            );
             if i == m::TEMPERATURES.len() - 1 {
                 return dr;
             }
@@ -579,6 +687,7 @@ impl Decoder {
         &mut self,
         mel: &Tensor,
         audio_frames: usize,
+        prompt: &[u32],
         task: Task,
         mut result: UnboundedSender<Segment>,
     ) -> Result<(), WhisperError> {
@@ -590,6 +699,8 @@ impl Decoder {
         let start_time = Instant::now();
         let mut chunk_indices = Vec::new();
         let mut chunked = Vec::new();
+        // The language is detected once from the first window and used for the rest of the audio
+        let mut language = None;
         // Keep looping until we have all the chunks we need
         while seek < content_frames {
             // Take a chunk up to the maximum size
@@ -617,6 +728,10 @@ impl Decoder {
             let mut tokens_in_sentence_fragment = Vec::new();
 
             for (audio_features, range) in split.iter().zip(chunk_indices.iter()) {
+                let (segment_language, language_token) = match language {
+                    Some(language) => language,
+                    None => *language.insert(self.detect_language(audio_features)?),
+                };
                 let segment_size = range.end - range.start;
                 let end = range.end;
                 let time_offset = (end * m::HOP_LENGTH) as f64 / m::SAMPLE_RATE as f64;
@@ -636,6 +751,8 @@ impl Decoder {
                 let dr = self.decode_with_fallback(
                     audio_features,
                     task,
+                    language_token,
+                    prompt,
                     &tokens_in_sentence_fragment,
                     n_frames,
                 )?;
@@ -676,6 +793,7 @@ impl Decoder {
                     remaining_time: remaining,
                     elapsed_time: elapsed,
                     progress,
+                    language: segment_language,
                     result: dr,
                 };
 
@@ -690,6 +808,22 @@ impl Decoder {
     }
 }
 
+const SOT_PREV_TOKEN: &str = "<|startofprev|>";
+
+/// Find the token of every language the tokenizer supports
+fn language_tokens(tokenizer: &Tokenizer) -> Vec<(WhisperLanguage, u32)> {
+    let mut tokens: Vec<_> = tokenizer
+        .get_vocab(true)
+        .into_iter()
+        .filter_map(|(token, id)| {
+            let code = token.strip_prefix("<|")?.strip_suffix("|>")?;
+            Some((code.parse().ok()?, id))
+        })
+        .collect();
+    tokens.sort_by_key(|(_, id)| *id);
+    tokens
+}
+
 pub fn token_id(tokenizer: &Tokenizer, token: &str) -> candle_core::Result<u32> {
     match tokenizer.token_to_id(token) {
         None => candle_core::bail!("no token-id for {token}"),
@@ -31,6 +31,12 @@ pub enum WhisperSource {
     Large,
     /// The large model v2.
     LargeV2,
+    /// The large model v3.
+    LargeV3,
+    /// The large-v3-turbo model. This model has only 4 decoder layers, so it is much faster than the large-v3 model with similar accuracy.
+    LargeV3Turbo,
+    /// The distil-small english model.
+    DistilSmallEn,
     /// The distil-medium english model.
     DistilMediumEn,
     /// The distil-large model.
@@ -55,6 +61,8 @@ impl WhisperSource {
             | Self::Medium
             | Self::Large
             | Self::LargeV2
+            | Self::LargeV3
+            | Self::LargeV3Turbo
             | Self::DistilLargeV2
             | Self::DistilLargeV3
             | Self::QuantizedDistilLargeV3
@@ -65,6 +73,7 @@ impl WhisperSource {
             | Self::SmallEn
             | Self::MediumEn
             | Self::QuantizedDistilMediumEn
+            | Self::DistilSmallEn
             | Self::DistilMediumEn => false,
         }
     }
@@ -95,6 +104,9 @@ impl WhisperSource {
             Self::MediumEn => ("openai/whisper-medium.en", "main"),
             Self::Large => ("openai/whisper-large", "main"),
             Self::LargeV2 => ("openai/whisper-large-v2", "main"),
+            Self::LargeV3 => ("openai/whisper-large-v3", "main"),
+            Self::LargeV3Turbo => ("openai/whisper-large-v3-turbo", "main"),
+            Self::DistilSmallEn => ("distil-whisper/distil-small.en", "main"),
             Self::DistilMediumEn => ("distil-whisper/distil-medium.en", "main"),
             Self::DistilLargeV2 => ("distil-whisper/distil-large-v2", "main"),
             Self::DistilLargeV3 => ("distil-whisper/distil-large-v3", "main"),
@@ -112,7 +124,10 @@ impl WhisperSource {
 
     pub(crate) fn timestamp_attention_heads(&self) -> Option<&'static [[usize; 2]]> {
         match self {
-            Self::QuantizedDistilMediumEn | Self::DistilMediumEn | Self::DistilLargeV2 => None,
+            Self::QuantizedDistilMediumEn
+            | Self::DistilSmallEn
+            | Self::DistilMediumEn
+            | Self::DistilLargeV2 => None,
             Self::QuantizedTiny | Self::Tiny => {
                 Some(&[[2, 2], [3, 0], [3, 2], [3, 3], [3, 4], [3, 5]])
             }
@@ -227,7 +242,19 @@ impl WhisperSource {
                 [26, 12],
                 [27, 15],
             ]),
-            Self::QuantizedLargeV3Turbo => {
+            Self::LargeV3 => Some(&[
+                [7, 0],
+                [10, 17],
+                [12, 18],
+                [13, 12],
+                [16, 1],
+                [17, 14],
+                [19, 11],
+                [21, 4],
+                [24, 1],
+                [25, 6],
+            ]),
+            Self::LargeV3Turbo | Self::QuantizedLargeV3Turbo => {
                 Some(&[[2, 4], [2, 11], [3, 3], [3, 6], [3, 11], [3, 14]])
             }
             Self::DistilLargeV3 | Self::QuantizedDistilLargeV3 => Some(&[
@@ -283,10 +310,15 @@ impl FromStr for WhisperSource {
             "medium_en" => Ok(Self::MediumEn),
             "large" => Ok(Self::Large),
             "large_v2" => Ok(Self::LargeV2),
+            "large_v3" => Ok(Self::LargeV3),
+            "large_v3_turbo" => Ok(Self::LargeV3Turbo),
+            "distil_small_en" => Ok(Self::DistilSmallEn),
             "distil_medium_en" => Ok(Self::DistilMediumEn),
             "distil_large_v2" => Ok(Self::DistilLargeV2),
             "distil_large_v3" => Ok(Self::DistilLargeV3),
+            "quantized_distil_medium_en" => Ok(Self::QuantizedDistilMediumEn),
             "quantized_distil_large_v3" => Ok(Self::QuantizedDistilLargeV3),
+            "quantized_large_v3_turbo" => Ok(Self::QuantizedLargeV3Turbo),
             _ => Err(ParseWhisperSourceError(s.to_owned())),
         }
     }
@@ -307,6 +339,9 @@ impl Display for WhisperSource {
             Self::MediumEn => write!(f, "medium_en"),
             Self::Large => write!(f, "large"),
             Self::LargeV2 => write!(f, "large_v2"),
+            Self::LargeV3 => write!(f, "large_v3"),
+            Self::LargeV3Turbo => write!(f, "large_v3_turbo"),
+            Self::DistilSmallEn => write!(f, "distil_small_en"),
             Self::DistilMediumEn => write!(f, "distil_medium_en"),
             Self::DistilLargeV2 => write!(f, "distil_large_v2"),
             Self::DistilLargeV3 => write!(f, "distil_large_v3"),
@@ -331,3 +366,35 @@ impl WhisperModelConfig {
         }
     }
 }
+
+#[cfg(test)]
+#[test]
+fn every_source_round_trips_through_its_name() {
+    let sources = [
+        WhisperSource::Tiny,
+        WhisperSource::QuantizedTiny,
+        WhisperSource::TinyEn,
+        WhisperSource::QuantizedTinyEn,
+        WhisperSource::Base,
+        WhisperSource::BaseEn,
+        WhisperSource::Small,
+        WhisperSource::SmallEn,
+        WhisperSource::Medium,
+        WhisperSource::MediumEn,
+        WhisperSource::QuantizedDistilMediumEn,
+        WhisperSource::Large,
+        WhisperSource::LargeV2,
+        WhisperSource::LargeV3,
+        WhisperSource::LargeV3Turbo,
+        WhisperSource::DistilSmallEn,
+        WhisperSource::DistilMediumEn,
+        WhisperSource::DistilLargeV2,
+        WhisperSource::DistilLargeV3,
+        WhisperSource::QuantizedDistilLargeV3,
+        WhisperSource::QuantizedLargeV3Turbo,
+    ];
+    for source in sources {
+        let parsed: WhisperSource = source.to_string().parse().unwrap();
+        assert_eq!(parsed.to_string(), source.to_string());
+    }
+}
@@ -0,0 +1,261 @@
+use std::{fmt::Write, future::Future, ops::Range};
+
+use futures_util::{Stream, StreamExt};
+
+use crate::{m::SAMPLE_RATE, Segment};
+
+/// A subtitle file format
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SubtitleFormat {
+    /// The SubRip (`.srt`) format
+    Srt,
+    /// The WebVTT (`.vtt`) format
+    WebVtt,
+}
+
+/// A single subtitle shown on screen for a range of time
+#[derive(Debug, Clone, PartialEq)]
+struct Cue {
+    /// The start and end of the cue in seconds
+    time: Range<f64>,
+    lines: Vec<String>,
+}
+
+/// Builds a subtitle file from transcribed [`Segment`]s.
+///
+/// If the segments were transcribed with word level timestamps (see [`crate::TranscriptionTask::timestamped`]), each subtitle is timed to the words it contains. Otherwise the time of each segment is split between its subtitles based on the length of the text.
+///
+/// ```rust, no_run
+/// use kalosm::sound::*;
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), anyhow::Error> {
+/// let model = Whisper::new().await?;
+/// let audio = rodio::Decoder::new(std::io::BufReader::new(std::fs::File::open("talk.wav")?))?;
+/// let srt = model.transcribe(audio).timestamped().to_srt().await;
+/// std::fs::write("talk.srt", srt)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct SubtitleWriter {
+    format: SubtitleFormat,
+    max_line_length: usize,
+    max_lines: usize,
+    cues: Vec<Cue>,
+}
+
+impl SubtitleWriter {
+    /// Create a new subtitle writer for the given format
+    pub fn new(format: SubtitleFormat) -> Self {
+        Self {
+            format,
+            max_line_length: 42,
+            max_lines: 2,
+            cues: Vec::new(),
+        }
+    }
+
+    /// Set the maximum number of characters in a line. Longer lines are wrapped at word boundaries. (defaults to 42)
+    pub fn with_max_line_length(mut self, max_line_length: usize) -> Self {
+        self.max_line_length = max_line_length.max(1);
+        self
+    }
+
+    /// Set the maximum number of lines shown on screen at once. Text that doesn't fit is split into multiple subtitles. (defaults to 2)
+    pub fn with_max_lines(mut self, max_lines: usize) -> Self {
+        self.max_lines = max_lines.max(1);
+        self
+    }
+
+    /// Add a segment to the subtitles. Segments must be added in the order they appear in the audio
+    pub fn push_segment(&mut self, segment: &Segment) {
+        let range = segment.sample_range();
+        let segment_start = range.start as f64 / SAMPLE_RATE as f64;
+        let segment_end = range.end as f64 / SAMPLE_RATE as f64;
+
+        // Group the token chunks into words. A chunk that starts with a space starts a new word
+        let mut words: Vec<(String, Option<Range<f64>>)> = Vec::new();
+        for chunk in segment.chunks() {
+            let text = chunk.text();
+            let time = chunk
+                .timestamp()
+                .map(|time| segment_start + time.start as f64..segment_start + time.end as f64);
+            match words.last_mut() {
+                Some((word, word_time)) if !text.starts_with(char::is_whitespace) => {
+                    word.push_str(text);
+                    *word_time = word_time.clone().zip(time).map(|(a, b)| a.start..b.end);
+                }
+                _ => words.push((text.trim().to_string(), time)),
+            }
+        }
+        words.retain(|(word, _)| !word.is_empty());
+        if words.is_empty() {
+            return;
+        }
+
+        let groups = self.group_words(words.iter().map(|(word, _)| word.as_str()));
+        let timed = words.iter().all(|(_, time)| time.is_some());
+        let total_chars: usize = words.iter().map(|(word, _)| word.len()).sum();
+        let mut word_index = 0;
+        let mut chars_before = 0;
+        for (lines, word_count) in groups {
+            let group = &words[word_index..word_index + word_count];
+            let time = if timed {
+                let start = group.first().unwrap().1.as_ref().unwrap().start;
+                let end = group.last().unwrap().1.as_ref().unwrap().end;
+                start..end
+            } else {
+                // Without word timestamps, assume the words are spoken at a constant rate
+                let chars: usize = group.iter().map(|(word, _)| word.len()).sum();
+                let duration = segment_end - segment_start;
+                let start = segment_start + duration * chars_before as f64 / total_chars as f64;
+                let end =
+                    segment_start + duration * (chars_before + chars) as f64 / total_chars as f64;
+                chars_before += chars;
+                start..end
+            };
+            word_index += word_count;
+            self.cues.push(Cue { time, lines });
+        }
+    }
+
+    /// Wrap the words into lines, and split the lines into groups that fit on screen at once. Returns the lines of each group and the number of words in the group
+    fn group_words<'a>(&self, words: impl Iterator<Item = &'a str>) -> Vec<(Vec<String>, usize)> {
+        // Each line and the number of words in it
+        let mut lines: Vec<(String, usize)> = Vec::new();
+        for word in words {
+            match lines.last_mut() {
+                Some((line, count)) if line.len() + 1 + word.len() <= self.max_line_length => {
+                    line.push(' ');
+                    line.push_str(word);
+                    *count += 1;
+                }
+                _ => lines.push((word.to_string(), 1)),
+            }
+        }
+        lines
+            .chunks(self.max_lines)
+            .map(|group| {
+                let lines = group.iter().map(|(line, _)| line.clone()).collect();
+                let count = group.iter().map(|(_, count)| count).sum();
+                (lines, count)
+            })
+            .collect()
+    }
+
+    /// Write the subtitles in the format of the writer
+    pub fn finish(&self) -> String {
+        let mut output = String::new();
+        if self.format == SubtitleFormat::WebVtt {
+            output.push_str("WEBVTT\n\n");
+        }
+        for (index, cue) in self.cues.iter().enumerate() {
+            if self.format == SubtitleFormat::Srt {
+                writeln!(output, "{}", index + 1).unwrap();
+            }
+            writeln!(
+                output,
+                "{} --> {}",
+                self.format_time(cue.time.start),
+                self.format_time(cue.time.end)
+            )
+            .unwrap();
+            for line in &cue.lines {
+                writeln!(output, "{line}").unwrap();
+            }
+            output.push('\n');
+        }
+        output
+    }
+
+    fn format_time(&self, seconds: f64) -> String {
+        let millis = (seconds.max(0.) * 1000.).round() as u64;
+        let separator = match self.format {
+            SubtitleFormat::Srt => ',',
+            SubtitleFormat::WebVtt => '.',
+        };
+        format!(
+            "{:02}:{:02}:{:02}{separator}{:03}",
+            millis / 3_600_000,
+            millis / 60_000 % 60,
+            millis / 1000 % 60,
+            millis % 1000
+        )
+    }
+}
+
+/// An extension trait for streams of transcribed [`Segment`]s that writes them as subtitles
+pub trait SubtitleStreamExt: Stream<Item = Segment> + Sized {
+    /// Collect the stream into a subtitle file written with the given writer
+    fn to_subtitles(self, mut writer: SubtitleWriter) -> impl Future<Output = String> {
+        async move {
+            let mut stream = std::pin::pin!(self);
+            while let Some(segment) = stream.next().await {
+                writer.push_segment(&segment);
+            }
+            writer.finish()
+        }
+    }
+
+    /// Collect the stream into a SubRip (`.srt`) subtitle file
+    fn to_srt(self) -> impl Future<Output = String> {
+        self.to_subtitles(SubtitleWriter::new(SubtitleFormat::Srt))
+    }
+
+    /// Collect the stream into a WebVTT (`.vtt`) subtitle file
+    fn to_vtt(self) -> impl Future<Output = String> {
+        self.to_subtitles(SubtitleWriter::new(SubtitleFormat::WebVtt))
+    }
+}
+
+impl<S: Stream<Item = Segment>> SubtitleStreamExt for S {}
+
+#[cfg(test)]
+#[test]
+fn subtitles_are_wrapped_and_timed() {
+    use crate::{DecodingResult, TokenChunk};
+
+    let text = " The quick brown fox jumps over the lazy dog and keeps running far away.";
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    for (i, _) in text.match_indices(' ').skip(1).chain([(text.len(), "")]) {
+        chunks.push(TokenChunk {
+            text_range: start..i,
+            timestamp: None,
+        });
+        start = i;
+    }
+    let segment = Segment {
+        sample_range: SAMPLE_RATE..SAMPLE_RATE * 8,
+        start: 1.,
+        duration: 7.,
+        elapsed_time: Default::default(),
+        remaining_time: Default::default(),
+        progress: 1.,
+        language: None,
+        result: DecodingResult {
+            text: text.to_string(),
+            avg_logprob: 0.,
+            no_speech_prob: 0.,
+            compression_ratio: 1.,
+            chunks,
+        },
+    };
+
+    let mut writer = SubtitleWriter::new(SubtitleFormat::Srt)
+        .with_max_line_length(20)
+        .with_max_lines(2);
+    writer.push_segment(&segment);
+    assert_eq!(
+        writer.finish(),
+        "1\n00:00:01,000 --> 00:00:04,862\nThe quick brown fox\njumps over the lazy\n\n\
+         2\n00:00:04,862 --> 00:00:08,000\ndog and keeps\nrunning far away.\n\n"
+    );
+
+    let mut writer = SubtitleWriter::new(SubtitleFormat::WebVtt).with_max_line_length(100);
+    writer.push_segment(&segment);
+    assert_eq!(
+        writer.finish(),
+        "WEBVTT\n\n00:00:01.000 --> 00:00:08.000\nThe quick brown fox jumps over the lazy dog and keeps running far away.\n\n"
+    );
+}
@@ -0,0 +1,705 @@
+//! The UNet used by both stages of Stable Cascade (Wuerstchen v3).
+//!
+//! A port of <https://github.com/huggingface/diffusers/blob/main/src/diffusers/models/unets/unet_stable_cascade.py> that loads the diffusers weights.
+
+use std::path::Path;
+
+use candle_core::{DType, Module, Result, Tensor, D};
+use candle_nn::{Conv2d, ConvTranspose2d, Linear, VarBuilder};
+use candle_transformers::models::wuerstchen::common::{
+    AttnBlock, GlobalResponseNorm, LayerNormNoWeights, WLayerNorm,
+};
+
+/// The configuration of a Stable Cascade UNet. The per level settings are ordered from the highest resolution level to the lowest.
+#[derive(Debug, Clone)]
+pub(crate) struct StableCascadeConfig {
+    in_channels: usize,
+    out_channels: usize,
+    patch_size: usize,
+    timestep_ratio_embedding_dim: usize,
+    /// The number of extra timestep conditions (sca, crp) the timestep blocks are conditioned on.
+    timestep_conditions: usize,
+    conditioning_dim: usize,
+    block_out_channels: Vec<usize>,
+    /// The number of attention heads in each level. Levels without heads have no attention blocks.
+    num_attention_heads: Vec<usize>,
+    down_num_layers_per_block: Vec<usize>,
+    up_num_layers_per_block: Vec<usize>,
+    down_blocks_repeat_mappers: Vec<usize>,
+    up_blocks_repeat_mappers: Vec<usize>,
+    clip_text_in_channels: Option<usize>,
+    clip_text_pooled_in_channels: usize,
+    clip_image_in_channels: Option<usize>,
+    clip_seq: usize,
+    effnet_in_channels: Option<usize>,
+    pixel_mapper_in_channels: Option<usize>,
+    /// If the levels are connected with 1x1 convolutions instead of strided convolutions.
+    switch_level: bool,
+}
+
+impl StableCascadeConfig {
+    // https://huggingface.co/stabilityai/stable-cascade-prior/blob/main/prior/config.json
+    pub(crate) fn prior() -> Self {
+        Self {
+            in_channels: 16,
+            out_channels: 16,
+            patch_size: 1,
+            timestep_ratio_embedding_dim: 64,
+            timestep_conditions: 2,
+            conditioning_dim: 2048,
+            block_out_channels: vec![2048, 2048],
+            num_attention_heads: vec![32, 32],
+            down_num_layers_per_block: vec![8, 24],
+            up_num_layers_per_block: vec![8, 24],
+            down_blocks_repeat_mappers: vec![1, 1],
+            up_blocks_repeat_mappers: vec![1, 1],
+            clip_text_in_channels: Some(1280),
+            clip_text_pooled_in_channels: 1280,
+            clip_image_in_channels: Some(768),
+            clip_seq: 4,
+            effnet_in_channels: None,
+            pixel_mapper_in_channels: None,
+            switch_level: true,
+        }
+    }
+
+    // https://huggingface.co/stabilityai/stable-cascade-prior/blob/main/prior_lite/config.json
+    pub(crate) fn prior_lite() -> Self {
+        Self {
+            block_out_channels: vec![1536, 1536],
+            num_attention_heads: vec![24, 24],
+            down_num_layers_per_block: vec![4, 12],
+            up_num_layers_per_block: vec![4, 12],
+            ..Self::prior()
+        }
+    }
+
+    // https://huggingface.co/stabilityai/stable-cascade/blob/main/decoder/config.json
+    pub(crate) fn decoder() -> Self {
+        Self {
+            in_channels: 4,
+            out_channels: 4,
+            patch_size: 4,
+            timestep_ratio_embedding_dim: 64,
+            timestep_conditions: 1,
+            conditioning_dim: 1280,
+            block_out_channels: vec![320, 640, 1280, 1280],
+            num_attention_heads: vec![0, 0, 20, 20],
+            down_num_layers_per_block: vec![2, 6, 28, 6],
+            up_num_layers_per_block: vec![2, 6, 28, 6],
+            down_blocks_repeat_mappers: vec![1, 1, 1, 1],
+            up_blocks_repeat_mappers: vec![2, 2, 3, 3],
+            clip_text_in_channels: None,
+            clip_text_pooled_in_channels: 1280,
+            clip_image_in_channels: None,
+            clip_seq: 4,
+            effnet_in_channels: Some(16),
+            pixel_mapper_in_channels: Some(3),
+            switch_level: false,
+        }
+    }
+
+    // https://huggingface.co/stabilityai/stable-cascade/blob/main/decoder_lite/config.json
+    pub(crate) fn decoder_lite() -> Self {
+        Self {
+            block_out_channels: vec![320, 576, 1152, 1152],
+            num_attention_heads: vec![0, 0, 18, 18],
+            down_num_layers_per_block: vec![2, 4, 14, 4],
+            up_num_layers_per_block: vec![2, 4, 14, 4],
+            ..Self::decoder()
+        }
+    }
+
+    /// Pick the full or lite prior configuration that matches the weights in the file.
+    pub(crate) fn prior_for_weights(weights: &Path) -> Result<Self> {
+        let lite = Self::prior_lite();
+        let channels = output_channels(weights, "embedding.1.weight")?;
+        Ok(match channels == lite.block_out_channels[0] {
+            true => lite,
+            false => Self::prior(),
+        })
+    }
+
+    /// Pick the full or lite decoder configuration that matches the weights in the file.
+    pub(crate) fn decoder_for_weights(weights: &Path) -> Result<Self> {
+        let lite = Self::decoder_lite();
+        let channels = output_channels(weights, "down_downscalers.1.1.weight")?;
+        Ok(match channels == lite.block_out_channels[1] {
+            true => lite,
+            false => Self::decoder(),
+        })
+    }
+}
+
+/// Read the number of output channels of a layer from the header of a safetensors file.
+fn output_channels(weights: &Path, name: &str) -> Result<usize> {
+    let tensors = unsafe { candle_core::safetensors::MmapedSafetensors::new(weights)? };
+    let tensor = tensors.get(name)?;
+    tensor
+        .shape()
+        .first()
+        .copied()
+        .ok_or_else(|| candle_core::Error::Msg(format!("{name} has no dimensions")))
+}
+
+/// A residual block with a depthwise convolution followed by a channelwise MLP. Unlike the Wuerstchen v2 block, the skip connection is added after the depthwise convolution.
+#[derive(Debug)]
+struct ResBlock {
+    depthwise: Conv2d,
+    norm: WLayerNorm,
+    channelwise_lin1: Linear,
+    channelwise_grn: GlobalResponseNorm,
+    channelwise_lin2: Linear,
+}
+
+impl ResBlock {
+    fn new(c: usize, c_skip: usize, vb: VarBuilder) -> Result<Self> {
+        let cfg = candle_nn::Conv2dConfig {
+            padding: 1,
+            groups: c,
+            ..Default::default()
+        };
+        let depthwise = candle_nn::conv2d(c, c, 3, cfg, vb.pp("depthwise"))?;
+        let norm = WLayerNorm::new(c)?;
+        let channelwise_lin1 = candle_nn::linear(c + c_skip, c * 4, vb.pp("channelwise.0"))?;
+        let channelwise_grn = GlobalResponseNorm::new(c * 4, vb.pp("channelwise.2"))?;
+        let channelwise_lin2 = candle_nn::linear(c * 4, c, vb.pp("channelwise.4"))?;
+        Ok(Self {
+            depthwise,
+            norm,
+            channelwise_lin1,
+            channelwise_grn,
+            channelwise_lin2,
+        })
+    }
+
+    fn forward(&self, xs: &Tensor, x_skip: Option<&Tensor>) -> Result<Tensor> {
+        let x_res = xs;
+        let xs = xs.apply(&self.depthwise)?.apply(&self.norm)?;
+        let xs = match x_skip {
+            None => xs,
+            Some(x_skip) => Tensor::cat(&[&xs, x_skip], 1)?,
+        };
+        let xs = xs
+            .permute((0, 2, 3, 1))?
+            .apply(&self.channelwise_lin1)?
+            .gelu_erf()?
+            .apply(&self.channelwise_grn)?
+            .apply(&self.channelwise_lin2)?
+            .permute((0, 3, 1, 2))?;
+        xs + x_res
+    }
+}
+
+/// Modulates the features with the timestep ratio and the extra timestep conditions.
+#[derive(Debug)]
+struct TimestepBlock {
+    mapper: Linear,
+    condition_mappers: Vec<Linear>,
+}
+
+impl TimestepBlock {
+    fn new(c: usize, c_timestep: usize, conditions: usize, vb: VarBuilder) -> Result<Self> {
+        let mapper = candle_nn::linear(c_timestep, c * 2, vb.pp("mapper"))?;
+        let condition_mappers = ["sca", "crp"][..conditions]
+            .iter()
+            .map(|name| candle_nn::linear(c_timestep, c * 2, vb.pp(format!("mapper_{name}"))))
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            mapper,
+            condition_mappers,
+        })
+    }
+
+    fn forward(&self, xs: &Tensor, t: &Tensor) -> Result<Tensor> {
+        let t = t.chunk(self.condition_mappers.len() + 1, 1)?;
+        let ab = self.mapper.forward(&t[0])?;
+        let ab = self
+            .condition_mappers
+            .iter()
+            .zip(&t[1..])
+            .try_fold(ab, |ab, (mapper, t)| ab + mapper.forward(t)?)?;
+        let ab = ab.unsqueeze(2)?.unsqueeze(3)?.chunk(2, 1)?;
+        xs.broadcast_mul(&(&ab[0] + 1.)?)?.broadcast_add(&ab[1])
+    }
+}
+
+#[derive(Debug)]
+enum Block {
+    Res(ResBlock),
+    Timestep(TimestepBlock),
+    Attn(AttnBlock),
+}
+
+/// The blocks of one level of the UNet.
+#[derive(Debug)]
+struct Level {
+    blocks: Vec<Block>,
+    repeat_mappers: Vec<Conv2d>,
+}
+
+impl Level {
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        config: &StableCascadeConfig,
+        level: usize,
+        layers: usize,
+        repeats: usize,
+        c_skip: usize,
+        use_flash_attn: bool,
+        vb: VarBuilder,
+        repeat_vb: VarBuilder,
+    ) -> Result<Self> {
+        let c = config.block_out_channels[level];
+        let nhead = config.num_attention_heads[level];
+        let mut blocks = Vec::new();
+        for layer in 0..layers {
+            let c_skip = if layer == 0 { c_skip } else { 0 };
+            blocks.push(Block::Res(ResBlock::new(
+                c,
+                c_skip,
+                vb.pp(blocks.len().to_string()),
+            )?));
+            blocks.push(Block::Timestep(TimestepBlock::new(
+                c,
+                config.timestep_ratio_embedding_dim,
+                config.timestep_conditions,
+                vb.pp(blocks.len().to_string()),
+            )?));
+            if nhead > 0 {
+                blocks.push(Block::Attn(AttnBlock::new(
+                    c,
+                    config.conditioning_dim,
+                    nhead,
+                    true,
+                    use_flash_attn,
+                    vb.pp(blocks.len().to_string()),
+                )?));
+            }
+        }
+        let repeat_mappers = (0..repeats.saturating_sub(1))
+            .map(|index| {
+                candle_nn::conv2d(c, c, 1, Default::default(), repeat_vb.pp(index.to_string()))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            blocks,
+            repeat_mappers,
+        })
+    }
+
+    fn forward(
+        &self,
+        mut xs: Tensor,
+        r_embed: &Tensor,
+        clip: &Tensor,
+        skip: Option<&Tensor>,
+    ) -> Result<Tensor> {
+        for repeat in 0..=self.repeat_mappers.len() {
+            for (index, block) in self.blocks.iter().enumerate() {
+                xs = match block {
+                    Block::Res(block) => {
+                        let skip = skip.filter(|_| index == 0);
+                        // The skip connection can be a different size if the image size isn't a multiple of the downscaling
+                        if let Some(skip) = skip {
+                            let (_, _, height, width) = skip.dims4()?;
+                            xs = resize_bilinear(&xs, height, width)?;
+                        }
+                        block.forward(&xs, skip)?
+                    }
+                    Block::Timestep(block) => block.forward(&xs, r_embed)?,
+                    Block::Attn(block) => block.forward(&xs, clip)?,
+                };
+            }
+            if let Some(mapper) = self.repeat_mappers.get(repeat) {
+                xs = xs.apply(mapper)?;
+            }
+        }
+        Ok(xs)
+    }
+}
+
+/// The convolution that moves between two levels of the UNet.
+#[derive(Debug)]
+enum LevelConv {
+    Conv(Conv2d),
+    Transpose(ConvTranspose2d),
+}
+
+#[derive(Debug)]
+struct LevelMapper {
+    norm: WLayerNorm,
+    conv: LevelConv,
+}
+
+impl LevelMapper {
+    fn new(c: usize, conv: LevelConv) -> Result<Self> {
+        Ok(Self {
+            norm: WLayerNorm::new(c)?,
+            conv,
+        })
+    }
+}
+
+impl Module for LevelMapper {
+    fn forward(&self, xs: &Tensor) -> Result<Tensor> {
+        let xs = xs.apply(&self.norm)?;
+        match &self.conv {
+            LevelConv::Conv(conv) => xs.apply(conv),
+            LevelConv::Transpose(conv) => xs.apply(conv),
+        }
+    }
+}
+
+/// The conditioning mappers for the effnet latents and pixels the decoder is conditioned on.
+#[derive(Debug)]
+struct ImageMapper {
+    conv1: Conv2d,
+    conv2: Conv2d,
+    norm: WLayerNorm,
+}
+
+impl ImageMapper {
+    fn new(c_in: usize, c: usize, vb: VarBuilder) -> Result<Self> {
+        let conv1 = candle_nn::conv2d(c_in, c * 4, 1, Default::default(), vb.pp("0"))?;
+        let conv2 = candle_nn::conv2d(c * 4, c, 1, Default::default(), vb.pp("2"))?;
+        Ok(Self {
+            conv1,
+            conv2,
+            norm: WLayerNorm::new(c)?,
+        })
+    }
+}
+
+impl Module for ImageMapper {
+    fn forward(&self, xs: &Tensor) -> Result<Tensor> {
+        xs.apply(&self.conv1)?
+            .gelu_erf()?
+            .apply(&self.conv2)?
+            .apply(&self.norm)
+    }
+}
+
+/// The UNet of the Stable Cascade prior (stage C) and decoder (stage B).
+#[derive(Debug)]
+pub(crate) struct StableCascadeUNet {
+    config: StableCascadeConfig,
+    effnet_mapper: Option<ImageMapper>,
+    pixels_mapper: Option<ImageMapper>,
+    clip_txt_pooled_mapper: Linear,
+    clip_txt_mapper: Option<Linear>,
+    clip_img_mapper: Option<Linear>,
+    clip_norm: LayerNormNoWeights,
+    embedding_conv: Conv2d,
+    embedding_ln: WLayerNorm,
+    down_levels: Vec<Level>,
+    down_mappers: Vec<LevelMapper>,
+    up_levels: Vec<Level>,
+    up_mappers: Vec<LevelMapper>,
+    clf_ln: WLayerNorm,
+    clf_conv: Conv2d,
+}
+
+impl StableCascadeUNet {
+    pub(crate) fn new(
+        config: StableCascadeConfig,
+        use_flash_attn: bool,
+        vb: VarBuilder,
+    ) -> Result<Self> {
+        let c = &config.block_out_channels;
+        let levels = c.len();
+
+        let effnet_mapper = config
+            .effnet_in_channels
+            .map(|c_in| ImageMapper::new(c_in, c[0], vb.pp("effnet_mapper")))
+            .transpose()?;
+        let pixels_mapper = config
+            .pixel_mapper_in_channels
+            .map(|c_in| ImageMapper::new(c_in, c[0], vb.pp("pixels_mapper")))
+            .transpose()?;
+        let clip_txt_pooled_mapper = candle_nn::linear(
+            config.clip_text_pooled_in_channels,
+            config.conditioning_dim * config.clip_seq,
+            vb.pp("clip_txt_pooled_mapper"),
+        )?;
+        let clip_txt_mapper = config
+            .clip_text_in_channels
+            .map(|c_in| candle_nn::linear(c_in, config.conditioning_dim, vb.pp("clip_txt_mapper")))
+            .transpose()?;
+        let clip_img_mapper = config
+            .clip_image_in_channels
+            .map(|c_in| {
+                candle_nn::linear(
+                    c_in,
+                    config.conditioning_dim * config.clip_seq,
+                    vb.pp("clip_img_mapper"),
+                )
+            })
+            .transpose()?;
+        let clip_norm = LayerNormNoWeights::new(config.conditioning_dim)?;
+
+        let embedding_conv = candle_nn::conv2d(
+            config.in_channels * config.patch_size.pow(2),
+            c[0],
+            1,
+            Default::default(),
+            vb.pp("embedding.1"),
+        )?;
+        let embedding_ln = WLayerNorm::new(c[0])?;
+
+        let mut down_levels = Vec::with_capacity(levels);
+        let mut down_mappers = Vec::with_capacity(levels - 1);
+        for level in 0..levels {
+            if level > 0 {
+                let vb = vb.pp(format!("down_downscalers.{level}.1"));
+                let conv = if config.switch_level {
+                    let conv_vb = vb.pp("blocks.0");
+                    LevelConv::Conv(candle_nn::conv2d(
+                        c[level - 1],
+                        c[level],
+                        1,
+                        Default::default(),
+                        conv_vb,
+                    )?)
+                } else {
+                    let cfg = candle_nn::Conv2dConfig {
+                        stride: 2,
+                        ..Default::default()
+                    };
+                    LevelConv::Conv(candle_nn::conv2d(c[level - 1], c[level], 2, cfg, vb)?)
+                };
+                down_mappers.push(LevelMapper::new(c[level - 1], conv)?);
+            }
+            down_levels.push(Level::new(
+                &config,
+                level,
+                config.down_num_layers_per_block[level],
+                config.down_blocks_repeat_mappers[level],
+                0,
+                use_flash_attn,
+                vb.pp(format!("down_blocks.{level}")),
+                vb.pp(format!("down_repeat_mappers.{level}")),
+            )?);
+        }
+
+        // The up levels are stored from the lowest resolution level to the highest
+        let mut up_levels = Vec::with_capacity(levels);
+        let mut up_mappers = Vec::with_capacity(levels - 1);
+        for (index, level) in (0..levels).rev().enumerate() {
+            let c_skip = if level < levels - 1 { c[level] } else { 0 };
+            up_levels.push(Level::new(
+                &config,
+                level,
+                config.up_num_layers_per_block[level],
+                config.up_blocks_repeat_mappers[level],
+                c_skip,
+                use_flash_attn,
+                vb.pp(format!("up_blocks.{index}")),
+                vb.pp(format!("up_repeat_mappers.{index}")),
+            )?);
+            if level > 0 {
+                let vb = vb.pp(format!("up_upscalers.{index}.1"));
+                let conv = if config.switch_level {
+                    let conv_vb = vb.pp("blocks.1");
+                    LevelConv::Conv(candle_nn::conv2d(
+                        c[level],
+                        c[level - 1],
+                        1,
+                        Default::default(),
+                        conv_vb,
+                    )?)
+                } else {
+                    let cfg = candle_nn::ConvTranspose2dConfig {
+                        stride: 2,
+                        ..Default::default()
+                    };
+                    LevelConv::Transpose(candle_nn::conv_transpose2d(
+                        c[level],
+                        c[level - 1],
+                        2,
+                        cfg,
+                        vb,
+                    )?)
+                };
+                up_mappers.push(LevelMapper::new(c[level], conv)?);
+            }
+        }
+
+        let clf_ln = WLayerNorm::new(c[0])?;
+        let clf_conv = candle_nn::conv2d(
+            c[0],
+            config.out_channels * config.patch_size.pow(2),
+            1,
+            Default::default(),
+            vb.pp("clf.1"),
+        )?;
+
+        Ok(Self {
+            config,
+            effnet_mapper,
+            pixels_mapper,
+            clip_txt_pooled_mapper,
+            clip_txt_mapper,
+            clip_img_mapper,
+            clip_norm,
+            embedding_conv,
+            embedding_ln,
+            down_levels,
+            down_mappers,
+            up_levels,
+            up_mappers,
+            clf_ln,
+            clf_conv,
+        })
+    }
+
+    fn gen_r_embedding(&self, r: &Tensor) -> Result<Tensor> {
+        const MAX_POSITIONS: usize = 10000;
+        let dim = self.config.timestep_ratio_embedding_dim;
+        let r = (r * MAX_POSITIONS as f64)?;
+        let half_dim = dim / 2;
+        let emb = (MAX_POSITIONS as f64).ln() / (half_dim - 1) as f64;
+        let emb = (Tensor::arange(0u32, half_dim as u32, r.device())?.to_dtype(DType::F32)?
+            * -emb)?
+            .exp()?;
+        let emb = r
+            .to_dtype(DType::F32)?
+            .unsqueeze(1)?
+            .broadcast_mul(&emb.unsqueeze(0)?)?;
+        let emb = Tensor::cat(&[emb.sin()?, emb.cos()?], 1)?;
+        let emb = if dim % 2 == 1 {
+            emb.pad_with_zeros(D::Minus1, 0, 1)?
+        } else {
+            emb
+        };
+        emb.to_dtype(r.dtype())
+    }
+
+    fn clip_embeddings(
+        &self,
+        clip_text_pooled: &Tensor,
+        clip_text: Option<&Tensor>,
+        clip_img: Option<&Tensor>,
+    ) -> Result<Tensor> {
+        let (b_size, seq_len, _) = clip_text_pooled.dims3()?;
+        let clip_text_pooled = clip_text_pooled
+            .apply(&self.clip_txt_pooled_mapper)?
+            .reshape((b_size, seq_len * self.config.clip_seq, ()))?;
+        let clip = match (
+            clip_text,
+            clip_img,
+            &self.clip_txt_mapper,
+            &self.clip_img_mapper,
+        ) {
+            (Some(clip_text), Some(clip_img), Some(text_mapper), Some(img_mapper)) => {
+                let clip_text = clip_text.apply(text_mapper)?;
+                let (b_size, seq_len, _) = clip_img.dims3()?;
+                let clip_img = clip_img.apply(img_mapper)?.reshape((
+                    b_size,
+                    seq_len * self.config.clip_seq,
+                    (),
+                ))?;
+                Tensor::cat(&[clip_text, clip_text_pooled, clip_img], 1)?
+            }
+            _ => clip_text_pooled,
+        };
+        clip.apply(&self.clip_norm)
+    }
+
+    /// Predict the noise in the latents.
+    ///
+    /// - `clip_text_pooled` has the shape `[batch, 1, clip_text_pooled_in_channels]`
+    /// - `clip_text` has the shape `[batch, seq_len, clip_text_in_channels]` and is only used by the prior
+    /// - `clip_img` has the shape `[batch, 1, clip_image_in_channels]` and is only used by the prior
+    /// - `effnet` is the output of the prior and is only used by the decoder
+    pub(crate) fn forward(
+        &self,
+        xs: &Tensor,
+        r: &Tensor,
+        clip_text_pooled: &Tensor,
+        clip_text: Option<&Tensor>,
+        clip_img: Option<&Tensor>,
+        effnet: Option<&Tensor>,
+    ) -> Result<Tensor> {
+        let b_size = xs.dim(0)?;
+
+        // The extra timestep conditions are always zero for text to image generation
+        let r_embed = self.gen_r_embedding(r)?;
+        let zero_embed = self.gen_r_embedding(&r.zeros_like()?)?;
+        let mut r_embeds = vec![r_embed];
+        r_embeds.extend((0..self.config.timestep_conditions).map(|_| zero_embed.clone()));
+        let r_embed = Tensor::cat(&r_embeds, 1)?;
+
+        let clip = self.clip_embeddings(clip_text_pooled, clip_text, clip_img)?;
+
+        let mut xs = candle_nn::ops::pixel_unshuffle(xs, self.config.patch_size)?
+            .apply(&self.embedding_conv)?
+            .apply(&self.embedding_ln)?;
+        let (_, _, height, width) = xs.dims4()?;
+        if let (Some(mapper), Some(effnet)) = (&self.effnet_mapper, effnet) {
+            xs = (xs + resize_bilinear(effnet, height, width)?.apply(mapper)?)?;
+        }
+        if let Some(mapper) = &self.pixels_mapper {
+            let pixels = Tensor::zeros((b_size, 3, 8, 8), xs.dtype(), xs.device())?;
+            xs = (xs + resize_bilinear(&pixels.apply(mapper)?, height, width)?)?;
+        }
+
+        let mut level_outputs = Vec::with_capacity(self.down_levels.len());
+        for (index, level) in self.down_levels.iter().enumerate() {
+            if index > 0 {
+                xs = xs.apply(&self.down_mappers[index - 1])?;
+            }
+            xs = level.forward(xs, &r_embed, &clip, None)?;
+            level_outputs.push(xs.clone());
+        }
+
+        for (index, level) in self.up_levels.iter().enumerate() {
+            let skip = match index {
+                0 => None,
+                _ => Some(&level_outputs[level_outputs.len() - 1 - index]),
+            };
+            xs = level.forward(xs, &r_embed, &clip, skip)?;
+            if let Some(mapper) = self.up_mappers.get(index) {
+                xs = xs.apply(mapper)?;
+            }
+        }
+
+        let xs = xs.apply(&self.clf_ln)?.apply(&self.clf_conv)?;
+        candle_nn::ops::pixel_shuffle(&xs, self.config.patch_size)
+    }
+}
+
+/// Resize a batch of images with bilinear interpolation. The corners of the input and output are aligned like `align_corners=True` in pytorch.
+fn resize_bilinear(xs: &Tensor, height: usize, width: usize) -> Result<Tensor> {
+    let (_, _, in_height, in_width) = xs.dims4()?;
+    if in_height == height && in_width == width {
+        return Ok(xs.clone());
+    }
+
+    // Each output pixel is a weighted sum of the two closest input pixels, so the resize is a matrix multiplication along each axis
+    fn weights(input: usize, output: usize) -> Vec<f32> {
+        let mut weights = vec![0.; output * input];
+        for out in 0..output {
+            let position = if output > 1 {
+                out as f64 * (input - 1) as f64 / (output - 1) as f64
+            } else {
+                0.
+            };
+            let low = (position.floor() as usize).min(input - 1);
+            let high = (low + 1).min(input - 1);
+            let fraction = (position - low as f64) as f32;
+            weights[out * input + low] += 1. - fraction;
+            weights[out * input + high] += fraction;
+        }
+        weights
+    }
+
+    let dtype = xs.dtype();
+    let device = xs.device();
+    let height_weights = Tensor::from_vec(weights(in_height, height), (height, in_height), device)?;
+    let width_weights = Tensor::from_vec(weights(in_width, width), (width, in_width), device)?;
+    let xs = xs
+        .to_dtype(DType::F32)?
+        .broadcast_matmul(&width_weights.t()?)?;
+    height_weights.broadcast_matmul(&xs)?.to_dtype(dtype)
+}
@@ -27,37 +27,112 @@
 
 #![warn(missing_docs)]
 
-use std::{sync::OnceLock, time::Duration};
-
-use futures_channel::mpsc::{UnboundedReceiver, UnboundedSender};
-use futures_util::{Stream, StreamExt};
-use image::ImageBuffer;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+use std::{future::Future, sync::OnceLock, time::Duration};
+
+pub use candle_core::{DType, Tensor};
+use futures_channel::mpsc::{Receiver, SendError, Sender, UnboundedReceiver, UnboundedSender};
+use futures_channel::oneshot;
+use futures_util::future::Shared;
+use futures_util::{FutureExt, Stream, StreamExt};
+use image::{ImageBuffer, RgbImage};
+use kalosm_common::metrics::{LoadMetrics, Timer};
 use kalosm_common::{Cache, CacheError};
 use kalosm_language_model::ModelBuilder;
-use kalosm_model_types::FileSource;
-pub use kalosm_model_types::ModelLoadingProgress;
+pub use kalosm_model_types::{FileSource, ModelLoadingProgress};
+use tracing::Instrument;
 
+use lora::LoraAdapter;
 use model::{WuerstcheModelSettings, WuerstchenInner};
+pub use safety::SafetyCheckerAction;
+pub use scheduler::Scheduler;
 
+mod cascade;
+mod lora;
 mod model;
+mod offload;
+mod prompt;
+mod quantized;
+mod safety;
+mod scheduler;
+mod upscaler;
 
 static ZERO_IMAGE: OnceLock<ImageBuffer<image::Rgb<u8>, Vec<u8>>> = OnceLock::new();
 
 #[derive(Debug, Clone)]
 struct DiffusionResult {
     image: ImageBuffer<image::Rgb<u8>, Vec<u8>>,
+    latents: Option<Tensor>,
     height: usize,
     width: usize,
+    /// If the safety checker flagged the image
+    nsfw: bool,
+}
+
+/// A stage of the image generation pipeline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DiffusionStage {
+    /// The prior (stage C) is generating the image embeddings from the prompt. The prior runs once for all samples.
+    Prior,
+    /// The denoiser (stage B) is generating the latents of a sample from the image embeddings.
+    Denoiser,
+    /// The VQGAN (stage A) is decoding the latents of a sample into images.
+    Decode,
+}
+
+/// The progress of an inference run.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DiffusionProgress {
+    stage: DiffusionStage,
+    step: usize,
+    stage_steps: usize,
+    elapsed_time: Duration,
+    remaining_time: Duration,
+    progress: f32,
+}
+
+impl DiffusionProgress {
+    /// Get the stage of the pipeline that is running
+    pub fn stage(&self) -> DiffusionStage {
+        self.stage
+    }
+
+    /// Get the number of finished steps in the current stage
+    pub fn step(&self) -> usize {
+        self.step
+    }
+
+    /// Get the total number of steps in the current stage
+    pub fn stage_steps(&self) -> usize {
+        self.stage_steps
+    }
+
+    /// Get the time since the inference started
+    pub fn elapsed_time(&self) -> Duration {
+        self.elapsed_time
+    }
+
+    /// Get the estimated time remaining to generate every sample. The estimate is based on a moving average of the step time of each stage.
+    pub fn remaining_time(&self) -> Duration {
+        self.remaining_time
+    }
+
+    /// The progress of the inference, from 0 to 1. The progress is weighted by the estimated time of each stage, and never moves backwards.
+    pub fn progress(&self) -> f32 {
+        self.progress
+    }
 }
 
 /// An image generated by the model
 #[derive(Debug)]
 pub struct Image {
     sample_num: i64,
-    elapsed_time: Duration,
-    remaining_time: Duration,
-    progress: f32,
-    result: candle_core::Result<DiffusionResult>,
+    prompt_index: usize,
+    progress: DiffusionProgress,
+    preview: bool,
+    result: Result<DiffusionResult, WuerstchenError>,
 }
 
 impl Image {
@@ -66,21 +141,56 @@ impl Image {
         self.sample_num
     }
 
+    /// Get the index of the prompt the image was generated from. Settings created with [`WuerstchenInferenceSettings::new`] have one prompt, so the index is always 0.
+    pub fn prompt_index(&self) -> usize {
+        self.prompt_index
+    }
+
     /// Get the elapsed time
     pub fn elapsed_time(&self) -> Duration {
-        self.elapsed_time
+        self.progress.elapsed_time
     }
 
-    /// Get the estimated time remaining to process the entire audio file
+    /// Get the estimated time remaining to generate every sample
     pub fn remaining_time(&self) -> Duration {
-        self.remaining_time
+        self.progress.remaining_time
     }
 
-    /// The progress of the transcription, from 0 to 1
+    /// The progress of the inference, from 0 to 1
     pub fn progress(&self) -> f32 {
+        self.progress.progress
+    }
+
+    /// Get the stage of the pipeline the image was sent from. Previews are sent from [`DiffusionStage::Denoiser`] and final images from [`DiffusionStage::Decode`].
+    pub fn stage(&self) -> DiffusionStage {
+        self.progress.stage
+    }
+
+    /// Get the number of finished steps in the stage the image was sent from
+    pub fn step(&self) -> usize {
+        self.progress.step
+    }
+
+    /// Get the total number of steps in the stage the image was sent from
+    pub fn stage_steps(&self) -> usize {
+        self.progress.stage_steps
+    }
+
+    /// Get the full progress of the inference when the image was sent
+    pub fn diffusion_progress(&self) -> DiffusionProgress {
         self.progress
     }
 
+    /// Check if the image is a preview of a sample that is still being denoised. Previews are only sent if [`WuerstchenInferenceSettings::with_preview_every_n_steps`] is set.
+    pub fn is_preview(&self) -> bool {
+        self.preview
+    }
+
+    /// Check if the safety checker flagged the image as NSFW. This is always false if the safety checker isn't enabled with [`WuerstchenBuilder::with_safety_checker`].
+    pub fn is_nsfw(&self) -> bool {
+        self.result.as_ref().is_ok_and(|val| val.nsfw)
+    }
+
     /// Get the height in px of the generated image
     pub fn height(&self) -> Option<usize> {
         self.result.as_ref().ok().map(|val| val.height)
@@ -96,12 +206,95 @@ impl Image {
         self.result.as_ref().ok().map(|val| val.image.clone())
     }
 
+    /// Get the latents the image was decoded from if [`WuerstchenInferenceSettings::with_return_latents`] is set. The latents are on the cpu with the shape `[1, 4, height / 4, width / 4]`.
+    ///
+    /// The latents are much smaller than the image. They can be stored and decoded again later, or combined with the latents of other images, with [`Wuerstchen::decode_latents`].
+    pub fn latents(&self) -> Option<&Tensor> {
+        self.result
+            .as_ref()
+            .ok()
+            .and_then(|val| val.latents.as_ref())
+    }
+
     /// Get the error message if no image has been generated
-    pub fn error(&self) -> Option<&candle_core::Error> {
+    pub fn error(&self) -> Option<&WuerstchenError> {
         self.result.as_ref().err()
     }
 }
 
+/// A finished image returned by [`Wuerstchen::run_sync`]
+#[derive(Debug, Clone)]
+pub struct GeneratedImage {
+    sample_num: i64,
+    prompt_index: usize,
+    image: RgbImage,
+    latents: Option<Tensor>,
+    nsfw: bool,
+}
+
+impl GeneratedImage {
+    /// Get the sample number
+    pub fn sample_num(&self) -> i64 {
+        self.sample_num
+    }
+
+    /// Get the index of the prompt the image was generated from
+    pub fn prompt_index(&self) -> usize {
+        self.prompt_index
+    }
+
+    /// Check if the safety checker flagged the image as NSFW. See [`Image::is_nsfw`].
+    pub fn is_nsfw(&self) -> bool {
+        self.nsfw
+    }
+
+    /// Get the height in px of the generated image
+    pub fn height(&self) -> usize {
+        self.image.height() as usize
+    }
+
+    /// Get the width in px of the generated image
+    pub fn width(&self) -> usize {
+        self.image.width() as usize
+    }
+
+    /// Get the generated image
+    pub fn image(&self) -> &RgbImage {
+        &self.image
+    }
+
+    /// Take the generated image
+    pub fn into_image(self) -> RgbImage {
+        self.image
+    }
+
+    /// Get the latents the image was decoded from if [`WuerstchenInferenceSettings::with_return_latents`] is set. See [`Image::latents`].
+    pub fn latents(&self) -> Option<&Tensor> {
+        self.latents.as_ref()
+    }
+}
+
+impl TryFrom<Image> for GeneratedImage {
+    type Error = WuerstchenError;
+
+    fn try_from(image: Image) -> Result<Self, Self::Error> {
+        let result = image.result?;
+        Ok(Self {
+            sample_num: image.sample_num,
+            prompt_index: image.prompt_index,
+            image: result.image,
+            latents: result.latents,
+            nsfw: result.nsfw,
+        })
+    }
+}
+
+impl AsRef<RgbImage> for GeneratedImage {
+    fn as_ref(&self) -> &RgbImage {
+        &self.image
+    }
+}
+
 impl AsRef<ImageBuffer<image::Rgb<u8>, Vec<u8>>> for Image {
     fn as_ref(&self) -> &ImageBuffer<image::Rgb<u8>, Vec<u8>> {
         match &self.result {
@@ -111,36 +304,106 @@ impl AsRef<ImageBuffer<image::Rgb<u8>, Vec<u8>>> for Image {
     }
 }
 
+/// An error that can occur when loading or running a [`Wuerstchen`] model.
+#[derive(Debug, thiserror::Error)]
+pub enum WuerstchenError {
+    /// An error that can occur when downloading the model files from huggingface or loading a local file.
+    #[error("Failed to load model from huggingface or local file: {0}")]
+    Download(#[from] CacheError),
+    /// An error that can occur when loading the model weights into the device.
+    #[error("Failed to load model into device: {0}")]
+    Load(candle_core::Error),
+    /// An error that can occur when loading a tokenizer or tokenizing a prompt.
+    #[error("Tokenizer error: {0}")]
+    Tokenizer(tokenizers::Error),
+    /// The height or width of the image is not a multiple of 128.
+    #[error("Image resolution must be a multiple of 128, but found {width}x{height}")]
+    InvalidDimensions {
+        /// The requested width of the image.
+        width: usize,
+        /// The requested height of the image.
+        height: usize,
+    },
+    /// An error that can occur when creating the device the model runs on.
+    #[error("Failed to create device: {0}")]
+    Device(candle_core::Error),
+    /// An error that can occur while generating an image.
+    #[error("Failed to generate image: {0}")]
+    Inference(#[from] candle_core::Error),
+}
+
+/// The version of the Wuerstchen architecture to load.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WuerstchenVersion {
+    /// Würstchen v2 from [warp-ai/wuerstchen](https://huggingface.co/warp-ai/wuerstchen).
+    #[default]
+    V2,
+    /// Stable Cascade from [stabilityai/stable-cascade](https://huggingface.co/stabilityai/stable-cascade). Stable Cascade is the successor of Würstchen v2 and generates more detailed images.
+    ///
+    /// The lite prior and decoder are downloaded by default. The full prior and decoder are detected automatically when their weights are passed to [`WuerstchenBuilder::with_prior_weights`] and [`WuerstchenBuilder::with_decoder_weights`]. Stable Cascade works best with 20 prior steps and 10 denoiser steps.
+    StableCascade,
+}
+
 /// A builder for the Wuerstchen model.
 pub struct WuerstchenBuilder {
     use_flash_attn: bool,
 
+    /// The version of the model to load.
+    version: WuerstchenVersion,
+
+    /// The data type the weights are loaded in and the inference runs in.
+    dtype: DType,
+
+    /// Whether to keep only the stage that is running on the device.
+    cpu_offload: bool,
+
     /// The decoder weight file, in .safetensors format.
-    decoder_weights: Option<String>,
+    decoder_weights: Option<FileSource>,
 
     /// The CLIP weight file, in .safetensors format.
-    clip_weights: Option<String>,
+    clip_weights: Option<FileSource>,
 
     /// The CLIP weight file used by the prior model, in .safetensors format.
-    prior_clip_weights: Option<String>,
+    prior_clip_weights: Option<FileSource>,
 
     /// The prior weight file, in .safetensors format.
-    prior_weights: Option<String>,
+    prior_weights: Option<FileSource>,
 
     /// The VQGAN weight file, in .safetensors format.
-    vqgan_weights: Option<String>,
+    vqgan_weights: Option<FileSource>,
 
     /// The file specifying the tokenizer to used for tokenization.
-    tokenizer: Option<String>,
+    tokenizer: Option<FileSource>,
 
     /// The file specifying the tokenizer to used for prior tokenization.
-    prior_tokenizer: Option<String>,
+    prior_tokenizer: Option<FileSource>,
+
+    /// The LoRA adapter files to merge into the prior and decoder weights, in .safetensors format, with the scale of each adapter.
+    loras: Vec<(FileSource, f64)>,
+
+    /// What the safety checker does with flagged images, if the safety checker is enabled.
+    safety_checker: Option<SafetyCheckerAction>,
+
+    /// The safety checker weight file, in .safetensors format.
+    safety_checker_weights: Option<FileSource>,
+
+    /// The factor the final images are upscaled by.
+    upscale: usize,
+
+    /// The Real-ESRGAN weight file, in .safetensors or .pth format.
+    upscaler_weights: Option<FileSource>,
+
+    /// The cache location to use for the model (defaults DATA_DIR/kalosm/cache)
+    cache: Cache,
 }
 
 impl Default for WuerstchenBuilder {
     fn default() -> Self {
         Self {
             use_flash_attn: { cfg!(feature = "flash") },
+            version: WuerstchenVersion::V2,
+            dtype: DType::F32,
+            cpu_offload: false,
             decoder_weights: None,
             clip_weights: None,
             prior_clip_weights: None,
@@ -148,6 +411,12 @@ impl Default for WuerstchenBuilder {
             vqgan_weights: None,
             tokenizer: None,
             prior_tokenizer: None,
+            loras: Vec::new(),
+            safety_checker: None,
+            safety_checker_weights: None,
+            upscale: 1,
+            upscaler_weights: None,
+            cache: Cache::default(),
         }
     }
 }
@@ -159,61 +428,214 @@ impl WuerstchenBuilder {
         self
     }
 
-    /// Set the decoder weight file, in .safetensors format.
-    pub fn with_decoder_weights(mut self, decoder_weights: impl Into<String>) -> Self {
-        self.decoder_weights = Some(decoder_weights.into());
+    /// Set the version of the model to load. (Defaults to [`WuerstchenVersion::V2`])
+    ///
+    /// The default weights of the version are downloaded for any weights that are not set.
+    pub fn with_version(mut self, version: WuerstchenVersion) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// Set the data type the prior, decoder, VQGAN and CLIP weights are loaded in and the inference runs in. (Defaults to [`DType::F32`])
+    ///
+    /// [`DType::F16`] or [`DType::BF16`] use about half the memory of [`DType::F32`], which helps fit the model on GPUs with limited VRAM. Half precision is much slower than [`DType::F32`] on most CPUs.
+    pub fn with_dtype(mut self, dtype: DType) -> Self {
+        self.dtype = dtype;
+        self
+    }
+
+    /// Set whether to keep only the stage of the pipeline that is currently running on the GPU. (Defaults to false)
+    ///
+    /// With offloading, the weights of the prior, the decoder and the VQGAN are kept in host memory and each stage is moved to the GPU only while it runs. This lowers the VRAM the model needs to about the size of the largest stage at the cost of copying the weights of each stage to the GPU for every generation. The VQGAN is small, so it is moved to the GPU next to the decoder to decode previews and images. The CLIP text encoders always stay on the GPU.
+    pub fn with_cpu_offload(mut self, cpu_offload: bool) -> Self {
+        self.cpu_offload = cpu_offload;
+        self
+    }
+
+    /// Set the decoder weight file, in .safetensors or quantized .gguf format.
+    ///
+    /// Like the other files of the model, the weights can be a local file or a file from any Hugging Face repository or revision, so fine-tuned checkpoints are downloaded into the [`Cache`] with the default weights.
+    ///
+    /// ```rust, no_run
+    /// use rwuerstchen::*;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), anyhow::Error> {
+    /// let model = Wuerstchen::builder()
+    ///     .with_decoder_weights(FileSource::huggingface(
+    ///         "warp-ai/wuerstchen",
+    ///         "main",
+    ///         "decoder/diffusion_pytorch_model.safetensors",
+    ///     ))
+    ///     .with_prior_weights(FileSource::local("prior.safetensors".into()))
+    ///     .build()
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// Quantized weights use much less memory, which makes it possible to run the model on a CPU with limited RAM. Weights can be quantized with the `quantize` command of candle's `tensor-tools` example, for example with `--quantization q8_0` for 8 bit weights. Quantized weights are only supported for [`WuerstchenVersion::V2`] and can't be combined with [`WuerstchenBuilder::with_lora`].
+    pub fn with_decoder_weights(mut self, decoder_weights: FileSource) -> Self {
+        self.decoder_weights = Some(decoder_weights);
         self
     }
 
     /// Set the CLIP weight file, in .safetensors format.
-    pub fn with_clip_weights(mut self, clip_weights: impl Into<String>) -> Self {
-        self.clip_weights = Some(clip_weights.into());
+    ///
+    /// Only Würstchen v2 uses a separate CLIP for the decoder. Stable Cascade conditions the decoder on the prior CLIP.
+    pub fn with_clip_weights(mut self, clip_weights: FileSource) -> Self {
+        self.clip_weights = Some(clip_weights);
         self
     }
 
     /// Set the CLIP weight file used by the prior model, in .safetensors format.
-    pub fn with_prior_clip_weights(mut self, prior_clip_weights: impl Into<String>) -> Self {
-        self.prior_clip_weights = Some(prior_clip_weights.into());
+    pub fn with_prior_clip_weights(mut self, prior_clip_weights: FileSource) -> Self {
+        self.prior_clip_weights = Some(prior_clip_weights);
         self
     }
 
-    /// Set the prior weight file, in .safetensors format.
-    pub fn with_prior_weights(mut self, prior_weights: impl Into<String>) -> Self {
-        self.prior_weights = Some(prior_weights.into());
+    /// Set the prior weight file, in .safetensors or quantized .gguf format.
+    ///
+    /// See [`WuerstchenBuilder::with_decoder_weights`] for how to quantize the weights.
+    pub fn with_prior_weights(mut self, prior_weights: FileSource) -> Self {
+        self.prior_weights = Some(prior_weights);
         self
     }
 
     /// Set the Vector Quantized Generative Adversarial Network weight file, in .safetensors format.
-    pub fn with_vqgan_weights(mut self, vqgan_weights: impl Into<String>) -> Self {
-        self.vqgan_weights = Some(vqgan_weights.into());
+    pub fn with_vqgan_weights(mut self, vqgan_weights: FileSource) -> Self {
+        self.vqgan_weights = Some(vqgan_weights);
         self
     }
 
     /// Set the file specifying the tokenizer to used for tokenization.
-    pub fn with_tokenizer(mut self, tokenizer: impl Into<String>) -> Self {
-        self.tokenizer = Some(tokenizer.into());
+    ///
+    /// Only Würstchen v2 uses a separate tokenizer for the decoder. Stable Cascade uses the prior tokenizer.
+    pub fn with_tokenizer(mut self, tokenizer: FileSource) -> Self {
+        self.tokenizer = Some(tokenizer);
         self
     }
 
     /// Set the file specifying the tokenizer to used for prior tokenization.
-    pub fn with_prior_tokenizer(mut self, prior_tokenizer: impl Into<String>) -> Self {
-        self.prior_tokenizer = Some(prior_tokenizer.into());
+    pub fn with_prior_tokenizer(mut self, prior_tokenizer: FileSource) -> Self {
+        self.prior_tokenizer = Some(prior_tokenizer);
+        self
+    }
+
+    /// Add a LoRA adapter, in .safetensors format, that is merged into the prior and decoder weights when the model is loaded. The scale controls how strongly the adapter changes the model. 1.0 applies the adapter as it was trained.
+    ///
+    /// Adapters in the PEFT, diffusers and kohya formats are supported. Layers of the adapter that don't match a layer in the prior or decoder are ignored with a warning. Multiple adapters can be added and are merged in order.
+    pub fn with_lora(mut self, lora: FileSource, scale: f64) -> Self {
+        self.loras.push((lora, scale));
+        self
+    }
+
+    /// Run a safety checker on every image the model decodes, including previews, and flag the images that show NSFW content. The action controls what happens to the flagged images. (Defaults to no safety checker)
+    ///
+    /// The safety checker is the CLIP based classifier from Stable Diffusion. Flagged images can be found with [`Image::is_nsfw`]. The classifier misses some images and flags some safe images, so it should be one part of the moderation of an application, not all of it.
+    pub fn with_safety_checker(mut self, action: SafetyCheckerAction) -> Self {
+        self.safety_checker = Some(action);
+        self
+    }
+
+    /// Set the safety checker weight file, in .safetensors format. The weights are only loaded if the safety checker is enabled with [`WuerstchenBuilder::with_safety_checker`].
+    pub fn with_safety_checker_weights(mut self, safety_checker_weights: FileSource) -> Self {
+        self.safety_checker_weights = Some(safety_checker_weights);
+        self
+    }
+
+    /// Upscale the final images by a factor of 2 or 4 with Real-ESRGAN, so a 1024x1024 image is returned as a 2048x2048 image with `with_upscale(2)`. (Defaults to 1, which doesn't upscale the images)
+    ///
+    /// The images are upscaled on the device right after they are decoded, before they are copied to the host. Previews are not upscaled. Upscaling runs in tiles to bound the memory it needs, but it still takes a few seconds for each image.
+    pub fn with_upscale(mut self, upscale: usize) -> Self {
+        self.upscale = upscale;
+        self
+    }
+
+    /// Set the Real-ESRGAN weight file, in .safetensors or PyTorch .pth format. The weights must be for the factor set with [`WuerstchenBuilder::with_upscale`]. (Defaults to the official `RealESRGAN_x2plus` or `RealESRGAN_x4plus` weights)
+    pub fn with_upscaler_weights(mut self, upscaler_weights: FileSource) -> Self {
+        self.upscaler_weights = Some(upscaler_weights);
+        self
+    }
+
+    /// Set the cache location to use for the model (defaults DATA_DIR/kalosm/cache)
+    pub fn with_cache(mut self, cache: Cache) -> Self {
+        self.cache = cache;
         self
     }
 
     /// Build the model.
-    pub async fn build(self) -> Result<Wuerstchen, CacheError> {
+    pub async fn build(self) -> Result<Wuerstchen, WuerstchenError> {
         self.build_with_loading_handler(ModelLoadingProgress::multi_bar_loading_indicator())
             .await
     }
 
+    /// Get every file the builder loads with the current settings
+    fn required_files(&self) -> Vec<FileSource> {
+        let version = self.version;
+        let mut files = vec![
+            ModelFile::PriorTokenizer.get(self.prior_tokenizer.clone(), version),
+            ModelFile::PriorClip.get(self.prior_clip_weights.clone(), version),
+            ModelFile::Decoder.get(self.decoder_weights.clone(), version),
+            ModelFile::Prior.get(self.prior_weights.clone(), version),
+            ModelFile::VqGan.get(self.vqgan_weights.clone(), version),
+        ];
+        // Stable Cascade doesn't use a separate CLIP or tokenizer for the decoder
+        if version == WuerstchenVersion::V2 {
+            files.push(ModelFile::Tokenizer.get(self.tokenizer.clone(), version));
+            files.push(ModelFile::Clip.get(self.clip_weights.clone(), version));
+        }
+        files.extend(self.loras.iter().map(|(lora, _)| lora.clone()));
+        if self.safety_checker.is_some() {
+            files.push(ModelFile::SafetyChecker.get(self.safety_checker_weights.clone(), version));
+        }
+        if self.upscale != 1 {
+            files.push(
+                self.upscaler_weights
+                    .clone()
+                    .unwrap_or_else(|| upscaler::default_weights(self.upscale)),
+            );
+        }
+        files
+    }
+
     /// Build the model with a handler for progress as the download and loading progresses.
     pub async fn build_with_loading_handler(
         self,
-        mut progress_handler: impl FnMut(ModelLoadingProgress) + Send + Sync + 'static,
-    ) -> Result<Wuerstchen, CacheError> {
+        progress_handler: impl FnMut(ModelLoadingProgress) + Send + Sync + 'static,
+    ) -> Result<Wuerstchen, WuerstchenError> {
+        let metrics = LoadMetrics::start("wuerstchen");
+        let model = self
+            .load(progress_handler)
+            .instrument(metrics.span())
+            .await?;
+        metrics.finish();
+        Ok(model)
+    }
+
+    async fn load(
+        self,
+        progress_handler: impl FnMut(ModelLoadingProgress) + Send + Sync + 'static,
+    ) -> Result<Wuerstchen, WuerstchenError> {
+        if !upscaler::is_supported(self.upscale) {
+            return Err(WuerstchenError::Load(candle_core::Error::Msg(format!(
+                "Real-ESRGAN can only upscale images by a factor of 2 or 4, but found {}",
+                self.upscale
+            ))));
+        }
+
+        // Fail before downloading anything if the cache is offline and any of the files are missing
+        self.cache.ensure_available(&self.required_files())?;
+
+        // Every file is reported as a single download with a total size that is known before any of the files start
+        let size = self.cache.download_size(&self.required_files()).await;
+        let mut progress_handler =
+            ModelLoadingProgress::aggregate_with_size("Wuerstchen", size, progress_handler);
+
         let WuerstchenBuilder {
             use_flash_attn,
+            version,
+            dtype,
+            cpu_offload,
             decoder_weights,
             clip_weights,
             prior_clip_weights,
@@ -221,11 +643,16 @@ impl WuerstchenBuilder {
             vqgan_weights,
             tokenizer,
             prior_tokenizer,
+            loras,
+            safety_checker,
+            safety_checker_weights,
+            upscale,
+            upscaler_weights,
+            cache,
         } = self;
 
         // Download section
-        let cache = Cache::default();
-        let prior_tokenizer_source = ModelFile::PriorTokenizer.get(prior_tokenizer);
+        let prior_tokenizer_source = ModelFile::PriorTokenizer.get(prior_tokenizer, version);
         let prior_tokenizer_source_display =
             format!("Prior Tokenizer ({})", prior_tokenizer_source);
         let mut create_progress =
@@ -236,27 +663,34 @@ impl WuerstchenBuilder {
             })
             .await?;
 
-        let tokenizer_source = ModelFile::Tokenizer.get(tokenizer);
-        let tokenizer_source_display = format!("Tokenizer ({})", tokenizer_source);
-        let mut create_progress =
-            ModelLoadingProgress::downloading_progress(tokenizer_source_display);
-        let tokenizer = cache
-            .get(&tokenizer_source, |progress| {
-                progress_handler(create_progress(progress))
-            })
-            .await?;
-
-        let clip_weights_source = ModelFile::Clip.get(clip_weights);
-        let clip_weights_source_display = format!("Clip Weights ({})", clip_weights_source);
-        let mut create_progress =
-            ModelLoadingProgress::downloading_progress(clip_weights_source_display);
-        let clip_weights = cache
-            .get(&clip_weights_source, |progress| {
-                progress_handler(create_progress(progress))
-            })
-            .await?;
+        let (tokenizer, clip_weights) = match version {
+            WuerstchenVersion::V2 => {
+                let tokenizer_source = ModelFile::Tokenizer.get(tokenizer, version);
+                let tokenizer_source_display = format!("Tokenizer ({})", tokenizer_source);
+                let mut create_progress =
+                    ModelLoadingProgress::downloading_progress(tokenizer_source_display);
+                let tokenizer = cache
+                    .get(&tokenizer_source, |progress| {
+                        progress_handler(create_progress(progress))
+                    })
+                    .await?;
+
+                let clip_weights_source = ModelFile::Clip.get(clip_weights, version);
+                let clip_weights_source_display = format!("Clip Weights ({})", clip_weights_source);
+                let mut create_progress =
+                    ModelLoadingProgress::downloading_progress(clip_weights_source_display);
+                let clip_weights = cache
+                    .get(&clip_weights_source, |progress| {
+                        progress_handler(create_progress(progress))
+                    })
+                    .await?;
+                (Some(tokenizer), Some(clip_weights))
+            }
+            // Stable Cascade conditions both stages on the prior text encoder
+            WuerstchenVersion::StableCascade => (None, None),
+        };
 
-        let prior_clip_weights_source = ModelFile::PriorClip.get(prior_clip_weights);
+        let prior_clip_weights_source = ModelFile::PriorClip.get(prior_clip_weights, version);
         let prior_clip_weights_source_display =
             format!("Prior Clip Weights ({})", prior_clip_weights_source);
         let mut create_progress =
@@ -267,7 +701,7 @@ impl WuerstchenBuilder {
             })
             .await?;
 
-        let decoder_weights_source = ModelFile::Decoder.get(decoder_weights);
+        let decoder_weights_source = ModelFile::Decoder.get(decoder_weights, version);
         let decoder_weights_source_display =
             format!("Decoder Weights ({})", decoder_weights_source);
         let mut create_progress =
@@ -278,7 +712,7 @@ impl WuerstchenBuilder {
             })
             .await?;
 
-        let prior_weights_source = ModelFile::Prior.get(prior_weights);
+        let prior_weights_source = ModelFile::Prior.get(prior_weights, version);
         let prior_weights_source_display = format!("Prior Weights ({})", prior_weights_source);
         let mut create_progress =
             ModelLoadingProgress::downloading_progress(prior_weights_source_display);
@@ -288,7 +722,7 @@ impl WuerstchenBuilder {
             })
             .await?;
 
-        let vqgan_weights_source = ModelFile::VqGan.get(vqgan_weights);
+        let vqgan_weights_source = ModelFile::VqGan.get(vqgan_weights, version);
         let vqgan_weights_source_display = format!("VQGAN Weights ({})", vqgan_weights_source);
         let mut create_progress =
             ModelLoadingProgress::downloading_progress(vqgan_weights_source_display);
@@ -298,8 +732,59 @@ impl WuerstchenBuilder {
             })
             .await?;
 
+        let mut lora_adapters = Vec::with_capacity(loras.len());
+        for (lora_source, scale) in loras {
+            let lora_source_display = format!("LoRA Weights ({})", lora_source);
+            let mut create_progress =
+                ModelLoadingProgress::downloading_progress(lora_source_display);
+            let path = cache
+                .get(&lora_source, |progress| {
+                    progress_handler(create_progress(progress))
+                })
+                .await?;
+            lora_adapters.push(LoraAdapter { path, scale });
+        }
+
+        let safety_checker_weights = match safety_checker {
+            Some(_) => {
+                let safety_checker_source =
+                    ModelFile::SafetyChecker.get(safety_checker_weights, version);
+                let safety_checker_source_display =
+                    format!("Safety Checker Weights ({})", safety_checker_source);
+                let mut create_progress =
+                    ModelLoadingProgress::downloading_progress(safety_checker_source_display);
+                let safety_checker_weights = cache
+                    .get(&safety_checker_source, |progress| {
+                        progress_handler(create_progress(progress))
+                    })
+                    .await?;
+                Some(safety_checker_weights)
+            }
+            None => None,
+        };
+
+        let upscaler_weights = match upscale {
+            1 => None,
+            _ => {
+                let upscaler_source =
+                    upscaler_weights.unwrap_or_else(|| upscaler::default_weights(upscale));
+                let upscaler_source_display = format!("Upscaler Weights ({})", upscaler_source);
+                let mut create_progress =
+                    ModelLoadingProgress::downloading_progress(upscaler_source_display);
+                let upscaler_weights = cache
+                    .get(&upscaler_source, |progress| {
+                        progress_handler(create_progress(progress))
+                    })
+                    .await?;
+                Some(upscaler_weights)
+            }
+        };
+
         let settings = WuerstcheModelSettings {
             use_flash_attn,
+            version,
+            dtype,
+            cpu_offload,
             decoder_weights,
             clip_weights,
             prior_clip_weights,
@@ -307,16 +792,24 @@ impl WuerstchenBuilder {
             vqgan_weights,
             tokenizer,
             prior_tokenizer,
+            loras: lora_adapters,
+            safety_checker_weights,
+            safety_checker_action: safety_checker.unwrap_or_default(),
+            upscale,
+            upscaler_weights,
         };
-        let model = WuerstchenInner::new(settings).unwrap();
+        let model = WuerstchenInner::new(settings)?;
 
         let (rx, tx) = std::sync::mpsc::channel();
         let thread = std::thread::spawn(move || {
             while let Ok(message) = tx.recv() {
                 match message {
                     WuerstchenMessage::Kill => return,
-                    WuerstchenMessage::Generate(input, result) => {
-                        model.run(input, result);
+                    WuerstchenMessage::Generate(input, result, queued, control) => {
+                        model.run(*input, result, queued, &control.aborted);
+                    }
+                    WuerstchenMessage::Decode(latents, result) => {
+                        _ = result.send(model.decode_latents(latents));
                     }
                 }
             }
@@ -331,7 +824,7 @@ impl WuerstchenBuilder {
 
 impl ModelBuilder for WuerstchenBuilder {
     type Model = Wuerstchen;
-    type Error = CacheError;
+    type Error = WuerstchenError;
 
     async fn start_with_loading_handler(
         self,
@@ -341,35 +834,7 @@ impl ModelBuilder for WuerstchenBuilder {
     }
 
     fn requires_download(&self) -> bool {
-        let cache = Cache::default();
-        let downloaded_decoder_weights = self.decoder_weights.is_none()
-            || cache.exists(&<&ModelFile as Into<FileSource>>::into(&ModelFile::Decoder));
-        let downloaded_clip_weights = self.clip_weights.is_none()
-            || cache.exists(&<&ModelFile as Into<FileSource>>::into(&ModelFile::Clip));
-        let downloaded_prior_clip_weights = self.prior_clip_weights.is_none()
-            || cache.exists(&<&ModelFile as Into<FileSource>>::into(
-                &ModelFile::PriorClip,
-            ));
-        let downloaded_prior_weights = self.prior_weights.is_none()
-            || cache.exists(&<&ModelFile as Into<FileSource>>::into(&ModelFile::Prior));
-        let downloaded_vqgan_weights = self.vqgan_weights.is_none()
-            || cache.exists(&<&ModelFile as Into<FileSource>>::into(&ModelFile::VqGan));
-        let downloaded_tokenizer = self.tokenizer.is_none()
-            || cache.exists(&<&ModelFile as Into<FileSource>>::into(
-                &ModelFile::Tokenizer,
-            ));
-        let downloaded_prior_tokenizer = self.prior_tokenizer.is_none()
-            || cache.exists(&<&ModelFile as Into<FileSource>>::into(
-                &ModelFile::PriorTokenizer,
-            ));
-
-        !(downloaded_decoder_weights
-            && downloaded_clip_weights
-            && downloaded_prior_clip_weights
-            && downloaded_prior_weights
-            && downloaded_vqgan_weights
-            && downloaded_tokenizer
-            && downloaded_prior_tokenizer)
+        !self.cache.missing(&self.required_files()).is_empty()
     }
 }
 
@@ -381,7 +846,7 @@ pub struct Wuerstchen {
 
 impl Wuerstchen {
     /// Create a default Wuerstchen model.
-    pub async fn new() -> Result<Self, CacheError> {
+    pub async fn new() -> Result<Self, WuerstchenError> {
         Self::builder().build().await
     }
 
@@ -392,20 +857,113 @@ impl Wuerstchen {
 
     /// Run inference with the given settings.
     ///
-    /// Dropping the returned channel will stop the inference early.
-    pub fn run(&self, settings: WuerstchenInferenceSettings) -> ChannelImageStream<Image> {
+    /// The returned handle is a stream of the generated images. The inference can be stopped with [`GenerationHandle::abort`] or by dropping the handle.
+    pub fn run(&self, settings: WuerstchenInferenceSettings) -> GenerationHandle {
         let (sender, receiver) = futures_channel::mpsc::unbounded();
-        self.run_into(settings, sender);
-        ChannelImageStream::from(receiver)
+        self.start_generation(settings, sender.into(), receiver.into())
+    }
+
+    /// Run inference with the given settings and block the current thread until every image is generated.
+    ///
+    /// This is meant for CLI tools and other synchronous code that doesn't run an async runtime. Don't call it from an async task, since it blocks the thread the task runs on. Previews are skipped. If any image fails to generate, the first error is returned.
+    ///
+    /// # Example
+    /// ```rust, no_run
+    /// use rwuerstchen::*;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), anyhow::Error> {
+    /// let model = Wuerstchen::builder().build().await?;
+    /// let settings = WuerstchenInferenceSettings::new("a cute cat with a hat").with_sample_count(2);
+    /// for (index, image) in model.run_sync(settings)?.into_iter().enumerate() {
+    ///     image.image().save(format!("cat-{index}.png"))?;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn run_sync(
+        &self,
+        settings: WuerstchenInferenceSettings,
+    ) -> Result<Vec<GeneratedImage>, WuerstchenError> {
+        collect_blocking(self.run(settings))
+    }
+
+    /// Run inference with the given settings, buffering at most `capacity` images that have not been read from the returned handle yet.
+    ///
+    /// Unlike [`Wuerstchen::run`], the model pauses when the buffer is full until the consumer reads the next image. This keeps a slow consumer from buffering many large images in memory. Previews count towards the capacity.
+    pub fn run_with_capacity(
+        &self,
+        settings: WuerstchenInferenceSettings,
+        capacity: usize,
+    ) -> GenerationHandle {
+        let (sender, receiver) = bounded_image_channel(capacity);
+        self.start_generation(settings, sender.into(), receiver.into())
+    }
+
+    fn start_generation(
+        &self,
+        settings: WuerstchenInferenceSettings,
+        sender: ImageSender,
+        images: ChannelImageStream<Image>,
+    ) -> GenerationHandle {
+        let (handle, control) = GenerationHandle::new(images);
+        self.send_generate(settings, sender, control);
+        handle
     }
 
     /// Run inference with the given settings into a stream of images
     ///
     /// Dropping the receiver will stop the inference early.
     pub fn run_into(&self, settings: WuerstchenInferenceSettings, sender: UnboundedSender<Image>) {
-        _ = self
-            .sender
-            .send(WuerstchenMessage::Generate(settings, sender));
+        let (control, _) = GenerationControl::new();
+        self.send_generate(settings, sender.into(), control);
+    }
+
+    /// Decode latents from [`Image::latents`] into images with the VQGAN.
+    ///
+    /// The latents can have the shape `[4, height, width]` or a batch of latents with the shape `[batch, 4, height, width]`. One image is returned for each latent in the batch.
+    ///
+    /// # Example
+    /// ```rust, no_run
+    /// use futures_util::StreamExt;
+    /// use rwuerstchen::*;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), anyhow::Error> {
+    /// let model = Wuerstchen::builder().build().await?;
+    /// let settings = WuerstchenInferenceSettings::new_batch(["a red apple", "a green pear"])
+    ///     .with_seed(0)
+    ///     .with_return_latents(true);
+    /// let images: Vec<_> = model.run(settings).collect().await;
+    /// let apple = images[0].latents().unwrap();
+    /// let pear = images[1].latents().unwrap();
+    /// // Blend the latents of the two images
+    /// let blended = ((apple * 0.5)? + (pear * 0.5)?)?;
+    /// let blended = model.decode_latents(blended).await?;
+    /// blended[0].save("blended.png")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn decode_latents(&self, latents: Tensor) -> Result<Vec<RgbImage>, WuerstchenError> {
+        let (sender, receiver) = oneshot::channel();
+        _ = self.sender.send(WuerstchenMessage::Decode(latents, sender));
+        receiver.await.map_err(|_| {
+            WuerstchenError::Inference(candle_core::Error::Msg(
+                "The model thread stopped".to_string(),
+            ))
+        })?
+    }
+
+    fn send_generate(
+        &self,
+        settings: WuerstchenInferenceSettings,
+        sender: ImageSender,
+        control: GenerationControl,
+    ) {
+        _ = self.sender.send(WuerstchenMessage::Generate(
+            Box::new(settings),
+            sender,
+            Timer::start(),
+            control,
+        ));
     }
 }
 
@@ -418,13 +976,193 @@ impl Drop for Wuerstchen {
 
 enum WuerstchenMessage {
     Kill,
-    Generate(WuerstchenInferenceSettings, UnboundedSender<Image>),
+    Generate(
+        Box<WuerstchenInferenceSettings>,
+        ImageSender,
+        Timer,
+        GenerationControl,
+    ),
+    Decode(
+        Tensor,
+        oneshot::Sender<Result<Vec<RgbImage>, WuerstchenError>>,
+    ),
+}
+
+/// The state the model thread shares with a [`GenerationHandle`]
+struct GenerationControl {
+    aborted: Arc<AtomicBool>,
+    /// Dropped when the model thread is done with the generation, which resolves [`GenerationHandle::finished`]
+    _finished: oneshot::Sender<()>,
+}
+
+impl GenerationControl {
+    fn new() -> (Self, oneshot::Receiver<()>) {
+        let (finished, receiver) = oneshot::channel();
+        let control = Self {
+            aborted: Default::default(),
+            _finished: finished,
+        };
+        (control, receiver)
+    }
+}
+
+/// The channel the model thread sends generated images to.
+enum ImageSender {
+    Unbounded(UnboundedSender<Image>),
+    Bounded(Sender<Image>),
+}
+
+impl From<UnboundedSender<Image>> for ImageSender {
+    fn from(sender: UnboundedSender<Image>) -> Self {
+        Self::Unbounded(sender)
+    }
+}
+
+impl From<Sender<Image>> for ImageSender {
+    fn from(sender: Sender<Image>) -> Self {
+        Self::Bounded(sender)
+    }
+}
+
+impl ImageSender {
+    fn is_closed(&self) -> bool {
+        match self {
+            Self::Unbounded(sender) => sender.is_closed(),
+            Self::Bounded(sender) => sender.is_closed(),
+        }
+    }
+
+    /// Send an image. If the channel is bounded, this blocks the model thread until there is room in the channel or the generation is aborted.
+    fn send(&mut self, image: Image, aborted: &AtomicBool) -> Result<(), SendError> {
+        match self {
+            Self::Unbounded(sender) => sender.start_send(image),
+            Self::Bounded(sender) => {
+                let waker = Waker::from(Arc::new(ThreadWaker(std::thread::current())));
+                let mut cx = Context::from_waker(&waker);
+                while sender.poll_ready(&mut cx)?.is_pending() {
+                    // The image is never read if the generation is aborted
+                    if aborted.load(Ordering::SeqCst) {
+                        return Ok(());
+                    }
+                    std::thread::park_timeout(Duration::from_millis(100));
+                }
+                sender.start_send(image)
+            }
+        }
+    }
+}
+
+/// Create a channel that holds at most `capacity` images that have not been received yet. A capacity of 0 is treated as 1.
+fn bounded_image_channel(capacity: usize) -> (Sender<Image>, Receiver<Image>) {
+    // The channel always has room for one message per sender in addition to the buffer
+    futures_channel::mpsc::channel(capacity.max(1) - 1)
+}
+
+/// Block the current thread until the stream ends and collect the final images. Previews are skipped, and the first error is returned.
+fn collect_blocking(
+    mut images: impl Stream<Item = Image> + Unpin,
+) -> Result<Vec<GeneratedImage>, WuerstchenError> {
+    let waker = Waker::from(Arc::new(ThreadWaker(std::thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    let mut generated = Vec::new();
+    loop {
+        match images.poll_next_unpin(&mut cx) {
+            Poll::Ready(Some(image)) if image.is_preview() => {}
+            Poll::Ready(Some(image)) => generated.push(image.try_into()?),
+            Poll::Ready(None) => return Ok(generated),
+            Poll::Pending => std::thread::park(),
+        }
+    }
+}
+
+/// Wakes a blocked thread, either the model thread when a bounded channel has room for another image or the thread waiting in [`Wuerstchen::run_sync`].
+struct ThreadWaker(std::thread::Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// A handle to an image generation started with [`Wuerstchen::run`].
+///
+/// The handle is a stream of the images as they are generated. It can also stop the generation and wait for it to finish.
+///
+/// # Example
+/// ```rust, no_run
+/// use futures_util::StreamExt;
+/// use rwuerstchen::*;
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), anyhow::Error> {
+/// let model = Wuerstchen::builder().build().await?;
+/// let settings = WuerstchenInferenceSettings::new("a cute cat with a hat").with_sample_count(4);
+/// let mut images = model.run(settings);
+/// // Keep the first image and stop generating the rest
+/// if let Some(image) = images.next().await {
+///     image.generated_image().unwrap().save("cat.png")?;
+/// }
+/// images.abort();
+/// images.finished().await;
+/// # Ok(())
+/// # }
+/// ```
+pub struct GenerationHandle {
+    images: ChannelImageStream<Image>,
+    aborted: Arc<AtomicBool>,
+    finished: Shared<oneshot::Receiver<()>>,
+}
+
+impl GenerationHandle {
+    /// Create a handle for the images and the control the model thread uses to check for the abort and report when it is done.
+    fn new(images: ChannelImageStream<Image>) -> (Self, GenerationControl) {
+        let (control, finished) = GenerationControl::new();
+        let handle = Self {
+            images,
+            aborted: control.aborted.clone(),
+            finished: finished.shared(),
+        };
+        (handle, control)
+    }
+
+    /// Stop the generation. The model checks for the abort between diffusion steps, so the generation stops after the current step finishes and no more images are sent.
+    pub fn abort(&self) {
+        self.aborted.store(true, Ordering::SeqCst);
+    }
+
+    /// Check if the generation was aborted.
+    pub fn is_aborted(&self) -> bool {
+        self.aborted.load(Ordering::SeqCst)
+    }
+
+    /// Wait until the model is done with the generation, either because every image was generated or because it was aborted.
+    pub fn finished(&self) -> impl Future<Output = ()> + Send + 'static {
+        self.finished.clone().map(|_| ())
+    }
+}
+
+impl std::fmt::Debug for GenerationHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GenerationHandle")
+            .field("aborted", &self.is_aborted())
+            .finish()
+    }
+}
+
+impl Stream for GenerationHandle {
+    type Item = Image;
+
+    fn poll_next(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> core::task::Poll<Option<Self::Item>> {
+        self.images.poll_next_unpin(cx)
+    }
 }
 
 /// Settings for running inference with the Wuerstchen model.
 pub struct WuerstchenInferenceSettings {
-    /// The prompt to be used for image generation.
-    prompt: String,
+    /// The prompts to be used for image generation. One image is generated for each prompt in every sample.
+    prompts: Vec<String>,
 
     uncond_prompt: String,
 
@@ -445,13 +1183,45 @@ pub struct WuerstchenInferenceSettings {
 
     /// Higher guidance scale encourages to generate images that are closely linked to the text prompt, usually at the expense of lower image quality.
     prior_guidance_scale: f64,
+
+    /// The negative prompt used by the decoder (stage B).
+    decoder_uncond_prompt: String,
+
+    /// The guidance scale of the decoder (stage B). Guidance is disabled if the scale is 1 or less.
+    decoder_guidance_scale: f64,
+
+    /// The noise scheduler used by the prior and the denoiser.
+    scheduler: Scheduler,
+
+    /// The seed for the random noise the images are generated from.
+    seed: Option<u64>,
+
+    /// A handler that is called with the progress of the inference after each diffusion step.
+    progress_handler: Option<Box<dyn FnMut(DiffusionProgress) + Send>>,
+
+    /// The number of denoiser steps between each preview of the image.
+    preview_every_n_steps: Option<usize>,
+
+    /// If the latents of each image are returned with the image.
+    return_latents: bool,
 }
 
 impl WuerstchenInferenceSettings {
     /// Create a new settings object with the given prompt.
+    ///
+    /// Parts of the prompt can be emphasized with `(text:1.3)`, which scales the attention the model pays to the text by 1.3. `(text)` scales the attention by 1.1 and `[text]` scales it by 1/1.1. Use `\(` and `\[` to include a literal bracket in the prompt.
+    ///
+    /// Prompts longer than the 77 token CLIP context are split into chunks that are encoded separately and joined together, so long prompts are not truncated.
     pub fn new(prompt: impl Into<String>) -> Self {
+        Self::new_batch([prompt])
+    }
+
+    /// Create a new settings object that generates one image for each prompt in a single pass.
+    ///
+    /// The prompts run through the prior and decoder together in one batch, which is much faster than generating each prompt with a separate [`Wuerstchen::run`] call. Use [`Image::prompt_index`] to find the prompt each image was generated from.
+    pub fn new_batch(prompts: impl IntoIterator<Item = impl Into<String>>) -> Self {
         Self {
-            prompt: prompt.into(),
+            prompts: prompts.into_iter().map(Into::into).collect(),
 
             uncond_prompt: String::new(),
 
@@ -466,10 +1236,24 @@ impl WuerstchenInferenceSettings {
             num_samples: 1,
 
             prior_guidance_scale: 4.0,
+
+            decoder_uncond_prompt: String::new(),
+
+            decoder_guidance_scale: 0.0,
+
+            scheduler: Scheduler::default(),
+
+            seed: None,
+
+            progress_handler: None,
+
+            preview_every_n_steps: None,
+
+            return_latents: false,
         }
     }
 
-    /// Set the negative prompt to be used for image generation.
+    /// Set the negative prompt to be used by the prior (stage C).
     pub fn with_negative_prompt(mut self, uncond_prompt: impl Into<String>) -> Self {
         self.uncond_prompt = uncond_prompt.into();
         self
@@ -499,7 +1283,7 @@ impl WuerstchenInferenceSettings {
         self
     }
 
-    /// Set the number of samples to generate.
+    /// Set the number of samples to generate. Each sample has one image for each prompt.
     pub fn with_sample_count(mut self, sample_count: i64) -> Self {
         self.num_samples = sample_count;
         self
@@ -510,6 +1294,65 @@ impl WuerstchenInferenceSettings {
         self.prior_guidance_scale = prior_guidance_scale;
         self
     }
+
+    /// Set the negative prompt to be used by the decoder (stage B). The negative prompt is only used if the decoder guidance scale is greater than 1.
+    pub fn with_decoder_negative_prompt(mut self, uncond_prompt: impl Into<String>) -> Self {
+        self.decoder_uncond_prompt = uncond_prompt.into();
+        self
+    }
+
+    /// Set the decoder guidance scale. (Defaults to 0, which disables guidance for the decoder)
+    ///
+    /// The prior already ties the image to the prompt, so the decoder usually runs without guidance. Scales greater than 1 run the decoder twice per step, with and without the prompt, and push the image towards the prompt and away from the decoder negative prompt.
+    pub fn with_decoder_guidance_scale(mut self, decoder_guidance_scale: f64) -> Self {
+        self.decoder_guidance_scale = decoder_guidance_scale;
+        self
+    }
+
+    /// Set the noise scheduler used by the prior and the denoiser. Some schedulers produce good images with fewer steps, so you can lower [`Self::with_prior_steps`] and [`Self::with_denoiser_steps`] to generate images faster. (Defaults to [`Scheduler::Ddpm`])
+    pub fn with_scheduler(mut self, scheduler: Scheduler) -> Self {
+        self.scheduler = scheduler;
+        self
+    }
+
+    /// Set the seed for the random noise. Running the same settings with the same seed on the same device generates the same images. (Defaults to a random seed)
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Set a handler that is called with the progress of the inference, from 0 to 1, after each diffusion step.
+    ///
+    /// Unlike [`Image::progress`], which only updates when a sample finishes, this reports progress while each image is being generated.
+    pub fn with_progress_handler(mut self, mut handler: impl FnMut(f32) + Send + 'static) -> Self {
+        self.progress_handler = Some(Box::new(move |progress: DiffusionProgress| {
+            handler(progress.progress())
+        }));
+        self
+    }
+
+    /// Set a handler that is called with the stage, the step within the stage and the estimated time remaining after each diffusion step. This replaces the handler set with [`Self::with_progress_handler`].
+    pub fn with_stage_progress_handler(
+        mut self,
+        handler: impl FnMut(DiffusionProgress) + Send + 'static,
+    ) -> Self {
+        self.progress_handler = Some(Box::new(handler));
+        self
+    }
+
+    /// Decode a preview of each sample every `steps` denoiser steps and send it to the image stream before the final image. Previews can be detected with [`Image::is_preview`]. (Defaults to no previews)
+    ///
+    /// Previews let frontends show the image while it is generated. Each preview decodes the partially denoised latents with the VQGAN, which is much cheaper than the denoiser steps but still adds some time to each sample.
+    pub fn with_preview_every_n_steps(mut self, steps: usize) -> Self {
+        self.preview_every_n_steps = (steps > 0).then_some(steps);
+        self
+    }
+
+    /// Set if the latents each image was decoded from are returned with the image in [`Image::latents`]. (Defaults to false)
+    pub fn with_return_latents(mut self, return_latents: bool) -> Self {
+        self.return_latents = return_latents;
+        self
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -521,37 +1364,74 @@ enum ModelFile {
     Decoder,
     VqGan,
     Prior,
+    SafetyChecker,
 }
 
+/// The safety checker is the same for every version of the model
+const SAFETY_CHECKER: (&str, &str) = (
+    "CompVis/stable-diffusion-safety-checker",
+    "model.safetensors",
+);
+
 impl ModelFile {
-    fn get(&self, filename: Option<String>) -> FileSource {
-        match filename {
-            Some(filename) => FileSource::local(std::path::PathBuf::from(filename)),
-            None => self.into(),
-        }
+    fn get(&self, source: Option<FileSource>, version: WuerstchenVersion) -> FileSource {
+        source.unwrap_or_else(|| self.source(version))
     }
-}
 
-impl From<&ModelFile> for FileSource {
-    fn from(val: &ModelFile) -> Self {
-        let repo_main = "warp-ai/wuerstchen";
-        let repo_prior = "warp-ai/wuerstchen-prior";
-        let (repo, path) = match val {
-            ModelFile::Tokenizer => (repo_main, "tokenizer/tokenizer.json"),
-            ModelFile::PriorTokenizer => (repo_prior, "tokenizer/tokenizer.json"),
-            ModelFile::Clip => (repo_main, "text_encoder/model.safetensors"),
-            ModelFile::PriorClip => (repo_prior, "text_encoder/model.safetensors"),
-            ModelFile::Decoder => (repo_main, "decoder/diffusion_pytorch_model.safetensors"),
-            ModelFile::VqGan => (repo_main, "vqgan/diffusion_pytorch_model.safetensors"),
-            ModelFile::Prior => (repo_prior, "prior/diffusion_pytorch_model.safetensors"),
+    /// The default source of the file for a version of the model.
+    fn source(&self, version: WuerstchenVersion) -> FileSource {
+        let (repo, path) = match version {
+            WuerstchenVersion::V2 => {
+                let repo_main = "warp-ai/wuerstchen";
+                let repo_prior = "warp-ai/wuerstchen-prior";
+                match self {
+                    ModelFile::Tokenizer => (repo_main, "tokenizer/tokenizer.json"),
+                    ModelFile::PriorTokenizer => (repo_prior, "tokenizer/tokenizer.json"),
+                    ModelFile::Clip => (repo_main, "text_encoder/model.safetensors"),
+                    ModelFile::PriorClip => (repo_prior, "text_encoder/model.safetensors"),
+                    ModelFile::Decoder => {
+                        (repo_main, "decoder/diffusion_pytorch_model.safetensors")
+                    }
+                    ModelFile::VqGan => (repo_main, "vqgan/diffusion_pytorch_model.safetensors"),
+                    ModelFile::Prior => (repo_prior, "prior/diffusion_pytorch_model.safetensors"),
+                    ModelFile::SafetyChecker => SAFETY_CHECKER,
+                }
+            }
+            WuerstchenVersion::StableCascade => {
+                let repo_main = "stabilityai/stable-cascade";
+                let repo_prior = "stabilityai/stable-cascade-prior";
+                match self {
+                    // Stable Cascade uses the same CLIP-G tokenizer as the Würstchen v2 prior
+                    ModelFile::Tokenizer | ModelFile::PriorTokenizer => {
+                        ("warp-ai/wuerstchen-prior", "tokenizer/tokenizer.json")
+                    }
+                    ModelFile::Clip | ModelFile::PriorClip => {
+                        (repo_prior, "text_encoder/model.safetensors")
+                    }
+                    ModelFile::Decoder => (
+                        repo_main,
+                        "decoder_lite/diffusion_pytorch_model.safetensors",
+                    ),
+                    ModelFile::VqGan => (repo_main, "vqgan/diffusion_pytorch_model.safetensors"),
+                    ModelFile::Prior => {
+                        (repo_prior, "prior_lite/diffusion_pytorch_model.safetensors")
+                    }
+                    ModelFile::SafetyChecker => SAFETY_CHECKER,
+                }
+            }
         };
         FileSource::huggingface(repo.to_owned(), "main".to_owned(), path.to_owned())
     }
 }
 
-/// A stream of images from a tokio channel.
+/// A stream of images from a channel.
 pub struct ChannelImageStream<S: AsRef<ImageBuffer<image::Rgb<u8>, Vec<u8>>>> {
-    receiver: UnboundedReceiver<S>,
+    receiver: ImageReceiver<S>,
+}
+
+enum ImageReceiver<S> {
+    Unbounded(UnboundedReceiver<S>),
+    Bounded(Receiver<S>),
 }
 
 impl<S: AsRef<ImageBuffer<image::Rgb<u8>, Vec<u8>>>> std::fmt::Debug for ChannelImageStream<S> {
@@ -564,7 +1444,17 @@ impl<S: AsRef<ImageBuffer<image::Rgb<u8>, Vec<u8>>>> From<UnboundedReceiver<S>>
     for ChannelImageStream<S>
 {
     fn from(receiver: UnboundedReceiver<S>) -> Self {
-        Self { receiver }
+        Self {
+            receiver: ImageReceiver::Unbounded(receiver),
+        }
+    }
+}
+
+impl<S: AsRef<ImageBuffer<image::Rgb<u8>, Vec<u8>>>> From<Receiver<S>> for ChannelImageStream<S> {
+    fn from(receiver: Receiver<S>) -> Self {
+        Self {
+            receiver: ImageReceiver::Bounded(receiver),
+        }
     }
 }
 
@@ -575,6 +1465,121 @@ impl<S: AsRef<ImageBuffer<image::Rgb<u8>, Vec<u8>>>> Stream for ChannelImageStre
         mut self: std::pin::Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
     ) -> core::task::Poll<Option<Self::Item>> {
-        self.receiver.poll_next_unpin(cx)
+        match &mut self.receiver {
+            ImageReceiver::Unbounded(receiver) => receiver.poll_next_unpin(cx),
+            ImageReceiver::Bounded(receiver) => receiver.poll_next_unpin(cx),
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn zero_preview_steps_disables_previews() {
+    let settings = WuerstchenInferenceSettings::new("a cat").with_preview_every_n_steps(0);
+    assert_eq!(settings.preview_every_n_steps, None);
+    let settings = settings.with_preview_every_n_steps(5);
+    assert_eq!(settings.preview_every_n_steps, Some(5));
+    let settings = settings.with_preview_every_n_steps(0);
+    assert_eq!(settings.preview_every_n_steps, None);
+}
+
+#[cfg(test)]
+#[test]
+fn generation_handle_aborts_and_finishes() {
+    let (_sender, receiver) = futures_channel::mpsc::unbounded::<Image>();
+    let (handle, control) = GenerationHandle::new(receiver.into());
+    let finished_before_drop = handle.finished();
+    let finished = handle.finished();
+
+    assert!(!handle.is_aborted());
+    assert!(!control.aborted.load(Ordering::SeqCst));
+    handle.abort();
+    assert!(handle.is_aborted());
+    assert!(control.aborted.load(Ordering::SeqCst));
+
+    // The generation is finished when the model thread drops the control, even if the handle is already dropped
+    assert!(handle.finished().now_or_never().is_none());
+    drop(handle);
+    assert!(finished_before_drop.now_or_never().is_none());
+    drop(control);
+    assert!(finished.now_or_never().is_some());
+}
+
+#[cfg(test)]
+fn test_image(sample_num: i64, preview: bool) -> Image {
+    Image {
+        sample_num,
+        prompt_index: 0,
+        progress: DiffusionProgress {
+            stage: DiffusionStage::Decode,
+            step: 0,
+            stage_steps: 1,
+            elapsed_time: Duration::ZERO,
+            remaining_time: Duration::ZERO,
+            progress: 0.,
+        },
+        preview,
+        result: Ok(DiffusionResult {
+            image: RgbImage::new(1, 1),
+            latents: None,
+            height: 1,
+            width: 1,
+            nsfw: false,
+        }),
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn bounded_image_channel_holds_capacity_images() {
+    let mut cx = Context::from_waker(futures_util::task::noop_waker_ref());
+    for capacity in [0, 1, 2, 5] {
+        let (mut sender, mut receiver) = bounded_image_channel(capacity);
+        for sample_num in 0..capacity.max(1) {
+            assert!(sender.poll_ready(&mut cx).is_ready(), "capacity {capacity}");
+            sender
+                .start_send(test_image(sample_num as i64, false))
+                .unwrap();
+        }
+        // The model pauses until the consumer reads an image
+        assert!(
+            sender.poll_ready(&mut cx).is_pending(),
+            "capacity {capacity}"
+        );
+        let image = receiver.next().now_or_never().flatten().unwrap();
+        assert_eq!(image.sample_num(), 0);
+        assert!(sender.poll_ready(&mut cx).is_ready(), "capacity {capacity}");
     }
 }
+
+#[cfg(test)]
+#[test]
+fn collect_blocking_skips_previews() {
+    let (mut sender, receiver) = futures_channel::mpsc::unbounded();
+    sender.start_send(test_image(1, true)).unwrap();
+    sender.start_send(test_image(1, false)).unwrap();
+    // Images that arrive after the thread starts waiting wake it up
+    let producer = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(50));
+        sender.start_send(test_image(2, true)).unwrap();
+        sender.start_send(test_image(2, false)).unwrap();
+    });
+    let images = collect_blocking(receiver).unwrap();
+    producer.join().unwrap();
+    let samples: Vec<_> = images.iter().map(|image| image.sample_num()).collect();
+    assert_eq!(samples, [1, 2]);
+
+    let (mut sender, receiver) = futures_channel::mpsc::unbounded();
+    sender.start_send(test_image(1, false)).unwrap();
+    sender
+        .start_send(Image {
+            result: Err(WuerstchenError::Inference(candle_core::Error::Msg(
+                "failed".to_string(),
+            ))),
+            ..test_image(2, false)
+        })
+        .unwrap();
+    sender.start_send(test_image(3, false)).unwrap();
+    drop(sender);
+    assert!(collect_blocking(receiver).is_err());
+}
@@ -0,0 +1,208 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use candle_core::{DType, Device, Tensor};
+use candle_nn::VarBuilder;
+
+/// The suffixes of the down and up projections of a LoRA layer in the PEFT, diffusers and kohya formats.
+const LORA_SUFFIXES: [(&str, &str); 3] = [
+    (".lora_A.weight", ".lora_B.weight"),
+    (".lora.down.weight", ".lora.up.weight"),
+    (".lora_down.weight", ".lora_up.weight"),
+];
+
+/// Prefixes LoRA trainers add before the name of the layer in the model.
+const LAYER_PREFIXES: [&str; 5] = [
+    "base_model.model.",
+    "prior.",
+    "decoder.",
+    "unet.",
+    "transformer.",
+];
+
+/// Prefixes kohya style LoRAs add before the name of the layer with the dots replaced by underscores.
+const KOHYA_PREFIXES: [&str; 3] = ["lora_unet_", "lora_prior_", "lora_decoder_"];
+
+/// A LoRA adapter with the scale it is merged with.
+pub(crate) struct LoraAdapter {
+    pub(crate) path: PathBuf,
+    pub(crate) scale: f64,
+}
+
+/// One layer of a LoRA adapter.
+struct LoraLayer {
+    /// The name of the layer in the LoRA file.
+    name: String,
+    down: Tensor,
+    up: Tensor,
+    scale: f64,
+}
+
+/// The layers of every LoRA adapter that will be merged into the model.
+pub(crate) struct LoraLayers {
+    layers: Vec<LoraLayer>,
+    merged: HashSet<String>,
+}
+
+impl LoraLayers {
+    /// Read the layers of every adapter.
+    pub(crate) fn load(adapters: &[LoraAdapter]) -> candle_core::Result<Self> {
+        let mut layers = Vec::new();
+        for adapter in adapters {
+            let tensors = candle_core::safetensors::load(&adapter.path, &Device::Cpu)?;
+            for (key, down) in &tensors {
+                let Some((name, up_suffix)) = LORA_SUFFIXES
+                    .iter()
+                    .find_map(|(down, up)| key.strip_suffix(down).map(|name| (name, up)))
+                else {
+                    continue;
+                };
+                let Some(up) = tensors.get(&format!("{name}{up_suffix}")) else {
+                    tracing::warn!(
+                        "LoRA layer {name} in {:?} is missing the up projection",
+                        adapter.path
+                    );
+                    continue;
+                };
+                // If the adapter was trained with an alpha, the update is scaled by alpha / rank
+                let rank = down.dim(0)?;
+                let alpha = match tensors.get(&format!("{name}.alpha")) {
+                    Some(alpha) => alpha.to_dtype(DType::F32)?.to_scalar::<f32>()? as f64,
+                    None => rank as f64,
+                };
+                layers.push(LoraLayer {
+                    name: name.to_string(),
+                    down: down.to_dtype(DType::F32)?,
+                    up: up.to_dtype(DType::F32)?,
+                    scale: adapter.scale * alpha / rank as f64,
+                });
+            }
+        }
+        Ok(Self {
+            layers,
+            merged: HashSet::new(),
+        })
+    }
+
+    /// Load the weights in the safetensors file and merge every LoRA layer that matches a layer in the file into the weights.
+    pub(crate) fn merge_into(
+        &mut self,
+        weights: &Path,
+        dtype: DType,
+        device: &Device,
+    ) -> candle_core::Result<VarBuilder<'static>> {
+        if self.layers.is_empty() {
+            return unsafe { VarBuilder::from_mmaped_safetensors(&[weights], dtype, device) };
+        }
+
+        let mut tensors = candle_core::safetensors::load(weights, &Device::Cpu)?;
+        let kohya_names: HashMap<String, String> = tensors
+            .keys()
+            .filter_map(|key| key.strip_suffix(".weight"))
+            .map(|name| (name.replace('.', "_"), name.to_string()))
+            .collect();
+
+        for layer in &self.layers {
+            let Some(name) = target_layer(&layer.name, &tensors, &kohya_names) else {
+                continue;
+            };
+            let key = format!("{name}.weight");
+            let weight = &tensors[&key];
+            // Convolutions are merged as a matrix multiplication over the flattened kernel
+            let delta = layer
+                .up
+                .flatten_from(1)?
+                .matmul(&layer.down.flatten_from(1)?)?
+                .reshape(weight.shape())?;
+            let merged = (weight.to_dtype(DType::F32)? + (delta * layer.scale)?)?
+                .to_dtype(weight.dtype())?;
+            tensors.insert(key, merged);
+            self.merged.insert(layer.name.clone());
+        }
+
+        Ok(VarBuilder::from_tensors(tensors, dtype, device))
+    }
+
+    /// Warn about every LoRA layer that didn't match a layer in any of the models.
+    pub(crate) fn warn_unmerged(&self) {
+        let unmerged = self
+            .layers
+            .iter()
+            .filter(|layer| !self.merged.contains(&layer.name))
+            .count();
+        if unmerged > 0 {
+            tracing::warn!(
+                "{unmerged} of {} LoRA layers don't match a layer in the prior or decoder and were ignored",
+                self.layers.len()
+            );
+        }
+    }
+}
+
+/// Find the name of the layer in the model a LoRA layer applies to.
+fn target_layer(
+    name: &str,
+    tensors: &HashMap<String, Tensor>,
+    kohya_names: &HashMap<String, String>,
+) -> Option<String> {
+    let mut name = name;
+    loop {
+        if tensors.contains_key(&format!("{name}.weight")) {
+            return Some(name.to_string());
+        }
+        match LAYER_PREFIXES
+            .iter()
+            .find_map(|prefix| name.strip_prefix(prefix))
+        {
+            Some(stripped) => name = stripped,
+            None => break,
+        }
+    }
+
+    KOHYA_PREFIXES
+        .iter()
+        .find_map(|prefix| name.strip_prefix(prefix))
+        .and_then(|name| kohya_names.get(name))
+        .cloned()
+}
+
+#[cfg(test)]
+#[test]
+fn finds_target_layers() {
+    let weight = Tensor::zeros((2, 2), DType::F32, &Device::Cpu).unwrap();
+    let tensors: HashMap<String, Tensor> = ["blocks.0.attention.to_q", "blocks.0.attention.to_k"]
+        .iter()
+        .map(|name| (format!("{name}.weight"), weight.clone()))
+        .collect();
+    let kohya_names = tensors
+        .keys()
+        .filter_map(|key| key.strip_suffix(".weight"))
+        .map(|name| (name.replace('.', "_"), name.to_string()))
+        .collect();
+    let target = |name| target_layer(name, &tensors, &kohya_names);
+
+    assert_eq!(
+        target("blocks.0.attention.to_q").as_deref(),
+        Some("blocks.0.attention.to_q")
+    );
+    // Trainer prefixes are stripped, even if there are several of them
+    assert_eq!(
+        target("prior.blocks.0.attention.to_k").as_deref(),
+        Some("blocks.0.attention.to_k")
+    );
+    assert_eq!(
+        target("base_model.model.decoder.blocks.0.attention.to_q").as_deref(),
+        Some("blocks.0.attention.to_q")
+    );
+    // Kohya names replace the dots with underscores
+    assert_eq!(
+        target("lora_unet_blocks_0_attention_to_k").as_deref(),
+        Some("blocks.0.attention.to_k")
+    );
+    assert_eq!(
+        target("lora_prior_blocks_0_attention_to_q").as_deref(),
+        Some("blocks.0.attention.to_q")
+    );
+    assert_eq!(target("blocks.0.attention.to_v"), None);
+    assert_eq!(target("lora_unet_blocks_1_attention_to_q"), None);
+}
@@ -5,6 +5,7 @@ extern crate accelerate_src;
 extern crate intel_mkl_src;
 
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::{Duration, Instant};
 
 use candle_core::IndexOp;
@@ -15,59 +16,449 @@ use candle_transformers::models::wuerstchen::prior::WPrior;
 use candle_transformers::models::{stable_diffusion, wuerstchen::diffnext::WDiffNeXt};
 
 use candle_core::{DType, Device, Tensor};
-use futures_channel::mpsc::UnboundedSender;
-use image::ImageBuffer;
+use candle_nn::Linear;
+use image::{ImageBuffer, RgbImage};
+use kalosm_common::metrics::{DiffusionMetrics, Timer};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
 use tokenizers::Tokenizer;
 
-use crate::{DiffusionResult, Image, WuerstchenInferenceSettings};
+use crate::cascade::{StableCascadeConfig, StableCascadeUNet};
+use crate::lora::{LoraAdapter, LoraLayers};
+use crate::offload::Stage;
+use crate::prompt::{parse_prompt_weights, token_weights};
+use crate::quantized;
+use crate::safety::{SafetyChecker, SafetyCheckerAction};
+use crate::scheduler::{self, SchedulerState};
+use crate::upscaler::{self, Upscaler};
+use crate::{
+    DiffusionProgress, DiffusionResult, DiffusionStage, Image, ImageSender, WuerstchenError,
+    WuerstchenInferenceSettings, WuerstchenVersion,
+};
 
 const RESOLUTION_MULTIPLE: f64 = 42.67;
 const LATENT_DIM_SCALE: f64 = 10.67;
 const PRIOR_CIN: usize = 16;
 const DECODER_CIN: usize = 4;
+/// The size of the projected pooled output of the Stable Cascade text encoder.
+const STABLE_CASCADE_CLIP_DIM: usize = 1280;
+/// The size of the image embeddings the Stable Cascade prior can be conditioned on.
+const STABLE_CASCADE_CLIP_IMAGE_DIM: usize = 768;
 
 pub(crate) struct WuerstcheModelSettings {
     pub(crate) use_flash_attn: bool,
 
-    /// The decoder weight file, in .safetensors format.
+    /// The version of the model the weights are for.
+    pub(crate) version: WuerstchenVersion,
+
+    /// The data type the weights are loaded in and the inference runs in.
+    pub(crate) dtype: DType,
+
+    /// Whether to keep only the stage that is running on the device.
+    pub(crate) cpu_offload: bool,
+
+    /// The decoder weight file, in .safetensors or quantized .gguf format.
     pub(crate) decoder_weights: PathBuf,
 
-    /// The CLIP weight file, in .safetensors format.
-    pub(crate) clip_weights: PathBuf,
+    /// The CLIP weight file, in .safetensors format. Stable Cascade uses the prior CLIP for both stages, so this is only set for Wuerstchen v2.
+    pub(crate) clip_weights: Option<PathBuf>,
 
     /// The CLIP weight file used by the prior model, in .safetensors format.
     pub(crate) prior_clip_weights: PathBuf,
 
-    /// The prior weight file, in .safetensors format.
+    /// The prior weight file, in .safetensors or quantized .gguf format.
     pub(crate) prior_weights: PathBuf,
 
     /// The VQGAN weight file, in .safetensors format.
     pub(crate) vqgan_weights: PathBuf,
 
-    /// The file specifying the tokenizer to used for tokenization.
-    pub(crate) tokenizer: PathBuf,
+    /// The file specifying the tokenizer to used for tokenization. Only set for Wuerstchen v2.
+    pub(crate) tokenizer: Option<PathBuf>,
 
     /// The file specifying the tokenizer to used for prior tokenization.
     pub(crate) prior_tokenizer: PathBuf,
+
+    /// The LoRA adapters to merge into the prior and decoder weights.
+    pub(crate) loras: Vec<LoraAdapter>,
+
+    /// The safety checker weight file, in .safetensors format. Only set if the safety checker is enabled.
+    pub(crate) safety_checker_weights: Option<PathBuf>,
+
+    /// What the safety checker does with images it flags.
+    pub(crate) safety_checker_action: SafetyCheckerAction,
+
+    /// The factor the final images are upscaled by. 1 disables the upscaler.
+    pub(crate) upscale: usize,
+
+    /// The Real-ESRGAN weight file, in .safetensors or .pth format. Only set if the images are upscaled.
+    pub(crate) upscaler_weights: Option<PathBuf>,
+}
+
+/// The remaining steps of one stage of the pipeline and a moving average of the time each step takes.
+struct StageTimer {
+    remaining: usize,
+    average: Option<Duration>,
+}
+
+impl StageTimer {
+    /// How much each new step moves the average. Lower values keep the estimate steadier, higher values adapt to changes faster.
+    const SMOOTHING: f64 = 0.3;
+
+    fn new(steps: usize) -> Self {
+        Self {
+            remaining: steps,
+            average: None,
+        }
+    }
+
+    fn finish_step(&mut self, duration: Duration) {
+        self.remaining = self.remaining.saturating_sub(1);
+        self.average = Some(match self.average {
+            Some(average) => {
+                average.mul_f64(1. - Self::SMOOTHING) + duration.mul_f64(Self::SMOOTHING)
+            }
+            None => duration,
+        });
+    }
 }
+
+/// The state shared between the diffusion steps of one inference run.
+struct DiffusionSteps<'a> {
+    /// The rng used to create the initial noise and the noise schedulers add at each step if a seed was set.
+    rng: Option<StdRng>,
+    progress_handler: Option<Box<dyn FnMut(DiffusionProgress) + Send>>,
+    metrics: DiffusionMetrics,
+    aborted: &'a AtomicBool,
+    start_time: Instant,
+    /// When the last step of any stage finished, or when the current stage started
+    last_step: Instant,
+    stage: DiffusionStage,
+    step: usize,
+    stage_steps: usize,
+    prior: StageTimer,
+    denoiser: StageTimer,
+    /// The VQGAN decodes of both previews and final images
+    decode: StageTimer,
+    progress: f32,
+}
+
+impl<'a> DiffusionSteps<'a> {
+    fn new(
+        settings: &mut WuerstchenInferenceSettings,
+        queued: Timer,
+        aborted: &'a AtomicBool,
+    ) -> Self {
+        let num_samples = settings.num_samples.max(0) as usize;
+        let denoiser_steps = num_samples * settings.denoiser_steps;
+        // A preview is decoded every n denoiser steps, except after the last step
+        let previews = settings
+            .preview_every_n_steps
+            .map(|every| settings.denoiser_steps.saturating_sub(1) / every)
+            .unwrap_or(0);
+        let now = Instant::now();
+        Self {
+            rng: settings.seed.map(StdRng::seed_from_u64),
+            progress_handler: settings.progress_handler.take(),
+            metrics: DiffusionMetrics::start(
+                "wuerstchen",
+                settings.prior_steps + denoiser_steps,
+                queued,
+            ),
+            aborted,
+            start_time: now,
+            last_step: now,
+            stage: DiffusionStage::Prior,
+            step: 0,
+            stage_steps: settings.prior_steps,
+            prior: StageTimer::new(settings.prior_steps),
+            denoiser: StageTimer::new(denoiser_steps),
+            decode: StageTimer::new(num_samples * (1 + previews)),
+            progress: 0.,
+        }
+    }
+
+    /// Check if the generation was aborted
+    fn is_aborted(&self) -> bool {
+        self.aborted.load(Ordering::SeqCst)
+    }
+
+    /// Create normally distributed noise with the given shape.
+    fn noise(
+        &mut self,
+        shape: (usize, usize, usize, usize),
+        dtype: DType,
+        device: &Device,
+    ) -> candle_core::Result<Tensor> {
+        scheduler::noise(shape, dtype, device, self.rng.as_mut())
+    }
+
+    /// Start a stage of the pipeline with the given number of steps. Time spent between stages, like loading an offloaded stage, isn't counted towards the step time of the stage.
+    fn start_stage(&mut self, stage: DiffusionStage, steps: usize) {
+        self.stage = stage;
+        self.step = 0;
+        self.stage_steps = steps;
+        self.last_step = Instant::now();
+    }
+
+    /// Get the time since the last step finished and start timing the next step
+    fn lap(&mut self) -> Duration {
+        let now = Instant::now();
+        let duration = now - self.last_step;
+        self.last_step = now;
+        duration
+    }
+
+    /// Mark one diffusion step as finished and report the progress. Returns an error if the generation was aborted.
+    fn step(&mut self) -> candle_core::Result<()> {
+        if self.is_aborted() {
+            candle_core::bail!("The generation was aborted");
+        }
+        let duration = self.lap();
+        match self.stage {
+            DiffusionStage::Prior => self.prior.finish_step(duration),
+            DiffusionStage::Denoiser => self.denoiser.finish_step(duration),
+            DiffusionStage::Decode => self.decode.finish_step(duration),
+        }
+        self.step += 1;
+        self.metrics.step_finished();
+        self.update_progress();
+        let progress = self.progress();
+        if let Some(handler) = &mut self.progress_handler {
+            handler(progress);
+        }
+        Ok(())
+    }
+
+    /// Mark one VQGAN decode as finished. Previews are decoded between the steps of the denoiser stage, final images in the decode stage.
+    fn decoded(&mut self) {
+        let duration = self.lap();
+        self.decode.finish_step(duration);
+        if self.stage == DiffusionStage::Decode {
+            self.step += 1;
+        }
+        self.update_progress();
+    }
+
+    /// Estimate the time remaining from the moving average step time of each stage. Stages that haven't run yet are estimated from the stages that have.
+    fn remaining_time(&self) -> Duration {
+        let timers = [&self.prior, &self.denoiser, &self.decode];
+        let fallback = timers
+            .iter()
+            .find_map(|timer| timer.average)
+            .unwrap_or_default();
+        timers
+            .iter()
+            .map(|timer| timer.average.unwrap_or(fallback) * timer.remaining as u32)
+            .sum()
+    }
+
+    /// Update the progress from the elapsed and estimated remaining time. The progress never moves backwards, even if the estimate grows.
+    fn update_progress(&mut self) {
+        let elapsed = self.start_time.elapsed().as_secs_f32();
+        let total = elapsed + self.remaining_time().as_secs_f32();
+        if total > 0. {
+            self.progress = self.progress.max(elapsed / total).min(1.);
+        }
+    }
+
+    /// The progress of the inference
+    fn progress(&self) -> DiffusionProgress {
+        DiffusionProgress {
+            stage: self.stage,
+            step: self.step,
+            stage_steps: self.stage_steps,
+            elapsed_time: self.start_time.elapsed(),
+            remaining_time: self.remaining_time(),
+            progress: self.progress,
+        }
+    }
+}
+
+/// A CLIP text encoder and its tokenizer.
+struct TextEncoder {
+    tokenizer: Tokenizer,
+    clip: ClipTextTransformer,
+    config: stable_diffusion::clip::Config,
+    /// The projection of the pooled output. Stable Cascade is conditioned on the projected pooled output and the hidden states before the final layer norm.
+    text_projection: Option<Linear>,
+}
+
+impl TextEncoder {
+    fn load(
+        tokenizer: PathBuf,
+        weights: PathBuf,
+        config: stable_diffusion::clip::Config,
+        version: WuerstchenVersion,
+        dtype: DType,
+        device: &Device,
+    ) -> Result<Self, WuerstchenError> {
+        let tokenizer = Tokenizer::from_file(tokenizer).map_err(WuerstchenError::Tokenizer)?;
+        let vb = unsafe {
+            candle_nn::VarBuilder::from_mmaped_safetensors(&[weights], dtype, device)
+                .map_err(WuerstchenError::Load)?
+        };
+        let clip = ClipTextTransformer::new(vb.clone(), &config).map_err(WuerstchenError::Load)?;
+        let text_projection = match version {
+            WuerstchenVersion::V2 => None,
+            WuerstchenVersion::StableCascade => Some(
+                candle_nn::linear_no_bias(
+                    STABLE_CASCADE_CLIP_DIM,
+                    STABLE_CASCADE_CLIP_DIM,
+                    vb.pp("text_projection"),
+                )
+                .map_err(WuerstchenError::Load)?,
+            ),
+        };
+        Ok(Self {
+            tokenizer,
+            clip,
+            config,
+            text_projection,
+        })
+    }
+}
+
+/// The CLIP embeddings of a batch of prompts.
+struct TextEmbeddings {
+    hidden: Tensor,
+    /// The projected pooled output if the text encoder has a projection.
+    pooled: Option<Tensor>,
+}
+
+impl TextEmbeddings {
+    fn cat(embeddings: &[TextEmbeddings]) -> candle_core::Result<Self> {
+        let hidden = embeddings
+            .iter()
+            .map(|embeddings| embeddings.hidden.clone())
+            .collect::<Vec<_>>();
+        let pooled = embeddings
+            .iter()
+            .map(|embeddings| embeddings.pooled.clone())
+            .collect::<Option<Vec<_>>>();
+        Ok(Self {
+            hidden: Tensor::cat(&hidden, 0)?,
+            pooled: pooled.map(|pooled| Tensor::cat(&pooled, 0)).transpose()?,
+        })
+    }
+
+    fn repeat(&self, times: usize) -> candle_core::Result<Self> {
+        Ok(Self {
+            hidden: self.hidden.repeat((times, 1, 1))?,
+            pooled: self
+                .pooled
+                .as_ref()
+                .map(|pooled| pooled.repeat((times, 1, 1)))
+                .transpose()?,
+        })
+    }
+
+    fn pooled(&self) -> candle_core::Result<&Tensor> {
+        self.pooled
+            .as_ref()
+            .ok_or_else(|| candle_core::Error::Msg("the text encoder has no projection".into()))
+    }
+}
+
+/// The prior of the model version that was loaded.
+enum Prior {
+    Wuerstchen(Box<WPrior>),
+    QuantizedWuerstchen(Box<quantized::WPrior>),
+    StableCascade(Box<StableCascadeUNet>),
+}
+
+/// The decoder of the model version that was loaded.
+enum Decoder {
+    Wuerstchen(Box<WDiffNeXt>),
+    QuantizedWuerstchen(Box<quantized::WDiffNeXt>),
+    StableCascade(Box<StableCascadeUNet>),
+}
+
+impl Prior {
+    /// Predict the noise in the prior latents.
+    fn forward(
+        &self,
+        latents: &Tensor,
+        ratio: &Tensor,
+        text_embeddings: &TextEmbeddings,
+    ) -> candle_core::Result<Tensor> {
+        match self {
+            Self::Wuerstchen(prior) => prior.forward(latents, ratio, &text_embeddings.hidden),
+            Self::QuantizedWuerstchen(prior) => {
+                prior.forward(latents, ratio, &text_embeddings.hidden)
+            }
+            Self::StableCascade(prior) => {
+                // Text to image generation isn't conditioned on an image
+                let clip_img = Tensor::zeros(
+                    (latents.dim(0)?, 1, STABLE_CASCADE_CLIP_IMAGE_DIM),
+                    latents.dtype(),
+                    latents.device(),
+                )?;
+                prior.forward(
+                    latents,
+                    ratio,
+                    text_embeddings.pooled()?,
+                    Some(&text_embeddings.hidden),
+                    Some(&clip_img),
+                    None,
+                )
+            }
+        }
+    }
+}
+
+impl Decoder {
+    /// Predict the noise in the decoder latents.
+    fn forward(
+        &self,
+        latents: &Tensor,
+        ratio: &Tensor,
+        image_embeddings: &Tensor,
+        text_embeddings: &TextEmbeddings,
+    ) -> candle_core::Result<Tensor> {
+        match self {
+            Self::Wuerstchen(decoder) => decoder.forward(
+                latents,
+                ratio,
+                image_embeddings,
+                Some(&text_embeddings.hidden),
+            ),
+            Self::QuantizedWuerstchen(decoder) => {
+                decoder.forward(latents, ratio, image_embeddings, &text_embeddings.hidden)
+            }
+            Self::StableCascade(decoder) => decoder.forward(
+                latents,
+                ratio,
+                text_embeddings.pooled()?,
+                None,
+                None,
+                Some(image_embeddings),
+            ),
+        }
+    }
+}
+
 /// The Wuerstchen model.
 pub(crate) struct WuerstchenInner {
-    clip: ClipTextTransformer,
-    clip_config: stable_diffusion::clip::Config,
-    prior_clip: ClipTextTransformer,
-    prior_clip_config: stable_diffusion::clip::Config,
-    decoder: WDiffNeXt,
-    prior: WPrior,
-    vqgan: PaellaVQ,
-    prior_tokenizer: Tokenizer,
-    tokenizer: Tokenizer,
+    /// The text encoder of the decoder. Stable Cascade conditions both stages on the prior text encoder, so it doesn't have a separate decoder text encoder.
+    text_encoder: Option<TextEncoder>,
+    prior_text_encoder: TextEncoder,
+    prior: Stage<Prior>,
+    decoder: Stage<Decoder>,
+    vqgan: Stage<PaellaVQ>,
+    safety_checker: Option<Stage<SafetyChecker>>,
+    safety_checker_action: SafetyCheckerAction,
+    upscaler: Option<Stage<Upscaler>>,
     device: Device,
+    dtype: DType,
 }
 
 impl WuerstchenInner {
-    pub(crate) fn new(settings: WuerstcheModelSettings) -> candle_core::Result<Self> {
+    pub(crate) fn new(settings: WuerstcheModelSettings) -> Result<Self, WuerstchenError> {
         let WuerstcheModelSettings {
             use_flash_attn,
+            version,
+            dtype,
+            cpu_offload,
             decoder_weights,
             clip_weights,
             prior_clip_weights,
@@ -75,159 +466,368 @@ impl WuerstchenInner {
             vqgan_weights,
             tokenizer,
             prior_tokenizer,
+            loras,
+            safety_checker_weights,
+            safety_checker_action,
+            upscale,
+            upscaler_weights,
         } = settings;
 
-        let prior_tokenizer = Tokenizer::from_file(prior_tokenizer)
-            .map_err(|err| candle_core::Error::Msg(format!("Failed to load tokenizer: {err}")))?;
+        let device =
+            kalosm_common::accelerated_device_if_available().map_err(WuerstchenError::Device)?;
 
-        let tokenizer = Tokenizer::from_file(tokenizer)
-            .map_err(|err| candle_core::Error::Msg(format!("Failed to load tokenizer: {err}")))?;
-
-        let device = kalosm_common::accelerated_device_if_available()?;
-
-        let clip_config = stable_diffusion::clip::Config::wuerstchen();
-        let clip = stable_diffusion::build_clip_transformer(
-            &clip_config,
-            clip_weights,
-            &device,
-            DType::F32,
-        )?;
+        let text_encoder = match (tokenizer, clip_weights) {
+            (Some(tokenizer), Some(clip_weights)) => Some(TextEncoder::load(
+                tokenizer,
+                clip_weights,
+                stable_diffusion::clip::Config::wuerstchen(),
+                version,
+                dtype,
+                &device,
+            )?),
+            _ => None,
+        };
 
-        let prior_clip_config = stable_diffusion::clip::Config::wuerstchen_prior();
-        let prior_clip = stable_diffusion::build_clip_transformer(
-            &prior_clip_config,
+        // Stable Cascade uses the same CLIP-G text encoder as the Wuerstchen v2 prior
+        let prior_text_encoder = TextEncoder::load(
+            prior_tokenizer,
             prior_clip_weights,
+            stable_diffusion::clip::Config::wuerstchen_prior(),
+            version,
+            dtype,
             &device,
-            DType::F32,
         )?;
 
-        let decoder = {
-            let vb = unsafe {
-                candle_nn::VarBuilder::from_mmaped_safetensors(
-                    &[decoder_weights],
-                    DType::F32,
-                    &device,
-                )?
-            };
-            wuerstchen::diffnext::WDiffNeXt::new(
-                DECODER_CIN,
-                DECODER_CIN,
-                64,
-                1024,
-                1024,
-                2,
-                use_flash_attn,
-                vb,
-            )?
-        };
+        let prior_quantized = quantized::is_quantized(&prior_weights);
+        let decoder_quantized = quantized::is_quantized(&decoder_weights);
+        if prior_quantized || decoder_quantized {
+            if version == WuerstchenVersion::StableCascade {
+                return Err(WuerstchenError::Load(candle_core::Error::Msg(
+                    "Quantized weights are only supported for Würstchen v2".into(),
+                )));
+            }
+            if !loras.is_empty() {
+                return Err(WuerstchenError::Load(candle_core::Error::Msg(
+                    "LoRA adapters can't be merged into quantized weights".into(),
+                )));
+            }
+        }
 
-        let prior = {
-            let vb = unsafe {
-                candle_nn::VarBuilder::from_mmaped_safetensors(
-                    &[prior_weights],
-                    DType::F32,
-                    &device,
-                )?
-            };
-            wuerstchen::prior::WPrior::new(
-                /* c_in */ PRIOR_CIN,
-                /* c */ 1536,
-                /* c_cond */ 1280,
-                /* c_r */ 64,
-                /* depth */ 32,
-                /* nhead */ 24,
-                use_flash_attn,
-                vb,
-            )?
+        let mut loras = LoraLayers::load(&loras).map_err(WuerstchenError::Load)?;
+
+        // Offloaded stages keep their weights in host memory until they run
+        let weights_device = if cpu_offload {
+            Device::Cpu
+        } else {
+            device.clone()
         };
 
+        let decoder = match version {
+            WuerstchenVersion::V2 if decoder_quantized => {
+                Stage::new(cpu_offload, &device, move |device| {
+                    let vb = candle_transformers::quantized_var_builder::VarBuilder::from_gguf(
+                        &decoder_weights,
+                        device,
+                    )?;
+                    let decoder =
+                        quantized::WDiffNeXt::new(DECODER_CIN, DECODER_CIN, 64, 1024, 1024, 2, vb)?;
+                    Ok(Decoder::QuantizedWuerstchen(Box::new(decoder)))
+                })
+            }
+            WuerstchenVersion::V2 => {
+                let vb = loras
+                    .merge_into(&decoder_weights, dtype, &weights_device)
+                    .map_err(WuerstchenError::Load)?;
+                Stage::new(cpu_offload, &device, move |device| {
+                    let decoder = wuerstchen::diffnext::WDiffNeXt::new(
+                        DECODER_CIN,
+                        DECODER_CIN,
+                        64,
+                        1024,
+                        1024,
+                        2,
+                        use_flash_attn,
+                        vb.clone().set_device(device.clone()),
+                    )?;
+                    Ok(Decoder::Wuerstchen(Box::new(decoder)))
+                })
+            }
+            WuerstchenVersion::StableCascade => {
+                let vb = loras
+                    .merge_into(&decoder_weights, dtype, &weights_device)
+                    .map_err(WuerstchenError::Load)?;
+                // The full and lite weights have different configurations
+                let config = StableCascadeConfig::decoder_for_weights(&decoder_weights)
+                    .map_err(WuerstchenError::Load)?;
+                Stage::new(cpu_offload, &device, move |device| {
+                    let decoder = StableCascadeUNet::new(
+                        config.clone(),
+                        use_flash_attn,
+                        vb.clone().set_device(device.clone()),
+                    )?;
+                    Ok(Decoder::StableCascade(Box::new(decoder)))
+                })
+            }
+        }
+        .map_err(WuerstchenError::Load)?;
+
+        let prior = match version {
+            WuerstchenVersion::V2 if prior_quantized => {
+                Stage::new(cpu_offload, &device, move |device| {
+                    let vb = candle_transformers::quantized_var_builder::VarBuilder::from_gguf(
+                        &prior_weights,
+                        device,
+                    )?;
+                    let prior = quantized::WPrior::new(PRIOR_CIN, 1536, 1280, 64, 32, 24, vb)?;
+                    Ok(Prior::QuantizedWuerstchen(Box::new(prior)))
+                })
+            }
+            WuerstchenVersion::V2 => {
+                let vb = loras
+                    .merge_into(&prior_weights, dtype, &weights_device)
+                    .map_err(WuerstchenError::Load)?;
+                Stage::new(cpu_offload, &device, move |device| {
+                    let prior = wuerstchen::prior::WPrior::new(
+                        /* c_in */ PRIOR_CIN,
+                        /* c */ 1536,
+                        /* c_cond */ 1280,
+                        /* c_r */ 64,
+                        /* depth */ 32,
+                        /* nhead */ 24,
+                        use_flash_attn,
+                        vb.clone().set_device(device.clone()),
+                    )?;
+                    Ok(Prior::Wuerstchen(Box::new(prior)))
+                })
+            }
+            WuerstchenVersion::StableCascade => {
+                let vb = loras
+                    .merge_into(&prior_weights, dtype, &weights_device)
+                    .map_err(WuerstchenError::Load)?;
+                let config = StableCascadeConfig::prior_for_weights(&prior_weights)
+                    .map_err(WuerstchenError::Load)?;
+                Stage::new(cpu_offload, &device, move |device| {
+                    let prior = StableCascadeUNet::new(
+                        config.clone(),
+                        use_flash_attn,
+                        vb.clone().set_device(device.clone()),
+                    )?;
+                    Ok(Prior::StableCascade(Box::new(prior)))
+                })
+            }
+        }
+        .map_err(WuerstchenError::Load)?;
+        loras.warn_unmerged();
+
         let vqgan = {
             let vb = unsafe {
                 candle_nn::VarBuilder::from_mmaped_safetensors(
                     &[vqgan_weights],
-                    DType::F32,
-                    &device,
-                )?
+                    dtype,
+                    &weights_device,
+                )
+                .map_err(WuerstchenError::Load)?
             };
-            wuerstchen::paella_vq::PaellaVQ::new(vb)?
+            Stage::new(cpu_offload, &device, move |device| {
+                wuerstchen::paella_vq::PaellaVQ::new(vb.clone().set_device(device.clone()))
+            })
+            .map_err(WuerstchenError::Load)?
         };
 
+        let safety_checker = safety_checker_weights
+            .map(|safety_checker_weights| {
+                let vb = unsafe {
+                    candle_nn::VarBuilder::from_mmaped_safetensors(
+                        &[safety_checker_weights],
+                        dtype,
+                        &weights_device,
+                    )?
+                };
+                Stage::new(cpu_offload, &device, move |device| {
+                    SafetyChecker::new(vb.clone().set_device(device.clone()))
+                })
+            })
+            .transpose()
+            .map_err(WuerstchenError::Load)?;
+
+        let upscaler = upscaler_weights
+            .map(|upscaler_weights| {
+                let vb = upscaler::load_weights(&upscaler_weights, dtype, &weights_device)?;
+                Stage::new(cpu_offload, &device, move |device| {
+                    Upscaler::new(upscale, vb.clone().set_device(device.clone()))
+                })
+            })
+            .transpose()
+            .map_err(WuerstchenError::Load)?;
+
         Ok(Self {
-            clip,
-            clip_config,
-            prior_clip,
-            prior_clip_config,
-            decoder,
+            text_encoder,
+            prior_text_encoder,
             prior,
+            decoder,
             vqgan,
-            prior_tokenizer,
-            tokenizer,
+            safety_checker,
+            safety_checker_action,
+            upscaler,
             device,
+            dtype,
         })
     }
 
-    fn encode_prompt(
+    /// Encode a batch of prompts with CLIP. If there is an unconditional prompt, its embeddings are repeated for each prompt and added after the prompt embeddings.
+    ///
+    /// Prompts longer than the CLIP context are split into chunks that are encoded separately and concatenated. Every prompt in the batch is padded to the same number of chunks.
+    fn encode_prompts(
         &self,
-        prompt: &str,
+        prompts: &[String],
         uncond_prompt: Option<&str>,
-        tokenizer: &Tokenizer,
-        clip: &ClipTextTransformer,
-        clip_config: &stable_diffusion::clip::Config,
-    ) -> candle_core::Result<Tensor> {
-        let mut tokens = tokenizer
-            .encode(prompt, true)
-            .map_err(|err| candle_core::Error::Msg(format!("Failed to tokenize: {err}")))?
-            .get_ids()
-            .to_vec();
-        let pad_id = match &clip_config.pad_with {
-            Some(padding) => *tokenizer.get_vocab(true).get(padding.as_str()).unwrap(),
-            None => *tokenizer.get_vocab(true).get("<|endoftext|>").unwrap(),
-        };
-        let tokens_len = tokens.len();
-        while tokens.len() < clip_config.max_position_embeddings {
-            tokens.push(pad_id)
+        encoder: &TextEncoder,
+    ) -> Result<TextEmbeddings, WuerstchenError> {
+        let prompt_tokens = prompts
+            .iter()
+            .map(|prompt| tokenize_prompt(prompt, &encoder.tokenizer))
+            .collect::<Result<Vec<_>, _>>()?;
+        let uncond_tokens = uncond_prompt
+            .map(|prompt| tokenize_prompt(prompt, &encoder.tokenizer))
+            .transpose()?;
+
+        // Leave room for the start and end tokens in each chunk
+        let chunk_size = encoder.config.max_position_embeddings - 2;
+        let chunks = prompt_tokens
+            .iter()
+            .chain(&uncond_tokens)
+            .map(|(tokens, _)| tokens.len().div_ceil(chunk_size))
+            .max()
+            .unwrap_or_default()
+            .max(1);
+        if chunks > 1 {
+            tracing::debug!("encoding prompts in {chunks} chunks of {chunk_size} tokens");
         }
-        let tokens = Tensor::new(tokens.as_slice(), &self.device)?.unsqueeze(0)?;
 
-        let text_embeddings = clip.forward_with_mask(&tokens, tokens_len - 1)?;
-        match uncond_prompt {
+        // CLIP masks every prompt in a batch after the same position, so prompts with different lengths are encoded one at a time
+        let text_embeddings = prompt_tokens
+            .iter()
+            .map(|(tokens, weights)| self.encode_tokens(tokens, weights, chunks, encoder))
+            .collect::<Result<Vec<_>, _>>()?;
+        let text_embeddings = TextEmbeddings::cat(&text_embeddings)?;
+        match uncond_tokens {
             None => Ok(text_embeddings),
-            Some(uncond_prompt) => {
-                let mut uncond_tokens = tokenizer
-                    .encode(uncond_prompt, true)
-                    .map_err(|err| candle_core::Error::Msg(format!("Failed to tokenize: {err}")))?
-                    .get_ids()
-                    .to_vec();
-                let uncond_tokens_len = uncond_tokens.len();
-                while uncond_tokens.len() < clip_config.max_position_embeddings {
-                    uncond_tokens.push(pad_id)
-                }
-                let uncond_tokens =
-                    Tensor::new(uncond_tokens.as_slice(), &self.device)?.unsqueeze(0)?;
+            Some((tokens, weights)) => {
+                let uncond_embeddings = self
+                    .encode_tokens(&tokens, &weights, chunks, encoder)?
+                    .repeat(prompts.len())?;
+                Ok(TextEmbeddings::cat(&[text_embeddings, uncond_embeddings])?)
+            }
+        }
+    }
 
-                let uncond_embeddings =
-                    clip.forward_with_mask(&uncond_tokens, uncond_tokens_len - 1)?;
-                let text_embeddings = Tensor::cat(&[text_embeddings, uncond_embeddings], 0)?;
-                Ok(text_embeddings)
+    /// Encode the tokens of a prompt in the given number of chunks and concatenate the embeddings of the chunks.
+    fn encode_tokens(
+        &self,
+        tokens: &[u32],
+        weights: &[f32],
+        chunks: usize,
+        encoder: &TextEncoder,
+    ) -> Result<TextEmbeddings, WuerstchenError> {
+        let clip_config = &encoder.config;
+        let token_id = |token: &str| {
+            encoder.tokenizer.token_to_id(token).ok_or_else(|| {
+                WuerstchenError::Tokenizer(format!("the tokenizer has no {token} token").into())
+            })
+        };
+        let start_id = token_id("<|startoftext|>")?;
+        let end_id = token_id("<|endoftext|>")?;
+        let pad_id = match &clip_config.pad_with {
+            Some(padding) => token_id(padding)?,
+            None => end_id,
+        };
+
+        let chunk_size = clip_config.max_position_embeddings - 2;
+        let mut embeddings = Vec::with_capacity(chunks);
+        let mut pooled = None;
+        for chunk in 0..chunks {
+            let start = (chunk * chunk_size).min(tokens.len());
+            let end = ((chunk + 1) * chunk_size).min(tokens.len());
+
+            let mut chunk_tokens = Vec::with_capacity(clip_config.max_position_embeddings);
+            chunk_tokens.push(start_id);
+            chunk_tokens.extend_from_slice(&tokens[start..end]);
+            chunk_tokens.push(end_id);
+            let mut chunk_weights = Vec::with_capacity(clip_config.max_position_embeddings);
+            chunk_weights.push(1.0);
+            chunk_weights.extend_from_slice(&weights[start..end]);
+            chunk_weights.push(1.0);
+
+            let tokens_len = chunk_tokens.len();
+            while chunk_tokens.len() < clip_config.max_position_embeddings {
+                chunk_tokens.push(pad_id);
+                chunk_weights.push(1.0);
             }
+            let chunk_tokens = Tensor::new(chunk_tokens.as_slice(), &self.device)?.unsqueeze(0)?;
+
+            let chunk_embeddings = match &encoder.text_projection {
+                None => encoder
+                    .clip
+                    .forward_with_mask(&chunk_tokens, tokens_len - 1)?,
+                Some(text_projection) => {
+                    let (output, hidden) = encoder.clip.forward_until_encoder_layer(
+                        &chunk_tokens,
+                        tokens_len - 1,
+                        -1,
+                    )?;
+                    // The pooled output is the output at the end token of the first chunk
+                    if pooled.is_none() {
+                        pooled = Some(
+                            output
+                                .i((.., tokens_len - 1..tokens_len, ..))?
+                                .apply(text_projection)?,
+                        );
+                    }
+                    hidden
+                }
+            };
+            embeddings.push(self.apply_token_weights(chunk_embeddings, &chunk_weights)?);
         }
+
+        Ok(TextEmbeddings {
+            hidden: Tensor::cat(&embeddings, 1)?,
+            pooled,
+        })
+    }
+
+    /// Scale the embedding of each token by its weight, then restore the original mean so the prompt as a whole keeps the same strength.
+    fn apply_token_weights(
+        &self,
+        embeddings: Tensor,
+        weights: &[f32],
+    ) -> Result<Tensor, WuerstchenError> {
+        if weights.iter().all(|weight| *weight == 1.0) {
+            return Ok(embeddings);
+        }
+
+        let weights = Tensor::new(weights, &self.device)?
+            .reshape((1, (), 1))?
+            .to_dtype(embeddings.dtype())?;
+        let original_mean = embeddings.mean_all()?;
+        let weighted = embeddings.broadcast_mul(&weights)?;
+        let weighted_mean = weighted.mean_all()?;
+        Ok(weighted.broadcast_mul(&(original_mean / weighted_mean)?)?)
     }
 
     fn image_embeddings(
         &self,
         settings: &WuerstchenInferenceSettings,
+        steps: &mut DiffusionSteps,
         b_size: usize,
-    ) -> candle_core::Result<Tensor> {
+    ) -> Result<Tensor, WuerstchenError> {
         let height = settings.height;
         let width = settings.width;
 
         let prior_text_embeddings = {
-            self.encode_prompt(
-                &settings.prompt,
+            self.encode_prompts(
+                &settings.prompts,
                 Some(&settings.uncond_prompt),
-                &self.prior_tokenizer,
-                &self.prior_clip,
-                &self.prior_clip_config,
+                &self.prior_text_encoder,
             )?
         };
 
@@ -235,94 +835,194 @@ impl WuerstchenInner {
             // https://huggingface.co/warp-ai/wuerstchen-prior/blob/main/prior/config.json
             let latent_height = (height as f64 / RESOLUTION_MULTIPLE).ceil() as usize;
             let latent_width = (width as f64 / RESOLUTION_MULTIPLE).ceil() as usize;
-            let mut latents = Tensor::randn(
-                0f32,
-                1f32,
+            let mut latents = steps.noise(
                 (b_size, PRIOR_CIN, latent_height, latent_width),
+                self.dtype,
                 &self.device,
             )?;
 
-            let prior_scheduler =
-                wuerstchen::ddpm::DDPMWScheduler::new(settings.prior_steps, Default::default())?;
+            // An offloaded prior is moved back out of the device when it goes out of scope
+            let prior = self.prior.load(&self.device)?;
+            let mut prior_scheduler =
+                SchedulerState::new(settings.scheduler, settings.prior_steps)?;
             let timesteps = prior_scheduler.timesteps();
-            let timesteps = &timesteps[..timesteps.len() - 1];
-            for &t in timesteps {
+            steps.start_stage(DiffusionStage::Prior, timesteps.len());
+            for t in timesteps {
                 let latent_model_input = Tensor::cat(&[&latents, &latents], 0)?;
-                let ratio = (Tensor::ones(2, DType::F32, &self.device)? * t)?;
+                let ratio = (Tensor::ones(2 * b_size, self.dtype, &self.device)? * t)?;
                 let noise_pred =
-                    self.prior
-                        .forward(&latent_model_input, &ratio, &prior_text_embeddings)?;
+                    prior.forward(&latent_model_input, &ratio, &prior_text_embeddings)?;
                 let noise_pred = noise_pred.chunk(2, 0)?;
                 let (noise_pred_text, noise_pred_uncond) = (&noise_pred[0], &noise_pred[1]);
                 let noise_pred = (noise_pred_uncond
                     + ((noise_pred_text - noise_pred_uncond)? * settings.prior_guidance_scale)?)?;
-                latents = prior_scheduler.step(&noise_pred, t, &latents)?;
+                latents = prior_scheduler.step(&noise_pred, t, &latents, steps.rng.as_mut())?;
                 tracing::trace!(
                     "generating embeddings t: {}, noise_pred: {:?}",
                     t,
                     noise_pred
                 );
+                steps.step()?;
+            }
+            match *prior {
+                Prior::Wuerstchen(_) | Prior::QuantizedWuerstchen(_) => {
+                    Ok(((latents * 42.)? - 1.)?)
+                }
+                // The Stable Cascade decoder takes the prior latents as is
+                Prior::StableCascade(_) => Ok(latents),
             }
-            (latents * 42.)? - 1.
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn generate_image(
         &self,
-        text_embeddings: &Tensor,
+        decoder: &Decoder,
+        text_embeddings: &TextEmbeddings,
         image_embeddings: &Tensor,
         settings: &WuerstchenInferenceSettings,
+        steps: &mut DiffusionSteps,
         b_size: usize,
-    ) -> candle_core::Result<ImageBuffer<image::Rgb<u8>, Vec<u8>>> {
+        mut on_preview: impl FnMut(&DiffusionSteps, Vec<(RgbImage, bool)>),
+    ) -> Result<Tensor, WuerstchenError> {
         // https://huggingface.co/warp-ai/wuerstchen/blob/main/model_index.json
         let latent_height = (image_embeddings.dim(2)? as f64 * LATENT_DIM_SCALE) as usize;
         let latent_width = (image_embeddings.dim(3)? as f64 * LATENT_DIM_SCALE) as usize;
 
-        let mut latents = Tensor::randn(
-            0f32,
-            1f32,
+        let mut latents = steps.noise(
             (b_size, DECODER_CIN, latent_height, latent_width),
+            self.dtype,
             &self.device,
         )?;
 
-        let scheduler =
-            wuerstchen::ddpm::DDPMWScheduler::new(settings.denoiser_steps, Default::default())?;
+        // With guidance, the decoder runs on the prompt and the negative prompt in one batch. The negative prompt is paired with empty image embeddings
+        let guidance = settings.decoder_guidance_scale > 1.;
+        let image_embeddings = if guidance {
+            Tensor::cat(&[image_embeddings, &image_embeddings.zeros_like()?], 0)?
+        } else {
+            image_embeddings.clone()
+        };
+        let batch = if guidance { 2 * b_size } else { b_size };
+
+        let mut scheduler = SchedulerState::new(settings.scheduler, settings.denoiser_steps)?;
         let timesteps = scheduler.timesteps();
-        let timesteps = &timesteps[..timesteps.len() - 1];
-        for &t in timesteps {
-            let ratio = (Tensor::ones(1, DType::F32, &self.device)? * t)?;
-            let noise_pred =
-                self.decoder
-                    .forward(&latents, &ratio, image_embeddings, Some(text_embeddings))?;
-            latents = scheduler.step(&noise_pred, t, &latents)?;
-            tracing::trace!("t: {}, noise_pred: {:?}", t, noise_pred)
+        steps.start_stage(DiffusionStage::Denoiser, timesteps.len());
+        for (index, &t) in timesteps.iter().enumerate() {
+            let ratio = (Tensor::ones(batch, self.dtype, &self.device)? * t)?;
+            let latent_model_input = if guidance {
+                Tensor::cat(&[&latents, &latents], 0)?
+            } else {
+                latents.clone()
+            };
+            let noise_pred = decoder.forward(
+                &latent_model_input,
+                &ratio,
+                &image_embeddings,
+                text_embeddings,
+            )?;
+            let noise_pred = if guidance {
+                let noise_pred = noise_pred.chunk(2, 0)?;
+                let (noise_pred_text, noise_pred_uncond) = (&noise_pred[0], &noise_pred[1]);
+                (noise_pred_uncond
+                    + ((noise_pred_text - noise_pred_uncond)? * settings.decoder_guidance_scale)?)?
+            } else {
+                noise_pred
+            };
+            latents = scheduler.step(&noise_pred, t, &latents, steps.rng.as_mut())?;
+            tracing::trace!("t: {}, noise_pred: {:?}", t, noise_pred);
+            steps.step()?;
+
+            let step = index + 1;
+            if let Some(every) = settings.preview_every_n_steps {
+                if step < timesteps.len() && step.is_multiple_of(every) {
+                    let previews = self.check_safety(self.decode(&latents, false)?)?;
+                    steps.decoded();
+                    on_preview(steps, previews);
+                }
+            }
         }
-        let img_tensor = self.vqgan.decode(&(&latents * 0.3764)?)?;
+        Ok(latents)
+    }
+
+    /// Decode latents from [`Image::latents`](crate::Image::latents) into images.
+    pub(crate) fn decode_latents(&self, latents: Tensor) -> Result<Vec<RgbImage>, WuerstchenError> {
+        let latents = match latents.rank() {
+            3 => latents.unsqueeze(0)?,
+            _ => latents,
+        };
+        let latents = latents.to_device(&self.device)?.to_dtype(self.dtype)?;
+        Ok(self
+            .check_safety(self.decode(&latents, true)?)?
+            .into_iter()
+            .map(|(image, _)| image)
+            .collect())
+    }
+
+    /// Run the safety checker on decoded images and apply the safety checker action to the flagged images. Returns each image with whether it was flagged.
+    fn check_safety(&self, images: Vec<RgbImage>) -> candle_core::Result<Vec<(RgbImage, bool)>> {
+        let Some(safety_checker) = &self.safety_checker else {
+            return Ok(images.into_iter().map(|image| (image, false)).collect());
+        };
+        let nsfw = safety_checker
+            .load(&self.device)?
+            .is_nsfw(&images, self.dtype)?;
+        Ok(images
+            .into_iter()
+            .zip(nsfw)
+            .map(|(image, nsfw)| match nsfw {
+                true => (self.safety_checker_action.apply(image), true),
+                false => (image, false),
+            })
+            .collect())
+    }
+
+    /// Decode a batch of denoised latents into images with the VQGAN. If `upscale` is set and the upscaler is enabled, the images are upscaled on the device before they are copied to the host.
+    fn decode(&self, latents: &Tensor, upscale: bool) -> candle_core::Result<Vec<RgbImage>> {
+        // The VQGAN is much smaller than the decoder, so it is moved to the device next to the decoder to decode previews
+        let vqgan = self.vqgan.load(&self.device)?;
+        let img_tensor = vqgan.decode(&(latents * 0.3764)?)?;
+        drop(vqgan);
+        let img_tensor = match (&self.upscaler, upscale) {
+            (Some(upscaler), true) => upscaler.load(&self.device)?.upscale(&img_tensor)?,
+            _ => img_tensor,
+        };
+        let img_tensor = img_tensor.to_dtype(DType::F32)?;
         // TODO: Add the clamping between 0 and 1.
-        let img_tensor = (img_tensor * 255.)?.to_dtype(DType::U8)?.i(0)?;
-        let (channel, height, width) = img_tensor.dims3()?;
-        if channel != 3 {
-            candle_core::bail!("image must have 3 channels");
-        }
-        let img = img_tensor.permute((1, 2, 0))?.flatten_all()?;
-        let pixels = img.to_vec1::<u8>()?;
-        ImageBuffer::from_raw(width as u32, height as u32, pixels).ok_or(candle_core::Error::Msg(
-            format!("error creating image {img_tensor:?}"),
-        ))
+        let img_tensor = (img_tensor * 255.)?.to_dtype(DType::U8)?;
+        (0..img_tensor.dim(0)?)
+            .map(|index| {
+                let img_tensor = img_tensor.i(index)?;
+                let (channel, height, width) = img_tensor.dims3()?;
+                if channel != 3 {
+                    candle_core::bail!("image must have 3 channels");
+                }
+                let img = img_tensor.permute((1, 2, 0))?.flatten_all()?;
+                let pixels = img.to_vec1::<u8>()?;
+                ImageBuffer::from_raw(width as u32, height as u32, pixels).ok_or(
+                    candle_core::Error::Msg(format!("error creating image {img_tensor:?}")),
+                )
+            })
+            .collect()
     }
 
     /// Run inference with the given settings.
-    pub fn run(&self, settings: WuerstchenInferenceSettings, mut result: UnboundedSender<Image>) {
-        // If the channel is closed, we know that the result will never be read so we can stop early.
+    pub fn run(
+        &self,
+        mut settings: WuerstchenInferenceSettings,
+        mut result: ImageSender,
+        queued: Timer,
+        aborted: &AtomicBool,
+    ) {
+        // If the channel is closed or the generation was aborted, we know that the result will never be read so we can stop early.
         macro_rules! return_if_closed {
             () => {
-                if result.is_closed() {
+                if result.is_closed() || aborted.load(Ordering::SeqCst) {
                     return;
                 }
             };
         }
 
-        let start_time = Instant::now();
+        let mut steps = DiffusionSteps::new(&mut settings, queued, aborted);
         let height = settings.height;
         let width = settings.width;
 
@@ -333,41 +1033,62 @@ impl WuerstchenInner {
             println!("Warning: Würstchen was trained on image resolutions between 1024x1024 & 1536x1536. {}x{} is above the maximum resolution. Image quality may be poor.", height, width);
         }
         let chech_dims = if height % 128 != 0 || width % 128 != 0 {
-            Err(candle_core::Error::Msg(
-                "Image resolution must be a multiple of 128".to_string(),
-            ))
+            Err(WuerstchenError::InvalidDimensions { width, height })
         } else {
             Ok(())
         };
 
-        let b_size = 1;
+        let b_size = settings.prompts.len();
+        if b_size == 0 {
+            return;
+        }
 
+        let decoder_guidance = settings.decoder_guidance_scale > 1.;
         let text_embeddings = {
-            self.encode_prompt(
-                &settings.prompt,
-                None,
-                &self.tokenizer,
-                &self.clip,
-                &self.clip_config,
+            self.encode_prompts(
+                &settings.prompts,
+                decoder_guidance.then_some(settings.decoder_uncond_prompt.as_str()),
+                self.text_encoder
+                    .as_ref()
+                    .unwrap_or(&self.prior_text_encoder),
             )
         };
 
         return_if_closed!();
 
-        let image_embeddings = self.image_embeddings(&settings, b_size);
-        if chech_dims.is_err() || text_embeddings.is_err() || image_embeddings.is_err() {
+        let image_embeddings = self.image_embeddings(&settings, &mut steps, b_size);
+
+        return_if_closed!();
+
+        // The prior is already offloaded at this point, so only the decoder is on the device while it runs
+        let decoder = self
+            .decoder
+            .load(&self.device)
+            .map_err(WuerstchenError::from);
+
+        if chech_dims.is_err()
+            || text_embeddings.is_err()
+            || image_embeddings.is_err()
+            || decoder.is_err()
+        {
             let err = Err(chech_dims
                 .err()
-                .or_else(|| text_embeddings.err().or_else(|| image_embeddings.err()))
+                .or_else(|| text_embeddings.err())
+                .or_else(|| image_embeddings.err())
+                .or_else(|| decoder.err())
                 .unwrap());
             let image = Image {
                 sample_num: 0,
-                elapsed_time: start_time.elapsed(),
-                remaining_time: Duration::from_secs(0),
-                progress: 1.,
+                prompt_index: 0,
+                progress: DiffusionProgress {
+                    remaining_time: Duration::ZERO,
+                    progress: 1.,
+                    ..steps.progress()
+                },
+                preview: false,
                 result: err,
             };
-            if let Err(err) = result.start_send(image) {
+            if let Err(err) = result.send(image, aborted) {
                 tracing::error!("Error sending segment: {err}");
             }
             return;
@@ -375,38 +1096,164 @@ impl WuerstchenInner {
 
         let text_embeddings = text_embeddings.unwrap();
         let image_embeddings = image_embeddings.unwrap();
-
-        return_if_closed!();
+        let decoder = decoder.unwrap();
 
         for index in 1..=settings.num_samples {
-            let iter_start_time = Instant::now();
-            let remaining_samples = (settings.num_samples - index) as u32;
-            let progress = (index / settings.num_samples) as f32;
-
             tracing::trace!("Generating image {}/{}", index, settings.num_samples);
 
-            let image = self
-                .generate_image(&text_embeddings, &image_embeddings, &settings, b_size)
-                .map(|val| DiffusionResult {
-                    image: val,
-                    height,
-                    width,
-                });
+            let latents = self.generate_image(
+                &decoder,
+                &text_embeddings,
+                &image_embeddings,
+                &settings,
+                &mut steps,
+                b_size,
+                |steps, previews| {
+                    for (prompt_index, (preview, nsfw)) in previews.into_iter().enumerate() {
+                        let preview = Image {
+                            sample_num: index,
+                            prompt_index,
+                            progress: steps.progress(),
+                            preview: true,
+                            result: Ok(DiffusionResult {
+                                image: preview,
+                                latents: None,
+                                height,
+                                width,
+                                nsfw,
+                            }),
+                        };
+                        if let Err(err) = result.send(preview, aborted) {
+                            tracing::error!("Error sending preview: {err}");
+                        }
+                    }
+                },
+            );
 
-            let remaining_time = remaining_samples * iter_start_time.elapsed();
+            return_if_closed!();
 
-            let image = Image {
-                sample_num: index,
-                elapsed_time: start_time.elapsed(),
-                remaining_time,
-                progress,
-                result: image,
+            steps.start_stage(DiffusionStage::Decode, 1);
+            let images = latents.and_then(|latents| {
+                let images = self.check_safety(self.decode(&latents, true)?)?;
+                steps.decoded();
+                images
+                    .into_iter()
+                    .enumerate()
+                    .map(|(prompt_index, (image, nsfw))| {
+                        // Latents are moved to the cpu so they don't hold onto memory on the device. The latents of hidden images would let the image be decoded again, so they are not returned
+                        let hidden = nsfw && self.safety_checker_action.hides_images();
+                        let latents = match settings.return_latents && !hidden {
+                            true => Some(
+                                latents
+                                    .i(prompt_index..prompt_index + 1)?
+                                    .to_device(&Device::Cpu)?,
+                            ),
+                            false => None,
+                        };
+                        // The upscaler changes the size of the final images
+                        Ok(DiffusionResult {
+                            height: image.height() as usize,
+                            width: image.width() as usize,
+                            image,
+                            latents,
+                            nsfw,
+                        })
+                    })
+                    .collect::<Result<Vec<_>, WuerstchenError>>()
+            });
+            let images = match images {
+                Ok(images) => images.into_iter().map(Ok).collect(),
+                Err(err) => vec![Err(err)],
             };
+            for (prompt_index, image) in images.into_iter().enumerate() {
+                let image = Image {
+                    sample_num: index,
+                    prompt_index,
+                    progress: steps.progress(),
+                    preview: false,
+                    result: image,
+                };
 
-            if let Err(err) = result.start_send(image) {
-                tracing::error!("Error sending segment: {err}");
-                break;
+                if let Err(err) = result.send(image, aborted) {
+                    tracing::error!("Error sending segment: {err}");
+                    return;
+                }
             }
         }
     }
 }
+
+/// Tokenize a prompt without the start and end tokens and find the attention weight of each token.
+fn tokenize_prompt(
+    prompt: &str,
+    tokenizer: &Tokenizer,
+) -> Result<(Vec<u32>, Vec<f32>), WuerstchenError> {
+    let segments = parse_prompt_weights(prompt);
+    let text: String = segments
+        .iter()
+        .map(|segment| segment.text.as_str())
+        .collect();
+    let encoding = tokenizer
+        .encode(text.as_str(), false)
+        .map_err(WuerstchenError::Tokenizer)?;
+    Ok((
+        encoding.get_ids().to_vec(),
+        token_weights(&segments, &encoding),
+    ))
+}
+
+#[cfg(test)]
+#[test]
+fn counts_the_decodes_of_previews() {
+    let aborted = AtomicBool::new(false);
+    for (every, previews) in [(0, 0), (1, 11), (5, 2), (6, 1), (11, 1), (12, 0), (20, 0)] {
+        let mut settings = WuerstchenInferenceSettings::new("a cat")
+            .with_denoiser_steps(12)
+            .with_sample_count(2)
+            .with_preview_every_n_steps(every);
+        let steps = DiffusionSteps::new(&mut settings, Timer::start(), &aborted);
+        assert_eq!(steps.denoiser.remaining, 24);
+        // Each sample decodes its previews and the final image. There is no preview after the last step
+        assert_eq!(steps.decode.remaining, 2 * (1 + previews), "every {every}");
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn estimates_the_remaining_time_from_the_step_times() {
+    fn assert_duration(duration: Duration, millis: f64) {
+        assert!(
+            (duration.as_secs_f64() * 1000. - millis).abs() < 1e-3,
+            "{duration:?} != {millis}ms"
+        );
+    }
+
+    let aborted = AtomicBool::new(false);
+    let mut settings = WuerstchenInferenceSettings::new("a cat")
+        .with_prior_steps(2)
+        .with_denoiser_steps(12)
+        .with_sample_count(2)
+        .with_preview_every_n_steps(5);
+    let mut steps = DiffusionSteps::new(&mut settings, Timer::start(), &aborted);
+    assert_eq!(steps.prior.remaining, 2);
+    assert_eq!(steps.decode.remaining, 6);
+    // Nothing can be estimated before the first step finishes
+    assert_eq!(steps.remaining_time(), Duration::ZERO);
+
+    // Stages that haven't run yet are estimated from the first stage that has
+    steps.prior.finish_step(Duration::from_millis(100));
+    assert_duration(steps.remaining_time(), 100. * (1. + 24. + 6.));
+
+    // Each step moves the average towards the new step time
+    steps.prior.finish_step(Duration::from_millis(200));
+    assert_duration(steps.prior.average.unwrap(), 130.);
+    assert_eq!(steps.prior.remaining, 0);
+    assert_duration(steps.remaining_time(), 130. * (24. + 6.));
+
+    steps.denoiser.finish_step(Duration::from_millis(50));
+    assert_duration(steps.remaining_time(), 50. * 23. + 130. * 6.);
+
+    // The remaining steps never go below zero
+    steps.prior.finish_step(Duration::from_millis(100));
+    assert_eq!(steps.prior.remaining, 0);
+}
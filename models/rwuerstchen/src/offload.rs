@@ -0,0 +1,52 @@
+use std::ops::Deref;
+
+use candle_core::{Device, Result};
+
+/// Builds a stage of the pipeline on a device.
+type StageLoader<M> = Box<dyn Fn(&Device) -> Result<M> + Send>;
+
+/// A stage of the pipeline. An offloaded stage keeps its weights in host memory and is only moved to the device while it runs.
+pub(crate) enum Stage<M> {
+    Loaded(M),
+    Offloaded(StageLoader<M>),
+}
+
+impl<M> Stage<M> {
+    /// Create a stage from a loader that builds the stage on a device. If the stage isn't offloaded, it is built on the device right away.
+    pub(crate) fn new(
+        offload: bool,
+        device: &Device,
+        load: impl Fn(&Device) -> Result<M> + Send + 'static,
+    ) -> Result<Self> {
+        if offload {
+            Ok(Self::Offloaded(Box::new(load)))
+        } else {
+            load(device).map(Self::Loaded)
+        }
+    }
+
+    /// Get the stage on the device. An offloaded stage is removed from the device again when the returned stage is dropped.
+    pub(crate) fn load(&self, device: &Device) -> Result<LoadedStage<'_, M>> {
+        match self {
+            Self::Loaded(stage) => Ok(LoadedStage::Borrowed(stage)),
+            Self::Offloaded(load) => load(device).map(LoadedStage::Owned),
+        }
+    }
+}
+
+/// A stage of the pipeline that is on the device.
+pub(crate) enum LoadedStage<'a, M> {
+    Borrowed(&'a M),
+    Owned(M),
+}
+
+impl<M> Deref for LoadedStage<'_, M> {
+    type Target = M;
+
+    fn deref(&self) -> &M {
+        match self {
+            Self::Borrowed(stage) => stage,
+            Self::Owned(stage) => stage,
+        }
+    }
+}
@@ -0,0 +1,224 @@
+use tokenizers::Encoding;
+
+/// The weight `(text)` multiplies the attention of the text by and `[text]` divides it by.
+const EMPHASIS_MULTIPLIER: f32 = 1.1;
+
+/// A piece of a prompt with the attention weight it was given.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct WeightedText {
+    pub(crate) text: String,
+    pub(crate) weight: f32,
+}
+
+/// Parse the attention weights in a prompt.
+///
+/// `(text:1.3)` sets the weight of the text to 1.3, `(text)` multiplies the weight by 1.1 and `[text]` divides the weight by 1.1. Groups can be nested and the weights are multiplied together. Brackets can be escaped with a backslash to include them in the prompt.
+pub(crate) fn parse_prompt_weights(prompt: &str) -> Vec<WeightedText> {
+    let mut result: Vec<WeightedText> = Vec::new();
+    let mut current = String::new();
+    // The bracket and the index of the first segment of every group that is still open
+    let mut open_groups: Vec<(char, usize)> = Vec::new();
+
+    fn flush(current: &mut String, result: &mut Vec<WeightedText>) {
+        if !current.is_empty() {
+            result.push(WeightedText {
+                text: std::mem::take(current),
+                weight: 1.0,
+            });
+        }
+    }
+
+    fn multiply(result: &mut [WeightedText], start: usize, multiplier: f32) {
+        for segment in &mut result[start..] {
+            segment.weight *= multiplier;
+        }
+    }
+
+    let mut chars = prompt.char_indices().peekable();
+    while let Some((index, char)) = chars.next() {
+        match char {
+            '\\' => match chars.next() {
+                Some((_, escaped)) => current.push(escaped),
+                None => current.push('\\'),
+            },
+            '(' | '[' => {
+                flush(&mut current, &mut result);
+                open_groups.push((char, result.len()));
+            }
+            ':' if matches!(open_groups.last(), Some(('(', _))) => {
+                let rest = &prompt[index + 1..];
+                let weight = rest
+                    .find(')')
+                    .and_then(|end| Some((end, rest[..end].trim().parse::<f32>().ok()?)));
+                match weight {
+                    Some((end, weight)) => {
+                        flush(&mut current, &mut result);
+                        let (_, start) = open_groups.pop().unwrap();
+                        multiply(&mut result, start, weight);
+                        // Skip the weight and the closing bracket
+                        let end = index + 1 + end;
+                        while chars.next_if(|(index, _)| *index <= end).is_some() {}
+                    }
+                    None => current.push(char),
+                }
+            }
+            ')' if matches!(open_groups.last(), Some(('(', _))) => {
+                flush(&mut current, &mut result);
+                let (_, start) = open_groups.pop().unwrap();
+                multiply(&mut result, start, EMPHASIS_MULTIPLIER);
+            }
+            ']' if matches!(open_groups.last(), Some(('[', _))) => {
+                flush(&mut current, &mut result);
+                let (_, start) = open_groups.pop().unwrap();
+                multiply(&mut result, start, 1.0 / EMPHASIS_MULTIPLIER);
+            }
+            _ => current.push(char),
+        }
+    }
+    flush(&mut current, &mut result);
+
+    // Groups that are never closed still apply to the rest of the prompt
+    for (bracket, start) in open_groups.into_iter().rev() {
+        let multiplier = match bracket {
+            '(' => EMPHASIS_MULTIPLIER,
+            _ => 1.0 / EMPHASIS_MULTIPLIER,
+        };
+        multiply(&mut result, start, multiplier);
+    }
+
+    // Merge neighboring segments with the same weight
+    let mut merged: Vec<WeightedText> = Vec::with_capacity(result.len());
+    for segment in result {
+        match merged.last_mut() {
+            Some(last) if last.weight == segment.weight => last.text.push_str(&segment.text),
+            _ => merged.push(segment),
+        }
+    }
+    merged
+}
+
+/// Find the weight of every token in the encoding of the text of the weighted segments. Special tokens always have a weight of 1.
+pub(crate) fn token_weights(segments: &[WeightedText], encoding: &Encoding) -> Vec<f32> {
+    let mut segment_ends = Vec::with_capacity(segments.len());
+    let mut end = 0;
+    for segment in segments {
+        end += segment.text.len();
+        segment_ends.push((end, segment.weight));
+    }
+
+    encoding
+        .get_offsets()
+        .iter()
+        .zip(encoding.get_special_tokens_mask())
+        .map(|(&(start, _), &special)| {
+            if special == 1 {
+                return 1.0;
+            }
+            segment_ends
+                .iter()
+                .find(|(end, _)| start < *end)
+                .map_or(1.0, |(_, weight)| *weight)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+#[test]
+fn parses_prompt_weights() {
+    fn parse(prompt: &str) -> Vec<(String, f32)> {
+        parse_prompt_weights(prompt)
+            .into_iter()
+            .map(|segment| (segment.text, segment.weight))
+            .collect()
+    }
+    fn assert_weights(prompt: &str, expected: &[(&str, f32)]) {
+        let parsed = parse(prompt);
+        assert_eq!(parsed.len(), expected.len(), "{prompt}: {parsed:?}");
+        for ((text, weight), (expected_text, expected_weight)) in parsed.iter().zip(expected) {
+            assert_eq!(text, expected_text, "{prompt}: {parsed:?}");
+            assert!(
+                (weight - expected_weight).abs() < 1e-5,
+                "{prompt}: {parsed:?}"
+            );
+        }
+    }
+    let up = EMPHASIS_MULTIPLIER;
+    let down = 1.0 / EMPHASIS_MULTIPLIER;
+
+    assert_weights("a cat", &[("a cat", 1.0)]);
+    assert_weights(
+        "a (cat:1.3) on a mat",
+        &[("a ", 1.0), ("cat", 1.3), (" on a mat", 1.0)],
+    );
+    assert_weights("a (cat: 0.5 )", &[("a ", 1.0), ("cat", 0.5)]);
+    assert_weights("(cat) [dog]", &[("cat", up), (" ", 1.0), ("dog", down)]);
+    // Nested groups multiply their weights
+    assert_weights("((cat) dog:1.5)", &[("cat", up * 1.5), (" dog", 1.5)]);
+    assert_weights("[[cat]]", &[("cat", down * down)]);
+    // Escaped brackets are part of the text
+    assert_weights(r"\(cat\) \[dog\]", &[("(cat) [dog]", 1.0)]);
+    assert_weights(r"(cat\))", &[("cat)", up)]);
+    // Unclosed groups apply to the rest of the prompt
+    assert_weights("a (cat", &[("a ", 1.0), ("cat", up)]);
+    assert_weights("a ((cat", &[("a ", 1.0), ("cat", up * up)]);
+    // Closing brackets that don't match the open group are part of the text
+    assert_weights("a cat] (dog]", &[("a cat] ", 1.0), ("dog]", up)]);
+    // A colon without a number is part of the text
+    assert_weights("(cat: dog)", &[("cat: dog", up)]);
+    assert_weights("(cat:)", &[("cat:", up)]);
+    // The weight only reads up to the closing bracket of its own group
+    assert_weights(
+        "(cat:big) (dog:1.2)",
+        &[("cat:big", up), (" ", 1.0), ("dog", 1.2)],
+    );
+    assert_weights("(cat:(dog:1.2))", &[("cat:", up), ("dog", up * 1.2)]);
+    // Neighboring segments with the same weight are merged
+    assert_weights("(cat)(dog)", &[("catdog", up)]);
+    assert_weights("(cat:1.0) dog", &[("cat dog", 1.0)]);
+    assert_weights(
+        "(cat) [dog:1.1]",
+        &[("cat", up), (" ", 1.0), ("dog:1.1", down)],
+    );
+    assert!(parse_prompt_weights("").is_empty());
+    assert!(parse_prompt_weights("()").is_empty());
+}
+
+#[cfg(test)]
+#[test]
+fn maps_token_offsets_to_weights() {
+    let segments = [
+        WeightedText {
+            text: "a ".to_string(),
+            weight: 1.0,
+        },
+        WeightedText {
+            text: "cat".to_string(),
+            weight: 1.3,
+        },
+        WeightedText {
+            text: " on a mat".to_string(),
+            weight: 0.5,
+        },
+    ];
+    // The text is "a cat on a mat" with a start and end token around it
+    let offsets = vec![(0, 0), (0, 1), (2, 5), (6, 8), (9, 10), (11, 14), (0, 0)];
+    let special = vec![1, 0, 0, 0, 0, 0, 1];
+    let len = offsets.len();
+    let encoding = Encoding::new(
+        (0..len as u32).collect(),
+        vec![0; len],
+        vec![String::new(); len],
+        vec![None; len],
+        offsets,
+        special,
+        vec![1; len],
+        Vec::new(),
+        Default::default(),
+    );
+    assert_eq!(
+        token_weights(&segments, &encoding),
+        vec![1.0, 1.0, 1.3, 0.5, 0.5, 0.5, 1.0]
+    );
+    // Tokens past the end of the segments aren't weighted
+    assert_eq!(token_weights(&segments[..1], &encoding), vec![1.0; len]);
+}
@@ -0,0 +1,676 @@
+// Modified from https://github.com/huggingface/candle/blob/main/candle-transformers/src/models/wuerstchen
+
+use std::path::Path;
+
+use candle_core::{DType, Module, Result, Tensor, D};
+use candle_nn::{Conv2d, Conv2dConfig, ConvTranspose2d, ConvTranspose2dConfig};
+use candle_transformers::models::wuerstchen::common::{LayerNormNoWeights, WLayerNorm};
+use candle_transformers::{quantized_nn, quantized_var_builder::VarBuilder};
+
+/// Check if a weight file is a quantized gguf file.
+pub(crate) fn is_quantized(weights: &Path) -> bool {
+    weights
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("gguf"))
+}
+
+fn conv2d(
+    in_channels: usize,
+    out_channels: usize,
+    kernel_size: usize,
+    config: Conv2dConfig,
+    vb: VarBuilder,
+) -> Result<Conv2d> {
+    let weight = vb
+        .get(
+            (
+                out_channels,
+                in_channels / config.groups,
+                kernel_size,
+                kernel_size,
+            ),
+            "weight",
+        )?
+        .dequantize(vb.device())?;
+    let bias = vb.get(out_channels, "bias")?.dequantize(vb.device())?;
+    Ok(Conv2d::new(weight, Some(bias), config))
+}
+
+fn conv_transpose2d(
+    in_channels: usize,
+    out_channels: usize,
+    kernel_size: usize,
+    config: ConvTranspose2dConfig,
+    vb: VarBuilder,
+) -> Result<ConvTranspose2d> {
+    let weight = vb
+        .get(
+            (in_channels, out_channels, kernel_size, kernel_size),
+            "weight",
+        )?
+        .dequantize(vb.device())?;
+    let bias = vb.get(out_channels, "bias")?.dequantize(vb.device())?;
+    Ok(ConvTranspose2d::new(weight, Some(bias), config))
+}
+
+/// A linear layer with quantized weights. The quantized matmul only supports contiguous inputs.
+#[derive(Debug)]
+struct Linear(quantized_nn::Linear);
+
+impl Linear {
+    fn new(in_dim: usize, out_dim: usize, vb: VarBuilder) -> Result<Self> {
+        quantized_nn::linear(in_dim, out_dim, vb).map(Self)
+    }
+}
+
+impl Module for Linear {
+    fn forward(&self, xs: &Tensor) -> Result<Tensor> {
+        self.0.forward(&xs.contiguous()?)
+    }
+}
+
+#[derive(Debug)]
+struct GlobalResponseNorm {
+    gamma: Tensor,
+    beta: Tensor,
+}
+
+impl GlobalResponseNorm {
+    fn new(dim: usize, vb: VarBuilder) -> Result<Self> {
+        let gamma = vb.get((1, 1, 1, dim), "gamma")?.dequantize(vb.device())?;
+        let beta = vb.get((1, 1, 1, dim), "beta")?.dequantize(vb.device())?;
+        Ok(Self { gamma, beta })
+    }
+}
+
+impl Module for GlobalResponseNorm {
+    fn forward(&self, xs: &Tensor) -> Result<Tensor> {
+        let agg_norm = xs.sqr()?.sum_keepdim((1, 2))?.sqrt()?;
+        let stand_div_norm =
+            agg_norm.broadcast_div(&(agg_norm.mean_keepdim(D::Minus1)? + 1e-6)?)?;
+        xs.broadcast_mul(&stand_div_norm)?
+            .broadcast_mul(&self.gamma)?
+            .broadcast_add(&self.beta)?
+            + xs
+    }
+}
+
+#[derive(Debug)]
+struct TimestepBlock {
+    mapper: Linear,
+}
+
+impl TimestepBlock {
+    fn new(c: usize, c_timestep: usize, vb: VarBuilder) -> Result<Self> {
+        let mapper = Linear::new(c_timestep, c * 2, vb.pp("mapper"))?;
+        Ok(Self { mapper })
+    }
+
+    fn forward(&self, xs: &Tensor, t: &Tensor) -> Result<Tensor> {
+        let ab = self
+            .mapper
+            .forward(t)?
+            .unsqueeze(2)?
+            .unsqueeze(3)?
+            .chunk(2, 1)?;
+        xs.broadcast_mul(&(&ab[0] + 1.)?)?.broadcast_add(&ab[1])
+    }
+}
+
+#[derive(Debug)]
+struct ResBlock {
+    depthwise: Conv2d,
+    norm: WLayerNorm,
+    channelwise_lin1: Linear,
+    channelwise_grn: GlobalResponseNorm,
+    channelwise_lin2: Linear,
+}
+
+impl ResBlock {
+    fn new(c: usize, c_skip: usize, ksize: usize, vb: VarBuilder) -> Result<Self> {
+        let cfg = Conv2dConfig {
+            padding: ksize / 2,
+            groups: c,
+            ..Default::default()
+        };
+        let depthwise = conv2d(c + c_skip, c, ksize, cfg, vb.pp("depthwise"))?;
+        let norm = WLayerNorm::new(c)?;
+        let channelwise_lin1 = Linear::new(c, c * 4, vb.pp("channelwise.0"))?;
+        let channelwise_grn = GlobalResponseNorm::new(c * 4, vb.pp("channelwise.2"))?;
+        let channelwise_lin2 = Linear::new(c * 4, c, vb.pp("channelwise.4"))?;
+        Ok(Self {
+            depthwise,
+            norm,
+            channelwise_lin1,
+            channelwise_grn,
+            channelwise_lin2,
+        })
+    }
+
+    fn forward(&self, xs: &Tensor) -> Result<Tensor> {
+        let x_res = xs;
+        let xs = xs
+            .apply(&self.depthwise)?
+            .apply(&self.norm)?
+            .permute((0, 2, 3, 1))?;
+        let xs = xs
+            .apply(&self.channelwise_lin1)?
+            .gelu_erf()?
+            .apply(&self.channelwise_grn)?
+            .apply(&self.channelwise_lin2)?
+            .permute((0, 3, 1, 2))?;
+        xs + x_res
+    }
+}
+
+#[derive(Debug)]
+struct ResBlockStageB {
+    depthwise: Conv2d,
+    norm: WLayerNorm,
+    channelwise_lin1: Linear,
+    channelwise_grn: GlobalResponseNorm,
+    channelwise_lin2: Linear,
+}
+
+impl ResBlockStageB {
+    fn new(c: usize, c_skip: usize, ksize: usize, vb: VarBuilder) -> Result<Self> {
+        let cfg = Conv2dConfig {
+            groups: c,
+            padding: ksize / 2,
+            ..Default::default()
+        };
+        let depthwise = conv2d(c, c, ksize, cfg, vb.pp("depthwise"))?;
+        let norm = WLayerNorm::new(c)?;
+        let channelwise_lin1 = Linear::new(c + c_skip, c * 4, vb.pp("channelwise.0"))?;
+        let channelwise_grn = GlobalResponseNorm::new(4 * c, vb.pp("channelwise.2"))?;
+        let channelwise_lin2 = Linear::new(c * 4, c, vb.pp("channelwise.4"))?;
+        Ok(Self {
+            depthwise,
+            norm,
+            channelwise_lin1,
+            channelwise_grn,
+            channelwise_lin2,
+        })
+    }
+
+    fn forward(&self, xs: &Tensor, x_skip: Option<&Tensor>) -> Result<Tensor> {
+        let x_res = xs;
+        let xs = xs.apply(&self.depthwise)?.apply(&self.norm)?;
+        let xs = match x_skip {
+            None => xs.clone(),
+            Some(x_skip) => Tensor::cat(&[&xs, x_skip], 1)?,
+        };
+        let xs = xs
+            .permute((0, 2, 3, 1))?
+            .apply(&self.channelwise_lin1)?
+            .gelu()?
+            .apply(&self.channelwise_grn)?
+            .apply(&self.channelwise_lin2)?
+            .permute((0, 3, 1, 2))?;
+        xs + x_res
+    }
+}
+
+// A simplified version of:
+// https://github.com/huggingface/diffusers/blob/119ad2c3dc8a8fb8446a83f4bf6f20929487b47f/src/diffusers/models/attention_processor.py#L38
+#[derive(Debug)]
+struct Attention {
+    to_q: Linear,
+    to_k: Linear,
+    to_v: Linear,
+    to_out: Linear,
+    heads: usize,
+    scale: f64,
+}
+
+impl Attention {
+    fn new(query_dim: usize, heads: usize, dim_head: usize, vb: VarBuilder) -> Result<Self> {
+        let inner_dim = dim_head * heads;
+        let scale = 1.0 / f64::sqrt(dim_head as f64);
+        let to_q = Linear::new(query_dim, inner_dim, vb.pp("to_q"))?;
+        let to_k = Linear::new(query_dim, inner_dim, vb.pp("to_k"))?;
+        let to_v = Linear::new(query_dim, inner_dim, vb.pp("to_v"))?;
+        let to_out = Linear::new(inner_dim, query_dim, vb.pp("to_out.0"))?;
+        Ok(Self {
+            to_q,
+            to_k,
+            to_v,
+            to_out,
+            heads,
+            scale,
+        })
+    }
+
+    fn batch_to_head_dim(&self, xs: &Tensor) -> Result<Tensor> {
+        let (b_size, seq_len, dim) = xs.dims3()?;
+        xs.reshape((b_size / self.heads, self.heads, seq_len, dim))?
+            .permute((0, 2, 1, 3))?
+            .reshape((b_size / self.heads, seq_len, dim * self.heads))
+    }
+
+    fn head_to_batch_dim(&self, xs: &Tensor) -> Result<Tensor> {
+        let (b_size, seq_len, dim) = xs.dims3()?;
+        xs.reshape((b_size, seq_len, self.heads, dim / self.heads))?
+            .permute((0, 2, 1, 3))?
+            .reshape((b_size * self.heads, seq_len, dim / self.heads))
+    }
+
+    fn forward(&self, xs: &Tensor, encoder_hidden_states: &Tensor) -> Result<Tensor> {
+        let (b_size, channel, h, w) = xs.dims4()?;
+        let xs = xs.reshape((b_size, channel, h * w))?.t()?;
+
+        let query = self.head_to_batch_dim(&self.to_q.forward(&xs)?)?;
+        let key = self.head_to_batch_dim(&self.to_k.forward(encoder_hidden_states)?)?;
+        let value = self.head_to_batch_dim(&self.to_v.forward(encoder_hidden_states)?)?;
+
+        let attn_probs = (query.matmul(&key.t()?)? * self.scale)?;
+        let xs = candle_nn::ops::softmax_last_dim(&attn_probs)?.matmul(&value)?;
+        let xs = self.batch_to_head_dim(&xs)?;
+
+        self.to_out
+            .forward(&xs)?
+            .t()?
+            .reshape((b_size, channel, h, w))
+    }
+}
+
+#[derive(Debug)]
+struct AttnBlock {
+    norm: WLayerNorm,
+    attention: Attention,
+    kv_mapper_lin: Linear,
+}
+
+impl AttnBlock {
+    fn new(c: usize, c_cond: usize, nhead: usize, vb: VarBuilder) -> Result<Self> {
+        let norm = WLayerNorm::new(c)?;
+        let attention = Attention::new(c, nhead, c / nhead, vb.pp("attention"))?;
+        let kv_mapper_lin = Linear::new(c_cond, c, vb.pp("kv_mapper.1"))?;
+        Ok(Self {
+            norm,
+            attention,
+            kv_mapper_lin,
+        })
+    }
+
+    /// Both the prior and the decoder attend to the image and the text together.
+    fn forward(&self, xs: &Tensor, kv: &Tensor) -> Result<Tensor> {
+        let kv = candle_nn::ops::silu(kv)?.apply(&self.kv_mapper_lin)?;
+        let norm_xs = self.norm.forward(xs)?;
+        let (b_size, channel, _, _) = xs.dims4()?;
+        let image_kv = norm_xs.reshape((b_size, channel, ()))?.transpose(1, 2)?;
+        let kv = Tensor::cat(&[&image_kv, &kv], 1)?.contiguous()?;
+        xs + self.attention.forward(&norm_xs, &kv)
+    }
+}
+
+fn gen_r_embedding(r: &Tensor, c_r: usize) -> Result<Tensor> {
+    const MAX_POSITIONS: usize = 10000;
+    let r = (r * MAX_POSITIONS as f64)?;
+    let half_dim = c_r / 2;
+    let emb = (MAX_POSITIONS as f64).ln() / (half_dim - 1) as f64;
+    let emb =
+        (Tensor::arange(0u32, half_dim as u32, r.device())?.to_dtype(DType::F32)? * -emb)?.exp()?;
+    let emb = r.unsqueeze(1)?.broadcast_mul(&emb.unsqueeze(0)?)?;
+    let emb = Tensor::cat(&[emb.sin()?, emb.cos()?], 1)?;
+    let emb = if c_r % 2 == 1 {
+        emb.pad_with_zeros(D::Minus1, 0, 1)?
+    } else {
+        emb
+    };
+    emb.to_dtype(r.dtype())
+}
+
+#[derive(Debug)]
+struct PriorBlock {
+    res_block: ResBlock,
+    ts_block: TimestepBlock,
+    attn_block: AttnBlock,
+}
+
+/// The Würstchen v2 prior with quantized weights.
+#[derive(Debug)]
+pub(crate) struct WPrior {
+    projection: Conv2d,
+    cond_mapper_lin1: Linear,
+    cond_mapper_lin2: Linear,
+    blocks: Vec<PriorBlock>,
+    out_ln: WLayerNorm,
+    out_conv: Conv2d,
+    c_r: usize,
+}
+
+impl WPrior {
+    pub(crate) fn new(
+        c_in: usize,
+        c: usize,
+        c_cond: usize,
+        c_r: usize,
+        depth: usize,
+        nhead: usize,
+        vb: VarBuilder,
+    ) -> Result<Self> {
+        let projection = conv2d(c_in, c, 1, Default::default(), vb.pp("projection"))?;
+        let cond_mapper_lin1 = Linear::new(c_cond, c, vb.pp("cond_mapper.0"))?;
+        let cond_mapper_lin2 = Linear::new(c, c, vb.pp("cond_mapper.2"))?;
+        let out_ln = WLayerNorm::new(c)?;
+        let out_conv = conv2d(c, c_in * 2, 1, Default::default(), vb.pp("out.1"))?;
+        let mut blocks = Vec::with_capacity(depth);
+        for index in 0..depth {
+            let res_block = ResBlock::new(c, 0, 3, vb.pp(format!("blocks.{}", 3 * index)))?;
+            let ts_block = TimestepBlock::new(c, c_r, vb.pp(format!("blocks.{}", 3 * index + 1)))?;
+            let attn_block =
+                AttnBlock::new(c, c, nhead, vb.pp(format!("blocks.{}", 3 * index + 2)))?;
+            blocks.push(PriorBlock {
+                res_block,
+                ts_block,
+                attn_block,
+            })
+        }
+        Ok(Self {
+            projection,
+            cond_mapper_lin1,
+            cond_mapper_lin2,
+            blocks,
+            out_ln,
+            out_conv,
+            c_r,
+        })
+    }
+
+    pub(crate) fn forward(&self, xs: &Tensor, r: &Tensor, c: &Tensor) -> Result<Tensor> {
+        // The quantized matmul only supports f32 inputs
+        let dtype = xs.dtype();
+        let (x_in, r, c) = (
+            xs.to_dtype(DType::F32)?,
+            r.to_dtype(DType::F32)?,
+            c.to_dtype(DType::F32)?,
+        );
+
+        let mut xs = x_in.apply(&self.projection)?;
+        let c_embed = c
+            .apply(&self.cond_mapper_lin1)?
+            .apply(&|xs: &_| candle_nn::ops::leaky_relu(xs, 0.2))?
+            .apply(&self.cond_mapper_lin2)?;
+        let r_embed = gen_r_embedding(&r, self.c_r)?;
+        for block in self.blocks.iter() {
+            xs = block.res_block.forward(&xs)?;
+            xs = block.ts_block.forward(&xs, &r_embed)?;
+            xs = block.attn_block.forward(&xs, &c_embed)?;
+        }
+        let ab = xs.apply(&self.out_ln)?.apply(&self.out_conv)?.chunk(2, 1)?;
+        ((x_in - &ab[0])? / ((&ab[1] - 1.)?.abs()? + 1e-5)?)?.to_dtype(dtype)
+    }
+}
+
+#[derive(Debug)]
+struct SubBlock {
+    res_block: ResBlockStageB,
+    ts_block: TimestepBlock,
+    attn_block: Option<AttnBlock>,
+}
+
+#[derive(Debug)]
+struct DownBlock {
+    layer_norm: Option<WLayerNorm>,
+    conv: Option<Conv2d>,
+    sub_blocks: Vec<SubBlock>,
+}
+
+#[derive(Debug)]
+struct UpBlock {
+    sub_blocks: Vec<SubBlock>,
+    layer_norm: Option<WLayerNorm>,
+    conv: Option<ConvTranspose2d>,
+}
+
+/// The Würstchen v2 decoder with quantized weights.
+#[derive(Debug)]
+pub(crate) struct WDiffNeXt {
+    clip_mapper: Linear,
+    effnet_mappers: Vec<Option<Conv2d>>,
+    seq_norm: LayerNormNoWeights,
+    embedding_conv: Conv2d,
+    embedding_ln: WLayerNorm,
+    down_blocks: Vec<DownBlock>,
+    up_blocks: Vec<UpBlock>,
+    clf_ln: WLayerNorm,
+    clf_conv: Conv2d,
+    c_r: usize,
+    patch_size: usize,
+}
+
+impl WDiffNeXt {
+    pub(crate) fn new(
+        c_in: usize,
+        c_out: usize,
+        c_r: usize,
+        c_cond: usize,
+        clip_embd: usize,
+        patch_size: usize,
+        vb: VarBuilder,
+    ) -> Result<Self> {
+        const C_HIDDEN: [usize; 4] = [320, 640, 1280, 1280];
+        const BLOCKS: [usize; 4] = [4, 4, 14, 4];
+        const NHEAD: [usize; 4] = [1, 10, 20, 20];
+        const INJECT_EFFNET: [bool; 4] = [false, true, true, true];
+        const EFFNET_EMBD: usize = 16;
+
+        let clip_mapper = Linear::new(clip_embd, c_cond, vb.pp("clip_mapper"))?;
+        let vb_e = vb.pp("effnet_mappers");
+        let effnet_mappers = INJECT_EFFNET
+            .iter()
+            .chain(INJECT_EFFNET.iter().rev())
+            .enumerate()
+            .map(|(i, &inject)| {
+                inject
+                    .then(|| conv2d(EFFNET_EMBD, c_cond, 1, Default::default(), vb_e.pp(i)))
+                    .transpose()
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let seq_norm = LayerNormNoWeights::new(c_cond)?;
+        let embedding_ln = WLayerNorm::new(C_HIDDEN[0])?;
+        let embedding_conv = conv2d(
+            c_in * patch_size * patch_size,
+            C_HIDDEN[0],
+            1,
+            Default::default(),
+            vb.pp("embedding.1"),
+        )?;
+
+        let sub_block = |i: usize, c_skip: usize, layer_i: &mut usize, vb: &VarBuilder| {
+            let c_hidden = C_HIDDEN[i];
+            let res_block = ResBlockStageB::new(c_hidden, c_skip, 3, vb.pp(*layer_i))?;
+            *layer_i += 1;
+            let ts_block = TimestepBlock::new(c_hidden, c_r, vb.pp(*layer_i))?;
+            *layer_i += 1;
+            let attn_block = if i == 0 {
+                None
+            } else {
+                let attn_block = AttnBlock::new(c_hidden, c_cond, NHEAD[i], vb.pp(*layer_i))?;
+                *layer_i += 1;
+                Some(attn_block)
+            };
+            Result::Ok(SubBlock {
+                res_block,
+                ts_block,
+                attn_block,
+            })
+        };
+
+        let mut down_blocks = Vec::with_capacity(C_HIDDEN.len());
+        for (i, &c_hidden) in C_HIDDEN.iter().enumerate() {
+            let vb = vb.pp("down_blocks").pp(i);
+            let (layer_norm, conv, mut layer_i) = if i > 0 {
+                let layer_norm = WLayerNorm::new(C_HIDDEN[i - 1])?;
+                let cfg = Conv2dConfig {
+                    stride: 2,
+                    ..Default::default()
+                };
+                let conv = conv2d(C_HIDDEN[i - 1], c_hidden, 2, cfg, vb.pp("0.1"))?;
+                (Some(layer_norm), Some(conv), 1)
+            } else {
+                (None, None, 0)
+            };
+            let c_skip = if INJECT_EFFNET[i] { c_cond } else { 0 };
+            let sub_blocks = (0..BLOCKS[i])
+                .map(|_| sub_block(i, c_skip, &mut layer_i, &vb))
+                .collect::<Result<Vec<_>>>()?;
+            down_blocks.push(DownBlock {
+                layer_norm,
+                conv,
+                sub_blocks,
+            })
+        }
+
+        let mut up_blocks = Vec::with_capacity(C_HIDDEN.len());
+        for (i, &c_hidden) in C_HIDDEN.iter().enumerate().rev() {
+            let vb = vb.pp("up_blocks").pp(C_HIDDEN.len() - 1 - i);
+            let mut layer_i = 0;
+            let c_skip = if INJECT_EFFNET[i] { c_cond } else { 0 };
+            let sub_blocks = (0..BLOCKS[i])
+                .map(|j| {
+                    let c_skip_res = if i < BLOCKS.len() - 1 && j == 0 {
+                        c_hidden + c_skip
+                    } else {
+                        c_skip
+                    };
+                    sub_block(i, c_skip_res, &mut layer_i, &vb)
+                })
+                .collect::<Result<Vec<_>>>()?;
+            let (layer_norm, conv) = if i > 0 {
+                let layer_norm = WLayerNorm::new(C_HIDDEN[i - 1])?;
+                let cfg = ConvTranspose2dConfig {
+                    stride: 2,
+                    ..Default::default()
+                };
+                let conv =
+                    conv_transpose2d(c_hidden, C_HIDDEN[i - 1], 2, cfg, vb.pp(layer_i).pp(1))?;
+                (Some(layer_norm), Some(conv))
+            } else {
+                (None, None)
+            };
+            up_blocks.push(UpBlock {
+                layer_norm,
+                conv,
+                sub_blocks,
+            })
+        }
+
+        let clf_ln = WLayerNorm::new(C_HIDDEN[0])?;
+        let clf_conv = conv2d(
+            C_HIDDEN[0],
+            2 * c_out * patch_size * patch_size,
+            1,
+            Default::default(),
+            vb.pp("clf.1"),
+        )?;
+        Ok(Self {
+            clip_mapper,
+            effnet_mappers,
+            seq_norm,
+            embedding_conv,
+            embedding_ln,
+            down_blocks,
+            up_blocks,
+            clf_ln,
+            clf_conv,
+            c_r,
+            patch_size,
+        })
+    }
+
+    pub(crate) fn forward(
+        &self,
+        xs: &Tensor,
+        r: &Tensor,
+        effnet: &Tensor,
+        clip: &Tensor,
+    ) -> Result<Tensor> {
+        const EPS: f64 = 1e-3;
+
+        // The quantized matmul only supports f32 inputs
+        let dtype = xs.dtype();
+        let (x_in, r, effnet, clip) = (
+            xs.to_dtype(DType::F32)?,
+            r.to_dtype(DType::F32)?,
+            effnet.to_dtype(DType::F32)?,
+            clip.to_dtype(DType::F32)?,
+        );
+
+        let r_embed = gen_r_embedding(&r, self.c_r)?;
+        let clip = clip.apply(&self.clip_mapper)?.apply(&self.seq_norm)?;
+
+        let mut xs = x_in
+            .apply(&|xs: &_| candle_nn::ops::pixel_unshuffle(xs, self.patch_size))?
+            .apply(&self.embedding_conv)?
+            .apply(&self.embedding_ln)?;
+
+        let effnet_mapping = |mapper: &Option<Conv2d>, xs: &Tensor| {
+            mapper
+                .as_ref()
+                .map(|mapper| {
+                    effnet
+                        .interpolate2d(xs.dim(D::Minus2)?, xs.dim(D::Minus1)?)?
+                        .apply(mapper)
+                })
+                .transpose()
+        };
+
+        let mut level_outputs = Vec::new();
+        for (i, down_block) in self.down_blocks.iter().enumerate() {
+            if let Some(ln) = &down_block.layer_norm {
+                xs = xs.apply(ln)?
+            }
+            if let Some(conv) = &down_block.conv {
+                xs = xs.apply(conv)?
+            }
+            let skip = effnet_mapping(&self.effnet_mappers[i], &xs)?;
+            for block in down_block.sub_blocks.iter() {
+                xs = block.res_block.forward(&xs, skip.as_ref())?;
+                xs = block.ts_block.forward(&xs, &r_embed)?;
+                if let Some(attn_block) = &block.attn_block {
+                    xs = attn_block.forward(&xs, &clip)?;
+                }
+            }
+            level_outputs.push(xs.clone())
+        }
+        level_outputs.reverse();
+        let mut xs = level_outputs[0].clone();
+
+        for (i, up_block) in self.up_blocks.iter().enumerate() {
+            let effnet_c = effnet_mapping(&self.effnet_mappers[self.down_blocks.len() + i], &xs)?;
+            for (j, block) in up_block.sub_blocks.iter().enumerate() {
+                let skip = if j == 0 && i > 0 {
+                    Some(&level_outputs[i])
+                } else {
+                    None
+                };
+                let skip = match (skip, effnet_c.as_ref()) {
+                    (Some(skip), Some(effnet_c)) => Some(Tensor::cat(&[skip, effnet_c], 1)?),
+                    (None, Some(skip)) | (Some(skip), None) => Some(skip.clone()),
+                    (None, None) => None,
+                };
+                xs = block.res_block.forward(&xs, skip.as_ref())?;
+                xs = block.ts_block.forward(&xs, &r_embed)?;
+                if let Some(attn_block) = &block.attn_block {
+                    xs = attn_block.forward(&xs, &clip)?;
+                }
+            }
+            if let Some(ln) = &up_block.layer_norm {
+                xs = xs.apply(ln)?
+            }
+            if let Some(conv) = &up_block.conv {
+                xs = xs.apply(conv)?
+            }
+        }
+
+        let ab = xs
+            .apply(&self.clf_ln)?
+            .apply(&self.clf_conv)?
+            .apply(&|xs: &_| candle_nn::ops::pixel_shuffle(xs, self.patch_size))?
+            .chunk(2, 1)?;
+        let b = ((candle_nn::ops::sigmoid(&ab[1])? * (1. - EPS * 2.))? + EPS)?;
+        ((x_in - &ab[0])? / b)?.to_dtype(dtype)
+    }
+}
@@ -0,0 +1,170 @@
+//! The Stable Diffusion safety checker, which flags images that show NSFW concepts.
+//!
+//! https://github.com/huggingface/diffusers/blob/main/src/diffusers/pipelines/stable_diffusion/safety_checker.py
+
+use candle_core::{DType, Module, Result, Tensor, D};
+use candle_nn::{Linear, VarBuilder};
+use candle_transformers::models::clip::text_model::Activation;
+use candle_transformers::models::clip::vision_model::{ClipVisionConfig, ClipVisionTransformer};
+use image::imageops::FilterType;
+use image::{DynamicImage, RgbImage};
+
+/// The mean of each channel of the images CLIP was trained on
+const IMAGE_MEAN: [f32; 3] = [0.481_454_66, 0.457_827_5, 0.408_210_73];
+/// The standard deviation of each channel of the images CLIP was trained on
+const IMAGE_STD: [f32; 3] = [0.268_629_54, 0.261_302_6, 0.275_777_1];
+/// The input size of the CLIP vision model
+const IMAGE_SIZE: usize = 224;
+/// The size of the projected image embeddings
+const PROJECTION_DIM: usize = 768;
+/// The number of NSFW concepts the checker compares images against
+const CONCEPTS: usize = 17;
+/// The number of concepts that lower the thresholds of the other concepts if an image shows them
+const SPECIAL_CARE_CONCEPTS: usize = 3;
+/// How much the concept thresholds are lowered for images that show a special care concept
+const SPECIAL_CARE_ADJUSTMENT: f32 = 0.01;
+/// The size blurred images are scaled down to
+const BLUR_SIZE: u32 = 16;
+
+/// What the safety checker does with images it flags as NSFW.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SafetyCheckerAction {
+    /// Only flag the image. Flagged images can be found with [`Image::is_nsfw`](crate::Image::is_nsfw).
+    #[default]
+    Flag,
+    /// Flag the image and blur it beyond recognition.
+    Blur,
+    /// Flag the image and replace it with a black image.
+    Block,
+}
+
+impl SafetyCheckerAction {
+    /// Apply the action to a flagged image
+    pub(crate) fn apply(self, image: RgbImage) -> RgbImage {
+        let (width, height) = image.dimensions();
+        match self {
+            Self::Flag => image,
+            // Scaling the image down to a few pixels and back up removes every detail, and is much faster than a gaussian blur with a large radius
+            Self::Blur => {
+                let small =
+                    image::imageops::resize(&image, BLUR_SIZE, BLUR_SIZE, FilterType::Triangle);
+                image::imageops::resize(&small, width, height, FilterType::Triangle)
+            }
+            Self::Block => RgbImage::new(width, height),
+        }
+    }
+
+    /// Check if the action hides flagged images. The latents of hidden images are not returned either.
+    pub(crate) fn hides_images(self) -> bool {
+        self != Self::Flag
+    }
+}
+
+/// A CLIP vision model and the embeddings of the concepts the safety checker flags.
+pub(crate) struct SafetyChecker {
+    vision_model: ClipVisionTransformer,
+    visual_projection: Linear,
+    /// The normalized embeddings of the NSFW concepts
+    concept_embeds: Tensor,
+    /// The similarity with each concept above which an image shows the concept
+    concept_thresholds: Tensor,
+    special_care_embeds: Tensor,
+    special_care_thresholds: Tensor,
+}
+
+impl SafetyChecker {
+    pub(crate) fn new(vb: VarBuilder) -> Result<Self> {
+        // https://huggingface.co/CompVis/stable-diffusion-safety-checker/blob/main/config.json
+        let config = ClipVisionConfig {
+            embed_dim: 1024,
+            activation: Activation::QuickGelu,
+            intermediate_size: 4096,
+            num_hidden_layers: 24,
+            num_attention_heads: 16,
+            projection_dim: PROJECTION_DIM,
+            num_channels: 3,
+            image_size: IMAGE_SIZE,
+            patch_size: 14,
+        };
+        let vision_model =
+            ClipVisionTransformer::new(vb.pp("vision_model").pp("vision_model"), &config)?;
+        let visual_projection = candle_nn::linear_no_bias(
+            config.embed_dim,
+            PROJECTION_DIM,
+            vb.pp("visual_projection"),
+        )?;
+        // The similarities are small, so they are computed in f32 even if the model runs in half precision
+        let get = |shape: &[usize], name: &str| vb.get(shape, name)?.to_dtype(DType::F32);
+        Ok(Self {
+            vision_model,
+            visual_projection,
+            concept_embeds: normalize(&get(&[CONCEPTS, PROJECTION_DIM], "concept_embeds")?)?,
+            concept_thresholds: get(&[CONCEPTS], "concept_embeds_weights")?,
+            special_care_embeds: normalize(&get(
+                &[SPECIAL_CARE_CONCEPTS, PROJECTION_DIM],
+                "special_care_embeds",
+            )?)?,
+            special_care_thresholds: get(&[SPECIAL_CARE_CONCEPTS], "special_care_embeds_weights")?,
+        })
+    }
+
+    /// Check which of the images show NSFW concepts.
+    pub(crate) fn is_nsfw(&self, images: &[RgbImage], dtype: DType) -> Result<Vec<bool>> {
+        if images.is_empty() {
+            return Ok(Vec::new());
+        }
+        let device = self.concept_embeds.device();
+        let pixels = images
+            .iter()
+            .map(|image| image_to_tensor(image, device))
+            .collect::<Result<Vec<_>>>()?;
+        let pixels = Tensor::stack(&pixels, 0)?.to_dtype(dtype)?;
+        let image_embeds = self
+            .vision_model
+            .forward(&pixels)?
+            .apply(&self.visual_projection)?
+            .to_dtype(DType::F32)?;
+        let image_embeds = normalize(&image_embeds)?;
+
+        let scores = |embeds: &Tensor, thresholds: &Tensor| {
+            image_embeds
+                .matmul(&embeds.t()?)?
+                .broadcast_sub(thresholds)?
+                .to_vec2::<f32>()
+        };
+        let special_care = scores(&self.special_care_embeds, &self.special_care_thresholds)?;
+        let concepts = scores(&self.concept_embeds, &self.concept_thresholds)?;
+        Ok(special_care
+            .iter()
+            .zip(&concepts)
+            .map(|(special_care, concepts)| {
+                // Images that show a special care concept are flagged more easily
+                let adjustment = if special_care.iter().any(|&score| score > 0.) {
+                    SPECIAL_CARE_ADJUSTMENT
+                } else {
+                    0.
+                };
+                concepts.iter().any(|&score| score + adjustment > 0.)
+            })
+            .collect())
+    }
+}
+
+/// Scale each row to unit length, so the dot product of two rows is their cosine similarity
+fn normalize(embeds: &Tensor) -> Result<Tensor> {
+    embeds.broadcast_div(&embeds.sqr()?.sum_keepdim(D::Minus1)?.sqrt()?)
+}
+
+/// Resize and crop an image to the input size of the model and normalize the pixels
+fn image_to_tensor(image: &RgbImage, device: &candle_core::Device) -> Result<Tensor> {
+    let image = DynamicImage::ImageRgb8(image.clone())
+        .resize_to_fill(IMAGE_SIZE as u32, IMAGE_SIZE as u32, FilterType::CatmullRom)
+        .to_rgb8();
+    let image = Tensor::from_vec(image.into_raw(), (IMAGE_SIZE, IMAGE_SIZE, 3), device)?
+        .permute((2, 0, 1))?
+        .to_dtype(DType::F32)?
+        .affine(1. / 255., 0.)?;
+    let mean = Tensor::new(&IMAGE_MEAN, device)?.reshape((3, 1, 1))?;
+    let std = Tensor::new(&IMAGE_STD, device)?.reshape((3, 1, 1))?;
+    image.broadcast_sub(&mean)?.broadcast_div(&std)
+}
@@ -0,0 +1,229 @@
+use candle_core::{DType, Device, Result, Shape, Tensor};
+use candle_transformers::models::wuerstchen::ddpm::{DDPMWScheduler, DDPMWSchedulerConfig};
+use rand::rngs::StdRng;
+use rand_distr::{Distribution, StandardNormal};
+
+/// The offset of the cosine noise schedule Wuerstchen was trained with.
+const COSINE_OFFSET: f64 = 0.008;
+
+/// The noise scheduler used to step through the diffusion process of the prior and the denoiser.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Scheduler {
+    /// The DDPM scheduler Wuerstchen was trained with. It adds fresh noise at every step.
+    #[default]
+    Ddpm,
+    /// The deterministic DDIM scheduler. It usually needs fewer steps than DDPM for a similar quality.
+    Ddim,
+    /// The deterministic second order DPM-Solver++ (2M) scheduler. It produces good images with very few steps.
+    DpmPlusPlus2M,
+    /// The Euler ancestral scheduler. It adds fresh noise at every step which gives more variety between images.
+    EulerAncestral,
+}
+
+/// The state of a scheduler while it steps through one diffusion process.
+pub(crate) struct SchedulerState {
+    scheduler: Scheduler,
+    ddpm: DDPMWScheduler,
+    init_alpha_cumprod: f64,
+    /// The predicted denoised sample and the step size of the previous step for multistep schedulers.
+    previous: Option<(Tensor, f64)>,
+}
+
+impl SchedulerState {
+    pub(crate) fn new(scheduler: Scheduler, steps: usize) -> Result<Self> {
+        let config = DDPMWSchedulerConfig::default();
+        let init_alpha_cumprod = (COSINE_OFFSET / (1. + COSINE_OFFSET) * std::f64::consts::PI)
+            .cos()
+            .powi(2);
+        Ok(Self {
+            scheduler,
+            ddpm: DDPMWScheduler::new(steps, config)?,
+            init_alpha_cumprod,
+            previous: None,
+        })
+    }
+
+    /// The timesteps the model runs at. Each step moves the sample from the timestep to the next timestep in the list, and the last step moves it to 0.
+    pub(crate) fn timesteps(&self) -> Vec<f64> {
+        let timesteps = self.ddpm.timesteps();
+        timesteps[..timesteps.len() - 1].to_vec()
+    }
+
+    /// Move the sample from the timestep to the previous timestep of the diffusion process with the noise the model predicted.
+    ///
+    /// Schedulers that add fresh noise at every step draw it from `rng` if it is set, so seeded runs are reproducible.
+    pub(crate) fn step(
+        &mut self,
+        noise_pred: &Tensor,
+        t: f64,
+        sample: &Tensor,
+        rng: Option<&mut StdRng>,
+    ) -> Result<Tensor> {
+        let timesteps = self.ddpm.timesteps();
+        let prev_t = timesteps
+            .iter()
+            .position(|&timestep| timestep == t)
+            .and_then(|index| timesteps.get(index + 1).copied())
+            .unwrap_or(0.);
+
+        let alpha_cumprod = self.alpha_cumprod(t);
+        let alpha_cumprod_prev = self.alpha_cumprod(prev_t);
+        let (alpha, sigma) = (alpha_cumprod.sqrt(), (1. - alpha_cumprod).sqrt());
+        let (alpha_prev, sigma_prev) =
+            (alpha_cumprod_prev.sqrt(), (1. - alpha_cumprod_prev).sqrt());
+        // The denoised sample the model predicts
+        let predicted = || (sample - (noise_pred * sigma)?)? / alpha;
+
+        match self.scheduler {
+            Scheduler::Ddpm => {
+                let alpha = alpha_cumprod / alpha_cumprod_prev;
+                let mu =
+                    ((sample - (noise_pred * ((1. - alpha) / sigma))?)? * (1. / alpha).sqrt())?;
+                if prev_t == 0. {
+                    return Ok(mu);
+                }
+                let std = ((1. - alpha) * (1. - alpha_cumprod_prev) / (1. - alpha_cumprod)).sqrt();
+                let noise = noise(sample.shape(), sample.dtype(), sample.device(), rng)?;
+                mu + (noise * std)?
+            }
+            Scheduler::Ddim => (predicted()? * alpha_prev)? + (noise_pred * sigma_prev)?,
+            Scheduler::EulerAncestral => {
+                // Work with the noise level of the variance exploding formulation of the sample
+                let noise_level = sigma / alpha;
+                let noise_level_prev = sigma_prev / alpha_prev;
+                let noise_up = (noise_level_prev.powi(2)
+                    * (noise_level.powi(2) - noise_level_prev.powi(2))
+                    / noise_level.powi(2))
+                .sqrt()
+                .min(noise_level_prev);
+                let noise_down = (noise_level_prev.powi(2) - noise_up.powi(2)).sqrt();
+                let noise = noise(sample.shape(), sample.dtype(), sample.device(), rng)?;
+                let next = ((predicted()? + (noise_pred * noise_down)?)? + (noise * noise_up)?)?;
+                next * alpha_prev
+            }
+            Scheduler::DpmPlusPlus2M => {
+                let lambda = (alpha / sigma).ln();
+                let lambda_prev = (alpha_prev / sigma_prev).ln();
+                let h = lambda_prev - lambda;
+                let predicted = predicted()?;
+                // Use the first order update for the first step and the last step
+                let denoised = match self.previous.take() {
+                    Some((previous, previous_h)) if prev_t > 0. => {
+                        let r = previous_h / h;
+                        ((&predicted * (1. + 1. / (2. * r)))? - (previous * (1. / (2. * r)))?)?
+                    }
+                    _ => predicted.clone(),
+                };
+                self.previous = Some((predicted, h));
+                (sample * (sigma_prev / sigma))? - (denoised * (alpha_prev * ((-h).exp() - 1.)))?
+            }
+        }
+    }
+
+    /// The cosine schedule of the fraction of the signal left in the sample at a timestep.
+    fn alpha_cumprod(&self, t: f64) -> f64 {
+        let alpha_cumprod =
+            ((t + COSINE_OFFSET) / (1. + COSINE_OFFSET) * std::f64::consts::PI * 0.5)
+                .cos()
+                .powi(2)
+                / self.init_alpha_cumprod;
+        alpha_cumprod.clamp(0.0001, 0.9999)
+    }
+}
+
+/// Create normally distributed noise with the given shape. The CPU device can't be seeded, so if a rng is set the noise is created with the rng and then moved to the device.
+pub(crate) fn noise(
+    shape: impl Into<Shape>,
+    dtype: DType,
+    device: &Device,
+    rng: Option<&mut StdRng>,
+) -> Result<Tensor> {
+    let shape = shape.into();
+    let noise = match rng {
+        Some(rng) => {
+            let noise = (0..shape.elem_count())
+                .map(|_| StandardNormal.sample(rng))
+                .collect::<Vec<f32>>();
+            Tensor::from_vec(noise, shape, &Device::Cpu)?.to_device(device)?
+        }
+        None => Tensor::randn(0f32, 1f32, shape, device)?,
+    };
+    noise.to_dtype(dtype)
+}
+
+#[cfg(test)]
+fn assert_close(a: &Tensor, b: &Tensor, tolerance: f32) {
+    let a = a.to_vec1::<f32>().unwrap();
+    let b = b.to_vec1::<f32>().unwrap();
+    for (a, b) in a.iter().zip(&b) {
+        assert!((a - b).abs() < tolerance, "{a:?} != {b:?}");
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn deterministic_schedulers_converge_to_the_predicted_sample() {
+    let device = Device::Cpu;
+    let x0 = Tensor::new(&[0.5f32, -1., 2.], &device).unwrap();
+    let noise_pred = Tensor::new(&[1f32, -0.5, 0.25], &device).unwrap();
+    // The noisy sample at a timestep if the model always predicts the same noise
+    let noisy = |state: &SchedulerState, t: f64| {
+        let alpha_cumprod = state.alpha_cumprod(t);
+        ((&x0 * alpha_cumprod.sqrt()).unwrap() + (&noise_pred * (1. - alpha_cumprod).sqrt()))
+            .unwrap()
+    };
+
+    for scheduler in [Scheduler::Ddim, Scheduler::DpmPlusPlus2M] {
+        let steps = 4;
+        let mut state = SchedulerState::new(scheduler, steps).unwrap();
+        let timesteps = state.timesteps();
+        assert_eq!(timesteps, [1., 0.75, 0.5, 0.25]);
+
+        let mut sample = noisy(&state, timesteps[0]);
+        for (i, &t) in timesteps.iter().enumerate() {
+            sample = state.step(&noise_pred, t, &sample, None).unwrap();
+            // Each step moves the sample to the next timestep, and the last step moves it to 0
+            let prev_t = timesteps.get(i + 1).copied().unwrap_or(0.);
+            assert_close(&sample, &noisy(&state, prev_t), 1e-4);
+        }
+        // The final sample only keeps the noise of the clamped schedule at timestep 0
+        assert_close(&sample, &x0, 0.02);
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn dpm_plus_plus_uses_first_order_steps_at_the_ends() {
+    let device = Device::Cpu;
+    let steps = 4;
+    let sample = Tensor::new(&[0.5f32, -1., 2.], &device).unwrap();
+    let noise_preds = [
+        Tensor::new(&[1f32, -0.5, 0.25], &device).unwrap(),
+        Tensor::new(&[-0.25f32, 0.75, 1.], &device).unwrap(),
+        Tensor::new(&[0.5f32, 0.5, -1.5], &device).unwrap(),
+        Tensor::new(&[2f32, -1., 0.], &device).unwrap(),
+    ];
+    let mut ddim = SchedulerState::new(Scheduler::Ddim, steps).unwrap();
+    let mut dpm = SchedulerState::new(Scheduler::DpmPlusPlus2M, steps).unwrap();
+    let timesteps = ddim.timesteps();
+
+    for (i, (&t, noise_pred)) in timesteps.iter().zip(&noise_preds).enumerate() {
+        // The first order DPM-Solver++ update is the same as DDIM
+        let ddim_step = ddim.step(noise_pred, t, &sample, None).unwrap();
+        let dpm_step = dpm.step(noise_pred, t, &sample, None).unwrap();
+        let first_order = i == 0 || i == steps - 1;
+        let difference = (ddim_step - dpm_step)
+            .unwrap()
+            .abs()
+            .unwrap()
+            .max(0)
+            .unwrap()
+            .to_scalar::<f32>()
+            .unwrap();
+        if first_order {
+            assert!(difference < 1e-5, "step {i} differs by {difference}");
+        } else {
+            assert!(difference > 1e-3, "step {i} only differs by {difference}");
+        }
+    }
+}
@@ -0,0 +1,226 @@
+//! The Real-ESRGAN super-resolution model. The decoded images are upscaled on the device before they are copied to the host.
+//!
+//! https://github.com/xinntao/Real-ESRGAN
+//! https://github.com/XPixelGroup/BasicSR/blob/master/basicsr/archs/rrdbnet_arch.py
+
+use std::path::Path;
+
+use candle_core::{DType, Device, IndexOp, Module, Result, Tensor};
+use candle_nn::{Conv2d, Conv2dConfig, VarBuilder};
+use kalosm_model_types::FileSource;
+
+/// The number of channels of the features
+const NUM_FEAT: usize = 64;
+/// The number of channels each convolution of a dense block adds to the features
+const NUM_GROW_CH: usize = 32;
+/// The number of residual in residual dense blocks
+const NUM_BLOCKS: usize = 23;
+/// The size of the tiles the images are upscaled in. Upscaling a whole 1024x1024 image at once needs several GB for the activations at the output resolution
+const TILE_SIZE: usize = 256;
+/// The overlap between neighboring tiles, which hides the seams between the tiles
+const TILE_PAD: usize = 16;
+
+/// Check if the upscaler supports an upscale factor. A factor of 1 disables the upscaler.
+pub(crate) fn is_supported(scale: usize) -> bool {
+    matches!(scale, 1 | 2 | 4)
+}
+
+/// The official Real-ESRGAN weights for an upscale factor
+pub(crate) fn default_weights(scale: usize) -> FileSource {
+    let url = match scale {
+        2 => {
+            "https://github.com/xinntao/Real-ESRGAN/releases/download/v0.2.1/RealESRGAN_x2plus.pth"
+        }
+        _ => {
+            "https://github.com/xinntao/Real-ESRGAN/releases/download/v0.1.0/RealESRGAN_x4plus.pth"
+        }
+    };
+    FileSource::Url(url.to_string())
+}
+
+/// Load Real-ESRGAN weights in .safetensors or PyTorch .pth format.
+pub(crate) fn load_weights(
+    path: &Path,
+    dtype: DType,
+    device: &Device,
+) -> Result<VarBuilder<'static>> {
+    if path
+        .extension()
+        .is_some_and(|extension| extension == "safetensors")
+    {
+        return unsafe { VarBuilder::from_mmaped_safetensors(&[path], dtype, device) };
+    }
+    // The official checkpoints store the weights under `params_ema`, but some fine-tunes use `params` or store the weights directly
+    let pth = candle_core::pickle::PthTensors::new(path, Some("params_ema"))
+        .or_else(|_| candle_core::pickle::PthTensors::new(path, Some("params")))
+        .or_else(|_| candle_core::pickle::PthTensors::new(path, None))?;
+    Ok(VarBuilder::from_backend(
+        Box::new(pth),
+        dtype,
+        device.clone(),
+    ))
+}
+
+fn conv(in_channels: usize, out_channels: usize, vb: VarBuilder) -> Result<Conv2d> {
+    let config = Conv2dConfig {
+        padding: 1,
+        ..Default::default()
+    };
+    candle_nn::conv2d(in_channels, out_channels, 3, config, vb)
+}
+
+fn leaky_relu(xs: &Tensor) -> Result<Tensor> {
+    xs.maximum(&(xs * 0.2)?)
+}
+
+/// Move each `factor`x`factor` block of pixels into the channels, like `torch.nn.functional.pixel_unshuffle`
+fn pixel_unshuffle(xs: &Tensor, factor: usize) -> Result<Tensor> {
+    let (b, c, h, w) = xs.dims4()?;
+    xs.reshape(vec![b, c, h / factor, factor, w / factor, factor])?
+        .permute(vec![0, 1, 3, 5, 2, 4])?
+        .reshape((b, c * factor * factor, h / factor, w / factor))
+}
+
+/// A block of convolutions where each convolution sees the output of every convolution before it.
+struct ResidualDenseBlock {
+    convs: Vec<Conv2d>,
+}
+
+impl ResidualDenseBlock {
+    fn new(vb: VarBuilder) -> Result<Self> {
+        let convs = (0..5)
+            .map(|index| {
+                let out_channels = if index == 4 { NUM_FEAT } else { NUM_GROW_CH };
+                conv(
+                    NUM_FEAT + index * NUM_GROW_CH,
+                    out_channels,
+                    vb.pp(format!("conv{}", index + 1)),
+                )
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { convs })
+    }
+}
+
+impl Module for ResidualDenseBlock {
+    fn forward(&self, xs: &Tensor) -> Result<Tensor> {
+        let (last, convs) = self.convs.split_last().unwrap();
+        let mut features = vec![xs.clone()];
+        for conv in convs {
+            let out = leaky_relu(&Tensor::cat(&features, 1)?.apply(conv)?)?;
+            features.push(out);
+        }
+        let out = Tensor::cat(&features, 1)?.apply(last)?;
+        (out * 0.2)? + xs
+    }
+}
+
+/// A residual in residual dense block.
+struct Rrdb {
+    blocks: Vec<ResidualDenseBlock>,
+}
+
+impl Rrdb {
+    fn new(vb: VarBuilder) -> Result<Self> {
+        let blocks = (1..=3)
+            .map(|index| ResidualDenseBlock::new(vb.pp(format!("rdb{index}"))))
+            .collect::<Result<_>>()?;
+        Ok(Self { blocks })
+    }
+}
+
+impl Module for Rrdb {
+    fn forward(&self, xs: &Tensor) -> Result<Tensor> {
+        let out = self
+            .blocks
+            .iter()
+            .try_fold(xs.clone(), |out, block| block.forward(&out))?;
+        (out * 0.2)? + xs
+    }
+}
+
+/// The RRDBNet generator of Real-ESRGAN.
+pub(crate) struct Upscaler {
+    scale: usize,
+    conv_first: Conv2d,
+    body: Vec<Rrdb>,
+    conv_body: Conv2d,
+    conv_up1: Conv2d,
+    conv_up2: Conv2d,
+    conv_hr: Conv2d,
+    conv_last: Conv2d,
+}
+
+impl Upscaler {
+    /// Create an upscaler for a factor of 2 or 4.
+    pub(crate) fn new(scale: usize, vb: VarBuilder) -> Result<Self> {
+        // The network always upscales by 4, so the x2 model folds the pixels into the channels first
+        let in_channels = 3 * (4 / scale).pow(2);
+        Ok(Self {
+            scale,
+            conv_first: conv(in_channels, NUM_FEAT, vb.pp("conv_first"))?,
+            body: (0..NUM_BLOCKS)
+                .map(|index| Rrdb::new(vb.pp("body").pp(index)))
+                .collect::<Result<_>>()?,
+            conv_body: conv(NUM_FEAT, NUM_FEAT, vb.pp("conv_body"))?,
+            conv_up1: conv(NUM_FEAT, NUM_FEAT, vb.pp("conv_up1"))?,
+            conv_up2: conv(NUM_FEAT, NUM_FEAT, vb.pp("conv_up2"))?,
+            conv_hr: conv(NUM_FEAT, NUM_FEAT, vb.pp("conv_hr"))?,
+            conv_last: conv(NUM_FEAT, 3, vb.pp("conv_last"))?,
+        })
+    }
+
+    /// Upscale a batch of images with the shape `[batch, 3, height, width]` and pixels between 0 and 1.
+    pub(crate) fn upscale(&self, images: &Tensor) -> Result<Tensor> {
+        let images = images.clamp(0f32, 1f32)?;
+        let (_, _, height, width) = images.dims4()?;
+        let scale = self.scale;
+        let mut rows = Vec::new();
+        for y in (0..height).step_by(TILE_SIZE) {
+            let y_end = (y + TILE_SIZE).min(height);
+            let y_start_pad = y.saturating_sub(TILE_PAD);
+            let y_end_pad = (y_end + TILE_PAD).min(height);
+            let mut tiles = Vec::new();
+            for x in (0..width).step_by(TILE_SIZE) {
+                let x_end = (x + TILE_SIZE).min(width);
+                let x_start_pad = x.saturating_sub(TILE_PAD);
+                let x_end_pad = (x_end + TILE_PAD).min(width);
+                let tile = images
+                    .i((.., .., y_start_pad..y_end_pad, x_start_pad..x_end_pad))?
+                    .contiguous()?;
+                let tile = self.forward(&tile)?;
+                // Crop the overlap with the neighboring tiles
+                let tile = tile.i((
+                    ..,
+                    ..,
+                    (y - y_start_pad) * scale..(y_end - y_start_pad) * scale,
+                    (x - x_start_pad) * scale..(x_end - x_start_pad) * scale,
+                ))?;
+                tiles.push(tile);
+            }
+            rows.push(Tensor::cat(&tiles, 3)?);
+        }
+        Tensor::cat(&rows, 2)?.clamp(0f32, 1f32)
+    }
+}
+
+impl Module for Upscaler {
+    fn forward(&self, xs: &Tensor) -> Result<Tensor> {
+        let feat = match self.scale {
+            2 => pixel_unshuffle(xs, 2)?,
+            _ => xs.clone(),
+        };
+        let feat = feat.apply(&self.conv_first)?;
+        let body = self
+            .body
+            .iter()
+            .try_fold(feat.clone(), |out, block| block.forward(&out))?
+            .apply(&self.conv_body)?;
+        let mut feat = (feat + body)?;
+        for conv in [&self.conv_up1, &self.conv_up2] {
+            let (_, _, h, w) = feat.dims4()?;
+            feat = leaky_relu(&feat.upsample_nearest2d(h * 2, w * 2)?.apply(conv)?)?;
+        }
+        leaky_relu(&feat.apply(&self.conv_hr)?)?.apply(&self.conv_last)
+    }
+}
@@ -1,7 +1,8 @@
 use segment_anything_rs::*;
 
-fn main() {
-    let model = SegmentAnything::builder().build().unwrap();
+#[tokio::main]
+async fn main() {
+    let model = SegmentAnything::builder().build().await.unwrap();
     let image = image::open("examples/landscape.jpg").unwrap();
     let x = image.width() / 2;
     let y = image.height() / 4;
@@ -1,7 +1,8 @@
 use segment_anything_rs::*;
 
-fn main() {
-    let model = SegmentAnything::builder().build().unwrap();
+#[tokio::main]
+async fn main() {
+    let model = SegmentAnything::builder().build().await.unwrap();
     let image = image::open("examples/landscape.jpg").unwrap();
     let images = model.segment_everything(image).unwrap();
     for (i, img) in images.iter().enumerate() {
@@ -0,0 +1,190 @@
+use candle_core::IndexOp;
+use image::{
+    DynamicImage, GenericImage, GenericImageView, GrayImage, ImageBuffer, Rgba, RgbaImage,
+};
+
+use crate::postprocess::BinaryMask;
+use crate::{SegmentAnything, SegmentAnythingInferenceError};
+
+/// The size of the masks generated by [`candle_transformers::models::segment_anything::sam::Sam::generate_masks`]
+const LOW_RES_MASK_SIZE: usize = 256;
+
+/// Settings for [`SegmentAnything::remove_background_with_settings`].
+pub struct BackgroundRemovalSettings {
+    image: ImageBuffer<image::Rgba<u8>, Vec<u8>>,
+    feather_radius: f32,
+}
+
+impl BackgroundRemovalSettings {
+    /// Creates a new [`BackgroundRemovalSettings`] from an image.
+    pub fn new<I: GenericImageView<Pixel = Rgba<u8>>>(input: I) -> Self {
+        let mut image = ImageBuffer::new(input.width(), input.height());
+        image.copy_from(&input, 0, 0).unwrap();
+        Self {
+            image,
+            feather_radius: 2.,
+        }
+    }
+
+    /// Sets how far the edges of the subject are blurred in pixels. 0 gives hard edges, 2 is the default value.
+    pub fn set_feather_radius(mut self, feather_radius: f32) -> Self {
+        self.feather_radius = feather_radius;
+        self
+    }
+}
+
+impl SegmentAnything {
+    /// Remove the background from an image. Returns the image with a transparent background around the main subject.
+    ///
+    /// # Example
+    /// ```rust, no_run
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// use segment_anything_rs::*;
+    ///
+    /// let model = SegmentAnything::builder().build().await.unwrap();
+    /// let image = image::open("examples/landscape.jpg").unwrap();
+    /// let subject = model.remove_background(image).unwrap();
+    /// subject.save("subject.png").unwrap();
+    /// # }
+    /// ```
+    pub fn remove_background(
+        &self,
+        image: DynamicImage,
+    ) -> Result<RgbaImage, SegmentAnythingInferenceError> {
+        self.remove_background_with_settings(BackgroundRemovalSettings::new(image))
+    }
+
+    /// Remove the background from an image with custom settings. Returns the image with a transparent background around the main subject.
+    ///
+    /// Masks are generated for everything in the image and the mask most likely to be the subject is kept. Masks near the center of the image that don't touch the edges of the image are preferred.
+    pub fn remove_background_with_settings(
+        &self,
+        settings: BackgroundRemovalSettings,
+    ) -> Result<RgbaImage, SegmentAnythingInferenceError> {
+        let BackgroundRemovalSettings {
+            image,
+            feather_radius,
+        } = settings;
+        let (image_width, image_height) = image.dimensions();
+
+        let image_tensor = self.image_to_tensor(DynamicImage::ImageRgba8(image.clone()))?;
+        let (_, height, width) = image_tensor.dims3()?;
+        // The masks cover the image padded to a square, so only part of each mask is inside the image
+        let scale = LOW_RES_MASK_SIZE as f64 / height.max(width) as f64;
+        let mask_width = ((width as f64 * scale).round() as usize).max(1);
+        let mask_height = ((height as f64 * scale).round() as usize).max(1);
+
+        let mut candidates = Vec::new();
+        for bbox in self
+            .sam
+            .generate_masks(&image_tensor, 32, 0, 512. / 1500., 1)?
+        {
+            let data = bbox
+                .data
+                .i((..mask_height, ..mask_width))?
+                .flatten_all()?
+                .to_vec1::<u32>()?;
+            let mask = BinaryMask {
+                width: mask_width,
+                height: mask_height,
+                data: data.into_iter().map(|value| value > 0).collect(),
+            };
+            candidates.push((mask, bbox.confidence));
+        }
+
+        let mut mask =
+            pick_foreground(candidates).ok_or(SegmentAnythingInferenceError::NoForeground)?;
+        let area = mask.width * mask.height;
+        mask.fill_holes(area / 20);
+        mask.remove_small_regions(area / 100);
+
+        // Upscale the mask with interpolation so the edges are smooth, then feather them
+        let alpha = DynamicImage::ImageLuma8(mask.to_image()).resize_exact(
+            image_width,
+            image_height,
+            image::imageops::FilterType::Triangle,
+        );
+        let alpha: GrayImage = if feather_radius > 0. {
+            image::imageops::blur(&alpha.to_luma8(), feather_radius)
+        } else {
+            alpha.to_luma8()
+        };
+
+        let mut output = image;
+        for (pixel, mask) in output.pixels_mut().zip(alpha.pixels()) {
+            pixel.0[3] = ((pixel.0[3] as u16 * mask.0[0] as u16) / 255) as u8;
+        }
+        Ok(output)
+    }
+}
+
+/// Pick the mask that is most likely to be the subject of the image from masks with the model's confidence in each mask
+fn pick_foreground(candidates: Vec<(BinaryMask, f32)>) -> Option<BinaryMask> {
+    candidates
+        .into_iter()
+        .filter_map(|(mask, confidence)| {
+            let score = foreground_score(&mask)? * confidence;
+            Some((mask, score))
+        })
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(mask, _)| mask)
+}
+
+/// Score how much a mask looks like the subject of the image. Subjects are usually large, close to the center of the image and don't touch the edges of the image
+fn foreground_score(mask: &BinaryMask) -> Option<f32> {
+    let (width, height) = (mask.width, mask.height);
+    let mut area = 0;
+    let mut border = 0;
+    let (mut sum_x, mut sum_y) = (0., 0.);
+    for y in 0..height {
+        for x in 0..width {
+            if !mask.data[y * width + x] {
+                continue;
+            }
+            area += 1;
+            sum_x += x as f32;
+            sum_y += y as f32;
+            if x == 0 || y == 0 || x == width - 1 || y == height - 1 {
+                border += 1;
+            }
+        }
+    }
+    let coverage = area as f32 / (width * height) as f32;
+    // Tiny masks are noise and masks that cover almost everything are the background
+    if !(0.01..=0.95).contains(&coverage) {
+        return None;
+    }
+    let border_length = (2 * (width + height)).saturating_sub(4).max(1);
+    let border_fraction = border as f32 / border_length as f32;
+
+    let (center_x, center_y) = (width as f32 / 2., height as f32 / 2.);
+    let (mean_x, mean_y) = (sum_x / area as f32, sum_y / area as f32);
+    let distance = ((mean_x - center_x) / center_x).hypot((mean_y - center_y) / center_y)
+        / std::f32::consts::SQRT_2;
+
+    Some(coverage.sqrt() * (1. - border_fraction) * (1. - distance))
+}
+
+#[cfg(test)]
+#[test]
+fn picks_the_centered_subject() {
+    let rect = |left: usize, top: usize, right: usize, bottom: usize| BinaryMask {
+        width: 20,
+        height: 20,
+        data: (0..400)
+            .map(|i| (left..right).contains(&(i % 20)) && (top..bottom).contains(&(i / 20)))
+            .collect(),
+    };
+    let subject = rect(6, 5, 14, 16);
+    let candidates = vec![
+        // The sky touches the edge of the image
+        (rect(0, 0, 20, 8), 0.99),
+        (subject.clone(), 0.95),
+        // A small object in the corner
+        (rect(1, 15, 4, 19), 0.99),
+        // Everything
+        (rect(0, 0, 20, 20), 1.),
+    ];
+    assert_eq!(pick_foreground(candidates), Some(subject));
+}
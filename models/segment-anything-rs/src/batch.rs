@@ -0,0 +1,325 @@
+use candle_core::{IndexOp, Tensor};
+use candle_transformers::models::segment_anything::sam;
+use image::{DynamicImage, GenericImage, GenericImageView, ImageBuffer, Rgba};
+
+use crate::postprocess::{stability_score, BinaryMask};
+use crate::{SegmentAnything, SegmentAnythingInferenceError};
+
+/// The offset of the thresholds used to calculate the stability score of a mask
+const STABILITY_SCORE_OFFSET: f32 = 1.;
+
+/// A prompt for one mask in [`SegmentAnything::segment_batch`]. Coordinates are between 0 and 1 (0.5 is at the middle of the image).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SegmentAnythingPrompt {
+    goal_points: Vec<(f64, f64)>,
+    avoid_points: Vec<(f64, f64)>,
+    bounding_box: Option<[f64; 4]>,
+}
+
+impl SegmentAnythingPrompt {
+    /// Creates a new empty [`SegmentAnythingPrompt`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a point to the list of points to segment.
+    pub fn add_goal_point(mut self, x: impl Into<f64>, y: impl Into<f64>) -> Self {
+        self.goal_points.push((x.into(), y.into()));
+        self
+    }
+
+    /// Add a point to the list of points to avoid.
+    pub fn add_avoid_point(mut self, x: impl Into<f64>, y: impl Into<f64>) -> Self {
+        self.avoid_points.push((x.into(), y.into()));
+        self
+    }
+
+    /// Set the box the object is in from the top left and bottom right corners.
+    ///
+    /// The box is passed to the model along with any goal or avoid points.
+    pub fn set_box(
+        mut self,
+        left: impl Into<f64>,
+        top: impl Into<f64>,
+        right: impl Into<f64>,
+        bottom: impl Into<f64>,
+    ) -> Self {
+        self.bounding_box = Some([left.into(), top.into(), right.into(), bottom.into()]);
+        self
+    }
+
+    fn points(&self) -> Vec<(f64, f64, bool)> {
+        self.goal_points
+            .iter()
+            .map(|(x, y)| (*x, *y, true))
+            .chain(self.avoid_points.iter().map(|(x, y)| (*x, *y, false)))
+            .collect()
+    }
+}
+
+/// Settings for running many prompts on one image with [`SegmentAnything::segment_batch`].
+pub struct SegmentAnythingBatchSettings {
+    image: ImageBuffer<image::Rgba<u8>, Vec<u8>>,
+    prompts: Vec<SegmentAnythingPrompt>,
+    threshold: f32,
+    min_region_area: usize,
+    max_hole_area: usize,
+    min_stability_score: f32,
+}
+
+impl SegmentAnythingBatchSettings {
+    /// Creates a new [`SegmentAnythingBatchSettings`] from an image.
+    pub fn new<I: GenericImageView<Pixel = Rgba<u8>>>(input: I) -> Self {
+        let mut image = ImageBuffer::new(input.width(), input.height());
+        image.copy_from(&input, 0, 0).unwrap();
+        Self {
+            image,
+            prompts: Vec::new(),
+            threshold: 0.,
+            min_region_area: 0,
+            max_hole_area: 0,
+            min_stability_score: 0.,
+        }
+    }
+
+    /// Add a prompt to segment.
+    pub fn add_prompt(mut self, prompt: SegmentAnythingPrompt) -> Self {
+        self.prompts.push(prompt);
+        self
+    }
+
+    /// Set the list of prompts to segment.
+    pub fn set_prompts(mut self, prompts: Vec<SegmentAnythingPrompt>) -> Self {
+        self.prompts = prompts;
+        self
+    }
+
+    /// Sets the detection threshold for the masks, 0 is the default value.
+    /// - A negative values makes the model return larger masks.
+    /// - A positive makes the model return smaller masks.
+    pub fn set_threshold(mut self, threshold: f32) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Remove disconnected regions of each mask that are smaller than this many pixels in the original image, 0 (the default value) keeps every region.
+    pub fn set_min_region_area(mut self, area: usize) -> Self {
+        self.min_region_area = area;
+        self
+    }
+
+    /// Fill holes in each mask that are smaller than this many pixels in the original image, 0 (the default value) keeps every hole.
+    pub fn set_max_hole_area(mut self, area: usize) -> Self {
+        self.max_hole_area = area;
+        self
+    }
+
+    /// Skip masks with a stability score lower than this value. The stability score is between 0 and 1, and measures how much the mask changes with the threshold. 0 is the default value.
+    pub fn set_min_stability_score(mut self, score: f32) -> Self {
+        self.min_stability_score = score;
+        self
+    }
+}
+
+/// A mask created by [`SegmentAnything::segment_batch`].
+#[derive(Debug, Clone)]
+pub struct SegmentAnythingMask {
+    prompt_index: usize,
+    mask: DynamicImage,
+    predicted_iou: f32,
+    stability_score: f32,
+}
+
+impl SegmentAnythingMask {
+    /// Get the index of the prompt this mask was created from.
+    pub fn prompt_index(&self) -> usize {
+        self.prompt_index
+    }
+
+    /// Get the black and white mask. The mask is the same size as the input image.
+    pub fn mask(&self) -> &DynamicImage {
+        &self.mask
+    }
+
+    /// Get the quality of the mask predicted by the model.
+    pub fn predicted_iou(&self) -> f32 {
+        self.predicted_iou
+    }
+
+    /// Get the stability score of the mask.
+    pub fn stability_score(&self) -> f32 {
+        self.stability_score
+    }
+}
+
+impl From<SegmentAnythingMask> for DynamicImage {
+    fn from(mask: SegmentAnythingMask) -> Self {
+        mask.mask
+    }
+}
+
+impl SegmentAnything {
+    /// Segment an image from many prompts. The image is only embedded once, so this is much faster than calling [`SegmentAnything::segment_from_points`] for each prompt.
+    ///
+    /// Masks that are filtered out by [`SegmentAnythingBatchSettings::set_min_stability_score`] are skipped, so use [`SegmentAnythingMask::prompt_index`] to match masks to prompts.
+    ///
+    /// # Example
+    /// ```rust, no_run
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// use segment_anything_rs::*;
+    ///
+    /// let model = SegmentAnything::builder().build().await.unwrap();
+    /// let image = image::open("examples/landscape.jpg").unwrap();
+    /// let masks = model
+    ///     .segment_batch(
+    ///         SegmentAnythingBatchSettings::new(image)
+    ///             .add_prompt(SegmentAnythingPrompt::new().add_goal_point(0.5, 0.25))
+    ///             .add_prompt(SegmentAnythingPrompt::new().set_box(0.1, 0.6, 0.4, 0.9))
+    ///             .set_min_region_area(100)
+    ///             .set_max_hole_area(100)
+    ///             .set_min_stability_score(0.9),
+    ///     )
+    ///     .unwrap();
+    ///
+    /// for mask in masks {
+    ///     mask.mask()
+    ///         .save(format!("{}.png", mask.prompt_index()))
+    ///         .unwrap();
+    /// }
+    /// # }
+    /// ```
+    pub fn segment_batch(
+        &self,
+        settings: SegmentAnythingBatchSettings,
+    ) -> Result<Vec<SegmentAnythingMask>, SegmentAnythingInferenceError> {
+        let SegmentAnythingBatchSettings {
+            image,
+            prompts,
+            threshold,
+            min_region_area,
+            max_hole_area,
+            min_stability_score,
+        } = settings;
+
+        let image = DynamicImage::ImageRgba8(image);
+        let image_width = image.width();
+        let image_height = image.height();
+
+        let image_tensor = self.image_to_tensor(image)?;
+        let (_, height, width) = image_tensor.dims3()?;
+        let embeddings = self.sam.embeddings(&image_tensor)?;
+        // The area thresholds are in pixels of the original image, but the masks are the size of the resized image
+        let scale = (width * height) as f64 / (image_width as f64 * image_height as f64);
+        let scale_area = |area: usize| (area as f64 * scale).round() as usize;
+
+        let mut masks = Vec::new();
+        for (prompt_index, prompt) in prompts.iter().enumerate() {
+            let (low_res_mask, iou_predictions) =
+                self.forward_for_prompt(&embeddings, height, width, prompt)?;
+            let logits = low_res_mask
+                .upsample_nearest2d(sam::IMAGE_SIZE, sam::IMAGE_SIZE)?
+                .get(0)?
+                .i((0, ..height, ..width))?
+                .flatten_all()?
+                .to_vec1::<f32>()?;
+
+            let stability_score = stability_score(&logits, threshold, STABILITY_SCORE_OFFSET);
+            if stability_score < min_stability_score {
+                continue;
+            }
+            let predicted_iou = iou_predictions.flatten_all()?.to_vec1::<f32>()?[0];
+
+            let mut mask = BinaryMask::from_logits(&logits, width, height, threshold);
+            mask.remove_small_regions(scale_area(min_region_area));
+            mask.fill_holes(scale_area(max_hole_area));
+
+            let mask = DynamicImage::ImageLuma8(mask.to_image()).resize_exact(
+                image_width,
+                image_height,
+                image::imageops::FilterType::Nearest,
+            );
+            masks.push(SegmentAnythingMask {
+                prompt_index,
+                mask,
+                predicted_iou,
+                stability_score,
+            });
+        }
+
+        Ok(masks)
+    }
+    /// Run the prompt encoder and mask decoder on the embeddings of an image with the size `height` x `width`
+    fn forward_for_prompt(
+        &self,
+        embeddings: &Tensor,
+        height: usize,
+        width: usize,
+        prompt: &SegmentAnythingPrompt,
+    ) -> candle_core::Result<(Tensor, Tensor)> {
+        let device = embeddings.device();
+        let points = prompt.points();
+        let points = if points.is_empty() {
+            None
+        } else {
+            let xys = points
+                .iter()
+                .flat_map(|(x, y, _)| [(*x * width as f64) as f32, (*y * height as f64) as f32])
+                .collect::<Vec<_>>();
+            let labels = points
+                .iter()
+                .map(|(_, _, goal)| if *goal { 1f32 } else { 0f32 })
+                .collect::<Vec<_>>();
+            Some((
+                Tensor::from_vec(xys, (1, points.len(), 2), device)?,
+                Tensor::from_vec(labels, (1, points.len()), device)?,
+            ))
+        };
+        let boxes = prompt
+            .bounding_box
+            .map(|[left, top, right, bottom]| {
+                let corners = [
+                    (left * width as f64) as f32,
+                    (top * height as f64) as f32,
+                    (right * width as f64) as f32,
+                    (bottom * height as f64) as f32,
+                ];
+                Tensor::from_vec(corners.to_vec(), (1, 4), device)
+            })
+            .transpose()?;
+
+        // Candle's prompt encoder flattens the two box corner embeddings into one token, which can't be concatenated with
+        // the point embeddings. Instead, embed the points and box separately and join them the same way SAM does.
+        let (point_embeddings, dense_embeddings) = self.prompt_encoder.forward(
+            points.as_ref().map(|(xys, labels)| (xys, labels)),
+            None,
+            None,
+        )?;
+        let sparse_embeddings = match boxes {
+            Some(boxes) => {
+                let (box_embeddings, _) = self.prompt_encoder.forward(None, Some(&boxes), None)?;
+                let box_embeddings = box_embeddings.reshape((1, (), point_embeddings.dim(2)?))?;
+                match &points {
+                    // Without a box, the prompt encoder adds a padding point to the end of the points
+                    Some((xys, _)) => Tensor::cat(
+                        &[
+                            &point_embeddings.narrow(1, 0, xys.dim(1)?)?,
+                            &box_embeddings,
+                        ],
+                        1,
+                    )?,
+                    None => box_embeddings,
+                }
+            }
+            None => point_embeddings,
+        };
+        let image_pe = self.prompt_encoder.get_dense_pe()?;
+        self.mask_decoder.forward(
+            embeddings,
+            &image_pe,
+            &sparse_embeddings,
+            &dense_embeddings,
+            false,
+        )
+    }
+}
@@ -0,0 +1,361 @@
+//! Export masks to formats used by annotation tools
+
+use image::DynamicImage;
+
+use crate::postprocess::BinaryMask;
+use crate::SegmentAnythingMask;
+
+/// A mask compressed with run length encoding in the format used by the [COCO](https://cocodataset.org/#format-data) dataset.
+///
+/// The counts alternate between runs of pixels outside and inside of the mask, starting with pixels outside of the mask. Pixels are read column by column from the top left of the mask.
+///
+/// # Example
+/// ```rust, no_run
+/// # #[tokio::main]
+/// # async fn main() {
+/// use segment_anything_rs::*;
+///
+/// let model = SegmentAnything::builder().build().await.unwrap();
+/// let image = image::open("examples/landscape.jpg").unwrap();
+/// let x = image.width() / 2;
+/// let y = image.height() / 4;
+/// let mask = model
+///     .segment_from_points(SegmentAnythingInferenceSettings::new(image).add_goal_point(x, y))
+///     .unwrap();
+///
+/// let rle = CocoRle::from_mask(&mask);
+/// println!("size: {:?}", rle.size());
+/// println!("counts: {}", rle.compressed_counts());
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CocoRle {
+    width: u32,
+    height: u32,
+    counts: Vec<u32>,
+}
+
+impl CocoRle {
+    /// Encode a black and white mask. Pixels brighter than half of the maximum brightness are part of the mask.
+    pub fn from_mask(mask: &DynamicImage) -> Self {
+        Self::from_binary_mask(&BinaryMask::from_image(mask))
+    }
+
+    fn from_binary_mask(mask: &BinaryMask) -> Self {
+        let mut counts = Vec::new();
+        let mut current = false;
+        let mut run = 0;
+        for x in 0..mask.width {
+            for y in 0..mask.height {
+                let value = mask.data[y * mask.width + x];
+                if value != current {
+                    counts.push(run);
+                    run = 0;
+                    current = value;
+                }
+                run += 1;
+            }
+        }
+        counts.push(run);
+        Self {
+            width: mask.width as u32,
+            height: mask.height as u32,
+            counts,
+        }
+    }
+
+    /// Get the size of the mask as `[height, width]`, the order COCO uses.
+    pub fn size(&self) -> [u32; 2] {
+        [self.height, self.width]
+    }
+
+    /// Get the uncompressed counts of each run.
+    pub fn counts(&self) -> &[u32] {
+        &self.counts
+    }
+
+    /// Get the number of pixels inside of the mask.
+    pub fn area(&self) -> u32 {
+        self.counts.iter().skip(1).step_by(2).sum()
+    }
+
+    /// Get the counts compressed into a string the same way as the COCO api.
+    pub fn compressed_counts(&self) -> String {
+        let mut compressed = String::new();
+        for (i, count) in self.counts.iter().enumerate() {
+            let mut x = *count as i64;
+            // After the first two runs, each run is stored as the difference from the run of the same value before it
+            if i > 2 {
+                x -= self.counts[i - 2] as i64;
+            }
+            // Write the count in chunks of 5 bits. The 6th bit marks if there are more chunks
+            let mut more = true;
+            while more {
+                let mut c = x & 0x1f;
+                x >>= 5;
+                more = if c & 0x10 != 0 { x != -1 } else { x != 0 };
+                if more {
+                    c |= 0x20;
+                }
+                compressed.push((c as u8 + 48) as char);
+            }
+        }
+        compressed
+    }
+
+    /// Decode the mask into a black and white image.
+    pub fn to_mask(&self) -> DynamicImage {
+        let mut image = image::GrayImage::new(self.width, self.height);
+        let mut index = 0;
+        for (i, count) in self.counts.iter().enumerate() {
+            for _ in 0..*count {
+                let (x, y) = (index / self.height, index % self.height);
+                if i % 2 == 1 {
+                    image.put_pixel(x, y, image::Luma([255]));
+                }
+                index += 1;
+            }
+        }
+        DynamicImage::ImageLuma8(image)
+    }
+}
+
+/// The outline of one region of a mask.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MaskPolygon {
+    points: Vec<(f32, f32)>,
+}
+
+impl MaskPolygon {
+    /// Find the outlines of every region in a black and white mask. Pixels brighter than half of the maximum brightness are part of the mask.
+    ///
+    /// Each outline is simplified so that no pixel on the outline is further than `tolerance` pixels away from the polygon. A tolerance of 0 keeps every corner of the outline. Holes inside of regions are not included in the outlines.
+    ///
+    /// # Example
+    /// ```rust, no_run
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// use segment_anything_rs::*;
+    ///
+    /// let model = SegmentAnything::builder().build().await.unwrap();
+    /// let image = image::open("examples/landscape.jpg").unwrap();
+    /// let x = image.width() / 2;
+    /// let y = image.height() / 4;
+    /// let mask = model
+    ///     .segment_from_points(SegmentAnythingInferenceSettings::new(image).add_goal_point(x, y))
+    ///     .unwrap();
+    ///
+    /// for polygon in MaskPolygon::from_mask(&mask, 1.0) {
+    ///     println!("{:?}", polygon.to_coco());
+    /// }
+    /// # }
+    /// ```
+    pub fn from_mask(mask: &DynamicImage, tolerance: f32) -> Vec<Self> {
+        Self::from_binary_mask(&BinaryMask::from_image(mask), tolerance)
+    }
+
+    fn from_binary_mask(mask: &BinaryMask, tolerance: f32) -> Vec<Self> {
+        let mut visited = vec![false; mask.data.len()];
+        let mut stack = Vec::new();
+        let mut polygons = Vec::new();
+        for start in 0..mask.data.len() {
+            if visited[start] || !mask.data[start] {
+                continue;
+            }
+            // Mark the region as visited with 4-connectivity to match the outline tracing
+            visited[start] = true;
+            stack.push(start);
+            while let Some(index) = stack.pop() {
+                let (x, y) = (index % mask.width, index / mask.width);
+                let neighbors = [
+                    (x > 0).then(|| index - 1),
+                    (x + 1 < mask.width).then(|| index + 1),
+                    (y > 0).then(|| index - mask.width),
+                    (y + 1 < mask.height).then(|| index + mask.width),
+                ];
+                for neighbor in neighbors.into_iter().flatten() {
+                    if !visited[neighbor] && mask.data[neighbor] {
+                        visited[neighbor] = true;
+                        stack.push(neighbor);
+                    }
+                }
+            }
+
+            // The first pixel of each region is its top left pixel, so the top edge of the pixel is on the outline
+            let outline = trace_outline(mask, start % mask.width, start / mask.width);
+            let points = simplify_closed(&outline, tolerance);
+            if points.len() >= 3 {
+                polygons.push(Self { points });
+            }
+        }
+        polygons
+    }
+
+    /// Get the points of the polygon in clockwise order. Points are in pixel coordinates where (0, 0) is the top left corner of the top left pixel.
+    pub fn points(&self) -> &[(f32, f32)] {
+        &self.points
+    }
+
+    /// Get the points flattened into `[x1, y1, x2, y2, ...]`, the format COCO uses for polygons.
+    pub fn to_coco(&self) -> Vec<f32> {
+        self.points.iter().flat_map(|(x, y)| [*x, *y]).collect()
+    }
+
+    /// Get the area of the polygon in pixels.
+    pub fn area(&self) -> f32 {
+        let twice_area: f32 = self
+            .points
+            .iter()
+            .zip(self.points.iter().cycle().skip(1))
+            .map(|((x1, y1), (x2, y2))| x1 * y2 - x2 * y1)
+            .sum();
+        twice_area.abs() / 2.
+    }
+}
+
+impl SegmentAnythingMask {
+    /// Encode the mask with COCO run length encoding. See [`CocoRle`] for more details.
+    pub fn to_rle(&self) -> CocoRle {
+        CocoRle::from_mask(self.mask())
+    }
+
+    /// Find the simplified outlines of every region in the mask. See [`MaskPolygon::from_mask`] for more details.
+    pub fn to_polygons(&self, tolerance: f32) -> Vec<MaskPolygon> {
+        MaskPolygon::from_mask(self.mask(), tolerance)
+    }
+}
+
+/// Follow the edges between pixels around the outside of a region, keeping the region on the right. Returns the corners of the outline
+fn trace_outline(mask: &BinaryMask, x: usize, y: usize) -> Vec<(isize, isize)> {
+    let start = (x as isize, y as isize);
+    let (mut position, mut direction) = (start, (1, 0));
+    // The start is always a corner because it is the top left corner of the region
+    let mut corners = vec![start];
+    loop {
+        let (dx, dy) = direction;
+        let (x, y) = position;
+        // The pixels ahead of the current corner on the right and left side of the edge
+        let ahead_right = mask.get(x + (dx - dy - 1) / 2, y + (dy + dx - 1) / 2);
+        let ahead_left = mask.get(x + (dx + dy - 1) / 2, y + (dy - dx - 1) / 2);
+        let new_direction = match (ahead_left, ahead_right) {
+            // Turn right around the corner of the region
+            (_, false) => (-dy, dx),
+            // Keep following the edge
+            (false, true) => (dx, dy),
+            // Turn left into the corner of the region
+            (true, true) => (dy, -dx),
+        };
+        if new_direction != direction {
+            corners.push(position);
+        }
+        direction = new_direction;
+        position = (x + direction.0, y + direction.1);
+        // Only the region touches the start corner, so the outline is closed when it gets back to the start
+        if position == start {
+            break;
+        }
+    }
+    corners
+}
+
+/// Simplify a closed polygon with the Ramer–Douglas–Peucker algorithm
+fn simplify_closed(points: &[(isize, isize)], tolerance: f32) -> Vec<(f32, f32)> {
+    let points: Vec<(f32, f32)> = points.iter().map(|(x, y)| (*x as f32, *y as f32)).collect();
+    if points.len() < 3 {
+        return points;
+    }
+    // Split the polygon at the point furthest from the first point and simplify each half
+    let (first_x, first_y) = points[0];
+    let furthest = (1..points.len())
+        .max_by(|a, b| {
+            let distance = |i: &usize| {
+                let (x, y) = points[*i];
+                (x - first_x).hypot(y - first_y)
+            };
+            distance(a).total_cmp(&distance(b))
+        })
+        .unwrap();
+    let mut first_half = simplify(&points[..=furthest], tolerance);
+    let mut closed_second_half = points[furthest..].to_vec();
+    closed_second_half.push(points[0]);
+    let second_half = simplify(&closed_second_half, tolerance);
+    first_half.extend_from_slice(&second_half[1..second_half.len() - 1]);
+    first_half
+}
+
+/// Simplify a line with the Ramer–Douglas–Peucker algorithm. The first and last points are always kept
+fn simplify(points: &[(f32, f32)], tolerance: f32) -> Vec<(f32, f32)> {
+    let (start, end) = (points[0], points[points.len() - 1]);
+    let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+    let length = dx.hypot(dy);
+    let distance = |(x, y): (f32, f32)| {
+        if length == 0. {
+            (x - start.0).hypot(y - start.1)
+        } else {
+            (dy * (x - start.0) - dx * (y - start.1)).abs() / length
+        }
+    };
+    let furthest = (1..points.len() - 1)
+        .map(|i| (i, distance(points[i])))
+        .max_by(|(_, a), (_, b)| a.total_cmp(b));
+    match furthest {
+        Some((i, distance)) if distance > tolerance => {
+            let mut simplified = simplify(&points[..=i], tolerance);
+            simplified.pop();
+            simplified.extend(simplify(&points[i..], tolerance));
+            simplified
+        }
+        _ => vec![start, end],
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn exports_rle_and_polygons() {
+    #[rustfmt::skip]
+    let rows = [
+        "......",
+        ".###..",
+        ".####.",
+        ".####.",
+        "......",
+        ".....#",
+    ];
+    let mask = BinaryMask {
+        width: rows[0].len(),
+        height: rows.len(),
+        data: rows
+            .iter()
+            .flat_map(|row| row.chars().map(|c| c == '#'))
+            .collect(),
+    };
+
+    let rle = CocoRle::from_binary_mask(&mask);
+    assert_eq!(rle.size(), [6, 6]);
+    assert_eq!(rle.counts(), [7, 3, 3, 3, 3, 3, 4, 2, 7, 1]);
+    assert_eq!(rle.area(), 12);
+    assert_eq!(BinaryMask::from_image(&rle.to_mask()), mask);
+    let rle = CocoRle {
+        width: 3,
+        height: 3,
+        counts: vec![3, 2, 4, 1],
+    };
+    assert_eq!(rle.compressed_counts(), "324O");
+
+    let polygons = MaskPolygon::from_binary_mask(&mask, 0.);
+    assert_eq!(polygons.len(), 2);
+    assert_eq!(
+        polygons[0].points(),
+        [(1., 1.), (4., 1.), (4., 2.), (5., 2.), (5., 4.), (1., 4.)]
+    );
+    assert_eq!(polygons[0].area(), 11.);
+    assert_eq!(polygons[1].area(), 1.);
+    assert_eq!(
+        polygons[0].to_coco(),
+        [1., 1., 4., 1., 4., 2., 5., 2., 5., 4., 1., 4.]
+    );
+
+    // The notch in the top right corner is within the tolerance
+    let simplified = MaskPolygon::from_binary_mask(&mask, 1.);
+    assert_eq!(simplified[0].points().len(), 4);
+}
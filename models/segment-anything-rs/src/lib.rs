@@ -4,14 +4,17 @@
 //! ## Usage
 //!
 //! ```rust, no_run
+//! # #[tokio::main]
+//! # async fn main() {
 //! use segment_anything_rs::*;
 //!
-//! let model = SegmentAnything::builder().build().unwrap();
+//! let model = SegmentAnything::builder().build().await.unwrap();
 //! let image = image::open("examples/landscape.jpg").unwrap();
 //! let images = model.segment_everything(image).unwrap();
 //! for (i, img) in images.iter().enumerate() {
 //!     img.save(&format!("{}.png", i)).unwrap();
 //! }
+//! # }
 //! ```
 
 #![warn(missing_docs)]
@@ -24,13 +27,26 @@ extern crate accelerate_src;
 use candle_core::DType;
 use candle_core::{Device, Tensor};
 use candle_nn::VarBuilder;
+use candle_transformers::models::segment_anything::mask_decoder::MaskDecoder;
+use candle_transformers::models::segment_anything::prompt_encoder::PromptEncoder;
 use candle_transformers::models::segment_anything::sam::{self, Sam};
 use image::{DynamicImage, GenericImage, GenericImageView, ImageBuffer, Rgba};
+use kalosm_common::*;
+use kalosm_model_types::{FileSource, ModelLoadingProgress};
+
+mod background;
+pub use background::*;
+mod batch;
+pub use batch::*;
+mod export;
+pub use export::*;
+mod postprocess;
 
 /// A builder for [`SegmentAnything`].
 #[derive(Default)]
 pub struct SegmentAnythingBuilder {
     source: SegmentAnythingSource,
+    cache: Cache,
 }
 
 impl SegmentAnythingBuilder {
@@ -40,27 +56,45 @@ impl SegmentAnythingBuilder {
         self
     }
 
+    /// Set the cache location to use for the model (defaults DATA_DIR/kalosm/cache)
+    pub fn with_cache(mut self, cache: Cache) -> Self {
+        self.cache = cache;
+        self
+    }
+
     /// Builds the [`SegmentAnything`] model.
-    pub fn build(self) -> Result<SegmentAnything, LoadSegmentAnythingError> {
-        SegmentAnything::new(self)
+    pub async fn build(self) -> Result<SegmentAnything, LoadSegmentAnythingError> {
+        SegmentAnything::new(self, |_| {}).await
+    }
+
+    /// Builds the [`SegmentAnything`] model with a handler for the progress of the download.
+    pub async fn build_with_loading_handler(
+        self,
+        handler: impl FnMut(ModelLoadingProgress) + Send + Sync + 'static,
+    ) -> Result<SegmentAnything, LoadSegmentAnythingError> {
+        SegmentAnything::new(self, handler).await
     }
 }
 
 /// The source of the model.
 pub struct SegmentAnythingSource {
-    model: String,
-    filename: String,
+    model: FileSource,
     tiny: bool,
 }
 
 impl SegmentAnythingSource {
-    /// Creates a new [`SegmentAnythingSource`].
+    /// Creates a new [`SegmentAnythingSource`] from a safetensors file in a Hugging Face repo.
     pub fn new(model: impl Into<String>, filename: impl Into<String>) -> Self {
-        Self {
-            model: model.into(),
-            filename: filename.into(),
-            tiny: false,
-        }
+        Self::from_file(FileSource::huggingface(
+            model.into(),
+            "main",
+            filename.into(),
+        ))
+    }
+
+    /// Creates a new [`SegmentAnythingSource`] from a safetensors file with the weights of a sam_vit_b model.
+    pub fn from_file(model: FileSource) -> Self {
+        Self { model, tiny: false }
     }
 
     /// Create the tiny SAM model source.
@@ -157,9 +191,9 @@ pub enum LoadSegmentAnythingError {
     /// An error that can occur when trying to load a [`SegmentAnything`] model into a device.
     #[error("Failed to load model into device: {0}")]
     LoadModel(#[from] candle_core::Error),
-    /// An error that can occur when downloading a [`SegmentAnything`] model from Hugging Face.
-    #[error("Failed to download model from Hugging Face: {0}")]
-    DownloadModel(#[from] hf_hub::api::sync::ApiError),
+    /// An error that can occur when downloading a [`SegmentAnything`] model from the cache.
+    #[error("Failed to download model: {0}")]
+    DownloadModel(#[from] CacheError),
 }
 
 /// An error that can occur when running a [`SegmentAnything`] model.
@@ -171,12 +205,18 @@ pub enum SegmentAnythingInferenceError {
     /// An error that can occur when converting the result of a [`SegmentAnything`] model to an image.
     #[error("Failed to merge masks")]
     MergeMasks,
+    /// No subject was found in the image when removing the background.
+    #[error("No foreground subject was found in the image")]
+    NoForeground,
 }
 
 /// The [segment anything](https://segment-anything.com/) model.
 pub struct SegmentAnything {
     device: Device,
     sam: Sam,
+    // Sam doesn't expose box prompts, so we load a second copy of the prompt encoder and mask decoder for them
+    prompt_encoder: PromptEncoder,
+    mask_decoder: MaskDecoder,
 }
 
 impl SegmentAnything {
@@ -185,32 +225,56 @@ impl SegmentAnything {
         SegmentAnythingBuilder::default()
     }
 
-    fn new(settings: SegmentAnythingBuilder) -> Result<Self, LoadSegmentAnythingError> {
-        let SegmentAnythingBuilder { source } = settings;
+    async fn new(
+        settings: SegmentAnythingBuilder,
+        mut handler: impl FnMut(ModelLoadingProgress) + Send + Sync,
+    ) -> Result<Self, LoadSegmentAnythingError> {
+        let SegmentAnythingBuilder { source, cache } = settings;
         let model = {
-            let api = hf_hub::api::sync::Api::new()?;
-            let api = api.model(source.model);
-            api.get(&source.filename)?
+            let mut create_progress =
+                ModelLoadingProgress::downloading_progress(format!("Model ({})", source.model));
+            cache
+                .load(&source.model, |progress| handler(create_progress(progress)))
+                .await?
         };
         // Currently, candle doesn't support some operations that are required for segment anything
         // let device = kalosm_common::accelerated_device_if_available()?;
         let device = Device::Cpu;
-        let vb = unsafe { VarBuilder::from_mmaped_safetensors(&[model], DType::F32, &device)? };
+        let weights = model
+            .load_weights(WeightLoadingOptions::new())
+            .map_err(CacheError::from)?;
+        let vb = VarBuilder::from_slice_safetensors(&weights, DType::F32, &device)?;
+        let image_embedding_size = sam::IMAGE_SIZE / 16;
+        let prompt_encoder = PromptEncoder::new(
+            256,
+            (image_embedding_size, image_embedding_size),
+            (sam::IMAGE_SIZE, sam::IMAGE_SIZE),
+            16,
+            vb.pp("prompt_encoder"),
+        )?;
+        let mask_decoder = MaskDecoder::new(256, 3, 3, 256, vb.pp("mask_decoder"))?;
         let sam = if source.tiny {
             sam::Sam::new_tiny(vb)? // tiny vit_t
         } else {
             sam::Sam::new(768, 12, 12, &[2, 5, 8, 11], vb)? // sam_vit_b
         };
-        Ok(Self { device, sam })
+        Ok(Self {
+            device,
+            sam,
+            prompt_encoder,
+            mask_decoder,
+        })
     }
 
     /// Segment an image from a list of points. Returns a [`DynamicImage`] mask.
     ///
     /// # Example
     /// ```rust, no_run
+    /// # #[tokio::main]
+    /// # async fn main() {
     /// use segment_anything_rs::*;
     ///
-    /// let model = SegmentAnything::builder().build().unwrap();
+    /// let model = SegmentAnything::builder().build().await.unwrap();
     /// let image = image::open("examples/landscape.jpg").unwrap();
     /// let x = image.width() / 2;
     /// let y = image.height() / 4;
@@ -219,6 +283,7 @@ impl SegmentAnything {
     ///     .unwrap();
     ///
     /// images.save("out.png").unwrap();
+    /// # }
     /// ```
     pub fn segment_from_points(
         &self,
@@ -295,14 +360,17 @@ impl SegmentAnything {
     /// # Example
     ///
     /// ```rust, no_run
+    /// # #[tokio::main]
+    /// # async fn main() {
     /// use segment_anything_rs::*;
     ///
-    /// let model = SegmentAnything::builder().build().unwrap();
+    /// let model = SegmentAnything::builder().build().await.unwrap();
     /// let image = image::open("examples/landscape.jpg").unwrap();
     /// let images = model.segment_everything(image).unwrap();
     /// for (i, img) in images.iter().enumerate() {
     ///     img.save(&format!("{}.png", i)).unwrap();
     /// }
+    /// # }
     /// ```
     pub fn segment_everything(
         &self,
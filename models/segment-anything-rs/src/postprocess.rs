@@ -0,0 +1,152 @@
+//! Cleans up binary masks produced by the model
+
+/// A binary mask stored in row major order
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct BinaryMask {
+    pub(crate) width: usize,
+    pub(crate) height: usize,
+    pub(crate) data: Vec<bool>,
+}
+
+impl BinaryMask {
+    /// Create a mask from the logits of the model
+    pub(crate) fn from_logits(logits: &[f32], width: usize, height: usize, threshold: f32) -> Self {
+        Self {
+            width,
+            height,
+            data: logits.iter().map(|logit| *logit > threshold).collect(),
+        }
+    }
+
+    /// Create a mask from a black and white image. Pixels brighter than half of the maximum brightness are part of the mask
+    pub(crate) fn from_image(image: &image::DynamicImage) -> Self {
+        let image = image.to_luma8();
+        Self {
+            width: image.width() as usize,
+            height: image.height() as usize,
+            data: image.pixels().map(|pixel| pixel.0[0] > 127).collect(),
+        }
+    }
+
+    /// Check if a pixel is part of the mask. Pixels outside of the mask are never part of the mask
+    pub(crate) fn get(&self, x: isize, y: isize) -> bool {
+        x >= 0
+            && y >= 0
+            && (x as usize) < self.width
+            && (y as usize) < self.height
+            && self.data[y as usize * self.width + x as usize]
+    }
+
+    /// Remove connected regions of the mask with fewer than `min_area` pixels
+    pub(crate) fn remove_small_regions(&mut self, min_area: usize) {
+        self.replace_small_components(true, min_area, false);
+    }
+
+    /// Fill holes in the mask with fewer than `max_area` pixels. Background regions that touch the edge of the image are not holes
+    pub(crate) fn fill_holes(&mut self, max_area: usize) {
+        self.replace_small_components(false, max_area, true);
+    }
+
+    /// Set every connected region of pixels with the value `value` and an area smaller than `area` to `!value`
+    fn replace_small_components(&mut self, value: bool, area: usize, skip_edges: bool) {
+        if area == 0 {
+            return;
+        }
+        let mut visited = vec![false; self.data.len()];
+        let mut stack = Vec::new();
+        let mut component = Vec::new();
+        for start in 0..self.data.len() {
+            if visited[start] || self.data[start] != value {
+                continue;
+            }
+            // Flood fill the component with 4-connectivity
+            visited[start] = true;
+            stack.push(start);
+            component.clear();
+            let mut touches_edge = false;
+            while let Some(index) = stack.pop() {
+                component.push(index);
+                let (x, y) = (index % self.width, index / self.width);
+                touches_edge |= x == 0 || y == 0 || x == self.width - 1 || y == self.height - 1;
+                let neighbors = [
+                    (x > 0).then(|| index - 1),
+                    (x + 1 < self.width).then(|| index + 1),
+                    (y > 0).then(|| index - self.width),
+                    (y + 1 < self.height).then(|| index + self.width),
+                ];
+                for neighbor in neighbors.into_iter().flatten() {
+                    if !visited[neighbor] && self.data[neighbor] == value {
+                        visited[neighbor] = true;
+                        stack.push(neighbor);
+                    }
+                }
+            }
+            if component.len() < area && !(skip_edges && touches_edge) {
+                for index in &component {
+                    self.data[*index] = !value;
+                }
+            }
+        }
+    }
+
+    /// Convert the mask to a black and white image
+    pub(crate) fn to_image(&self) -> image::GrayImage {
+        image::GrayImage::from_fn(self.width as u32, self.height as u32, |x, y| {
+            let value = self.data[y as usize * self.width + x as usize];
+            image::Luma([if value { 255 } else { 0 }])
+        })
+    }
+}
+
+/// The stability score of a mask is the intersection over union of the mask thresholded slightly above and below the threshold. Masks that change a lot with the threshold are less reliable
+pub(crate) fn stability_score(logits: &[f32], threshold: f32, offset: f32) -> f32 {
+    let high = logits
+        .iter()
+        .filter(|logit| **logit > threshold + offset)
+        .count();
+    let low = logits
+        .iter()
+        .filter(|logit| **logit > threshold - offset)
+        .count();
+    if low == 0 {
+        return 0.;
+    }
+    high as f32 / low as f32
+}
+
+#[cfg(test)]
+#[test]
+fn removes_regions_and_fills_holes() {
+    #[rustfmt::skip]
+    let rows = [
+        "##.......",
+        ".........",
+        "..#####..",
+        "..#.#.#..",
+        "..#####..",
+        ".........",
+    ];
+    let parse = |rows: &[&str]| BinaryMask {
+        width: rows[0].len(),
+        height: rows.len(),
+        data: rows
+            .iter()
+            .flat_map(|row| row.chars().map(|c| c == '#'))
+            .collect(),
+    };
+    let mut mask = parse(&rows);
+    mask.remove_small_regions(3);
+    mask.fill_holes(2);
+    #[rustfmt::skip]
+    let expected = [
+        ".........",
+        ".........",
+        "..#####..",
+        "..#####..",
+        "..#####..",
+        ".........",
+    ];
+    assert_eq!(mask, parse(&expected));
+
+    assert_eq!(stability_score(&[2., 0.5, -0.5, -2.], 0., 1.), 1. / 3.);
+}